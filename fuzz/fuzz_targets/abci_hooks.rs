@@ -0,0 +1,50 @@
+//! Feeds arbitrary bytes to the check_tx, deliver_tx and query hooks of an in-memory application,
+//! see `FuzzedChain::run` for how the input is split between them. Run with `cargo fuzz run abci_hooks`.
+#![no_main]
+// not every item of the application modules is used by the target
+#![allow(dead_code)]
+
+// the application is part of the aleo_abci binary rather than the library,
+// so its modules are mounted here the same way src/blockchain/main.rs does
+#[path = "../../src/blockchain/application.rs"]
+mod application;
+#[path = "../../src/blockchain/bloom.rs"]
+mod bloom;
+#[path = "../../src/blockchain/bridge.rs"]
+mod bridge;
+#[path = "../../src/blockchain/config.rs"]
+mod config;
+#[path = "../../src/blockchain/fuzz.rs"]
+mod fuzz;
+#[path = "../../src/blockchain/governance.rs"]
+mod governance;
+#[path = "../../src/blockchain/health.rs"]
+mod health;
+#[path = "../../src/blockchain/kv_backend.rs"]
+mod kv_backend;
+#[path = "../../src/blockchain/migrations.rs"]
+mod migrations;
+#[path = "../../src/blockchain/pending_relay.rs"]
+mod pending_relay;
+#[path = "../../src/blockchain/pipeline.rs"]
+mod pipeline;
+#[path = "../../src/blockchain/program_store.rs"]
+mod program_store;
+#[path = "../../src/blockchain/record_store.rs"]
+mod record_store;
+#[path = "../../src/blockchain/validator_set.rs"]
+mod validator_set;
+#[path = "../../src/blockchain/watchdog.rs"]
+mod watchdog;
+
+use fuzz::FuzzedChain;
+use libfuzzer_sys::fuzz_target;
+
+thread_local! {
+    // starting the chain is slow compared to running an input, so it's kept across inputs
+    static CHAIN: FuzzedChain = FuzzedChain::start();
+}
+
+fuzz_target!(|data: &[u8]| {
+    CHAIN.with(|chain| chain.run(data));
+});
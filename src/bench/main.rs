@@ -0,0 +1,406 @@
+/// Binary to measure the end to end throughput of a running node. The `setup` step generates a set of accounts
+/// and pre-mints a credits record for each of them in the genesis files of the nodes, so the `run` step can
+/// generate transfer executions from those accounts and flood the node with them at a configurable rate.
+///
+/// Transactions are generated before the flood starts so proving times don't affect the measurements, which are:
+/// the latency of the mempool validations (check_tx, as the round trip of a sync broadcast), the latency until
+/// the transaction is committed (from its submission to the time of the block that included it, which accounts
+/// for deliver_tx and commit) and the achieved transactions per second.
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::{anyhow, ensure, Result};
+use clap::Parser;
+use lib::transaction::Transaction;
+use lib::validator::GenesisState;
+use lib::vm;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tendermint::block::Height;
+use tendermint_rpc::query::Query;
+use tendermint_rpc::{Client, HttpClient, Order};
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+
+/// Default tendermint url
+const LOCAL_BLOCKCHAIN_URL: &str = "http://127.0.0.1:26657";
+
+#[derive(Debug, Parser)]
+#[clap()]
+pub enum Cli {
+    /// Generate the benchmark accounts and add a credits record for each of them to the genesis files of the given
+    /// node directories. Should run after the genesis binary and before starting the nodes.
+    Setup {
+        /// List of node directories, each one is expected to contain a config/genesis.json
+        #[clap()]
+        node_dirs: Vec<PathBuf>,
+        /// Amount of accounts to generate.
+        #[clap(long, default_value_t = 10)]
+        accounts: usize,
+        /// The amount of gates to assign to each account.
+        #[clap(long, default_value_t = 1000)]
+        amount: u64,
+        /// File where the accounts and their records are saved, to be used by the run step.
+        #[clap(long, default_value = "bench_accounts.json")]
+        output: PathBuf,
+    },
+    /// Generate transfer executions from the benchmark accounts and send them to the node at the given rate,
+    /// reporting the measured latencies and throughput.
+    Run {
+        /// File with the accounts generated by the setup step.
+        #[clap(long, default_value = "bench_accounts.json")]
+        accounts: PathBuf,
+        /// Total amount of transactions to send.
+        #[clap(long, default_value_t = 100)]
+        transactions: usize,
+        /// Amount of transactions sent per second.
+        #[clap(long, default_value_t = 10.0)]
+        rate: f64,
+        /// Seconds to wait for the transactions to be committed after the last one is sent.
+        #[clap(long, default_value_t = 120)]
+        timeout: u64,
+        /// tendermint node url
+        #[clap(short, long, env = "BLOCKCHAIN_URL", default_value = LOCAL_BLOCKCHAIN_URL)]
+        url: String,
+    },
+}
+
+/// An account generated for the benchmark, along with the record it was assigned in the genesis.
+#[derive(Serialize, Deserialize)]
+struct BenchAccount {
+    private_key: vm::PrivateKey,
+    record: (vm::Field, vm::EncryptedRecord),
+}
+
+/// A transaction sent to the node, along with the result of its mempool validations.
+struct Submission {
+    id: String,
+    submitted_at: SystemTime,
+    check_latency: Duration,
+    accepted: bool,
+}
+
+#[tokio::main()]
+async fn main() {
+    tracing_subscriber::fmt()
+        // Use a more compact, abbreviated log format
+        .compact()
+        .with_env_filter(EnvFilter::from_default_env())
+        // Build and init the subscriber
+        .finish()
+        .init();
+
+    let result = match Cli::parse() {
+        Cli::Setup {
+            node_dirs,
+            accounts,
+            amount,
+            output,
+        } => setup(&node_dirs, accounts, amount, &output),
+        Cli::Run {
+            accounts,
+            transactions,
+            rate,
+            timeout,
+            url,
+        } => {
+            run(
+                &accounts,
+                transactions,
+                rate,
+                Duration::from_secs(timeout),
+                &url,
+            )
+            .await
+        }
+    };
+
+    let (exit_code, output) = match result {
+        Ok(output) => (0, output),
+        Err(err) => (1, json!({"error": err.to_string()})),
+    };
+    println!("{output:#}");
+    std::process::exit(exit_code);
+}
+
+fn setup(
+    node_dirs: &[PathBuf],
+    accounts: usize,
+    amount: u64,
+    output: &Path,
+) -> Result<serde_json::Value> {
+    ensure!(!node_dirs.is_empty(), "need at least one node directory");
+
+    let accounts = (0..accounts)
+        .map(|index| {
+            let private_key = vm::PrivateKey::new(&mut rand::thread_rng())?;
+            let view_key = vm::ViewKey::try_from(&private_key)?;
+            let address = vm::Address::try_from(&view_key)?;
+            // NOTE: using the account index as seed, not for production!
+            let record =
+                vm::mint_record("credits.aleo", "credits", &address, amount, index as u64)?;
+            Ok(BenchAccount {
+                private_key,
+                record,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    for node_dir in node_dirs {
+        let genesis_path = node_dir.join("config/genesis.json");
+        let mut genesis: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&genesis_path)?)?;
+        let mut genesis_state: GenesisState = serde_json::from_value(genesis["app_state"].take())
+            .map_err(|e| {
+            anyhow!("invalid app state in {genesis_path:?}, was the genesis binary run? {e}")
+        })?;
+        genesis_state
+            .records
+            .extend(accounts.iter().map(|account| account.record.clone()));
+        genesis["app_state"] = serde_json::to_value(genesis_state)?;
+
+        info!("Writing genesis to {}", genesis_path.to_string_lossy());
+        std::fs::write(genesis_path, serde_json::to_string_pretty(&genesis)?)?;
+    }
+
+    std::fs::write(output, serde_json::to_string(&accounts)?)?;
+    Ok(json!({ "accounts": accounts.len(), "path": output }))
+}
+
+async fn run(
+    accounts_path: &Path,
+    transactions: usize,
+    rate: f64,
+    timeout: Duration,
+    url: &str,
+) -> Result<serde_json::Value> {
+    ensure!(rate > 0.0, "the rate should be positive");
+    let accounts: Vec<BenchAccount> =
+        serde_json::from_str(&std::fs::read_to_string(accounts_path)?)?;
+    ensure!(!accounts.is_empty(), "there are no benchmark accounts");
+
+    info!("Generating {transactions} transactions");
+    let preparation_start = Instant::now();
+    let transactions = generate_transactions(&accounts, transactions)?;
+    let preparation_time = preparation_start.elapsed();
+
+    info!("Sending transactions at {rate} per second");
+    let client = HttpClient::new(url)?;
+    let mut ticker = tokio::time::interval(Duration::from_secs_f64(1.0 / rate));
+    let mut handles = Vec::new();
+    for transaction in transactions {
+        ticker.tick().await;
+        let client = client.clone();
+        handles.push(tokio::spawn(
+            async move { submit(&client, &transaction).await },
+        ));
+    }
+    let mut submissions = Vec::new();
+    for handle in handles {
+        submissions.push(handle.await?);
+    }
+
+    info!("Waiting for the transactions to be committed");
+    let (accepted, rejected): (Vec<_>, Vec<_>) = submissions
+        .into_iter()
+        .partition(|submission| submission.accepted);
+    let check_latencies = accepted.iter().map(|s| s.check_latency).collect();
+    let first_submission = accepted.iter().map(|s| s.submitted_at).min();
+
+    let deadline = Instant::now() + timeout;
+    let mut block_times = HashMap::new();
+    let mut commit_latencies = Vec::new();
+    let mut pending = accepted;
+    let mut last_commit = None;
+    while !pending.is_empty() && Instant::now() < deadline {
+        let mut still_pending = Vec::new();
+        for submission in pending {
+            let height = match committed_height(&client, &submission.id).await? {
+                Some(height) => height,
+                None => {
+                    still_pending.push(submission);
+                    continue;
+                }
+            };
+
+            let block_time = match block_times.get(&height) {
+                Some(time) => *time,
+                None => {
+                    let time = block_time(&client, height).await?;
+                    block_times.insert(height, time);
+                    time
+                }
+            };
+            commit_latencies.push(
+                block_time
+                    .duration_since(submission.submitted_at)
+                    .unwrap_or_default(),
+            );
+            last_commit = last_commit.max(Some(block_time));
+        }
+        pending = still_pending;
+
+        if !pending.is_empty() {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    }
+    if !pending.is_empty() {
+        warn!("{} transactions weren't committed", pending.len());
+    }
+
+    let committed = commit_latencies.len();
+    let elapsed = first_submission
+        .zip(last_commit)
+        .and_then(|(first, last)| last.duration_since(first).ok())
+        .unwrap_or_default();
+    let tps = if elapsed.is_zero() {
+        0.0
+    } else {
+        committed as f64 / elapsed.as_secs_f64()
+    };
+
+    Ok(json!({
+        "preparation_seconds": preparation_time.as_secs_f64(),
+        "rejected_by_check_tx": rejected.len(),
+        "committed": committed,
+        "not_committed": pending.len(),
+        "blocks": block_times.len(),
+        "check_tx_latency_ms": latency_stats(check_latencies),
+        "commit_latency_ms": latency_stats(commit_latencies),
+        "elapsed_seconds": elapsed.as_secs_f64(),
+        "tps": tps,
+    }))
+}
+
+/// Generate the given amount of transfers, spread across the accounts. Each account transfers its whole balance
+/// to itself, spending the output of its previous transfer, so no record is spent twice. The transfers are returned
+/// in round robin order, so the ones of each account are sent in the order they were generated.
+fn generate_transactions(accounts: &[BenchAccount], amount: usize) -> Result<Vec<Transaction>> {
+    let per_account = (amount + accounts.len() - 1) / accounts.len();
+    let parallelism = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = (accounts.len() + parallelism - 1) / parallelism;
+
+    let chains = std::thread::scope(|scope| {
+        let handles: Vec<_> = accounts
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|account| transfer_chain(account, per_account))
+                        .collect::<Result<Vec<_>>>()
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("transaction generation panicked"))
+            .collect::<Result<Vec<_>>>()
+    })?;
+    let chains: Vec<Vec<Transaction>> = chains.into_iter().flatten().collect();
+
+    let mut transactions = Vec::new();
+    for round in 0..per_account {
+        transactions.extend(chains.iter().filter_map(|chain| chain.get(round).cloned()));
+    }
+    transactions.truncate(amount);
+    Ok(transactions)
+}
+
+#[allow(clippy::clone_on_copy)] // identifiers are strings on lambdavm and so clippy generates a warning for `.clone()`
+fn transfer_chain(account: &BenchAccount, length: usize) -> Result<Vec<Transaction>> {
+    let view_key = vm::ViewKey::try_from(&account.private_key)?;
+    let address = vm::Address::try_from(&view_key)?;
+    let recipient = vm::UserInputValueType::from_str(&address.to_string())?;
+    let function = vm::Identifier::from_str("transfer")?;
+    let mut record = account.record.1.decrypt(&view_key)?;
+
+    (0..length)
+        .map(|_| {
+            let amount = vm::gates(&record);
+            let inputs = [
+                vm::UserInputValueType::Record(record.clone()),
+                recipient.clone(),
+                vm::u64_to_value(amount),
+            ];
+            let transaction = Transaction::credits_execution(
+                function.clone(),
+                &inputs,
+                &account.private_key,
+                None,
+            )?;
+
+            // both outputs belong to the account, keep spending the one with the transferred gates
+            record = transaction
+                .output_records()
+                .iter()
+                .filter_map(|(_commitment, record)| record.decrypt(&view_key).ok())
+                .max_by_key(vm::gates)
+                .ok_or_else(|| anyhow!("transfer has no output records"))?;
+            Ok(transaction)
+        })
+        .collect()
+}
+
+/// Send the transaction without retries, measuring how long the mempool validations take.
+async fn submit(client: &HttpClient, transaction: &Transaction) -> Submission {
+    let submitted_at = SystemTime::now();
+    let start = Instant::now();
+    let tx: tendermint::abci::Transaction = bincode::serialize(transaction).unwrap().into();
+    let accepted = match client.broadcast_tx_sync(tx).await {
+        Ok(response) => matches!(response.code, tendermint::abci::Code::Ok),
+        Err(e) => {
+            warn!("failed to broadcast transaction {}: {e}", transaction.id());
+            false
+        }
+    };
+
+    Submission {
+        id: transaction.id().to_string(),
+        submitted_at,
+        check_latency: start.elapsed(),
+        accepted,
+    }
+}
+
+/// Return the height of the block that included the transaction, if it was successfully delivered.
+async fn committed_height(client: &HttpClient, tx_id: &str) -> Result<Option<u64>> {
+    let response = client
+        .tx_search(
+            Query::contains("app.tx_id", tx_id),
+            false,
+            1,
+            1,
+            Order::Ascending,
+        )
+        .await?;
+    Ok(response.txs.first().map(|tx| tx.height.value()))
+}
+
+async fn block_time(client: &HttpClient, height: u64) -> Result<SystemTime> {
+    let block = client.block(Height::try_from(height)?).await?.block;
+    let since_epoch = block
+        .header
+        .time
+        .duration_since(tendermint::Time::unix_epoch())?;
+    Ok(SystemTime::UNIX_EPOCH + since_epoch)
+}
+
+/// Summarize the latencies in milliseconds, null if there are none.
+fn latency_stats(mut latencies: Vec<Duration>) -> serde_json::Value {
+    if latencies.is_empty() {
+        return serde_json::Value::Null;
+    }
+    latencies.sort();
+    let percentile = |p: usize| latencies[(latencies.len() - 1) * p / 100].as_secs_f64() * 1000.0;
+    json!({
+        "p50": percentile(50),
+        "p90": percentile(90),
+        "p99": percentile(99),
+        "max": percentile(100),
+    })
+}
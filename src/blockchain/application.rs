@@ -1,17 +1,41 @@
-use std::path::Path;
-use std::sync::{Arc, Mutex};
-
-use crate::program_store::ProgramStore;
-use crate::record_store::RecordStore;
-use crate::validator_set::ValidatorSet;
-use anyhow::{bail, ensure, Result};
-use itertools::Itertools;
-use lib::validator::GenesisState;
-use lib::{query::AbciQuery, transaction::Transaction, vm};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::config::{NodeConfig, ProgramPolicy, StorageBackend, VerificationLevel};
+use crate::health::{HealthReport, Liveness};
+use crate::kv_backend::Column;
+use crate::pending_relay::PendingRelay;
+use crate::pipeline::TransactionPipeline;
+use crate::program_peers::ProgramPeers;
+use anyhow::{anyhow, ensure, Result};
+use lib::bridge::{self, PacketsResponse};
+use lib::diagnostic::{Check, Diagnostic};
+use lib::pending::PendingRecords;
+use lib::query::{
+    AbciQuery, BlockEvent, BlockEventsResponse, BlockRewards, BlockRewardsResponse, ChainStatus,
+    MemosResponse, NameResponse, NodeInfo, NodeRole, ProgramAvailability, ProgramListResponse,
+    ProgramResponse, QueryResponse, RecordEntry, RecordResponse, RecordsResponse,
+    SourceVerification, SpentEntry, SpentResponse, StoreStats, TombstonesResponse,
+    ValidatorPerformance, ValidatorPerformanceResponse, ValidatorsResponse, VerifyingKeysResponse,
+    DEFAULT_PERFORMANCE_BLOCKS, MAX_REWARDS_BLOCKS,
+};
+use lib::transaction::{Transaction, MAX_BLOCK_GAS};
+use lib::validator::{GenesisState, Validator};
+use lib::{merkle, names, vm};
 use tendermint_abci::Application;
 use tendermint_proto::abci;
+use tendermint_proto::crypto::{ProofOp, ProofOps};
 
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, info_span};
+
+/// Version of the application logic implemented by this binary, reported to tendermint.
+/// Chain upgrades are coordinated by raising the required version through governance, see `halt_if_outdated`.
+/// It should be bumped along with the store schema version when adding migrations, see `migrations::MIGRATIONS`.
+const APP_VERSION: u64 = 6;
+
+/// Used to annualize the validator rewards, see `AbciQuery::GetValidatorPerformance`.
+const SECONDS_PER_YEAR: u64 = 365 * 24 * 60 * 60;
 
 /// An Tendermint ABCI application that works with a SnarkVM backend.
 /// This struct implements the ABCI application hooks, forwarding commands through
@@ -19,14 +43,118 @@ use tracing::{debug, error, info};
 /// For reference see https://docs.tendermint.com/v0.34/introduction/what-is-tendermint.html#abci-overview
 #[derive(Debug, Clone)]
 pub struct SnarkVMApp {
-    records: RecordStore,
-    programs: ProgramStore,
-
-    // NOTE: Wrapping in mutex here because we need mut access to ValidatorSet and the alternative to setup
-    // a channel was overkilll for this particular case. Also, at the moment we only ever access these field
-    // from a single tendermint abci connection (the consensus connection), but using Rc instead of Arc would
-    // introduce subtle bugs should that ever change.
-    validators: Arc<Mutex<ValidatorSet>>,
+    /// The application state and the validation and application of transactions, which the hooks drive.
+    pipeline: TransactionPipeline,
+
+    /// Operator policy applied to the transactions relayed by this node, see `reload_policy`.
+    policy: Arc<RwLock<ProgramPolicy>>,
+    policy_file: Option<PathBuf>,
+
+    /// Transactions in the mempool by sender, to enforce `NodeConfig::max_mempool_txs_per_sender`.
+    mempool_senders: Arc<Mutex<MempoolSenders>>,
+    max_mempool_txs_per_sender: Option<usize>,
+
+    /// Verification applied to the transactions before accepting them in the mempool, see `VerificationLevel`.
+    mempool_verification: VerificationLevel,
+
+    /// Programs deployed by transactions in the mempool, so their executions can be included in the same block.
+    pending_deployments: Arc<Mutex<PendingDeployments>>,
+
+    /// Announces the output records of the transactions accepted in the mempool, and pushes the committed ones
+    /// to the subscribers that registered view keys, if enabled by the operator.
+    pending_relay: Option<PendingRelay>,
+
+    /// Signals of the ABCI connections reported by the health endpoints, see `health`.
+    liveness: Arc<Liveness>,
+
+    /// Which history of the chain the node keeps, see `NodeRole`.
+    role: NodeRole,
+}
+
+/// Ids of the transactions accepted in the mempool by their sender. Transactions are released when they are delivered
+/// in a block or fail a mempool recheck.
+#[derive(Debug, Default)]
+struct MempoolSenders {
+    transactions: HashMap<String, HashSet<String>>,
+    senders: HashMap<String, String>,
+}
+
+impl MempoolSenders {
+    /// Count the transaction for its sender, failing if the sender already has `limit` other transactions in the mempool.
+    fn reserve(&mut self, sender: &str, tx_id: &str, limit: usize) -> Result<()> {
+        let transactions = self.transactions.entry(sender.to_string()).or_default();
+        if !transactions.contains(tx_id) {
+            ensure!(
+                transactions.len() < limit,
+                "Sender {sender} already has {limit} transactions in the mempool"
+            );
+            transactions.insert(tx_id.to_string());
+            self.senders.insert(tx_id.to_string(), sender.to_string());
+        }
+        Ok(())
+    }
+
+    /// Stop counting the transaction, if it was counted.
+    fn release(&mut self, tx_id: &str) {
+        if let Some(sender) = self.senders.remove(tx_id) {
+            if let Some(transactions) = self.transactions.get_mut(&sender) {
+                transactions.remove(tx_id);
+                if transactions.is_empty() {
+                    self.transactions.remove(&sender);
+                }
+            }
+        }
+    }
+}
+
+/// Verifying keys of the programs deployed by the transactions accepted in the mempool and not delivered yet.
+/// The mempool accepts executions of these programs optimistically, so they can be included in the same block as
+/// the deployment. Blocks are still validated deterministically: deliver_tx applies their transactions in order,
+/// so an execution is only valid if its program was deployed by a previous transaction or block.
+/// Deployments are released when they are delivered in a block or fail a mempool recheck.
+#[derive(Debug, Default)]
+struct PendingDeployments {
+    keys: Arc<HashMap<String, vm::VerifyingKeyMap>>,
+    programs: HashMap<String, String>,
+}
+
+impl PendingDeployments {
+    /// Make the program of a deployment available to the mempool, ignoring any other kind of transaction.
+    fn add(&mut self, transaction: &Transaction) {
+        // signed deployments are still released by the id of the envelope, which is the one in the mempool
+        let deployment = match transaction {
+            Transaction::Signed { transaction, .. } => transaction.as_ref(),
+            transaction => transaction,
+        };
+        if let Transaction::Deployment {
+            program,
+            verifying_keys,
+            ..
+        } = deployment
+        {
+            let program_id = program.id().to_string();
+            Arc::make_mut(&mut self.keys).insert(program_id.clone(), verifying_keys.clone());
+            self.programs
+                .insert(transaction.id().to_string(), program_id);
+        }
+    }
+
+    /// Stop accepting executions of the program of the deployment, if it was added.
+    fn release(&mut self, tx_id: &str) {
+        if let Some(program_id) = self.programs.remove(tx_id) {
+            Arc::make_mut(&mut self.keys).remove(&program_id);
+        }
+    }
+
+    /// Whether a deployment of the program is pending.
+    fn contains(&self, program_id: &vm::ProgramID) -> bool {
+        self.keys.contains_key(&program_id.to_string())
+    }
+
+    /// Return the keys of the pending programs, without holding the lock while verifying the transactions.
+    fn keys(&self) -> Arc<HashMap<String, vm::VerifyingKeyMap>> {
+        self.keys.clone()
+    }
 }
 
 impl Application for SnarkVMApp {
@@ -39,15 +167,75 @@ impl Application for SnarkVMApp {
         let state: GenesisState =
             serde_json::from_slice(&request.app_state_bytes).expect("invalid genesis state");
 
-        for (commitment, record) in state.records {
+        // fail loudly rather than starting a chain where the app and tendermint disagree on the validators
+        let tendermint_validators = tendermint_validators(&request.validators)
+            .expect("invalid tendermint genesis validators");
+        let validators = state
+            .merge_validators(&tendermint_validators)
+            .expect("genesis app state validators don't match the tendermint ones");
+        self.pipeline.validators.replace(validators);
+        self.pipeline.bridge.lock().unwrap().init(&state.bridge);
+
+        // genesis validators below the minimum self stake or past the maximum amount of validators start as candidates
+        let mut governance = self.pipeline.governance.lock().unwrap();
+        governance.init(&state);
+        self.pipeline.validators.set_params(governance.params());
+        drop(governance);
+        self.pipeline.validators.select_active();
+        let validators = self.pipeline.validators.validators();
+
+        let vesting_records = state
+            .vesting_records()
+            .expect("invalid genesis vesting schedules");
+        for (commitment, record) in state.records.into_iter().chain(vesting_records) {
             debug!("Storing genesis record {}", commitment);
-            self.records
-                .add(commitment, record)
+            self.pipeline
+                .records
+                .index_program_record("credits.aleo", &commitment)
+                .and_then(|_| self.pipeline.records.add(commitment, record))
                 .expect("failure adding genesis records");
         }
 
-        self.validators.lock().unwrap().replace(state.validators);
-        Default::default()
+        // if the tendermint genesis has no validators, it takes the ones of the app state from the response,
+        // and so it does if some of its validators were left out of the active set
+        let validator_updates = if tendermint_validators.is_empty()
+            || validators.iter().any(|validator| validator.candidate)
+        {
+            validators
+                .iter()
+                .filter(|validator| validator.consensus_power() > 0)
+                .map(|validator| abci::ValidatorUpdate {
+                    pub_key: Some(validator.pub_key.into()),
+                    power: validator.consensus_power() as i64,
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        // tendermint always sends the chain id, but in-process test chains may leave it empty
+        if !request.chain_id.is_empty() {
+            self.pipeline
+                .records
+                .set_chain_id(&request.chain_id)
+                .expect("failure storing the chain id");
+            *self.pipeline.chain_id.write().unwrap() = Some(request.chain_id);
+        }
+
+        // make tendermint fill the blocks up to the gas limit enforced in deliver_tx,
+        // based on the gas_wanted returned by check_tx for each transaction
+        let consensus_params = request.consensus_params.map(|mut params| {
+            let mut block = params.block.unwrap_or_default();
+            block.max_gas = MAX_BLOCK_GAS as i64;
+            params.block = Some(block);
+            params
+        });
+
+        abci::ResponseInitChain {
+            consensus_params,
+            validators: validator_updates,
+            ..Default::default()
+        }
     }
 
     /// This hook provides information about the ABCI application.
@@ -56,51 +244,224 @@ impl Application for SnarkVMApp {
             "Got info request. Tendermint version: {}; Block version: {}; P2P version: {}",
             request.version, request.block_version, request.p2p_version
         );
+        self.liveness.connected();
 
+        // clients read the role to know whether to send the queries about older heights to an archive node
+        let node_info = NodeInfo {
+            role: self.role,
+            earliest_height: self.earliest_height(),
+        };
+        // on startup tendermint replays the blocks after this height, the one of the committed state, see
+        // `HeightFile::reconcile`, and refuses to start if the state is ahead of the blocks it has
         abci::ResponseInfo {
-            data: "snarkvm-app".to_string(),
+            data: serde_json::to_string(&node_info).unwrap_or_default(),
             version: "0.1.0".to_string(),
-            app_version: 1,
-            last_block_height: HeightFile::read_or_create(),
-
-            // using a fixed hash, see the commit() hook
-            last_block_app_hash: vec![],
+            app_version: APP_VERSION,
+            last_block_height: self.pipeline.height.get(),
+            last_block_app_hash: self.pipeline.app_hash(),
         }
     }
 
     /// This hook is to query the application for data at the current or past height.
     fn query(&self, request: abci::RequestQuery) -> abci::ResponseQuery {
+        let proof_ops = match bincode::deserialize(&request.data) {
+            Ok(query) if request.prove => self.query_proof(&query),
+            _ => Ok(None),
+        };
+
         let query_result = match bincode::deserialize(&request.data) {
-            Ok(AbciQuery::GetRecords) => {
+            Ok(AbciQuery::GetRecords { from, limit }) => {
                 debug!("Fetching records");
-                // TODO: This fetches all the records from the RecordStore to filter here the
-                // owned ones. With a large database this will involve a lot of data/time
-                // so we should think of a better way to handle this. (eg. pagination or asynchronous
-                // querying)
-                // https://trello.com/c/bP8Nbs7C/170-handle-record-querying-properly-in-recordstore
-                self.records
-                    .scan(None, None)
-                    .map(|result| bincode::serialize(&result).unwrap())
+                self.records_page(None, from, limit)
+                    .and_then(QueryResponse::encode)
+            }
+            Ok(AbciQuery::GetMemos { commitments }) => {
+                debug!("Fetching record memos");
+                self.pipeline
+                    .records
+                    .memos(&commitments)
+                    .and_then(|memos| QueryResponse::encode(MemosResponse { memos }))
             }
             Ok(AbciQuery::GetSpentSerialNumbers) => {
                 debug!("Fetching spent records's serial numbers");
-
-                self.records
-                    .scan_spent()
-                    .map(|result| bincode::serialize(&result).unwrap())
+                self.pipeline
+                    .records
+                    .scan_spent_with_heights()
+                    .and_then(|spent| {
+                        let serial_numbers = spent
+                            .into_iter()
+                            .map(|(serial_number, height)| SpentEntry {
+                                serial_number,
+                                height,
+                            })
+                            .collect();
+                        QueryResponse::encode(SpentResponse { serial_numbers })
+                    })
             }
             Ok(AbciQuery::GetProgram { program_id }) => {
                 debug!("Fetching {}", program_id);
-                self.programs.get(&program_id).map(|result| {
-                    bincode::serialize(&result.map(|(program, _keys)| program)).unwrap()
+                self.program_response(&program_id)
+                    .and_then(QueryResponse::encode)
+            }
+            Ok(AbciQuery::GetRecord { commitment }) => {
+                debug!("Fetching record {commitment}");
+                self.pipeline
+                    .records
+                    .get(&commitment)
+                    .and_then(|record| QueryResponse::encode(RecordResponse { record }))
+            }
+            Ok(AbciQuery::GetValidators) => {
+                debug!("Fetching validators");
+                let validators = self.pipeline.validators.validators();
+                QueryResponse::encode(ValidatorsResponse { validators })
+            }
+            Ok(AbciQuery::ListPrograms {
+                prefix,
+                from,
+                limit,
+            }) => {
+                debug!("Listing programs");
+                self.pipeline
+                    .programs
+                    .list(prefix, from, limit)
+                    .and_then(|(programs, cursor)| {
+                        QueryResponse::encode(ProgramListResponse { programs, cursor })
+                    })
+            }
+            Ok(AbciQuery::GetSpendProof { serial_number }) => {
+                debug!("Fetching spend proof for {serial_number}");
+                self.pipeline
+                    .records
+                    .spent_proof(&serial_number)
+                    .and_then(|proof| self.state_proof(merkle::SPENT_COMPONENT, proof))
+                    .and_then(QueryResponse::encode)
+            }
+            Ok(AbciQuery::GetRecordProof { commitment }) => {
+                debug!("Fetching record proof for {commitment}");
+                self.pipeline
+                    .records
+                    .record_proof(&commitment)
+                    .and_then(|proof| self.state_proof(merkle::RECORDS_COMPONENT, proof))
+                    .and_then(QueryResponse::encode)
+            }
+            Ok(AbciQuery::GetProgramProof { program_id }) => {
+                debug!("Fetching program proof for {program_id}");
+                self.pipeline
+                    .programs
+                    .proof(&program_id)
+                    .and_then(|proof| self.state_proof(merkle::PROGRAMS_COMPONENT, proof))
+                    .and_then(QueryResponse::encode)
+            }
+            Ok(AbciQuery::GetGovernance) => {
+                debug!("Fetching governance status");
+                let status = self.pipeline.governance.lock().unwrap().status();
+                QueryResponse::encode(status)
+            }
+            Ok(AbciQuery::GetCommunityPool) => {
+                debug!("Fetching community pool");
+                let pool = self.pipeline.governance.lock().unwrap().community_pool();
+                QueryResponse::encode(pool)
+            }
+            Ok(AbciQuery::EstimateFee {
+                program_id,
+                function,
+            }) => {
+                debug!("Estimating fee for {program_id}/{function}");
+                self.pipeline
+                    .estimate_fee(&program_id, &function)
+                    .and_then(QueryResponse::encode)
+            }
+            Ok(AbciQuery::GetProgramStats { program_id }) => {
+                debug!("Fetching stats of {program_id}");
+                if self.pipeline.programs.exists(&program_id) {
+                    self.pipeline
+                        .programs
+                        .stats(&program_id)
+                        .and_then(QueryResponse::encode)
+                } else {
+                    Err(anyhow!("Program {program_id} does not exist"))
+                }
+            }
+            Ok(AbciQuery::VerifySource { program_id, source }) => {
+                debug!("Verifying the source of {program_id}");
+                self.verify_source(&program_id, &source)
+                    .and_then(QueryResponse::encode)
+            }
+            Ok(AbciQuery::GetBlockEvents { height }) => {
+                debug!("Fetching events of block {height}");
+                self.block_events_at(height)
+                    .and_then(|events| QueryResponse::encode(BlockEventsResponse { events }))
+            }
+            Ok(AbciQuery::GetRecordsByProgram {
+                program_id,
+                from,
+                limit,
+            }) => {
+                debug!("Fetching records of {program_id}");
+                self.records_page(Some(&program_id), from, limit)
+                    .and_then(QueryResponse::encode)
+            }
+            Ok(AbciQuery::GetBridgeStatus) => {
+                debug!("Fetching bridge status");
+                let status = self.pipeline.bridge.lock().unwrap().status();
+                QueryResponse::encode(status)
+            }
+            Ok(AbciQuery::GetPackets {
+                destination_chain,
+                from_sequence,
+                limit,
+            }) => {
+                debug!("Fetching packets to {destination_chain}");
+                self.packets_page(&destination_chain, from_sequence, limit)
+                    .and_then(QueryResponse::encode)
+            }
+            Ok(AbciQuery::GetBlockRewards { address, from, to }) => {
+                debug!("Fetching block rewards from height {from}");
+                self.block_rewards_in(address.as_ref(), from, to)
+                    .and_then(|blocks| QueryResponse::encode(BlockRewardsResponse { blocks }))
+            }
+            Ok(AbciQuery::GetTombstones) => {
+                debug!("Fetching tombstoned validators");
+                let validators = self.pipeline.validators.tombstones();
+                QueryResponse::encode(TombstonesResponse { validators })
+            }
+            Ok(AbciQuery::GetVerifyingKeys { program_id }) => {
+                debug!("Fetching the verifying keys of {program_id}");
+                self.pipeline.programs.get(&program_id).and_then(|program| {
+                    QueryResponse::encode(VerifyingKeysResponse {
+                        verifying_keys: program.map(|(_program, keys)| keys),
+                    })
                 })
             }
+            Ok(AbciQuery::GetValidatorPerformance { blocks }) => {
+                debug!("Fetching validator performance");
+                self.validator_performance(blocks)
+                    .and_then(QueryResponse::encode)
+            }
+            Ok(AbciQuery::GetChainStatus) => {
+                debug!("Fetching chain status");
+                self.chain_status().and_then(QueryResponse::encode)
+            }
+            Ok(AbciQuery::GetStoreStats) => {
+                debug!("Fetching store stats");
+                self.store_stats().and_then(QueryResponse::encode)
+            }
+            Ok(AbciQuery::GetProgramAvailability { program_id }) => {
+                debug!("Fetching availability of {program_id}");
+                self.program_availability(&program_id)
+                    .and_then(QueryResponse::encode)
+            }
+            Ok(AbciQuery::ResolveName { name }) => {
+                debug!("Resolving name {name}");
+                self.resolve_name(name).and_then(QueryResponse::encode)
+            }
             Err(e) => Err(e.into()),
         };
 
-        match query_result {
-            Ok(value) => abci::ResponseQuery {
+        match query_result.and_then(|value| Ok((value, proof_ops?))) {
+            Ok((value, proof_ops)) => abci::ResponseQuery {
                 value,
+                proof_ops,
                 ..Default::default()
             },
             Err(e) => abci::ResponseQuery {
@@ -115,29 +476,64 @@ impl Application for SnarkVMApp {
     /// This ABCI hook validates an incoming transaction before inserting it in the
     /// mempool and relaying it to other nodes.
     fn check_tx(&self, request: abci::RequestCheckTx) -> abci::ResponseCheckTx {
-        let tx: Transaction = bincode::deserialize(&request.tx).unwrap();
+        let tx: Transaction = match bincode::deserialize(&request.tx) {
+            Ok(tx) => tx,
+            Err(e) => {
+                return abci::ResponseCheckTx {
+                    code: 1,
+                    log: Diagnostic::new(Check::Decode, &e).to_json(),
+                    info: format!("Could not decode transaction: {e}"),
+                    ..Default::default()
+                }
+            }
+        };
+        // the transactions are checked against the state of the next block
+        let _span = info_span!(
+            "check_tx",
+            tx_id = %tx.id(),
+            height = self.pipeline.height.get() + 1
+        )
+        .entered();
         info!("Check Tx ID: {}", tx.id());
 
+        // a rechecked deployment is added again if still valid, it shouldn't conflict with its own program
+        self.pending_deployments.lock().unwrap().release(tx.id());
+
+        let gas = tx.gas();
         let result = self
-            .check_no_duplicate_records(&tx)
-            .and_then(|_| self.check_inputs_are_unspent(&tx))
-            .and_then(|_| self.validate_transaction(&tx));
+            .check_program_policy(&tx)
+            .and_then(|_| self.validate_for_mempool(&tx))
+            .and_then(|_| self.reserve_sender_slot(&tx));
 
-        // by making the priority equal to the fees we give more priority to higher-paying transactions
+        // by making the priority equal to the fees per kilobyte we give more priority to higher-paying transactions,
+        // without letting big transactions jump ahead of small ones just because their total fee is higher
         // NOTE: we haven't thoroughly tested tendermint prioritized mempool, see for background
         // https://github.com/tendermint/tendermint/discussions/9772
-        let priority = tx.fees();
+        let priority = fee_priority(&tx);
 
         if let Err(err) = result {
+            // a transaction that fails a recheck is dropped from the mempool
+            self.mempool_senders.lock().unwrap().release(tx.id());
+
+            // the log has the structured diagnostic for clients, the info the message for humans
             abci::ResponseCheckTx {
                 code: 1,
-                log: format!("Could not verify transaction: {err}"),
+                log: Diagnostic::of(&err).for_transaction(tx.id()).to_json(),
                 info: format!("Could not verify transaction: {err}"),
                 ..Default::default()
             }
         } else {
+            self.pending_deployments.lock().unwrap().add(&tx);
+
+            // rechecked transactions were already announced when they reached the mempool
+            if request.r#type == abci::CheckTxType::New as i32 {
+                if let Some(relay) = &self.pending_relay {
+                    relay.announce(&PendingRecords::of(&tx));
+                }
+            }
             abci::ResponseCheckTx {
                 priority,
+                gas_wanted: gas as i64,
                 ..Default::default()
             }
         }
@@ -145,7 +541,7 @@ impl Application for SnarkVMApp {
 
     /// This hook is called before the app starts processing transactions on a block.
     /// Used to store current proposer and the previous block's voters to assign fees and coinbase
-    /// credits when the block is committed.
+    /// credits when the block is committed, and to punish the validators reported for double signing.
     fn begin_block(&self, request: abci::RequestBeginBlock) -> abci::ResponseBeginBlock {
         // a call to begin block without header doesn't seem to make sense, verify it can happen
         // supporting this case is cumbersome, assuming it won't happen until proven wrong
@@ -153,6 +549,10 @@ impl Application for SnarkVMApp {
             .header
             .expect("received block without header, aborting");
 
+        let _span = info_span!("begin_block", height = header.height).entered();
+        self.halt_if_outdated(header.height);
+        self.halt_if_out_of_sequence(header.height);
+
         // store current block proposer and previous block voters in the validator set
         // NOTE: because of how tendermint makes information available to this hook,
         // the block rewards go to this block's porposer and the **previous** block voters.
@@ -182,13 +582,50 @@ impl Application for SnarkVMApp {
             })
             .collect();
 
-        self.validators.lock().unwrap().begin_block(
-            &header.proposer_address,
-            votes,
-            header.height as u64,
-        );
+        let time = header
+            .time
+            .and_then(|time| u64::try_from(time.seconds).ok());
+        self.pipeline
+            .begin_block(&header.proposer_address, votes, header.height as u64, time);
 
-        Default::default()
+        // validators that signed two different blocks at the same height are slashed and tombstoned.
+        // The evidence of light client attacks isn't handled yet.
+        let events: Vec<abci::Event> = request
+            .byzantine_validators
+            .iter()
+            .filter(|evidence| evidence.r#type == abci::EvidenceType::DuplicateVote as i32)
+            .filter_map(|evidence| {
+                let address = &evidence.validator.as_ref()?.address;
+                let validator = self
+                    .pipeline
+                    .validators
+                    .tombstone(address, evidence.height as u64)?;
+                let offense = validator.tombstone.as_ref()?;
+                info!(
+                    "Tombstoned validator {validator} for double signing at height {}, slashing {} gates",
+                    offense.height, offense.slashed
+                );
+                let attribute = |key: &str, value: String, index: bool| abci::EventAttribute {
+                    key: key.to_string().into_bytes(),
+                    value: value.into_bytes(),
+                    index,
+                };
+                Some(abci::Event {
+                    r#type: "tombstone".to_string(),
+                    attributes: vec![
+                        attribute("address", hex::encode_upper(validator.address()), true),
+                        attribute("aleo_address", validator.aleo_address.to_string(), false),
+                        attribute("height", offense.height.to_string(), false),
+                        attribute("slashed", offense.slashed.to_string(), false),
+                    ],
+                })
+            })
+            .collect();
+        for event in &events {
+            self.pipeline.push_event(block_event(event));
+        }
+
+        abci::ResponseBeginBlock { events }
     }
 
     /// This ABCI hook validates a transaction and applies it to the application state,
@@ -197,64 +634,111 @@ impl Application for SnarkVMApp {
     fn deliver_tx(&self, request: abci::RequestDeliverTx) -> abci::ResponseDeliverTx {
         info!("Deliver Tx");
 
-        let tx: Transaction = bincode::deserialize(&request.tx).unwrap();
+        // a byzantine proposer could include bytes that aren't a transaction
+        let tx: Transaction = match bincode::deserialize(&request.tx) {
+            Ok(tx) => tx,
+            Err(e) => {
+                self.liveness
+                    .failed(format!("Error decoding delivered transaction: {e}"));
+                return abci::ResponseDeliverTx {
+                    code: 1,
+                    log: Diagnostic::new(Check::Decode, &e).to_json(),
+                    info: format!("Could not decode transaction: {e}"),
+                    ..Default::default()
+                };
+            }
+        };
+        let _span = info_span!(
+            "deliver_tx",
+            tx_id = %tx.id(),
+            height = self.pipeline.height.get() + 1
+        )
+        .entered();
+        self.mempool_senders.lock().unwrap().release(tx.id());
+        self.pending_deployments.lock().unwrap().release(tx.id());
 
-        // we need to repeat the same validations as deliver_tx and only, because the protocol can't
-        // guarantee that a bynzantine validator won't propose a block with invalid transactions.
-        // if validation they pass  apply (but not commit) the application state changes.
-        // Note that we check for duplicate records within the transaction before attempting to spend them
-        // so we don't end up with a half-applied transaction in the record store.
-        let result = self
-            .check_no_duplicate_records(&tx)
-            .and_then(|_| self.check_inputs_are_unspent(&tx))
-            .and_then(|_| self.validate_transaction(&tx))
-            .map(|_| self.update_validators(&tx))
-            .and_then(|_| self.spend_input_records(&tx))
-            .and_then(|_| self.add_output_records(&tx))
-            .and_then(|_| self.store_program(&tx));
-
-        match result {
+        // if the validations pass, apply (but not commit) the application state changes
+        match self.pipeline.apply(&tx) {
             Ok(_) => {
-                // prepare this transaction to be queried by app.tx_id
+                if let Some(relay) = &self.pending_relay {
+                    relay.stage(PendingRecords::of(&tx));
+                }
+
+                // prepare this transaction to be queried by app.tx_id, or by app.program for the programs it
+                // deploys or runs, and by app.sender for signed transactions. the kind and fee are part of the
+                // receipts shown by the client
+                let attribute = |key: &str, value: String, index: bool| abci::EventAttribute {
+                    key: key.to_string().into_bytes(),
+                    value: value.into_bytes(),
+                    index,
+                };
+                let mut attributes = vec![
+                    attribute("tx_id", tx.id().to_string(), true),
+                    attribute("kind", tx.kind().to_string(), false),
+                    attribute("fee", tx.fees().to_string(), false),
+                ];
+                attributes.extend(
+                    tx.programs()
+                        .into_iter()
+                        .map(|program| attribute("program", program, true)),
+                );
+                if let Transaction::Signed { sender, .. } = &tx {
+                    attributes.push(attribute("sender", sender.to_string(), true));
+                }
                 let index_event = abci::Event {
                     r#type: "app".to_string(),
-                    attributes: vec![abci::EventAttribute {
-                        key: "tx_id".to_string().into_bytes(),
-                        value: tx.id().to_string().into_bytes(),
-                        index: true,
-                    }],
+                    attributes,
                 };
+                self.pipeline.push_event(block_event(&index_event));
 
                 abci::ResponseDeliverTx {
                     events: vec![index_event],
+                    gas_wanted: tx.gas() as i64,
+                    gas_used: tx.gas() as i64,
+                    ..Default::default()
+                }
+            }
+            Err(e) => {
+                self.liveness
+                    .failed(format!("Error delivering transaction {}: {e}", tx.id()));
+                self.pipeline.record_failed_deployments(&tx, &e);
+                abci::ResponseDeliverTx {
+                    code: 1,
+                    log: Diagnostic::of(&e).for_transaction(tx.id()).to_json(),
+                    info: format!("Error delivering transaction: {e}"),
                     ..Default::default()
                 }
             }
-            Err(e) => abci::ResponseDeliverTx {
-                code: 1,
-                log: format!("Error delivering transaction: {e}"),
-                info: format!("Error delivering transaction: {e}"),
-                ..Default::default()
-            },
         }
     }
 
-    /// Applies validator set updates based on staking transactions included in the block.
+    /// Applies validator set updates based on staking transactions included in the block,
+    /// and the executions that were scheduled for this block height.
     /// For details about validator set update semantics see:
     /// https://github.com/tendermint/tendermint/blob/v0.34.x/spec/abci/apps.md#endblock
-    fn end_block(&self, _request: abci::RequestEndBlock) -> abci::ResponseEndBlock {
-        let validator_set = self.validators.lock().unwrap();
-        let validator_updates = validator_set
+    fn end_block(&self, request: abci::RequestEndBlock) -> abci::ResponseEndBlock {
+        let _span = info_span!("end_block", height = request.height).entered();
+        let mut events = self.apply_scheduled_executions(request.height as u64);
+        let (entered, left) = self.pipeline.validators.select_active();
+        events.extend(validator_set_events(&entered, &left));
+        for event in &events {
+            self.pipeline.push_event(block_event(event));
+        }
+
+        let validator_updates = self
+            .pipeline
+            .validators
             .pending_updates()
             .iter()
             .map(|validator| abci::ValidatorUpdate {
                 pub_key: Some(validator.pub_key.into()),
-                power: validator.voting_power as i64,
+                power: validator.consensus_power() as i64,
             })
             .collect();
 
         abci::ResponseEndBlock {
             validator_updates,
+            events,
             ..Default::default()
         }
     }
@@ -266,249 +750,757 @@ impl Application for SnarkVMApp {
     /// This hash should be deterministic, different app state hashes will produce blockchain forks.
     /// New credits records are created to assign validator rewards.
     fn commit(&self) -> abci::ResponseCommit {
-        // the app hash is intended to capture the state of the application that's not contained directly
-        // in the blockchain transactions (as tendermint already accounts for that with other hashes).
-        // we could do something in the RecordStore and ProgramStore to track state changes there and
-        // calculate a hash based on that, if we expected some aspect of that data not to be completely
-        // determined by the list of committed transactions (for example if we expected different versions
-        // of the app with differing logic to coexist). At this stage it seems overkill to add support for that
-        // scenario so we just to use a fixed hash. See below for more discussion on the use of app hash:
-        // https://github.com/tendermint/tendermint/issues/1179
-        // https://github.com/tendermint/tendermint/blob/v0.34.x/spec/abci/apps.md#query-proofs
-        let app_hash = vec![];
-
-        // apply pending changes in the record store: mark used records as spent, add inputs as unspent
-        if let Err(err) = self.records.commit() {
-            error!("Failure while committing the record store {}", err);
-        }
-
-        let height = HeightFile::increment();
-
-        let mut validators = self.validators.lock().unwrap();
-        for (commitment, record) in validators.block_rewards() {
-            if let Err(err) = self.records.add(commitment, record) {
-                error!("Failed to add reward record to store {}", err);
+        let _span = info_span!("commit", height = self.pipeline.height.get() + 1).entered();
+        // pruned nodes drop the history of the blocks that fall out of the retained heights along with the commit
+        let retain_height = match self.role {
+            NodeRole::Archive => 0,
+            NodeRole::Pruned { .. } => {
+                let height = self.pipeline.height.get() as u64 + 1;
+                let earliest_height = self.role.earliest_height(height);
+                if let Err(err) = self.pipeline.records.stage_prune(earliest_height) {
+                    error!("Failed to prune the blocks below height {earliest_height}: {err}");
+                }
+                earliest_height as i64
             }
+        };
+        if let Err(err) = self.pipeline.commit() {
+            error!("Failure while committing the stores {}", err);
+            self.liveness
+                .failed(format!("Failure while committing the stores {err}"));
+        }
+        let height = self.pipeline.height.get();
+        if let Some(relay) = &self.pending_relay {
+            relay.commit(height as u64);
         }
-        validators
-            .commit()
-            .unwrap_or_else(|e| error!("failed to save validators: {e}"));
 
+        // the app hash commits to the application state that's not contained directly in the blockchain
+        // transactions, so clients can verify query responses against the block headers.
+        // See lib::merkle and https://github.com/tendermint/tendermint/blob/v0.34.x/spec/abci/apps.md#query-proofs
+        let app_hash = self.pipeline.app_hash();
+
+        self.liveness.committed();
         info!("Committing height {}", height);
+        // tendermint prunes its blocks below the retain height, zero keeps them all
         abci::ResponseCommit {
             data: app_hash,
-            retain_height: 0,
+            retain_height,
         }
     }
 }
 
 impl SnarkVMApp {
     /// Constructor.
-    pub fn new() -> Self {
-        let validators_path = Path::new("abci.validators");
+    pub fn new(config: &NodeConfig) -> Self {
+        let policy = config
+            .policy_file
+            .as_ref()
+            .map_or_else(Default::default, |path| {
+                ProgramPolicy::load(path).expect("could not load the node policy file")
+            });
+        let pending_relay = config.pending_relay_addr.map(|addr| {
+            PendingRelay::start(addr).expect("could not start the pending record relay")
+        });
+        let pipeline = if config.storage == StorageBackend::Memory {
+            TransactionPipeline::new_in_memory()
+        } else {
+            TransactionPipeline::new(config.fsync)
+        };
+        // the programs are fetched before tendermint connects, so no block is processed while the store changes
+        let committed_height = pipeline
+            .programs
+            .committed_height()
+            .expect("could not read the committed height of the program store")
+            .unwrap_or(0);
+        match ProgramPeers::new(config.program_peers.clone())
+            .catch_up(&pipeline.programs, committed_height)
+        {
+            Ok(programs) if !programs.is_empty() => info!(
+                "Fetched the missing programs {} from the program peers",
+                programs
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Ok(_) => {}
+            Err(e) => error!("failed to fetch the missing programs from the program peers: {e}"),
+        }
+        match pipeline.programs.precompile(config.precompiled_programs) {
+            Ok(programs) => info!(
+                "Loaded the verifying keys of {}",
+                programs
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Err(e) => error!("failed to load the verifying keys of the executed programs: {e}"),
+        }
+
         Self {
-            // we rather crash than start with badly initialized stores
-            programs: ProgramStore::new("programs").expect("could not create a program store"),
-            records: RecordStore::new("records").expect("could not create a record store"),
-            validators: Arc::new(Mutex::new(ValidatorSet::load_or_create(validators_path))),
+            pipeline,
+            policy: Arc::new(RwLock::new(policy)),
+            policy_file: config.policy_file.clone(),
+            mempool_senders: Default::default(),
+            max_mempool_txs_per_sender: config.max_mempool_txs_per_sender,
+            mempool_verification: config.mempool_verification,
+            pending_deployments: Default::default(),
+            pending_relay,
+            liveness: Default::default(),
+            role: config.role,
         }
     }
 
-    /// Fail if the same record appears more than once as a function input in the transaction.
-    fn check_no_duplicate_records(&self, transaction: &Transaction) -> Result<()> {
-        let serial_numbers = transaction.record_serial_numbers();
-        if let Some(serial_number) = serial_numbers.iter().duplicates().next() {
-            bail!(
-                "record with serial number {} in transaction {} is duplicate",
-                serial_number,
-                transaction.id()
-            );
+    /// Create an application that keeps all of its state in memory, starting from an empty chain.
+    /// Nothing is read from or written to the working directory, so it's suitable for tests.
+    #[cfg(any(test, feature = "test-harness"))]
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn new_in_memory() -> Self {
+        Self {
+            pipeline: TransactionPipeline::new_in_memory(),
+            policy: Default::default(),
+            policy_file: None,
+            mempool_senders: Default::default(),
+            max_mempool_txs_per_sender: None,
+            mempool_verification: VerificationLevel::Full,
+            pending_deployments: Default::default(),
+            pending_relay: None,
+            liveness: Default::default(),
+            role: NodeRole::Archive,
         }
-        Ok(())
     }
 
-    /// the transaction should be rejected if its input records don't exist
-    /// or they aren't known to be unspent either in the ledger or in an unconfirmed transaction output
-    fn check_inputs_are_unspent(&self, transaction: &Transaction) -> Result<()> {
-        let serial_numbers = transaction.record_serial_numbers();
-        let already_spent = serial_numbers
-            .iter()
-            .find(|serial_number| !self.records.is_unspent(serial_number).unwrap_or(true));
+    /// Report whether the stores work and the node is connected to tendermint and committing blocks.
+    pub fn health_report(&self) -> HealthReport {
+        HealthReport {
+            store: self.pipeline.records.chain_id().is_ok()
+                && self.pipeline.programs.root().is_ok(),
+            abci_connected: self.liveness.is_connected(),
+            height: self.pipeline.height.get(),
+            seconds_since_commit: self
+                .liveness
+                .since_last_commit()
+                .map(|elapsed| elapsed.as_secs()),
+            last_error: self.liveness.last_error(),
+        }
+    }
+
+    /// Read the policy file again, replacing the current policy. If the file can't be loaded the previous policy
+    /// is kept. Transactions already in the mempool are checked against the new policy when tendermint rechecks them.
+    pub fn reload_policy(&self) {
+        if let Some(path) = &self.policy_file {
+            match ProgramPolicy::load(path) {
+                Ok(policy) => {
+                    info!("Reloaded node policy from {}", path.display());
+                    *self.policy.write().unwrap() = policy;
+                }
+                Err(e) => error!("Keeping the current node policy, failed to reload it: {e}"),
+            }
+        }
+    }
 
-        if let Some(serial_number) = already_spent {
-            bail!(
-                "input record serial number {} is unknown or already spent",
-                serial_number
-            )
+    /// Stop the node if the chain requires a newer version of the application than this one, i.e. a chain upgrade
+    /// was approved through governance and its activation height was reached. Exiting before processing the block
+    /// leaves the state at the last committed height, so tendermint replays the block once the node is restarted
+    /// with the upgraded binary. Until then, this binary will keep refusing to process further blocks.
+    fn halt_if_outdated(&self, height: i64) {
+        let required_version = self
+            .pipeline
+            .governance
+            .lock()
+            .unwrap()
+            .params()
+            .app_version;
+        if required_version > APP_VERSION {
+            error!(
+                "Halting at height {height}: the chain was upgraded to app version {required_version} but this node runs version {APP_VERSION}. Restart the node with the upgraded binary to continue"
+            );
+            std::process::exit(1);
         }
-        Ok(())
     }
 
-    /// Mark all input records as spent in the record store. This operation could fail if the records are unknown or already spent,
-    /// but it's assumed the that was validated before as to prevent half-applied transactions in the block.
-    fn spend_input_records(&self, transaction: &Transaction) -> Result<()> {
-        transaction
-            .record_serial_numbers()
-            .iter()
-            .map(|serial_number| self.records.spend(serial_number))
-            .find(|result| result.is_err())
-            .unwrap_or(Ok(()))
+    /// Stop the node if the block doesn't follow the last one committed by the application, rather than applying it
+    /// on top of a state that misses blocks or already has it. Tendermint replays the blocks after the height
+    /// reported in `info` on startup, so this only happens if the state changed underneath, e.g. if the stores were
+    /// replaced while the node was running.
+    fn halt_if_out_of_sequence(&self, height: i64) {
+        let committed = self.pipeline.height.get();
+        if height != committed + 1 {
+            error!(
+                "Halting at height {height}: the application state has the blocks up to height {committed}. Restart the node for tendermint to replay the blocks the state misses, or restore the state from a backup if it's ahead"
+            );
+            std::process::exit(1);
+        }
     }
 
-    /// Add the tranasction output records as unspent in the record store.
-    fn add_output_records(&self, transaction: &Transaction) -> Result<()> {
-        #[allow(clippy::clone_on_copy)]
-        transaction
-            .output_records()
-            .iter()
-            .map(|(commitment, record)| self.records.add(commitment.clone(), record.clone()))
-            .find(|result| result.is_err())
-            .unwrap_or(Ok(()))
-    }
-
-    /// Apply validator set side-effects of the transaction: collecting fees and changing
-    /// the voting power based on staking transactions.
-    fn update_validators(&self, transaction: &Transaction) -> Result<()> {
-        let mut validator_set = self.validators.lock().unwrap();
-        validator_set.collect(transaction.fees() as u64);
-        transaction
-            .stake_updates()?
+    /// Return the committed packets sent to the given chain starting at the given sequence, with their state proofs.
+    fn packets_page(
+        &self,
+        destination_chain: &str,
+        from_sequence: u64,
+        limit: usize,
+    ) -> Result<PacketsResponse> {
+        ensure!(
+            self.pipeline.bridge.lock().unwrap().is_enabled(),
+            "This chain has no bridge counterparties"
+        );
+        let limit = limit.min(bridge::MAX_PACKETS_PER_RECEIVE);
+        let packets = self
+            .pipeline
+            .records
+            .scan_packets(destination_chain, from_sequence, limit)?
             .into_iter()
-            .for_each(|update| validator_set.apply(update));
+            .map(|packet| {
+                let key_proof = self.pipeline.records.packet_proof(packet.key())?;
+                let proof = self.state_proof(merkle::PACKETS_COMPONENT, key_proof)?;
+                Ok((packet, proof))
+            })
+            .collect::<Result<_>>()?;
+        Ok(PacketsResponse {
+            height: self.pipeline.height.get() as u64,
+            packets,
+        })
+    }
+
+    /// Build a proof against the last app hash out of the key proof in one of the state components.
+    fn state_proof(
+        &self,
+        component: &str,
+        key_proof: merkle::KeyProof,
+    ) -> Result<merkle::StateProof> {
+        merkle::StateProof::new(
+            self.pipeline.height.get() as u64,
+            &self.pipeline.state_components()?,
+            component,
+            key_proof,
+        )
+    }
+
+    /// Build the state proof of the result of the given query, as a tendermint proof op,
+    /// for the queries that support it.
+    fn query_proof(&self, query: &AbciQuery) -> Result<Option<ProofOps>> {
+        let (key, proof) = match query {
+            AbciQuery::GetProgram { program_id } => (
+                program_id.to_string(),
+                self.state_proof(
+                    merkle::PROGRAMS_COMPONENT,
+                    self.pipeline.programs.proof(program_id)?,
+                )?,
+            ),
+            AbciQuery::GetRecord { commitment } => (
+                commitment.to_string(),
+                self.state_proof(
+                    merkle::RECORDS_COMPONENT,
+                    self.pipeline.records.record_proof(commitment)?,
+                )?,
+            ),
+            _ => return Ok(None),
+        };
+        Ok(Some(ProofOps {
+            ops: vec![ProofOp {
+                r#type: merkle::STATE_PROOF_OP.to_string(),
+                key: key.into_bytes(),
+                data: bincode::serialize(&proof)?,
+            }],
+        }))
+    }
+
+    /// Fail if the node policy refuses to relay executions of any of the programs in the transaction.
+    /// Only used in check_tx, blocks are processed regardless of the policy.
+    fn check_program_policy(&self, transaction: &Transaction) -> Result<()> {
+        if let Transaction::Batch { transactions, .. } = transaction {
+            return transactions
+                .iter()
+                .try_for_each(|transaction| self.check_program_policy(transaction));
+        }
+        if let Transaction::Signed { transaction, .. } = transaction {
+            return self.check_program_policy(transaction);
+        }
+        if let Transaction::Execution { transitions, .. } = transaction {
+            let policy = self.policy.read().unwrap();
+            for transition in transitions {
+                policy.check(&transition.program_id().to_string())?;
+            }
+        }
+        Ok(())
+    }
 
+    /// Count the transaction for its sender in the mempool, failing if the sender already reached the configured limit.
+    /// Only used in check_tx, transactions without a known sender are not limited.
+    fn reserve_sender_slot(&self, transaction: &Transaction) -> Result<()> {
+        if let (Some(limit), Some(sender)) = (self.max_mempool_txs_per_sender, transaction.sender())
+        {
+            self.mempool_senders
+                .lock()
+                .unwrap()
+                .reserve(&sender, transaction.id(), limit)?;
+        }
         Ok(())
     }
 
-    fn validate_transaction(&self, transaction: &Transaction) -> Result<()> {
-        transaction.verify()?;
+    /// Validate a transaction for the mempool, accepting the executions of the programs deployed by the transactions
+    /// already in it, see `PendingDeployments`. Signatures and proofs are only checked if the node is configured to,
+    /// see `NodeConfig::mempool_verification`.
+    fn validate_for_mempool(&self, transaction: &Transaction) -> Result<()> {
+        let deployments = self.pending_deployments.lock().unwrap().keys();
+        self.pipeline
+            .check(transaction, &deployments, self.mempool_verification)
+    }
 
-        let result = match transaction {
-            Transaction::Deployment {
-                ref program,
-                verifying_keys,
-                fee,
-                ..
-            } => {
-                ensure!(
-                    !self.programs.exists(program.id()),
-                    format!("Program already exists: {}", program.id())
-                );
+    /// Compare the given source code with a deployed program and the source hash published by its deployer.
+    /// Queries are local to the node, so the result isn't stored: it would end up in the state of this node only.
+    fn verify_source(
+        &self,
+        program_id: &vm::ProgramID,
+        source: &str,
+    ) -> Result<SourceVerification> {
+        let (program, _keys) = self
+            .pipeline
+            .programs
+            .get(program_id)?
+            .ok_or_else(|| anyhow!("Program {program_id} does not exist"))?;
+        let metadata = self
+            .pipeline
+            .programs
+            .metadata(program_id)?
+            .unwrap_or_default();
+
+        let compiled = vm::generate_program(source)?;
+        let program_matches = merkle::program_hash(&compiled) == merkle::program_hash(&program);
+        let source_hash_matches =
+            metadata.source_hash.as_deref() == Some(Transaction::hash_source(source).as_str());
+        Ok(SourceVerification {
+            program_matches,
+            source_hash_matches,
+            verified: program_matches && source_hash_matches,
+        })
+    }
+
+    /// The earliest height whose events and rewards the node keeps, see `NodeRole`.
+    fn earliest_height(&self) -> u64 {
+        self.role.earliest_height(self.pipeline.height.get() as u64)
+    }
+
+    /// Fail if the history of the block at the given height was pruned by this node.
+    fn check_retained(&self, height: u64) -> Result<()> {
+        let earliest_height = self.earliest_height();
+        ensure!(
+            self.role == NodeRole::Archive || height >= earliest_height,
+            "Block {height} was pruned by this node, which keeps the blocks from height {earliest_height}; query an archive node"
+        );
+        Ok(())
+    }
+
+    /// Return the events persisted for the committed block at the given height. Blocks committed before events
+    /// were persisted have none.
+    fn block_events_at(&self, height: u64) -> Result<Vec<BlockEvent>> {
+        let last_height = self.pipeline.height.get() as u64;
+        ensure!(
+            height <= last_height,
+            "Block {height} is not committed yet, the last committed block is {last_height}"
+        );
+        self.check_retained(height)?;
+        match self.pipeline.records.events(height)? {
+            Some(bytes) => Ok(bincode::deserialize(&bytes)?),
+            None => Ok(vec![]),
+        }
+    }
 
-                if let Some(transition) = fee {
-                    self.verify_transition(transition)?;
+    /// Return the rewards distributed by the committed blocks from height `from` to `to`, or to the last committed
+    /// one, optionally keeping only the ones assigned to the given account. See `AbciQuery::GetBlockRewards`.
+    fn block_rewards_in(
+        &self,
+        address: Option<&vm::Address>,
+        from: u64,
+        to: Option<u64>,
+    ) -> Result<Vec<BlockRewards>> {
+        let last_height = self.pipeline.height.get() as u64;
+        let to = to.unwrap_or(last_height);
+        ensure!(
+            from <= to.min(last_height),
+            "Invalid block range {from} to {to}, the last committed block is {last_height}"
+        );
+        ensure!(
+            to - from < MAX_REWARDS_BLOCKS,
+            "Can't query the rewards of more than {MAX_REWARDS_BLOCKS} blocks at once"
+        );
+        self.check_retained(from)?;
+
+        let mut blocks = Vec::new();
+        for bytes in self.pipeline.records.rewards(from, to.min(last_height))? {
+            let mut block: BlockRewards = serde_json::from_slice(&bytes)?;
+            if let Some(address) = address {
+                block
+                    .rewards
+                    .retain(|reward| &reward.aleo_address == address);
+                if block.rewards.is_empty() {
+                    continue;
                 }
+            }
+            blocks.push(block);
+        }
+        Ok(blocks)
+    }
 
-                // verify deployment is correct and keys are valid
-                vm::verify_deployment(program, verifying_keys.clone())
+    /// Return a summary of the state of the chain at the last committed block, see `AbciQuery::GetChainStatus`.
+    fn chain_status(&self) -> Result<ChainStatus> {
+        let (transactions, records) = self.pipeline.records.counts()?;
+        let validators = self
+            .pipeline
+            .validators
+            .validators()
+            .iter()
+            .filter(|validator| {
+                validator.voting_power > 0
+                    && !validator.candidate
+                    && validator.tombstone.is_none()
+                    && validator.rotated_to.is_none()
+            })
+            .count();
+        Ok(ChainStatus {
+            chain_id: self.pipeline.chain_id.read().unwrap().clone(),
+            height: self.pipeline.height.get() as u64,
+            app_hash: hex::encode(self.pipeline.app_hash()),
+            transactions,
+            records,
+            validators,
+            base_fee: self.pipeline.governance.lock().unwrap().params().base_fee,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        })
+    }
+
+    /// Count the entries of the stores and measure their size on disk, see `AbciQuery::GetStoreStats`.
+    fn store_stats(&self) -> Result<StoreStats> {
+        let mut entries = BTreeMap::new();
+        for (column, count) in self
+            .pipeline
+            .records
+            .count_entries()?
+            .into_iter()
+            .chain(self.pipeline.programs.count_entries()?)
+            .filter(|(_, count)| *count > 0)
+        {
+            *entries.entry(column.name().to_string()).or_insert(0) += count;
+        }
+        let count = |column: Column| entries.get(column.name()).copied().unwrap_or(0);
+        let records = count(Column::Records);
+        let spent_records = count(Column::Spent);
+        Ok(StoreStats {
+            height: self.pipeline.height.get() as u64,
+            records,
+            spent_records,
+            unspent_records: records.saturating_sub(spent_records),
+            programs: count(Column::Programs),
+            disk_bytes: self.pipeline.disk_usage(),
+            entries,
+        })
+    }
+
+    /// Return how each validator performed over the last `blocks` committed blocks, computed from their rewards.
+    /// Validators without stake are only included if they got rewards in the window.
+    /// See `AbciQuery::GetValidatorPerformance`.
+    fn validator_performance(&self, blocks: Option<u64>) -> Result<ValidatorPerformanceResponse> {
+        let window = blocks.unwrap_or(DEFAULT_PERFORMANCE_BLOCKS);
+        ensure!(
+            (1..=MAX_REWARDS_BLOCKS).contains(&window),
+            "The window must be between 1 and {MAX_REWARDS_BLOCKS} blocks"
+        );
+        let to = self.pipeline.height.get() as u64;
+        ensure!(to > 0, "There are no committed blocks yet");
+        // pruned nodes cover the blocks they kept
+        let from = to.saturating_sub(window - 1).max(self.earliest_height());
+        let blocks = self.block_rewards_in(None, from, Some(to))?;
+        let recorded = blocks.len() as u64;
+
+        // the pace of the chain is estimated from the first and last blocks of the window that have a time
+        let timed: Vec<(u64, u64)> = blocks
+            .iter()
+            .filter_map(|block| Some((block.height, block.time?)))
+            .collect();
+        let blocks_per_year = match (timed.first(), timed.last()) {
+            (Some((first_height, first_time)), Some((last_height, last_time)))
+                if last_time > first_time =>
+            {
+                Some((last_height - first_height) * SECONDS_PER_YEAR / (last_time - first_time))
             }
-            Transaction::Execution { transitions, .. } => {
-                ensure!(
-                    !transitions.is_empty(),
-                    "There are no transitions in the execution"
-                );
+            _ => None,
+        };
 
-                let validator_set = self.validators.lock().unwrap();
-                for update in transaction.stake_updates()? {
-                    validator_set.validate(&update)?
+        let mut validators: Vec<ValidatorPerformance> = self
+            .pipeline
+            .validators
+            .validators()
+            .into_iter()
+            // validators that rotated their key are listed under the new one
+            .filter(|validator| validator.rotated_to.is_none())
+            .map(|validator| {
+                let rewards: Vec<_> = blocks
+                    .iter()
+                    .flat_map(|block| &block.rewards)
+                    .filter(|reward| reward.pub_key == validator.pub_key)
+                    .collect();
+                let signed_blocks = rewards.len() as u64;
+                let gates: u64 = rewards.iter().map(|reward| reward.gates).sum();
+                let apy = blocks_per_year
+                    .filter(|_| validator.voting_power > 0)
+                    .map(|per_year| {
+                        gates as f64 / recorded as f64 * per_year as f64
+                            / validator.voting_power as f64
+                    });
+                ValidatorPerformance {
+                    pub_key: validator.pub_key,
+                    aleo_address: validator.aleo_address,
+                    voting_power: validator.voting_power,
+                    signed_blocks,
+                    proposed_blocks: rewards.iter().filter(|reward| reward.proposer).count() as u64,
+                    uptime: if recorded == 0 {
+                        0.0
+                    } else {
+                        signed_blocks as f64 / recorded as f64
+                    },
+                    rewards: gates,
+                    apy,
                 }
+            })
+            .filter(|performance| performance.voting_power > 0 || performance.signed_blocks > 0)
+            .collect();
+        validators.sort_by(|a, b| b.voting_power.cmp(&a.voting_power));
+
+        Ok(ValidatorPerformanceResponse {
+            from,
+            to,
+            blocks: recorded,
+            blocks_per_year,
+            validators,
+        })
+    }
 
-                for transition in transitions {
-                    self.verify_transition(transition)?;
-                }
-                Ok(())
-            }
+    /// Return a page of up to `limit` records, sorted by commitment, starting after the `from` one.
+    /// If a program id is given, only the records created by that program are returned.
+    /// The cursor to the next page is only set when there are more records.
+    #[allow(clippy::clone_on_copy)]
+    fn records_page(
+        &self,
+        program_id: Option<&vm::ProgramID>,
+        from: Option<vm::Field>,
+        limit: Option<usize>,
+    ) -> Result<RecordsResponse> {
+        // the scan includes the starting record, which was already returned in the previous page,
+        // and one more record than requested to know whether there's a next page
+        let scan_limit = limit.map(|limit| limit.saturating_add(1 + usize::from(from.is_some())));
+        let (records, _) = match program_id {
+            Some(program_id) => self.pipeline.records.scan_program_with_heights(
+                &program_id.to_string(),
+                from.clone(),
+                scan_limit,
+            )?,
+            None => self
+                .pipeline
+                .records
+                .scan_with_heights(from.clone(), scan_limit)?,
         };
+        let mut records: Vec<RecordEntry> = records
+            .into_iter()
+            .filter(|(commitment, _, _)| Some(commitment) != from.as_ref())
+            .map(|(commitment, ciphertext, height)| RecordEntry {
+                commitment,
+                ciphertext,
+                height,
+            })
+            .collect();
 
-        match result {
-            Err(ref e) => error!("Transaction {} verification failed: {}", transaction, e),
-            _ => info!("Transaction {} verification successful", transaction),
+        let cursor = match limit {
+            Some(limit) if records.len() > limit => {
+                records.truncate(limit);
+                records.last().map(|entry| entry.commitment.clone())
+            }
+            _ => None,
         };
-        result
+        Ok(RecordsResponse { records, cursor })
+    }
+
+    /// Tell whether the program id can be deployed, see `AbciQuery::GetProgramAvailability`.
+    fn program_availability(&self, program_id: &vm::ProgramID) -> Result<ProgramAvailability> {
+        let metadata = self.pipeline.programs.metadata(program_id)?;
+        let pending = self
+            .pending_deployments
+            .lock()
+            .unwrap()
+            .contains(program_id);
+        Ok(ProgramAvailability {
+            program_id: *program_id,
+            available: metadata.is_none() && !pending,
+            deployed_at: metadata.as_ref().map(|metadata| metadata.height),
+            deployer: metadata.and_then(|metadata| metadata.deployer),
+            pending,
+            failed_deployments: self.pipeline.programs.failed_deployments(program_id)?,
+        })
     }
 
-    /// Check the given execution transition with the verifying keys from the program store
-    fn verify_transition(&self, transition: &vm::Transition) -> Result<()> {
-        let stored_keys = self.programs.get(transition.program_id())?;
+    /// Look up the program of a registry name and the owner of its namespace, see `AbciQuery::ResolveName`.
+    fn resolve_name(&self, name: String) -> Result<NameResponse> {
+        let (namespace, _) = names::parse(&name)?;
+        Ok(NameResponse {
+            program_id: self.pipeline.programs.resolve_name(&name)?,
+            owner: self.pipeline.programs.namespace_owner(namespace)?,
+            sequence: self.pipeline.programs.name_sequence(&name)?,
+            name,
+        })
+    }
 
-        // only verify if we have the program available
-        if let Some((_program, keys)) = stored_keys {
-            vm::verify_execution(transition, &keys)
-        } else {
-            bail!(format!(
-                "Program {} does not exist",
-                transition.program_id()
-            ))
-        }
+    /// Return the program with the given id along with its deployment metadata, if it's deployed.
+    fn program_response(&self, program_id: &vm::ProgramID) -> Result<ProgramResponse> {
+        let program = match self.pipeline.programs.get(program_id)? {
+            Some((program, _keys)) => program,
+            None => {
+                return Ok(ProgramResponse {
+                    program: None,
+                    metadata: None,
+                })
+            }
+        };
+        let metadata = self
+            .pipeline
+            .programs
+            .metadata(program_id)?
+            .unwrap_or_default();
+        Ok(ProgramResponse {
+            metadata: Some(metadata.summary(&program)),
+            program: Some(program),
+        })
     }
 
-    fn store_program(&self, transaction: &Transaction) -> Result<()> {
-        if let Transaction::Deployment {
-            program,
-            verifying_keys,
-            ..
-        } = transaction
-        {
-            self.programs.add(program.id(), program, verifying_keys)?
+    /// Add the output records of the executions scheduled for the given height, which is the one of the
+    /// block being delivered, returning an event for each of them so they can be looked up by transaction id.
+    fn apply_scheduled_executions(&self, height: u64) -> Vec<abci::Event> {
+        let transactions = self.pipeline.apply_scheduled(height);
+        if let Some(relay) = &self.pending_relay {
+            for transaction in &transactions {
+                relay.stage(PendingRecords::of(transaction));
+            }
         }
-        Ok(())
+        transactions
+            .iter()
+            .map(|transaction| abci::Event {
+                r#type: "scheduled_execution".to_string(),
+                attributes: vec![
+                    abci::EventAttribute {
+                        key: "tx_id".to_string().into_bytes(),
+                        value: transaction.id().to_string().into_bytes(),
+                        index: true,
+                    },
+                    abci::EventAttribute {
+                        key: "height".to_string().into_bytes(),
+                        value: height.to_string().into_bytes(),
+                        index: false,
+                    },
+                ],
+            })
+            .collect()
     }
 }
 
-/// Local file used to track the last block height seen by the abci application.
-struct HeightFile;
-
-impl HeightFile {
-    const PATH: &str = "abci.height";
+/// Parse the validators of the tendermint genesis, as received in init_chain.
+fn tendermint_validators(
+    updates: &[abci::ValidatorUpdate],
+) -> Result<Vec<(tendermint::PublicKey, u64)>> {
+    updates
+        .iter()
+        .map(|update| {
+            let pub_key = update
+                .pub_key
+                .clone()
+                .ok_or_else(|| anyhow!("validator without public key"))?;
+            let pub_key = tendermint::PublicKey::try_from(pub_key)
+                .map_err(|e| anyhow!("invalid validator public key: {e}"))?;
+            ensure!(
+                update.power >= 0,
+                "negative voting power for validator {}",
+                base64::encode(pub_key.to_bytes())
+            );
+            Ok((pub_key, update.power as u64))
+        })
+        .collect()
+}
 
-    fn read_or_create() -> i64 {
-        // if height file is missing or unreadable, create a new one from zero height
-        if let Ok(bytes) = std::fs::read(Self::PATH) {
-            // if contents are not readable, crash intentionally
-            bincode::deserialize(&bytes).expect("Contents of height file are not readable")
-        } else {
-            std::fs::write(Self::PATH, bincode::serialize(&0i64).unwrap()).unwrap();
-            0i64
-        }
+/// Convert an event emitted through tendermint to the format in which the node persists it.
+fn block_event(event: &abci::Event) -> BlockEvent {
+    BlockEvent {
+        kind: event.r#type.clone(),
+        attributes: event
+            .attributes
+            .iter()
+            .map(|attribute| {
+                (
+                    String::from_utf8_lossy(&attribute.key).to_string(),
+                    String::from_utf8_lossy(&attribute.value).to_string(),
+                )
+            })
+            .collect(),
     }
+}
 
-    fn increment() -> i64 {
-        // if the file is missing or contents are unexpected, we crash intentionally;
-        let mut height: i64 = bincode::deserialize(&std::fs::read(Self::PATH).unwrap()).unwrap();
-        height += 1;
-        std::fs::write(Self::PATH, bincode::serialize(&height).unwrap()).unwrap();
-        height
-    }
+/// A `validator_set` event for each validator that entered or left the active set, with its tendermint address,
+/// aleo address and voting power, so delegators can follow which validators are producing blocks.
+fn validator_set_events(entered: &[Validator], left: &[Validator]) -> Vec<abci::Event> {
+    let attribute = |key: &str, value: String, index: bool| abci::EventAttribute {
+        key: key.to_string().into_bytes(),
+        value: value.into_bytes(),
+        index,
+    };
+    let changes = entered
+        .iter()
+        .map(|validator| (validator, "entered"))
+        .chain(left.iter().map(|validator| (validator, "left")));
+    changes
+        .map(|(validator, change)| abci::Event {
+            r#type: "validator_set".to_string(),
+            attributes: vec![
+                attribute("change", change.to_string(), true),
+                attribute("address", hex::encode_upper(validator.address()), true),
+                attribute("aleo_address", validator.aleo_address.to_string(), false),
+                attribute("voting_power", validator.voting_power.to_string(), false),
+            ],
+        })
+        .collect()
+}
+
+/// Mempool priority of a transaction: the fees it pays per kilobyte of its serialized size.
+/// Blocks are filled in this order, since the ABCI 0.34 of tendermint-abci has no PrepareProposal request
+/// for the application to build the proposals itself.
+fn fee_priority(transaction: &Transaction) -> i64 {
+    let size = transaction.size().unwrap_or(u64::MAX).max(1);
+    (transaction.fees() as i128 * 1000 / size as i128) as i64
 }
 
 // just covering a few special cases here. lower level test are done in record store and program store, higher level in integration tests.
 #[cfg(test)]
 mod tests {
+    use crate::config::{NodeConfig, StorageBackend};
+    use crate::test_chain::{TestChain, BLOCK_SECONDS};
+    use lib::diagnostic::{Check, Diagnostic};
+    use lib::governance::Params;
+    use lib::merkle;
+    use lib::query::{
+        AbciQuery, BlockEvent, BlockEventsResponse, BlockRewardsResponse, ChainStatus,
+        NameResponse, NodeInfo, NodeRole, ProgramAvailability, ProgramResponse, QueryResponse,
+        StoreStats, ValidatorPerformanceResponse, MAX_REWARDS_BLOCKS,
+    };
     use lib::{
         transaction::Transaction,
         vm::{self, Identifier},
     };
     use serde_json::json;
-    use std::{
-        path::Path,
-        str::FromStr,
-        sync::{Arc, Mutex},
-    };
+    use std::collections::HashSet;
+    use std::{path::Path, str::FromStr};
     use tendermint_abci::Application;
-    use tendermint_proto::abci::{RequestCheckTx, RequestDeliverTx};
-
-    use crate::{
-        program_store::ProgramStore, record_store::RecordStore, validator_set::ValidatorSet,
-    };
+    use tendermint_proto::abci::{CheckTxType, RequestCheckTx, RequestDeliverTx, RequestQuery};
 
-    use super::SnarkVMApp;
+    use super::{MempoolSenders, SnarkVMApp, SECONDS_PER_YEAR};
 
     #[test]
     fn test_abci_hooks() {
-        let app = SnarkVMApp {
-            programs: ProgramStore::new("programs_test").expect("could not create a program store"),
-            records: RecordStore::new("records_test").expect("could not create a record store"),
-            validators: Arc::new(Mutex::new(ValidatorSet::load_or_create(Path::new("void")))),
-        };
+        let app = SnarkVMApp::new_in_memory();
 
         let private_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
         let view_key = vm::ViewKey::try_from(&private_key).unwrap();
@@ -520,7 +1512,7 @@ mod tests {
         let deployment_transaction =
             Transaction::deployment(Path::new("aleo/records.aleo"), &private_key, None).unwrap();
 
-        let _ = app.store_program(&deployment_transaction);
+        app.pipeline.apply(&deployment_transaction).unwrap();
 
         // normal execution to mint a record, validations should succeed
         let transaction = Transaction::execution(
@@ -572,10 +1564,26 @@ mod tests {
         let check_tx_req = check_request(&consume_two_transaction);
         let deliver_tx_req = deliver_request(&consume_two_transaction);
         assert!(app.check_tx(check_tx_req).code != 0);
-        assert!(app.deliver_tx(deliver_tx_req).code != 0);
+        let response = app.deliver_tx(deliver_tx_req);
+        assert!(response.code != 0);
+        // the log tells which check failed and on which record
+        let diagnostic = Diagnostic::from_log(&response.log).unwrap();
+        assert_eq!(Check::DuplicateInput, diagnostic.check);
+        assert_eq!(
+            Some(consume_two_transaction.id().to_string()),
+            diagnostic.transaction
+        );
+        assert_eq!(
+            consume_two_transaction
+                .record_serial_numbers()
+                .first()
+                .map(ToString::to_string),
+            diagnostic.serial_number
+        );
 
         // because validations failed, inputs should not be spent in the store
-        app.check_inputs_are_unspent(&consume_two_transaction)
+        app.pipeline
+            .check_inputs_are_unspent(&consume_two_transaction)
             .unwrap();
 
         // consume the record
@@ -596,10 +1604,487 @@ mod tests {
         assert!(app.deliver_tx(deliver_tx_req.clone()).code == 0);
 
         // because deliver_tx() spends the records, further validations should fail
-        assert!(app.check_tx(check_tx_req).code != 0);
+        let response = app.check_tx(check_tx_req);
+        assert!(response.code != 0);
+        assert!(Diagnostic::from_log(&response.log).is_some());
         assert!(app.deliver_tx(deliver_tx_req).code != 0);
     }
 
+    #[test]
+    fn program_query_includes_state_proof() {
+        let app = SnarkVMApp::new_in_memory();
+        let program_id = vm::ProgramID::from_str("credits.aleo").unwrap();
+        let request = RequestQuery {
+            data: bincode::serialize(&AbciQuery::GetProgram { program_id }).unwrap(),
+            prove: true,
+            ..Default::default()
+        };
+
+        let response = app.query(request.clone());
+        assert_eq!(0, response.code);
+        let program = QueryResponse::<ProgramResponse>::decode(&response.value)
+            .unwrap()
+            .program;
+        let ops = response.proof_ops.unwrap().ops;
+        assert_eq!(merkle::STATE_PROOF_OP, ops[0].r#type);
+
+        // the proof commits to the returned program
+        let proof: merkle::StateProof = bincode::deserialize(&ops[0].data).unwrap();
+        let app_hash = merkle::app_hash(&app.pipeline.state_components().unwrap());
+        let value = proof
+            .verify(merkle::PROGRAMS_COMPONENT, b"credits.aleo", &app_hash)
+            .unwrap();
+        assert_eq!(
+            Some(merkle::program_hash(&program.unwrap()).to_vec()),
+            value
+        );
+
+        // proofs are only built when requested
+        let response = app.query(RequestQuery {
+            prove: false,
+            ..request
+        });
+        assert!(response.proof_ops.is_none());
+    }
+
+    #[test]
+    fn block_events_are_persisted() {
+        let app = SnarkVMApp::new_in_memory();
+        let private_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let deployment =
+            Transaction::deployment(Path::new("aleo/hello.aleo"), &private_key, None).unwrap();
+        assert_eq!(0, app.deliver_tx(deliver_request(&deployment)).code);
+        app.commit();
+
+        let query = |height| {
+            app.query(RequestQuery {
+                data: bincode::serialize(&AbciQuery::GetBlockEvents { height }).unwrap(),
+                ..Default::default()
+            })
+        };
+        let response = query(1);
+        assert_eq!(0, response.code);
+        let events = QueryResponse::<BlockEventsResponse>::decode(&response.value)
+            .unwrap()
+            .events;
+        assert_eq!(
+            vec![BlockEvent {
+                kind: "app".to_string(),
+                attributes: vec![
+                    ("tx_id".to_string(), deployment.id().to_string()),
+                    ("kind".to_string(), "deployment".to_string()),
+                    ("fee".to_string(), "0".to_string()),
+                    ("program".to_string(), "hello.aleo".to_string()),
+                ],
+            }],
+            events
+        );
+
+        // the next block isn't committed yet
+        assert_ne!(0, query(2).code);
+    }
+
+    #[test]
+    fn block_rewards_are_persisted() {
+        let private_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let address = vm::Address::try_from(&vm::ViewKey::try_from(&private_key).unwrap()).unwrap();
+        let chain = TestChain::with_account(&address, 100);
+        chain.produce_block(&[]);
+        chain.produce_block(&[]);
+
+        let rewards = |address, from, to| {
+            chain
+                .query::<BlockRewardsResponse>(AbciQuery::GetBlockRewards { address, from, to })
+                .map(|response| response.blocks)
+        };
+        let blocks = rewards(Some(address), 1, None).unwrap();
+        assert_eq!(
+            vec![1, 2],
+            blocks.iter().map(|block| block.height).collect::<Vec<_>>()
+        );
+        let block_reward = Params::default().block_reward;
+        for block in blocks {
+            assert_eq!(block_reward, block.minted);
+            assert_eq!(0, block.fees);
+            assert_eq!(1, block.rewards.len());
+            assert_eq!(block_reward, block.rewards[0].gates);
+            assert!(block.rewards[0].proposer);
+        }
+        assert_eq!(1, rewards(None, 2, Some(5)).unwrap().len());
+
+        // other accounts got nothing
+        let other = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let other = vm::Address::try_from(&vm::ViewKey::try_from(&other).unwrap()).unwrap();
+        assert!(rewards(Some(other), 1, None).unwrap().is_empty());
+
+        assert!(rewards(None, 3, None).is_err());
+        assert!(rewards(None, 0, Some(MAX_REWARDS_BLOCKS)).is_err());
+    }
+
+    #[test]
+    fn pruned_nodes_keep_the_last_heights() {
+        let private_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let address = vm::Address::try_from(&vm::ViewKey::try_from(&private_key).unwrap()).unwrap();
+        let app = SnarkVMApp::new(&NodeConfig {
+            storage: StorageBackend::Memory,
+            role: NodeRole::Pruned {
+                retained_heights: 2,
+            },
+            ..Default::default()
+        });
+        let chain = TestChain::with_app(app, TestChain::account_genesis(&address, 100));
+        for _ in 0..4 {
+            chain.produce_block(&[]);
+        }
+
+        let info = chain.app().info(Default::default());
+        let node_info: NodeInfo = serde_json::from_str(&info.data).unwrap();
+        assert_eq!(3, node_info.earliest_height);
+
+        let events =
+            |height| chain.query::<BlockEventsResponse>(AbciQuery::GetBlockEvents { height });
+        assert!(events(2).is_err());
+        events(3).unwrap();
+        let rewards = chain
+            .query::<BlockRewardsResponse>(AbciQuery::GetBlockRewards {
+                address: None,
+                from: 3,
+                to: None,
+            })
+            .unwrap();
+        assert_eq!(2, rewards.blocks.len());
+        assert!(chain
+            .query::<BlockRewardsResponse>(AbciQuery::GetBlockRewards {
+                address: None,
+                from: 1,
+                to: None,
+            })
+            .is_err());
+    }
+
+    #[test]
+    fn chain_status() {
+        let private_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let address = vm::Address::try_from(&vm::ViewKey::try_from(&private_key).unwrap()).unwrap();
+        let chain = TestChain::with_account(&address, 100);
+        for _ in 0..3 {
+            chain.produce_block(&[]);
+        }
+
+        let status = chain
+            .query::<ChainStatus>(AbciQuery::GetChainStatus)
+            .unwrap();
+        let info = chain.app().info(Default::default());
+        assert_eq!(3, status.height);
+        assert_eq!(hex::encode(info.last_block_app_hash), status.app_hash);
+        assert_eq!(0, status.transactions);
+        // the genesis record plus the rewards of the blocks
+        assert!(status.records > 1);
+        assert_eq!(1, status.validators);
+        assert_eq!(Params::default().base_fee, status.base_fee);
+        assert_eq!(env!("CARGO_PKG_VERSION"), status.version);
+    }
+
+    #[test]
+    fn store_stats() {
+        let private_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let address = vm::Address::try_from(&vm::ViewKey::try_from(&private_key).unwrap()).unwrap();
+        let chain = TestChain::with_account(&address, 100);
+        chain.produce_block(&[]);
+
+        let stats = chain.query::<StoreStats>(AbciQuery::GetStoreStats).unwrap();
+        assert_eq!(1, stats.height);
+        assert!(stats.records > 0);
+        assert_eq!(0, stats.spent_records);
+        assert_eq!(stats.records, stats.unspent_records);
+        assert_eq!(Some(&stats.records), stats.entries.get("records"));
+        // credits.aleo
+        assert_eq!(1, stats.programs);
+        assert!(stats.disk_bytes.is_empty());
+    }
+
+    #[test]
+    fn validator_performance() {
+        let private_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let address = vm::Address::try_from(&vm::ViewKey::try_from(&private_key).unwrap()).unwrap();
+        let chain = TestChain::with_account(&address, 100);
+        let performance = |blocks| {
+            chain.query::<ValidatorPerformanceResponse>(AbciQuery::GetValidatorPerformance {
+                blocks,
+            })
+        };
+        // there are no blocks to measure yet
+        assert!(performance(None).is_err());
+
+        for _ in 0..4 {
+            chain.produce_block(&[]);
+        }
+        let response = performance(None).unwrap();
+        assert_eq!((1, 4, 4), (response.from, response.to, response.blocks));
+        let blocks_per_year = SECONDS_PER_YEAR / BLOCK_SECONDS;
+        assert_eq!(Some(blocks_per_year), response.blocks_per_year);
+        assert_eq!(1, response.validators.len());
+        let validator = &response.validators[0];
+        assert_eq!(address, validator.aleo_address);
+        assert_eq!(4, validator.signed_blocks);
+        assert_eq!(4, validator.proposed_blocks);
+        assert!(validator.uptime > 0.99);
+        let block_reward = Params::default().block_reward;
+        assert_eq!(4 * block_reward, validator.rewards);
+        // the only validator gets the whole block reward, staking a single gate
+        let expected_apy = block_reward as f64 * blocks_per_year as f64;
+        assert!((validator.apy.unwrap() - expected_apy).abs() < 1.0);
+
+        let response = performance(Some(2)).unwrap();
+        assert_eq!((3, 4, 2), (response.from, response.to, response.blocks));
+        assert_eq!(2, response.validators[0].signed_blocks);
+        assert!(performance(Some(0)).is_err());
+        assert!(performance(Some(MAX_REWARDS_BLOCKS + 1)).is_err());
+    }
+
+    #[test]
+    fn records_are_paginated() {
+        let app = SnarkVMApp::new_in_memory();
+        let private_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let address = vm::Address::try_from(&vm::ViewKey::try_from(&private_key).unwrap()).unwrap();
+        for seed in 0..5 {
+            let (commitment, record) =
+                vm::mint_record("credits.aleo", "credits", &address, 10, seed).unwrap();
+            app.pipeline.records.add(commitment, record).unwrap();
+        }
+        app.commit();
+
+        let mut commitments = Vec::new();
+        let mut from = None;
+        loop {
+            let page = app.records_page(None, from, Some(2)).unwrap();
+            assert!(page.records.len() <= 2);
+            commitments.extend(page.records.into_iter().map(|entry| entry.commitment));
+            match page.cursor {
+                Some(cursor) => from = Some(cursor),
+                None => break,
+            }
+        }
+        assert_eq!(5, commitments.len());
+        assert_eq!(5, commitments.iter().collect::<HashSet<_>>().len());
+
+        let all = app.records_page(None, None, None).unwrap();
+        assert_eq!(5, all.records.len());
+        assert!(all.cursor.is_none());
+    }
+
+    #[test]
+    fn source_verification() {
+        let app = SnarkVMApp::new_in_memory();
+        let private_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let source = include_str!("../../aleo/hello.aleo");
+        let deployment = Transaction::deployment_from_source(source, &private_key, None)
+            .unwrap()
+            .with_source_hash(source)
+            .unwrap();
+        app.pipeline.apply(&deployment).unwrap();
+        let program_id = vm::ProgramID::from_str("hello.aleo").unwrap();
+
+        let metadata = || {
+            let metadata = app
+                .pipeline
+                .programs
+                .metadata(&program_id)
+                .unwrap()
+                .unwrap();
+            bincode::serialize(&metadata).unwrap()
+        };
+        let stored = metadata();
+
+        // same program, but not the source that was hashed
+        let reformatted = format!("// reformatted\n{source}");
+        let verification = app.verify_source(&program_id, &reformatted).unwrap();
+        assert!(verification.program_matches);
+        assert!(!verification.source_hash_matches);
+        assert!(!verification.verified);
+
+        // the query is read-only, a successful verification doesn't change the stored metadata
+        let verification = app.verify_source(&program_id, source).unwrap();
+        assert!(verification.verified);
+        assert_eq!(stored, metadata());
+    }
+
+    #[test]
+    fn deploy_and_execute_in_the_same_block() {
+        let app = SnarkVMApp::new_in_memory();
+        let private_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let deployment =
+            Transaction::deployment(Path::new("aleo/hello.aleo"), &private_key, None).unwrap();
+        let program = vm::generate_program(include_str!("../../aleo/hello.aleo")).unwrap();
+        let execution = Transaction::execution(
+            program,
+            Identifier::from_str("hello").unwrap(),
+            &[
+                vm::UserInputValueType::from_str("1u32").unwrap(),
+                vm::UserInputValueType::from_str("1u32").unwrap(),
+            ],
+            &private_key,
+            None,
+        )
+        .unwrap();
+
+        // the mempool accepts the execution once the deployment is in it
+        assert!(app.check_tx(check_request(&execution)).code != 0);
+        assert!(app.check_tx(check_request(&deployment)).code == 0);
+        assert!(app.check_tx(check_request(&execution)).code == 0);
+        // rechecking the deployment doesn't conflict with its own pending program
+        assert!(app.check_tx(recheck_request(&deployment)).code == 0);
+
+        // but the block is applied in order, so the execution is only valid after the deployment
+        assert!(app.deliver_tx(deliver_request(&execution)).code != 0);
+        assert!(app.deliver_tx(deliver_request(&deployment)).code == 0);
+        assert!(app.deliver_tx(deliver_request(&execution)).code == 0);
+        assert!(app.pending_deployments.lock().unwrap().keys().is_empty());
+    }
+
+    #[test]
+    fn programs_can_be_deployed_again_after_failing() {
+        let private_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let address = vm::Address::try_from(&vm::ViewKey::try_from(&private_key).unwrap()).unwrap();
+        let chain = TestChain::with_account(&address, 100);
+        let program_id = vm::ProgramID::from_str("hello.aleo").unwrap();
+        let availability = || {
+            chain
+                .query::<ProgramAvailability>(AbciQuery::GetProgramAvailability { program_id })
+                .unwrap()
+        };
+        assert!(availability().available);
+
+        // the deployment expires before it's included, so it fails in the block
+        let expired = Transaction::deployment(Path::new("aleo/hello.aleo"), &private_key, None)
+            .unwrap()
+            .with_expiration(chain.height() as u64)
+            .unwrap();
+        assert!(chain.produce_block(&[expired.clone()])[0].code != 0);
+        let failed = availability();
+        assert!(failed.available);
+        assert_eq!(1, failed.failed_deployments.len());
+        assert_eq!(expired.id(), failed.failed_deployments[0].transaction_id);
+        assert_eq!(&address, &failed.failed_deployments[0].deployer);
+        assert!(failed.failed_deployments[0].error.contains("expired"));
+
+        // the same account deploys the program again, signing it so it's recorded as the deployer
+        let deployment = Transaction::signed(
+            Transaction::deployment(Path::new("aleo/hello.aleo"), &private_key, None).unwrap(),
+            &private_key,
+            None,
+        )
+        .unwrap();
+        assert_eq!(0, chain.check(&deployment).code);
+        let pending = availability();
+        assert!(pending.pending && !pending.available);
+
+        assert_eq!(0, chain.produce_block(&[deployment])[0].code);
+        let deployed = availability();
+        assert!(!deployed.available && !deployed.pending);
+        assert_eq!(Some(chain.height() as u64), deployed.deployed_at);
+        assert_eq!(Some(&address), deployed.deployer.as_ref());
+        assert!(deployed.failed_deployments.is_empty());
+    }
+
+    #[test]
+    fn names_point_to_programs_of_the_namespace_owner() {
+        let private_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let other_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let address = vm::Address::try_from(&vm::ViewKey::try_from(&private_key).unwrap()).unwrap();
+        let chain = TestChain::with_account(&address, 100);
+        let program_id = vm::ProgramID::from_str("hello.aleo").unwrap();
+        let resolve = |name: &str| {
+            chain
+                .query::<NameResponse>(AbciQuery::ResolveName {
+                    name: name.to_string(),
+                })
+                .unwrap()
+        };
+        let registration = |name: &str, private_key: &vm::PrivateKey| {
+            let sequence = resolve(name).sequence;
+            Transaction::name_registration(name, program_id, sequence, private_key, None).unwrap()
+        };
+        let register = |name: &str, private_key: &vm::PrivateKey| {
+            chain.produce_block(&[registration(name, private_key)])[0].code
+        };
+
+        // the program has to be deployed first, in an envelope signed by the deployer
+        assert!(register("greetings/hello", &private_key) != 0);
+        let deployment = Transaction::signed(
+            Transaction::deployment(Path::new("aleo/hello.aleo"), &private_key, None).unwrap(),
+            &private_key,
+            None,
+        )
+        .unwrap();
+        assert_eq!(0, chain.produce_block(&[deployment])[0].code);
+        let first = registration("greetings/hello", &private_key);
+        assert_eq!(0, chain.produce_block(&[first.clone()])[0].code);
+
+        let name = resolve("greetings/hello");
+        assert_eq!(Some(program_id), name.program_id);
+        assert_eq!(Some(&address), name.owner.as_ref());
+        assert_eq!(1, name.sequence);
+        // a registration can't be replayed once the name is registered again
+        assert_eq!(0, register("greetings/hello", &private_key));
+        assert!(chain.produce_block(&[first])[0].code != 0);
+        assert_eq!(2, resolve("greetings/hello").sequence);
+        assert!(resolve("greetings/bye").program_id.is_none());
+        assert!(chain
+            .query::<NameResponse>(AbciQuery::ResolveName {
+                name: "hello.aleo".to_string()
+            })
+            .is_err());
+
+        // other accounts can't register names in the namespace, nor point their names to the program
+        assert!(register("greetings/bye", &other_key) != 0);
+        assert!(register("other/hello", &other_key) != 0);
+        assert!(resolve("other/hello").owner.is_none());
+        // while the owner can add more
+        assert_eq!(0, register("greetings/hi", &private_key));
+
+        // programs deployed without an envelope have no deployer, since anyone could have filled it in
+        let unsigned =
+            Transaction::deployment(Path::new("aleo/records.aleo"), &private_key, None).unwrap();
+        assert_eq!(0, chain.produce_block(&[unsigned])[0].code);
+        let records_id = vm::ProgramID::from_str("records.aleo").unwrap();
+        let registration =
+            Transaction::name_registration("greetings/records", records_id, 0, &private_key, None)
+                .unwrap();
+        assert!(chain.produce_block(&[registration])[0].code != 0);
+    }
+
+    #[test]
+    fn failed_deployments_are_not_pending() {
+        let app = SnarkVMApp::new_in_memory();
+        let private_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let deployment =
+            Transaction::deployment(Path::new("aleo/hello.aleo"), &private_key, None).unwrap();
+        assert!(app.check_tx(check_request(&deployment)).code == 0);
+        assert_eq!(1, app.pending_deployments.lock().unwrap().keys().len());
+
+        // another deployment of the same program is committed first, so the pending one fails its recheck
+        let other_deployment =
+            Transaction::deployment(Path::new("aleo/hello.aleo"), &private_key, None).unwrap();
+        app.pipeline.apply(&other_deployment).unwrap();
+        assert!(app.check_tx(recheck_request(&deployment)).code != 0);
+        assert!(app.pending_deployments.lock().unwrap().keys().is_empty());
+    }
+
+    #[test]
+    fn mempool_sender_limit() {
+        let mut senders = MempoolSenders::default();
+        senders.reserve("alice", "tx1", 2).unwrap();
+        senders.reserve("alice", "tx2", 2).unwrap();
+        // rechecking an already counted transaction doesn't use another slot
+        senders.reserve("alice", "tx2", 2).unwrap();
+        assert!(senders.reserve("alice", "tx3", 2).is_err());
+        senders.reserve("bob", "tx4", 2).unwrap();
+
+        senders.release("tx1");
+        senders.reserve("alice", "tx3", 2).unwrap();
+    }
+
     fn check_request(transaction: &Transaction) -> RequestCheckTx {
         RequestCheckTx {
             tx: bincode::serialize(transaction).unwrap(),
@@ -607,6 +2092,13 @@ mod tests {
         }
     }
 
+    fn recheck_request(transaction: &Transaction) -> RequestCheckTx {
+        RequestCheckTx {
+            tx: bincode::serialize(transaction).unwrap(),
+            r#type: CheckTxType::Recheck as i32,
+        }
+    }
+
     fn deliver_request(transaction: &Transaction) -> RequestDeliverTx {
         RequestDeliverTx {
             tx: bincode::serialize(transaction).unwrap(),
@@ -0,0 +1,225 @@
+//! Bloom filters of the keys of the record store columns, so checking a key that isn't in the column, e.g. the serial
+//! numbers of unknown records sent in spam transactions, takes a lookup in memory instead of a read from storage.
+//! The filters are persisted in the `Filters` column along with the commits that change them, and rebuilt from
+//! their column when they are missing or full.
+
+use crate::kv_backend::{BatchOperation, Column, KvRead};
+use log::{info, warn};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+
+/// Bits of the filter per key it's sized for. Along with `HASHES`, it gives a false positive rate of about 1% when
+/// the filter is full.
+const BITS_PER_KEY: u64 = 10;
+
+/// Amount of bits set for each key.
+const HASHES: u64 = 7;
+
+/// Bytes of each of the chunks the filter is persisted in, so a commit only rewrites the chunks it changed.
+const CHUNK_BYTES: usize = 4096;
+
+/// Amount of keys new filters are sized for. When a filter gets more keys it's rebuilt with twice the capacity.
+const MIN_CAPACITY: u64 = 100_000;
+
+/// A bloom filter of the keys of a column. It can tell for sure that a key isn't in the column, but a key it may
+/// contain still has to be looked up.
+pub struct BloomFilter {
+    /// Prefix of the keys of the filter in the `Filters` column.
+    name: &'static str,
+    /// Column whose keys are in the filter.
+    column: Column,
+    bits: Vec<u8>,
+    /// Amount of keys the filter is sized for.
+    capacity: u64,
+    /// Amount of keys inserted in the filter.
+    keys: u64,
+    /// Chunks changed since the filter was last persisted, see `take_changes`.
+    dirty: BTreeSet<usize>,
+}
+
+impl BloomFilter {
+    /// Load the filter persisted with the given name, or rebuild it from the keys of the column if it's missing or
+    /// invalid. A rebuilt filter has all its chunks pending to be persisted.
+    pub fn load<B: KvRead + ?Sized>(backend: &B, name: &'static str, column: Column) -> Self {
+        match Self::read(backend, name, column) {
+            Some(filter) => filter,
+            None => {
+                info!("building the {name} filter from the store");
+                Self::rebuild(backend, name, column, MIN_CAPACITY)
+            }
+        }
+    }
+
+    /// Build the filter from the keys of the column, sized for at least the given amount of keys.
+    pub fn rebuild<B: KvRead + ?Sized>(
+        backend: &B,
+        name: &'static str,
+        column: Column,
+        capacity: u64,
+    ) -> Self {
+        let keys: Vec<Vec<u8>> = backend.iterate(column, None).map(|(key, _)| key).collect();
+        let mut filter = Self::empty(name, column, capacity.max(keys.len() as u64 * 2));
+        for key in &keys {
+            filter.insert(key);
+        }
+        filter.dirty = (0..filter.bits.len() / CHUNK_BYTES).collect();
+        filter
+    }
+
+    fn empty(name: &'static str, column: Column, capacity: u64) -> Self {
+        let chunks =
+            ((capacity * BITS_PER_KEY / 8 + CHUNK_BYTES as u64 - 1) / CHUNK_BYTES as u64).max(1);
+        Self {
+            name,
+            column,
+            bits: vec![0; chunks as usize * CHUNK_BYTES],
+            capacity,
+            keys: 0,
+            dirty: BTreeSet::new(),
+        }
+    }
+
+    /// Read the persisted filter, None if it's missing or any of its chunks is.
+    fn read<B: KvRead + ?Sized>(backend: &B, name: &'static str, column: Column) -> Option<Self> {
+        let header = backend.get(Column::Filters, name.as_bytes()).ok()??;
+        if header.len() != 16 {
+            warn!("ignoring the malformed {name} filter");
+            return None;
+        }
+        let capacity = u64::from_be_bytes(header[..8].try_into().ok()?);
+        let keys = u64::from_be_bytes(header[8..].try_into().ok()?);
+        let mut filter = Self::empty(name, column, capacity);
+        filter.keys = keys;
+        for (index, chunk) in filter.bits.chunks_mut(CHUNK_BYTES).enumerate() {
+            match backend.get(Column::Filters, &chunk_key(name, index)) {
+                Ok(Some(bytes)) if bytes.len() == CHUNK_BYTES => chunk.copy_from_slice(&bytes),
+                _ => {
+                    warn!("ignoring the {name} filter, its chunk {index} is missing");
+                    return None;
+                }
+            }
+        }
+        Some(filter)
+    }
+
+    /// Add the key to the filter.
+    pub fn insert(&mut self, key: &[u8]) {
+        for bit in self.positions(key) {
+            self.bits[bit / 8] |= 1 << (bit % 8);
+            self.dirty.insert(bit / 8 / CHUNK_BYTES);
+        }
+        self.keys += 1;
+    }
+
+    /// Return false if the key was never inserted in the filter. True means it may have been, at the rate of false
+    /// positives of the filter.
+    pub fn may_contain(&self, key: &[u8]) -> bool {
+        self.positions(key)
+            .all(|bit| self.bits[bit / 8] & (1 << (bit % 8)) != 0)
+    }
+
+    /// Whether the filter has more keys than it's sized for, so it should be rebuilt to keep its false positive rate.
+    pub fn is_full(&self) -> bool {
+        self.keys > self.capacity
+    }
+
+    /// Build the filter again from its column, with twice the capacity. Meant to be called after writing the keys
+    /// inserted since the filter was loaded, since the ones that weren't written are lost.
+    pub fn grow<B: KvRead + ?Sized>(&mut self, backend: &B) {
+        info!(
+            "the {} filter has {} keys, rebuilding it for {}",
+            self.name,
+            self.keys,
+            self.capacity * 2
+        );
+        *self = Self::rebuild(backend, self.name, self.column, self.capacity * 2);
+    }
+
+    /// The writes that persist the changes to the filter since it was last persisted. They should be written atomically
+    /// with the keys inserted in the filter, so the persisted filter is never missing keys of the column.
+    pub fn take_changes(&mut self) -> Vec<BatchOperation> {
+        if self.dirty.is_empty() {
+            return Vec::new();
+        }
+        let mut header = self.capacity.to_be_bytes().to_vec();
+        header.extend(self.keys.to_be_bytes());
+        let mut batch = vec![BatchOperation::Put(
+            Column::Filters,
+            self.name.as_bytes().to_vec(),
+            header,
+        )];
+        for index in std::mem::take(&mut self.dirty) {
+            let chunk = self.bits[index * CHUNK_BYTES..(index + 1) * CHUNK_BYTES].to_vec();
+            batch.push(BatchOperation::Put(
+                Column::Filters,
+                chunk_key(self.name, index),
+                chunk,
+            ));
+        }
+        batch
+    }
+
+    /// Positions of the bits of the key, derived from two halves of its hash (Kirsch-Mitzenmacher double hashing).
+    fn positions(&self, key: &[u8]) -> impl Iterator<Item = usize> {
+        let hash = Sha256::digest(key);
+        let first = u64::from_be_bytes(hash[..8].try_into().expect("hash has 32 bytes"));
+        let second = u64::from_be_bytes(hash[8..16].try_into().expect("hash has 32 bytes"));
+        let size = self.bits.len() as u64 * 8;
+        (0..HASHES).map(move |i| (first.wrapping_add(i.wrapping_mul(second)) % size) as usize)
+    }
+}
+
+/// Key of a chunk of the filter in the `Filters` column, `{name}/` followed by the big endian chunk index.
+fn chunk_key(name: &str, index: usize) -> Vec<u8> {
+    let mut key = format!("{name}/").into_bytes();
+    key.extend((index as u32).to_be_bytes());
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kv_backend::{KvBackend, MemoryBackend};
+
+    #[test]
+    fn filters_are_persisted_and_rebuilt() {
+        let mut backend = MemoryBackend::default();
+        backend
+            .write(vec![BatchOperation::Put(
+                Column::Spent,
+                b"spent".to_vec(),
+                vec![],
+            )])
+            .unwrap();
+
+        // missing filters are built from the column
+        let mut filter = BloomFilter::load(&backend, "spent", Column::Spent);
+        assert!(filter.may_contain(b"spent"));
+        assert!(!filter.may_contain(b"unknown"));
+        filter.insert(b"new");
+        let changes = filter.take_changes();
+        assert_eq!(1 + filter.bits.len() / CHUNK_BYTES, changes.len());
+        backend.write(changes).unwrap();
+
+        // only the chunks changed since the last write are persisted again
+        filter.insert(b"other");
+        assert!(filter.take_changes().len() <= 1 + HASHES as usize);
+        assert!(filter.take_changes().is_empty());
+
+        let loaded = BloomFilter::load(&backend, "spent", Column::Spent);
+        assert!(loaded.may_contain(b"new"));
+        assert!(!loaded.may_contain(b"other"));
+        assert_eq!(2, loaded.keys);
+
+        // the false positive rate at full capacity stays around 1%
+        let mut filter = BloomFilter::empty("test", Column::Records, 10_000);
+        (0..10_000u32).for_each(|i| filter.insert(&i.to_be_bytes()));
+        assert!(!filter.is_full());
+        let false_positives = (10_000..20_000u32)
+            .filter(|i| filter.may_contain(&i.to_be_bytes()))
+            .count();
+        assert!(false_positives < 200, "{false_positives} false positives");
+        filter.insert(b"one too many");
+        assert!(filter.is_full());
+    }
+}
@@ -0,0 +1,216 @@
+use crate::record_store::RecordStore;
+use anyhow::{anyhow, ensure, Result};
+use lib::bridge::{self, BridgeStatus, Counterparty, CounterpartyChain, Packet};
+use lib::{light, merkle, vm};
+use std::collections::BTreeMap;
+use std::path::Path;
+use tendermint::block::signed_header::SignedHeader;
+use tendermint::validator;
+
+/// Tracks the counterparty chains this one exchanges credits with, see `lib::bridge`.
+/// For each of them it keeps the validator set trusted to sign its headers and the sequences of the packets
+/// sent and received, so packets are received exactly once and in order.
+/// Like the governance state, it's committed to the record store along with each block, see `encode`.
+#[derive(Debug)]
+pub struct Bridge {
+    /// Counterparties by chain id, sorted so the status is deterministic.
+    counterparties: BTreeMap<String, Counterparty>,
+}
+
+impl Bridge {
+    /// Create the bridge state committed to the record store with the last block. Nodes that haven't committed it
+    /// yet start from the bridge file used by previous versions of the application, if found, or with no
+    /// counterparties until the genesis configures them otherwise.
+    pub fn load(records: &RecordStore, legacy_path: &Path) -> Result<Self> {
+        let json = match records.bridge_state()? {
+            Some(bytes) => String::from_utf8(bytes)?,
+            None => match std::fs::read_to_string(legacy_path) {
+                Ok(json) => json,
+                Err(_) => return Ok(Self::new_in_memory()),
+            },
+        };
+        let status: BridgeStatus =
+            serde_json::from_str(&json).map_err(|e| anyhow!("bridge state is invalid: {e}"))?;
+
+        Ok(Self {
+            counterparties: status
+                .counterparties
+                .into_iter()
+                .map(|counterparty| (counterparty.chain_id.clone(), counterparty))
+                .collect(),
+        })
+    }
+
+    /// Create a bridge state with no counterparties.
+    pub fn new_in_memory() -> Self {
+        Self {
+            counterparties: BTreeMap::new(),
+        }
+    }
+
+    /// Set the counterparties configured at genesis.
+    pub fn init(&mut self, chains: &[CounterpartyChain]) {
+        self.counterparties = chains
+            .iter()
+            .map(|chain| (chain.chain_id.clone(), Counterparty::new(chain)))
+            .collect();
+    }
+
+    /// Whether the chain has counterparties, in which case the packets are committed in the app hash.
+    pub fn is_enabled(&self) -> bool {
+        !self.counterparties.is_empty()
+    }
+
+    pub fn status(&self) -> BridgeStatus {
+        BridgeStatus {
+            counterparties: self.counterparties.values().cloned().collect(),
+        }
+    }
+
+    /// Fail if credits can't be sent to the given chain.
+    pub fn validate_transfer(&self, destination_chain: &str) -> Result<()> {
+        self.counterparty(destination_chain).map(|_| ())
+    }
+
+    /// Build the next packet to the given chain and record it as sent.
+    /// Assumes the transfer has been validated previously with `validate_transfer`.
+    pub fn send(
+        &mut self,
+        source_chain: &str,
+        destination_chain: &str,
+        receiver: vm::Address,
+        amount: u64,
+    ) -> Result<Packet> {
+        let counterparty = self.counterparty_mut(destination_chain)?;
+        let packet = Packet {
+            source_chain: source_chain.to_string(),
+            destination_chain: destination_chain.to_string(),
+            sequence: counterparty.next_send_sequence,
+            receiver,
+            amount,
+        };
+        counterparty.next_send_sequence += 1;
+        counterparty.sent += amount;
+        Ok(packet)
+    }
+
+    /// Fail unless the header is signed by the validator set trusted for its chain, and the packets are
+    /// committed by it, sent to this chain and the next ones expected from it. Returns the decoded header.
+    pub fn validate_receive(
+        &self,
+        own_chain: &str,
+        header: &[u8],
+        validators: &[u8],
+        packets: &[(Packet, merkle::StateProof)],
+    ) -> Result<SignedHeader> {
+        let signed_header = bridge::decode_header(header)?;
+        let validators: validator::Set = bridge::decode_validators(validators)?;
+        let source_chain = signed_header.header.chain_id.to_string();
+        let counterparty = self.counterparty(&source_chain)?;
+
+        ensure!(
+            validators.hash().to_string() == counterparty.validators_hash,
+            "validator set of {source_chain} is not the trusted one {}",
+            counterparty.validators_hash
+        );
+        ensure!(
+            signed_header.header.height.value() >= counterparty.latest_height,
+            "header of {source_chain} at height {} is older than the latest verified one {}",
+            signed_header.header.height,
+            counterparty.latest_height
+        );
+        light::verify_header(&signed_header, &validators)?;
+
+        let mut sequence = counterparty.next_receive_sequence;
+        for (packet, proof) in packets {
+            ensure!(
+                packet.destination_chain == own_chain,
+                "packet {} is meant for {}",
+                packet.sequence,
+                packet.destination_chain
+            );
+            ensure!(
+                packet.sequence == sequence,
+                "expected packet {sequence} from {source_chain}, got {}",
+                packet.sequence
+            );
+            bridge::verify_packet(packet, proof, &signed_header.header)?;
+            sequence += 1;
+        }
+        Ok(signed_header)
+    }
+
+    /// Record the packets as received, trusting the next validator set committed by the header to sign
+    /// the following ones. Assumes they have been validated previously with `validate_receive`.
+    pub fn receive(&mut self, signed_header: &SignedHeader, packets: &[Packet]) -> Result<()> {
+        let header = &signed_header.header;
+        let counterparty = self.counterparty_mut(header.chain_id.as_str())?;
+        counterparty.validators_hash = header.next_validators_hash.to_string();
+        counterparty.latest_height = header.height.value();
+        for packet in packets {
+            counterparty.next_receive_sequence = packet.sequence + 1;
+            counterparty.received += packet.amount;
+        }
+        Ok(())
+    }
+
+    /// Serialize the bridge state to be committed with `RecordStore::stage_bridge`.
+    pub fn encode(&self) -> Vec<u8> {
+        serde_json::to_vec(&self.status()).expect("couldn't serialize bridge")
+    }
+
+    fn counterparty(&self, chain_id: &str) -> Result<&Counterparty> {
+        self.counterparties
+            .get(chain_id)
+            .ok_or_else(|| anyhow!("{chain_id} is not a counterparty of the bridge"))
+    }
+
+    fn counterparty_mut(&mut self, chain_id: &str) -> Result<&mut Counterparty> {
+        self.counterparties
+            .get_mut(chain_id)
+            .ok_or_else(|| anyhow!("{chain_id} is not a counterparty of the bridge"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[allow(clippy::clone_on_copy)]
+    fn packets_are_sent_in_sequence() {
+        let mut bridge = Bridge::new_in_memory();
+        assert!(!bridge.is_enabled());
+        bridge.init(&[
+            "chain-b=8F9C0E5C4D9A1B2C3D4E5F60718293A4B5C6D7E8F90A1B2C3D4E5F60718293A4"
+                .parse()
+                .unwrap(),
+        ]);
+        assert!(bridge.is_enabled());
+        assert!(bridge.validate_transfer("chain-c").is_err());
+        bridge.validate_transfer("chain-b").unwrap();
+
+        let private_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let receiver =
+            vm::Address::try_from(&vm::ViewKey::try_from(&private_key).unwrap()).unwrap();
+        let first = bridge
+            .send("chain-a", "chain-b", receiver.clone(), 10)
+            .unwrap();
+        let second = bridge.send("chain-a", "chain-b", receiver, 5).unwrap();
+        assert_eq!(0, first.sequence);
+        assert_eq!(1, second.sequence);
+
+        let status = bridge.status();
+        assert_eq!(2, status.counterparties[0].next_send_sequence);
+        assert_eq!(15, status.counterparties[0].sent);
+        assert_eq!(0, status.counterparties[0].next_receive_sequence);
+    }
+
+    #[test]
+    fn receives_need_a_valid_header() {
+        let bridge = Bridge::new_in_memory();
+        assert!(bridge
+            .validate_receive("chain-a", b"not a header", &[], &[])
+            .is_err());
+    }
+}
@@ -0,0 +1,265 @@
+use anyhow::{anyhow, bail, ensure, Result};
+use clap::ValueEnum;
+use lib::query::NodeRole;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashSet};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Storage engine used to keep the application state.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum StorageBackend {
+    /// Persist the state on disk with RocksDB.
+    #[default]
+    Rocksdb,
+    /// Keep the state in memory, losing it when the node stops. Intended for tests.
+    Memory,
+}
+
+/// When the store writes of the committed blocks are synced to disk. The writes of each block are done together at
+/// commit, so they reach the operating system whatever the policy, and survive the node crashing. Syncing less often
+/// cuts the commit latency on slow disks, at the risk of losing the last blocks if the whole machine crashes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FsyncPolicy {
+    /// Sync the writes of every block.
+    #[default]
+    Always,
+    /// Sync the writes every this many blocks, along with the ones of the blocks since the last sync.
+    EveryBlocks(u64),
+}
+
+impl FsyncPolicy {
+    /// Whether the writes should be synced when committing the block at the given height.
+    pub fn syncs_at(self, height: u64) -> bool {
+        match self {
+            Self::Always => true,
+            Self::EveryBlocks(blocks) => height % blocks == 0,
+        }
+    }
+}
+
+impl FromStr for FsyncPolicy {
+    type Err = anyhow::Error;
+
+    /// Parse `always` or an amount of blocks.
+    fn from_str(s: &str) -> Result<Self> {
+        if s == "always" {
+            return Ok(Self::Always);
+        }
+        let blocks: u64 = s
+            .parse()
+            .map_err(|_| anyhow!("expected always or an amount of blocks, got {s}"))?;
+        ensure!(
+            blocks > 0,
+            "the amount of blocks between syncs can't be zero"
+        );
+        Ok(Self::EveryBlocks(blocks))
+    }
+}
+
+/// How thoroughly transactions are verified before being accepted in the mempool. Relay nodes can skip the
+/// expensive checks to gossip more transactions, at the risk of relaying invalid ones that will fail once delivered.
+/// Transactions are always verified fully when delivered in a block, whatever the level.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum VerificationLevel {
+    /// Only check the transaction is well formed and consistent with the state, e.g. that its id matches its
+    /// contents and the programs it executes exist, without checking signatures or proofs.
+    Structure,
+    /// Also check the signatures of governance, deprecation and key rotation transactions.
+    Signature,
+    /// Also verify the execution proofs and the verifying keys of deployments.
+    #[default]
+    Full,
+}
+
+impl VerificationLevel {
+    pub fn checks_signatures(self) -> bool {
+        self >= Self::Signature
+    }
+
+    pub fn checks_proofs(self) -> bool {
+        self >= Self::Full
+    }
+}
+
+/// Node settings that aren't part of the consensus state, and so can differ between validators.
+#[derive(Clone, Debug, Default)]
+pub struct NodeConfig {
+    /// Storage backend used for the record store.
+    pub storage: StorageBackend,
+    /// When the store writes are synced to disk, see `FsyncPolicy`.
+    pub fsync: FsyncPolicy,
+    /// Amount of the most executed programs whose verifying keys are loaded at startup, besides credits.aleo's.
+    pub precompiled_programs: usize,
+    /// File with the `ProgramPolicy` applied to the transactions relayed by the node, if any.
+    pub policy_file: Option<PathBuf>,
+    /// Maximum amount of transactions from the same sender accepted in the mempool, if limited.
+    /// See `Transaction::sender` for the transactions this applies to.
+    pub max_mempool_txs_per_sender: Option<usize>,
+    /// Address to serve the pending record relay websocket at, if enabled. See `PendingRelay`.
+    pub pending_relay_addr: Option<SocketAddr>,
+    /// Verification applied by check_tx before accepting transactions in the mempool.
+    pub mempool_verification: VerificationLevel,
+    /// Which history of the chain the node keeps, see `NodeRole`.
+    pub role: NodeRole,
+    /// Tendermint RPC urls of the trusted peers that programs missing from the program store are fetched from.
+    pub program_peers: Vec<String>,
+}
+
+/// How the node writes its logs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// One abbreviated line per event, for humans.
+    #[default]
+    Compact,
+    /// One JSON object per event, with its level, target, fields and spans, for log shippers like Loki or Elastic.
+    Json,
+}
+
+/// Logging settings of the node, read from a JSON file like
+/// `{"level": "info", "modules": {"aleo_abci::record_store": "debug", "tendermint_abci": "warn"}, "format": "json"}`.
+/// Modules are tracing targets, i.e. module paths like `aleo_abci::pipeline` or `lib::vm`, and their levels apply to
+/// their submodules too.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LogConfig {
+    /// Level of the modules without one of their own, info if unset.
+    #[serde(default)]
+    pub level: Option<String>,
+    #[serde(default)]
+    pub modules: BTreeMap<String, String>,
+    #[serde(default)]
+    pub format: LogFormat,
+}
+
+impl LogConfig {
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    /// The filter directives of the config, in `tracing_subscriber::EnvFilter` syntax, e.g.
+    /// `info,aleo_abci::record_store=debug`. The given level, e.g. from the command line, replaces the one of the file.
+    pub fn directives(&self, level: Option<&str>) -> String {
+        let level = level.or(self.level.as_deref()).unwrap_or("info");
+        std::iter::once(level.to_string())
+            .chain(
+                self.modules
+                    .iter()
+                    .map(|(module, level)| format!("{module}={level}")),
+            )
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+/// Operator policy on which program executions the node accepts in its mempool, read from a JSON file like
+/// `{"deny": ["token.aleo"]}` or `{"allow": ["token.aleo"]}`. It only applies to check_tx: executions of refused
+/// programs are still delivered when a block includes them, so nodes with different policies stay in consensus.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProgramPolicy {
+    /// If set, only executions of these programs are relayed. credits.aleo is always allowed, since fees are paid with it.
+    #[serde(default)]
+    pub allow: Option<HashSet<String>>,
+    /// Programs whose executions are never relayed, even if they are in the allow list.
+    #[serde(default)]
+    pub deny: HashSet<String>,
+}
+
+impl ProgramPolicy {
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    /// Fail if the policy refuses to relay executions of the given program.
+    pub fn check(&self, program_id: &str) -> Result<()> {
+        if self.deny.contains(program_id) {
+            bail!("Executions of {program_id} are denied by the node policy");
+        }
+        if let Some(allow) = &self.allow {
+            if program_id != "credits.aleo" && !allow.contains(program_id) {
+                bail!("Executions of {program_id} are not allowed by the node policy");
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verification_levels_are_cumulative() {
+        assert!(!VerificationLevel::Structure.checks_signatures());
+        assert!(!VerificationLevel::Structure.checks_proofs());
+        assert!(VerificationLevel::Signature.checks_signatures());
+        assert!(!VerificationLevel::Signature.checks_proofs());
+        assert!(VerificationLevel::Full.checks_signatures());
+        assert!(VerificationLevel::Full.checks_proofs());
+        assert_eq!(VerificationLevel::Full, VerificationLevel::default());
+    }
+
+    #[test]
+    fn fsync_policy() {
+        assert_eq!(
+            FsyncPolicy::Always,
+            FsyncPolicy::from_str("always").unwrap()
+        );
+        let every_ten = FsyncPolicy::from_str("10").unwrap();
+        assert_eq!(FsyncPolicy::EveryBlocks(10), every_ten);
+        assert!(FsyncPolicy::from_str("0").is_err());
+        assert!(FsyncPolicy::from_str("never").is_err());
+
+        assert!(FsyncPolicy::Always.syncs_at(7));
+        assert!(!every_ten.syncs_at(7));
+        assert!(every_ten.syncs_at(20));
+    }
+
+    #[test]
+    fn log_config() {
+        assert_eq!("info", LogConfig::default().directives(None));
+
+        let config: LogConfig = serde_json::from_str(
+            r#"{"level": "warn", "modules": {"tendermint_abci": "error", "aleo_abci::record_store": "debug"}, "format": "json"}"#,
+        )
+        .unwrap();
+        assert_eq!(LogFormat::Json, config.format);
+        assert_eq!(
+            "warn,aleo_abci::record_store=debug,tendermint_abci=error",
+            config.directives(None)
+        );
+        assert_eq!(
+            "off,aleo_abci::record_store=debug,tendermint_abci=error",
+            config.directives(Some("off"))
+        );
+
+        assert!(serde_json::from_str::<LogConfig>(r#"{"format": "xml"}"#).is_err());
+        assert!(serde_json::from_str::<LogConfig>(r#"{"levels": {}}"#).is_err());
+    }
+
+    #[test]
+    fn program_policy() {
+        let policy = ProgramPolicy::default();
+        assert!(policy.check("token.aleo").is_ok());
+
+        let policy: ProgramPolicy = serde_json::from_str(r#"{"deny": ["token.aleo"]}"#).unwrap();
+        assert!(policy.check("token.aleo").is_err());
+        assert!(policy.check("hello.aleo").is_ok());
+
+        let policy: ProgramPolicy = serde_json::from_str(
+            r#"{"allow": ["hello.aleo", "token.aleo"], "deny": ["token.aleo"]}"#,
+        )
+        .unwrap();
+        assert!(policy.check("hello.aleo").is_ok());
+        assert!(policy.check("credits.aleo").is_ok());
+        assert!(policy.check("token.aleo").is_err());
+        assert!(policy.check("other.aleo").is_err());
+
+        assert!(serde_json::from_str::<ProgramPolicy>(r#"{"block": ["token.aleo"]}"#).is_err());
+    }
+}
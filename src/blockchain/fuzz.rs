@@ -0,0 +1,207 @@
+//! Drives the ABCI hooks with arbitrary input, to check that nothing tendermint or a byzantine proposer can send
+//! makes the application panic or corrupt its state. `FuzzedChain` is shared by the property tests below
+//! and the libfuzzer target in the `fuzz` directory.
+
+use crate::application::SnarkVMApp;
+use lib::validator::{GenesisState, Validator};
+use lib::vm;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use tendermint_abci::Application;
+use tendermint_proto::abci;
+
+/// Tendermint public key of the only validator of the fuzzed chains.
+const VALIDATOR_KEY: &str = "vM+mkdPMvplfxO7wM57z4FXy0TlBC2Onb+MaqcXE8ig=";
+
+/// An in-memory chain fed with arbitrary input, along with a reference chain started from the same genesis
+/// that only gets the transactions the first one delivered successfully. Since the state must only depend on the
+/// delivered transactions, both have to keep the same app hash whatever the input.
+pub struct FuzzedChain {
+    app: SnarkVMApp,
+    reference: SnarkVMApp,
+    validator: Validator,
+}
+
+impl FuzzedChain {
+    /// Start both chains with a credits record and a validator owned by an account derived from a fixed seed,
+    /// so the fuzzed state is the same across runs.
+    pub fn start() -> Self {
+        let private_key = vm::PrivateKey::new(&mut ChaCha8Rng::seed_from_u64(0)).unwrap();
+        let address = vm::Address::try_from(&vm::ViewKey::try_from(&private_key).unwrap()).unwrap();
+        let record = vm::mint_record("credits.aleo", "credits", &address, 1000, 0).unwrap();
+        let validator = Validator::from_str(VALIDATOR_KEY, &address.to_string(), 1).unwrap();
+        let genesis = GenesisState {
+            records: vec![record],
+            validators: vec![validator.clone()],
+            default_reward_address: None,
+            vesting: Vec::new(),
+            bridge: Vec::new(),
+            min_self_stake: 0,
+            max_validators: 0,
+            community_pool_percentage: 0,
+        };
+
+        let start = || {
+            let app = SnarkVMApp::new_in_memory();
+            app.init_chain(abci::RequestInitChain {
+                app_state_bytes: serde_json::to_vec(&genesis).unwrap(),
+                ..Default::default()
+            });
+            app
+        };
+        Self {
+            app: start(),
+            reference: start(),
+            validator,
+        }
+    }
+
+    /// Run a single input: its first byte selects the hook and the rest is the payload sent to it, either as the
+    /// transaction of `check_tx` and `deliver_tx` or as the data of a query, with a proof when the top bit is set.
+    pub fn run(&self, input: &[u8]) {
+        let (selector, payload) = match input.split_first() {
+            Some(split) => split,
+            None => return,
+        };
+        match selector % 3 {
+            0 => {
+                self.app.check_tx(abci::RequestCheckTx {
+                    tx: payload.to_vec(),
+                    r#type: abci::CheckTxType::New as i32,
+                });
+            }
+            1 => {
+                self.deliver(payload);
+            }
+            _ => {
+                self.app.query(abci::RequestQuery {
+                    data: payload.to_vec(),
+                    prove: selector & 0x80 != 0,
+                    ..Default::default()
+                });
+            }
+        }
+    }
+
+    /// Deliver the transaction in a block of its own, then check the state of the chain is the same as the one
+    /// of the reference chain, to which it's only delivered if it was accepted.
+    pub fn deliver(&self, tx: &[u8]) -> abci::ResponseDeliverTx {
+        let response = self.produce_block(&self.app, Some(tx));
+        if response.code == 0 {
+            let reference = self.produce_block(&self.reference, Some(tx));
+            assert_eq!(
+                0, reference.code,
+                "a transaction delivered by the fuzzed chain was rejected by the reference one: {}",
+                reference.log
+            );
+        } else {
+            self.produce_block(&self.reference, None);
+        }
+
+        let info_of = |app: &SnarkVMApp| app.info(Default::default());
+        let (info, reference) = (info_of(&self.app), info_of(&self.reference));
+        assert_eq!(info.last_block_height, reference.last_block_height);
+        assert_eq!(
+            info.last_block_app_hash, reference.last_block_app_hash,
+            "the state changed after delivering a transaction that failed with: {}",
+            response.log
+        );
+        response
+    }
+
+    /// Produce and commit a block with the given transaction, if any, returning its deliver_tx response.
+    fn produce_block(&self, app: &SnarkVMApp, tx: Option<&[u8]>) -> abci::ResponseDeliverTx {
+        let height = app.info(Default::default()).last_block_height + 1;
+        let votes = vec![abci::VoteInfo {
+            validator: Some(abci::Validator {
+                address: self.validator.address(),
+                power: self.validator.voting_power as i64,
+            }),
+            signed_last_block: true,
+        }];
+
+        app.begin_block(abci::RequestBeginBlock {
+            header: Some(tendermint_proto::types::Header {
+                height,
+                proposer_address: self.validator.address(),
+                ..Default::default()
+            }),
+            last_commit_info: Some(abci::LastCommitInfo { round: 0, votes }),
+            ..Default::default()
+        });
+        let response = tx
+            .map(|tx| app.deliver_tx(abci::RequestDeliverTx { tx: tx.to_vec() }))
+            .unwrap_or_default();
+        app.end_block(abci::RequestEndBlock { height });
+        app.commit();
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lib::transaction::Transaction;
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+    use proptest::sample::Index;
+    use proptest::test_runner::{Config, TestRunner};
+    use std::path::Path;
+
+    proptest! {
+        #![proptest_config(Config::with_cases(64))]
+
+        #[test]
+        fn arbitrary_inputs_are_handled(inputs in vec(vec(any::<u8>(), 0..512), 1..8)) {
+            let chain = FuzzedChain::start();
+            for input in inputs {
+                chain.run(&input);
+            }
+        }
+
+        #[test]
+        fn arbitrary_queries_are_handled(
+            variant in 0u32..32,
+            fields in vec(any::<u8>(), 0..64),
+            prove in any::<bool>(),
+        ) {
+            // a known variant tag followed by arbitrary fields gets further into the handlers than random bytes
+            let selector = if prove { 0x80 } else { 2 };
+            let input = [&[selector][..], &variant.to_le_bytes()[..], &fields[..]].concat();
+            FuzzedChain::start().run(&input);
+        }
+    }
+
+    #[test]
+    fn mutated_transactions_are_handled() {
+        let private_key = vm::PrivateKey::new(&mut ChaCha8Rng::seed_from_u64(1)).unwrap();
+        let deployment =
+            Transaction::deployment(Path::new("aleo/hello.aleo"), &private_key, None).unwrap();
+        let deployment = bincode::serialize(&deployment).unwrap();
+
+        // the unchanged transaction is delivered by both chains
+        assert_eq!(0, FuzzedChain::start().deliver(&deployment).code);
+
+        let mutations = (
+            vec((any::<Index>(), any::<u8>()), 1..4),
+            proptest::option::of(any::<Index>()),
+        );
+        TestRunner::new(Config::with_cases(16))
+            .run(&mutations, |(writes, truncate)| {
+                let mut tx = deployment.clone();
+                for (index, byte) in writes {
+                    let index = index.index(tx.len());
+                    tx[index] = byte;
+                }
+                if let Some(length) = truncate {
+                    tx.truncate(length.index(tx.len()));
+                }
+
+                let chain = FuzzedChain::start();
+                chain.run(&[&[0][..], &tx[..]].concat());
+                chain.deliver(&tx);
+                Ok(())
+            })
+            .unwrap();
+    }
+}
@@ -5,7 +5,7 @@ use std::{collections::HashMap, path::PathBuf};
 
 use anyhow::Result;
 use clap::Parser;
-use lib::{validator, vm};
+use lib::{bridge, validator, vm};
 
 /// Takes a list of node directories and updates the genesis files on each of them
 /// to include records to assign default credits to each validator and a mapping
@@ -23,6 +23,33 @@ pub struct Cli {
     /// The amount of gates to assign to each validator
     #[clap(long, default_value = "1000")]
     amount: u64,
+
+    /// Aleo account that gets the rewards of tendermint genesis validators without an aleo account in the app state,
+    /// e.g. validators added to the tendermint genesis after running this binary.
+    #[clap(long)]
+    default_reward_address: Option<vm::Address>,
+
+    /// JSON file with a list of vesting schedules, e.g.
+    /// [{"address": "aleo1...", "total": 1000000, "cliff_height": 100000, "release_period": 10000, "releases": 12}]
+    #[clap(long)]
+    vesting_file: Option<PathBuf>,
+
+    /// Counterparty chain of the credits bridge, as {chain_id}={validators_hash} with the hash of the validator set
+    /// trusted to sign its headers. Can be repeated.
+    #[clap(long = "bridge-counterparty")]
+    bridge: Vec<bridge::CounterpartyChain>,
+
+    /// Minimum stake, in gates, that a validator needs to be part of the active set.
+    #[clap(long, default_value_t = 0)]
+    min_self_stake: u64,
+
+    /// Maximum amount of validators in the active set, 0 for no limit.
+    #[clap(long, default_value_t = 0)]
+    max_validators: u64,
+
+    /// Percentage of the block rewards and fees paid to the community pool instead of the validators.
+    #[clap(long, default_value_t = 0, value_parser = clap::value_parser!(u64).range(0..=100))]
+    community_pool_percentage: u64,
 }
 
 fn main() -> Result<()> {
@@ -84,10 +111,25 @@ fn main() -> Result<()> {
         validators.push(validator);
     }
 
+    let vesting: Vec<validator::VestingSchedule> = match &cli.vesting_file {
+        Some(path) => serde_json::from_str(&std::fs::read_to_string(path)?)?,
+        None => Vec::new(),
+    };
+    for schedule in &vesting {
+        // fail here rather than when the nodes start the chain
+        schedule.releases()?;
+    }
+
     // update the genesis JSON with the calculated app state
     let genesis_state = validator::GenesisState {
         records: genesis_records,
         validators,
+        default_reward_address: cli.default_reward_address,
+        vesting,
+        bridge: cli.bridge,
+        min_self_stake: cli.min_self_stake,
+        max_validators: cli.max_validators,
+        community_pool_percentage: cli.community_pool_percentage,
     };
     genesis.as_object_mut().unwrap().insert(
         "app_state".to_string(),
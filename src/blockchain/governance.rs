@@ -0,0 +1,521 @@
+use crate::record_store::RecordStore;
+use anyhow::{anyhow, bail, ensure, Result};
+use lib::governance::{Action, CommunityPool, GovernanceStatus, Parameter, Params, Proposal};
+use lib::validator::{GenesisState, Validator};
+use lib::vm;
+use log::{error, info, warn};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Tracks the chain parameters and the governance proposals to change them.
+/// Validators submit proposals and vote on them with governance transactions; when a proposal reaches
+/// its activation height, it's applied if validators with more than 2/3 of the voting power approved it.
+/// Proposals can also spend the community pool, which accrues part of the rewards of every block.
+/// Like the validator set, the state is committed to the record store along with each block, see `encode`.
+#[derive(Debug)]
+pub struct Governance {
+    params: Params,
+    /// Pending proposals by id, sorted to apply them in a deterministic order.
+    proposals: BTreeMap<String, Proposal>,
+    /// Gates in the community pool.
+    community_pool: u64,
+    /// Nonce of the next action of each validator, by validator address, see `GovernanceStatus::nonces`.
+    nonces: BTreeMap<String, u64>,
+}
+
+impl Governance {
+    /// Create the governance state committed to the record store with the last block. Nodes that haven't
+    /// committed it yet start from the governance file used by previous versions of the application, if found,
+    /// or from the default parameters and no proposals otherwise.
+    pub fn load(records: &RecordStore, legacy_path: &Path) -> Result<Self> {
+        let json = match records.governance_state()? {
+            Some(bytes) => Some(String::from_utf8(bytes)?),
+            None => std::fs::read_to_string(legacy_path).ok().map(|json| {
+                info!("loading governance from {}", legacy_path.display());
+                json
+            }),
+        };
+        let status: GovernanceStatus = match json {
+            Some(json) => serde_json::from_str(&json)
+                .map_err(|e| anyhow!("governance state is invalid: {e}"))?,
+            None => return Ok(Self::new_in_memory()),
+        };
+
+        Ok(Self {
+            params: status.params,
+            proposals: status
+                .proposals
+                .into_iter()
+                .map(|proposal| (proposal.id.clone(), proposal))
+                .collect(),
+            community_pool: status.community_pool,
+            nonces: status.nonces,
+        })
+    }
+
+    /// Create a governance state with the default parameters.
+    pub fn new_in_memory() -> Self {
+        Self {
+            params: Params::default(),
+            proposals: BTreeMap::new(),
+            community_pool: 0,
+            nonces: BTreeMap::new(),
+        }
+    }
+
+    /// Set the chain parameters given in the genesis state.
+    pub fn init(&mut self, genesis: &GenesisState) {
+        self.params.min_self_stake = genesis.min_self_stake;
+        self.params.max_validators = genesis.max_validators;
+        self.params.community_pool_percentage = genesis.community_pool_percentage;
+    }
+
+    pub fn params(&self) -> &Params {
+        &self.params
+    }
+
+    pub fn status(&self) -> GovernanceStatus {
+        GovernanceStatus {
+            params: self.params.clone(),
+            proposals: self.proposals.values().cloned().collect(),
+            community_pool: self.community_pool,
+            nonces: self.nonces.clone(),
+        }
+    }
+
+    pub fn community_pool(&self) -> CommunityPool {
+        CommunityPool {
+            balance: self.community_pool,
+            percentage: self.params.community_pool_percentage,
+            pending_spends: self
+                .proposals
+                .values()
+                .filter(|proposal| proposal.recipient.is_some())
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// The nonce the next governance action of the given validator has to have.
+    fn next_nonce(&self, validator: &Validator) -> u64 {
+        self.nonces
+            .get(&hex::encode_upper(validator.address()))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Add the share of the block rewards diverted to the community pool.
+    pub fn fund_community_pool(&mut self, gates: u64) {
+        self.community_pool += gates;
+    }
+
+    /// Fail if the given validator can't submit the action of the given transaction at the given height, e.g.
+    /// because the proposal is unknown or its voting period is over, or because the action doesn't have the next
+    /// nonce of the validator, which would make it a replay of an action already applied.
+    pub fn validate(
+        &self,
+        transaction_id: &str,
+        action: &Action,
+        nonce: u64,
+        validator: Option<&Validator>,
+        height: u64,
+    ) -> Result<()> {
+        let validator =
+            validator.ok_or_else(|| anyhow!("only validators can submit governance actions"))?;
+        ensure!(
+            validator.voting_power > 0,
+            "validator {validator} has no voting power"
+        );
+        let expected = self.next_nonce(validator);
+        ensure!(
+            nonce == expected,
+            "the next action of validator {validator} must have nonce {expected}, not {nonce}"
+        );
+        if !matches!(action, Action::Vote { .. }) {
+            ensure!(
+                !self.proposals.contains_key(transaction_id),
+                "proposal {transaction_id} already exists"
+            );
+        }
+
+        match action {
+            Action::Propose {
+                parameter,
+                value,
+                activation_height,
+            } => {
+                ensure!(
+                    *activation_height > height,
+                    "activation height {activation_height} should be after the current height {height}"
+                );
+                // nodes can't go back to an older version once they upgraded
+                if *parameter == Parameter::AppVersion {
+                    ensure!(
+                        *value > self.params.app_version,
+                        "app version {value} should be greater than the current version {}",
+                        self.params.app_version
+                    );
+                }
+                if *parameter == Parameter::CommunityPoolPercentage {
+                    ensure!(
+                        *value <= 100,
+                        "community pool percentage {value} should be at most 100"
+                    );
+                }
+            }
+            Action::SpendCommunityPool {
+                amount,
+                activation_height,
+                ..
+            } => {
+                ensure!(
+                    *activation_height > height,
+                    "activation height {activation_height} should be after the current height {height}"
+                );
+                ensure!(*amount > 0, "the amount to spend should be positive");
+                // the balance is checked again at the activation height, since other spends may pass before
+                ensure!(
+                    *amount <= self.community_pool,
+                    "the community pool has {} gates, can't spend {amount}",
+                    self.community_pool
+                );
+            }
+            Action::Vote { proposal_id, .. } => {
+                let proposal = self
+                    .proposals
+                    .get(proposal_id)
+                    .ok_or_else(|| anyhow!("unknown proposal {proposal_id}"))?;
+                ensure!(
+                    proposal.activation_height > height,
+                    "voting for proposal {proposal_id} is over"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply the action submitted by the validator with the given transaction id, using up its nonce.
+    /// Assumes it has been validated previously with `validate`.
+    pub fn apply(&mut self, transaction_id: &str, action: &Action, validator: &Validator) {
+        let voter = hex::encode_upper(validator.address());
+        *self.nonces.entry(voter.clone()).or_default() += 1;
+        match action {
+            Action::Propose {
+                parameter,
+                value,
+                activation_height,
+            } => {
+                // proposers implicitly approve their own proposals
+                let proposal = Proposal {
+                    id: transaction_id.to_string(),
+                    parameter: Some(*parameter),
+                    value: *value,
+                    recipient: None,
+                    activation_height: *activation_height,
+                    votes: vec![(voter, true)],
+                };
+                self.proposals.insert(transaction_id.to_string(), proposal);
+            }
+            Action::SpendCommunityPool {
+                recipient,
+                amount,
+                activation_height,
+            } => {
+                #[allow(clippy::clone_on_copy)]
+                let proposal = Proposal {
+                    id: transaction_id.to_string(),
+                    parameter: None,
+                    value: *amount,
+                    recipient: Some(recipient.clone()),
+                    activation_height: *activation_height,
+                    votes: vec![(voter, true)],
+                };
+                self.proposals.insert(transaction_id.to_string(), proposal);
+            }
+            Action::Vote {
+                proposal_id,
+                approve,
+            } => {
+                if let Some(proposal) = self.proposals.get_mut(proposal_id) {
+                    proposal.votes.retain(|(address, _)| *address != voter);
+                    proposal.votes.push((voter, *approve));
+                }
+            }
+        }
+    }
+
+    /// Resolve the proposals that reached their activation height, applying the ones that were approved
+    /// by validators with more than 2/3 of the current voting power. Returns the id, recipient and amount of
+    /// the community pool spends that passed, whose gates were taken from the pool, to mint their records.
+    pub fn activate(
+        &mut self,
+        height: u64,
+        validators: &[Validator],
+    ) -> Vec<(String, vm::Address, u64)> {
+        let total_power: u64 = validators.iter().map(|v| v.voting_power).sum();
+        let voting_power = |address: &str| {
+            validators
+                .iter()
+                .find(|validator| hex::encode_upper(validator.address()) == address)
+                .map_or(0, |validator| validator.voting_power)
+        };
+
+        let due: Vec<String> = self
+            .proposals
+            .values()
+            .filter(|proposal| proposal.activation_height <= height)
+            .map(|proposal| proposal.id.clone())
+            .collect();
+
+        let mut spends = Vec::new();
+        for id in due {
+            let proposal = self.proposals.remove(&id).unwrap();
+            let approvals: u64 = proposal
+                .votes
+                .iter()
+                .filter(|(_, approve)| *approve)
+                .map(|(address, _)| voting_power(address))
+                .sum();
+
+            if approvals * 3 <= total_power * 2 {
+                info!("proposal {id} rejected with {approvals} of {total_power} voting power");
+                continue;
+            }
+            match (proposal.parameter, proposal.recipient) {
+                (Some(parameter), _) => {
+                    info!(
+                        "proposal {id} passed, setting {parameter} to {}",
+                        proposal.value
+                    );
+                    self.params.set(parameter, proposal.value);
+                }
+                (None, Some(recipient)) if proposal.value <= self.community_pool => {
+                    info!(
+                        "proposal {id} passed, paying {} gates of the community pool to {recipient}",
+                        proposal.value
+                    );
+                    self.community_pool -= proposal.value;
+                    spends.push((id, recipient, proposal.value));
+                }
+                (None, Some(_)) => {
+                    warn!(
+                        "proposal {id} passed, but the community pool has {} gates, can't spend {}",
+                        self.community_pool, proposal.value
+                    );
+                }
+                (None, None) => error!("proposal {id} has neither a parameter nor a recipient"),
+            }
+        }
+        spends
+    }
+
+    /// Serialize the governance state to be committed with `RecordStore::stage_governance`.
+    pub fn encode(&self) -> Vec<u8> {
+        serde_json::to_vec(&self.status()).expect("couldn't serialize governance")
+    }
+
+    /// Fail if the transaction creates records below the minimum amount of gates. Records with zero gates are
+    /// explicit burns, e.g. what's left of a record spent entirely, so they are accepted.
+    pub fn check_record_gates(&self, commitment: &vm::Field, gates: u64) -> Result<()> {
+        let min_record_gates = self.params.min_record_gates;
+        ensure!(
+            gates == 0 || gates >= min_record_gates,
+            "Output record {commitment} has {gates} gates, below the minimum of {min_record_gates}"
+        );
+        Ok(())
+    }
+
+    /// Fail if the transaction pays less than the base fee.
+    pub fn check_base_fee(&self, fees: i64) -> Result<()> {
+        if fees < self.params.base_fee as i64 {
+            bail!(
+                "Transaction fee {fees} is lower than the base fee {}",
+                self.params.base_fee
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proposals_need_two_thirds() {
+        let validators = vec![
+            validator("vM+mkdPMvplfxO7wM57z4FXy0TlBC2Onb+MaqcXE8ig=", 2),
+            validator("2HWbuGk04WQm/CrI/0HxoEtjGY0DXp8oMY6RsyrWwbU=", 1),
+            validator("TtJ9B7yGXANFIJqH2LJO8JN6M2WOn2w7sRN0HHi14UE=", 1),
+        ];
+        let mut governance = Governance::new_in_memory();
+        let propose = Action::Propose {
+            parameter: Parameter::BlockReward,
+            value: 200,
+            activation_height: 10,
+        };
+
+        // can't propose a change in the past
+        assert!(governance
+            .validate("proposal", &propose, 0, Some(&validators[0]), 10)
+            .is_err());
+        // only validators can propose
+        assert!(governance
+            .validate("proposal", &propose, 0, None, 1)
+            .is_err());
+
+        governance
+            .validate("proposal", &propose, 0, Some(&validators[0]), 1)
+            .unwrap();
+        governance.apply("proposal", &propose, &validators[0]);
+        // the same action can't be applied twice, nor can a proposal with the same id
+        assert!(governance
+            .validate("proposal", &propose, 0, Some(&validators[0]), 1)
+            .is_err());
+        assert!(governance
+            .validate("proposal", &propose, 1, Some(&validators[0]), 1)
+            .is_err());
+        assert_eq!(Some(&1), governance.status().nonces.values().next());
+
+        // proposals aren't resolved before their activation height
+        governance.activate(9, &validators);
+        assert_eq!(1, governance.status().proposals.len());
+
+        let vote = Action::Vote {
+            proposal_id: "proposal".to_string(),
+            approve: true,
+        };
+        governance
+            .validate("vote", &vote, 0, Some(&validators[1]), 5)
+            .unwrap();
+        governance.apply("vote", &vote, &validators[1]);
+
+        // the proposal is applied at the activation height
+        governance.activate(10, &validators);
+        assert_eq!(200, governance.params().block_reward);
+        assert!(governance.status().proposals.is_empty());
+
+        // can't vote on resolved proposals
+        assert!(governance
+            .validate("late vote", &vote, 0, Some(&validators[2]), 11)
+            .is_err());
+    }
+
+    #[test]
+    fn upgrades_only_increase_app_version() {
+        let validators = vec![validator("vM+mkdPMvplfxO7wM57z4FXy0TlBC2Onb+MaqcXE8ig=", 1)];
+        let mut governance = Governance::new_in_memory();
+        let downgrade = Action::Propose {
+            parameter: Parameter::AppVersion,
+            value: 1,
+            activation_height: 10,
+        };
+        assert!(governance
+            .validate("downgrade", &downgrade, 0, Some(&validators[0]), 1)
+            .is_err());
+
+        let upgrade = Action::Propose {
+            parameter: Parameter::AppVersion,
+            value: 2,
+            activation_height: 10,
+        };
+        governance
+            .validate("upgrade", &upgrade, 0, Some(&validators[0]), 1)
+            .unwrap();
+        governance.apply("upgrade", &upgrade, &validators[0]);
+        governance.activate(10, &validators);
+        assert_eq!(2, governance.params().app_version);
+    }
+
+    #[test]
+    fn dust_records_are_rejected() {
+        let validators = vec![validator("vM+mkdPMvplfxO7wM57z4FXy0TlBC2Onb+MaqcXE8ig=", 1)];
+        let (commitment, _) =
+            vm::mint_record("credits.aleo", "credits", &validators[0].aleo_address, 1, 0).unwrap();
+
+        // disabled by default
+        let mut governance = Governance::new_in_memory();
+        governance.check_record_gates(&commitment, 1).unwrap();
+
+        let propose = Action::Propose {
+            parameter: Parameter::MinRecordGates,
+            value: 10,
+            activation_height: 10,
+        };
+        governance.apply("dust", &propose, &validators[0]);
+        governance.activate(10, &validators);
+
+        assert!(governance.check_record_gates(&commitment, 9).is_err());
+        governance.check_record_gates(&commitment, 10).unwrap();
+        // burns are allowed
+        governance.check_record_gates(&commitment, 0).unwrap();
+    }
+
+    #[test]
+    #[allow(clippy::clone_on_copy)]
+    fn community_pool_spends() {
+        let validators = vec![validator("vM+mkdPMvplfxO7wM57z4FXy0TlBC2Onb+MaqcXE8ig=", 1)];
+        let recipient = validators[0].aleo_address.clone();
+        let mut governance = Governance::new_in_memory();
+        let spend = |amount, activation_height| Action::SpendCommunityPool {
+            recipient: recipient.clone(),
+            amount,
+            activation_height,
+        };
+
+        // can't spend more than the pool has
+        assert!(governance
+            .validate("first", &spend(10, 5), 0, Some(&validators[0]), 1)
+            .is_err());
+        governance.fund_community_pool(15);
+        governance
+            .validate("first", &spend(10, 5), 0, Some(&validators[0]), 1)
+            .unwrap();
+        governance.apply("first", &spend(10, 5), &validators[0]);
+        governance.apply("second", &spend(10, 5), &validators[0]);
+        assert_eq!(2, governance.community_pool().pending_spends.len());
+
+        // only the first spend fits in the pool when they are resolved
+        let spends = governance.activate(5, &validators);
+        assert_eq!(1, spends.len());
+        assert_eq!("first", spends[0].0);
+        assert_eq!(recipient.to_string(), spends[0].1.to_string());
+        assert_eq!(10, spends[0].2);
+        assert_eq!(5, governance.community_pool().balance);
+        assert!(governance.community_pool().pending_spends.is_empty());
+    }
+
+    #[test]
+    fn state_is_loaded_from_the_record_store() {
+        let records = RecordStore::new_in_memory();
+        let missing = Path::new("missing.governance");
+        let mut governance = Governance::load(&records, missing).unwrap();
+        assert_eq!(0, governance.community_pool().balance);
+
+        // the staged state is only loaded once it's committed along with the block
+        governance.fund_community_pool(15);
+        records.stage_governance(governance.encode()).unwrap();
+        assert_eq!(
+            0,
+            Governance::load(&records, missing)
+                .unwrap()
+                .community_pool()
+                .balance
+        );
+        records.commit(1).unwrap();
+        assert_eq!(
+            15,
+            Governance::load(&records, missing)
+                .unwrap()
+                .community_pool()
+                .balance
+        );
+    }
+
+    fn validator(pub_key: &str, voting_power: u64) -> Validator {
+        let private_key = lib::vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let view_key = lib::vm::ViewKey::try_from(&private_key).unwrap();
+        let address = lib::vm::Address::try_from(&view_key).unwrap();
+        Validator::from_str(pub_key, &address.to_string(), voting_power).unwrap()
+    }
+}
@@ -0,0 +1,207 @@
+use crate::application::SnarkVMApp;
+use anyhow::Result;
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tracing::{debug, error, info};
+
+/// Maximum size of the requests read by the health server, probes only send a request line and a few headers.
+const MAX_REQUEST_BYTES: usize = 8192;
+
+/// Signals of the ABCI connections that the health endpoints and the stall watchdog report on,
+/// updated by the application hooks.
+#[derive(Debug, Default)]
+pub struct Liveness {
+    /// Whether tendermint connected to the application, which it does by sending an info request.
+    connected: AtomicBool,
+    /// When the last block was committed by this process, if any.
+    last_commit: Mutex<Option<Instant>>,
+    /// Last error processing a block, e.g. a transaction that failed to be delivered.
+    last_error: Mutex<Option<String>>,
+}
+
+impl Liveness {
+    pub fn connected(&self) {
+        self.connected.store(true, Ordering::SeqCst);
+    }
+
+    pub fn committed(&self) {
+        *self.last_commit.lock().unwrap() = Some(Instant::now());
+    }
+
+    pub fn failed(&self, error: String) {
+        *self.last_error.lock().unwrap() = Some(error);
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::SeqCst)
+    }
+
+    /// Time elapsed since the last block was committed by this process, if any was.
+    pub fn since_last_commit(&self) -> Option<Duration> {
+        self.last_commit
+            .lock()
+            .unwrap()
+            .map(|instant| instant.elapsed())
+    }
+
+    pub fn last_error(&self) -> Option<String> {
+        self.last_error.lock().unwrap().clone()
+    }
+}
+
+/// Body of the health endpoint responses.
+#[derive(Debug, Serialize)]
+pub struct HealthReport {
+    /// Whether the record store answers requests.
+    pub store: bool,
+    /// Whether tendermint connected to the application.
+    pub abci_connected: bool,
+    /// Last committed block height.
+    pub height: i64,
+    /// Seconds since this process committed the last block, None if it didn't commit any yet.
+    pub seconds_since_commit: Option<u64>,
+    /// Last error processing a block, if any since the process started.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+impl HealthReport {
+    /// The node is live as long as its stores work, even if it's not making progress, e.g. while tendermint
+    /// catches up with the network.
+    pub fn is_live(&self) -> bool {
+        self.store
+    }
+
+    /// The node is ready if it's live, connected to tendermint and committed a block recently.
+    pub fn is_ready(&self, max_block_age: Duration) -> bool {
+        self.is_live()
+            && self.abci_connected
+            && self
+                .seconds_since_commit
+                .map_or(false, |seconds| seconds <= max_block_age.as_secs())
+    }
+}
+
+/// Start serving the health endpoints at the given address, in a thread of its own, returning the address
+/// it's bound to. `GET /healthz` answers 200 while the node is live and `GET /readyz` answers 200 while it's ready,
+/// see `HealthReport`, both with a JSON report; they answer 503 otherwise, so orchestrators can use them as probes.
+pub fn start(addr: SocketAddr, app: SnarkVMApp, max_block_age: Duration) -> Result<SocketAddr> {
+    let listener = std::net::TcpListener::bind(addr)?;
+    listener.set_nonblocking(true)?;
+    let addr = listener.local_addr()?;
+
+    std::thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("could not start the health server");
+        runtime.block_on(async move {
+            let listener = tokio::net::TcpListener::from_std(listener)
+                .expect("could not listen for health probes");
+            info!("Serving health probes at http://{addr}");
+            loop {
+                match listener.accept().await {
+                    Ok((stream, peer)) => {
+                        if let Err(e) = serve(stream, &app, max_block_age).await {
+                            debug!("Failed to answer the health probe of {peer}: {e}");
+                        }
+                    }
+                    Err(e) => error!("Could not accept a health probe: {e}"),
+                }
+            }
+        });
+    });
+    Ok(addr)
+}
+
+async fn serve(mut stream: TcpStream, app: &SnarkVMApp, max_block_age: Duration) -> Result<()> {
+    let mut request = Vec::new();
+    let mut buffer = [0; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n")
+        && request.len() < MAX_REQUEST_BYTES
+    {
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&buffer[..read]);
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let mut request_line = request
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .split_whitespace();
+    let (status, body) = match (request_line.next(), request_line.next()) {
+        (Some("GET"), Some("/healthz")) => {
+            let report = app.health_report();
+            (
+                status_line(report.is_live()),
+                serde_json::to_string(&report)?,
+            )
+        }
+        (Some("GET"), Some("/readyz")) => {
+            let report = app.health_report();
+            (
+                status_line(report.is_ready(max_block_age)),
+                serde_json::to_string(&report)?,
+            )
+        }
+        _ => ("404 Not Found", String::new()),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+fn status_line(ok: bool) -> &'static str {
+    if ok {
+        "200 OK"
+    } else {
+        "503 Service Unavailable"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_chain::TestChain;
+    use lib::vm;
+
+    #[tokio::test]
+    async fn probes_report_readiness_after_a_commit() {
+        let private_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let address = vm::Address::try_from(&vm::ViewKey::try_from(&private_key).unwrap()).unwrap();
+        let chain = TestChain::with_account(&address, 100);
+        let addr = start(
+            "127.0.0.1:0".parse().unwrap(),
+            chain.app().clone(),
+            Duration::from_secs(60),
+        )
+        .unwrap();
+
+        let get = |path: &str| reqwest::get(format!("http://{addr}{path}"));
+        assert_eq!(200, get("/healthz").await.unwrap().status());
+        // nothing was committed yet
+        assert_eq!(503, get("/readyz").await.unwrap().status());
+        assert_eq!(404, get("/metrics").await.unwrap().status());
+
+        chain.produce_block(&[]);
+        let response = get("/readyz").await.unwrap();
+        assert_eq!(200, response.status());
+        let report: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(1, report["height"]);
+        assert!(report["abci_connected"].as_bool().unwrap());
+    }
+}
@@ -0,0 +1,611 @@
+use anyhow::{anyhow, Result};
+use itertools::{EitherOrBoth, Itertools};
+use rocksdb::{ColumnFamily, Direction, IteratorMode, Options, WriteBatch, DB};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+type Key = Vec<u8>;
+type Value = Vec<u8>;
+
+/// The separate key spaces used by the stores. On RocksDB each of them is a column family,
+/// so the different tables can share a single database (and write atomically across them).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Column {
+    /// Record ciphertexts by commitment.
+    Records,
+    /// Height of spending by record serial number.
+    Spent,
+    /// Height of creation by record commitment.
+    Heights,
+    /// Deployed programs and their verifying keys by program id.
+    Programs,
+    /// Deployment metadata by program id.
+    ProgramMetadata,
+    /// Store metadata, e.g. the schema version.
+    Meta,
+    /// Encrypted record memos by record commitment.
+    Memos,
+    /// Execution statistics by program id.
+    ProgramStats,
+    /// Encoded validator set snapshots by the height of the block that committed them.
+    Validators,
+    /// Scheduled executions waiting for their target height, by height and transaction id.
+    Scheduled,
+    /// Output record commitments of the scheduled executions, with the `Scheduled` key of their execution as value.
+    ScheduledOutputs,
+    /// Encoded events emitted while processing each block, by block height.
+    Events,
+    /// Chunks of deployments waiting for the rest of their chunks, by deployment hash and chunk index.
+    DeploymentChunks,
+    /// Height at which the staging of a chunked deployment was paid and its amount of chunks, by deployment hash.
+    DeploymentStagings,
+    /// Index of the records created by each program, keyed by program id and record commitment.
+    ProgramRecords,
+    /// Bridge packets sent to counterparty chains, by destination chain and sequence.
+    Packets,
+    /// Encoded distribution of the block rewards between the validators, by block height.
+    Rewards,
+    /// Id of the program whose address owns the record, by the serial number of the records owned by programs.
+    ProgramOwned,
+    /// Persisted bloom filters of the keys of other columns, by filter name and chunk index.
+    Filters,
+    /// Failed attempts to deploy a program by program id, until the program gets deployed.
+    FailedDeployments,
+    /// Registered program names, by `namespace/name`, and the owners of their namespaces, by namespace.
+    Names,
+}
+
+impl Column {
+    pub const ALL: [Column; 21] = [
+        Column::Records,
+        Column::Spent,
+        Column::Heights,
+        Column::Programs,
+        Column::ProgramMetadata,
+        Column::Meta,
+        Column::Memos,
+        Column::ProgramStats,
+        Column::Validators,
+        Column::Scheduled,
+        Column::ScheduledOutputs,
+        Column::Events,
+        Column::DeploymentChunks,
+        Column::DeploymentStagings,
+        Column::ProgramRecords,
+        Column::Packets,
+        Column::Rewards,
+        Column::ProgramOwned,
+        Column::Filters,
+        Column::FailedDeployments,
+        Column::Names,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Column::Records => "records",
+            Column::Spent => "spent",
+            Column::Heights => "heights",
+            Column::Programs => "programs",
+            Column::ProgramMetadata => "program_metadata",
+            Column::Meta => "meta",
+            Column::Memos => "memos",
+            Column::ProgramStats => "program_stats",
+            Column::Validators => "validators",
+            Column::Scheduled => "scheduled",
+            Column::ScheduledOutputs => "scheduled_outputs",
+            Column::Events => "events",
+            Column::DeploymentChunks => "deployment_chunks",
+            Column::DeploymentStagings => "deployment_stagings",
+            Column::ProgramRecords => "program_records",
+            Column::Packets => "packets",
+            Column::Rewards => "rewards",
+            Column::ProgramOwned => "program_owned",
+            Column::Filters => "filters",
+            Column::FailedDeployments => "failed_deployments",
+            Column::Names => "names",
+        }
+    }
+}
+
+/// A single change to be applied as part of a write batch.
+#[derive(Clone, Debug)]
+pub enum BatchOperation {
+    Put(Column, Key, Value),
+    Delete(Column, Key),
+}
+
+impl BatchOperation {
+    /// Serialize a batch to be written later, see `decode_batch`. Columns are encoded by name, so the batch can
+    /// be decoded by versions of the application that add columns.
+    pub fn encode_batch(batch: &[BatchOperation]) -> Result<Vec<u8>> {
+        let entries: Vec<(&str, &Key, Option<&Value>)> = batch
+            .iter()
+            .map(|operation| match operation {
+                BatchOperation::Put(column, key, value) => (column.name(), key, Some(value)),
+                BatchOperation::Delete(column, key) => (column.name(), key, None),
+            })
+            .collect();
+        Ok(bincode::serialize(&entries)?)
+    }
+
+    /// Parse a batch serialized with `encode_batch`.
+    pub fn decode_batch(bytes: &[u8]) -> Result<Vec<BatchOperation>> {
+        let entries: Vec<(String, Key, Option<Value>)> = bincode::deserialize(bytes)?;
+        entries
+            .into_iter()
+            .map(|(name, key, value)| {
+                let column = *Column::ALL
+                    .iter()
+                    .find(|column| column.name() == name)
+                    .ok_or_else(|| anyhow!("unknown column {name}"))?;
+                Ok(match value {
+                    Some(value) => BatchOperation::Put(column, key, value),
+                    None => BatchOperation::Delete(column, key),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Read access to the stored entries, shared by the backends and their replicas.
+pub trait KvRead: Send + 'static {
+    /// Return the value stored for the key, if any.
+    fn get(&self, column: Column, key: &[u8]) -> Result<Option<Value>>;
+
+    /// Iterate the column entries sorted by key, starting from the given key (inclusive) if any.
+    fn iterate(
+        &self,
+        column: Column,
+        from: Option<&[u8]>,
+    ) -> Box<dyn Iterator<Item = (Key, Value)> + '_>;
+
+    /// Return whether the key is present. Errors reading the storage are reported as present,
+    /// to err on the side of rejecting duplicate records and double spends.
+    fn contains(&self, column: Column, key: &[u8]) -> bool {
+        !matches!(self.get(column, key), Ok(None))
+    }
+}
+
+/// Minimal key/value storage interface needed by the stores, so the storage engine can be swapped,
+/// e.g. to run tests in memory while validators persist their state on disk.
+/// Backends are owned by the store thread so they don't need to be shared across threads.
+pub trait KvBackend: KvRead {
+    /// Apply all the given operations atomically.
+    fn write(&mut self, batch: Vec<BatchOperation>) -> Result<()>;
+
+    /// Make the writes done so far durable, so they survive a crash of the machine and not just of the node.
+    /// Backends that don't persist their state have nothing to do.
+    fn sync(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Open a read-only view of the stored entries, which can be moved to another thread to serve queries
+    /// without contending with the writes. It only sees the writes done before it was opened or refreshed.
+    fn replica(&self) -> Box<dyn KvReplica>;
+}
+
+/// Read-only view of a backend, see `KvBackend::replica`.
+pub trait KvReplica: KvRead {
+    /// Catch up with the writes done to the backend since the replica was opened or last refreshed.
+    fn refresh(&mut self);
+}
+
+/// RocksDB backend, using one column family per store table.
+/// Its replicas share the database, since RocksDB reads don't block writes and each iteration
+/// reads from an implicit snapshot, so they always see the last write.
+#[derive(Clone)]
+pub struct RocksDbBackend {
+    db: Arc<DB>,
+}
+
+impl RocksDbBackend {
+    pub fn open(path: &str) -> Result<Self> {
+        let mut options = Options::default();
+        options.create_if_missing(true);
+        options.create_missing_column_families(true);
+
+        let db = DB::open_cf(&options, path, Column::ALL.iter().map(Column::name))?;
+        Ok(Self { db: Arc::new(db) })
+    }
+
+    fn handle(&self, column: Column) -> Result<&ColumnFamily> {
+        self.db
+            .cf_handle(column.name())
+            .ok_or_else(|| anyhow!("missing column family {}", column.name()))
+    }
+}
+
+impl KvRead for RocksDbBackend {
+    fn get(&self, column: Column, key: &[u8]) -> Result<Option<Value>> {
+        Ok(self.db.get_cf(self.handle(column)?, key)?)
+    }
+
+    fn iterate(
+        &self,
+        column: Column,
+        from: Option<&[u8]>,
+    ) -> Box<dyn Iterator<Item = (Key, Value)> + '_> {
+        let handle = match self.handle(column) {
+            Ok(handle) => handle,
+            Err(_) => return Box::new(std::iter::empty()),
+        };
+
+        let mode = from.map_or(IteratorMode::Start, |key| {
+            IteratorMode::From(key, Direction::Forward)
+        });
+        Box::new(
+            self.db
+                .iterator_cf(handle, mode)
+                .flatten()
+                .map(|(key, value)| (key.to_vec(), value.to_vec())),
+        )
+    }
+}
+
+impl KvBackend for RocksDbBackend {
+    fn write(&mut self, batch: Vec<BatchOperation>) -> Result<()> {
+        let mut write_batch = WriteBatch::default();
+        for operation in batch {
+            match operation {
+                BatchOperation::Put(column, key, value) => {
+                    write_batch.put_cf(self.handle(column)?, key, value)
+                }
+                BatchOperation::Delete(column, key) => {
+                    write_batch.delete_cf(self.handle(column)?, key)
+                }
+            }
+        }
+        Ok(self.db.write(write_batch)?)
+    }
+
+    /// The writes go through the write-ahead log without waiting for it to reach the disk, this waits for it.
+    fn sync(&mut self) -> Result<()> {
+        Ok(self.db.flush_wal(true)?)
+    }
+
+    fn replica(&self) -> Box<dyn KvReplica> {
+        Box::new(self.clone())
+    }
+}
+
+impl KvReplica for RocksDbBackend {
+    fn refresh(&mut self) {}
+}
+
+type Columns = HashMap<Column, Arc<BTreeMap<Key, Value>>>;
+
+/// Volatile backend that keeps everything in memory, intended for tests and throwaway nodes.
+/// Columns are shared with the replicas and copied on write, so a replica keeps reading the entries it was
+/// refreshed with while the backend changes.
+#[derive(Debug, Default)]
+pub struct MemoryBackend {
+    columns: Columns,
+    /// The columns after the last write, for the replicas to refresh from.
+    latest: Arc<Mutex<Columns>>,
+}
+
+/// Replica of a `MemoryBackend`, see `KvBackend::replica`.
+#[derive(Debug)]
+pub struct MemoryReplica {
+    columns: Columns,
+    latest: Arc<Mutex<Columns>>,
+}
+
+fn get_entry(columns: &Columns, column: Column, key: &[u8]) -> Option<Value> {
+    columns
+        .get(&column)
+        .and_then(|entries| entries.get(key))
+        .cloned()
+}
+
+fn iterate_entries<'a>(
+    columns: &'a Columns,
+    column: Column,
+    from: Option<&[u8]>,
+) -> Box<dyn Iterator<Item = (Key, Value)> + 'a> {
+    let from = from.map(|key| key.to_vec()).unwrap_or_default();
+    match columns.get(&column) {
+        Some(entries) => Box::new(
+            entries
+                .range(from..)
+                .map(|(key, value)| (key.clone(), value.clone())),
+        ),
+        None => Box::new(std::iter::empty()),
+    }
+}
+
+impl KvRead for MemoryBackend {
+    fn get(&self, column: Column, key: &[u8]) -> Result<Option<Value>> {
+        Ok(get_entry(&self.columns, column, key))
+    }
+
+    fn iterate(
+        &self,
+        column: Column,
+        from: Option<&[u8]>,
+    ) -> Box<dyn Iterator<Item = (Key, Value)> + '_> {
+        iterate_entries(&self.columns, column, from)
+    }
+}
+
+impl KvBackend for MemoryBackend {
+    fn write(&mut self, batch: Vec<BatchOperation>) -> Result<()> {
+        for operation in batch {
+            match operation {
+                BatchOperation::Put(column, key, value) => {
+                    Arc::make_mut(self.columns.entry(column).or_default()).insert(key, value);
+                }
+                BatchOperation::Delete(column, key) => {
+                    Arc::make_mut(self.columns.entry(column).or_default()).remove(&key);
+                }
+            }
+        }
+        *self.latest.lock().unwrap() = self.columns.clone();
+        Ok(())
+    }
+
+    fn replica(&self) -> Box<dyn KvReplica> {
+        Box::new(MemoryReplica {
+            columns: self.columns.clone(),
+            latest: self.latest.clone(),
+        })
+    }
+}
+
+impl KvRead for MemoryReplica {
+    fn get(&self, column: Column, key: &[u8]) -> Result<Option<Value>> {
+        Ok(get_entry(&self.columns, column, key))
+    }
+
+    fn iterate(
+        &self,
+        column: Column,
+        from: Option<&[u8]>,
+    ) -> Box<dyn Iterator<Item = (Key, Value)> + '_> {
+        iterate_entries(&self.columns, column, from)
+    }
+}
+
+impl KvReplica for MemoryReplica {
+    fn refresh(&mut self) {
+        self.columns = self.latest.lock().unwrap().clone();
+    }
+}
+
+/// Backend that keeps the writes in memory until they are flushed to the wrapped backend in a single batch,
+/// e.g. once per block instead of once per transaction. Reads see the pending writes, as if they were applied.
+pub struct BufferedBackend<B> {
+    backend: B,
+    /// Pending writes by column and key, None for the deleted keys.
+    pending: HashMap<Column, BTreeMap<Key, Option<Value>>>,
+}
+
+impl<B: KvBackend> BufferedBackend<B> {
+    pub fn new(backend: B) -> Self {
+        Self {
+            backend,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Return the pending writes as the batch `flush` would apply.
+    pub fn pending_batch(&self) -> Vec<BatchOperation> {
+        self.pending
+            .iter()
+            .flat_map(|(column, entries)| {
+                entries.iter().map(|(key, value)| match value {
+                    Some(value) => BatchOperation::Put(*column, key.clone(), value.clone()),
+                    None => BatchOperation::Delete(*column, key.clone()),
+                })
+            })
+            .collect()
+    }
+
+    /// Apply the pending writes to the wrapped backend atomically. They are kept if the write fails.
+    pub fn flush(&mut self) -> Result<()> {
+        let batch = self.pending_batch();
+        if !batch.is_empty() {
+            self.backend.write(batch)?;
+            self.pending.clear();
+        }
+        Ok(())
+    }
+}
+
+impl<B: KvBackend> KvRead for BufferedBackend<B> {
+    fn get(&self, column: Column, key: &[u8]) -> Result<Option<Value>> {
+        match self
+            .pending
+            .get(&column)
+            .and_then(|entries| entries.get(key))
+        {
+            Some(value) => Ok(value.clone()),
+            None => self.backend.get(column, key),
+        }
+    }
+
+    fn iterate(
+        &self,
+        column: Column,
+        from: Option<&[u8]>,
+    ) -> Box<dyn Iterator<Item = (Key, Value)> + '_> {
+        let pending = match self.pending.get(&column) {
+            Some(pending) if !pending.is_empty() => pending,
+            _ => return self.backend.iterate(column, from),
+        };
+        let from_key = from.map(|key| key.to_vec()).unwrap_or_default();
+        Box::new(
+            self.backend
+                .iterate(column, from)
+                .merge_join_by(pending.range(from_key..), |(key, _), (pending_key, _)| {
+                    key.cmp(*pending_key)
+                })
+                .filter_map(|entry| match entry {
+                    EitherOrBoth::Left(entry) => Some(entry),
+                    // a pending write replaces the stored entry, or removes it if it's a delete
+                    EitherOrBoth::Right((key, value)) | EitherOrBoth::Both(_, (key, value)) => {
+                        value.clone().map(|value| (key.clone(), value))
+                    }
+                }),
+        )
+    }
+}
+
+impl<B: KvBackend> KvBackend for BufferedBackend<B> {
+    fn write(&mut self, batch: Vec<BatchOperation>) -> Result<()> {
+        for operation in batch {
+            let (column, key, value) = match operation {
+                BatchOperation::Put(column, key, value) => (column, key, Some(value)),
+                BatchOperation::Delete(column, key) => (column, key, None),
+            };
+            self.pending.entry(column).or_default().insert(key, value);
+        }
+        Ok(())
+    }
+
+    /// Sync the writes already flushed to the wrapped backend.
+    fn sync(&mut self) -> Result<()> {
+        self.backend.sync()
+    }
+
+    /// Replicas of the wrapped backend, which only see the flushed writes.
+    fn replica(&self) -> Box<dyn KvReplica> {
+        self.backend.replica()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn memory_backend() {
+        check_backend(MemoryBackend::default());
+    }
+
+    #[test]
+    fn rocksdb_backend() {
+        fs::remove_dir_all(".db_test/kv_backend").unwrap_or_default();
+        check_backend(RocksDbBackend::open(".db_test/kv_backend").unwrap());
+    }
+
+    fn check_backend(mut backend: impl KvBackend) {
+        backend
+            .write(vec![
+                BatchOperation::Put(Column::Records, b"b".to_vec(), b"2".to_vec()),
+                BatchOperation::Put(Column::Records, b"a".to_vec(), b"1".to_vec()),
+                BatchOperation::Put(Column::Records, b"c".to_vec(), b"3".to_vec()),
+                BatchOperation::Put(Column::Spent, b"a".to_vec(), b"spent".to_vec()),
+            ])
+            .unwrap();
+
+        // columns don't share keys
+        assert_eq!(
+            Some(b"1".to_vec()),
+            backend.get(Column::Records, b"a").unwrap()
+        );
+        assert_eq!(
+            Some(b"spent".to_vec()),
+            backend.get(Column::Spent, b"a").unwrap()
+        );
+        assert!(!backend.contains(Column::Heights, b"a"));
+
+        // entries are sorted by key, and iteration can start from a given key
+        let keys: Vec<Key> = backend
+            .iterate(Column::Records, None)
+            .map(|(k, _)| k)
+            .collect();
+        assert_eq!(vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()], keys);
+        let keys: Vec<Key> = backend
+            .iterate(Column::Records, Some(b"b"))
+            .map(|(k, _)| k)
+            .collect();
+        assert_eq!(vec![b"b".to_vec(), b"c".to_vec()], keys);
+
+        backend
+            .write(vec![BatchOperation::Delete(Column::Records, b"b".to_vec())])
+            .unwrap();
+        assert!(!backend.contains(Column::Records, b"b"));
+
+        // replicas see the writes once refreshed
+        let mut replica = backend.replica();
+        backend
+            .write(vec![BatchOperation::Put(
+                Column::Records,
+                b"d".to_vec(),
+                b"4".to_vec(),
+            )])
+            .unwrap();
+        replica.refresh();
+        assert_eq!(
+            Some(b"4".to_vec()),
+            replica.get(Column::Records, b"d").unwrap()
+        );
+        assert_eq!(3, replica.iterate(Column::Records, None).count());
+    }
+
+    #[test]
+    fn buffered_backend() {
+        let mut backend = BufferedBackend::new(MemoryBackend::default());
+        let put = |key: &[u8], value: &[u8]| {
+            BatchOperation::Put(Column::Records, key.to_vec(), value.to_vec())
+        };
+        backend
+            .write(vec![put(b"a", b"1"), put(b"c", b"3")])
+            .unwrap();
+        backend.flush().unwrap();
+
+        // pending writes are read back along with the flushed ones, but don't reach the wrapped backend
+        backend
+            .write(vec![
+                put(b"b", b"2"),
+                put(b"c", b"4"),
+                BatchOperation::Delete(Column::Records, b"a".to_vec()),
+            ])
+            .unwrap();
+        let entries: Vec<(Key, Value)> = backend.iterate(Column::Records, None).collect();
+        assert_eq!(
+            vec![
+                (b"b".to_vec(), b"2".to_vec()),
+                (b"c".to_vec(), b"4".to_vec())
+            ],
+            entries
+        );
+        assert!(!backend.contains(Column::Records, b"a"));
+        assert!(backend.backend.contains(Column::Records, b"a"));
+        assert_eq!(
+            Some(b"3".to_vec()),
+            backend.backend.get(Column::Records, b"c").unwrap()
+        );
+
+        backend.flush().unwrap();
+        assert!(backend.pending.is_empty());
+        assert!(!backend.backend.contains(Column::Records, b"a"));
+        assert_eq!(
+            Some(b"4".to_vec()),
+            backend.backend.get(Column::Records, b"c").unwrap()
+        );
+    }
+
+    #[test]
+    fn memory_replica_is_a_snapshot() {
+        let mut backend = MemoryBackend::default();
+        let mut replica = backend.replica();
+        backend
+            .write(vec![BatchOperation::Put(
+                Column::Records,
+                b"a".to_vec(),
+                b"1".to_vec(),
+            )])
+            .unwrap();
+        assert!(!replica.contains(Column::Records, b"a"));
+
+        replica.refresh();
+        backend
+            .write(vec![BatchOperation::Delete(Column::Records, b"a".to_vec())])
+            .unwrap();
+        assert!(replica.contains(Column::Records, b"a"));
+        assert!(!backend.contains(Column::Records, b"a"));
+    }
+}
@@ -2,13 +2,38 @@
 
 use application::SnarkVMApp;
 use clap::Parser;
+use config::{FsyncPolicy, LogConfig, LogFormat, NodeConfig, StorageBackend, VerificationLevel};
+use lib::query::NodeRole;
+use opentelemetry::sdk::{trace, Resource};
+use opentelemetry::KeyValue;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
 use tendermint_abci::ServerBuilder;
-use tracing_subscriber::{filter::LevelFilter, util::SubscriberInitExt};
+use tokio::signal::unix::{signal, SignalKind};
+use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+use watchdog::WatchdogConfig;
 
 mod application;
+mod bloom;
+mod bridge;
+mod config;
+#[cfg(test)]
+mod fuzz;
+mod governance;
+mod health;
+mod kv_backend;
+mod migrations;
+mod pending_relay;
+mod pipeline;
+mod program_peers;
 mod program_store;
 mod record_store;
+#[cfg(any(test, feature = "test-harness"))]
+#[cfg_attr(not(test), allow(dead_code))] // the binary itself never drives the harness
+pub mod test_chain;
 mod validator_set;
+mod watchdog;
 
 #[derive(Debug, Parser)]
 #[clap(author, version, about)]
@@ -30,38 +55,224 @@ struct Cli {
     #[clap(short, long)]
     verbose: bool,
 
-    /// Suppress all output logging (overrides --verbose).
+    /// Suppress all output logging (overrides --verbose and the log filters).
     #[clap(short, long)]
     quiet: bool,
+
+    /// JSON file with the logging settings: the default level, the levels of specific modules and the format, e.g.
+    /// `{"level": "info", "modules": {"aleo_abci::record_store": "debug"}, "format": "json"}`.
+    #[clap(long, env = "ALEO_LOG_CONFIG")]
+    log_config: Option<PathBuf>,
+
+    /// Log filter directives that replace the levels of --log-config and --verbose, e.g. `info,aleo_abci::pipeline=debug`.
+    #[clap(long, env = "ALEO_LOG")]
+    log_filter: Option<String>,
+
+    /// Format of the logs, `compact` lines or one JSON object per event. Overrides the format of --log-config.
+    #[clap(long, value_enum, env = "ALEO_LOG_FORMAT")]
+    log_format: Option<LogFormat>,
+
+    /// Storage engine for the application state.
+    #[clap(long, value_enum, default_value_t = StorageBackend::Rocksdb)]
+    storage: StorageBackend,
+
+    /// When the store writes of the committed blocks are synced to disk: `always`, or every this many blocks.
+    /// Syncing less often cuts the commit latency on slow disks, but a crash of the machine (not just of the node)
+    /// may lose the blocks since the last sync.
+    #[clap(long, default_value = "always")]
+    fsync: FsyncPolicy,
+
+    /// Load the verifying keys of credits.aleo and of this many of the most executed programs at startup, so the
+    /// first blocks after a restart don't wait for them to be deserialized.
+    #[clap(long, default_value = "10")]
+    precompiled_programs: usize,
+
+    /// JSON file with the programs whose executions this node refuses to relay, reloaded on SIGHUP.
+    #[clap(long)]
+    policy_file: Option<PathBuf>,
+
+    /// Maximum amount of transactions with the same known sender (deployer account or validator) kept in the mempool.
+    #[clap(long)]
+    max_mempool_txs_per_sender: Option<usize>,
+
+    /// How thoroughly check_tx verifies transactions before accepting them in the mempool. Relay nodes can lower it
+    /// to gossip more transactions, at the risk of relaying invalid ones; delivered transactions are always verified fully.
+    #[clap(long, value_enum, default_value_t = VerificationLevel::Full)]
+    mempool_verification: VerificationLevel,
+
+    /// Serve a websocket at this address (e.g. 127.0.0.1:26670) announcing the output records of the transactions
+    /// accepted in the mempool, so wallets learn about pending payments. Off by default, since it reveals when each
+    /// transaction reached the node.
+    #[clap(long)]
+    pending_relay_addr: Option<SocketAddr>,
+
+    /// Serve the /healthz and /readyz HTTP probes at this address (e.g. 0.0.0.0:26671), for orchestrators like
+    /// Kubernetes to check whether the node is live and ready.
+    #[clap(long)]
+    health_addr: Option<SocketAddr>,
+
+    /// Seconds since the last committed block after which /readyz reports the node as not ready.
+    #[clap(long, default_value = "60")]
+    health_max_block_age: u64,
+
+    /// Log an error, and post an alert to --stall-webhook if set, when no block is committed for this many seconds.
+    #[clap(long)]
+    stall_timeout: Option<u64>,
+
+    /// Url that gets a POST with a JSON alert when block production stalls, see --stall-timeout.
+    #[clap(long, requires = "stall_timeout")]
+    stall_webhook: Option<String>,
+
+    /// Url of the tendermint RPC of this node, used to report its mempool size in stall alerts.
+    #[clap(long, default_value = "http://127.0.0.1:26657")]
+    tendermint_rpc_url: String,
+
+    /// Which history of the chain the node keeps: `archive` keeps everything, while `pruned:<heights>` keeps the
+    /// block events and rewards of the last heights only and lets tendermint prune older blocks. The records and
+    /// spent serial numbers are always kept, so pruned nodes can still validate transactions.
+    #[clap(long, default_value = "archive")]
+    role: NodeRole,
+
+    /// Tendermint RPC urls of trusted nodes (e.g. http://10.0.0.2:26657) to fetch, at startup, the deployed programs
+    /// missing from the program store, instead of rejecting the executions that need them. The programs are checked
+    /// against a state proof, but their verifying keys are trusted from the peer.
+    #[clap(long, value_delimiter = ',')]
+    program_peers: Vec<String>,
+
+    /// Export the traces of the ABCI hooks and the proof verifications to this OpenTelemetry collector, as OTLP
+    /// over gRPC (e.g. http://localhost:4317). The spans carry the id of the transaction and the block height.
+    #[clap(long, env = "OTLP_ENDPOINT")]
+    otlp_endpoint: Option<String>,
 }
 
 fn main() {
     let cli: Cli = Cli::parse();
-    let log_level = if cli.quiet {
-        LevelFilter::OFF
-    } else if cli.verbose {
-        LevelFilter::DEBUG
+    let log_config = cli
+        .log_config
+        .as_deref()
+        .map(|path| LogConfig::load(path).expect("could not read the log config"))
+        .unwrap_or_default();
+    let directives = if cli.quiet {
+        "off".to_string()
+    } else if let Some(directives) = cli.log_filter {
+        directives
     } else {
-        LevelFilter::INFO
+        log_config.directives(cli.verbose.then_some("debug"))
     };
+    let filter = EnvFilter::try_new(directives).expect("invalid log filter");
 
-    let subscriber = tracing_subscriber::fmt()
-        // Use a more compact, abbreviated log format
-        .compact()
-        .with_max_level(log_level)
-        // Display the thread ID an event was recorded on
-        .with_thread_ids(true)
-        // Don't display the event's target (module path)
-        .with_target(false)
-        // Build the subscriber
-        .finish();
+    // only one of the formats is set, the layers of the other are disabled
+    let (compact, json) = match cli.log_format.unwrap_or(log_config.format) {
+        LogFormat::Compact => (
+            Some(
+                fmt::layer()
+                    // Use a more compact, abbreviated log format
+                    .compact()
+                    // Display the thread ID an event was recorded on
+                    .with_thread_ids(true)
+                    // Don't display the event's target (module path)
+                    .with_target(false),
+            ),
+            None,
+        ),
+        LogFormat::Json => (
+            None,
+            Some(
+                fmt::layer()
+                    .json()
+                    .with_thread_ids(true)
+                    // Include the spans of the event, e.g. the transaction id of check_tx
+                    .with_current_span(true)
+                    .with_span_list(true),
+            ),
+        ),
+    };
 
-    subscriber.init();
+    // the spans are exported in batches from a runtime of their own, since the ABCI server doesn't run on tokio
+    let telemetry_runtime = cli
+        .otlp_endpoint
+        .as_ref()
+        .map(|_| tokio::runtime::Runtime::new().expect("could not start the telemetry runtime"));
+    let otlp_layer = cli
+        .otlp_endpoint
+        .as_ref()
+        .zip(telemetry_runtime.as_ref())
+        .map(|(endpoint, runtime)| {
+            let _guard = runtime.enter();
+            let tracer = opentelemetry_otlp::new_pipeline()
+                .tracing()
+                .with_exporter(
+                    opentelemetry_otlp::new_exporter()
+                        .tonic()
+                        .with_endpoint(endpoint),
+                )
+                .with_trace_config(trace::config().with_resource(Resource::new(vec![
+                    KeyValue::new("service.name", "aleo_abci"),
+                ])))
+                .install_batch(opentelemetry::runtime::Tokio)
+                .expect("could not start the OTLP exporter");
+            tracing_opentelemetry::layer().with_tracer(tracer)
+        });
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(compact)
+        .with(json)
+        .with(otlp_layer)
+        .init();
 
-    let app = SnarkVMApp::new();
+    let config = NodeConfig {
+        storage: cli.storage,
+        fsync: cli.fsync,
+        precompiled_programs: cli.precompiled_programs,
+        policy_file: cli.policy_file,
+        max_mempool_txs_per_sender: cli.max_mempool_txs_per_sender,
+        pending_relay_addr: cli.pending_relay_addr,
+        mempool_verification: cli.mempool_verification,
+        role: cli.role,
+        program_peers: cli.program_peers,
+    };
+    let app = SnarkVMApp::new(&config);
+    if config.policy_file.is_some() {
+        reload_policy_on_sighup(app.clone());
+    }
+    if let Some(addr) = cli.health_addr {
+        health::start(
+            addr,
+            app.clone(),
+            Duration::from_secs(cli.health_max_block_age),
+        )
+        .expect("could not start the health server");
+    }
+    if let Some(timeout) = cli.stall_timeout {
+        watchdog::start(
+            app.clone(),
+            WatchdogConfig {
+                timeout: Duration::from_secs(timeout),
+                webhook: cli.stall_webhook,
+                tendermint_url: cli.tendermint_rpc_url,
+            },
+        );
+    }
     let server = ServerBuilder::new(cli.read_buf_size)
         .bind(format!("{}:{}", cli.host, cli.port), app)
         .unwrap();
 
     server.listen().unwrap();
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+/// Reload the node policy file every time the process gets a SIGHUP, so operators can update it without a restart.
+fn reload_policy_on_sighup(app: SnarkVMApp) {
+    std::thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("could not start the signal handler");
+        runtime.block_on(async move {
+            let mut hangup = signal(SignalKind::hangup()).expect("could not listen for SIGHUP");
+            while hangup.recv().await.is_some() {
+                app.reload_policy();
+            }
+        });
+    });
 }
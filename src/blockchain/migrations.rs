@@ -0,0 +1,306 @@
+use crate::kv_backend::{BatchOperation, Column, KvBackend};
+use crate::program_store::ProgramMetadata;
+use anyhow::{anyhow, Result};
+use lib::vm;
+use log::info;
+use rocksdb::{IteratorMode, Options, DB};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Key of the `Meta` column entry that holds the schema version of a store.
+const SCHEMA_VERSION_KEY: &[u8] = b"schema_version";
+
+/// Schema version of stores created before versioning was introduced.
+const INITIAL_SCHEMA_VERSION: u64 = 1;
+
+/// A change to the on-disk layout of the stores, that upgrades them from the previous schema version to `version`.
+pub struct Migration {
+    pub version: u64,
+    pub description: &'static str,
+    pub run: fn(&mut dyn KvBackend) -> Result<()>,
+}
+
+/// Registry of the schema migrations, sorted by version. Every store is brought to the last version on startup,
+/// so new migrations should be appended here (along with a bump of the application version) whenever the way data
+/// is stored changes. Migrations run on every store, so they should be no-ops for columns the store doesn't use.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 2,
+        description: "add the missing deployment metadata of programs",
+        run: backfill_program_metadata,
+    },
+    Migration {
+        version: 3,
+        description: "add the deprecation height to the program metadata",
+        run: add_deprecation_height,
+    },
+    Migration {
+        version: 4,
+        description: "add the source hash and verification status to the program metadata",
+        run: add_source_verification,
+    },
+    Migration {
+        version: 5,
+        description: "track the record existence filters",
+        run: clear_filters,
+    },
+];
+
+/// Databases kept by the record store before it moved to a single database with a column family per kind of data,
+/// by the suffix of their file name.
+pub const LEGACY_RECORD_DATABASES: &[(&str, Column)] = &[
+    ("records", Column::Records),
+    ("spent", Column::Spent),
+    ("heights", Column::Heights),
+];
+
+/// Databases kept by the program store before it moved to a single database, see `LEGACY_RECORD_DATABASES`.
+pub const LEGACY_PROGRAM_DATABASES: &[(&str, Column)] = &[
+    ("deployed", Column::Programs),
+    ("metadata", Column::ProgramMetadata),
+];
+
+/// Program metadata as stored up to schema version 2.
+#[derive(Default, Serialize, Deserialize)]
+struct ProgramMetadataV2 {
+    height: u64,
+    deployer: Option<vm::Address>,
+}
+
+/// Program metadata as stored up to schema version 3.
+#[derive(Default, Serialize, Deserialize)]
+struct ProgramMetadataV3 {
+    height: u64,
+    deployer: Option<vm::Address>,
+    deprecated_at: Option<u64>,
+}
+
+/// The schema version the stores are expected to have after running the migrations.
+pub fn current_schema_version() -> u64 {
+    MIGRATIONS
+        .last()
+        .map_or(INITIAL_SCHEMA_VERSION, |migration| migration.version)
+}
+
+/// Read the schema version of the store from its backend.
+pub fn schema_version(backend: &impl KvBackend) -> Result<u64> {
+    match backend.get(Column::Meta, SCHEMA_VERSION_KEY)? {
+        Some(bytes) => Ok(u64::from_be_bytes(
+            bytes
+                .try_into()
+                .map_err(|_| anyhow!("invalid schema version"))?,
+        )),
+        None => Ok(INITIAL_SCHEMA_VERSION),
+    }
+}
+
+/// Run, in order, the migrations that haven't been applied to the store yet, recording the new schema version
+/// after each of them so an interrupted upgrade resumes from the last successful step.
+/// Fails if the store was written by a newer version of the application.
+pub fn run(backend: &mut impl KvBackend) -> Result<u64> {
+    let mut version = schema_version(backend)?;
+    if version > current_schema_version() {
+        return Err(anyhow!(
+            "store schema version {version} is newer than the latest supported version {}",
+            current_schema_version()
+        ));
+    }
+
+    for migration in MIGRATIONS.iter().filter(|m| m.version > version) {
+        info!(
+            "migrating store to schema version {}: {}",
+            migration.version, migration.description
+        );
+        (migration.run)(backend)?;
+        backend.write(vec![BatchOperation::Put(
+            Column::Meta,
+            SCHEMA_VERSION_KEY.to_vec(),
+            migration.version.to_be_bytes().to_vec(),
+        )])?;
+        version = migration.version;
+    }
+    Ok(version)
+}
+
+/// Copy the entries of the legacy databases found at `{path}.{suffix}.db` into their column of the store, which holds
+/// them at the initial schema version so the migrations upgrade them afterwards. Each imported database is renamed
+/// with an `.imported` suffix so it's not read again; an interrupted import is resumed on the next start, since
+/// copying the same entries twice is harmless.
+/// Fails if the store was already initialized, as the legacy files would otherwise be silently ignored.
+pub fn import_legacy_databases(
+    path: &str,
+    databases: &[(&str, Column)],
+    backend: &mut impl KvBackend,
+) -> Result<()> {
+    let legacy_paths: Vec<(String, Column)> = databases
+        .iter()
+        .map(|(suffix, column)| (format!("{path}.{suffix}.db"), *column))
+        .filter(|(legacy_path, _)| Path::new(legacy_path).exists())
+        .collect();
+    if legacy_paths.is_empty() {
+        return Ok(());
+    }
+    if backend.contains(Column::Meta, SCHEMA_VERSION_KEY) {
+        return Err(anyhow!(
+            "found legacy databases next to the already initialized store {path}.db, remove them or the store to start"
+        ));
+    }
+
+    for (legacy_path, column) in legacy_paths {
+        info!("importing legacy database {legacy_path}");
+        let db = DB::open_for_read_only(&Options::default(), &legacy_path, false)?;
+        let batch = db
+            .iterator(IteratorMode::Start)
+            .map(|item| {
+                let (key, value) = item?;
+                Ok(BatchOperation::Put(column, key.to_vec(), value.to_vec()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        drop(db);
+
+        backend.write(batch)?;
+        backend.sync()?;
+        std::fs::rename(&legacy_path, format!("{legacy_path}.imported"))?;
+    }
+    Ok(())
+}
+
+/// Programs deployed before deployment metadata was tracked have no entry in the metadata column,
+/// store the default metadata for them so every deployed program can be listed consistently.
+fn backfill_program_metadata(backend: &mut dyn KvBackend) -> Result<()> {
+    let metadata = bincode::serialize(&ProgramMetadataV2::default())?;
+    let batch: Vec<BatchOperation> = backend
+        .iterate(Column::Programs, None)
+        .filter(|(key, _)| !backend.contains(Column::ProgramMetadata, key))
+        .map(|(key, _)| BatchOperation::Put(Column::ProgramMetadata, key, metadata.clone()))
+        .collect();
+    backend.write(batch)
+}
+
+/// Re-encode the metadata of every program with the deprecation height, which is unset for existing programs.
+fn add_deprecation_height(backend: &mut dyn KvBackend) -> Result<()> {
+    let batch = backend
+        .iterate(Column::ProgramMetadata, None)
+        .map(|(key, value)| {
+            let ProgramMetadataV2 { height, deployer } = bincode::deserialize(&value)?;
+            let metadata = ProgramMetadataV3 {
+                height,
+                deployer,
+                deprecated_at: None,
+            };
+            Ok(BatchOperation::Put(
+                Column::ProgramMetadata,
+                key,
+                bincode::serialize(&metadata)?,
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    backend.write(batch)
+}
+
+/// Re-encode the metadata of every program with an unset source hash.
+fn add_source_verification(backend: &mut dyn KvBackend) -> Result<()> {
+    let batch = backend
+        .iterate(Column::ProgramMetadata, None)
+        .map(|(key, value)| {
+            let ProgramMetadataV3 {
+                height,
+                deployer,
+                deprecated_at,
+            } = bincode::deserialize(&value)?;
+            let metadata = ProgramMetadata {
+                height,
+                deployer,
+                deprecated_at,
+                source_hash: None,
+            };
+            Ok(BatchOperation::Put(
+                Column::ProgramMetadata,
+                key,
+                bincode::serialize(&metadata)?,
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    backend.write(batch)
+}
+
+/// The record store keeps bloom filters of the spent serial numbers and the record commitments, which must have every
+/// key of their columns. Nothing to convert, the filters are built on startup when missing; the version bump keeps
+/// older versions, which would write keys without updating the filters, from opening the store again.
+/// Stores of older versions have no filters, clearing them is only a safeguard.
+fn clear_filters(backend: &mut dyn KvBackend) -> Result<()> {
+    let batch = backend
+        .iterate(Column::Filters, None)
+        .map(|(key, _)| BatchOperation::Delete(Column::Filters, key))
+        .collect();
+    backend.write(batch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kv_backend::MemoryBackend;
+
+    #[test]
+    fn migrations_run_once() {
+        let mut backend = MemoryBackend::default();
+        backend
+            .write(vec![BatchOperation::Put(
+                Column::Programs,
+                b"hello.aleo".to_vec(),
+                b"keys".to_vec(),
+            )])
+            .unwrap();
+        assert_eq!(INITIAL_SCHEMA_VERSION, schema_version(&backend).unwrap());
+
+        assert_eq!(current_schema_version(), run(&mut backend).unwrap());
+        assert_eq!(current_schema_version(), schema_version(&backend).unwrap());
+        let metadata: ProgramMetadata = bincode::deserialize(
+            &backend
+                .get(Column::ProgramMetadata, b"hello.aleo")
+                .unwrap()
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(None, metadata.deprecated_at);
+        assert_eq!(None, metadata.source_hash);
+
+        // running again is a no-op
+        assert_eq!(current_schema_version(), run(&mut backend).unwrap());
+
+        // stores from newer versions are rejected
+        backend
+            .write(vec![BatchOperation::Put(
+                Column::Meta,
+                SCHEMA_VERSION_KEY.to_vec(),
+                (current_schema_version() + 1).to_be_bytes().to_vec(),
+            )])
+            .unwrap();
+        assert!(run(&mut backend).is_err());
+    }
+
+    #[test]
+    fn legacy_databases_are_imported() {
+        let path = ".db_test/legacy_import/records";
+        std::fs::remove_dir_all(".db_test/legacy_import").unwrap_or_default();
+        std::fs::create_dir_all(".db_test/legacy_import").unwrap();
+        {
+            let legacy = DB::open_default(format!("{path}.spent.db")).unwrap();
+            legacy.put(b"serial", 3_u64.to_be_bytes()).unwrap();
+        }
+
+        let mut backend = MemoryBackend::default();
+        import_legacy_databases(path, LEGACY_RECORD_DATABASES, &mut backend).unwrap();
+        assert_eq!(
+            Some(3_u64.to_be_bytes().to_vec()),
+            backend.get(Column::Spent, b"serial").unwrap()
+        );
+        assert!(!Path::new(&format!("{path}.spent.db")).exists());
+        assert!(Path::new(&format!("{path}.spent.db.imported")).exists());
+
+        // legacy files next to an initialized store are rejected
+        run(&mut backend).unwrap();
+        DB::open_default(format!("{path}.records.db")).unwrap();
+        assert!(import_legacy_databases(path, LEGACY_RECORD_DATABASES, &mut backend).is_err());
+    }
+}
@@ -0,0 +1,385 @@
+use anyhow::{ensure, Result};
+use futures_util::{SinkExt, StreamExt};
+use lib::pending::{CommittedRecord, PendingRecords, Registration, RelayMessage};
+use lib::vm;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpStream;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+use tracing::{debug, error, info, warn};
+
+/// Announcements kept for subscribers that are behind, older ones are dropped for them.
+const RELAY_BUFFER: usize = 1024;
+
+/// Committed blocks kept for registered subscribers that are behind, e.g. while decrypting a previous one.
+const BLOCK_BUFFER: usize = 64;
+
+/// Maximum amount of view keys a subscriber can register. Every record of every block is checked against each of
+/// them, so this bounds the work a single connection can cause.
+const MAX_REGISTERED_VIEW_KEYS: usize = 10_000;
+
+/// Output records of the transactions of a committed block.
+#[derive(Debug)]
+struct CommittedBlock {
+    height: u64,
+    transactions: Vec<PendingRecords>,
+}
+
+/// Announces the output records of the transactions this node accepts in its mempool to the clients subscribed to a
+/// websocket, so wallets learn about incoming payments before they are committed. Since tendermint gossips the
+/// transactions, every node running the relay announces them as they reach its mempool.
+/// It's opt-in, see `NodeConfig::pending_relay_addr`: anyone watching learns when each transaction reached the node,
+/// which can help link it to its sender.
+/// Subscribers can instead register view keys, see `lib::pending::Registration`, to get the records of those
+/// accounts decrypted as the blocks that create them are committed.
+#[derive(Clone, Debug)]
+pub struct PendingRelay {
+    sender: broadcast::Sender<String>,
+    blocks: broadcast::Sender<Arc<CommittedBlock>>,
+    /// Output records of the transactions delivered in the current block, sent to the subscribers on commit.
+    block: Arc<Mutex<Vec<PendingRecords>>>,
+    addr: SocketAddr,
+}
+
+impl PendingRelay {
+    /// Start serving the relay websocket at the given address, in a thread of its own.
+    pub fn start(addr: SocketAddr) -> Result<Self> {
+        let listener = std::net::TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+        let (sender, _) = broadcast::channel(RELAY_BUFFER);
+        let (blocks, _) = broadcast::channel(BLOCK_BUFFER);
+
+        let subscriptions = sender.clone();
+        let block_subscriptions = blocks.clone();
+        std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("could not start the pending record relay");
+            runtime.block_on(async move {
+                let listener = tokio::net::TcpListener::from_std(listener)
+                    .expect("could not listen for pending record relay subscribers");
+                info!("Relaying pending records at ws://{addr}");
+                loop {
+                    match listener.accept().await {
+                        Ok((stream, peer)) => {
+                            tokio::spawn(serve(
+                                stream,
+                                peer,
+                                subscriptions.subscribe(),
+                                block_subscriptions.clone(),
+                            ));
+                        }
+                        Err(e) => error!("Could not accept a pending record subscriber: {e}"),
+                    }
+                }
+            });
+        });
+        Ok(Self {
+            sender,
+            blocks,
+            block: Default::default(),
+            addr,
+        })
+    }
+
+    /// Address the relay websocket is served at.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Send the output records of a transaction to the current subscribers, if any.
+    pub fn announce(&self, records: &PendingRecords) {
+        if records.records.is_empty() {
+            return;
+        }
+        match serde_json::to_string(records) {
+            // it only fails when there are no subscribers
+            Ok(json) => {
+                let _ = self.sender.send(json);
+            }
+            Err(e) => error!(
+                "Could not serialize the pending records of {}: {e}",
+                records.tx_id
+            ),
+        }
+    }
+
+    /// Keep the output records of a transaction delivered in the current block, to push them to the registered
+    /// subscribers once it's committed.
+    pub fn stage(&self, records: PendingRecords) {
+        if !records.records.is_empty() {
+            self.block.lock().unwrap().push(records);
+        }
+    }
+
+    /// Push the records of the transactions delivered in the committed block to the registered subscribers.
+    pub fn commit(&self, height: u64) {
+        let transactions = std::mem::take(&mut *self.block.lock().unwrap());
+        if !transactions.is_empty() {
+            // it only fails when there are no subscribers
+            let _ = self.blocks.send(Arc::new(CommittedBlock {
+                height,
+                transactions,
+            }));
+        }
+    }
+}
+
+async fn serve(
+    stream: TcpStream,
+    peer: SocketAddr,
+    mut announcements: broadcast::Receiver<String>,
+    blocks: broadcast::Sender<Arc<CommittedBlock>>,
+) {
+    let mut socket = match tokio_tungstenite::accept_async(stream).await {
+        Ok(socket) => socket,
+        Err(e) => {
+            debug!("Pending record subscriber {peer} failed the handshake: {e}");
+            return;
+        }
+    };
+    debug!("Pending record subscriber {peer} connected");
+
+    // once the subscriber registers view keys it gets the committed records of those accounts instead of the
+    // pending announcements
+    let mut registered: Option<(broadcast::Receiver<Arc<CommittedBlock>>, Arc<Vec<Account>>)> =
+        None;
+    loop {
+        let messages = tokio::select! {
+            received = socket.next() => match received {
+                Some(Ok(Message::Text(json))) => match register(&json) {
+                    Ok(accounts) => {
+                        debug!("Subscriber {peer} registered {} view keys", accounts.len());
+                        #[allow(clippy::clone_on_copy)]
+                        let addresses = accounts.iter().map(|(address, _)| address.clone());
+                        let message = RelayMessage::Registered {
+                            addresses: addresses.collect(),
+                        };
+                        let receiver = match registered.take() {
+                            Some((receiver, _)) => receiver,
+                            None => blocks.subscribe(),
+                        };
+                        registered = Some((receiver, Arc::new(accounts)));
+                        to_json(&message).into_iter().collect()
+                    }
+                    Err(e) => {
+                        debug!("Subscriber {peer} sent an invalid registration: {e}");
+                        let reason = format!("invalid registration: {e}");
+                        close(&mut socket, CloseCode::Policy, reason).await;
+                        return;
+                    }
+                },
+                Some(Ok(_)) => continue,
+                Some(Err(_)) | None => {
+                    debug!("Pending record subscriber {peer} disconnected");
+                    return;
+                }
+            },
+            announcement = announcements.recv(), if registered.is_none() => match announcement {
+                Ok(json) => vec![json],
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Pending record subscriber {peer} missed {skipped} announcements");
+                    continue;
+                }
+                Err(RecvError::Closed) => return,
+            },
+            block = async { registered.as_mut().unwrap().0.recv().await },
+                if registered.is_some() => match block {
+                Ok(block) => {
+                    // decrypting takes a while with many view keys, so it's kept off the runtime thread
+                    let accounts = registered.as_ref().unwrap().1.clone();
+                    let decrypt = move || owned_records(&block, &accounts);
+                    match tokio::task::spawn_blocking(decrypt).await {
+                        Ok(messages) => messages,
+                        Err(e) => {
+                            error!("Could not decrypt the records of a block for {peer}: {e}");
+                            return;
+                        }
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    // closing lets the subscriber know it has to scan the blocks it missed
+                    warn!("Subscriber {peer} missed {skipped} committed blocks, disconnecting it");
+                    let reason = format!("missed {skipped} committed blocks");
+                    close(&mut socket, CloseCode::Again, reason).await;
+                    return;
+                }
+                Err(RecvError::Closed) => return,
+            },
+        };
+        for json in messages {
+            if socket.send(Message::Text(json)).await.is_err() {
+                debug!("Pending record subscriber {peer} disconnected");
+                return;
+            }
+        }
+    }
+}
+
+/// An account registered by a subscriber, with the address derived from the view key.
+type Account = (vm::Address, vm::ViewKey);
+
+/// Parse a `Registration`, deriving the address of each view key.
+fn register(json: &str) -> Result<Vec<Account>> {
+    let registration: Registration = serde_json::from_str(json)?;
+    ensure!(
+        !registration.view_keys.is_empty()
+            && registration.view_keys.len() <= MAX_REGISTERED_VIEW_KEYS,
+        "between 1 and {MAX_REGISTERED_VIEW_KEYS} view keys can be registered"
+    );
+    registration
+        .view_keys
+        .into_iter()
+        .map(|view_key| Ok((vm::Address::try_from(&view_key)?, view_key)))
+        .collect()
+}
+
+/// The `RelayMessage::Committed` messages of the records of the block owned by any of the accounts.
+#[allow(clippy::clone_on_copy)]
+fn owned_records(block: &CommittedBlock, accounts: &[Account]) -> Vec<String> {
+    let mut messages = Vec::new();
+    for transaction in &block.transactions {
+        for (commitment, ciphertext) in &transaction.records {
+            let owned = accounts.iter().find_map(|(owner, view_key)| {
+                if !ciphertext.is_owner(owner, view_key) {
+                    return None;
+                }
+                let record = ciphertext.decrypt(view_key).ok()?;
+                Some(CommittedRecord {
+                    height: block.height,
+                    tx_id: transaction.tx_id.clone(),
+                    owner: owner.clone(),
+                    commitment: commitment.clone(),
+                    record,
+                })
+            });
+            if let Some(record) = owned {
+                messages.extend(to_json(&RelayMessage::Committed(record)));
+            }
+        }
+    }
+    messages
+}
+
+fn to_json(message: &RelayMessage) -> Option<String> {
+    serde_json::to_string(message)
+        .map_err(|e| error!("Could not serialize a record relay message: {e}"))
+        .ok()
+}
+
+async fn close(socket: &mut WebSocketStream<TcpStream>, code: CloseCode, reason: String) {
+    let frame = CloseFrame {
+        code,
+        reason: reason.into(),
+    };
+    let _ = socket.close(Some(frame)).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lib::decryptor::ViewKeyDecryptor;
+    use lib::vm;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn subscribers_get_their_pending_records() {
+        let relay = PendingRelay::start("127.0.0.1:0".parse().unwrap()).unwrap();
+        let private_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let view_key = vm::ViewKey::try_from(&private_key).unwrap();
+        let address = vm::Address::try_from(&view_key).unwrap();
+        let other_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let other_address =
+            vm::Address::try_from(&vm::ViewKey::try_from(&other_key).unwrap()).unwrap();
+        let records = PendingRecords {
+            tx_id: "pending".to_string(),
+            records: vec![
+                vm::mint_record("credits.aleo", "credits", &other_address, 5, 1).unwrap(),
+                vm::mint_record("credits.aleo", "credits", &address, 10, 2).unwrap(),
+            ],
+        };
+
+        let url = format!("ws://{}", relay.addr());
+        let watcher = tokio::spawn(async move {
+            let decryptor = ViewKeyDecryptor::new(view_key).unwrap();
+            let mut payments = Vec::new();
+            lib::pending::watch(&url, &decryptor, |payment| {
+                payments.push(payment);
+                false
+            })
+            .await
+            .unwrap();
+            payments
+        });
+
+        // keep announcing until the subscriber is connected and gets it
+        while !watcher.is_finished() {
+            relay.announce(&records);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        let payments = watcher.await.unwrap();
+        assert_eq!(1, payments.len());
+        assert_eq!("pending", payments[0].tx_id);
+        assert_eq!(10, vm::gates(&payments[0].record));
+    }
+
+    #[tokio::test]
+    async fn registered_subscribers_get_their_committed_records() {
+        let relay = PendingRelay::start("127.0.0.1:0".parse().unwrap()).unwrap();
+        let private_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let view_key = vm::ViewKey::try_from(&private_key).unwrap();
+        let address = vm::Address::try_from(&view_key).unwrap();
+        let other_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let other_address =
+            vm::Address::try_from(&vm::ViewKey::try_from(&other_key).unwrap()).unwrap();
+        let records = PendingRecords {
+            tx_id: "committed".to_string(),
+            records: vec![
+                vm::mint_record("credits.aleo", "credits", &other_address, 5, 1).unwrap(),
+                vm::mint_record("credits.aleo", "credits", &address, 10, 2).unwrap(),
+            ],
+        };
+
+        let url = format!("ws://{}", relay.addr());
+        let watcher = tokio::spawn(async move {
+            let mut received = Vec::new();
+            lib::pending::watch_committed(&url, vec![view_key], |record| {
+                received.push(record);
+                false
+            })
+            .await
+            .unwrap();
+            received
+        });
+
+        // keep committing blocks until the subscriber is registered and gets the record
+        let mut height = 0;
+        while !watcher.is_finished() {
+            height += 1;
+            relay.stage(records.clone());
+            relay.commit(height);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        let received = watcher.await.unwrap();
+        assert_eq!(1, received.len());
+        assert_eq!("committed", received[0].tx_id);
+        assert_eq!(address, received[0].owner);
+        assert_eq!(10, vm::gates(&received[0].record));
+    }
+
+    #[tokio::test]
+    async fn invalid_registrations_are_rejected() {
+        let relay = PendingRelay::start("127.0.0.1:0".parse().unwrap()).unwrap();
+        let url = format!("ws://{}", relay.addr());
+        let error = lib::pending::watch_committed(&url, vec![], |_| false)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("invalid registration"));
+    }
+}
@@ -0,0 +1,1716 @@
+//! The validation and application of transactions, apart from the ABCI hooks that drive them. The phases
+//! follow the ones of a block: `check` validates a transaction against the committed state, `begin_block` starts
+//! a block, `apply` validates a transaction and applies it to the stores, and `commit` persists the block.
+//! `SnarkVMApp` wraps the pipeline translating to and from tendermint types, and the pipeline can be used on its
+//! own, e.g. to run transactions against the application state in tests without going through tendermint.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::bridge::Bridge;
+use crate::config::{FsyncPolicy, VerificationLevel};
+use crate::governance::Governance;
+use crate::program_store::{ProgramMetadata, ProgramStore};
+use crate::record_store::RecordStore;
+use crate::validator_set::ValidatorSet;
+use anyhow::{anyhow, bail, ensure, Result};
+use itertools::Itertools;
+use lib::diagnostic::{self, Check, Diagnostic};
+use lib::names;
+use lib::query::{BlockEvent, BlockRewards, FailedDeployment, FeeEstimate};
+use lib::transaction::{
+    Transaction, MAX_BLOCK_GAS, MAX_EXECUTION_DELAY, TRANSACTION_GAS, TRANSITION_GAS,
+};
+use lib::validator::{pub_key_to_address, Address, VotingPower};
+use lib::{merkle, vm};
+
+use tracing::{error, info, warn};
+
+/// Maximum amount of transactions that can be delivered in a single block.
+const MAX_BLOCK_TRANSACTIONS: u64 = 500;
+
+/// Maximum amount of chunked deployments whose staging can be opened in a single block.
+const MAX_BLOCK_STAGED_DEPLOYMENTS: u64 = 10;
+
+/// Maximum amount of chunked deployments staged at the same time, waiting for the rest of their chunks.
+const MAX_STAGED_DEPLOYMENTS: usize = 100;
+
+/// Blocks after which the chunks of a deployment that wasn't completed are discarded, see `expire_stagings`.
+const STAGING_EXPIRY_BLOCKS: u64 = 100;
+
+/// App version from which chains run the credits.aleo with locked credits, see `ProgramStore::upgrade_credits`.
+/// Chains below it keep the version they started with, so nodes that don't know about locked credits agree with
+/// the rest until they halt for the upgrade.
+const LOCKED_CREDITS_VERSION: u64 = 6;
+
+/// Files and directories where the stores opened by `TransactionPipeline::new` keep their state, by store name,
+/// relative to the working directory.
+const STORE_PATHS: [(&str, &str); 6] = [
+    ("records", "records.db"),
+    ("programs", "programs.db"),
+    ("governance", "abci.governance"),
+    ("bridge", "abci.bridge"),
+    ("validators", "abci.validators"),
+    ("height", HeightFile::PATH),
+];
+
+/// The application state and the logic to validate transactions and apply them to it.
+/// Clones share the same state, so the ABCI connections can each hold one.
+#[derive(Debug, Clone)]
+pub struct TransactionPipeline {
+    pub records: RecordStore,
+    pub programs: ProgramStore,
+
+    // NOTE: the validator set synchronizes its own state, so the mempool and query connections can read it
+    // while the consensus connection is updating it, see `ValidatorSet`.
+    pub validators: Arc<ValidatorSet>,
+
+    /// Chain parameters and pending governance proposals.
+    pub governance: Arc<Mutex<Governance>>,
+
+    /// Counterparty chains credits are sent to and received from.
+    pub bridge: Arc<Mutex<Bridge>>,
+
+    /// Last committed block height.
+    pub height: HeightFile,
+
+    /// Id of the chain received at genesis, transactions meant for other chains are rejected.
+    /// Nodes of chains started before it was tracked don't know it and don't check it.
+    pub chain_id: Arc<RwLock<Option<String>>>,
+
+    /// Whether the stores are persisted in the working directory, see `STORE_PATHS`.
+    persistent: bool,
+
+    /// Transactions and gas already included in the block being delivered, to enforce the block limits.
+    block_usage: Arc<Mutex<BlockUsage>>,
+
+    /// Events emitted while processing the block being delivered, persisted when it's committed.
+    block_events: Arc<Mutex<Vec<BlockEvent>>>,
+
+    /// Unix time in seconds of the block being delivered, saved along with its rewards.
+    block_time: Arc<Mutex<Option<u64>>>,
+}
+
+#[derive(Debug, Default)]
+struct BlockUsage {
+    transactions: u64,
+    gas: u64,
+    staged_deployments: u64,
+}
+
+impl TransactionPipeline {
+    /// Load the stores from the working directory, creating them if missing. Their writes are synced to disk
+    /// following the given policy.
+    pub fn new(fsync: FsyncPolicy) -> Self {
+        // we rather crash than start with badly initialized stores
+        let records = RecordStore::new("records", fsync).expect("could not create a record store");
+        let governance = Governance::load(&records, Path::new("abci.governance"))
+            .expect("could not load the governance state");
+        let bridge = Bridge::load(&records, Path::new("abci.bridge"))
+            .expect("could not load the bridge state");
+        let validators = ValidatorSet::load(&records, Path::new("abci.validators"))
+            .expect("could not load the validator set");
+        validators.set_params(governance.params());
+        let chain_id = records.chain_id().expect("could not load the chain id");
+        let programs =
+            ProgramStore::new("programs", fsync).expect("could not create a program store");
+
+        // the height reported to tendermint is the one of the state in the stores, so it replays the blocks they miss
+        let height = HeightFile::load_or_create(Some(Path::new(HeightFile::PATH)));
+        let committed = recover_state_height(&records, &programs)
+            .expect("could not load the height of the stores");
+        height.reconcile(committed);
+
+        Self {
+            programs,
+            records,
+            validators: Arc::new(validators),
+            governance: Arc::new(Mutex::new(governance)),
+            bridge: Arc::new(Mutex::new(bridge)),
+            height,
+            chain_id: Arc::new(RwLock::new(chain_id)),
+            persistent: true,
+            block_usage: Default::default(),
+            block_events: Default::default(),
+            block_time: Default::default(),
+        }
+    }
+
+    /// Create a pipeline that keeps all of its state in memory, starting from an empty chain.
+    pub fn new_in_memory() -> Self {
+        Self {
+            programs: ProgramStore::new_in_memory().expect("could not create a program store"),
+            records: RecordStore::new_in_memory(),
+            validators: Arc::new(ValidatorSet::new_in_memory()),
+            governance: Arc::new(Mutex::new(Governance::new_in_memory())),
+            bridge: Arc::new(Mutex::new(Bridge::new_in_memory())),
+            height: HeightFile::load_or_create(None),
+            chain_id: Default::default(),
+            persistent: false,
+            block_usage: Default::default(),
+            block_events: Default::default(),
+            block_time: Default::default(),
+        }
+    }
+
+    /// Return the bytes taken on disk by each store, by name. Empty if the state is kept in memory.
+    pub fn disk_usage(&self) -> BTreeMap<String, u64> {
+        if !self.persistent {
+            return BTreeMap::new();
+        }
+        STORE_PATHS
+            .iter()
+            .map(|(name, path)| (name.to_string(), disk_usage(Path::new(path))))
+            .collect()
+    }
+
+    /// Validate a transaction against the committed state, without changing it, as done before accepting it in
+    /// the mempool. The deployments are programs that can be executed as if they were already deployed, see
+    /// `validate_with_deployments`, and the level tells which of the expensive checks are done.
+    pub fn check(
+        &self,
+        transaction: &Transaction,
+        deployments: &HashMap<String, vm::VerifyingKeyMap>,
+        level: VerificationLevel,
+    ) -> Result<()> {
+        let transaction = self.open_envelope(transaction, level)?;
+        self.check_fits_in_block(transaction.gas())?;
+        self.check_no_duplicate_records(transaction)?;
+        self.check_inputs_are_unspent(transaction)?;
+        self.check_program_owned_inputs(transaction)?;
+        self.check_no_dust_outputs(transaction)?;
+        self.check_chain_id(transaction)?;
+        self.validate_with_deployments(transaction, deployments, level)
+    }
+
+    /// Start a new block, crediting the rewards of the previous one to its proposer and the voters of the
+    /// previous block, and resetting the block limits and events. The time is the one of the block header, if any.
+    pub fn begin_block(
+        &self,
+        proposer: &Address,
+        votes: HashMap<Address, VotingPower>,
+        height: u64,
+        time: Option<u64>,
+    ) {
+        self.validators.begin_block(proposer, votes, height);
+        *self.block_usage.lock().unwrap() = BlockUsage::default();
+        self.block_events.lock().unwrap().clear();
+        *self.block_time.lock().unwrap() = time;
+
+        // the upgraded credits.aleo is stored in the first block of the new version, so it's committed with it
+        let app_version = self.governance.lock().unwrap().params().app_version;
+        if app_version >= LOCKED_CREDITS_VERSION
+            && self
+                .programs
+                .upgrade_credits()
+                .expect("failed to upgrade the credits program")
+        {
+            info!("Upgraded credits.aleo at height {height} to the version with locked credits");
+        }
+        self.expire_stagings(height);
+    }
+
+    /// Discard the chunks of the deployments whose staging was opened more than `STAGING_EXPIRY_BLOCKS` before the
+    /// given height, so incomplete deployments don't keep their chunks stored forever. The discarded chunks are
+    /// committed along with the block.
+    fn expire_stagings(&self, height: u64) {
+        let stagings = self
+            .programs
+            .stagings()
+            .expect("failed to read the staged deployments");
+        for (deployment_hash, staging) in stagings {
+            if staging.opened_at + STAGING_EXPIRY_BLOCKS < height {
+                self.programs
+                    .discard_chunks(&deployment_hash)
+                    .expect("failed to discard expired deployment chunks");
+                info!("Discarded the chunks of deployment {deployment_hash}, staged at height {} and never completed", staging.opened_at);
+            }
+        }
+    }
+
+    /// Validate the transaction and apply it to the application state, as part of the block being delivered.
+    /// The changes take effect when the block is committed.
+    pub fn apply(&self, transaction: &Transaction) -> Result<()> {
+        // we need to repeat the same validations as check, because the protocol can't guarantee that a byzantine
+        // validator won't propose a block with invalid transactions.
+        // Note that we check for duplicate records within the transaction before attempting to spend them
+        // so we don't end up with a half-applied transaction in the record store.
+        // The block limits are checked first, since a byzantine proposer could ignore them to make
+        // validators spend too much time verifying the block.
+        let sender = transaction.envelope_sender();
+        let transaction = self.open_envelope(transaction, VerificationLevel::Full)?;
+        self.consume_block_capacity(transaction)?;
+        self.check_no_duplicate_records(transaction)?;
+        self.check_inputs_are_unspent(transaction)?;
+        self.check_program_owned_inputs(transaction)?;
+        self.check_no_dust_outputs(transaction)?;
+        self.validate_transaction(transaction)?;
+        // the stake updates were validated along with the transaction, so a failure here doesn't stop it from
+        // being applied, which would leave the fees collected but the records unspent
+        if let Err(e) = self.update_validators(transaction) {
+            error!(
+                "failed to update the validators with transaction {}: {e}",
+                transaction.id()
+            );
+        }
+        self.spend_input_records(transaction)?;
+        self.apply_outputs(transaction)?;
+        self.store_program(transaction, sender)?;
+        self.stage_deployment_chunk(transaction)?;
+        self.deprecate_program(transaction)?;
+        self.register_name(transaction)?;
+        self.rotate_validator_key(transaction)?;
+        self.apply_governance_action(transaction)?;
+        self.apply_bridge(transaction)?;
+        self.records.count_transaction()
+    }
+
+    /// Keep track of the deployments of a transaction that failed to apply in the block being delivered, so their
+    /// senders can tell why with the `GetProgramAvailability` query. Failures of programs that are already deployed
+    /// aren't kept, since those ids can't be deployed again anyway.
+    pub fn record_failed_deployments(&self, transaction: &Transaction, error: &anyhow::Error) {
+        for part in transaction.parts() {
+            if let Transaction::Deployment {
+                program, deployer, ..
+            } = part
+            {
+                if self.programs.exists(program.id()) {
+                    continue;
+                }
+                #[allow(clippy::clone_on_copy)]
+                let failed = FailedDeployment {
+                    transaction_id: transaction.id().to_string(),
+                    deployer: deployer.clone(),
+                    height: self.height.get() as u64 + 1,
+                    error: error.to_string(),
+                };
+                if let Err(e) = self.programs.record_failed_deployment(program.id(), failed) {
+                    error!(
+                        "failed to record the failed deployment of {}: {e}",
+                        program.id()
+                    );
+                }
+            }
+        }
+    }
+
+    /// Add an event of the block being delivered, to be persisted when it's committed.
+    pub fn push_event(&self, event: BlockEvent) {
+        self.block_events.lock().unwrap().push(event);
+    }
+
+    /// Add the output records of the executions scheduled for the given height, which is the one of the
+    /// block being delivered, returning the ones that were applied.
+    pub fn apply_scheduled(&self, height: u64) -> Vec<Transaction> {
+        let scheduled = self.records.take_scheduled(height).unwrap_or_else(|e| {
+            error!("failed to read the executions scheduled for height {height}: {e}");
+            vec![]
+        });
+
+        scheduled
+            .iter()
+            .filter_map(|bytes| {
+                let transaction: Transaction = bincode::deserialize(bytes)
+                    .map_err(|e| error!("failed to decode scheduled execution: {e}"))
+                    .ok()?;
+                if let Err(e) = self
+                    .add_output_records(&transaction)
+                    .and_then(|_| self.record_program_stats(&transaction))
+                {
+                    error!("failed to apply scheduled execution {transaction}: {e}");
+                    return None;
+                }
+
+                info!("Applied scheduled execution {transaction} at height {height}");
+                Some(transaction)
+            })
+            .collect()
+    }
+
+    /// Persist the changes of the block being delivered and increment the height, creating the reward records,
+    /// funding the community pool and resolving the governance proposals that reached their activation height.
+    /// A failure to commit the stores is returned once the rest of the block was committed, so the node
+    /// can report it while keeping up with the chain.
+    pub fn commit(&self) -> Result<()> {
+        let height = self.height.increment();
+
+        // apply pending changes in the stores: mark used records as spent, add inputs as unspent,
+        // add the deployed programs. Each store writes the changes of the block at once, see `FsyncPolicy`.
+        // The validator set, governance and bridge states are saved in the same write, so they can't get out of sync
+        // with the records after a crash.
+        // The block events are saved along with them, so they can be queried after tendermint prunes them,
+        // and so is the distribution of the block rewards, for stakers to follow the yield of their validators.
+        let validators = &self.validators;
+        let events = std::mem::take(&mut *self.block_events.lock().unwrap());
+        let time = *self.block_time.lock().unwrap();
+        let rewards = validators
+            .rewards_summary()
+            .map(|rewards| BlockRewards { time, ..rewards });
+
+        // resolve the governance proposals that reached their activation height before the write, so the state
+        // they leave is saved with the block. Parameter changes take effect from the next block
+        let mut governance = self.governance.lock().unwrap();
+        governance.fund_community_pool(validators.community_pool_share());
+        let spends = governance.activate(height as u64, &validators.validators());
+        validators.set_params(governance.params());
+
+        let result = self
+            .records
+            .stage_validators(validators.encode())
+            .and_then(|_| self.records.stage_events(bincode::serialize(&events)?))
+            .and_then(|_| match &rewards {
+                Some(rewards) => self.records.stage_rewards(serde_json::to_vec(rewards)?),
+                None => Ok(()),
+            })
+            .and_then(|_| self.records.stage_governance(governance.encode()))
+            .and_then(|_| {
+                self.records
+                    .stage_bridge(self.bridge.lock().unwrap().encode())
+            })
+            // the program store commits last, so its changes are saved with the records to redo them if it misses
+            // them, see `recover_state_height`
+            .and_then(|_| self.programs.prepare_commit(height as u64))
+            .and_then(|changes| self.records.stage_program_changes(changes))
+            .and_then(|_| self.records.commit(height as u64))
+            .and_then(|_| self.programs.commit(height as u64));
+
+        for (commitment, record) in validators.block_rewards() {
+            if let Err(err) = self
+                .records
+                .index_program_record("credits.aleo", &commitment)
+                .and_then(|_| self.records.add(commitment, record))
+            {
+                error!("Failed to add reward record to store {}", err);
+            }
+        }
+
+        // the records of the spends are added with the next block, like the reward records
+        for (proposal_id, recipient, gates) in spends {
+            if let Err(err) = self.mint_community_pool_spend(&proposal_id, &recipient, gates) {
+                error!("Failed to add community pool spend {proposal_id} to store: {err}");
+            }
+        }
+
+        result
+    }
+
+    /// Mint the credits record paid by a community pool spend that passed. The nonce is derived from the
+    /// proposal id, so every node mints the same record.
+    fn mint_community_pool_spend(
+        &self,
+        proposal_id: &str,
+        recipient: &vm::Address,
+        gates: u64,
+    ) -> Result<()> {
+        let seed = u64::from_be_bytes(
+            merkle::leaf_hash(b"community_pool", proposal_id.as_bytes())[..8].try_into()?,
+        );
+        let (commitment, record) =
+            vm::mint_record("credits.aleo", "credits", recipient, gates, seed)?;
+        self.records
+            .index_program_record("credits.aleo", &commitment)
+            .and_then(|_| self.records.add(commitment, record))
+    }
+
+    /// Roots of the state components committed in the app hash, in the order they are hashed.
+    pub fn state_components(&self) -> Result<Vec<(&'static str, merkle::Hash)>> {
+        let mut components = vec![
+            (merkle::SPENT_COMPONENT, self.records.spent_root()?),
+            (merkle::RECORDS_COMPONENT, self.records.records_root()?),
+            (merkle::PROGRAMS_COMPONENT, self.programs.root()?),
+            (
+                merkle::PROGRAM_OWNED_COMPONENT,
+                self.records.program_owned_root()?,
+            ),
+            (merkle::NAMES_COMPONENT, self.programs.names_root()?),
+            (merkle::SCHEDULED_COMPONENT, self.records.scheduled_root()?),
+        ];
+        // only chains with bridge counterparties commit to the packets, so the app hash of the rest is unchanged
+        if self.bridge.lock().unwrap().is_enabled() {
+            components.push((merkle::PACKETS_COMPONENT, self.records.packets_root()?));
+        }
+        Ok(components)
+    }
+
+    /// Hash of the committed application state. It's empty before the first block, to match the genesis app hash.
+    pub fn app_hash(&self) -> Vec<u8> {
+        if self.height.get() == 0 {
+            return vec![];
+        }
+        // a node that can't compute its app hash can't participate in consensus
+        let components = self
+            .state_components()
+            .expect("failed to compute the state app hash");
+        merkle::app_hash(&components).to_vec()
+    }
+
+    /// Fail if a transaction with the given gas would never fit in a block.
+    fn check_fits_in_block(&self, gas: u64) -> Result<()> {
+        ensure!(
+            gas <= MAX_BLOCK_GAS,
+            Diagnostic::new(
+                Check::BlockLimits,
+                format!("Transaction gas {gas} exceeds the block gas limit {MAX_BLOCK_GAS}")
+            )
+        );
+        Ok(())
+    }
+
+    /// Fail if including the transaction would exceed the block limits, otherwise account for it in the current block usage.
+    /// Transactions that fail later validations are still accounted for, since they still needed to be verified.
+    fn consume_block_capacity(&self, transaction: &Transaction) -> Result<()> {
+        let mut usage = self.block_usage.lock().unwrap();
+        let gas = transaction.gas();
+        ensure!(
+            usage.transactions < MAX_BLOCK_TRANSACTIONS,
+            Diagnostic::new(
+                Check::BlockLimits,
+                format!("Block exceeded the maximum of {MAX_BLOCK_TRANSACTIONS} transactions")
+            )
+        );
+        ensure!(
+            usage.gas + gas <= MAX_BLOCK_GAS,
+            Diagnostic::new(
+                Check::BlockLimits,
+                format!(
+                    "Transaction gas {gas} exceeds the remaining block gas {}",
+                    MAX_BLOCK_GAS - usage.gas
+                )
+            )
+        );
+        let opens_staging = matches!(transaction, Transaction::DeploymentChunk { index: 0, .. });
+        ensure!(
+            !opens_staging || usage.staged_deployments < MAX_BLOCK_STAGED_DEPLOYMENTS,
+            Diagnostic::new(
+                Check::BlockLimits,
+                format!("Block exceeded the maximum of {MAX_BLOCK_STAGED_DEPLOYMENTS} staged deployments")
+            )
+        );
+
+        usage.transactions += 1;
+        usage.gas += gas;
+        usage.staged_deployments += opens_staging as u64;
+        Ok(())
+    }
+
+    /// Fail if the same record appears more than once as a function input in the transaction.
+    fn check_no_duplicate_records(&self, transaction: &Transaction) -> Result<()> {
+        let serial_numbers = transaction.record_serial_numbers();
+        if let Some(serial_number) = serial_numbers.iter().duplicates().next() {
+            let message = format!(
+                "record with serial number {} in transaction {} is duplicate",
+                serial_number,
+                transaction.id()
+            );
+            bail!(Diagnostic::new(Check::DuplicateInput, message).with_serial_number(serial_number));
+        }
+        Ok(())
+    }
+
+    /// the transaction should be rejected if its input records don't exist
+    /// or they aren't known to be unspent either in the ledger or in an unconfirmed transaction output.
+    /// Locked credits records can't be spent until the chain reaches their unlock height.
+    pub fn check_inputs_are_unspent(&self, transaction: &Transaction) -> Result<()> {
+        // the transaction would be included at best in the block following the last committed one
+        let next_height = self.height.get() as u64 + 1;
+        for unlock_height in transaction.unlock_heights()? {
+            ensure!(
+                next_height >= unlock_height,
+                Diagnostic::new(
+                    Check::LockedInput,
+                    format!("input record is locked until height {unlock_height}")
+                )
+            );
+        }
+
+        let serial_numbers = transaction.record_serial_numbers();
+        let already_spent = serial_numbers
+            .iter()
+            .find(|serial_number| !self.records.is_unspent(serial_number).unwrap_or(true));
+
+        if let Some(serial_number) = already_spent {
+            let message = format!(
+                "input record serial number {} is unknown or already spent",
+                serial_number
+            );
+            bail!(Diagnostic::new(Check::SpentInput, message).with_serial_number(serial_number))
+        }
+        Ok(())
+    }
+
+    /// Fail if the transaction spends a record owned by the address of a program, see `vm::program_account`, as the
+    /// input of a transition of another program. Since the keys of program accounts are public, this is what keeps
+    /// others from spending the records a program holds.
+    fn check_program_owned_inputs(&self, transaction: &Transaction) -> Result<()> {
+        for (program_id, serial_number) in transaction.record_serial_numbers_by_program() {
+            if let Some(owner) = self.records.owner_program(&serial_number)? {
+                let message = format!(
+                    "input record serial number {serial_number} is owned by program {owner} and can only be spent by its transitions"
+                );
+                ensure!(
+                    program_id == owner,
+                    Diagnostic::new(Check::ProgramOwnedInput, message)
+                        .with_serial_number(&serial_number)
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Mark all input records as spent in the record store. This operation could fail if the records are unknown or already spent,
+    /// but it's assumed the that was validated before as to prevent half-applied transactions in the block.
+    fn spend_input_records(&self, transaction: &Transaction) -> Result<()> {
+        transaction
+            .record_serial_numbers()
+            .iter()
+            .map(|serial_number| self.records.spend(serial_number))
+            .find(|result| result.is_err())
+            .unwrap_or(Ok(()))
+    }
+
+    /// Add the tranasction output records as unspent in the record store, indexed by the program that created them.
+    fn add_output_records(&self, transaction: &Transaction) -> Result<()> {
+        #[allow(clippy::clone_on_copy)]
+        transaction
+            .output_records_by_program()
+            .iter()
+            .map(|(program_id, commitment, record)| {
+                self.records
+                    .add(commitment.clone(), record.clone())
+                    .and_then(|_| self.records.index_program_record(program_id, commitment))
+            })
+            .find(|result| result.is_err())
+            .unwrap_or(Ok(()))?;
+
+        for part in transaction.parts() {
+            for (commitment, memo) in part.memos() {
+                self.records.add_memo(commitment, memo)?;
+            }
+        }
+        self.index_program_owned_outputs(transaction)
+    }
+
+    /// Record which output records are owned by the address of a program run by the same part of the transaction,
+    /// so they can only be spent by executions of that program, see `check_program_owned_inputs`. Records sent to a
+    /// program address by executions of other programs aren't recognized, since checking every output against every
+    /// program account would be too expensive.
+    #[allow(clippy::clone_on_copy)]
+    fn index_program_owned_outputs(&self, transaction: &Transaction) -> Result<()> {
+        for part in transaction.parts() {
+            let outputs = part.output_records();
+            for program_id in part.executed_programs() {
+                let account = vm::program_account(&vm::ProgramID::from_str(&program_id)?)?;
+                for (commitment, record) in &outputs {
+                    if record.is_owner(&account.address, &account.view_key) {
+                        let serial_number = vm::compute_serial_number(
+                            account.private_key.clone(),
+                            commitment.clone(),
+                        )?;
+                        self.records
+                            .add_program_owned(&serial_number, &program_id)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that the output records of the transaction aren't below the minimum amount of gates set through
+    /// governance, for the ones whose gates can be read.
+    fn check_no_dust_outputs(&self, transaction: &Transaction) -> Result<()> {
+        let governance = self.governance.lock().unwrap();
+        for (commitment, record) in transaction.output_records() {
+            if let Some(gates) = vm::public_gates(&record) {
+                governance.check_record_gates(&commitment, gates)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply validator set side-effects of the transaction: collecting fees and changing
+    /// the voting power based on staking transactions.
+    fn update_validators(&self, transaction: &Transaction) -> Result<()> {
+        self.validators.collect(transaction.fees() as u64);
+        transaction
+            .stake_updates()?
+            .into_iter()
+            .for_each(|update| self.validators.apply(update));
+
+        Ok(())
+    }
+
+    fn validate_transaction(&self, transaction: &Transaction) -> Result<()> {
+        self.check_chain_id(transaction)?;
+        self.validate_with_deployments(transaction, &HashMap::new(), VerificationLevel::Full)
+    }
+
+    fn check_chain_id(&self, transaction: &Transaction) -> Result<()> {
+        if let Some(chain_id) = self.chain_id.read().unwrap().as_deref() {
+            ensure!(
+                transaction.chain_id() == Some(chain_id),
+                Diagnostic::new(
+                    Check::ChainId,
+                    format!("Transaction is not meant for chain {chain_id}")
+                )
+            );
+        }
+        Ok(())
+    }
+
+    /// Verify the envelope of a signed transaction, returning the transaction it wraps, or the given one if it
+    /// isn't signed. This happens before any other check, so that a forged sender is rejected without verifying
+    /// the proofs of the wrapped transaction. Once the chain has a chain id, the transactions that don't cover it
+    /// with a signature of their own have to come in an envelope, see `Transaction::binds_chain_id`.
+    fn open_envelope<'a>(
+        &self,
+        transaction: &'a Transaction,
+        level: VerificationLevel,
+    ) -> Result<&'a Transaction> {
+        match transaction {
+            Transaction::Signed {
+                transaction: inner, ..
+            } => {
+                transaction
+                    .verify_structure()
+                    .map_err(diagnostic::failed(Check::Structure))?;
+                if level.checks_signatures() {
+                    transaction
+                        .verify_signatures()
+                        .map_err(diagnostic::failed(Check::Signature))?;
+                }
+                self.check_chain_id(transaction)?;
+                self.check_not_expired(transaction)?;
+                Ok(inner)
+            }
+            _ => {
+                if let Some(chain_id) = self.chain_id.read().unwrap().as_deref() {
+                    ensure!(
+                        transaction.binds_chain_id(),
+                        Diagnostic::new(
+                            Check::ChainId,
+                            format!("Transaction has to be wrapped in a signed envelope to be bound to chain {chain_id}")
+                        )
+                    );
+                }
+                Ok(transaction)
+            }
+        }
+    }
+
+    fn check_not_expired(&self, transaction: &Transaction) -> Result<()> {
+        if let Some(expires_at) = transaction.expires_at() {
+            // the transaction would be included at best in the block following the last committed one
+            let next_height = self.height.get() as u64 + 1;
+            ensure!(
+                next_height <= expires_at,
+                Diagnostic::new(
+                    Check::Expired,
+                    format!("Transaction expired at height {expires_at}")
+                )
+            );
+        }
+        Ok(())
+    }
+
+    /// Validate the transaction as if the given programs were deployed. These are the programs deployed by the
+    /// previous transactions of a batch, which can be executed before they reach the program store.
+    /// The level tells which of the expensive checks are done, the ones that depend on the state always are.
+    fn validate_with_deployments(
+        &self,
+        transaction: &Transaction,
+        deployments: &HashMap<String, vm::VerifyingKeyMap>,
+        level: VerificationLevel,
+    ) -> Result<()> {
+        transaction
+            .verify_structure()
+            .map_err(diagnostic::failed(Check::Structure))?;
+        if level.checks_signatures() {
+            transaction
+                .verify_signatures()
+                .map_err(diagnostic::failed(Check::Signature))?;
+        }
+        self.check_not_expired(transaction)?;
+
+        let result = match transaction {
+            Transaction::Deployment {
+                ref program,
+                verifying_keys,
+                fee,
+                ..
+            } => {
+                ensure!(
+                    !self.programs.exists(program.id())
+                        && !deployments.contains_key(&program.id().to_string()),
+                    format!("Program already exists: {}", program.id())
+                );
+
+                self.governance
+                    .lock()
+                    .unwrap()
+                    .check_base_fee(transaction.fees())?;
+
+                if let Some(transition) = fee {
+                    self.verify_transition(transition, deployments, level)?;
+                }
+
+                // verify deployment is correct and keys are valid
+                if level.checks_proofs() {
+                    vm::verify_deployment(program, verifying_keys.clone())
+                } else {
+                    Ok(())
+                }
+            }
+            Transaction::Execution {
+                transitions,
+                execute_at,
+                ..
+            } => {
+                ensure!(
+                    !transitions.is_empty(),
+                    "There are no transitions in the execution"
+                );
+                if let Some(execute_at) = *execute_at {
+                    let next_height = self.height.get() as u64 + 1;
+                    ensure!(
+                        next_height < execute_at,
+                        "Execution scheduled for past height {execute_at}"
+                    );
+                    ensure!(
+                        execute_at - next_height <= MAX_EXECUTION_DELAY,
+                        "Executions can't be scheduled more than {MAX_EXECUTION_DELAY} blocks ahead"
+                    );
+                    // the validator set changes can't be deferred, since the stake is moved with the output records
+                    ensure!(
+                        transaction.stake_updates()?.is_empty(),
+                        "Staking executions can't be scheduled"
+                    );
+                    // the outputs are added without any checks at the target height, so they are reserved now
+                    let commitments: Vec<_> = transaction
+                        .output_records()
+                        .into_iter()
+                        .map(|(commitment, _)| commitment)
+                        .collect();
+                    self.records.check_schedulable(&commitments)?;
+                }
+                self.governance
+                    .lock()
+                    .unwrap()
+                    .check_base_fee(transaction.fees())?;
+
+                for update in transaction.stake_updates()? {
+                    self.validators.validate(&update)?
+                }
+                self.check_pinned_programs(transaction, &HashMap::new())?;
+
+                for (index, transition) in transitions.iter().enumerate() {
+                    self.check_not_deprecated(transition.program_id())?;
+                    self.verify_transition(transition, deployments, level)
+                        .map_err(|e| Diagnostic::of(&e).at_index(index))?;
+                }
+                Ok(())
+            }
+            Transaction::Batch { transactions, .. } => {
+                let mut deployments = deployments.clone();
+                // the programs deployed by the batch aren't stored yet, so their pins are checked here
+                let mut deployed = HashMap::new();
+                for transaction in transactions {
+                    self.validate_with_deployments(transaction, &deployments, level)
+                        .and_then(|_| self.check_pinned_programs(transaction, &deployed))
+                        .map_err(|e| Diagnostic::of(&e).for_transaction(transaction.id()))?;
+                    if let Transaction::Deployment {
+                        program,
+                        verifying_keys,
+                        ..
+                    } = transaction
+                    {
+                        deployments.insert(program.id().to_string(), verifying_keys.clone());
+                        let (program_id, hash) = Transaction::program_pin(program);
+                        deployed.insert(program_id, hash);
+                    }
+                }
+                Ok(())
+            }
+            Transaction::Governance {
+                id,
+                action,
+                validator,
+                nonce,
+                ..
+            } => {
+                let validator = self.validators.get(&pub_key_to_address(validator));
+                let next_height = self.height.get() as u64 + 1;
+                self.governance.lock().unwrap().validate(
+                    id,
+                    action,
+                    *nonce,
+                    validator.as_ref(),
+                    next_height,
+                )
+            }
+            Transaction::Deprecation {
+                program_id,
+                deployer,
+                ..
+            } => {
+                let metadata = self
+                    .programs
+                    .metadata(program_id)?
+                    .ok_or_else(|| anyhow!("Program {program_id} does not exist"))?;
+                ensure!(
+                    metadata.deployer.as_ref() == Some(deployer),
+                    "Program {program_id} can only be deprecated by the account that deployed it"
+                );
+                ensure!(
+                    metadata.deprecated_at.is_none(),
+                    "Program {program_id} is already deprecated"
+                );
+                Ok(())
+            }
+            Transaction::NameRegistration {
+                name,
+                program_id,
+                owner,
+                sequence,
+                ..
+            } => {
+                let (namespace, _) = names::parse(name)?;
+                let expected = self.programs.name_sequence(name)?;
+                ensure!(
+                    *sequence == expected,
+                    "The next registration of {name} must have sequence {expected}, not {sequence}"
+                );
+                if let Some(namespace_owner) = self.programs.namespace_owner(namespace)? {
+                    ensure!(
+                        &namespace_owner == owner,
+                        "Namespace {namespace} is owned by another account"
+                    );
+                }
+                let metadata = self
+                    .programs
+                    .metadata(program_id)?
+                    .ok_or_else(|| anyhow!("Program {program_id} does not exist"))?;
+                ensure!(
+                    metadata.deployer.as_ref() == Some(owner),
+                    "Name {name} can only point to a program deployed by the owner of its namespace"
+                );
+                Ok(())
+            }
+            Transaction::KeyRotation {
+                validator, new_key, ..
+            } => self.validators.validate_rotation(validator, new_key),
+            // the deployment is validated once all its chunks are delivered, see stage_deployment_chunk. Here only
+            // the fee of the first chunk is, along with the room to stage one more deployment
+            Transaction::DeploymentChunk {
+                deployment_hash,
+                fee: Some(fee),
+                ..
+            } => {
+                let stagings = self.programs.stagings()?;
+                ensure!(
+                    !stagings.iter().any(|(hash, _)| hash == deployment_hash),
+                    "Deployment {deployment_hash} is already being staged"
+                );
+                ensure!(
+                    stagings.len() < MAX_STAGED_DEPLOYMENTS,
+                    "There are already {MAX_STAGED_DEPLOYMENTS} deployments being staged"
+                );
+                self.verify_transition(fee, deployments, level)
+            }
+            Transaction::DeploymentChunk { .. } => Ok(()),
+            Transaction::BridgeTransfer {
+                destination_chain,
+                burn,
+                ..
+            } => {
+                self.bridge
+                    .lock()
+                    .unwrap()
+                    .validate_transfer(destination_chain)?;
+                self.verify_transition(burn, deployments, level)
+            }
+            Transaction::BridgeReceive {
+                header,
+                validators,
+                packets,
+                ..
+            } => {
+                let chain_id = self.chain_id.read().unwrap().clone().ok_or_else(|| {
+                    anyhow!("Bridge packets can't be received by chains without a chain id")
+                })?;
+                self.bridge
+                    .lock()
+                    .unwrap()
+                    .validate_receive(&chain_id, header, validators, packets)
+                    .map(|_| ())
+            }
+            // the envelope itself was verified above
+            Transaction::Signed { transaction, .. } => {
+                self.validate_with_deployments(transaction, deployments, level)
+            }
+        };
+
+        match result {
+            Err(ref e) => error!("Transaction {} verification failed: {}", transaction, e),
+            _ => info!("Transaction {} verification successful", transaction),
+        };
+        result
+    }
+
+    /// Check the given execution transition with the verifying keys from the program store,
+    /// or from the given deployments if the program is not stored yet.
+    /// Below the full verification level, only check that the program exists.
+    fn verify_transition(
+        &self,
+        transition: &vm::Transition,
+        deployments: &HashMap<String, vm::VerifyingKeyMap>,
+        level: VerificationLevel,
+    ) -> Result<()> {
+        let proof_failed =
+            |e: anyhow::Error| Diagnostic::new(Check::Proof, e).with_transition(transition);
+        if let Some(keys) = deployments.get(&transition.program_id().to_string()) {
+            return if level.checks_proofs() {
+                vm::verify_execution(transition, keys).map_err(|e| proof_failed(e).into())
+            } else {
+                Ok(())
+            };
+        }
+        let stored_keys = self.programs.get(transition.program_id())?;
+
+        // only verify if we have the program available
+        if let Some((_program, keys)) = stored_keys {
+            if level.checks_proofs() {
+                vm::verify_execution(transition, &keys).map_err(|e| proof_failed(e).into())
+            } else {
+                Ok(())
+            }
+        } else {
+            let message = format!("Program {} does not exist", transition.program_id());
+            bail!(Diagnostic::new(Check::MissingProgram, message).with_transition(transition))
+        }
+    }
+
+    /// Fail if a program pinned by the execution doesn't match the deployed one, either stored or in the given
+    /// hashes of the programs deployed by the previous transactions of a batch. Programs that aren't deployed are
+    /// left to `verify_transition`.
+    fn check_pinned_programs(
+        &self,
+        transaction: &Transaction,
+        deployed: &HashMap<vm::ProgramID, String>,
+    ) -> Result<()> {
+        for (program_id, pinned_hash) in transaction.pinned_programs() {
+            let deployed_hash = match deployed.get(program_id) {
+                Some(hash) => Some(hash.clone()),
+                None => self
+                    .programs
+                    .get(program_id)?
+                    .map(|(program, _)| Transaction::program_pin(&program).1),
+            };
+            if let Some(deployed_hash) = deployed_hash {
+                ensure!(
+                    deployed_hash == *pinned_hash,
+                    Diagnostic::new(
+                        Check::ProgramMismatch,
+                        format!(
+                            "Program {program_id} doesn't match the version the execution was proven against"
+                        )
+                    )
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Fail if the program was deprecated by its deployer and its grace period is over.
+    fn check_not_deprecated(&self, program_id: &vm::ProgramID) -> Result<()> {
+        let next_height = self.height.get() as u64 + 1;
+        if let Some(deprecated_at) = self
+            .programs
+            .metadata(program_id)?
+            .and_then(|metadata| metadata.deprecated_at)
+        {
+            ensure!(
+                next_height < deprecated_at,
+                "Program {program_id} was deprecated at height {deprecated_at}"
+            );
+        }
+        Ok(())
+    }
+
+    /// Compute the minimum fee accepted for an execution of the given function, applying the same
+    /// program checks done when validating the execution transaction.
+    pub fn estimate_fee(
+        &self,
+        program_id: &vm::ProgramID,
+        function: &vm::Identifier,
+    ) -> Result<FeeEstimate> {
+        let (program, _keys) = self
+            .programs
+            .get(program_id)?
+            .ok_or_else(|| anyhow!("Program {program_id} does not exist"))?;
+        ensure!(
+            program.functions().contains_key(function),
+            "Program {program_id} has no function {function}"
+        );
+        self.check_not_deprecated(program_id)?;
+
+        let min_fee = self.governance.lock().unwrap().params().base_fee;
+        // the fee is paid with an additional credits transition
+        let transitions = if min_fee > 0 { 2 } else { 1 };
+        Ok(FeeEstimate {
+            min_fee,
+            gas: TRANSACTION_GAS + transitions * TRANSITION_GAS,
+        })
+    }
+
+    /// Move the voting power of the validator to its new key, which is informed to tendermint at the end of the block.
+    fn rotate_validator_key(&self, transaction: &Transaction) -> Result<()> {
+        if let Transaction::KeyRotation {
+            validator, new_key, ..
+        } = transaction
+        {
+            self.validators.rotate(validator, *new_key);
+        }
+        Ok(())
+    }
+
+    /// Record the proposal or vote of a governance transaction.
+    fn apply_governance_action(&self, transaction: &Transaction) -> Result<()> {
+        if let Transaction::Governance {
+            id,
+            action,
+            validator,
+            ..
+        } = transaction
+        {
+            let validator = self
+                .validators
+                .get(&pub_key_to_address(validator))
+                .ok_or_else(|| anyhow!("unknown validator"))?;
+            self.governance
+                .lock()
+                .unwrap()
+                .apply(id, action, &validator);
+        }
+        Ok(())
+    }
+
+    /// Commit the packet of a bridge transfer, or mint the credits of the packets of a bridge receive.
+    fn apply_bridge(&self, transaction: &Transaction) -> Result<()> {
+        match transaction {
+            Transaction::Batch { transactions, .. } => transactions
+                .iter()
+                .try_for_each(|transaction| self.apply_bridge(transaction)),
+            Transaction::BridgeTransfer {
+                destination_chain,
+                receiver,
+                ..
+            } => {
+                let source_chain = self.chain_id.read().unwrap().clone().unwrap_or_default();
+                #[allow(clippy::clone_on_copy)]
+                let packet = self.bridge.lock().unwrap().send(
+                    &source_chain,
+                    destination_chain,
+                    receiver.clone(),
+                    transaction.bridge_amount()?,
+                )?;
+                self.records.stage_packet(&packet)?;
+                self.block_events.lock().unwrap().push(BlockEvent {
+                    kind: "bridge_packet".to_string(),
+                    attributes: vec![
+                        ("tx_id".to_string(), transaction.id().to_string()),
+                        ("destination_chain".to_string(), packet.destination_chain),
+                        ("sequence".to_string(), packet.sequence.to_string()),
+                    ],
+                });
+                Ok(())
+            }
+            Transaction::BridgeReceive {
+                header,
+                validators,
+                packets,
+                ..
+            } => {
+                let chain_id = self.chain_id.read().unwrap().clone().unwrap_or_default();
+                let mut bridge = self.bridge.lock().unwrap();
+                let signed_header =
+                    bridge.validate_receive(&chain_id, header, validators, packets)?;
+                let packets: Vec<_> = packets.iter().map(|(packet, _)| packet.clone()).collect();
+                for packet in &packets {
+                    // the nonce is derived from the packet, so every node mints the same record
+                    let seed = u64::from_be_bytes(
+                        merkle::leaf_hash(&packet.key(), packet.source_chain.as_bytes())[..8]
+                            .try_into()?,
+                    );
+                    let (commitment, record) = vm::mint_record(
+                        "credits.aleo",
+                        "credits",
+                        &packet.receiver,
+                        packet.amount,
+                        seed,
+                    )?;
+                    self.records
+                        .index_program_record("credits.aleo", &commitment)
+                        .and_then(|_| self.records.add(commitment, record))?;
+                }
+                bridge.receive(&signed_header, &packets)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Add the programs deployed by the transaction to the store. The deployer is only recorded, which lets it
+    /// deprecate the program and point names to it, if it's the given sender of the envelope of the transaction.
+    fn store_program(&self, transaction: &Transaction, sender: Option<&vm::Address>) -> Result<()> {
+        if let Transaction::Batch { transactions, .. } = transaction {
+            return transactions
+                .iter()
+                .try_for_each(|transaction| self.store_program(transaction, sender));
+        }
+        if let Transaction::Deployment {
+            program,
+            verifying_keys,
+            deployer,
+            source_hash,
+            ..
+        } = transaction
+        {
+            // the program is being included in the block following the last committed one
+            #[allow(clippy::clone_on_copy)]
+            let metadata = ProgramMetadata {
+                height: self.height.get() as u64 + 1,
+                deployer: sender.filter(|sender| *sender == deployer).cloned(),
+                deprecated_at: None,
+                source_hash: source_hash.clone(),
+            };
+            self.programs
+                .add(program.id(), program, verifying_keys, metadata)?
+        }
+        Ok(())
+    }
+
+    /// Stage the chunk of a deployment until the rest of its chunks are delivered. The last one puts the deployment
+    /// back together and applies it like deliver_tx would; if it's invalid the chunk fails and nothing is applied.
+    /// The staged chunks are discarded either way, so a broken deployment has to be sent again from the first chunk.
+    fn stage_deployment_chunk(&self, transaction: &Transaction) -> Result<()> {
+        if let Transaction::DeploymentChunk {
+            deployment_hash,
+            index,
+            total,
+            data,
+            ..
+        } = transaction
+        {
+            let height = self.height.get() as u64 + 1;
+            let chunks =
+                match self
+                    .programs
+                    .stage_chunk(deployment_hash, *index, *total, data, height)?
+                {
+                    Some(chunks) => chunks,
+                    None => return Ok(()),
+                };
+            self.programs.discard_chunks(deployment_hash)?;
+
+            let assembled = Transaction::from_chunks(deployment_hash, &chunks)?;
+            let deployment = self.open_envelope(&assembled, VerificationLevel::Full)?;
+            // the chunks only pay for their own gas, the verification of the deployment counts against the block too
+            self.consume_block_capacity(deployment)
+                .and_then(|_| self.check_no_duplicate_records(deployment))
+                .and_then(|_| self.check_inputs_are_unspent(deployment))
+                .and_then(|_| self.check_program_owned_inputs(deployment))
+                .and_then(|_| self.check_no_dust_outputs(deployment))
+                .and_then(|_| self.validate_transaction(deployment))
+                .and_then(|_| self.spend_input_records(deployment))
+                .and_then(|_| self.apply_outputs(deployment))
+                .and_then(|_| self.store_program(deployment, assembled.envelope_sender()))
+                .map_err(|e| {
+                    self.record_failed_deployments(deployment, &e);
+                    e
+                })?;
+            info!("Deployment {} applied from {total} chunks", deployment.id());
+        }
+        Ok(())
+    }
+
+    /// Schedule the rejection of the executions of a program once the grace period of its deprecation is over.
+    fn deprecate_program(&self, transaction: &Transaction) -> Result<()> {
+        if let Transaction::Deprecation {
+            program_id,
+            grace_period,
+            ..
+        } = transaction
+        {
+            // the deprecation is being included in the block following the last committed one
+            let height = self.height.get() as u64 + 1 + grace_period;
+            self.programs.deprecate(program_id, height)?;
+        }
+        Ok(())
+    }
+
+    /// Point the registered name to its program, claiming its namespace for the owner if it had none.
+    fn register_name(&self, transaction: &Transaction) -> Result<()> {
+        if let Transaction::NameRegistration {
+            name,
+            program_id,
+            owner,
+            sequence,
+            ..
+        } = transaction
+        {
+            self.programs
+                .register_name(name, program_id, owner, *sequence)?;
+        }
+        Ok(())
+    }
+
+    /// Add the output records of the transaction and count its program executions. For scheduled executions
+    /// this is deferred: the transaction is stored to be applied at its target height, see `apply_scheduled`.
+    fn apply_outputs(&self, transaction: &Transaction) -> Result<()> {
+        match transaction.execute_at() {
+            Some(height) => {
+                let commitments: Vec<_> = transaction
+                    .output_records()
+                    .into_iter()
+                    .map(|(commitment, _)| commitment)
+                    .collect();
+                self.records.schedule(
+                    height,
+                    transaction.id(),
+                    bincode::serialize(transaction)?,
+                    &commitments,
+                )
+            }
+            None => self
+                .add_output_records(transaction)
+                .and_then(|_| self.record_program_stats(transaction)),
+        }
+    }
+
+    /// Count the execution in the statistics of each program with a transition in it, along with the
+    /// fees paid by the transaction. The credits fee transition doesn't count as an execution of credits.aleo.
+    fn record_program_stats(&self, transaction: &Transaction) -> Result<()> {
+        if let Transaction::Batch { transactions, .. } = transaction {
+            return transactions
+                .iter()
+                .try_for_each(|transaction| self.record_program_stats(transaction));
+        }
+        if let Transaction::Execution { transitions, .. } = transaction {
+            // the execution is being included in the block following the last committed one
+            let height = self.height.get() as u64 + 1;
+            let fees = transaction.fees() as u64;
+            let program_ids = transitions
+                .iter()
+                .filter(|transition| {
+                    transition.program_id().to_string() != "credits.aleo"
+                        || transition.function_name().to_string() != "fee"
+                })
+                .map(|transition| transition.program_id())
+                .unique();
+            for program_id in program_ids {
+                self.programs.record_execution(program_id, fees, height)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Bytes taken by the file at the given path, or by the files in the directory and its subdirectories.
+/// Missing or unreadable files count as empty.
+fn disk_usage(path: &Path) -> u64 {
+    let metadata = match std::fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(_) => return 0,
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    std::fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| disk_usage(&entry.path()))
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+/// Height of the last block committed to the stores. The record store is committed before the program store, so if
+/// the node stopped in between the program store misses the changes of the last block, which the record store saved
+/// along with its own; they are written again so both stores have the block, see `TransactionPipeline::commit`.
+fn recover_state_height(records: &RecordStore, programs: &ProgramStore) -> Result<Option<u64>> {
+    let records_height = records.committed_height()?;
+    let programs_height = programs.committed_height()?;
+    if let (Some(records_height), Some(programs_height)) = (records_height, programs_height) {
+        if records_height == programs_height + 1 {
+            if let Some(changes) = records.program_changes()? {
+                warn!("The program store misses the block at height {records_height}, writing its changes again");
+                programs.redo_commit(changes)?;
+                return state_height(Some(records_height), programs.committed_height()?);
+            }
+        }
+    }
+    state_height(records_height, programs_height)
+}
+
+/// Height of the last block committed to the stores, given the heights each of them saved along with the block.
+/// If they disagree the state can't be recovered, since replaying the block would apply part of it twice.
+fn state_height(records: Option<u64>, programs: Option<u64>) -> Result<Option<u64>> {
+    match (records, programs) {
+        (Some(records), Some(programs)) if records != programs => bail!(
+            "the record store has the blocks up to height {records} and the program store up to {programs}, so the \
+            state can't be recovered by replaying blocks. Restore it from a backup or sync the node from genesis"
+        ),
+        _ => Ok(records.or(programs)),
+    }
+}
+
+/// The last committed height, saved to a file so it survives restarts.
+#[derive(Debug, Clone)]
+pub struct HeightFile {
+    path: Option<PathBuf>,
+    height: Arc<AtomicI64>,
+}
+
+impl HeightFile {
+    const PATH: &str = "abci.height";
+
+    fn load_or_create(path: Option<&Path>) -> Self {
+        let height = match path {
+            // if height file is missing or unreadable, create a new one from zero height
+            Some(path) => {
+                if let Ok(bytes) = std::fs::read(path) {
+                    // if contents are not readable, crash intentionally
+                    bincode::deserialize(&bytes).expect("Contents of height file are not readable")
+                } else {
+                    std::fs::write(path, bincode::serialize(&0i64).unwrap()).unwrap();
+                    0i64
+                }
+            }
+            None => 0i64,
+        };
+
+        Self {
+            path: path.map(Path::to_path_buf),
+            height: Arc::new(AtomicI64::new(height)),
+        }
+    }
+
+    pub fn get(&self) -> i64 {
+        self.height.load(Ordering::SeqCst)
+    }
+
+    /// Make the height the one of the state committed to the stores, if they have it and the file disagrees.
+    /// The file is written before the stores commit a block, so it's ahead of them if the node crashed in between,
+    /// or if the machine crashed before the stores synced the last blocks to disk; it's behind them if it was lost
+    /// or restored from an older backup. Tendermint replays the blocks after the height reported in `info`, and
+    /// refuses to start if it's above the blocks it has, so the state must not be reported as further or behind.
+    fn reconcile(&self, committed: Option<u64>) {
+        let height = self.get();
+        match committed {
+            Some(committed) if committed as i64 != height => {
+                warn!(
+                    "The height file is at {height} but the stores have the blocks up to {committed}, \
+                    reporting {committed} to tendermint so it replays the blocks after it"
+                );
+                self.set(committed as i64);
+            }
+            _ => {}
+        }
+    }
+
+    fn set(&self, height: i64) {
+        self.height.store(height, Ordering::SeqCst);
+        if let Some(path) = &self.path {
+            // if the file can't be written, we crash intentionally;
+            std::fs::write(path, bincode::serialize(&height).unwrap()).unwrap();
+        }
+    }
+
+    fn increment(&self) -> i64 {
+        let height = self.height.fetch_add(1, Ordering::SeqCst) + 1;
+        if let Some(path) = &self.path {
+            // if the file can't be written, we crash intentionally;
+            std::fs::write(path, bincode::serialize(&height).unwrap()).unwrap();
+        }
+        height
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        disk_usage, recover_state_height, state_height, HeightFile, TransactionPipeline,
+        MAX_BLOCK_GAS,
+    };
+    use crate::program_store::ProgramStore;
+    use lib::diagnostic::{Check, Diagnostic};
+    use lib::transaction::Transaction;
+    use lib::vm;
+    use std::collections::HashMap;
+    use std::path::Path;
+    use std::str::FromStr;
+
+    #[test]
+    fn block_limits() {
+        let pipeline = TransactionPipeline::new_in_memory();
+        let private_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let deployment =
+            Transaction::deployment(Path::new("aleo/hello.aleo"), &private_key, None).unwrap();
+
+        // leave less gas in the block than what the deployment needs
+        pipeline.block_usage.lock().unwrap().gas = MAX_BLOCK_GAS - deployment.gas() + 1;
+        let error = pipeline.apply(&deployment).unwrap_err();
+        assert_eq!(Check::BlockLimits, Diagnostic::of(&error).check);
+        assert!(error
+            .to_string()
+            .contains("exceeds the remaining block gas"));
+
+        // a new block starts with no gas used
+        let proposer = [0; 20].to_vec();
+        pipeline.begin_block(&proposer, HashMap::new(), 1, None);
+        pipeline.apply(&deployment).unwrap();
+    }
+
+    /// Split the deployment in chunks, paying their staging with a new record of the given account.
+    fn deployment_chunks(
+        pipeline: &TransactionPipeline,
+        private_key: &vm::PrivateKey,
+        deployment: &Transaction,
+    ) -> Vec<Transaction> {
+        let view_key = vm::ViewKey::try_from(private_key).unwrap();
+        let address = vm::Address::try_from(&view_key).unwrap();
+        let (commitment, record) =
+            vm::mint_record("credits.aleo", "credits", &address, 100_000, 0).unwrap();
+        pipeline.records.add(commitment, record.clone()).unwrap();
+        let record = record.decrypt(&view_key).unwrap();
+        Transaction::deployment_chunks(deployment, private_key, record).unwrap()
+    }
+
+    #[test]
+    fn assembled_deployments_count_against_the_block_limits() {
+        let pipeline = TransactionPipeline::new_in_memory();
+        let private_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let deployment =
+            Transaction::deployment(Path::new("aleo/hello.aleo"), &private_key, None).unwrap();
+        let chunks = deployment_chunks(&pipeline, &private_key, &deployment);
+
+        // leave gas for the chunks but not for verifying the deployment they put together
+        pipeline.block_usage.lock().unwrap().gas =
+            MAX_BLOCK_GAS - chunks.iter().map(Transaction::gas).sum::<u64>();
+        let (last, staged) = chunks.split_last().unwrap();
+        for chunk in staged {
+            pipeline.apply(chunk).unwrap();
+        }
+        let error = pipeline.apply(last).unwrap_err();
+        assert_eq!(Check::BlockLimits, Diagnostic::of(&error).check);
+        assert!(!pipeline
+            .programs
+            .exists(&vm::ProgramID::from_str("hello.aleo").unwrap()));
+    }
+
+    #[test]
+    fn assembled_deployments_cant_spend_program_owned_records() {
+        let pipeline = TransactionPipeline::new_in_memory();
+        let private_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let view_key = vm::ViewKey::try_from(&private_key).unwrap();
+        let address = vm::Address::try_from(&view_key).unwrap();
+
+        // the deployment fee is paid with a record that belongs to a program
+        let (commitment, record) =
+            vm::mint_record("credits.aleo", "credits", &address, 100, 1).unwrap();
+        pipeline
+            .records
+            .add(commitment.clone(), record.clone())
+            .unwrap();
+        let serial_number = vm::compute_serial_number(private_key.clone(), commitment).unwrap();
+        pipeline
+            .records
+            .add_program_owned(&serial_number, "records.aleo")
+            .unwrap();
+        let fee = Some((1, record.decrypt(&view_key).unwrap()));
+        let deployment =
+            Transaction::deployment(Path::new("aleo/hello.aleo"), &private_key, fee).unwrap();
+        let chunks = deployment_chunks(&pipeline, &private_key, &deployment);
+
+        let (last, staged) = chunks.split_last().unwrap();
+        for chunk in staged {
+            pipeline.apply(chunk).unwrap();
+        }
+        let error = pipeline.apply(last).unwrap_err();
+        let diagnostic = Diagnostic::of(&error);
+        assert_eq!(Check::ProgramOwnedInput, diagnostic.check);
+        assert_eq!(Some(serial_number.to_string()), diagnostic.serial_number);
+        assert!(!pipeline
+            .programs
+            .exists(&vm::ProgramID::from_str("hello.aleo").unwrap()));
+    }
+
+    #[test]
+    fn transactions_for_other_chains_are_rejected() {
+        let pipeline = TransactionPipeline::new_in_memory();
+        *pipeline.chain_id.write().unwrap() = Some("testnet".to_string());
+        let private_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let deployment =
+            Transaction::deployment(Path::new("aleo/hello.aleo"), &private_key, None).unwrap();
+
+        assert!(pipeline.validate_transaction(&deployment).is_err());
+        let other_chain = deployment.clone().with_chain_id("mainnet").unwrap();
+        assert!(pipeline.validate_transaction(&other_chain).is_err());
+        let same_chain = deployment.with_chain_id("testnet").unwrap();
+        assert!(pipeline.validate_transaction(&same_chain).is_ok());
+    }
+
+    #[test]
+    fn chain_ids_are_bound_by_envelopes() {
+        let pipeline = TransactionPipeline::new_in_memory();
+        *pipeline.chain_id.write().unwrap() = Some("mainnet".to_string());
+        let private_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let deployment =
+            Transaction::deployment(Path::new("aleo/hello.aleo"), &private_key, None).unwrap();
+        let testnet = deployment
+            .clone()
+            .for_chain("testnet", &private_key)
+            .unwrap();
+
+        // anyone can rewrite the chain id of the wrapped deployment, but it's rejected without the envelope
+        let rewritten = testnet.parts()[0].clone().with_chain_id("mainnet").unwrap();
+        let error = pipeline.apply(&rewritten).unwrap_err();
+        assert_eq!(Check::ChainId, Diagnostic::of(&error).check);
+
+        // and the envelope signature doesn't cover the rewritten one
+        let mut forged = testnet;
+        if let Transaction::Signed {
+            transaction,
+            chain_id,
+            ..
+        } = &mut forged
+        {
+            *transaction = Box::new(rewritten);
+            *chain_id = Some("mainnet".to_string());
+        }
+        assert!(pipeline.apply(&forged).is_err());
+        assert!(!pipeline
+            .programs
+            .exists(&vm::ProgramID::from_str("hello.aleo").unwrap()));
+
+        let mainnet = deployment.for_chain("mainnet", &private_key).unwrap();
+        pipeline.apply(&mainnet).unwrap();
+    }
+
+    #[test]
+    #[allow(clippy::clone_on_copy)]
+    fn program_owned_records() {
+        let pipeline = TransactionPipeline::new_in_memory();
+        let private_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let program = vm::generate_program(include_str!("../../aleo/records.aleo")).unwrap();
+        let deployment =
+            Transaction::deployment(Path::new("aleo/records.aleo"), &private_key, None).unwrap();
+        pipeline.apply(&deployment).unwrap();
+
+        // an execution of the program mints a record to the program address
+        let account = vm::program_account(program.id()).unwrap();
+        let mint = Transaction::execution(
+            program.clone(),
+            vm::Identifier::from_str("mint").unwrap(),
+            &[
+                vm::u64_to_value(10),
+                vm::UserInputValueType::from_str(&account.address.to_string()).unwrap(),
+            ],
+            &private_key,
+            None,
+        )
+        .unwrap();
+        pipeline.apply(&mint).unwrap();
+        let (commitment, ciphertext) = mint.output_records().pop().unwrap();
+        let serial_number =
+            vm::compute_serial_number(account.private_key.clone(), commitment.clone()).unwrap();
+        assert_eq!(
+            Some("records.aleo".to_string()),
+            pipeline.records.owner_program(&serial_number).unwrap()
+        );
+
+        // anyone can sign for the program, but only in executions of its functions
+        let record = ciphertext.decrypt(&account.view_key).unwrap();
+        let consume = Transaction::execution(
+            program,
+            vm::Identifier::from_str("consume").unwrap(),
+            &[vm::UserInputValueType::Record(record)],
+            &account.private_key,
+            None,
+        )
+        .unwrap();
+        pipeline.check_program_owned_inputs(&consume).unwrap();
+
+        pipeline
+            .records
+            .add_program_owned(&serial_number, "hello.aleo")
+            .unwrap();
+        let error = pipeline.check_program_owned_inputs(&consume).unwrap_err();
+        let diagnostic = Diagnostic::of(&error);
+        assert_eq!(Check::ProgramOwnedInput, diagnostic.check);
+        assert_eq!(Some(serial_number.to_string()), diagnostic.serial_number);
+    }
+
+    #[test]
+    fn disk_usage_adds_up_the_files() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join("records.db/archive")).unwrap();
+        std::fs::write(dir.path().join("records.db/000001.sst"), [0; 100]).unwrap();
+        std::fs::write(dir.path().join("records.db/archive/000002.sst"), [0; 20]).unwrap();
+        std::fs::write(dir.path().join("abci.height"), [0; 8]).unwrap();
+
+        assert_eq!(120, disk_usage(&dir.path().join("records.db")));
+        assert_eq!(8, disk_usage(&dir.path().join("abci.height")));
+        assert_eq!(0, disk_usage(&dir.path().join("missing")));
+        assert!(TransactionPipeline::new_in_memory().disk_usage().is_empty());
+    }
+
+    #[test]
+    fn heights_follow_the_committed_state() {
+        let pipeline = TransactionPipeline::new_in_memory();
+        assert_eq!(None, pipeline.records.committed_height().unwrap());
+        pipeline.commit().unwrap();
+        pipeline.commit().unwrap();
+        assert_eq!(Some(2), pipeline.records.committed_height().unwrap());
+        assert_eq!(Some(2), pipeline.programs.committed_height().unwrap());
+
+        // a file ahead of the stores, e.g. after a crash mid commit, goes back so the block is replayed
+        let height = HeightFile::load_or_create(None);
+        height.set(3);
+        height.reconcile(Some(2));
+        assert_eq!(2, height.get());
+        // stores from before the height was kept don't change it
+        height.reconcile(None);
+        assert_eq!(2, height.get());
+
+        assert_eq!(Some(2), state_height(Some(2), Some(2)).unwrap());
+        assert_eq!(Some(2), state_height(Some(2), None).unwrap());
+        assert!(state_height(Some(3), Some(2)).is_err());
+    }
+
+    #[test]
+    fn program_store_changes_are_redone_after_a_crash() {
+        let pipeline = TransactionPipeline::new_in_memory();
+        let private_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let deployment =
+            Transaction::deployment(Path::new("aleo/hello.aleo"), &private_key, None).unwrap();
+        pipeline.commit().unwrap();
+        pipeline.apply(&deployment).unwrap();
+
+        // the node stops after the record store commits the block, before the program store does
+        let changes = pipeline.programs.prepare_commit(2).unwrap();
+        pipeline.records.stage_program_changes(changes).unwrap();
+        pipeline.records.commit(2).unwrap();
+        let programs = ProgramStore::new_in_memory().unwrap();
+        programs.commit(1).unwrap();
+
+        assert_eq!(
+            Some(2),
+            recover_state_height(&pipeline.records, &programs).unwrap()
+        );
+        assert!(programs.exists(&vm::ProgramID::from_str("hello.aleo").unwrap()));
+    }
+}
@@ -0,0 +1,196 @@
+//! Fetching of deployed programs that are missing from the program store, e.g. after restoring the stores of a
+//! node from an old backup, from trusted peers instead of rejecting the executions that need them.
+//!
+//! The programs are fetched once at startup, before the node processes any block: validating a transaction must not
+//! wait on the network, and adding programs in the middle of a block would change the programs root the node
+//! commits in its app hash.
+//! The program is checked against the state proof returned by the peer, which must verify against a block header
+//! signed by the validators (see `lib::light`), so a peer can't make up a program or change its instructions.
+//! The verifying keys and the deployment height aren't committed in the state, so they are trusted from the peer:
+//! only nodes operated by the same party, or by someone it trusts, should be configured.
+
+use crate::program_store::{ProgramMetadata, ProgramStore, StoredProgram};
+use anyhow::{anyhow, ensure, Result};
+use itertools::Itertools;
+use lib::client::Client;
+use lib::query::ProgramResponse;
+use lib::vm;
+use std::thread;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Time given to each peer to return a program, including the retries.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Times the program is requested from a peer before trying the next one. The state proof of a peer that just
+/// committed a block can only be checked once the next block is produced, since its header has the app hash.
+const FETCH_ATTEMPTS: usize = 3;
+
+/// Size of the pages of the program list requested from the peers.
+const LIST_PAGE_SIZE: usize = 100;
+
+/// Tendermint RPC urls of the trusted peers programs are fetched from, in order. Without peers nothing is fetched.
+#[derive(Clone, Debug, Default)]
+pub struct ProgramPeers {
+    urls: Vec<String>,
+}
+
+impl ProgramPeers {
+    pub fn new(urls: Vec<String>) -> Self {
+        Self { urls }
+    }
+
+    /// Add to the program store the programs the peers list that are missing from it, along with their verifying
+    /// keys and deployment information, and commit them at the given height, the last one committed by the store.
+    /// Programs deployed after that height are skipped: a peer ahead of this node would otherwise make it accept
+    /// executions the rest of the validators reject. Each program is taken from the first peer that returns a valid
+    /// one. Blocks the calling thread until every peer answered or failed, so it must run before the node starts
+    /// processing blocks. Returns the ids of the programs that were added.
+    pub fn catch_up(&self, programs: &ProgramStore, height: u64) -> Result<Vec<vm::ProgramID>> {
+        if self.urls.is_empty() {
+            return Ok(vec![]);
+        }
+        let urls = self.urls.clone();
+        let store = programs.clone();
+
+        // the ABCI server doesn't run on tokio, and the application may be created from tests that do, so the
+        // requests are made from a runtime on a thread of its own
+        let added = thread::spawn(move || -> Result<Vec<vm::ProgramID>> {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()?;
+            runtime.block_on(async move {
+                let mut missing = vec![];
+                for url in &urls {
+                    match list_missing(url, &store, height).await {
+                        Ok(programs) => missing.extend(programs),
+                        Err(e) => warn!("Could not list the programs of {url}: {e}"),
+                    }
+                }
+                let mut added = vec![];
+                for program_id in missing.into_iter().unique() {
+                    for url in &urls {
+                        let result = tokio::time::timeout(
+                            FETCH_TIMEOUT,
+                            fetch_from(url, &program_id, height),
+                        )
+                        .await
+                        .unwrap_or_else(|_| Err(anyhow!("timed out")));
+                        match result {
+                            Ok(((program, keys), metadata)) => {
+                                store.add(&program_id, &program, &keys, metadata)?;
+                                info!("Fetched program {program_id} from {url}");
+                                added.push(program_id);
+                                break;
+                            }
+                            Err(e) => {
+                                warn!("Could not fetch program {program_id} from {url}: {e}")
+                            }
+                        }
+                    }
+                }
+                Ok(added)
+            })
+        })
+        .join()
+        .map_err(|_| anyhow!("the thread fetching the programs panicked"))??;
+
+        if !added.is_empty() {
+            programs.commit(height)?;
+        }
+        Ok(added)
+    }
+}
+
+/// List the ids of the programs the peer deployed at or before the given height that the store doesn't have.
+async fn list_missing(
+    url: &str,
+    programs: &ProgramStore,
+    height: u64,
+) -> Result<Vec<vm::ProgramID>> {
+    let client = Client::new(url)?;
+    let mut missing = vec![];
+    let mut from = None;
+    loop {
+        let (page, cursor) = client
+            .list_programs(None, from, Some(LIST_PAGE_SIZE))
+            .await?;
+        missing.extend(
+            page.into_iter()
+                .filter(|summary| summary.height <= height)
+                .map(|summary| summary.program_id)
+                .filter(|program_id| !programs.exists(program_id)),
+        );
+        match cursor {
+            Some(cursor) => from = Some(cursor),
+            None => return Ok(missing),
+        }
+    }
+}
+
+async fn fetch_from(
+    url: &str,
+    program_id: &vm::ProgramID,
+    height: u64,
+) -> Result<(StoredProgram, ProgramMetadata)> {
+    let client = Client::new(url)?;
+    let mut attempt = 1;
+    let response = loop {
+        match client.get_verified_program(program_id).await {
+            Ok(response) => break response,
+            Err(e) if attempt < FETCH_ATTEMPTS => {
+                warn!("Could not verify program {program_id} from {url}, retrying: {e}");
+                attempt += 1;
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            Err(e) => return Err(e),
+        }
+    };
+    let (program, summary) = match response {
+        ProgramResponse {
+            program: Some(program),
+            metadata: Some(summary),
+        } => (program, summary),
+        _ => return Err(anyhow!("the peer doesn't know the program")),
+    };
+    ensure!(
+        summary.height <= height,
+        "the program was deployed at height {}, after the last block committed by this node ({height})",
+        summary.height
+    );
+
+    let verifying_keys = client
+        .get_verifying_keys(program_id)
+        .await?
+        .ok_or_else(|| anyhow!("the peer doesn't have the verifying keys of the program"))?;
+    vm::verify_deployment(&program, verifying_keys.clone())?;
+
+    let metadata = ProgramMetadata {
+        height: summary.height,
+        deployer: summary.deployer,
+        deprecated_at: summary.deprecated_at,
+        source_hash: summary.source_hash,
+    };
+    Ok(((program, verifying_keys), metadata))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn nothing_is_fetched_without_reachable_peers() {
+        let programs = ProgramStore::new_in_memory().unwrap();
+        assert!(ProgramPeers::default()
+            .catch_up(&programs, 10)
+            .unwrap()
+            .is_empty());
+
+        // nothing listens on the discard port
+        let peers = ProgramPeers::new(vec!["http://127.0.0.1:9".to_string()]);
+        assert!(peers.catch_up(&programs, 10).unwrap().is_empty());
+        let program_id = vm::ProgramID::from_str("hello.aleo").unwrap();
+        assert!(!programs.exists(&program_id));
+    }
+}
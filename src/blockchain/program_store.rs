@@ -1,6 +1,17 @@
-use anyhow::{anyhow, Result};
+use crate::config::FsyncPolicy;
+use crate::kv_backend::{
+    BatchOperation, BufferedBackend, Column, KvBackend, MemoryBackend, RocksDbBackend,
+};
+use crate::migrations;
+use anyhow::{anyhow, bail, ensure, Result};
+use lib::merkle::{self, Hash, KeyProof, MerkleSet};
+use lib::query::{FailedDeployment, ProgramStats, ProgramSummary};
+use lib::transaction::Transaction;
 use lib::vm::{self, VerifyingKeyMap};
 use log::{debug, error};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
 use std::sync::mpsc::{channel, sync_channel, Receiver, Sender, SyncSender};
 use std::thread;
 
@@ -9,6 +20,54 @@ pub type StoredProgram = (vm::Program, vm::VerifyingKeyMap);
 type Key = vm::ProgramID;
 type Value = StoredProgram;
 
+/// Failed deployments kept for each program id, the oldest ones are dropped first.
+const MAX_FAILED_DEPLOYMENTS: usize = 10;
+
+/// Key of the `Meta` column entry with the height of the last committed block, see `ProgramStore::committed_height`.
+const HEIGHT_KEY: &[u8] = b"height";
+
+/// Information about how a program got to the store, kept apart from the program itself.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ProgramMetadata {
+    /// Height of the block that included the deployment, zero for programs loaded at startup.
+    pub height: u64,
+    /// Address of the account that deployed the program, if it was deployed by a transaction.
+    pub deployer: Option<vm::Address>,
+    /// Height from which executions of the program are rejected, if it was deprecated by its deployer.
+    pub deprecated_at: Option<u64>,
+    /// Hash of the program source code, if it was published by the deployer.
+    pub source_hash: Option<String>,
+}
+
+impl ProgramMetadata {
+    /// Build the public summary of the given program out of its metadata.
+    pub fn summary(self, program: &vm::Program) -> ProgramSummary {
+        ProgramSummary {
+            program_id: *program.id(),
+            height: self.height,
+            deployer: self.deployer,
+            deprecated_at: self.deprecated_at,
+            source_hash: self.source_hash,
+            functions: program
+                .functions()
+                .keys()
+                .map(|name| name.to_string())
+                .collect(),
+        }
+    }
+}
+
+type ListReply = (Vec<ProgramSummary>, Option<Key>);
+
+/// A chunked deployment whose first chunk paid for the staging of all of them, see `ProgramStore::stage_chunk`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Staging {
+    /// Height of the block that included the first chunk.
+    pub opened_at: u64,
+    /// Amount of chunks the deployment was split in.
+    pub total: u32,
+}
+
 /// The program store tracks programs that have been deployed to the OS
 #[derive(Clone, Debug)]
 pub struct ProgramStore {
@@ -18,24 +77,94 @@ pub struct ProgramStore {
 
 #[derive(Debug)]
 enum Command {
-    Add(Key, Box<Value>, SyncSender<Result<()>>),
+    Add(Key, Box<Value>, ProgramMetadata, SyncSender<Result<()>>),
+    Replace(Key, Box<Value>, ProgramMetadata, SyncSender<Result<()>>),
     Get(Key, SyncSender<Result<Option<Value>>>),
     Exists(Key, SyncSender<bool>),
+    Metadata(Key, SyncSender<Result<Option<ProgramMetadata>>>),
+    Deprecate(Key, u64, SyncSender<Result<()>>),
+    RecordExecution(Key, u64, u64, SyncSender<Result<()>>),
+    RecordFailedDeployment(Key, FailedDeployment, SyncSender<Result<()>>),
+    FailedDeployments(Key, SyncSender<Result<Vec<FailedDeployment>>>),
+    RegisterName {
+        name: String,
+        program_id: Key,
+        owner: vm::Address,
+        sequence: u64,
+        reply_sender: SyncSender<Result<()>>,
+    },
+    GetName(String, SyncSender<Result<Option<Key>>>),
+    NamespaceOwner(String, SyncSender<Result<Option<vm::Address>>>),
+    NameSequence(String, SyncSender<Result<u64>>),
+    NamesRoot(SyncSender<Hash>),
+    Stats(Key, SyncSender<Result<ProgramStats>>),
+    List {
+        prefix: Option<String>,
+        from: Option<Key>,
+        limit: Option<usize>,
+        reply_sender: SyncSender<ListReply>,
+    },
+    Root(SyncSender<Hash>),
+    Proof(Key, SyncSender<KeyProof>),
+    StageChunk {
+        deployment_hash: String,
+        index: u32,
+        total: u32,
+        data: Vec<u8>,
+        height: u64,
+        reply_sender: SyncSender<Result<Option<Vec<Vec<u8>>>>>,
+    },
+    DiscardChunks(String, SyncSender<Result<()>>),
+    Stagings(SyncSender<Result<Vec<(String, Staging)>>>),
+    PrepareCommit(u64, SyncSender<Result<Vec<u8>>>),
+    Commit(u64, SyncSender<Result<()>>),
+    RedoCommit(Vec<u8>, SyncSender<Result<()>>),
+    CommittedHeight(SyncSender<Result<Option<u64>>>),
+    Precompile(usize, SyncSender<Vec<Key>>),
+    CountEntries(SyncSender<Vec<(Column, u64)>>),
 }
 
 impl ProgramStore {
-    /// Start a new record store on a new thread
-    pub fn new(path: &str) -> Result<Self> {
-        let db_programs = rocksdb::DB::open_default(format!("{path}.deployed.db"))?;
+    /// Start a new program store on a new thread, persisted with RocksDB at the given path.
+    /// The writes of each commit are synced to disk following the given policy.
+    pub fn new(path: &str, fsync: FsyncPolicy) -> Result<Self> {
+        let mut backend = RocksDbBackend::open(&format!("{path}.db"))?;
+        migrations::import_legacy_databases(
+            path,
+            migrations::LEGACY_PROGRAM_DATABASES,
+            &mut backend,
+        )?;
+        migrations::run(&mut backend)?;
+        Self::with_backend(backend, fsync)
+    }
+
+    /// Start a new program store on a new thread, that keeps its state in memory.
+    pub fn new_in_memory() -> Result<Self> {
+        Self::with_backend(MemoryBackend::default(), FsyncPolicy::default())
+    }
 
+    /// Start a new program store on a new thread, using the given storage backend.
+    /// Changes are buffered until they are committed along with the block, see `commit`.
+    pub fn with_backend(backend: impl KvBackend, fsync: FsyncPolicy) -> Result<Self> {
         let (command_sender, command_receiver): (Sender<Command>, Receiver<Command>) = channel();
+        let mut backend = BufferedBackend::new(backend);
 
         thread::spawn(move || {
+            // merkle tree of the program hashes, loaded on demand and updated as programs are added.
+            let mut tree: Option<MerkleSet> = None;
+            // merkle root of the registry entries, cleared when a name is registered.
+            let mut names_root: Option<Hash> = None;
+
+            // programs and keys kept deserialized, see `precompile`. Stored programs only change when credits.aleo
+            // is upgraded, see `upgrade_credits`, which evicts it.
+            let mut cache: HashMap<Vec<u8>, Value> = HashMap::new();
+
             while let Ok(command) = command_receiver.recv() {
                 match command {
-                    Command::Add(program_id, program_keys, reply_to) => {
-                        let result = if db_programs
-                            .get(program_id.to_string().as_bytes())
+                    Command::Add(program_id, program_keys, metadata, reply_to) => {
+                        let key = program_id.to_string().into_bytes();
+                        let result = if backend
+                            .get(Column::Programs, &key)
                             .unwrap_or(None)
                             .is_some()
                         {
@@ -44,34 +173,408 @@ impl ProgramStore {
                                 &program_id,
                             ))
                         } else {
+                            if let Some(tree) = tree.as_mut() {
+                                tree.insert(key.clone(), &merkle::program_hash(&program_keys.0));
+                            }
                             let program_keys = bincode::serialize(&program_keys);
-                            Ok(db_programs
-                                .put(program_id.to_string().as_bytes(), program_keys.unwrap())
+                            let metadata = bincode::serialize(&metadata);
+                            Ok(backend
+                                .write(vec![
+                                    BatchOperation::Delete(Column::FailedDeployments, key.clone()),
+                                    BatchOperation::Put(
+                                        Column::ProgramMetadata,
+                                        key.clone(),
+                                        metadata.unwrap(),
+                                    ),
+                                    BatchOperation::Put(
+                                        Column::Programs,
+                                        key,
+                                        program_keys.unwrap(),
+                                    ),
+                                ])
                                 .unwrap_or_else(|e| error!("failed to write to db {}", e)))
                         };
 
                         reply_to.send(result).unwrap_or_else(|e| error!("{}", e));
                     }
+                    Command::Replace(program_id, program_keys, metadata, reply_to) => {
+                        let key = program_id.to_string().into_bytes();
+                        cache.remove(&key);
+                        if let Some(tree) = tree.as_mut() {
+                            tree.insert(key.clone(), &merkle::program_hash(&program_keys.0));
+                        }
+                        let program_keys = bincode::serialize(&program_keys).unwrap();
+                        let metadata = bincode::serialize(&metadata).unwrap();
+                        let result = backend.write(vec![
+                            BatchOperation::Put(Column::ProgramMetadata, key.clone(), metadata),
+                            BatchOperation::Put(Column::Programs, key, program_keys),
+                        ]);
+                        reply_to.send(result).unwrap_or_else(|e| error!("{}", e));
+                    }
                     Command::Get(program_id, reply_to) => {
-                        let result = db_programs
-                            .get(program_id.to_string().as_bytes())
-                            .unwrap_or(None)
-                            .map(|value| bincode::deserialize::<Value>(&value).unwrap());
+                        let key = program_id.to_string().into_bytes();
+                        let result = match cache.get(&key) {
+                            Some(value) => Some(value.clone()),
+                            None => backend
+                                .get(Column::Programs, &key)
+                                .unwrap_or(None)
+                                .map(|value| bincode::deserialize::<Value>(&value).unwrap()),
+                        };
 
                         reply_to
                             .send(Ok(result))
                             .unwrap_or_else(|e| error!("{}", e));
                     }
                     Command::Exists(program_id, reply_to) => {
-                        let result = db_programs.key_may_exist(program_id.to_string().as_bytes());
+                        let result =
+                            backend.contains(Column::Programs, program_id.to_string().as_bytes());
                         reply_to.send(result).unwrap_or_else(|e| error!("{}", e));
                     }
+                    Command::Metadata(program_id, reply_to) => {
+                        let result = backend
+                            .get(Column::ProgramMetadata, program_id.to_string().as_bytes())
+                            .and_then(|value| {
+                                value
+                                    .map(|bytes| bincode::deserialize(&bytes).map_err(Into::into))
+                                    .transpose()
+                            });
+                        reply_to.send(result).unwrap_or_else(|e| error!("{}", e));
+                    }
+                    Command::Deprecate(program_id, height, reply_to) => {
+                        let key = program_id.to_string().into_bytes();
+                        let result = backend
+                            .get(Column::ProgramMetadata, &key)
+                            .and_then(|value| {
+                                value.ok_or_else(|| {
+                                    anyhow!("Program {} doesn't exist in the store", &program_id)
+                                })
+                            })
+                            .and_then(|bytes| {
+                                let mut metadata: ProgramMetadata = bincode::deserialize(&bytes)?;
+                                metadata.deprecated_at = Some(height);
+                                backend.write(vec![BatchOperation::Put(
+                                    Column::ProgramMetadata,
+                                    key,
+                                    bincode::serialize(&metadata)?,
+                                )])
+                            });
+                        reply_to.send(result).unwrap_or_else(|e| error!("{}", e));
+                    }
+                    Command::RecordExecution(program_id, fee, height, reply_to) => {
+                        let key = program_id.to_string().into_bytes();
+                        let result = read_stats(&backend, &key).and_then(|mut stats| {
+                            stats.executions += 1;
+                            stats.fees += fee;
+                            stats.last_execution_height = Some(height);
+                            backend.write(vec![BatchOperation::Put(
+                                Column::ProgramStats,
+                                key,
+                                bincode::serialize(&stats)?,
+                            )])
+                        });
+                        reply_to.send(result).unwrap_or_else(|e| error!("{}", e));
+                    }
+                    Command::RecordFailedDeployment(program_id, failed, reply_to) => {
+                        let key = program_id.to_string().into_bytes();
+                        let result = read_failed_deployments(&backend, &key).and_then(
+                            |mut failed_deployments| {
+                                failed_deployments.push(failed);
+                                let excess = failed_deployments
+                                    .len()
+                                    .saturating_sub(MAX_FAILED_DEPLOYMENTS);
+                                failed_deployments.drain(..excess);
+                                backend.write(vec![BatchOperation::Put(
+                                    Column::FailedDeployments,
+                                    key,
+                                    bincode::serialize(&failed_deployments)?,
+                                )])
+                            },
+                        );
+                        reply_to.send(result).unwrap_or_else(|e| error!("{}", e));
+                    }
+                    Command::FailedDeployments(program_id, reply_to) => {
+                        let result =
+                            read_failed_deployments(&backend, program_id.to_string().as_bytes());
+                        reply_to.send(result).unwrap_or_else(|e| error!("{}", e));
+                    }
+                    Command::RegisterName {
+                        name,
+                        program_id,
+                        owner,
+                        sequence,
+                        reply_sender,
+                    } => {
+                        names_root = None;
+                        // names have a slash and namespaces don't, so they can share the column, along with the
+                        // sequence of each name, which has a `#` that neither of them can have
+                        let namespace = name.split('/').next().unwrap_or_default().to_string();
+                        let result = read_name_entry(&backend, &sequence_key(&name))
+                            .and_then(|current: Option<u64>| {
+                                let current = current.unwrap_or_default();
+                                ensure!(
+                                    sequence == current,
+                                    "The next registration of {name} must have sequence {current}, not {sequence}"
+                                );
+                                backend.write(vec![
+                                    BatchOperation::Put(
+                                        Column::Names,
+                                        namespace.into_bytes(),
+                                        bincode::serialize(&owner)?,
+                                    ),
+                                    BatchOperation::Put(
+                                        Column::Names,
+                                        sequence_key(&name).into_bytes(),
+                                        bincode::serialize(&(current + 1))?,
+                                    ),
+                                    BatchOperation::Put(
+                                        Column::Names,
+                                        name.into_bytes(),
+                                        bincode::serialize(&program_id)?,
+                                    ),
+                                ])
+                            });
+                        reply_sender
+                            .send(result)
+                            .unwrap_or_else(|e| error!("{}", e));
+                    }
+                    Command::GetName(name, reply_to) => {
+                        let result = read_name_entry(&backend, &name);
+                        reply_to.send(result).unwrap_or_else(|e| error!("{}", e));
+                    }
+                    Command::NamespaceOwner(namespace, reply_to) => {
+                        let result = read_name_entry(&backend, &namespace);
+                        reply_to.send(result).unwrap_or_else(|e| error!("{}", e));
+                    }
+                    Command::NameSequence(name, reply_to) => {
+                        let result = read_name_entry(&backend, &sequence_key(&name))
+                            .map(Option::unwrap_or_default);
+                        reply_to.send(result).unwrap_or_else(|e| error!("{}", e));
+                    }
+                    Command::NamesRoot(reply_sender) => {
+                        let root = names_root.get_or_insert_with(|| {
+                            let leaves: Vec<Hash> = backend
+                                .iterate(Column::Names, None)
+                                .map(|(key, value)| merkle::leaf_hash(&key, &value))
+                                .collect();
+                            merkle::root(&leaves)
+                        });
+                        reply_sender.send(*root).unwrap_or_else(|e| error!("{}", e));
+                    }
+                    Command::StageChunk {
+                        deployment_hash,
+                        index,
+                        total,
+                        data,
+                        height,
+                        reply_sender,
+                    } => {
+                        let result =
+                            stage_chunk(&mut backend, &deployment_hash, index, total, data, height);
+                        reply_sender
+                            .send(result)
+                            .unwrap_or_else(|e| error!("{}", e));
+                    }
+                    Command::DiscardChunks(deployment_hash, reply_to) => {
+                        let prefix = chunk_prefix(&deployment_hash);
+                        let mut deletes: Vec<_> = backend
+                            .iterate(Column::DeploymentChunks, Some(&prefix))
+                            .take_while(|(key, _)| key.starts_with(&prefix))
+                            .map(|(key, _)| BatchOperation::Delete(Column::DeploymentChunks, key))
+                            .collect();
+                        deletes.push(BatchOperation::Delete(
+                            Column::DeploymentStagings,
+                            deployment_hash.into_bytes(),
+                        ));
+                        let result = backend.write(deletes);
+                        reply_to.send(result).unwrap_or_else(|e| error!("{}", e));
+                    }
+                    Command::Stagings(reply_to) => {
+                        let result: Result<Vec<(String, Staging)>> = backend
+                            .iterate(Column::DeploymentStagings, None)
+                            .map(|(key, value)| {
+                                Ok((String::from_utf8(key)?, bincode::deserialize(&value)?))
+                            })
+                            .collect();
+                        reply_to.send(result).unwrap_or_else(|e| error!("{}", e));
+                    }
+                    Command::PrepareCommit(height, reply_to) => {
+                        let result = backend
+                            .write(vec![BatchOperation::Put(
+                                Column::Meta,
+                                HEIGHT_KEY.to_vec(),
+                                height.to_be_bytes().to_vec(),
+                            )])
+                            .and_then(|_| BatchOperation::encode_batch(&backend.pending_batch()));
+                        reply_to.send(result).unwrap_or_else(|e| error!("{}", e));
+                    }
+                    Command::RedoCommit(batch, reply_to) => {
+                        // the cached programs and the tree may predate the batch
+                        tree = None;
+                        names_root = None;
+                        cache.clear();
+                        let result = BatchOperation::decode_batch(&batch)
+                            .and_then(|batch| backend.write(batch))
+                            .and_then(|_| backend.flush())
+                            .and_then(|_| backend.sync());
+                        reply_to.send(result).unwrap_or_else(|e| error!("{}", e));
+                    }
+                    Command::Commit(height, reply_to) => {
+                        // the height is flushed in the same batch as the changes of the block
+                        let result = backend
+                            .write(vec![BatchOperation::Put(
+                                Column::Meta,
+                                HEIGHT_KEY.to_vec(),
+                                height.to_be_bytes().to_vec(),
+                            )])
+                            .and_then(|_| backend.flush())
+                            .and_then(|_| {
+                                if fsync.syncs_at(height) {
+                                    backend.sync()
+                                } else {
+                                    Ok(())
+                                }
+                            });
+                        reply_to.send(result).unwrap_or_else(|e| error!("{}", e));
+                    }
+                    Command::CommittedHeight(reply_to) => {
+                        let result = backend.get(Column::Meta, HEIGHT_KEY).and_then(|bytes| {
+                            bytes
+                                .map(|bytes| {
+                                    let bytes = bytes.try_into().map_err(|_| {
+                                        anyhow!("the committed height of the store is corrupt")
+                                    })?;
+                                    Ok(u64::from_be_bytes(bytes))
+                                })
+                                .transpose()
+                        });
+                        reply_to.send(result).unwrap_or_else(|e| error!("{}", e));
+                    }
+                    Command::Precompile(limit, reply_to) => {
+                        let mut executed: Vec<(Vec<u8>, ProgramStats)> = backend
+                            .iterate(Column::ProgramStats, None)
+                            .filter_map(|(key, value)| {
+                                Some((key, bincode::deserialize(&value).ok()?))
+                            })
+                            .collect();
+                        executed.sort_by(|(_, a), (_, b)| b.executions.cmp(&a.executions));
+                        let credits = b"credits.aleo".to_vec();
+                        let hot = executed
+                            .into_iter()
+                            .map(|(key, _)| key)
+                            .filter(|key| *key != credits)
+                            .take(limit);
+
+                        let mut precompiled = Vec::new();
+                        for key in std::iter::once(credits.clone()).chain(hot) {
+                            if cache.contains_key(&key) {
+                                continue;
+                            }
+                            let value = backend.get(Column::Programs, &key).and_then(|bytes| {
+                                bytes
+                                    .map(|bytes| bincode::deserialize::<Value>(&bytes))
+                                    .transpose()
+                                    .map_err(Into::into)
+                            });
+                            match value {
+                                Ok(Some(value)) => {
+                                    precompiled.push(*value.0.id());
+                                    cache.insert(key, value);
+                                }
+                                Ok(None) => {}
+                                Err(e) => error!(
+                                    "failed to load program {}: {e}",
+                                    String::from_utf8_lossy(&key)
+                                ),
+                            }
+                        }
+                        reply_to
+                            .send(precompiled)
+                            .unwrap_or_else(|e| error!("{}", e));
+                    }
+                    Command::CountEntries(reply_to) => {
+                        let counts = Column::ALL
+                            .iter()
+                            .map(|column| (*column, backend.iterate(*column, None).count() as u64))
+                            .collect();
+                        reply_to.send(counts).unwrap_or_else(|e| error!("{}", e));
+                    }
+                    Command::Stats(program_id, reply_to) => {
+                        let result = read_stats(&backend, program_id.to_string().as_bytes());
+                        reply_to.send(result).unwrap_or_else(|e| error!("{}", e));
+                    }
+                    Command::List {
+                        prefix,
+                        from,
+                        limit,
+                        reply_sender,
+                    } => {
+                        let from = from.map(|program_id| program_id.to_string());
+
+                        let mut programs = Vec::new();
+                        let mut last_key = None;
+                        for (key, value) in backend
+                            .iterate(Column::Programs, from.as_ref().map(|key| key.as_bytes()))
+                        {
+                            let key = String::from_utf8_lossy(&key).to_string();
+
+                            // the starting key was already returned in the previous page
+                            if Some(&key) == from.as_ref() {
+                                continue;
+                            }
+                            if !prefix
+                                .as_ref()
+                                .map_or(true, |prefix| key.starts_with(prefix))
+                            {
+                                continue;
+                            }
+                            if limit.map_or(false, |l| programs.len() >= l) {
+                                break;
+                            }
+
+                            let (program, _keys) = bincode::deserialize::<Value>(&value).unwrap();
+                            let metadata: ProgramMetadata = backend
+                                .get(Column::ProgramMetadata, key.as_bytes())
+                                .unwrap_or(None)
+                                .map(|bytes| bincode::deserialize(&bytes).unwrap())
+                                .unwrap_or_default();
+
+                            last_key = Some(*program.id());
+                            programs.push(metadata.summary(&program));
+                        }
+
+                        reply_sender
+                            .send((programs, last_key))
+                            .unwrap_or_else(|e| error!("{}", e));
+                    }
+                    Command::Root(reply_sender) => {
+                        let root = tree.get_or_insert_with(|| programs_tree(&backend)).root();
+                        reply_sender.send(root).unwrap_or_else(|e| error!("{}", e));
+                    }
+                    Command::Proof(program_id, reply_sender) => {
+                        let proof = tree.get_or_insert_with(|| programs_tree(&backend)).proof(
+                            program_id.to_string().as_bytes(),
+                            |key| {
+                                backend
+                                    .get(Column::Programs, key)
+                                    .ok()
+                                    .flatten()
+                                    .map_or_else(Vec::new, |value| {
+                                        let (program, _keys) =
+                                            bincode::deserialize::<Value>(&value).unwrap();
+                                        merkle::program_hash(&program).to_vec()
+                                    })
+                            },
+                        );
+                        reply_sender.send(proof).unwrap_or_else(|e| error!("{}", e));
+                    }
                 };
             }
         });
         let program_store = Self { command_sender };
 
+        // credits.aleo is part of the state before the first block, so it's written right away
         program_store.load_credits()?;
+        program_store.commit(0)?;
         Ok(program_store)
     }
 
@@ -91,18 +594,141 @@ impl ProgramStore {
         program_id: &vm::ProgramID,
         program: &vm::Program,
         verifying_keys: &vm::VerifyingKeyMap,
+        metadata: ProgramMetadata,
     ) -> Result<()> {
         let (reply_sender, reply_receiver) = sync_channel(0);
 
         self.command_sender.send(Command::Add(
             program_id.to_owned(),
             Box::new((program.clone(), verifying_keys.clone())),
+            metadata,
             reply_sender,
         ))?;
 
         reply_receiver.recv()?
     }
 
+    /// Return the deployment metadata of a program, if it's stored.
+    pub fn metadata(&self, program_id: &vm::ProgramID) -> Result<Option<ProgramMetadata>> {
+        let (reply_sender, reply_receiver) = sync_channel(0);
+        self.command_sender
+            .send(Command::Metadata(program_id.to_owned(), reply_sender))?;
+        reply_receiver.recv()?
+    }
+
+    /// Mark a stored program as deprecated, rejecting its executions from the given height on.
+    pub fn deprecate(&self, program_id: &vm::ProgramID, height: u64) -> Result<()> {
+        let (reply_sender, reply_receiver) = sync_channel(0);
+        self.command_sender.send(Command::Deprecate(
+            program_id.to_owned(),
+            height,
+            reply_sender,
+        ))?;
+        reply_receiver.recv()?
+    }
+
+    /// Count an execution of a stored program included at the given height, that paid the given fee.
+    pub fn record_execution(
+        &self,
+        program_id: &vm::ProgramID,
+        fee: u64,
+        height: u64,
+    ) -> Result<()> {
+        let (reply_sender, reply_receiver) = sync_channel(0);
+        self.command_sender.send(Command::RecordExecution(
+            program_id.to_owned(),
+            fee,
+            height,
+            reply_sender,
+        ))?;
+        reply_receiver.recv()?
+    }
+
+    /// Return the execution statistics of a program, all zero if it was never executed.
+    pub fn stats(&self, program_id: &vm::ProgramID) -> Result<ProgramStats> {
+        let (reply_sender, reply_receiver) = sync_channel(0);
+        self.command_sender
+            .send(Command::Stats(program_id.to_owned(), reply_sender))?;
+        reply_receiver.recv()?
+    }
+
+    /// Return up to `limit` program summaries sorted by program id, optionally only the ones whose id starts
+    /// with `prefix`. `from` is the last program id of the previous page, which is returned along with the results.
+    pub fn list(
+        &self,
+        prefix: Option<String>,
+        from: Option<vm::ProgramID>,
+        limit: Option<usize>,
+    ) -> Result<(Vec<ProgramSummary>, Option<vm::ProgramID>)> {
+        let (reply_sender, reply_receiver) = sync_channel(0);
+
+        self.command_sender.send(Command::List {
+            prefix,
+            from,
+            limit,
+            reply_sender,
+        })?;
+
+        Ok(reply_receiver.recv()?)
+    }
+
+    /// Return the root of the merkle tree of the deployed programs, see `merkle::PROGRAMS_COMPONENT`.
+    pub fn root(&self) -> Result<Hash> {
+        let (reply_sender, reply_receiver) = sync_channel(0);
+        self.command_sender.send(Command::Root(reply_sender))?;
+        Ok(reply_receiver.recv()?)
+    }
+
+    /// Return a proof that the program with the given id is deployed (along with its hash)
+    /// or that it isn't, against the current `root`.
+    pub fn proof(&self, program_id: &vm::ProgramID) -> Result<KeyProof> {
+        let (reply_sender, reply_receiver) = sync_channel(0);
+        self.command_sender
+            .send(Command::Proof(program_id.to_owned(), reply_sender))?;
+        Ok(reply_receiver.recv()?)
+    }
+
+    /// Stage a chunk of a deployment split in `total` chunks, see `Transaction::DeploymentChunk`, in the block at
+    /// the given height. The first chunk, which pays for the rest, opens the staging of the deployment, and the
+    /// rest of the chunks are rejected unless it's open. If it's the last missing chunk it isn't staged; instead
+    /// the data of all the chunks is returned, sorted by index, to apply the deployment and then `discard_chunks`.
+    pub fn stage_chunk(
+        &self,
+        deployment_hash: &str,
+        index: u32,
+        total: u32,
+        data: &[u8],
+        height: u64,
+    ) -> Result<Option<Vec<Vec<u8>>>> {
+        let (reply_sender, reply_receiver) = sync_channel(0);
+        self.command_sender.send(Command::StageChunk {
+            deployment_hash: deployment_hash.to_string(),
+            index,
+            total,
+            data: data.to_vec(),
+            height,
+            reply_sender,
+        })?;
+        reply_receiver.recv()?
+    }
+
+    /// Return the open stagings of chunked deployments, by deployment hash.
+    pub fn stagings(&self) -> Result<Vec<(String, Staging)>> {
+        let (reply_sender, reply_receiver) = sync_channel(0);
+        self.command_sender.send(Command::Stagings(reply_sender))?;
+        reply_receiver.recv()?
+    }
+
+    /// Remove the staged chunks of a deployment, closing its staging.
+    pub fn discard_chunks(&self, deployment_hash: &str) -> Result<()> {
+        let (reply_sender, reply_receiver) = sync_channel(0);
+        self.command_sender.send(Command::DiscardChunks(
+            deployment_hash.to_string(),
+            reply_sender,
+        ))?;
+        reply_receiver.recv()?
+    }
+
     /// Returns whether a program ID is already stored
     pub fn exists(&self, program_id: &vm::ProgramID) -> bool {
         let (reply_sender, reply_receiver) = sync_channel(0);
@@ -114,30 +740,306 @@ impl ProgramStore {
         reply_receiver.recv().unwrap_or(false)
     }
 
+    /// Write the changes buffered since the last commit, e.g. the programs deployed in the block being committed,
+    /// syncing them to disk if the policy says so for the given height.
+    pub fn commit(&self, height: u64) -> Result<()> {
+        let (reply_sender, reply_receiver) = sync_channel(0);
+        self.command_sender
+            .send(Command::Commit(height, reply_sender))?;
+        reply_receiver.recv()?
+    }
+
+    /// Return the changes buffered since the last commit, along with the given height, encoded to be written again with
+    /// `redo_commit` if the node stops before they are committed. See `TransactionPipeline::commit`.
+    pub fn prepare_commit(&self, height: u64) -> Result<Vec<u8>> {
+        let (reply_sender, reply_receiver) = sync_channel(0);
+        self.command_sender
+            .send(Command::PrepareCommit(height, reply_sender))?;
+        reply_receiver.recv()?
+    }
+
+    /// Write the changes of a block returned by `prepare_commit` that weren't committed, syncing them to disk.
+    pub fn redo_commit(&self, batch: Vec<u8>) -> Result<()> {
+        let (reply_sender, reply_receiver) = sync_channel(0);
+        self.command_sender
+            .send(Command::RedoCommit(batch, reply_sender))?;
+        reply_receiver.recv()?
+    }
+
+    /// Return the height of the last block committed to the store, None if it has none or they were committed before
+    /// the store kept it.
+    pub fn committed_height(&self) -> Result<Option<u64>> {
+        let (reply_sender, reply_receiver) = sync_channel(0);
+        self.command_sender
+            .send(Command::CommittedHeight(reply_sender))?;
+        reply_receiver.recv()?
+    }
+
+    /// Deserialize the verifying keys of credits.aleo and of the `limit` most executed programs and keep them in
+    /// memory, so verifying their first executions after a restart doesn't have to wait for it. Returns the ids of
+    /// the programs that weren't kept already.
+    pub fn precompile(&self, limit: usize) -> Result<Vec<vm::ProgramID>> {
+        let (reply_sender, reply_receiver) = sync_channel(0);
+        self.command_sender
+            .send(Command::Precompile(limit, reply_sender))?;
+        Ok(reply_receiver.recv()?)
+    }
+
+    /// Return the amount of stored entries of each column, e.g. the deployed programs and their metadata.
+    pub fn count_entries(&self) -> Result<Vec<(Column, u64)>> {
+        let (reply_sender, reply_receiver) = sync_channel(0);
+        self.command_sender
+            .send(Command::CountEntries(reply_sender))?;
+        Ok(reply_receiver.recv()?)
+    }
+
+    /// Keep track of a deployment of the program that failed, until the program is deployed.
+    pub fn record_failed_deployment(
+        &self,
+        program_id: &vm::ProgramID,
+        failed: FailedDeployment,
+    ) -> Result<()> {
+        let (reply_sender, reply_receiver) = sync_channel(0);
+        self.command_sender.send(Command::RecordFailedDeployment(
+            program_id.to_owned(),
+            failed,
+            reply_sender,
+        ))?;
+        reply_receiver.recv()?
+    }
+
+    /// Return the last failed deployments of the program, oldest first. Empty once the program is deployed.
+    pub fn failed_deployments(&self, program_id: &vm::ProgramID) -> Result<Vec<FailedDeployment>> {
+        let (reply_sender, reply_receiver) = sync_channel(0);
+        self.command_sender.send(Command::FailedDeployments(
+            program_id.to_owned(),
+            reply_sender,
+        ))?;
+        reply_receiver.recv()?
+    }
+
+    /// Point a registry name to a program, making the given account the owner of the namespace of the name.
+    /// The name is expected to be validated, see `lib::names`. The registration fails unless it has the current
+    /// `name_sequence` of the name, which it increments, so each signed registration can only be applied once.
+    pub fn register_name(
+        &self,
+        name: &str,
+        program_id: &vm::ProgramID,
+        owner: &vm::Address,
+        sequence: u64,
+    ) -> Result<()> {
+        let (reply_sender, reply_receiver) = sync_channel(0);
+        #[allow(clippy::clone_on_copy)]
+        self.command_sender.send(Command::RegisterName {
+            name: name.to_string(),
+            program_id: program_id.to_owned(),
+            owner: owner.clone(),
+            sequence,
+            reply_sender,
+        })?;
+        reply_receiver.recv()?
+    }
+
+    /// Return the sequence the next registration of a name has to have, which is the amount of times it was
+    /// registered.
+    pub fn name_sequence(&self, name: &str) -> Result<u64> {
+        let (reply_sender, reply_receiver) = sync_channel(0);
+        self.command_sender
+            .send(Command::NameSequence(name.to_string(), reply_sender))?;
+        reply_receiver.recv()?
+    }
+
+    /// Return the merkle root of the registry: the names, the owners of their namespaces and their sequences.
+    pub fn names_root(&self) -> Result<Hash> {
+        let (reply_sender, reply_receiver) = sync_channel(0);
+        self.command_sender.send(Command::NamesRoot(reply_sender))?;
+        Ok(reply_receiver.recv()?)
+    }
+
+    /// Return the id of the program a registry name points to, if it's registered.
+    pub fn resolve_name(&self, name: &str) -> Result<Option<vm::ProgramID>> {
+        let (reply_sender, reply_receiver) = sync_channel(0);
+        self.command_sender
+            .send(Command::GetName(name.to_string(), reply_sender))?;
+        reply_receiver.recv()?
+    }
+
+    /// Return the account that owns a namespace of the registry, if any name was registered in it.
+    pub fn namespace_owner(&self, namespace: &str) -> Result<Option<vm::Address>> {
+        let (reply_sender, reply_receiver) = sync_channel(0);
+        self.command_sender
+            .send(Command::NamespaceOwner(namespace.to_string(), reply_sender))?;
+        reply_receiver.recv()?
+    }
+
+    /// Store the version of credits.aleo chains start with, see `lib::CREDITS_V1_SOURCE`, unless the store
+    /// already has a version of it.
     fn load_credits(&self) -> Result<()> {
-        let (credits_program, keys) = lib::load_credits();
+        let (credits_program, keys) = lib::load_credits_v1();
 
         if self.exists(credits_program.id()) {
             debug!("Credits program already exists in program store");
             Ok(())
         } else {
             debug!("Loading credits.aleo as part of Program Store initialization");
-
-            let key_map = keys
-                .map
-                .into_iter()
-                .map(|(i, (_, verifying_key))| (i, verifying_key))
-                .collect();
-
             self.add(
                 credits_program.id(),
                 &credits_program,
-                &VerifyingKeyMap { map: key_map },
+                &verifying_keys(keys),
+                ProgramMetadata::default(),
             )?;
 
             Ok(())
         }
     }
+
+    /// Replace the stored credits.aleo with its current version, the one with locked credits, unless it was
+    /// replaced already. The source hash of the program metadata tells which version is stored. It's called in
+    /// the blocks of chains upgraded to the app version that introduced it, so the new program is committed along
+    /// with the first of them. Returns whether the program was replaced.
+    pub fn upgrade_credits(&self) -> Result<bool> {
+        let source_hash = Transaction::hash_source(lib::CREDITS_SOURCE);
+        let program_id = vm::ProgramID::from_str("credits.aleo")?;
+        let metadata = self.metadata(&program_id)?.unwrap_or_default();
+        if metadata.source_hash.as_ref() == Some(&source_hash) {
+            return Ok(false);
+        }
+
+        let (credits_program, keys) = lib::load_credits();
+        let (reply_sender, reply_receiver) = sync_channel(0);
+        self.command_sender.send(Command::Replace(
+            credits_program.id().to_owned(),
+            Box::new((credits_program, verifying_keys(keys))),
+            ProgramMetadata {
+                source_hash: Some(source_hash),
+                ..metadata
+            },
+            reply_sender,
+        ))?;
+        reply_receiver.recv()??;
+        Ok(true)
+    }
+}
+
+/// Keep only the verifying keys of a program build.
+fn verifying_keys(build: vm::ProgramBuild) -> VerifyingKeyMap {
+    let map = build
+        .map
+        .into_iter()
+        .map(|(i, (_, verifying_key))| (i, verifying_key))
+        .collect();
+    VerifyingKeyMap { map }
+}
+
+/// Load the merkle tree of the hashes of the deployed programs, by program id. It's only loaded once, the programs
+/// added later are inserted into the loaded tree.
+fn programs_tree(backend: &impl KvBackend) -> MerkleSet {
+    MerkleSet::new(backend.iterate(Column::Programs, None).map(|(key, value)| {
+        let (program, _keys) = bincode::deserialize::<Value>(&value).unwrap();
+        (key, merkle::program_hash(&program).to_vec())
+    }))
+}
+
+/// Stage a chunk as explained in `ProgramStore::stage_chunk`.
+fn stage_chunk(
+    backend: &mut impl KvBackend,
+    deployment_hash: &str,
+    index: u32,
+    total: u32,
+    data: Vec<u8>,
+    height: u64,
+) -> Result<Option<Vec<Vec<u8>>>> {
+    let staging_key = deployment_hash.as_bytes().to_vec();
+    let staging: Option<Staging> = backend
+        .get(Column::DeploymentStagings, &staging_key)?
+        .map(|bytes| bincode::deserialize(&bytes))
+        .transpose()?;
+    let mut operations = Vec::new();
+    match (index, staging) {
+        (0, None) => operations.push(BatchOperation::Put(
+            Column::DeploymentStagings,
+            staging_key,
+            bincode::serialize(&Staging {
+                opened_at: height,
+                total,
+            })?,
+        )),
+        (0, Some(_)) => bail!("The staging of deployment {deployment_hash} is already open"),
+        (_, None) => bail!("The first chunk of deployment {deployment_hash} wasn't delivered"),
+        (_, Some(staging)) => ensure!(
+            staging.total == total,
+            "Deployment {deployment_hash} was split in {} chunks, not {total}",
+            staging.total
+        ),
+    }
+
+    let prefix = chunk_prefix(deployment_hash);
+    let mut chunks: BTreeMap<u32, Vec<u8>> = backend
+        .iterate(Column::DeploymentChunks, Some(&prefix))
+        .take_while(|(key, _)| key.starts_with(&prefix))
+        .filter_map(|(key, value)| Some((chunk_index(&key[prefix.len()..])?, value)))
+        .collect();
+    chunks.insert(index, data.clone());
+
+    // the chunk that completes the deployment isn't staged, the caller discards the rest once
+    // the deployment is applied
+    if (0..total).all(|index| chunks.contains_key(&index)) {
+        return Ok(Some(chunks.into_values().take(total as usize).collect()));
+    }
+    operations.push(BatchOperation::Put(
+        Column::DeploymentChunks,
+        chunk_key(deployment_hash, index),
+        data,
+    ));
+    backend.write(operations)?;
+    Ok(None)
+}
+
+/// Staged chunks are keyed by deployment hash and zero-padded index, so iterating from the prefix yields them in order.
+fn chunk_prefix(deployment_hash: &str) -> Vec<u8> {
+    format!("{deployment_hash}/").into_bytes()
+}
+
+fn chunk_key(deployment_hash: &str, index: u32) -> Vec<u8> {
+    format!("{deployment_hash}/{index:010}").into_bytes()
+}
+
+fn chunk_index(suffix: &[u8]) -> Option<u32> {
+    std::str::from_utf8(suffix).ok()?.parse().ok()
+}
+
+/// Load the execution statistics of the program with the given key, defaulting to zero if it was never executed.
+fn read_stats(backend: &impl KvBackend, key: &[u8]) -> Result<ProgramStats> {
+    Ok(backend
+        .get(Column::ProgramStats, key)?
+        .map(|bytes| bincode::deserialize(&bytes))
+        .transpose()?
+        .unwrap_or_default())
+}
+
+/// Load an entry of the name registry, either the program of a name or the owner of a namespace.
+fn read_name_entry<T: serde::de::DeserializeOwned>(
+    backend: &impl KvBackend,
+    key: &str,
+) -> Result<Option<T>> {
+    Ok(backend
+        .get(Column::Names, key.as_bytes())?
+        .map(|bytes| bincode::deserialize(&bytes))
+        .transpose()?)
+}
+
+/// Key of the `Names` column entry with the sequence of the given name, see `ProgramStore::name_sequence`.
+fn sequence_key(name: &str) -> String {
+    format!("{name}#sequence")
+}
+
+/// Load the failed deployments of the program with the given key, if any.
+fn read_failed_deployments(backend: &impl KvBackend, key: &[u8]) -> Result<Vec<FailedDeployment>> {
+    Ok(backend
+        .get(Column::FailedDeployments, key)?
+        .map(|bytes| bincode::deserialize(&bytes))
+        .transpose()?
+        .unwrap_or_default())
 }
 
 #[cfg(test)]
@@ -165,7 +1067,7 @@ mod tests {
 
     #[test]
     fn add_program() {
-        let store = ProgramStore::new(&db_path("program")).unwrap();
+        let store = ProgramStore::new(&db_path("program"), FsyncPolicy::Always).unwrap();
 
         let program_path = format!("{}{}", env!("CARGO_MANIFEST_DIR"), "/aleo/hello.aleo");
         let program =
@@ -182,6 +1084,177 @@ mod tests {
         std::mem::forget(store);
     }
 
+    #[test]
+    fn in_memory_store() {
+        let store = ProgramStore::new_in_memory().unwrap();
+        assert!(store.exists(Program::credits().unwrap().id()));
+
+        let program = store_program(&store, "/aleo/hello.aleo").unwrap();
+        let (programs, _) = store.list(Some("hello".to_string()), None, None).unwrap();
+        assert_eq!(1, programs.len());
+        assert_eq!(program.id(), &programs[0].program_id);
+    }
+
+    #[test]
+    fn deployment_chunks_are_returned_once_complete() {
+        let store = ProgramStore::new_in_memory().unwrap();
+        // the staging is opened by the first chunk, which pays for it
+        assert!(store.stage_chunk("abc", 2, 3, b"c", 1).is_err());
+        assert!(store.stage_chunk("abc", 0, 3, b"a", 1).unwrap().is_none());
+        assert!(store.stage_chunk("abc", 0, 3, b"a", 1).is_err());
+        assert!(store.stage_chunk("abc", 2, 4, b"c", 1).is_err());
+        assert!(store.stage_chunk("abc", 2, 3, b"c", 1).unwrap().is_none());
+        assert!(store.stage_chunk("other", 0, 2, b"x", 2).unwrap().is_none());
+        assert_eq!(
+            vec![
+                (
+                    "abc".to_string(),
+                    Staging {
+                        opened_at: 1,
+                        total: 3
+                    }
+                ),
+                (
+                    "other".to_string(),
+                    Staging {
+                        opened_at: 2,
+                        total: 2
+                    }
+                )
+            ],
+            store.stagings().unwrap()
+        );
+
+        let chunks = store.stage_chunk("abc", 1, 3, b"b", 2).unwrap().unwrap();
+        assert_eq!(vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()], chunks);
+
+        store.discard_chunks("abc").unwrap();
+        assert!(store.stage_chunk("abc", 1, 3, b"b", 3).is_err());
+        assert_eq!(1, store.stagings().unwrap().len());
+        let chunks = store.stage_chunk("other", 1, 2, b"y", 3).unwrap().unwrap();
+        assert_eq!(vec![b"x".to_vec(), b"y".to_vec()], chunks);
+    }
+
+    #[test]
+    fn deprecate_program() {
+        let store = ProgramStore::new_in_memory().unwrap();
+        let program = store_program(&store, "/aleo/hello.aleo").unwrap();
+        assert_eq!(
+            None,
+            store.metadata(program.id()).unwrap().unwrap().deprecated_at
+        );
+
+        store.deprecate(program.id(), 10).unwrap();
+        assert_eq!(
+            Some(10),
+            store.metadata(program.id()).unwrap().unwrap().deprecated_at
+        );
+        let (programs, _) = store.list(Some("hello".to_string()), None, None).unwrap();
+        assert_eq!(Some(10), programs[0].deprecated_at);
+
+        let missing = vm::ProgramID::from_str("missing.aleo").unwrap();
+        assert!(store.metadata(&missing).unwrap().is_none());
+        assert!(store.deprecate(&missing, 10).is_err());
+    }
+
+    #[test]
+    fn program_stats() {
+        let store = ProgramStore::new_in_memory().unwrap();
+        let program = store_program(&store, "/aleo/hello.aleo").unwrap();
+        assert_eq!(ProgramStats::default(), store.stats(program.id()).unwrap());
+
+        store.record_execution(program.id(), 0, 3).unwrap();
+        store.record_execution(program.id(), 25, 7).unwrap();
+        assert_eq!(
+            ProgramStats {
+                executions: 2,
+                fees: 25,
+                last_execution_height: Some(7),
+            },
+            store.stats(program.id()).unwrap()
+        );
+    }
+
+    #[test]
+    #[allow(clippy::clone_on_copy)]
+    fn failed_deployments_are_kept_until_deployed() {
+        let store = ProgramStore::new_in_memory().unwrap();
+        let program_id = vm::ProgramID::from_str("hello.aleo").unwrap();
+        let private_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let view_key = vm::ViewKey::try_from(&private_key).unwrap();
+        let deployer = vm::Address::try_from(&view_key).unwrap();
+        let failed = |height| FailedDeployment {
+            transaction_id: format!("tx{height}"),
+            deployer: deployer.clone(),
+            height,
+            error: "fee record already spent".to_string(),
+        };
+
+        for height in 0..12 {
+            store
+                .record_failed_deployment(&program_id, failed(height))
+                .unwrap();
+        }
+        // only the last ones are kept
+        let failed_deployments = store.failed_deployments(&program_id).unwrap();
+        assert_eq!(MAX_FAILED_DEPLOYMENTS, failed_deployments.len());
+        assert_eq!(failed(2), failed_deployments[0]);
+        assert_eq!(failed(11), failed_deployments[9]);
+
+        let program = store_program(&store, "/aleo/hello.aleo").unwrap();
+        assert_eq!(&program_id, program.id());
+        assert!(store.failed_deployments(&program_id).unwrap().is_empty());
+    }
+
+    #[test]
+    fn registered_names() {
+        let store = ProgramStore::new_in_memory().unwrap();
+        let program_id = vm::ProgramID::from_str("hello.aleo").unwrap();
+        let private_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let owner = vm::Address::try_from(&vm::ViewKey::try_from(&private_key).unwrap()).unwrap();
+        assert!(store.resolve_name("greetings/hello").unwrap().is_none());
+        assert!(store.namespace_owner("greetings").unwrap().is_none());
+        let empty_root = store.names_root().unwrap();
+
+        store
+            .register_name("greetings/hello", &program_id, &owner, 0)
+            .unwrap();
+        assert_eq!(
+            Some(program_id),
+            store.resolve_name("greetings/hello").unwrap()
+        );
+        assert_eq!(Some(owner), store.namespace_owner("greetings").unwrap());
+        assert!(store.resolve_name("greetings/bye").unwrap().is_none());
+        assert_ne!(empty_root, store.names_root().unwrap());
+
+        // each registration of a name has to have the next sequence, so none of them can be applied twice
+        assert_eq!(1, store.name_sequence("greetings/hello").unwrap());
+        assert_eq!(0, store.name_sequence("greetings/bye").unwrap());
+        assert!(store
+            .register_name("greetings/hello", &program_id, &owner, 0)
+            .is_err());
+        store
+            .register_name("greetings/hello", &program_id, &owner, 1)
+            .unwrap();
+        assert_eq!(2, store.name_sequence("greetings/hello").unwrap());
+    }
+
+    #[test]
+    fn most_executed_programs_are_precompiled() {
+        let store = ProgramStore::new_in_memory().unwrap();
+        let program = store_program(&store, "/aleo/hello.aleo").unwrap();
+        store.record_execution(program.id(), 0, 3).unwrap();
+
+        let credits = Program::credits().unwrap();
+        assert_eq!(vec![*credits.id()], store.precompile(0).unwrap());
+        assert_eq!(vec![*program.id()], store.precompile(5).unwrap());
+        assert!(store.precompile(5).unwrap().is_empty());
+
+        // cached programs are returned like the rest
+        let (cached, _keys) = store.get(program.id()).unwrap().unwrap();
+        assert_eq!(program.id(), cached.id());
+    }
+
     #[test]
     fn credits_loaded() {
         let program = Program::credits().expect("Problem loading Credits");
@@ -191,11 +1264,26 @@ mod tests {
             let get_program = store.get(program.id().to_string().into_bytes());
             assert!(get_program.unwrap().is_none());
         }
-        let store = ProgramStore::new(&db_path("credits")).unwrap();
+        let store = ProgramStore::new(&db_path("credits"), FsyncPolicy::Always).unwrap();
 
         assert!(store.exists(program.id()));
     }
 
+    #[test]
+    fn credits_are_upgraded_once() {
+        let store = ProgramStore::new_in_memory().unwrap();
+        let credits = vm::ProgramID::from_str("credits.aleo").unwrap();
+        let functions = || {
+            let (program, _) = store.get(&credits).unwrap().unwrap();
+            ProgramMetadata::default().summary(&program).functions
+        };
+        assert!(!functions().contains(&"unlock".to_string()));
+
+        assert!(store.upgrade_credits().unwrap());
+        assert!(functions().contains(&"unlock".to_string()));
+        assert!(!store.upgrade_credits().unwrap());
+    }
+
     fn store_program(program_store: &ProgramStore, path: &str) -> Result<vm::Program> {
         let program_path = format!("{}{}", env!("CARGO_MANIFEST_DIR"), path);
 
@@ -210,7 +1298,12 @@ mod tests {
             .map(|(i, (_, verifying_key))| (i, verifying_key))
             .collect();
 
-        program_store.add(program.id(), &program, &VerifyingKeyMap { map: keys })?;
+        program_store.add(
+            program.id(),
+            &program,
+            &VerifyingKeyMap { map: keys },
+            ProgramMetadata::default(),
+        )?;
 
         Ok(program)
     }
@@ -1,7 +1,14 @@
+use crate::bloom::BloomFilter;
+use crate::config::FsyncPolicy;
+use crate::kv_backend::{
+    BatchOperation, Column, KvBackend, KvReplica, MemoryBackend, RocksDbBackend,
+};
+use crate::migrations;
 use anyhow::{anyhow, Result};
+use lib::bridge::{self, Packet};
+use lib::merkle::{Hash, KeyProof, MerkleSet};
 use lib::vm::{self, EncryptedRecord, Field};
 use log::error;
-use rocksdb::{Direction, IteratorMode, WriteBatch};
 use std::collections::{HashMap, HashSet};
 use std::str::FromStr;
 use std::sync::mpsc::{channel, sync_channel, Receiver, Sender, SyncSender};
@@ -10,6 +17,8 @@ use std::thread;
 // because both serial numbers and Commitments are really fields, define types to differentiate them
 type SerialNumber = Field;
 type Commitment = Field;
+/// Block height at which a record was created or spent.
+pub type Height = u64;
 
 // TODO: Key and Value types should be concrete types instead of serialized data like in
 // program store, so that type errors bubble up asap (ie from the interaction with the DB)
@@ -17,56 +26,224 @@ type Key = Vec<u8>;
 type Value = Vec<u8>;
 
 /// Internal channel reply for the scan command
-type ScanReply = (Vec<(Key, Value)>, Option<Key>);
-/// Public return type for the scan command.
-type ScanResult = (Vec<(Commitment, vm::EncryptedRecord)>, Option<SerialNumber>);
+type ScanReply = (Vec<(Key, Value, Option<Height>)>, Option<Key>);
+/// Public return type for the scan command, including the height each record was committed at (if known).
+type HeightScanResult = (
+    Vec<(Commitment, vm::EncryptedRecord, Option<Height>)>,
+    Option<SerialNumber>,
+);
+
+/// Key of the `Meta` column entry that holds the id of the chain, as set at genesis.
+const CHAIN_ID_KEY: &[u8] = b"chain_id";
+
+/// Keys of the `Meta` column entries that count the committed transactions and records, see `RecordStore::counts`.
+const TRANSACTIONS_KEY: &[u8] = b"transactions";
+const RECORDS_KEY: &[u8] = b"records";
+
+/// Keys of the `Meta` column entries with the encoded governance and bridge states, written in the same batch as the
+/// changes of each block, see `RecordStore::stage_governance` and `RecordStore::stage_bridge`.
+const GOVERNANCE_KEY: &[u8] = b"governance";
+const BRIDGE_KEY: &[u8] = b"bridge";
+
+/// Key of the `Meta` column entry with the changes of the program store in the last committed block, see
+/// `RecordStore::stage_program_changes`.
+const PROGRAM_CHANGES_KEY: &[u8] = b"program_changes";
+
+/// Key of the `Meta` column entry with the height of the last committed block, written in the same batch as its
+/// changes, see `RecordStore::committed_height`.
+const HEIGHT_KEY: &[u8] = b"height";
+
+/// Amount of validator set snapshots kept in the store, so there's a previous one to fall back to if the last is corrupt.
+const VALIDATOR_SNAPSHOTS_KEPT: u64 = 2;
+
+/// Columns keyed by block height that pruned nodes only keep for the last heights, see `RecordStore::stage_prune`.
+const PRUNED_COLUMNS: [Column; 2] = [Column::Events, Column::Rewards];
+
+/// Names of the bloom filters of the spent serial numbers and the record commitments in the `Filters` column.
+const SPENT_FILTER: &str = "spent";
+const RECORDS_FILTER: &str = "records";
 
 /// The record store tracks the known unspent and spent record sets (similar to bitcoin's UTXO set)
 /// according to the transactions that are committed to the ledger.
 /// Because of how Tendermint ABCI applications are structured, this store is prepared to buffer
 /// updates (new unspent record additions and spending of known records) while transactions are being
 /// processed, and apply them together when the block is committed.
+/// Queries that scan the store are served by a separate task from a replica of the committed state,
+/// refreshed at each commit, so heavy queries don't hold up the processing of transactions.
 #[derive(Clone, Debug)]
 pub struct RecordStore {
     /// Channel used to send operations to the task that manages the store state.
     command_sender: Sender<Command>,
+    /// Channel used to send read-only queries to the task that manages the replica, see `serve_queries`.
+    query_sender: Sender<Query>,
 }
 
 #[derive(Debug)]
 enum Command {
     Add(Key, Value, SyncSender<Result<()>>),
+    AddMemo(Key, Value),
+    IndexProgramRecord(Key),
+    AddProgramOwned(Key, Value),
+    OwnerProgram(Key, SyncSender<Option<Value>>),
+    StagePacket(Key, Value),
+    StageValidators(Value),
+    ValidatorSnapshots(SyncSender<Vec<(Height, Value)>>),
+    StageMeta(&'static [u8], Value),
+    GetMeta(&'static [u8], SyncSender<Result<Option<Value>>>),
+    Schedule(Height, Key, Value, Vec<Key>),
+    CheckSchedulable(Vec<Key>, SyncSender<Result<()>>),
+    TakeScheduled(Height, SyncSender<Vec<Value>>),
+    StageEvents(Value),
+    StageRewards(Value),
+    StagePrune(Height),
+    SetChainId(Value, SyncSender<Result<()>>),
+    GetChainId(SyncSender<Result<Option<Value>>>),
+    GetCommittedHeight(SyncSender<Result<Option<Height>>>),
+    CountTransaction,
+    Counts(SyncSender<(u64, u64)>),
     Spend(Key, SyncSender<Result<()>>),
     IsUnspent(Key, SyncSender<bool>),
-    Commit,
-    ScanSpentRecords(SyncSender<HashSet<SerialNumber>>),
+    Commit(Height, SyncSender<()>),
+    ScanPackets {
+        prefix: Key,
+        from: Key,
+        limit: usize,
+        reply_sender: SyncSender<Vec<Value>>,
+    },
+    Root(Column, SyncSender<Hash>),
+    Proof(Column, Key, SyncSender<KeyProof>),
+}
+
+/// Read-only operations served from the replica of the committed state.
+#[derive(Debug)]
+enum Query {
+    GetEvents(Height, SyncSender<Option<Value>>),
+    ScanRewards {
+        from: Height,
+        to: Height,
+        reply_sender: SyncSender<Vec<Value>>,
+    },
+    GetMemos(Vec<Key>, SyncSender<Vec<(Key, Value)>>),
+    ScanSpentRecords(SyncSender<HashMap<SerialNumber, Option<Height>>>),
     ScanRecords {
         from: Option<Key>,
         limit: Option<usize>,
         reply_sender: SyncSender<ScanReply>,
     },
+    ScanProgramRecords {
+        prefix: Key,
+        from: Option<Key>,
+        limit: Option<usize>,
+        reply_sender: SyncSender<ScanReply>,
+    },
+    /// Count the committed entries of each column.
+    CountEntries(SyncSender<Vec<(Column, u64)>>),
+    /// Catch up with the last commit.
+    Refresh,
 }
 
 impl RecordStore {
-    /// Start a new record store on a new thread
-    pub fn new(path: &str) -> Result<Self> {
-        // TODO review column families, may be a more natural way to separate spent/unspent on the same db and still get the benefits
-        // https://github.com/EighteenZi/rocksdb_wiki/blob/master/Column-Families.md
-        // we may also like to try something other than rocksdb here, e.g. sqlite
-
-        // TODO: comment on this
-        let db_records = rocksdb::DB::open_default(format!("{path}.records.db"))?;
+    /// Start a new record store on a new thread, persisted with RocksDB at the given path.
+    /// The writes of each commit are synced to disk following the given policy.
+    pub fn new(path: &str, fsync: FsyncPolicy) -> Result<Self> {
+        let mut backend = RocksDbBackend::open(&format!("{path}.db"))?;
+        migrations::import_legacy_databases(
+            path,
+            migrations::LEGACY_RECORD_DATABASES,
+            &mut backend,
+        )?;
+        migrations::run(&mut backend)?;
+        Ok(Self::with_backend(backend, fsync))
+    }
 
-        // DB to track spent record serial_numbers. These are tracked to ensure that records aren't spent more than once
-        // (without having to _know_ the actual record contents).
-        let db_spent = rocksdb::DB::open_default(format!("{path}.spent.db"))?;
+    /// Start a new record store on a new thread, that keeps its state in memory.
+    pub fn new_in_memory() -> Self {
+        Self::with_backend(MemoryBackend::default(), FsyncPolicy::default())
+    }
 
+    /// Start a new record store on a new thread, using the given storage backend. The backend has separate columns for:
+    /// * Records: the known record ciphertexts by commitment.
+    /// * Spent: the spent record serial_numbers. These are tracked to ensure that records aren't spent more than once
+    /// (without having to _know_ the actual record contents). The value stored for each serial number is the height at which the record was spent.
+    /// * Heights: the height at which each record commitment was committed to the ledger, for auditing purposes.
+    /// * Memos: the encrypted memos attached to records, by record commitment.
+    /// * Validators: the last few validator set snapshots, by the height of the block that committed them.
+    /// * Scheduled: the transactions to apply at a later height, by target height and transaction id.
+    /// * ScheduledOutputs: the output commitments of the scheduled transactions, reserved until they are applied.
+    /// * Events: the events emitted while processing each block, by block height.
+    /// * Rewards: how the rewards of each block were distributed between the validators, by block height.
+    /// * ProgramRecords: the commitments of the records created by each program, keyed `{program_id}/{commitment}`.
+    /// * Packets: the bridge packets sent to counterparty chains, keyed by `bridge::packet_key`.
+    /// * Filters: the bloom filters of the spent serial numbers and record commitments, see `bloom::BloomFilter`.
+    /// * Meta: the chain id and the counts of committed transactions and records.
+    pub fn with_backend(mut backend: impl KvBackend, fsync: FsyncPolicy) -> Self {
         // map to store temporary unspent record additions until a block is comitted.
         let mut record_buffer = HashMap::new();
 
-        // map to store temporary spent record additions until a block is comitted.
-        let mut spent_buffer = HashMap::new();
+        // set to store temporary spent record additions until a block is comitted.
+        let mut spent_buffer = HashSet::new();
+
+        // map to store the memos of the buffered records until a block is comitted.
+        let mut memo_buffer = HashMap::new();
+
+        // index entries of the buffered records by the program that created them, added when a block is comitted.
+        let mut program_record_buffer = HashSet::new();
+
+        // owner programs of the buffered records owned by a program address, by serial number, added when a block is comitted.
+        let mut program_owned_buffer = HashMap::new();
+
+        // bridge packets sent in the block being processed, added when a block is comitted.
+        let mut packet_buffer = HashMap::new();
+
+        // the validator set to save along with the next commit, if it was staged.
+        let mut validators_buffer = None;
+
+        // the governance and bridge states to save along with the next commit, by `Meta` key.
+        let mut meta_buffer = HashMap::new();
+
+        // scheduled transactions to add and to remove (because they were applied) when a block is comitted.
+        let mut scheduled_buffer = HashMap::new();
+        let mut applied_buffer = HashSet::new();
+
+        // output commitments reserved by the scheduled transactions, by the key of their transaction, to add and to
+        // remove (because their records were added) when a block is comitted.
+        let mut reserved_buffer = HashMap::new();
+        let mut released_buffer = HashSet::new();
+
+        // the events of the block being processed, to save along with the next commit.
+        let mut events_buffer = None;
+
+        // the rewards distribution of the block being processed, to save along with the next commit.
+        let mut rewards_buffer = None;
+
+        // the height below which the history of the blocks is removed with the next commit, on pruned nodes.
+        let mut prune_buffer = None;
+
+        // merkle trees of the columns committed in the app hash, loaded on demand and updated with the entries
+        // written by each commit.
+        let mut trees: HashMap<Column, MerkleSet> = HashMap::new();
+
+        // bloom filters of the committed serial numbers and commitments, so checking the ones that aren't in the store,
+        // e.g. of unknown records in spam transactions, doesn't read the storage. They're updated along with each commit.
+        let mut spent_filter = BloomFilter::load(&backend, SPENT_FILTER, Column::Spent);
+        let mut records_filter = BloomFilter::load(&backend, RECORDS_FILTER, Column::Records);
+        let mut filter_changes = spent_filter.take_changes();
+        filter_changes.extend(records_filter.take_changes());
+        backend
+            .write(filter_changes)
+            .unwrap_or_else(|e| error!("failed to persist the record filters: {e}"));
+
+        // amounts of committed transactions and records, saved in the `Meta` column along with each commit. Stores
+        // that didn't keep the record count count the column once, the transactions are counted from then on.
+        let mut transaction_count = read_count(&backend, TRANSACTIONS_KEY).unwrap_or(0);
+        let mut record_count = read_count(&backend, RECORDS_KEY)
+            .unwrap_or_else(|| backend.iterate(Column::Records, None).count() as u64);
+        // transactions applied in the block being processed, added to the count when a block is comitted.
+        let mut transaction_buffer = 0;
 
         let (command_sender, command_receiver): (Sender<Command>, Receiver<Command>) = channel();
+        let query_sender = serve_queries(backend.replica());
+        let replica_sender = query_sender.clone();
 
         thread::spawn(move || {
             while let Ok(command) = command_receiver.recv() {
@@ -75,27 +252,171 @@ impl RecordStore {
                         // TODO: Remove/change this into something secure (merkle path to valid records exists)
                         // Because tracking existence and spent status leads to security concerns, existence of records will
                         // have to be proven by the execution. Until this is implemented, return Ok by default here and assume the record exists.
+                        // Reserved commitments can only be added by their scheduled transaction, once it's taken.
                         let result = if record_buffer.contains_key(&commitment)
-                            || key_exists_or_fails(&db_records, &commitment)
+                            || (records_filter.may_contain(&commitment)
+                                && backend.contains(Column::Records, &commitment))
                         {
                             Err(anyhow!(
                                 "record {} already exists",
                                 String::from_utf8_lossy(&commitment)
                             ))
                         } else {
-                            record_buffer.insert(commitment, ciphertext);
-                            Ok(())
+                            match reservation(&backend, &reserved_buffer, &commitment) {
+                                Some(key) if !applied_buffer.contains(&key) => Err(anyhow!(
+                                    "record {} is an output of a scheduled execution",
+                                    String::from_utf8_lossy(&commitment)
+                                )),
+                                reserved => {
+                                    if reserved.is_some() {
+                                        released_buffer.insert(commitment.clone());
+                                    }
+                                    record_buffer.insert(commitment, ciphertext);
+                                    Ok(())
+                                }
+                            }
                         };
                         reply_to.send(result).unwrap_or_else(|e| error!("{}", e));
                     }
+                    Command::AddMemo(commitment, memo) => {
+                        memo_buffer.insert(commitment, memo);
+                    }
+                    Command::IndexProgramRecord(key) => {
+                        program_record_buffer.insert(key);
+                    }
+                    Command::AddProgramOwned(serial_number, program_id) => {
+                        program_owned_buffer.insert(serial_number, program_id);
+                    }
+                    Command::OwnerProgram(serial_number, reply_to) => {
+                        let program_id = match program_owned_buffer.get(&serial_number) {
+                            Some(program_id) => Some(program_id.clone()),
+                            None => backend
+                                .get(Column::ProgramOwned, &serial_number)
+                                .unwrap_or_else(|e| {
+                                    error!("failed to read the owner program of a record: {e}");
+                                    None
+                                }),
+                        };
+                        reply_to
+                            .send(program_id)
+                            .unwrap_or_else(|e| error!("{}", e));
+                    }
+                    Command::StagePacket(key, packet) => {
+                        packet_buffer.insert(key, packet);
+                    }
+                    Command::StageValidators(validators) => {
+                        validators_buffer = Some(validators);
+                    }
+                    Command::StageMeta(key, value) => {
+                        meta_buffer.insert(key, value);
+                    }
+                    Command::GetMeta(key, reply_to) => {
+                        let result = backend.get(Column::Meta, key);
+                        reply_to.send(result).unwrap_or_else(|e| error!("{}", e));
+                    }
+                    Command::Schedule(height, id, transaction, commitments) => {
+                        let key = scheduled_key(height, &id);
+                        for commitment in commitments {
+                            reserved_buffer.insert(commitment, key.clone());
+                        }
+                        scheduled_buffer.insert(key, transaction);
+                    }
+                    Command::CheckSchedulable(commitments, reply_to) => {
+                        let result = commitments
+                            .iter()
+                            .find_map(|commitment| {
+                                let commitment_str = String::from_utf8_lossy(commitment);
+                                if record_buffer.contains_key(commitment)
+                                    || (records_filter.may_contain(commitment)
+                                        && backend.contains(Column::Records, commitment))
+                                {
+                                    Some(anyhow!("record {commitment_str} already exists"))
+                                } else if reservation(&backend, &reserved_buffer, commitment)
+                                    .is_some()
+                                {
+                                    Some(anyhow!(
+                                        "record {commitment_str} is already an output of a scheduled execution"
+                                    ))
+                                } else {
+                                    None
+                                }
+                            })
+                            .map_or(Ok(()), Err);
+                        reply_to.send(result).unwrap_or_else(|e| error!("{}", e));
+                    }
+                    Command::TakeScheduled(height, reply_to) => {
+                        let prefix = height.to_be_bytes();
+                        let transactions = backend
+                            .iterate(Column::Scheduled, Some(&prefix))
+                            .take_while(|(key, _)| key.starts_with(&prefix))
+                            .filter(|(key, _)| applied_buffer.insert(key.clone()))
+                            .map(|(_, transaction)| transaction)
+                            .collect();
+                        reply_to
+                            .send(transactions)
+                            .unwrap_or_else(|e| error!("{}", e));
+                    }
+                    Command::SetChainId(chain_id, reply_to) => {
+                        let result = backend.write(vec![BatchOperation::Put(
+                            Column::Meta,
+                            CHAIN_ID_KEY.to_vec(),
+                            chain_id,
+                        )]);
+                        reply_to.send(result).unwrap_or_else(|e| error!("{}", e));
+                    }
+                    Command::GetChainId(reply_to) => {
+                        let result = backend.get(Column::Meta, CHAIN_ID_KEY);
+                        reply_to.send(result).unwrap_or_else(|e| error!("{}", e));
+                    }
+                    Command::GetCommittedHeight(reply_to) => {
+                        let result = backend.get(Column::Meta, HEIGHT_KEY).and_then(|bytes| {
+                            bytes
+                                .map(|bytes| {
+                                    let bytes = bytes.try_into().map_err(|_| {
+                                        anyhow!("the committed height of the store is corrupt")
+                                    })?;
+                                    Ok(Height::from_be_bytes(bytes))
+                                })
+                                .transpose()
+                        });
+                        reply_to.send(result).unwrap_or_else(|e| error!("{}", e));
+                    }
+                    Command::CountTransaction => {
+                        transaction_buffer += 1;
+                    }
+                    Command::Counts(reply_to) => {
+                        reply_to
+                            .send((transaction_count, record_count))
+                            .unwrap_or_else(|e| error!("{}", e));
+                    }
+                    Command::StageEvents(events) => {
+                        events_buffer = Some(events);
+                    }
+                    Command::StageRewards(rewards) => {
+                        rewards_buffer = Some(rewards);
+                    }
+                    Command::StagePrune(height) => {
+                        prune_buffer = Some(height);
+                    }
+                    Command::ValidatorSnapshots(reply_to) => {
+                        let mut snapshots: Vec<(Height, Value)> = backend
+                            .iterate(Column::Validators, None)
+                            .filter_map(|(key, value)| {
+                                Some((Height::from_be_bytes(key.try_into().ok()?), value))
+                            })
+                            .collect();
+                        snapshots.reverse();
+                        reply_to.send(snapshots).unwrap_or_else(|e| error!("{}", e));
+                    }
                     Command::Spend(serial_number, reply_to) => {
                         // TODO: [related to above] implement record existence check and handle case where it exists and it doesn't
-                        let result = if key_exists_or_fails(&db_spent, &serial_number)
-                            || spent_buffer.contains_key(&serial_number)
+                        let result = if spent_buffer.contains(&serial_number)
+                            || (spent_filter.may_contain(&serial_number)
+                                && backend.contains(Column::Spent, &serial_number))
                         {
                             Err(anyhow!("record already spent"))
                         } else {
-                            spent_buffer.insert(serial_number, "1".as_bytes());
+                            spent_buffer.insert(serial_number);
                             Ok(())
                         };
 
@@ -103,80 +424,209 @@ impl RecordStore {
                     }
                     Command::IsUnspent(serial_number, reply_to) => {
                         // TODO: [related to above] handle record existence scenarios
-                        let is_unspent = !key_exists_or_fails(&db_spent, &serial_number)
-                            && !spent_buffer.contains_key(&serial_number);
+                        let is_unspent = !spent_buffer.contains(&serial_number)
+                            && !(spent_filter.may_contain(&serial_number)
+                                && backend.contains(Column::Spent, &serial_number));
                         reply_to
                             .send(is_unspent)
                             .unwrap_or_else(|e| error!("{}", e));
                     }
-                    Command::Commit => {
-                        // add new records to store
-                        let mut batch = WriteBatch::default();
-                        for (key, value) in record_buffer.iter() {
-                            batch.put(key, value);
+                    Command::Commit(height, reply_to) => {
+                        // add new records to store, and all buffer spent as spent, i.e. persisted consumed records (as a serial number for security)
+                        let mut batch = Vec::new();
+                        record_count += record_buffer.len() as u64;
+                        transaction_count += std::mem::take(&mut transaction_buffer);
+                        batch.push(BatchOperation::Put(
+                            Column::Meta,
+                            RECORDS_KEY.to_vec(),
+                            record_count.to_be_bytes().to_vec(),
+                        ));
+                        batch.push(BatchOperation::Put(
+                            Column::Meta,
+                            TRANSACTIONS_KEY.to_vec(),
+                            transaction_count.to_be_bytes().to_vec(),
+                        ));
+                        batch.push(BatchOperation::Put(
+                            Column::Meta,
+                            HEIGHT_KEY.to_vec(),
+                            height.to_be_bytes().to_vec(),
+                        ));
+                        for (key, value) in record_buffer.drain() {
+                            records_filter.insert(&key);
+                            batch.push(BatchOperation::Put(
+                                Column::Heights,
+                                key.clone(),
+                                height.to_be_bytes().to_vec(),
+                            ));
+                            batch.push(BatchOperation::Put(Column::Records, key, value));
                         }
-                        db_records
-                            .write(batch)
-                            .unwrap_or_else(|e| error!("failed to write to db {}", e));
-
-                        // add all buffer spent to db spent, i.e. persisted consumed records (as a serial number for security)
-                        let mut batch = WriteBatch::default();
-                        for (key, value) in spent_buffer.iter() {
-                            batch.put(key.clone(), value);
+                        for (key, memo) in memo_buffer.drain() {
+                            batch.push(BatchOperation::Put(Column::Memos, key, memo));
+                        }
+                        for key in program_record_buffer.drain() {
+                            batch.push(BatchOperation::Put(Column::ProgramRecords, key, vec![]));
+                        }
+                        for (key, program_id) in program_owned_buffer.drain() {
+                            batch.push(BatchOperation::Put(Column::ProgramOwned, key, program_id));
+                        }
+                        for (key, packet) in packet_buffer.drain() {
+                            batch.push(BatchOperation::Put(Column::Packets, key, packet));
+                        }
+                        for key in spent_buffer.drain() {
+                            spent_filter.insert(&key);
+                            batch.push(BatchOperation::Put(
+                                Column::Spent,
+                                key,
+                                height.to_be_bytes().to_vec(),
+                            ));
+                        }
+                        for (key, transaction) in scheduled_buffer.drain() {
+                            batch.push(BatchOperation::Put(Column::Scheduled, key, transaction));
+                        }
+                        for key in applied_buffer.drain() {
+                            batch.push(BatchOperation::Delete(Column::Scheduled, key));
+                        }
+                        for (commitment, key) in reserved_buffer.drain() {
+                            batch.push(BatchOperation::Put(
+                                Column::ScheduledOutputs,
+                                commitment,
+                                key,
+                            ));
+                        }
+                        for commitment in released_buffer.drain() {
+                            batch
+                                .push(BatchOperation::Delete(Column::ScheduledOutputs, commitment));
+                        }
+                        if let Some(events) = events_buffer.take() {
+                            batch.push(BatchOperation::Put(
+                                Column::Events,
+                                height.to_be_bytes().to_vec(),
+                                events,
+                            ));
+                        }
+                        if let Some(rewards) = rewards_buffer.take() {
+                            batch.push(BatchOperation::Put(
+                                Column::Rewards,
+                                height.to_be_bytes().to_vec(),
+                                rewards,
+                            ));
+                        }
+                        if let Some(below) = prune_buffer.take() {
+                            // heights are big endian, so the keys of the older blocks sort first
+                            let below = Height::to_be_bytes(below);
+                            for column in PRUNED_COLUMNS {
+                                for (key, _) in backend
+                                    .iterate(column, None)
+                                    .take_while(|(key, _)| key.as_slice() < below.as_slice())
+                                {
+                                    batch.push(BatchOperation::Delete(column, key));
+                                }
+                            }
+                        }
+                        for (key, value) in meta_buffer.drain() {
+                            batch.push(BatchOperation::Put(Column::Meta, key.to_vec(), value));
+                        }
+                        if let Some(validators) = validators_buffer.take() {
+                            batch.push(BatchOperation::Put(
+                                Column::Validators,
+                                height.to_be_bytes().to_vec(),
+                                validators,
+                            ));
+                            if let Some(old_height) = height.checked_sub(VALIDATOR_SNAPSHOTS_KEPT) {
+                                batch.push(BatchOperation::Delete(
+                                    Column::Validators,
+                                    old_height.to_be_bytes().to_vec(),
+                                ));
+                            }
                         }
 
-                        db_spent
-                            .write(batch)
-                            .unwrap_or_else(|e| error!("failed to write to db {}", e));
+                        // in the same batch as the keys, so the persisted filters never miss any
+                        batch.extend(spent_filter.take_changes());
+                        batch.extend(records_filter.take_changes());
 
-                        // remove all buffer spent from db unspent, i.e. consumed records should only be kept in spent db
-                        let mut batch = WriteBatch::default();
-                        for key in spent_buffer.keys() {
-                            batch.delete(key);
+                        for operation in &batch {
+                            match operation {
+                                BatchOperation::Put(column, key, value) => {
+                                    if let Some(tree) = trees.get_mut(column) {
+                                        tree.insert(key.clone(), value);
+                                    }
+                                }
+                                BatchOperation::Delete(column, key) => {
+                                    if let Some(tree) = trees.get_mut(column) {
+                                        tree.remove(key);
+                                    }
+                                }
+                            }
                         }
-                        spent_buffer.clear();
+                        backend
+                            .write(batch)
+                            .and_then(|_| {
+                                for filter in [&mut spent_filter, &mut records_filter] {
+                                    if filter.is_full() {
+                                        filter.grow(&backend);
+                                        backend.write(filter.take_changes())?;
+                                    }
+                                }
+                                if fsync.syncs_at(height) {
+                                    backend.sync()
+                                } else {
+                                    Ok(())
+                                }
+                            })
+                            .unwrap_or_else(|e| {
+                                error!("failed to write to db {}", e);
+                                // the trees may have entries that weren't written
+                                trees.clear();
+                            });
+                        // queued before the reply, so the queries sent after the commit see it
+                        replica_sender
+                            .send(Query::Refresh)
+                            .unwrap_or_else(|e| error!("{}", e));
+                        reply_to.send(()).unwrap_or_else(|e| error!("{}", e));
                     }
-                    Command::ScanRecords {
+                    Command::ScanPackets {
+                        prefix,
                         from,
                         limit,
                         reply_sender: reply_to,
                     } => {
-                        let iterator_mode = from.as_ref().map_or(IteratorMode::Start, |key| {
-                            IteratorMode::From(key, Direction::Forward)
-                        });
-                        let mut records = vec![];
-                        let mut last_key = None;
-                        for item in db_records.iterator(iterator_mode) {
-                            if limit.map_or(false, |l| records.len() >= l) {
-                                break;
-                            }
-                            if let Ok((key, record)) = item {
-                                records.push((key.to_vec(), record.to_vec()));
-                                last_key = Some(key.to_vec());
-                            }
-                        }
-                        reply_to
-                            .send((records, last_key))
-                            .unwrap_or_else(|e| error!("{}", e));
-                    }
-                    Command::ScanSpentRecords(reply_sender) => {
-                        let spent_records = db_spent
-                            .iterator(IteratorMode::Start)
-                            .filter_map(|s| {
-                                s.map(|(k, _)| {
-                                    SerialNumber::from_str(&String::from_utf8_lossy(&k)).unwrap()
-                                })
-                                .ok()
-                            })
+                        let packets = backend
+                            .iterate(Column::Packets, Some(&from))
+                            .take_while(|(key, _)| key.starts_with(&prefix))
+                            .take(limit)
+                            .map(|(_, packet)| packet)
                             .collect();
-                        reply_sender
-                            .send(spent_records)
-                            .unwrap_or_else(|e| error!("{}", e));
+                        reply_to.send(packets).unwrap_or_else(|e| error!("{}", e));
+                    }
+                    Command::Root(column, reply_sender) => {
+                        let root = trees
+                            .entry(column)
+                            .or_insert_with(|| column_tree(&backend, column))
+                            .root();
+                        reply_sender.send(root).unwrap_or_else(|e| error!("{}", e));
+                    }
+                    Command::Proof(column, key, reply_sender) => {
+                        let proof = trees
+                            .entry(column)
+                            .or_insert_with(|| column_tree(&backend, column))
+                            .proof(&key, |key| {
+                                backend
+                                    .get(column, key)
+                                    .unwrap_or_else(|e| {
+                                        error!("failed to read a proof entry: {e}");
+                                        None
+                                    })
+                                    .unwrap_or_default()
+                            });
+                        reply_sender.send(proof).unwrap_or_else(|e| error!("{}", e));
                     }
                 };
             }
         });
-        Ok(Self { command_sender })
+        Self {
+            command_sender,
+            query_sender,
+        }
     }
 
     /// Saves a new unspent record to the write buffer
@@ -192,6 +642,307 @@ impl RecordStore {
         reply_receiver.recv()?
     }
 
+    /// Saves the memo attached to a new record to the write buffer, to be committed along with the record.
+    #[allow(clippy::redundant_clone)] // commitments are strings on lambdavm and so clippy generates a warning for `.to_string()`
+    pub fn add_memo(&self, commitment: &Commitment, memo: &vm::EncryptedMemo) -> Result<()> {
+        let commitment = commitment.to_string().into_bytes();
+        let memo = bincode::serialize(memo)?;
+        Ok(self
+            .command_sender
+            .send(Command::AddMemo(commitment, memo))?)
+    }
+
+    /// Saves to the write buffer that the record with the given commitment was created by the given program, so it's
+    /// returned by `scan_program_with_heights`. It's committed along with the record.
+    #[allow(clippy::redundant_clone)] // commitments are strings on lambdavm and so clippy generates a warning for `.to_string()`
+    pub fn index_program_record(&self, program_id: &str, commitment: &Commitment) -> Result<()> {
+        let mut key = program_records_prefix(program_id);
+        key.extend(commitment.to_string().into_bytes());
+        Ok(self.command_sender.send(Command::IndexProgramRecord(key))?)
+    }
+
+    /// Saves to the write buffer that the record with the given serial number is owned by the address of the given
+    /// program, so it can only be spent by its transitions. It's committed along with the record.
+    #[allow(clippy::redundant_clone)] // serial numbers are strings on lambdavm and so clippy generates a warning for `.to_string()`
+    pub fn add_program_owned(&self, serial_number: &SerialNumber, program_id: &str) -> Result<()> {
+        let serial_number = serial_number.to_string().into_bytes();
+        Ok(self.command_sender.send(Command::AddProgramOwned(
+            serial_number,
+            program_id.as_bytes().to_vec(),
+        ))?)
+    }
+
+    /// Return the id of the program whose address owns the record with the given serial number, if it's owned by one.
+    #[allow(clippy::redundant_clone)] // serial numbers are strings on lambdavm and so clippy generates a warning for `.to_string()`
+    pub fn owner_program(&self, serial_number: &SerialNumber) -> Result<Option<String>> {
+        let (reply_sender, reply_receiver) = sync_channel(0);
+        let serial_number = serial_number.to_string().into_bytes();
+        self.command_sender
+            .send(Command::OwnerProgram(serial_number, reply_sender))?;
+        Ok(reply_receiver
+            .recv()?
+            .map(|program_id| String::from_utf8_lossy(&program_id).to_string()))
+    }
+
+    /// Saves the encoded validator set to the write buffer, to be committed atomically along with the block records.
+    /// Stage a bridge packet to be committed along with the next block.
+    pub fn stage_packet(&self, packet: &Packet) -> Result<()> {
+        self.command_sender
+            .send(Command::StagePacket(packet.key(), packet.commitment()?))?;
+        Ok(())
+    }
+
+    /// Return up to `limit` committed packets sent to the given chain, starting at the given sequence.
+    pub fn scan_packets(
+        &self,
+        destination_chain: &str,
+        from_sequence: u64,
+        limit: usize,
+    ) -> Result<Vec<Packet>> {
+        let (reply_sender, reply_receiver) = sync_channel(0);
+        self.command_sender.send(Command::ScanPackets {
+            prefix: bridge::packets_prefix(destination_chain),
+            from: bridge::packet_key(destination_chain, from_sequence),
+            limit,
+            reply_sender,
+        })?;
+        reply_receiver
+            .recv()?
+            .iter()
+            .map(|packet| Ok(bincode::deserialize(packet)?))
+            .collect()
+    }
+
+    /// Return the root of the merkle tree of committed bridge packets.
+    pub fn packets_root(&self) -> Result<Hash> {
+        self.root(Column::Packets)
+    }
+
+    /// Return a proof that the packet with the given key was sent or that it wasn't, against the current `packets_root`.
+    pub fn packet_proof(&self, key: Key) -> Result<KeyProof> {
+        self.proof(Column::Packets, key)
+    }
+
+    pub fn stage_validators(&self, validators: Vec<u8>) -> Result<()> {
+        Ok(self
+            .command_sender
+            .send(Command::StageValidators(validators))?)
+    }
+
+    /// Saves the encoded governance state to the write buffer, to be committed along with the block, so it can't get
+    /// out of sync with the records after a crash.
+    pub fn stage_governance(&self, governance: Vec<u8>) -> Result<()> {
+        Ok(self
+            .command_sender
+            .send(Command::StageMeta(GOVERNANCE_KEY, governance))?)
+    }
+
+    /// Return the encoded governance state committed with the last block, if any.
+    pub fn governance_state(&self) -> Result<Option<Vec<u8>>> {
+        self.meta(GOVERNANCE_KEY)
+    }
+
+    /// Saves the encoded bridge state to the write buffer, to be committed along with the block.
+    pub fn stage_bridge(&self, bridge: Vec<u8>) -> Result<()> {
+        Ok(self
+            .command_sender
+            .send(Command::StageMeta(BRIDGE_KEY, bridge))?)
+    }
+
+    /// Return the encoded bridge state committed with the last block, if any.
+    pub fn bridge_state(&self) -> Result<Option<Vec<u8>>> {
+        self.meta(BRIDGE_KEY)
+    }
+
+    /// Saves the changes the program store is about to commit with the block to the write buffer, see
+    /// `ProgramStore::prepare_commit`. The program store commits after this one, so if the node stops in between
+    /// they can be written again on startup instead of leaving the stores a block apart.
+    pub fn stage_program_changes(&self, changes: Vec<u8>) -> Result<()> {
+        Ok(self
+            .command_sender
+            .send(Command::StageMeta(PROGRAM_CHANGES_KEY, changes))?)
+    }
+
+    /// Return the changes of the program store committed with the last block, if any.
+    pub fn program_changes(&self) -> Result<Option<Vec<u8>>> {
+        self.meta(PROGRAM_CHANGES_KEY)
+    }
+
+    fn meta(&self, key: &'static [u8]) -> Result<Option<Vec<u8>>> {
+        let (reply_sender, reply_receiver) = sync_channel(0);
+        self.command_sender
+            .send(Command::GetMeta(key, reply_sender))?;
+        reply_receiver.recv()?
+    }
+
+    /// Saves a transaction to be applied at the given height to the write buffer, reserving the commitments of its
+    /// output records so no other transaction can add them before, see `check_schedulable`.
+    #[allow(clippy::redundant_clone)] // commitments/serial numbers are strings on lambdavm and so clippy generates a warning for `.to_string()`
+    pub fn schedule(
+        &self,
+        height: Height,
+        id: &str,
+        transaction: Vec<u8>,
+        commitments: &[Commitment],
+    ) -> Result<()> {
+        Ok(self.command_sender.send(Command::Schedule(
+            height,
+            id.as_bytes().to_vec(),
+            transaction,
+            commitments
+                .iter()
+                .map(|commitment| commitment.to_string().into_bytes())
+                .collect(),
+        ))?)
+    }
+
+    /// Fail if a transaction with the given output records can't be scheduled, because one of the records exists
+    /// or is the output of an execution that is already scheduled. Otherwise adding the records at the target
+    /// height couldn't fail.
+    #[allow(clippy::redundant_clone)] // commitments/serial numbers are strings on lambdavm and so clippy generates a warning for `.to_string()`
+    pub fn check_schedulable(&self, commitments: &[Commitment]) -> Result<()> {
+        let (reply_sender, reply_receiver) = sync_channel(0);
+        self.command_sender.send(Command::CheckSchedulable(
+            commitments
+                .iter()
+                .map(|commitment| commitment.to_string().into_bytes())
+                .collect(),
+            reply_sender,
+        ))?;
+        reply_receiver.recv()?
+    }
+
+    /// Return the committed transactions scheduled for the given height, sorted by id.
+    /// They are removed from the store on the next commit, so they are returned only once.
+    pub fn take_scheduled(&self, height: Height) -> Result<Vec<Vec<u8>>> {
+        let (reply_sender, reply_receiver) = sync_channel(0);
+        self.command_sender
+            .send(Command::TakeScheduled(height, reply_sender))?;
+        Ok(reply_receiver.recv()?)
+    }
+
+    /// Persist the id of the chain, received at genesis. Unlike other changes, it's written right away.
+    pub fn set_chain_id(&self, chain_id: &str) -> Result<()> {
+        let (reply_sender, reply_receiver) = sync_channel(0);
+        self.command_sender.send(Command::SetChainId(
+            chain_id.as_bytes().to_vec(),
+            reply_sender,
+        ))?;
+        reply_receiver.recv()?
+    }
+
+    /// Return the id of the chain, if it was set. Stores of chains started before it was tracked don't have one.
+    pub fn chain_id(&self) -> Result<Option<String>> {
+        let (reply_sender, reply_receiver) = sync_channel(0);
+        self.command_sender
+            .send(Command::GetChainId(reply_sender))?;
+        Ok(reply_receiver
+            .recv()??
+            .map(|bytes| String::from_utf8_lossy(&bytes).to_string()))
+    }
+
+    /// Return the height of the last block committed to the store, None if it has none or they were committed before
+    /// the store kept it. Since it's written along with the changes of the block, it tells which blocks the state
+    /// has even if the node crashed mid commit, or the machine before the store was synced to disk.
+    pub fn committed_height(&self) -> Result<Option<Height>> {
+        let (reply_sender, reply_receiver) = sync_channel(0);
+        self.command_sender
+            .send(Command::GetCommittedHeight(reply_sender))?;
+        reply_receiver.recv()?
+    }
+
+    /// Count a transaction applied in the block being processed, to be committed along with it.
+    pub fn count_transaction(&self) -> Result<()> {
+        Ok(self.command_sender.send(Command::CountTransaction)?)
+    }
+
+    /// Return the amounts of committed transactions and records. Transactions committed before the store counted
+    /// them aren't included.
+    pub fn counts(&self) -> Result<(u64, u64)> {
+        let (reply_sender, reply_receiver) = sync_channel(0);
+        self.command_sender.send(Command::Counts(reply_sender))?;
+        Ok(reply_receiver.recv()?)
+    }
+
+    /// Return the amount of committed entries of each column, e.g. to tell the size of the indexes. It goes through
+    /// every entry, from the replica so it doesn't hold up the processing of transactions.
+    pub fn count_entries(&self) -> Result<Vec<(Column, u64)>> {
+        let (reply_sender, reply_receiver) = sync_channel(0);
+        self.query_sender.send(Query::CountEntries(reply_sender))?;
+        Ok(reply_receiver.recv()?)
+    }
+
+    /// Saves the encoded events of the block being processed to the write buffer, to be committed along with it.
+    pub fn stage_events(&self, events: Vec<u8>) -> Result<()> {
+        Ok(self.command_sender.send(Command::StageEvents(events))?)
+    }
+
+    /// Return the encoded events committed for the block at the given height, if any.
+    pub fn events(&self, height: Height) -> Result<Option<Vec<u8>>> {
+        let (reply_sender, reply_receiver) = sync_channel(0);
+        self.query_sender
+            .send(Query::GetEvents(height, reply_sender))?;
+        Ok(reply_receiver.recv()?)
+    }
+
+    /// Saves the encoded rewards distribution of the block being processed to the write buffer, to be committed along with it.
+    pub fn stage_rewards(&self, rewards: Vec<u8>) -> Result<()> {
+        Ok(self.command_sender.send(Command::StageRewards(rewards))?)
+    }
+
+    /// Remove the events and rewards of the blocks below the given height along with the next commit.
+    /// Used by pruned nodes, see `NodeRole`. The records and spent serial numbers are never pruned, since
+    /// they are the state transactions are validated against and are committed in the app hash.
+    pub fn stage_prune(&self, below: Height) -> Result<()> {
+        Ok(self.command_sender.send(Command::StagePrune(below))?)
+    }
+
+    /// Return the encoded rewards distributions committed for the blocks from height `from` to `to`, both included,
+    /// sorted by height. Blocks without rewards are skipped.
+    pub fn rewards(&self, from: Height, to: Height) -> Result<Vec<Vec<u8>>> {
+        let (reply_sender, reply_receiver) = sync_channel(0);
+        self.query_sender.send(Query::ScanRewards {
+            from,
+            to,
+            reply_sender,
+        })?;
+        Ok(reply_receiver.recv()?)
+    }
+
+    /// Return the committed validator set snapshots along with the height they were committed at, newest first.
+    pub fn validator_snapshots(&self) -> Result<Vec<(Height, Vec<u8>)>> {
+        let (reply_sender, reply_receiver) = sync_channel(0);
+        self.command_sender
+            .send(Command::ValidatorSnapshots(reply_sender))?;
+        Ok(reply_receiver.recv()?)
+    }
+
+    /// Return the committed memos attached to the records with the given commitments, if any.
+    #[allow(clippy::redundant_clone)] // commitments are strings on lambdavm and so clippy generates a warning for `.to_string()`
+    pub fn memos(
+        &self,
+        commitments: &[Commitment],
+    ) -> Result<Vec<(Commitment, vm::EncryptedMemo)>> {
+        let (reply_sender, reply_receiver) = sync_channel(0);
+        let keys = commitments
+            .iter()
+            .map(|commitment| commitment.to_string().into_bytes())
+            .collect();
+        self.query_sender
+            .send(Query::GetMemos(keys, reply_sender))?;
+
+        reply_receiver
+            .recv()?
+            .into_iter()
+            .map(|(commitment, memo)| {
+                Ok((
+                    Commitment::from_str(&String::from_utf8_lossy(&commitment))?,
+                    bincode::deserialize(&memo)?,
+                ))
+            })
+            .collect()
+    }
+
     /// Marks a record as spent in the write buffer.
     /// Fails if the record is not found or was already spent.
     pub fn spend(&self, serial_number: &SerialNumber) -> Result<()> {
@@ -204,8 +955,13 @@ impl RecordStore {
     }
 
     /// Commit write buffer changes to persistent storage and empty the buffer.
-    pub fn commit(&self) -> Result<()> {
-        Ok(self.command_sender.send(Command::Commit)?)
+    /// The given height is stored along the committed records and serial numbers.
+    /// Returns once the changes are written, so the queries sent afterwards see them.
+    pub fn commit(&self, height: Height) -> Result<()> {
+        let (reply_sender, reply_receiver) = sync_channel(0);
+        self.command_sender
+            .send(Command::Commit(height, reply_sender))?;
+        Ok(reply_receiver.recv()?)
     }
 
     /// Returns whether a record by the given serial_number is known and not spent
@@ -218,13 +974,58 @@ impl RecordStore {
         Ok(reply_receiver.recv()?)
     }
 
-    /// Return up to `limit` record ciphertexts
+    /// Return up to `limit` record ciphertexts, along with the height they were committed at.
     #[allow(clippy::redundant_clone)] // commitments/serial numbers are strings on lambdavm and so clippy generates a warning for `.to_string()`
-    pub fn scan(&self, from: Option<SerialNumber>, limit: Option<usize>) -> Result<ScanResult> {
+    pub fn scan_with_heights(
+        &self,
+        from: Option<SerialNumber>,
+        limit: Option<usize>,
+    ) -> Result<HeightScanResult> {
         let from = from.map(|commitment| commitment.to_string().into_bytes());
         let (reply_sender, reply_receiver) = sync_channel(0);
 
-        self.command_sender.send(Command::ScanRecords {
+        self.query_sender.send(Query::ScanRecords {
+            from,
+            limit,
+            reply_sender,
+        })?;
+
+        let (results, last_key) = reply_receiver.recv()?;
+        let last_key = last_key
+            .map(|commitment| Commitment::from_str(&String::from_utf8_lossy(&commitment)).unwrap());
+        let results = results
+            .iter()
+            .map(|(commitment, record, height)| {
+                let commitment =
+                    Commitment::from_str(&String::from_utf8_lossy(commitment)).unwrap();
+
+                let record = EncryptedRecord::from_str(&String::from_utf8_lossy(record)).unwrap();
+
+                (commitment, record, *height)
+            })
+            .collect();
+        Ok((results, last_key))
+    }
+
+    /// Same as `scan_with_heights`, only for the records created by the given program. Only the matching records are
+    /// read, through the program index.
+    #[allow(clippy::redundant_clone)] // commitments/serial numbers are strings on lambdavm and so clippy generates a warning for `.to_string()`
+    pub fn scan_program_with_heights(
+        &self,
+        program_id: &str,
+        from: Option<Commitment>,
+        limit: Option<usize>,
+    ) -> Result<HeightScanResult> {
+        let prefix = program_records_prefix(program_id);
+        let from = from.map(|commitment| {
+            let mut key = prefix.clone();
+            key.extend(commitment.to_string().into_bytes());
+            key
+        });
+        let (reply_sender, reply_receiver) = sync_channel(0);
+
+        self.query_sender.send(Query::ScanProgramRecords {
+            prefix,
             from,
             limit,
             reply_sender,
@@ -235,34 +1036,251 @@ impl RecordStore {
             .map(|commitment| Commitment::from_str(&String::from_utf8_lossy(&commitment)).unwrap());
         let results = results
             .iter()
-            .map(|(commitment, record)| {
+            .map(|(commitment, record, height)| {
                 let commitment =
                     Commitment::from_str(&String::from_utf8_lossy(commitment)).unwrap();
 
                 let record = EncryptedRecord::from_str(&String::from_utf8_lossy(record)).unwrap();
 
-                (commitment, record)
+                (commitment, record, *height)
             })
             .collect();
         Ok((results, last_key))
     }
 
     // TODO: implement way of limiting response size/count or optimization for better scaling
-    /// Return all serial numbers
-    pub fn scan_spent(&self) -> Result<HashSet<SerialNumber>> {
+    /// Return all serial numbers along with the height they were spent at, if known.
+    pub fn scan_spent_with_heights(&self) -> Result<HashMap<SerialNumber, Option<Height>>> {
         let (reply_sender, reply_receiver) = sync_channel(0);
 
-        self.command_sender
-            .send(Command::ScanSpentRecords(reply_sender))?;
+        self.query_sender
+            .send(Query::ScanSpentRecords(reply_sender))?;
 
         let results = reply_receiver.recv()?;
         Ok(results)
     }
+
+    /// Return the root of the merkle tree of committed spent serial numbers and their spending heights.
+    pub fn spent_root(&self) -> Result<Hash> {
+        self.root(Column::Spent)
+    }
+
+    /// Return a proof that the given serial number was spent (and at what height) or that it wasn't,
+    /// against the current `spent_root`. Serial numbers that are spent but not yet committed are reported as not spent.
+    #[allow(clippy::redundant_clone)] // commitments/serial numbers are strings on lambdavm and so clippy generates a warning for `.to_string()`
+    pub fn spent_proof(&self, serial_number: &SerialNumber) -> Result<KeyProof> {
+        self.proof(Column::Spent, serial_number.to_string().into_bytes())
+    }
+
+    /// Return the root of the merkle tree of committed record ciphertexts by commitment.
+    pub fn records_root(&self) -> Result<Hash> {
+        self.root(Column::Records)
+    }
+
+    /// Return the root of the merkle tree of the committed scheduled transactions, see `schedule`.
+    pub fn scheduled_root(&self) -> Result<Hash> {
+        self.root(Column::Scheduled)
+    }
+
+    /// Return the root of the merkle tree of the committed serial numbers of program owned records, see
+    /// `add_program_owned`.
+    pub fn program_owned_root(&self) -> Result<Hash> {
+        self.root(Column::ProgramOwned)
+    }
+
+    /// Return a proof that the record with the given commitment exists (along with its ciphertext)
+    /// or that it doesn't, against the current `records_root`.
+    #[allow(clippy::redundant_clone)] // commitments/serial numbers are strings on lambdavm and so clippy generates a warning for `.to_string()`
+    pub fn record_proof(&self, commitment: &Commitment) -> Result<KeyProof> {
+        self.proof(Column::Records, commitment.to_string().into_bytes())
+    }
+
+    /// Return the ciphertext of the committed record with the given commitment, if it exists.
+    pub fn get(&self, commitment: &Commitment) -> Result<Option<vm::EncryptedRecord>> {
+        match self.record_proof(commitment)? {
+            KeyProof::Present(entry) => {
+                let ciphertext = String::from_utf8(entry.value)?;
+                Ok(Some(vm::EncryptedRecord::from_str(&ciphertext)?))
+            }
+            KeyProof::Absent { .. } => Ok(None),
+        }
+    }
+
+    fn root(&self, column: Column) -> Result<Hash> {
+        let (reply_sender, reply_receiver) = sync_channel(0);
+        self.command_sender
+            .send(Command::Root(column, reply_sender))?;
+        Ok(reply_receiver.recv()?)
+    }
+
+    fn proof(&self, column: Column, key: Key) -> Result<KeyProof> {
+        let (reply_sender, reply_receiver) = sync_channel(0);
+        self.command_sender
+            .send(Command::Proof(column, key, reply_sender))?;
+        Ok(reply_receiver.recv()?)
+    }
 }
 
-/// TODO explain the need for this
-fn key_exists_or_fails(db: &rocksdb::DB, key: &Key) -> bool {
-    !matches!(db.get(key), Ok(None))
+/// Start serving the queries from the given replica on a new thread, returning the channel to send them to.
+/// The replica is only refreshed when the record store commits, so a query never sees a block half written.
+fn serve_queries(mut replica: Box<dyn KvReplica>) -> Sender<Query> {
+    let (query_sender, query_receiver): (Sender<Query>, Receiver<Query>) = channel();
+    thread::spawn(move || {
+        while let Ok(query) = query_receiver.recv() {
+            match query {
+                Query::GetEvents(height, reply_to) => {
+                    let events = replica
+                        .get(Column::Events, &height.to_be_bytes())
+                        .unwrap_or(None);
+                    reply_to.send(events).unwrap_or_else(|e| error!("{}", e));
+                }
+                Query::ScanRewards {
+                    from,
+                    to,
+                    reply_sender: reply_to,
+                } => {
+                    // heights are big endian, so the keys are sorted by height
+                    let rewards = replica
+                        .iterate(Column::Rewards, Some(&from.to_be_bytes()))
+                        .take_while(|(key, _)| key.as_slice() <= to.to_be_bytes().as_slice())
+                        .map(|(_, rewards)| rewards)
+                        .collect();
+                    reply_to.send(rewards).unwrap_or_else(|e| error!("{}", e));
+                }
+                Query::GetMemos(commitments, reply_to) => {
+                    let memos = commitments
+                        .into_iter()
+                        .filter_map(|commitment| {
+                            replica
+                                .get(Column::Memos, &commitment)
+                                .unwrap_or(None)
+                                .map(|memo| (commitment, memo))
+                        })
+                        .collect();
+                    reply_to.send(memos).unwrap_or_else(|e| error!("{}", e));
+                }
+                Query::ScanRecords {
+                    from,
+                    limit,
+                    reply_sender: reply_to,
+                } => {
+                    let mut records = vec![];
+                    let mut last_key = None;
+                    for (key, record) in replica.iterate(Column::Records, from.as_deref()) {
+                        if limit.map_or(false, |l| records.len() >= l) {
+                            break;
+                        }
+                        let height = replica.get(Column::Heights, &key).unwrap_or(None);
+                        records.push((key.clone(), record, parse_height(height)));
+                        last_key = Some(key);
+                    }
+                    reply_to
+                        .send((records, last_key))
+                        .unwrap_or_else(|e| error!("{}", e));
+                }
+                Query::ScanProgramRecords {
+                    prefix,
+                    from,
+                    limit,
+                    reply_sender: reply_to,
+                } => {
+                    let start = from.unwrap_or_else(|| prefix.clone());
+                    let mut records = vec![];
+                    let mut last_key = None;
+                    for (key, _) in replica
+                        .iterate(Column::ProgramRecords, Some(&start))
+                        .take_while(|(key, _)| key.starts_with(&prefix))
+                    {
+                        if limit.map_or(false, |l| records.len() >= l) {
+                            break;
+                        }
+                        let commitment = key[prefix.len()..].to_vec();
+                        if let Ok(Some(record)) = replica.get(Column::Records, &commitment) {
+                            let height = replica.get(Column::Heights, &commitment).unwrap_or(None);
+                            records.push((commitment.clone(), record, parse_height(height)));
+                            last_key = Some(commitment);
+                        }
+                    }
+                    reply_to
+                        .send((records, last_key))
+                        .unwrap_or_else(|e| error!("{}", e));
+                }
+                Query::ScanSpentRecords(reply_sender) => {
+                    let spent_records = replica
+                        .iterate(Column::Spent, None)
+                        .map(|(k, v)| {
+                            (
+                                SerialNumber::from_str(&String::from_utf8_lossy(&k)).unwrap(),
+                                parse_height(Some(v)),
+                            )
+                        })
+                        .collect();
+                    reply_sender
+                        .send(spent_records)
+                        .unwrap_or_else(|e| error!("{}", e));
+                }
+                Query::CountEntries(reply_sender) => {
+                    let counts = Column::ALL
+                        .iter()
+                        .map(|column| (*column, replica.iterate(*column, None).count() as u64))
+                        .collect();
+                    reply_sender
+                        .send(counts)
+                        .unwrap_or_else(|e| error!("{}", e));
+                }
+                Query::Refresh => replica.refresh(),
+            }
+        }
+    });
+    query_sender
+}
+
+/// Load the merkle tree of the committed entries of the column. Each column is only loaded once, commits then
+/// update the loaded trees with the entries they write.
+fn column_tree(backend: &impl KvBackend, column: Column) -> MerkleSet {
+    MerkleSet::new(backend.iterate(column, None))
+}
+
+/// Prefix of the keys of the records of a program in the `ProgramRecords` column. Program ids can't contain a slash,
+/// so the prefix of a program never matches the keys of another one.
+fn program_records_prefix(program_id: &str) -> Key {
+    format!("{program_id}/").into_bytes()
+}
+
+/// Key of the scheduled transaction that reserved the given output commitment, if any.
+fn reservation(
+    backend: &impl KvBackend,
+    reserved_buffer: &HashMap<Key, Key>,
+    commitment: &Key,
+) -> Option<Key> {
+    reserved_buffer.get(commitment).cloned().or_else(|| {
+        backend
+            .get(Column::ScheduledOutputs, commitment)
+            .unwrap_or_else(|e| {
+                error!("failed to read the reservation of a scheduled output: {e}");
+                None
+            })
+    })
+}
+
+/// Key of a scheduled transaction: its target height as big endian bytes, so the column is sorted by height,
+/// followed by the transaction id.
+fn scheduled_key(height: Height, id: &[u8]) -> Key {
+    [height.to_be_bytes().as_slice(), id].concat()
+}
+
+/// Decode a height stored as big endian bytes. Entries written before heights were tracked
+/// don't have a valid encoding, in which case None is returned.
+fn parse_height(bytes: Option<Vec<u8>>) -> Option<Height> {
+    bytes
+        .and_then(|bytes| bytes.try_into().ok())
+        .map(Height::from_be_bytes)
+}
+
+/// Read a count of the `Meta` column, None if it's missing or malformed.
+fn read_count(backend: &impl KvBackend, key: &[u8]) -> Option<u64> {
+    let bytes = backend.get(Column::Meta, key).ok()??;
+    Some(u64::from_be_bytes(bytes.try_into().ok()?))
 }
 
 #[cfg(test)]
@@ -292,15 +1310,15 @@ mod tests {
 
     #[test]
     fn add_and_spend_record() {
-        let store = RecordStore::new(&db_path("records1")).unwrap();
+        let store = RecordStore::new(&db_path("records1"), FsyncPolicy::Always).unwrap();
         let (record, commitment, serial_number) = new_record();
         store.add(commitment, record).unwrap();
         assert!(store.is_unspent(&serial_number).unwrap());
-        store.commit().unwrap();
+        store.commit(1).unwrap();
         assert!(store.is_unspent(&serial_number).unwrap());
         store.spend(&serial_number).unwrap();
         assert!(!store.is_unspent(&serial_number).unwrap());
-        store.commit().unwrap();
+        store.commit(1).unwrap();
         assert!(!store.is_unspent(&serial_number).unwrap());
 
         let msg = store
@@ -314,10 +1332,60 @@ mod tests {
         std::mem::forget(store);
     }
 
+    #[test]
+    #[cfg(feature = "snarkvm_backend")]
+    fn memos_are_committed_with_records() {
+        let store = RecordStore::new_in_memory();
+        let (record, commitment, _) = new_record();
+        let private_key = PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let view_key = vm::ViewKey::try_from(&private_key).unwrap();
+        let owner = vm::Address::try_from(&view_key).unwrap();
+        let memo = vm::encrypt_memo(&owner, "invoice 42").unwrap();
+
+        store.add(commitment, record).unwrap();
+        store.add_memo(&commitment, &memo).unwrap();
+        assert!(store.memos(&[commitment]).unwrap().is_empty());
+
+        store.commit(1).unwrap();
+        let memos = store.memos(&[commitment]).unwrap();
+        assert_eq!(vec![(commitment, memo)], memos);
+        assert_eq!(
+            "invoice 42",
+            vm::decrypt_memo(&view_key, &memos[0].1).unwrap()
+        );
+    }
+
+    #[test]
+    #[allow(clippy::redundant_clone)]
+    fn spent_proofs() {
+        let store = RecordStore::new_in_memory();
+        let (record, commitment, serial_number) = new_record();
+        store.add(commitment, record).unwrap();
+        store.commit(1).unwrap();
+
+        let key = serial_number.to_string().into_bytes();
+        let root = store.spent_root().unwrap();
+        let proof = store.spent_proof(&serial_number).unwrap();
+        assert_eq!(None, proof.verify(&key, &root).unwrap());
+
+        store.spend(&serial_number).unwrap();
+        store.commit(2).unwrap();
+
+        let new_root = store.spent_root().unwrap();
+        assert_ne!(root, new_root);
+        let proof = store.spent_proof(&serial_number).unwrap();
+        assert_eq!(
+            Some(2u64.to_be_bytes().to_vec()),
+            proof.verify(&key, &new_root).unwrap()
+        );
+        // the proof is only valid for the current state
+        assert!(proof.verify(&key, &root).is_err());
+    }
+
     #[test]
     #[allow(clippy::clone_on_copy)]
     fn no_double_add_record() {
-        let store = RecordStore::new(&db_path("records2")).unwrap();
+        let store = RecordStore::new(&db_path("records2"), FsyncPolicy::Always).unwrap();
 
         let (record, commitment, _) = new_record();
         store.add(commitment.clone(), record.clone()).unwrap();
@@ -327,11 +1395,11 @@ mod tests {
             .root_cause()
             .to_string();
         assert_eq!(format!("record {commitment} already exists"), msg);
-        store.commit().unwrap();
+        store.commit(1).unwrap();
 
         let (record, commitment, _) = new_record();
         store.add(commitment.clone(), record.clone()).unwrap();
-        store.commit().unwrap();
+        store.commit(1).unwrap();
         let msg = store
             .add(commitment.clone(), record)
             .unwrap_err()
@@ -345,14 +1413,14 @@ mod tests {
 
     #[test]
     fn spend_before_commit() {
-        let store = RecordStore::new(&db_path("records3")).unwrap();
+        let store = RecordStore::new(&db_path("records3"), FsyncPolicy::Always).unwrap();
 
         let (record, commitment, serial_number) = new_record();
         store.add(commitment, record).unwrap();
         assert!(store.is_unspent(&serial_number).unwrap());
         store.spend(&serial_number).unwrap();
         assert!(!store.is_unspent(&serial_number).unwrap());
-        store.commit().unwrap();
+        store.commit(1).unwrap();
         assert!(!store.is_unspent(&serial_number).unwrap());
 
         // FIXME patching rocksdb weird behavior
@@ -361,15 +1429,15 @@ mod tests {
 
     #[test]
     fn no_double_spend_record() {
-        let store = RecordStore::new(&db_path("records4")).unwrap();
+        let store = RecordStore::new(&db_path("records4"), FsyncPolicy::Always).unwrap();
 
         // add, commit, spend, commit, fail spend
         let (record, commitment, serial_number) = new_record();
         store.add(commitment, record).unwrap();
-        store.commit().unwrap();
+        store.commit(1).unwrap();
         assert!(store.is_unspent(&serial_number).unwrap());
         store.spend(&serial_number).unwrap();
-        store.commit().unwrap();
+        store.commit(1).unwrap();
         assert!(!store.is_unspent(&serial_number).unwrap());
         let msg = store
             .spend(&serial_number)
@@ -381,7 +1449,7 @@ mod tests {
         // add, commit, spend, fail spend, commit, fail spend
         let (record, commitment, serial_number) = new_record();
         store.add(commitment, record).unwrap();
-        store.commit().unwrap();
+        store.commit(1).unwrap();
         assert!(store.is_unspent(&serial_number).unwrap());
         store.spend(&serial_number).unwrap();
         let msg = store
@@ -390,7 +1458,7 @@ mod tests {
             .root_cause()
             .to_string();
         assert_eq!("record already spent", msg);
-        store.commit().unwrap();
+        store.commit(1).unwrap();
         assert!(!store.is_unspent(&serial_number).unwrap());
         let msg = store
             .spend(&serial_number)
@@ -409,13 +1477,268 @@ mod tests {
             .root_cause()
             .to_string();
         assert_eq!("record already spent", msg);
-        store.commit().unwrap();
+        store.commit(1).unwrap();
         assert!(!store.is_unspent(&serial_number).unwrap());
 
         // FIXME patching rocksdb weird behavior
         std::mem::forget(store);
     }
 
+    #[test]
+    #[allow(clippy::clone_on_copy)]
+    fn track_heights() {
+        let store = RecordStore::new(&db_path("records5"), FsyncPolicy::Always).unwrap();
+
+        let (record, commitment, serial_number) = new_record();
+        store.add(commitment.clone(), record).unwrap();
+        store.commit(5).unwrap();
+
+        let (records, _) = store.scan_with_heights(None, None).unwrap();
+        let (_, _, height) = records
+            .into_iter()
+            .find(|(stored, _, _)| *stored == commitment)
+            .unwrap();
+        assert_eq!(Some(5), height);
+
+        store.spend(&serial_number).unwrap();
+        store.commit(7).unwrap();
+        let spent = store.scan_spent_with_heights().unwrap();
+        assert_eq!(Some(&Some(7)), spent.get(&serial_number));
+
+        // FIXME patching rocksdb weird behavior
+        std::mem::forget(store);
+    }
+
+    #[test]
+    fn in_memory_store() {
+        let store = RecordStore::new_in_memory();
+
+        let (record, commitment, serial_number) = new_record();
+        store.add(commitment, record).unwrap();
+        store.commit(1).unwrap();
+        assert_eq!(1, store.scan_with_heights(None, None).unwrap().0.len());
+
+        store.spend(&serial_number).unwrap();
+        store.commit(2).unwrap();
+        assert!(!store.is_unspent(&serial_number).unwrap());
+        assert!(store
+            .scan_spent_with_heights()
+            .unwrap()
+            .contains_key(&serial_number));
+    }
+
+    #[test]
+    #[allow(clippy::clone_on_copy)]
+    fn filters_are_built_from_the_store() {
+        // spent before the store kept filters
+        let (_, _, serial_number) = new_record();
+        let mut backend = MemoryBackend::default();
+        backend
+            .write(vec![BatchOperation::Put(
+                Column::Spent,
+                serial_number.to_string().into_bytes(),
+                Height::to_be_bytes(1).to_vec(),
+            )])
+            .unwrap();
+        let store = RecordStore::with_backend(backend, FsyncPolicy::default());
+        assert!(!store.is_unspent(&serial_number).unwrap());
+        assert!(store.spend(&serial_number).is_err());
+
+        let (record, commitment, serial_number) = new_record();
+        assert!(store.is_unspent(&serial_number).unwrap());
+        store.add(commitment.clone(), record.clone()).unwrap();
+        store.spend(&serial_number).unwrap();
+        store.commit(2).unwrap();
+        assert!(store.add(commitment, record).is_err());
+        assert!(!store.is_unspent(&serial_number).unwrap());
+    }
+
+    #[test]
+    fn pruned_history() {
+        let store = RecordStore::new_in_memory();
+        let (record, commitment, serial_number) = new_record();
+        store.add(commitment, record).unwrap();
+        for height in 1..=5 {
+            store.stage_events(vec![height as u8]).unwrap();
+            store.stage_rewards(vec![height as u8]).unwrap();
+            if height == 3 {
+                store.spend(&serial_number).unwrap();
+            }
+            if height == 5 {
+                store.stage_prune(4).unwrap();
+            }
+            store.commit(height).unwrap();
+        }
+
+        assert_eq!(None, store.events(3).unwrap());
+        assert_eq!(Some(vec![4]), store.events(4).unwrap());
+        assert_eq!(vec![vec![4], vec![5]], store.rewards(1, 5).unwrap());
+        // the state is kept
+        assert_eq!(1, store.scan_with_heights(None, None).unwrap().0.len());
+        assert!(!store.is_unspent(&serial_number).unwrap());
+    }
+
+    #[test]
+    #[allow(clippy::redundant_clone)]
+    fn records_by_program() {
+        let store = RecordStore::new_in_memory();
+        let mut token_records = Vec::new();
+        for _ in 0..3 {
+            let (record, commitment, _) = new_record();
+            store.add(commitment.clone(), record).unwrap();
+            store
+                .index_program_record("token.aleo", &commitment)
+                .unwrap();
+            token_records.push(commitment);
+        }
+        let (record, commitment, _) = new_record();
+        store.add(commitment.clone(), record).unwrap();
+        store
+            .index_program_record("token.aleo2", &commitment)
+            .unwrap();
+
+        // the index is committed along with the records
+        assert!(store
+            .scan_program_with_heights("token.aleo", None, None)
+            .unwrap()
+            .0
+            .is_empty());
+        store.commit(3).unwrap();
+
+        let (records, last) = store
+            .scan_program_with_heights("token.aleo", None, Some(2))
+            .unwrap();
+        assert_eq!(2, records.len());
+        assert!(records.iter().all(|(_, _, height)| *height == Some(3)));
+        let (rest, _) = store
+            .scan_program_with_heights("token.aleo", last, None)
+            .unwrap();
+        // the scan starts at the last record of the previous page
+        assert_eq!(2, rest.len());
+
+        let mut scanned: Vec<_> = records
+            .into_iter()
+            .chain(rest.into_iter().skip(1))
+            .map(|(commitment, _, _)| commitment.to_string())
+            .collect();
+        let mut expected: Vec<_> = token_records.iter().map(|c| c.to_string()).collect();
+        scanned.sort();
+        expected.sort();
+        assert_eq!(expected, scanned);
+        assert!(store
+            .scan_program_with_heights("credits.aleo", None, None)
+            .unwrap()
+            .0
+            .is_empty());
+    }
+
+    #[test]
+    #[allow(clippy::clone_on_copy)]
+    fn packets_are_committed_and_proven() {
+        let store = RecordStore::new_in_memory();
+        let private_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let receiver =
+            vm::Address::try_from(&vm::ViewKey::try_from(&private_key).unwrap()).unwrap();
+        let packets: Vec<Packet> = (0..3)
+            .map(|sequence| Packet {
+                source_chain: "chain-a".to_string(),
+                destination_chain: if sequence < 2 { "chain-b" } else { "chain-c" }.to_string(),
+                sequence,
+                receiver: receiver.clone(),
+                amount: 10,
+            })
+            .collect();
+        let empty_root = store.packets_root().unwrap();
+        for packet in &packets {
+            store.stage_packet(packet).unwrap();
+        }
+        assert!(store.scan_packets("chain-b", 0, 10).unwrap().is_empty());
+        store.commit(1).unwrap();
+
+        assert_eq!(packets[..2], store.scan_packets("chain-b", 0, 10).unwrap());
+        assert_eq!(packets[1..2], store.scan_packets("chain-b", 1, 10).unwrap());
+        assert_eq!(packets[..1], store.scan_packets("chain-b", 0, 1).unwrap());
+        assert_eq!(packets[2..], store.scan_packets("chain-c", 0, 10).unwrap());
+
+        let root = store.packets_root().unwrap();
+        assert_ne!(empty_root, root);
+        match store.packet_proof(packets[0].key()).unwrap() {
+            KeyProof::Present(entry) => assert_eq!(packets[0].commitment().unwrap(), entry.value),
+            KeyProof::Absent { .. } => panic!("packet should be committed"),
+        }
+    }
+
+    #[test]
+    fn scheduled_transactions() {
+        let store = RecordStore::new_in_memory();
+        store.schedule(5, "tx2", b"second".to_vec(), &[]).unwrap();
+        store.schedule(5, "tx1", b"first".to_vec(), &[]).unwrap();
+        store.schedule(6, "tx3", b"third".to_vec(), &[]).unwrap();
+
+        // nothing is scheduled until committed
+        assert!(store.take_scheduled(5).unwrap().is_empty());
+        store.commit(1).unwrap();
+
+        assert_eq!(
+            vec![b"first".to_vec(), b"second".to_vec()],
+            store.take_scheduled(5).unwrap()
+        );
+        assert!(store.take_scheduled(5).unwrap().is_empty());
+        store.commit(2).unwrap();
+        assert!(store.take_scheduled(5).unwrap().is_empty());
+        assert_eq!(vec![b"third".to_vec()], store.take_scheduled(6).unwrap());
+    }
+
+    #[test]
+    #[allow(clippy::clone_on_copy)]
+    fn scheduled_outputs_are_reserved() {
+        let store = RecordStore::new_in_memory();
+        let (record, commitment, _) = new_record();
+        let empty_root = store.scheduled_root().unwrap();
+        store.check_schedulable(&[commitment.clone()]).unwrap();
+        store
+            .schedule(3, "tx", b"scheduled".to_vec(), &[commitment.clone()])
+            .unwrap();
+
+        // the output can't be scheduled twice nor added by another transaction
+        assert!(store.check_schedulable(&[commitment.clone()]).is_err());
+        store.commit(1).unwrap();
+        assert_ne!(empty_root, store.scheduled_root().unwrap());
+        assert!(store.check_schedulable(&[commitment.clone()]).is_err());
+        assert!(store.add(commitment.clone(), record.clone()).is_err());
+        store.commit(2).unwrap();
+
+        // until its transaction is taken at the target height
+        assert_eq!(1, store.take_scheduled(3).unwrap().len());
+        store.add(commitment.clone(), record).unwrap();
+        store.commit(3).unwrap();
+        assert!(store.get(&commitment).unwrap().is_some());
+        assert!(store.check_schedulable(&[commitment]).is_err());
+        assert_eq!(empty_root, store.scheduled_root().unwrap());
+    }
+
+    #[test]
+    fn transactions_and_records_are_counted() {
+        // added before the store counted the records
+        let mut backend = MemoryBackend::default();
+        backend
+            .write(vec![BatchOperation::Put(
+                Column::Records,
+                b"commitment".to_vec(),
+                b"record".to_vec(),
+            )])
+            .unwrap();
+        let store = RecordStore::with_backend(backend, FsyncPolicy::default());
+        assert_eq!((0, 1), store.counts().unwrap());
+
+        let (record, commitment, _) = new_record();
+        store.add(commitment, record).unwrap();
+        store.count_transaction().unwrap();
+        assert_eq!((0, 1), store.counts().unwrap());
+        store.commit(1).unwrap();
+        assert_eq!((1, 2), store.counts().unwrap());
+    }
+
     // TODO: (check if it's possible) make a test for validating behavior related to spending a non-existant record
 
     #[cfg(feature = "lambdavm_backend")]
@@ -0,0 +1,241 @@
+//! Helpers to drive the ABCI application hooks in tests, without running tendermint nor touching the disk.
+//! A `TestChain` plays the role of the consensus engine: it initializes the chain from a genesis state
+//! and produces blocks out of lists of transactions, so program authors can check how their deployments
+//! and executions affect the application state.
+
+use crate::application::SnarkVMApp;
+use anyhow::{bail, Result};
+use lib::query::{AbciQuery, ProgramResponse, QueryResponse, SpentResponse};
+use lib::transaction::Transaction;
+use lib::validator::{GenesisState, Validator};
+use lib::vm;
+use serde::de::DeserializeOwned;
+use std::collections::HashSet;
+use tendermint_abci::Application;
+use tendermint_proto::abci;
+
+/// Tendermint public key used for the validator of chains created with `TestChain::with_account`.
+const TEST_VALIDATOR_KEY: &str = "vM+mkdPMvplfxO7wM57z4FXy0TlBC2Onb+MaqcXE8ig=";
+
+/// Seconds between the blocks produced by a `TestChain`, whose times count from the unix epoch.
+pub const BLOCK_SECONDS: u64 = 5;
+
+pub struct TestChain {
+    app: SnarkVMApp,
+    validators: Vec<Validator>,
+}
+
+impl TestChain {
+    /// Start an in-memory chain from the given genesis state. At least one validator is required,
+    /// since the block rewards are assigned to the proposer.
+    pub fn new(genesis: GenesisState) -> Self {
+        Self::with_app(SnarkVMApp::new_in_memory(), genesis)
+    }
+
+    /// Start a chain from the given genesis state on an application created by the test, e.g. with a node config.
+    pub fn with_app(app: SnarkVMApp, genesis: GenesisState) -> Self {
+        assert!(
+            !genesis.validators.is_empty(),
+            "the test chain needs at least one validator to propose blocks"
+        );
+
+        app.init_chain(abci::RequestInitChain {
+            app_state_bytes: serde_json::to_vec(&genesis).unwrap(),
+            ..Default::default()
+        });
+
+        Self {
+            app,
+            validators: genesis.validators,
+        }
+    }
+
+    /// Start an in-memory chain where the given address owns a credits record with the given amount of gates
+    /// and runs the only validator.
+    pub fn with_account(address: &vm::Address, gates: u64) -> Self {
+        Self::new(Self::account_genesis(address, gates))
+    }
+
+    /// The genesis state of the chains created with `TestChain::with_account`.
+    pub fn account_genesis(address: &vm::Address, gates: u64) -> GenesisState {
+        let record = vm::mint_record("credits.aleo", "credits", address, gates, 0).unwrap();
+        let validator = Validator::from_str(TEST_VALIDATOR_KEY, &address.to_string(), 1).unwrap();
+
+        GenesisState {
+            records: vec![record],
+            validators: vec![validator],
+            default_reward_address: None,
+            vesting: Vec::new(),
+            bridge: Vec::new(),
+            min_self_stake: 0,
+            max_validators: 0,
+            community_pool_percentage: 0,
+        }
+    }
+
+    /// The application being driven by this chain, to call its hooks directly.
+    pub fn app(&self) -> &SnarkVMApp {
+        &self.app
+    }
+
+    /// Last committed block height.
+    pub fn height(&self) -> i64 {
+        self.app.info(Default::default()).last_block_height
+    }
+
+    /// Run the mempool validations on the given transaction.
+    pub fn check(&self, transaction: &Transaction) -> abci::ResponseCheckTx {
+        self.app.check_tx(abci::RequestCheckTx {
+            tx: bincode::serialize(transaction).unwrap(),
+            r#type: 0,
+        })
+    }
+
+    /// Produce and commit a new block with the given transactions, running the begin_block, deliver_tx,
+    /// end_block and commit hooks in order. Every validator votes, the proposer rotates in a round robin fashion
+    /// and blocks are `BLOCK_SECONDS` apart.
+    /// Returns the deliver_tx response for each of the transactions.
+    pub fn produce_block(&self, transactions: &[Transaction]) -> Vec<abci::ResponseDeliverTx> {
+        let height = self.height() + 1;
+        let proposer = &self.validators[height as usize % self.validators.len()];
+        let votes = self
+            .validators
+            .iter()
+            .map(|validator| abci::VoteInfo {
+                validator: Some(abci::Validator {
+                    address: validator.address(),
+                    power: validator.voting_power as i64,
+                }),
+                signed_last_block: true,
+            })
+            .collect();
+
+        self.app.begin_block(abci::RequestBeginBlock {
+            header: Some(tendermint_proto::types::Header {
+                height,
+                proposer_address: proposer.address(),
+                time: Some(tendermint_proto::google::protobuf::Timestamp {
+                    seconds: height * BLOCK_SECONDS as i64,
+                    nanos: 0,
+                }),
+                ..Default::default()
+            }),
+            last_commit_info: Some(abci::LastCommitInfo { round: 0, votes }),
+            ..Default::default()
+        });
+
+        let responses = transactions
+            .iter()
+            .map(|transaction| {
+                self.app.deliver_tx(abci::RequestDeliverTx {
+                    tx: bincode::serialize(transaction).unwrap(),
+                })
+            })
+            .collect();
+
+        self.app.end_block(abci::RequestEndBlock { height });
+        self.app.commit();
+        responses
+    }
+
+    /// Run the given ABCI query against the application and deserialize its result.
+    pub fn query<T: DeserializeOwned>(&self, query: AbciQuery) -> Result<T> {
+        let response = self.app.query(abci::RequestQuery {
+            data: query.into(),
+            ..Default::default()
+        });
+
+        if response.code != 0 {
+            bail!("query failed: {}", response.log);
+        }
+        QueryResponse::decode(&response.value)
+    }
+
+    /// Fail if the given program hasn't been deployed.
+    pub fn assert_program_deployed(&self, program_id: &vm::ProgramID) {
+        let response: ProgramResponse = self
+            .query(AbciQuery::GetProgram {
+                program_id: *program_id,
+            })
+            .unwrap();
+        assert!(
+            response.program.is_some(),
+            "program {program_id} is not deployed"
+        );
+    }
+
+    /// Fail if the record with the given serial number has not been spent.
+    pub fn assert_spent(&self, serial_number: &vm::Field) {
+        assert!(
+            self.spent_serial_numbers().contains(serial_number),
+            "record {serial_number} was not spent"
+        );
+    }
+
+    /// Fail if the record with the given serial number has been spent.
+    pub fn assert_unspent(&self, serial_number: &vm::Field) {
+        assert!(
+            !self.spent_serial_numbers().contains(serial_number),
+            "record {serial_number} was spent"
+        );
+    }
+
+    fn spent_serial_numbers(&self) -> HashSet<vm::Field> {
+        let response: SpentResponse = self.query(AbciQuery::GetSpentSerialNumbers).unwrap();
+        response
+            .serial_numbers
+            .into_iter()
+            .map(|entry| entry.serial_number)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lib::query::RecordsResponse;
+    use std::path::Path;
+
+    #[test]
+    fn deploy_and_execute() {
+        let private_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let view_key = vm::ViewKey::try_from(&private_key).unwrap();
+        let address = vm::Address::try_from(&view_key).unwrap();
+
+        let chain = TestChain::with_account(&address, 100);
+        assert_eq!(0, chain.height());
+
+        let deployment =
+            Transaction::deployment(Path::new("aleo/hello.aleo"), &private_key, None).unwrap();
+        let program_id = match &deployment {
+            Transaction::Deployment { program, .. } => *program.id(),
+            _ => unreachable!(),
+        };
+        assert_eq!(0, chain.check(&deployment).code);
+
+        let responses = chain.produce_block(&[deployment.clone()]);
+        assert_eq!(0, responses[0].code);
+        assert_eq!(1, chain.height());
+        chain.assert_program_deployed(&program_id);
+
+        // the same deployment can't be included twice
+        let responses = chain.produce_block(&[deployment]);
+        assert_ne!(0, responses[0].code);
+        assert_eq!(2, chain.height());
+
+        // the genesis record is known but unspent
+        let response: RecordsResponse = chain
+            .query(AbciQuery::GetRecords {
+                from: None,
+                limit: None,
+            })
+            .unwrap();
+        let entry = response
+            .records
+            .into_iter()
+            .find(|entry| entry.ciphertext.is_owner(&address, &view_key))
+            .unwrap();
+        let serial_number = vm::compute_serial_number(private_key, entry.commitment).unwrap();
+        chain.assert_unspent(&serial_number);
+    }
+}
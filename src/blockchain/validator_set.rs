@@ -1,37 +1,60 @@
 use std::{
     collections::{HashMap, HashSet},
-    path::{Path, PathBuf},
+    path::Path,
+    sync::{Arc, Mutex, RwLock},
 };
 
+use crate::record_store::RecordStore;
 use lib::vm;
-use log::{debug, error, warn};
+use log::{debug, error, info, warn};
+use sha2::{Digest, Sha256};
 
-use anyhow::{anyhow, Result};
-use lib::validator::{Address, Stake, Validator, VotingPower};
+use anyhow::{anyhow, bail, ensure, Result};
+use lib::governance::Params;
+use lib::query::{BlockRewards, ValidatorReward};
+use lib::validator::{pub_key_to_address, Address, Offense, Stake, Validator, VotingPower};
 
 type Fee = u64;
 
-/// There's a baseline for the credits distributed among validators, in addition to fees.
-/// For now it's constant, but it could be made to decrease based on height to control inflation.
-const BASELINE_BLOCK_REWARD: Fee = 100;
 /// The portion of the total block rewards that is given to the block proposer. The rest is distributed
 /// among voters weighted by their voting power.
 const PROPOSER_REWARD_PERCENTAGE: u64 = 50;
 
+/// The portion of the voting power removed from a validator that signed two different blocks at the same height.
+const DOUBLE_SIGN_SLASH_PERCENTAGE: u64 = 5;
+
+/// Blocks that the staker of a tombstoned validator has to wait to unstake what's left after the slash, so it can
+/// still be held accountable for offenses reported in the meantime.
+const UNBONDING_BLOCKS: u64 = 100_000;
+
+/// Version of the format of the validator set snapshots saved to the record store, see `ValidatorSet::encode`.
+const SNAPSHOT_VERSION: u8 = 1;
+
 /// Tracks the network validator set, particularly how the tendermint addresses map to
 /// aleo account addresses needed to assign credits records for validator rewards.
 /// The ValidatorSet exposes methods to collect fees and has logic to distribute them
 /// (in addition to a baseline reward), based on block proposer and voting power.
 /// There are also methods to apply voting power changes on staking transactions.
+///
+/// The set is safe to share across threads and all of its methods take `&self`. Validating transactions only reads
+/// the known validators, which are kept as an immutable snapshot behind an `Arc`: readers clone the `Arc` and release
+/// the lock right away, while writers apply their changes copy-on-write, so mempool checks don't wait for the
+/// consensus connection to finish processing a block.
 #[derive(Debug)]
 pub struct ValidatorSet {
-    /// Path to the file used to persist the currently known validator list of validator, so the app works across restarts.
-    path: PathBuf,
     /// The currently known validator set, including the terndermint pub key/address to aleo account mapping
     /// and their last known voting power.
-    validators: HashMap<Address, Validator>,
-    /// The fees collected for the current block.
+    validators: RwLock<Arc<HashMap<Address, Validator>>>,
+    /// State of the block being processed, only updated from the consensus connection.
+    block: Mutex<BlockState>,
+}
+
+#[derive(Debug)]
+struct BlockState {
+    /// The fees collected for the current block, starting from the baseline block credits.
     fees: Fee,
+    /// The baseline block credits included in `fees`, as they were when the block began.
+    minted: Fee,
     /// The proposer of the current block.
     current_proposer: Option<Address>,
     /// The previous round block votes, to be considered to distribute this block's rewards.
@@ -40,51 +63,132 @@ pub struct ValidatorSet {
     current_height: u64,
     /// The list of validators that had voting power changes during the current block, including added or removed ones.
     updated_validators: HashSet<Address>,
+    /// There's a baseline for the credits distributed among validators, in addition to fees.
+    /// It's a chain parameter that can be changed through governance.
+    block_reward: Fee,
+    /// Minimum voting power to be part of the active set, a chain parameter.
+    min_self_stake: VotingPower,
+    /// Maximum amount of validators in the active set, zero for no limit. A chain parameter.
+    max_validators: u64,
+    /// Percentage of the fees paid to the community pool instead of the validators, a chain parameter.
+    community_pool_percentage: u64,
+    /// Validators in the active set when the block began, i.e. the ones tendermint knows with some voting power.
+    active_at_begin: HashSet<Address>,
 }
 
-impl ValidatorSet {
-    /// Create a new validator set. If a previous validators file is found, populate the set with its contents,
-    /// otherwise start with an empty one.
-    pub fn load_or_create(path: &Path) -> Self {
-        let validators = if let Ok(json) = std::fs::read_to_string(path) {
-            serde_json::from_str::<Vec<Validator>>(&json)
-                .expect("validators file content is invalid")
-                .into_iter()
-                .map(|validator| {
-                    debug!("loading validator {}", validator);
-                    (validator.address(), validator)
-                })
-                .collect()
-        } else {
-            HashMap::new()
-        };
-
+impl Default for BlockState {
+    fn default() -> Self {
         Self {
-            path: path.into(),
-            validators,
-            current_height: 0,
             fees: 0,
+            minted: 0,
             current_proposer: None,
             current_votes: HashMap::new(),
+            current_height: 0,
             updated_validators: HashSet::new(),
+            block_reward: Params::default().block_reward,
+            min_self_stake: 0,
+            max_validators: 0,
+            community_pool_percentage: 0,
+            active_at_begin: HashSet::new(),
         }
     }
+}
 
-    pub fn replace(&mut self, validators: Vec<Validator>) {
-        self.validators = validators
-            .into_iter()
-            .map(|validator| (validator.address(), validator))
-            .collect()
+impl ValidatorSet {
+    /// Create a validator set with the last snapshot committed to the record store, falling back to the previous
+    /// ones if it's corrupt. Nodes that haven't committed a snapshot yet start from the validators file used by
+    /// previous versions of the application, if found, or from an empty set otherwise.
+    pub fn load(records: &RecordStore, legacy_path: &Path) -> Result<Self> {
+        let snapshots = records.validator_snapshots()?;
+        let validators =
+            match snapshots
+                .iter()
+                .find_map(|(height, bytes)| match Self::decode(bytes) {
+                    Ok(validators) => Some(validators),
+                    Err(e) => {
+                        error!("skipping invalid validator set snapshot of height {height}: {e}");
+                        None
+                    }
+                }) {
+                Some(validators) => validators,
+                None if !snapshots.is_empty() => {
+                    bail!("all the validator set snapshots are invalid")
+                }
+                None => match std::fs::read_to_string(legacy_path) {
+                    Ok(json) => {
+                        info!("loading validators from {}", legacy_path.display());
+                        serde_json::from_str(&json)
+                            .map_err(|e| anyhow!("validators file content is invalid: {e}"))?
+                    }
+                    Err(_) => Vec::new(),
+                },
+            };
+
+        let set = Self::new_in_memory();
+        for validator in &validators {
+            debug!("loading validator {}", validator);
+        }
+        set.replace(validators);
+        Ok(set)
+    }
+
+    /// Create a new empty validator set.
+    pub fn new_in_memory() -> Self {
+        Self {
+            validators: Default::default(),
+            block: Default::default(),
+        }
+    }
+
+    /// Return the currently known validators by address. The snapshot isn't affected by later changes to the set.
+    fn snapshot(&self) -> Arc<HashMap<Address, Validator>> {
+        self.validators.read().unwrap().clone()
+    }
+
+    /// Change the known validators. The map is only copied if there are readers holding a snapshot of it.
+    fn update<T>(&self, change: impl FnOnce(&mut HashMap<Address, Validator>) -> T) -> T {
+        let mut validators = self.validators.write().unwrap();
+        change(Arc::make_mut(&mut validators))
+    }
+
+    pub fn replace(&self, validators: Vec<Validator>) {
+        *self.validators.write().unwrap() = Arc::new(
+            validators
+                .into_iter()
+                .map(|validator| (validator.address(), validator))
+                .collect(),
+        );
+    }
+
+    /// Set the chain parameters used by the set, starting from the next block: the baseline credits distributed
+    /// among validators on each block, the share of them paid to the community pool and the limits of the active set.
+    pub fn set_params(&self, params: &Params) {
+        let mut block = self.block.lock().unwrap();
+        block.block_reward = params.block_reward;
+        block.min_self_stake = params.min_self_stake;
+        block.max_validators = params.max_validators;
+        block.community_pool_percentage = params.community_pool_percentage;
+    }
+
+    /// Return the validator with the given tendermint address, if known.
+    pub fn get(&self, address: &Address) -> Option<Validator> {
+        self.snapshot().get(address).cloned()
+    }
+
+    /// Return the currently known validators, including the ones with zero voting power.
+    pub fn validators(&self) -> Vec<Validator> {
+        self.snapshot().values().cloned().collect()
     }
 
     /// Updates state based on previous commit votes, to know how awards should be assigned.
     pub fn begin_block(
-        &mut self,
+        &self,
         proposer: &Address,
         votes: HashMap<Address, VotingPower>,
         height: u64,
     ) {
-        if !self.validators.contains_key(proposer) {
+        let validators = self.snapshot();
+        if !validators.contains_key(proposer) {
             error!(
                 "received unknown address as proposer {}",
                 hex::encode_upper(proposer)
@@ -92,7 +196,7 @@ impl ValidatorSet {
         }
 
         for voter in votes.keys() {
-            if !self.validators.contains_key(voter) {
+            if !validators.contains_key(voter) {
                 error!(
                     "received unknown address as voter {}",
                     hex::encode_upper(voter)
@@ -100,15 +204,22 @@ impl ValidatorSet {
             }
         }
 
-        self.updated_validators = HashSet::new();
-        self.current_height = height;
-        self.current_proposer = Some(proposer.to_vec());
+        let mut block = self.block.lock().unwrap();
+        block.updated_validators = HashSet::new();
+        block.current_height = height;
+        block.current_proposer = Some(proposer.to_vec());
         // note that we rely on voting power for a given round as informed by tendermint as opposed to
         // using the one tracked in self.validators. This is because the voting power on the informed round
         // may not be the same as the last known one (e.g. there could be staking changes already applied
         // to self.validators that will take some rounds before affecting the consensus voting).
-        self.current_votes = votes;
-        self.fees = BASELINE_BLOCK_REWARD;
+        block.current_votes = votes;
+        block.fees = block.block_reward;
+        block.minted = block.block_reward;
+        block.active_at_begin = validators
+            .values()
+            .filter(|validator| validator.consensus_power() > 0)
+            .map(|validator| validator.address())
+            .collect();
     }
 
     /// Return whether is valid to apply the given validator update, e.g.
@@ -116,7 +227,21 @@ impl ValidatorSet {
     /// the known mappings. This takes into account pending updates if any, so it's safe
     /// to use both during lightweight mempool checks (check_tx) and transaction delivery (deliver_tx).
     pub fn validate(&self, update: &Stake) -> Result<()> {
-        if let Some(validator) = self.validators.get(&update.validator_address()) {
+        let validators = self.snapshot();
+        let update = &follow_rotations(&validators, update);
+        if let Some(validator) = validators.get(&update.validator_address()) {
+            if let Some(offense) = &validator.tombstone {
+                ensure!(
+                    update.gates_delta() < 0,
+                    "validator {validator} is tombstoned, it can't receive stakes"
+                );
+                let height = self.block.lock().unwrap().current_height;
+                ensure!(
+                    height >= offense.unbonding_height,
+                    "validator {validator} is tombstoned, its stake can be unstaked from height {}",
+                    offense.unbonding_height
+                );
+            }
             // this is an already known validator, try to apply the staking update and see if it succeeds
             validator.clone().apply(update)?;
         } else {
@@ -128,101 +253,264 @@ impl ValidatorSet {
 
     /// Add or update the given validator and its voting power.
     /// Assumes this update has been validated previously with is_valid_update.
-    pub fn apply(&mut self, update: Stake) {
+    pub fn apply(&self, update: Stake) {
+        let address = self.update(|validators| {
+            let update = follow_rotations(validators, &update);
+
+            // note that this could leave a validator with zero voting power, which will instruct
+            // tendermint to remove it, but we still need to keep it around since we can receive
+            // votes from that validator on subsequent rounds.
+            validators
+                .entry(update.validator_address())
+                .and_modify(|validator| {
+                    validator
+                        .apply(&update)
+                        .expect("attempted to apply an invalid update")
+                })
+                .or_insert_with(|| {
+                    Validator::from_stake(&update).expect("attempted to apply an invalid update")
+                });
+            update.validator_address()
+        });
+
         // mark as updated so its included in the pending updates result
-        self.updated_validators.insert(update.validator_address());
-
-        // note that this could leave a validator with zero voting power, which will instruct
-        // tendermint to remove it, but we still need to keep it around since we can receive
-        // votes from that validator on subsequent rounds.
-        self.validators
-            .entry(update.validator_address())
-            .and_modify(|validator| {
-                validator
-                    .apply(&update)
-                    .expect("attempted to apply an invalid update")
-            })
-            .or_insert_with(|| {
-                Validator::from_stake(&update).expect("attempted to apply an invalid update")
+        self.block
+            .lock()
+            .unwrap()
+            .updated_validators
+            .insert(address);
+    }
+
+    /// Return whether the validator with the given key can switch to the new one: it should be an active
+    /// validator, and the new key shouldn't belong to any known validator, including removed ones.
+    pub fn validate_rotation(
+        &self,
+        pub_key: &tendermint::PublicKey,
+        new_key: &tendermint::PublicKey,
+    ) -> Result<()> {
+        let validators = self.snapshot();
+        let validator = validators
+            .get(&pub_key_to_address(pub_key))
+            .ok_or_else(|| anyhow!("unknown validator"))?;
+        ensure!(
+            validator.rotated_to.is_none(),
+            "the key of validator {validator} was already rotated"
+        );
+        ensure!(
+            validator.tombstone.is_none(),
+            "validator {validator} is tombstoned"
+        );
+        ensure!(
+            validator.voting_power > 0,
+            "validator {validator} has no voting power"
+        );
+        ensure!(
+            !validators.contains_key(&pub_key_to_address(new_key)),
+            "the new key belongs to a known validator"
+        );
+        Ok(())
+    }
+
+    /// Move the voting power of the validator with the given key to the new one.
+    /// Assumes this rotation has been validated previously with validate_rotation.
+    pub fn rotate(&self, pub_key: &tendermint::PublicKey, new_key: tendermint::PublicKey) {
+        let address = pub_key_to_address(pub_key);
+        let rotated_address = self.update(|validators| {
+            let validator = validators
+                .get_mut(&address)
+                .expect("attempted to apply an invalid rotation");
+            let rotated = Validator {
+                aleo_address: validator.aleo_address,
+                pub_key: new_key,
+                voting_power: validator.voting_power,
+                rotated_to: None,
+                candidate: validator.candidate,
+                tombstone: None,
+            };
+
+            // as with removed validators, the old key is kept with zero voting power since we can still
+            // receive votes from it on subsequent rounds. Both are marked as updated so tendermint
+            // replaces one with the other.
+            validator.voting_power = 0;
+            validator.rotated_to = Some(new_key);
+            validator.candidate = false;
+            let rotated_address = rotated.address();
+            validators.insert(rotated.address(), rotated);
+            rotated_address
+        });
+
+        let mut block = self.block.lock().unwrap();
+        block.updated_validators.insert(address);
+        block.updated_validators.insert(rotated_address);
+    }
+
+    /// Slash and tombstone the validator that signed two different blocks at the given height, as reported by
+    /// tendermint evidence: `DOUBLE_SIGN_SLASH_PERCENTAGE` of its voting power is removed, and its key can't
+    /// receive stakes nor be part of the active set ever again. What's left of the stake can be unstaked after
+    /// `UNBONDING_BLOCKS`. Evidence against a rotated key applies to the key the validator rotated to.
+    /// Returns the tombstoned validator, or None if it's unknown or was already tombstoned.
+    pub fn tombstone(&self, address: &Address, offense_height: u64) -> Option<Validator> {
+        let height = self.block.lock().unwrap().current_height;
+        let validator = self.update(|validators| {
+            let mut address = address.clone();
+            while let Some(new_key) = validators
+                .get(&address)
+                .and_then(|validator| validator.rotated_to)
+            {
+                address = pub_key_to_address(&new_key);
+            }
+
+            let validator = validators.get_mut(&address)?;
+            if validator.tombstone.is_some() {
+                return None;
+            }
+            let slashed = validator.voting_power * DOUBLE_SIGN_SLASH_PERCENTAGE / 100;
+            validator.voting_power -= slashed;
+            validator.candidate = false;
+            validator.tombstone = Some(Offense {
+                height: offense_height,
+                slashed_at: height,
+                slashed,
+                unbonding_height: height + UNBONDING_BLOCKS,
             });
+            Some(validator.clone())
+        })?;
+
+        // marked as updated so it's removed from the consensus if it was active
+        self.block
+            .lock()
+            .unwrap()
+            .updated_validators
+            .insert(validator.address());
+        Some(validator)
+    }
+
+    /// Return the tombstoned validators, along with the offense they were slashed for.
+    pub fn tombstones(&self) -> Vec<Validator> {
+        let mut tombstones: Vec<Validator> = self
+            .snapshot()
+            .values()
+            .filter(|validator| validator.tombstone.is_some())
+            .cloned()
+            .collect();
+        tombstones.sort_by_key(|validator| validator.address());
+        tombstones
     }
 
     /// Add the given amount to the current block collected fees.
-    pub fn collect(&mut self, fee: u64) {
-        self.fees += fee;
+    pub fn collect(&self, fee: u64) {
+        self.block.lock().unwrap().fees += fee;
     }
 
-    /// Return the list of validators that have been updated by transactions in the current block.
+    /// Choose the active set: the validators with at least the minimum self stake, up to the maximum amount of
+    /// validators by voting power, with ties broken by address so every node picks the same ones. Tombstoned
+    /// validators are left out. The rest of the validators with stake are kept as candidates, which tendermint knows with zero voting power, until they can
+    /// enter the set. Returns the validators that entered and left the active set during the block, which are
+    /// included in the pending updates.
+    pub fn select_active(&self) -> (Vec<Validator>, Vec<Validator>) {
+        let mut block = self.block.lock().unwrap();
+        let validators = self.snapshot();
+        let mut eligible: Vec<&Validator> = validators
+            .values()
+            .filter(|validator| {
+                validator.tombstone.is_none()
+                    && validator.voting_power >= block.min_self_stake.max(1)
+            })
+            .collect();
+        eligible.sort_by(|a, b| {
+            b.voting_power
+                .cmp(&a.voting_power)
+                .then_with(|| a.address().cmp(&b.address()))
+        });
+        if block.max_validators > 0 {
+            eligible.truncate(block.max_validators as usize);
+        }
+        let active: HashSet<Address> = eligible
+            .into_iter()
+            .map(|validator| validator.address())
+            .collect();
+
+        // the set is only copied if some validator changed between candidate and active
+        let changed: Vec<Address> = validators
+            .iter()
+            .filter(|(address, validator)| {
+                let candidate = validator.voting_power > 0
+                    && validator.tombstone.is_none()
+                    && !active.contains(*address);
+                validator.candidate != candidate
+            })
+            .map(|(address, _)| address.clone())
+            .collect();
+        if !changed.is_empty() {
+            self.update(|validators| {
+                for address in &changed {
+                    if let Some(validator) = validators.get_mut(address) {
+                        validator.candidate = !validator.candidate;
+                    }
+                }
+            });
+        }
+
+        // sorted by address, so every node reports the changes in the same order
+        let validators = self.snapshot();
+        let mut entered: Vec<Validator> = active
+            .difference(&block.active_at_begin)
+            .filter_map(|address| validators.get(address).cloned())
+            .collect();
+        entered.sort_by_key(|validator| validator.address());
+        let mut left: Vec<Validator> = block
+            .active_at_begin
+            .difference(&active)
+            .filter_map(|address| validators.get(address).cloned())
+            .collect();
+        left.sort_by_key(|validator| validator.address());
+        for validator in entered.iter().chain(&left) {
+            block.updated_validators.insert(validator.address());
+        }
+        (entered, left)
+    }
+
+    /// Return the list of validators that have been updated in the current block, e.g. by staking transactions,
+    /// to inform tendermint of their `consensus_power`. Validators tendermint doesn't know, e.g. candidates,
+    /// are only included once they have some consensus power.
     pub fn pending_updates(&self) -> Vec<Validator> {
-        self.updated_validators
+        let validators = self.snapshot();
+        let block = self.block.lock().unwrap();
+        block
+            .updated_validators
             .iter()
-            .fold(Vec::new(), |mut acc, address| {
-                acc.push(
-                    self.validators
-                        .get(address)
-                        .expect("missing updated validator")
-                        .clone(),
-                );
-                acc
+            .map(|address| {
+                validators
+                    .get(address)
+                    .expect("missing updated validator")
+                    .clone()
             })
+            .filter(|validator| {
+                validator.consensus_power() > 0
+                    || block.active_at_begin.contains(&validator.address())
+            })
+            .collect()
     }
 
     /// Distributes the sum of the block fees plus some baseline block credits
     /// according to some rule, e.g. 50% for the proposer and 50% for validators
     /// weighted by their voting power (which is assumed to be proportional to its stake).
     /// If there are credits left because of rounding errors when dividing by voting power,
-    /// they are assigned to the proposer.
+    /// they are assigned to the proposer. The `community_pool_share` is left out.
     pub fn block_rewards(&self) -> Vec<(vm::Field, vm::EncryptedRecord)> {
-        if let Some(proposer) = &self.current_proposer {
-            // first calculate which part of the total belongs to voters
-            let voter_reward_percentage = 100 - PROPOSER_REWARD_PERCENTAGE;
-            let total_voter_reward = (self.fees * voter_reward_percentage) / 100;
-            let total_voting_power = self
-                .current_votes
-                .iter()
-                .fold(0, |accum, (_address, power)| accum + power);
-            debug!(
-                "total block rewards: {}, total voting power: {}, total voter rewards: {}",
-                self.fees, total_voting_power, total_voter_reward
-            );
-
-            // calculate how much belongs to each validator, proportional to its voting power
-            let mut remaining_fees = self.fees;
-            let mut rewards = HashMap::new();
-            for (address, voting_power) in &self.current_votes {
-                let credits = (*voting_power * total_voter_reward) / total_voting_power;
-                remaining_fees -= credits;
-                rewards.insert(address, credits);
-            }
-
-            // What's left of the fees, goes to the proposer.
-            // This should be roughly PROPOSER_REWARD_PERCENTAGE plus some leftover because
-            // of rounding errors when distributing based on voting power above
-            debug!(
-                "{} is current round proposer",
-                self.validators
-                    .get(proposer)
-                    .expect("proposer not found in address map")
-            );
-            *rewards.entry(proposer).or_default() += remaining_fees;
-
-            assert_eq!(
-                self.fees,
-                rewards.values().sum::<u64>(),
-                "the sum of rewarded credits is different than the fees: {rewards:?}"
-            );
-
+        let validators = self.snapshot();
+        let block = self.block.lock().unwrap();
+        if let Some(rewards) = distribute(&validators, &block) {
             // generate credits records based on the rewards
             let mut output_records = Vec::new();
             for (address, credits) in rewards {
-                let validator = self
-                    .validators
-                    .get(address)
+                let validator = validators
+                    .get(&address)
                     .expect("validator address not found");
 
                 debug!(
                     "Assigning {credits} credits to {validator} (voting power {})",
-                    self.current_votes.get(address).unwrap_or(&0)
+                    block.current_votes.get(&address).unwrap_or(&0)
                 );
 
                 let record = vm::mint_record(
@@ -230,7 +518,7 @@ impl ValidatorSet {
                     "credits",
                     &validator.aleo_address,
                     credits,
-                    self.current_height,
+                    block.current_height,
                 )
                 .expect("Couldn't mint credit records for reward");
 
@@ -244,19 +532,154 @@ impl ValidatorSet {
         }
     }
 
-    /// Saves the currently known list of validators to disk.
-    pub fn commit(&mut self) -> Result<()> {
-        let validators_vec: Vec<Validator> = self.validators.values().cloned().collect();
-        let json = serde_json::to_string(&validators_vec).expect("couldn't serialize validators");
-        std::fs::write(&self.path, json)
-            .map_err(|e| anyhow!("failed to write validators file {:?} {e}", self.path))
+    /// Summarize how `block_rewards` distributes the credits of the current block, to be saved with
+    /// `RecordStore::stage_rewards`. Returns None if there's no proposer, in which case there are no rewards.
+    #[allow(clippy::clone_on_copy)]
+    pub fn rewards_summary(&self) -> Option<BlockRewards> {
+        let validators = self.snapshot();
+        let block = self.block.lock().unwrap();
+        let proposer = block.current_proposer.as_ref()?;
+        let mut distribution: Vec<(Address, u64)> =
+            distribute(&validators, &block)?.into_iter().collect();
+        // sorted by tendermint address, so the summary is the same across nodes
+        distribution.sort();
+        let rewards = distribution
+            .into_iter()
+            .map(|(address, gates)| {
+                let validator = validators
+                    .get(&address)
+                    .expect("validator address not found");
+                ValidatorReward {
+                    pub_key: validator.pub_key,
+                    aleo_address: validator.aleo_address.clone(),
+                    gates,
+                    proposer: &address == proposer,
+                }
+            })
+            .collect();
+
+        Some(BlockRewards {
+            height: block.current_height,
+            minted: block.minted,
+            fees: block.fees.saturating_sub(block.minted),
+            community_pool: community_pool_share(&block),
+            rewards,
+            // the block time isn't known to the validator set, it's added by the pipeline
+            time: None,
+        })
+    }
+
+    /// Return the part of the fees of the current block, including the baseline block credits, that goes to the
+    /// community pool instead of the validators. Zero if there's no proposer, in which case there are no rewards.
+    pub fn community_pool_share(&self) -> u64 {
+        let block = self.block.lock().unwrap();
+        if block.current_proposer.is_some() {
+            community_pool_share(&block)
+        } else {
+            0
+        }
+    }
+
+    /// Serialize the currently known validators to be saved with `RecordStore::stage_validators`. The snapshot starts
+    /// with a version byte and a checksum of the rest of its content, so corrupt snapshots are detected on load.
+    pub fn encode(&self) -> Vec<u8> {
+        let json = serde_json::to_vec(&self.validators()).expect("couldn't serialize validators");
+        let mut bytes = vec![SNAPSHOT_VERSION];
+        bytes.extend_from_slice(&Sha256::digest(&json));
+        bytes.extend(json);
+        bytes
     }
+
+    /// Parse a validator set snapshot produced by `encode`, verifying its version and checksum.
+    fn decode(bytes: &[u8]) -> Result<Vec<Validator>> {
+        let (version, rest) = bytes
+            .split_first()
+            .ok_or_else(|| anyhow!("empty snapshot"))?;
+        ensure!(
+            *version == SNAPSHOT_VERSION,
+            "unsupported snapshot version {version}"
+        );
+        ensure!(rest.len() >= 32, "truncated snapshot");
+        let (checksum, json) = rest.split_at(32);
+        ensure!(
+            Sha256::digest(json).as_slice() == checksum,
+            "snapshot checksum mismatch"
+        );
+        Ok(serde_json::from_slice(json)?)
+    }
+}
+
+/// The part of the fees collected for the block paid to the community pool, rounded down.
+fn community_pool_share(block: &BlockState) -> Fee {
+    block.fees * block.community_pool_percentage.min(100) / 100
+}
+
+/// Split the fees collected for the block, which include the baseline block credits, between the validators,
+/// as explained in `ValidatorSet::block_rewards`. Returns the credits for each validator by tendermint address,
+/// or None if the block has no proposer.
+fn distribute(
+    validators: &HashMap<Address, Validator>,
+    block: &BlockState,
+) -> Option<HashMap<Address, Fee>> {
+    let proposer = block.current_proposer.as_ref()?;
+    let fees = block.fees - community_pool_share(block);
+
+    // first calculate which part of the total belongs to voters
+    let voter_reward_percentage = 100 - PROPOSER_REWARD_PERCENTAGE;
+    let total_voter_reward = (fees * voter_reward_percentage) / 100;
+    let total_voting_power = block
+        .current_votes
+        .iter()
+        .fold(0, |accum, (_address, power)| accum + power);
+    debug!(
+        "total block rewards: {}, total voting power: {}, total voter rewards: {}",
+        fees, total_voting_power, total_voter_reward
+    );
+
+    // calculate how much belongs to each validator, proportional to its voting power
+    let mut remaining_fees = fees;
+    let mut rewards = HashMap::new();
+    for (address, voting_power) in &block.current_votes {
+        let credits = (*voting_power * total_voter_reward) / total_voting_power;
+        remaining_fees -= credits;
+        rewards.insert(address.clone(), credits);
+    }
+
+    // What's left of the fees, goes to the proposer.
+    // This should be roughly PROPOSER_REWARD_PERCENTAGE plus some leftover because
+    // of rounding errors when distributing based on voting power above
+    debug!(
+        "{} is current round proposer",
+        validators
+            .get(proposer)
+            .expect("proposer not found in address map")
+    );
+    *rewards.entry(proposer.clone()).or_default() += remaining_fees;
+
+    assert_eq!(
+        fees,
+        rewards.values().sum::<u64>(),
+        "the sum of rewarded credits is different than the fees: {rewards:?}"
+    );
+    Some(rewards)
+}
+
+/// Stakes are tied to the consensus key the validator had when they were made, so redirect
+/// the ones made to a rotated key to the current key of the validator.
+fn follow_rotations(validators: &HashMap<Address, Validator>, update: &Stake) -> Stake {
+    let mut update = update.clone();
+    while let Some(new_key) = validators
+        .get(&update.validator_address())
+        .and_then(|validator| validator.rotated_to)
+    {
+        update = update.with_pub_key(new_key);
+    }
+    update
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use assert_fs::NamedTempFile;
     use lib::vm;
 
     #[test]
@@ -277,8 +700,7 @@ mod tests {
         let validator4 = Validator::from_str(tmint4, &aleo4.1.to_string(), 1).unwrap();
 
         // create validator set, set validators with voting power
-        let tempfile = NamedTempFile::new("validators").unwrap();
-        let mut set = ValidatorSet::load_or_create(tempfile.path());
+        let set = ValidatorSet::new_in_memory();
         set.replace(vec![
             validator1.clone(),
             validator2.clone(),
@@ -307,7 +729,7 @@ mod tests {
         let rewards4 = decrypt_rewards(&aleo4, &records);
 
         // check proposer gets 50% and the rest is distributed according to vote power
-        let total_rewards = BASELINE_BLOCK_REWARD + fees;
+        let total_rewards = Params::default().block_reward + fees;
         let voter_rewards = total_rewards * PROPOSER_REWARD_PERCENTAGE / 100;
 
         // ensure the no credits are lost in the process
@@ -322,6 +744,27 @@ mod tests {
         // so, basically, all the rest
         assert_eq!(total_rewards - rewards2 - rewards3, rewards1);
 
+        // the summary matches the minted records
+        let summary = set.rewards_summary().unwrap();
+        assert_eq!(1, summary.height);
+        assert_eq!(Params::default().block_reward, summary.minted);
+        assert_eq!(fees, summary.fees);
+        let summarized: Vec<(String, u64, bool)> = summary
+            .rewards
+            .iter()
+            .map(|reward| {
+                (
+                    reward.aleo_address.to_string(),
+                    reward.gates,
+                    reward.proposer,
+                )
+            })
+            .collect();
+        assert_eq!(3, summarized.len());
+        assert!(summarized.contains(&(aleo1.1.to_string(), rewards1, true)));
+        assert!(summarized.contains(&(aleo2.1.to_string(), rewards2, false)));
+        assert!(summarized.contains(&(aleo3.1.to_string(), rewards3, false)));
+
         // run another block with different votes, rewards start from scratch
         let mut votes = HashMap::new();
         votes.insert(validator4.address(), 10);
@@ -336,7 +779,7 @@ mod tests {
         assert_eq!(0, rewards1);
         assert_eq!(0, rewards2);
         assert_eq!(0, rewards3);
-        assert_eq!(BASELINE_BLOCK_REWARD + 10, rewards4);
+        assert_eq!(Params::default().block_reward + 10, rewards4);
     }
 
     #[test]
@@ -353,8 +796,7 @@ mod tests {
         let validator2 = Validator::from_str(tmint2, &aleo2.1.to_string(), 1).unwrap();
 
         // create validator set, set validators with voting power
-        let tempfile = NamedTempFile::new("validators").unwrap();
-        let mut set = ValidatorSet::load_or_create(tempfile.path());
+        let set = ValidatorSet::new_in_memory();
         set.replace(vec![validator1.clone(), validator2.clone()]);
 
         // tmint1 is proposer and didn't vote
@@ -373,7 +815,7 @@ mod tests {
         let rewards2 = decrypt_rewards(&aleo2, &records);
 
         // check proposer gets 50% and the rest is distributed according to vote power
-        let total_rewards = BASELINE_BLOCK_REWARD + fees;
+        let total_rewards = Params::default().block_reward + fees;
         let voter_rewards = total_rewards * PROPOSER_REWARD_PERCENTAGE / 100;
 
         // ensure the no credits are lost in the process
@@ -396,10 +838,8 @@ mod tests {
         let validator2 = Validator::from_str(tmint2, &aleo2.1.to_string(), 1).unwrap();
         let validators = vec![validator1.clone(), validator2.clone()];
 
-        let tempfile1 = NamedTempFile::new("validators").unwrap();
-        let tempfile2 = NamedTempFile::new("validators").unwrap();
-        let mut set1 = ValidatorSet::load_or_create(tempfile1.path());
-        let mut set2 = ValidatorSet::load_or_create(tempfile2.path());
+        let set1 = ValidatorSet::new_in_memory();
+        let set2 = ValidatorSet::new_in_memory();
         set1.replace(validators.clone());
         set2.replace(validators);
 
@@ -454,8 +894,7 @@ mod tests {
         let validator2 = Validator::from_str(tmint2, &aleo2.1.to_string(), 1).unwrap();
 
         // create validator set, set validators with voting power
-        let tempfile = NamedTempFile::new("validators").unwrap();
-        let mut set = ValidatorSet::load_or_create(tempfile.path());
+        let set = ValidatorSet::new_in_memory();
         set.replace(vec![validator1.clone(), validator2]);
 
         // in genesis there won't be any previous block votes
@@ -469,13 +908,39 @@ mod tests {
         let records = set.block_rewards();
         let rewards1 = decrypt_rewards(&aleo1, &records);
         let rewards2 = decrypt_rewards(&aleo2, &records);
-        let total_rewards = BASELINE_BLOCK_REWARD + fees;
+        let total_rewards = Params::default().block_reward + fees;
 
         // proposer takes all
         assert_eq!(total_rewards, rewards1);
         assert_eq!(0, rewards2);
     }
 
+    #[test]
+    fn community_pool_share() {
+        let tmint1 = "vM+mkdPMvplfxO7wM57z4FXy0TlBC2Onb+MaqcXE8ig=";
+        let aleo1 = account_keys();
+        let validator1 = Validator::from_str(tmint1, &aleo1.1.to_string(), 1).unwrap();
+        let set = ValidatorSet::new_in_memory();
+        set.replace(vec![validator1.clone()]);
+        set.set_params(&Params {
+            block_reward: 100,
+            community_pool_percentage: 10,
+            ..Params::default()
+        });
+
+        // no rewards without a proposer
+        assert_eq!(0, set.community_pool_share());
+
+        set.begin_block(&validator1.address(), HashMap::new(), 1);
+        set.collect(55);
+        assert_eq!(15, set.community_pool_share());
+        assert_eq!(140, decrypt_rewards(&aleo1, &set.block_rewards()));
+
+        let summary = set.rewards_summary().unwrap();
+        assert_eq!(15, summary.community_pool);
+        assert_eq!(140, summary.rewards[0].gates);
+    }
+
     #[test]
     fn add_update_validators() {
         // create set and setup initial 2 validators
@@ -489,8 +954,7 @@ mod tests {
         let validator2 = Validator::from_str(tmint2, &aleo2.1.to_string(), 1).unwrap();
 
         // create validator set, set validators with voting power
-        let tempfile = NamedTempFile::new("validators").unwrap();
-        let mut set = ValidatorSet::load_or_create(tempfile.path());
+        let set = ValidatorSet::new_in_memory();
         set.replace(vec![validator1.clone(), validator2]);
 
         // votes/begin block/commit
@@ -500,7 +964,6 @@ mod tests {
         // no updates on this round (should ignore default ones from before begin block)
         assert_eq!(0, set.pending_updates().len());
         let _records = set.block_rewards();
-        set.commit().unwrap();
 
         // votes/begin block
         let mut votes = HashMap::new();
@@ -523,7 +986,49 @@ mod tests {
         assert_eq!(6, updates[1].voting_power);
 
         let _records = set.block_rewards();
-        set.commit().unwrap();
+    }
+
+    #[test]
+    fn snapshots_are_not_affected_by_updates() {
+        let tmint1 = "vM+mkdPMvplfxO7wM57z4FXy0TlBC2Onb+MaqcXE8ig=";
+        let aleo1 = account_keys();
+        let validator1 = Validator::from_str(tmint1, &aleo1.1.to_string(), 5).unwrap();
+
+        let set = ValidatorSet::new_in_memory();
+        set.replace(vec![validator1.clone()]);
+
+        // a reader holding a snapshot sees the set as it was when it took it
+        let snapshot = set.snapshot();
+        set.apply(Stake::new(tmint1, aleo1.1, 3).unwrap());
+        assert_eq!(5, snapshot[&validator1.address()].voting_power);
+        assert_eq!(8, set.get(&validator1.address()).unwrap().voting_power);
+    }
+
+    #[test]
+    fn load_last_valid_snapshot() {
+        let tmint1 = "vM+mkdPMvplfxO7wM57z4FXy0TlBC2Onb+MaqcXE8ig=";
+        let aleo1 = account_keys();
+        let validator1 = Validator::from_str(tmint1, &aleo1.1.to_string(), 5).unwrap();
+        let records = RecordStore::new_in_memory();
+        let legacy_path = Path::new("missing.validators");
+
+        // nothing committed yet
+        let set = ValidatorSet::load(&records, legacy_path).unwrap();
+        assert!(set.validators().is_empty());
+
+        set.replace(vec![validator1.clone()]);
+        records.stage_validators(set.encode()).unwrap();
+        records.commit(1).unwrap();
+
+        // a corrupt snapshot is skipped in favor of the previous one
+        let mut corrupt = set.encode();
+        *corrupt.last_mut().unwrap() ^= 1;
+        assert!(ValidatorSet::decode(&corrupt).is_err());
+        records.stage_validators(corrupt).unwrap();
+        records.commit(2).unwrap();
+
+        let loaded = ValidatorSet::load(&records, legacy_path).unwrap();
+        assert_eq!(5, loaded.get(&validator1.address()).unwrap().voting_power);
     }
 
     #[test]
@@ -536,8 +1041,7 @@ mod tests {
         let validator1 = Validator::from_str(tmint1, &aleo1.1.to_string(), 5).unwrap();
         let validator2 = Validator::from_str(tmint2, &aleo2.1.to_string(), 5).unwrap();
 
-        let tempfile = NamedTempFile::new("validators").unwrap();
-        let mut set = ValidatorSet::load_or_create(tempfile.path());
+        let set = ValidatorSet::new_in_memory();
         set.replace(vec![validator1, validator2.clone()]);
 
         // votes/begin block
@@ -556,7 +1060,6 @@ mod tests {
         assert_eq!(2, updates[0].voting_power);
 
         let _records = set.block_rewards();
-        set.commit().unwrap();
 
         // votes/begin block
         let mut votes = HashMap::new();
@@ -575,7 +1078,6 @@ mod tests {
 
         // get rewards check as expected, include removed
         let _records = set.block_rewards();
-        set.commit().unwrap();
 
         // votes/begin block, shouldn't fail even if it includes votes from removed one
         let mut votes = HashMap::new();
@@ -583,7 +1085,6 @@ mod tests {
         set.begin_block(&validator2.address(), votes, 1);
         assert_eq!(0, set.pending_updates().len());
         let _records = set.block_rewards();
-        set.commit().unwrap();
     }
 
     #[test]
@@ -595,8 +1096,7 @@ mod tests {
         let validator1 = Validator::from_str(tmint1, &aleo1.1.to_string(), 5).unwrap();
         let validator2 = Validator::from_str(tmint2, &aleo2.1.to_string(), 5).unwrap();
 
-        let tempfile = NamedTempFile::new("validators").unwrap();
-        let mut set = ValidatorSet::load_or_create(tempfile.path());
+        let set = ValidatorSet::new_in_memory();
         let validators = vec![validator1, validator2];
         set.replace(validators);
 
@@ -630,6 +1130,199 @@ mod tests {
             .contains("attempted to unstake more voting power than available"));
     }
 
+    #[test]
+    fn rotate_validator_key() {
+        let tmint1 = "vM+mkdPMvplfxO7wM57z4FXy0TlBC2Onb+MaqcXE8ig=";
+        let tmint2 = "2HWbuGk04WQm/CrI/0HxoEtjGY0DXp8oMY6RsyrWwbU=";
+        let tmint3 = "TtJ9B7yGXANFIJqH2LJO8JN6M2WOn2w7sRN0HHi14UE=";
+        let aleo1 = account_keys();
+        let aleo2 = account_keys();
+        let validator1 = Validator::from_str(tmint1, &aleo1.1.to_string(), 5).unwrap();
+        let validator2 = Validator::from_str(tmint2, &aleo2.1.to_string(), 5).unwrap();
+        let new_key = Validator::from_str(tmint3, &aleo1.1.to_string(), 0)
+            .unwrap()
+            .pub_key;
+
+        let set = ValidatorSet::new_in_memory();
+        set.replace(vec![validator1.clone(), validator2.clone()]);
+        set.begin_block(&validator1.address(), HashMap::new(), 1);
+
+        // can't rotate to the key of another validator
+        let error = set
+            .validate_rotation(&validator1.pub_key, &validator2.pub_key)
+            .unwrap_err();
+        assert_eq!(
+            "the new key belongs to a known validator",
+            error.to_string()
+        );
+
+        set.validate_rotation(&validator1.pub_key, &new_key)
+            .unwrap();
+        set.rotate(&validator1.pub_key, new_key);
+
+        // the old key is removed from the consensus and the new one takes its voting power
+        let mut updates = set.pending_updates();
+        updates.sort_by_key(|v| v.voting_power);
+        assert_eq!(2, updates.len());
+        assert_eq!(validator1.address(), updates[0].address());
+        assert_eq!(0, updates[0].voting_power);
+        assert_eq!(pub_key_to_address(&new_key), updates[1].address());
+        assert_eq!(5, updates[1].voting_power);
+        assert_eq!(aleo1.1, updates[1].aleo_address);
+
+        // the old key can't be rotated again
+        assert!(set
+            .validate_rotation(&validator1.pub_key, &new_key)
+            .is_err());
+
+        // stakes made to the old key apply to the new one
+        let unstake = Stake::new(tmint1, aleo1.1, -5).unwrap();
+        set.validate(&unstake).unwrap();
+        set.apply(unstake);
+        let rotated = set.get(&pub_key_to_address(&new_key)).unwrap();
+        assert_eq!(0, rotated.voting_power);
+    }
+
+    #[test]
+    fn active_set_limits() {
+        let tmint1 = "vM+mkdPMvplfxO7wM57z4FXy0TlBC2Onb+MaqcXE8ig=";
+        let tmint2 = "2HWbuGk04WQm/CrI/0HxoEtjGY0DXp8oMY6RsyrWwbU=";
+        let tmint3 = "TtJ9B7yGXANFIJqH2LJO8JN6M2WOn2w7sRN0HHi14UE=";
+        let aleo1 = account_keys();
+        let aleo2 = account_keys();
+        let aleo3 = account_keys();
+        let validator1 = Validator::from_str(tmint1, &aleo1.1.to_string(), 10).unwrap();
+        let validator2 = Validator::from_str(tmint2, &aleo2.1.to_string(), 5).unwrap();
+        let validator3 = Validator::from_str(tmint3, &aleo3.1.to_string(), 1).unwrap();
+        let addresses = |validators: &[Validator]| -> Vec<Address> {
+            validators
+                .iter()
+                .map(|validator| validator.address())
+                .collect()
+        };
+
+        let set = ValidatorSet::new_in_memory();
+        set.replace(vec![
+            validator1.clone(),
+            validator2.clone(),
+            validator3.clone(),
+        ]);
+
+        // without limits every validator with stake is active
+        set.begin_block(&validator1.address(), HashMap::new(), 1);
+        let (entered, left) = set.select_active();
+        assert!(entered.is_empty() && left.is_empty());
+        assert!(set.pending_updates().is_empty());
+
+        // only the validator with the most stake fits, and the third one is below the minimum anyway
+        set.set_params(&Params {
+            min_self_stake: 2,
+            max_validators: 1,
+            ..Params::default()
+        });
+        set.begin_block(&validator1.address(), HashMap::new(), 2);
+        let (entered, left) = set.select_active();
+        assert!(entered.is_empty());
+        let mut expected = vec![validator2.address(), validator3.address()];
+        expected.sort();
+        assert_eq!(expected, addresses(&left));
+        let candidate = set.get(&validator3.address()).unwrap();
+        assert!(candidate.candidate);
+        assert_eq!(0, candidate.consensus_power());
+        assert_eq!(1, candidate.voting_power);
+        let updates = set.pending_updates();
+        assert_eq!(2, updates.len());
+        assert!(updates
+            .iter()
+            .all(|validator| validator.consensus_power() == 0));
+
+        // a candidate that gets more stake than the active validator replaces it
+        set.begin_block(&validator1.address(), HashMap::new(), 3);
+        set.apply(Stake::new(tmint2, aleo2.1, 10).unwrap());
+        let (entered, left) = set.select_active();
+        assert_eq!(vec![validator2.address()], addresses(&entered));
+        assert_eq!(vec![validator1.address()], addresses(&left));
+        let mut updates = set.pending_updates();
+        updates.sort_by_key(|validator| validator.consensus_power());
+        assert_eq!(
+            vec![0, 15],
+            updates
+                .iter()
+                .map(|validator| validator.consensus_power())
+                .collect::<Vec<_>>()
+        );
+
+        // candidates that stay out of the set aren't reported to tendermint, which doesn't know them
+        set.begin_block(&validator2.address(), HashMap::new(), 4);
+        set.apply(Stake::new(tmint3, aleo3.1, 1).unwrap());
+        let (entered, left) = set.select_active();
+        assert!(entered.is_empty() && left.is_empty());
+        assert!(set.pending_updates().is_empty());
+    }
+
+    #[test]
+    fn tombstone_double_signers() {
+        let tmint1 = "vM+mkdPMvplfxO7wM57z4FXy0TlBC2Onb+MaqcXE8ig=";
+        let tmint2 = "2HWbuGk04WQm/CrI/0HxoEtjGY0DXp8oMY6RsyrWwbU=";
+        let tmint3 = "TtJ9B7yGXANFIJqH2LJO8JN6M2WOn2w7sRN0HHi14UE=";
+        let aleo1 = account_keys();
+        let aleo2 = account_keys();
+        let validator1 = Validator::from_str(tmint1, &aleo1.1.to_string(), 100).unwrap();
+        let validator2 = Validator::from_str(tmint2, &aleo2.1.to_string(), 100).unwrap();
+        let new_key = Validator::from_str(tmint3, &aleo1.1.to_string(), 0)
+            .unwrap()
+            .pub_key;
+
+        let set = ValidatorSet::new_in_memory();
+        set.replace(vec![validator1.clone(), validator2.clone()]);
+        set.begin_block(&validator2.address(), HashMap::new(), 10);
+
+        let tombstoned = set.tombstone(&validator1.address(), 8).unwrap();
+        assert_eq!(
+            Some(Offense {
+                height: 8,
+                slashed_at: 10,
+                slashed: 5,
+                unbonding_height: 10 + UNBONDING_BLOCKS,
+            }),
+            tombstoned.tombstone
+        );
+        assert_eq!(95, tombstoned.voting_power);
+        assert_eq!(0, tombstoned.consensus_power());
+        // the same offense can be reported more than once, but it's only punished the first time
+        assert!(set.tombstone(&validator1.address(), 8).is_none());
+        assert!(set.tombstone(&pub_key_to_address(&new_key), 8).is_none());
+
+        // the validator leaves the consensus
+        let (entered, left) = set.select_active();
+        assert!(entered.is_empty());
+        assert_eq!(validator1.address(), left[0].address());
+        let updates = set.pending_updates();
+        assert_eq!(1, updates.len());
+        assert_eq!(0, updates[0].consensus_power());
+
+        // and it can't come back through stakes nor by rotating its key
+        let stake = Stake::new(tmint1, aleo1.1, 10).unwrap();
+        assert!(set.validate(&stake).is_err());
+        assert!(set
+            .validate_rotation(&validator1.pub_key, &new_key)
+            .is_err());
+
+        // what's left of the stake can be unstaked after the unbonding period
+        let unstake = Stake::new(tmint1, aleo1.1, -95).unwrap();
+        let error = set.validate(&unstake).unwrap_err();
+        assert!(error.to_string().contains("can be unstaked from height"));
+        set.begin_block(&validator2.address(), HashMap::new(), 10 + UNBONDING_BLOCKS);
+        set.validate(&unstake).unwrap();
+        set.apply(unstake);
+        assert_eq!(0, set.get(&validator1.address()).unwrap().voting_power);
+
+        // the offense stays in the registry
+        let tombstones = set.tombstones();
+        assert_eq!(1, tombstones.len());
+        assert_eq!(validator1.address(), tombstones[0].address());
+    }
+
     pub fn account_keys() -> (vm::ViewKey, vm::Address) {
         let private_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
         let view_key = vm::ViewKey::try_from(&private_key).unwrap();
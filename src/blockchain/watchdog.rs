@@ -0,0 +1,169 @@
+use crate::application::SnarkVMApp;
+use anyhow::{anyhow, ensure, Result};
+use serde::Serialize;
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
+
+/// How often the watchdog checks for new blocks.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Settings of the block production watchdog, see `start`.
+#[derive(Clone, Debug)]
+pub struct WatchdogConfig {
+    /// Time without committed blocks after which block production is considered stalled.
+    pub timeout: Duration,
+    /// Url that gets a POST with a `StallAlert` in JSON when block production stalls, if any.
+    pub webhook: Option<String>,
+    /// Url of the tendermint RPC of the node, used to read the size of its mempool.
+    pub tendermint_url: String,
+}
+
+/// Sent to the webhook, and logged, when no block is committed within the watchdog timeout.
+#[derive(Debug, Serialize)]
+pub struct StallAlert {
+    /// Last committed block height.
+    pub height: i64,
+    pub seconds_since_commit: u64,
+    /// Transactions in the tendermint mempool, if it could be read.
+    pub mempool_size: Option<u64>,
+    /// Last error processing a block, if any since the process started.
+    pub last_error: Option<String>,
+}
+
+/// Change in the block production reported by `StallDetector::check`.
+#[derive(Debug, PartialEq, Eq)]
+enum StallChange {
+    Stalled,
+    Resumed,
+}
+
+/// Tracks whether block production is stalled, so each stall is reported once.
+#[derive(Debug)]
+struct StallDetector {
+    timeout: Duration,
+    stalled: bool,
+}
+
+impl StallDetector {
+    fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            stalled: false,
+        }
+    }
+
+    /// Given the time since the last commit, return whether block production just stalled or resumed.
+    fn check(&mut self, since_last_commit: Duration) -> Option<StallChange> {
+        let stalled = since_last_commit > self.timeout;
+        if stalled == self.stalled {
+            return None;
+        }
+        self.stalled = stalled;
+        Some(if stalled {
+            StallChange::Stalled
+        } else {
+            StallChange::Resumed
+        })
+    }
+}
+
+/// Start watching the block production of the node, in a thread of its own. When no block is committed within the
+/// timeout, an alert with the current height, mempool size and last error is logged and posted to the webhook.
+/// It's reported once per stall, and again after blocks were committed in between.
+pub fn start(app: SnarkVMApp, config: WatchdogConfig) {
+    std::thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("could not start the block production watchdog");
+        runtime.block_on(async move {
+            let http = reqwest::Client::new();
+            let started = Instant::now();
+            let mut detector = StallDetector::new(config.timeout);
+            info!(
+                "Watching block production, alerting after {}s without blocks",
+                config.timeout.as_secs()
+            );
+            loop {
+                tokio::time::sleep(CHECK_INTERVAL).await;
+                let report = app.health_report();
+                // before the first commit, count from the start of the process
+                let since_last_commit = report
+                    .seconds_since_commit
+                    .map_or_else(|| started.elapsed(), Duration::from_secs);
+
+                match detector.check(since_last_commit) {
+                    Some(StallChange::Stalled) => {
+                        let alert = StallAlert {
+                            height: report.height,
+                            seconds_since_commit: since_last_commit.as_secs(),
+                            mempool_size: mempool_size(&http, &config.tendermint_url)
+                                .await
+                                .map_err(|e| warn!("Could not read the mempool size: {e}"))
+                                .ok(),
+                            last_error: report.last_error,
+                        };
+                        error!("Block production stalled: {}", serde_json::json!(alert));
+                        if let Some(webhook) = &config.webhook {
+                            if let Err(e) = post_alert(&http, webhook, &alert).await {
+                                error!("Could not send the stall alert to {webhook}: {e}");
+                            }
+                        }
+                    }
+                    Some(StallChange::Resumed) => {
+                        info!("Block production resumed at height {}", report.height)
+                    }
+                    None => {}
+                }
+            }
+        });
+    });
+}
+
+/// Read the amount of transactions in the mempool from the tendermint RPC.
+async fn mempool_size(http: &reqwest::Client, tendermint_url: &str) -> Result<u64> {
+    let url = format!(
+        "{}/num_unconfirmed_txs",
+        tendermint_url.trim_end_matches('/')
+    );
+    let response: serde_json::Value = http.get(&url).send().await?.json().await?;
+    // tendermint encodes integers as strings
+    response["result"]["total"]
+        .as_str()
+        .and_then(|total| total.parse().ok())
+        .ok_or_else(|| anyhow!("unexpected response from {url}: {response}"))
+}
+
+async fn post_alert(http: &reqwest::Client, webhook: &str, alert: &StallAlert) -> Result<()> {
+    let status = http.post(webhook).json(alert).send().await?.status();
+    ensure!(
+        status.is_success(),
+        "the webhook answered with status {status}"
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stalls_are_reported_once() {
+        let mut detector = StallDetector::new(Duration::from_secs(30));
+        assert_eq!(None, detector.check(Duration::from_secs(5)));
+        assert_eq!(None, detector.check(Duration::from_secs(30)));
+        assert_eq!(
+            Some(StallChange::Stalled),
+            detector.check(Duration::from_secs(31))
+        );
+        assert_eq!(None, detector.check(Duration::from_secs(120)));
+        assert_eq!(
+            Some(StallChange::Resumed),
+            detector.check(Duration::from_secs(1))
+        );
+        assert_eq!(
+            Some(StallChange::Stalled),
+            detector.check(Duration::from_secs(40))
+        );
+    }
+}
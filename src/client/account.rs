@@ -1,30 +1,159 @@
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, ensure, Result};
+use bip39::Mnemonic;
+use lib::decryptor::ViewKeyDecryptor;
 use lib::vm;
 use log::debug;
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::fs;
 use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Credentials already read by this process, so long running sessions like the REPL only load them once.
+static LOADED: Mutex<Option<Credentials>> = Mutex::new(None);
+
 /// File that stores the public and private keys associated with an account.
 /// Stores it at $ALEO_HOME/account.json, with ~/.aleo as the default ALEO_HOME.
-#[derive(Serialize, Deserialize)]
+/// Watch-only accounts have no private key: they can read the records of the account but not sign transactions.
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Credentials {
-    pub private_key: vm::PrivateKey,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub private_key: Option<vm::PrivateKey>,
     pub view_key: vm::ViewKey,
     pub address: vm::Address,
+    /// Accounts imported with `account merge`, whose records are listed and spent along with the ones of this account.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub merged: Vec<Credentials>,
 }
 
 impl Credentials {
     pub fn new() -> Result<Self> {
-        let private_key = vm::PrivateKey::new(&mut rand::thread_rng())?;
+        Self::from_private_key(vm::PrivateKey::new(&mut rand::thread_rng())?)
+    }
+
+    /// Generate a new 24 words mnemonic, to derive accounts with `from_mnemonic`.
+    pub fn new_mnemonic() -> Result<Mnemonic> {
+        let mut entropy = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut entropy);
+        Ok(Mnemonic::from_entropy(&entropy)?)
+    }
+
+    /// Derive the account with the given index from a BIP39 mnemonic. The private key is sampled from an rng seeded
+    /// with the hash of the mnemonic seed and the index, so the same words and index always give the same account.
+    /// Note this derivation is specific to this client, other Aleo wallets derive different accounts from the words.
+    pub fn from_mnemonic(mnemonic: &Mnemonic, index: u32) -> Result<Self> {
+        let mut hasher = Sha256::new();
+        hasher.update(b"aleo-account");
+        hasher.update(mnemonic.to_seed(""));
+        hasher.update(index.to_be_bytes());
+        let mut rng = ChaCha20Rng::from_seed(hasher.finalize().into());
+        Self::from_private_key(vm::PrivateKey::new(&mut rng)?)
+    }
+
+    fn from_private_key(private_key: vm::PrivateKey) -> Result<Self> {
         let view_key = vm::ViewKey::try_from(&private_key)?;
+        Ok(Self {
+            private_key: Some(private_key),
+            ..Self::watch_only(view_key)?
+        })
+    }
+
+    /// Credentials of a watch-only account, which can only decrypt the records of the account.
+    pub fn watch_only(view_key: vm::ViewKey) -> Result<Self> {
         let address = vm::Address::try_from(&view_key)?;
         Ok(Self {
-            private_key,
+            private_key: None,
             view_key,
             address,
+            merged: Vec::new(),
+        })
+    }
+
+    /// Import the other account, along with the ones merged into it. Accounts already known are skipped, unless
+    /// they were watch-only and the other file has their private key. Returns the addresses of the imported accounts.
+    #[allow(clippy::clone_on_copy)]
+    pub fn merge(&mut self, mut other: Credentials) -> Vec<vm::Address> {
+        let mut imported = Vec::new();
+        let others = std::mem::take(&mut other.merged);
+        for account in std::iter::once(other).chain(others) {
+            if account.address == self.address {
+                continue;
+            }
+            let address = account.address.clone();
+            match self
+                .merged
+                .iter_mut()
+                .find(|merged| merged.address == account.address)
+            {
+                Some(merged) if merged.private_key.is_none() && account.private_key.is_some() => {
+                    *merged = account;
+                }
+                Some(_) => continue,
+                None => self.merged.push(account),
+            }
+            imported.push(address);
+        }
+        imported
+    }
+
+    /// Credentials of the account of a program, see `vm::program_account`. Its keys are public, so it's only useful
+    /// to sign the executions of the program that spend the records it owns.
+    pub fn of_program(program_id: &vm::ProgramID) -> Result<Self> {
+        Self::from_private_key(vm::program_account(program_id)?.private_key)
+    }
+
+    /// This account followed by the merged ones, each on its own.
+    pub fn accounts(&self) -> Vec<Credentials> {
+        std::iter::once(self)
+            .chain(&self.merged)
+            .map(|account| Credentials {
+                merged: Vec::new(),
+                ..account.clone()
+            })
+            .collect()
+    }
+
+    /// Return the account that owns all of the given records, to sign the transactions that spend them.
+    /// It's this account if there are no records.
+    #[allow(clippy::clone_on_copy)]
+    pub fn owner_of(&self, records: &[vm::Record]) -> Result<Credentials> {
+        let mut accounts = self.accounts();
+        let owner = match records.first() {
+            Some(record) => vm::record_owner(record)?,
+            None => self.address.clone(),
+        };
+        for record in records {
+            ensure!(
+                vm::record_owner(record)? == owner,
+                "the input records are owned by different accounts, which can't sign the same transaction"
+            );
+        }
+        let position = accounts
+            .iter()
+            .position(|account| account.address == owner)
+            .ok_or_else(|| anyhow!("the input records are owned by {owner}, which is not one of the merged accounts"))?;
+        Ok(accounts.swap_remove(position))
+    }
+
+    /// Return the private key to sign transactions with, failing for watch-only accounts.
+    pub fn private_key(&self) -> Result<&vm::PrivateKey> {
+        self.private_key.as_ref().ok_or_else(|| {
+            anyhow!("{} is a watch-only account and can't sign transactions; build them with `aleo tx build` and sign them with `aleo tx sign` where the private key is kept", self.address)
         })
     }
 
+    /// Return a decryptor of the account records. Watch-only accounts derive the serial numbers of their records
+    /// with the view key, which is only possible on some VM backends.
+    #[allow(clippy::clone_on_copy)]
+    pub fn decryptor(&self) -> Result<ViewKeyDecryptor> {
+        match &self.private_key {
+            Some(private_key) => ViewKeyDecryptor::with_private_key(private_key.clone()),
+            None => ViewKeyDecryptor::new(self.view_key.clone()),
+        }
+    }
+
     pub fn save(&self) -> Result<PathBuf> {
         let file = Self::path();
         let dir = file.parent().unwrap();
@@ -32,15 +161,98 @@ impl Credentials {
         debug!("Saving credentials to {}", file.to_string_lossy());
         let account_json = serde_json::to_string(&self)?;
         fs::write(file.clone(), account_json)?;
+        *LOADED.lock().unwrap() = Some(self.clone());
         Ok(file)
     }
 
     pub fn load() -> Result<Self> {
+        let mut loaded = LOADED.lock().unwrap();
+        if let Some(credentials) = loaded.as_ref() {
+            return Ok(credentials.clone());
+        }
         let account_json = fs::read_to_string(Self::path())?;
-        serde_json::from_str(&account_json).map_err(|e| anyhow!(e))
+        let credentials: Self = serde_json::from_str(&account_json).map_err(|e| anyhow!(e))?;
+        *loaded = Some(credentials.clone());
+        Ok(credentials)
     }
 
     fn path() -> PathBuf {
         lib::aleo_home().join("account.json")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn accounts_are_derived_from_the_mnemonic_and_index() {
+        let mnemonic = Credentials::new_mnemonic().unwrap();
+        assert_eq!(24, mnemonic.word_count());
+        let account = Credentials::from_mnemonic(&mnemonic, 0).unwrap();
+
+        let imported = Mnemonic::from_str(&mnemonic.to_string()).unwrap();
+        assert_eq!(
+            account.address,
+            Credentials::from_mnemonic(&imported, 0).unwrap().address
+        );
+        assert_ne!(
+            account.address,
+            Credentials::from_mnemonic(&imported, 1).unwrap().address
+        );
+    }
+
+    #[test]
+    #[allow(clippy::clone_on_copy)]
+    fn merged_accounts_sign_for_their_records() {
+        let mut account = Credentials::new().unwrap();
+        let other = Credentials::new().unwrap();
+        let watch_only = Credentials::watch_only(other.view_key.clone()).unwrap();
+
+        assert_eq!(vec![other.address.clone()], account.merge(watch_only));
+        // known accounts are only imported again to add their private key
+        assert!(account
+            .merge(Credentials::watch_only(other.view_key.clone()).unwrap())
+            .is_empty());
+        assert_eq!(vec![other.address.clone()], account.merge(other.clone()));
+        let itself = account.clone();
+        assert!(account.merge(itself).is_empty());
+        assert_eq!(2, account.accounts().len());
+        assert!(account.merged[0].private_key.is_some());
+
+        let record = |owner: &vm::Address| {
+            let (_, ciphertext) = vm::mint_record("credits.aleo", "credits", owner, 10, 0).unwrap();
+            ciphertext
+        };
+        let own = record(&account.address).decrypt(&account.view_key).unwrap();
+        let merged = record(&other.address).decrypt(&other.view_key).unwrap();
+        assert_eq!(account.address, account.owner_of(&[]).unwrap().address);
+        assert_eq!(
+            account.address,
+            account.owner_of(&[own.clone()]).unwrap().address
+        );
+        assert_eq!(
+            other.address,
+            account.owner_of(&[merged.clone()]).unwrap().address
+        );
+        assert!(account.owner_of(&[own, merged]).is_err());
+    }
+
+    #[test]
+    #[allow(clippy::clone_on_copy)]
+    fn watch_only_accounts_can_not_sign() {
+        let account = Credentials::new().unwrap();
+        assert!(account.private_key().is_ok());
+
+        let watch_only = Credentials::watch_only(account.view_key.clone()).unwrap();
+        assert_eq!(account.address, watch_only.address);
+        assert!(watch_only.private_key().is_err());
+
+        // the private key is left out of the account file
+        let json = serde_json::to_value(&watch_only).unwrap();
+        assert!(json.get("private_key").is_none());
+        let loaded: Credentials = serde_json::from_value(json).unwrap();
+        assert!(loaded.private_key.is_none());
+    }
+}
@@ -0,0 +1,183 @@
+use crate::account::Credentials;
+use age::secrecy::Secret;
+use anyhow::{anyhow, bail, ensure, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, BufRead, Read, Write};
+use std::path::Path;
+
+/// Version of the backup contents, increased when they change in a way older clients can't restore.
+const BACKUP_VERSION: u8 = 1;
+
+/// Files of the client home that make up the wallet: the account keys, including the merged accounts,
+/// the client config with the fee record, the created tokens, the transaction history and the pinned chain id.
+/// The cached program keys aren't included, since they are built again when missing.
+const WALLET_FILES: [&str; 5] = [
+    "account.json",
+    "config.json",
+    "tokens.json",
+    "history.json",
+    "chain_id",
+];
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Backup {
+    version: u8,
+    /// Contents of the backed up files, by name in the client home.
+    files: BTreeMap<String, String>,
+}
+
+/// Save the wallet files of the given client home to a file encrypted with the passphrase, see `WALLET_FILES`.
+/// The file is in the [age](https://age-encryption.org) format, so it can also be decrypted with the age tools.
+pub fn create(home: &Path, out: &Path, passphrase: &str) -> Result<serde_json::Value> {
+    ensure!(
+        home.join("account.json").exists(),
+        "there's no account to back up in {}",
+        home.display()
+    );
+    ensure!(
+        !out.exists(),
+        "{} already exists, remove it or choose another path",
+        out.display()
+    );
+
+    let mut files = BTreeMap::new();
+    for name in WALLET_FILES {
+        if let Ok(contents) = fs::read_to_string(home.join(name)) {
+            files.insert(name.to_string(), contents);
+        }
+    }
+    let names: Vec<String> = files.keys().cloned().collect();
+    let backup = Backup {
+        version: BACKUP_VERSION,
+        files,
+    };
+    fs::write(out, encrypt(&serde_json::to_vec(&backup)?, passphrase)?)?;
+    Ok(json!({ "path": out, "files": names }))
+}
+
+/// Write the wallet files of the backup to the given client home. Unless forced, it fails if the home already has
+/// a different account, since its keys would be lost.
+pub fn restore(
+    home: &Path,
+    backup: &Path,
+    passphrase: &str,
+    force: bool,
+) -> Result<serde_json::Value> {
+    let backup: Backup = serde_json::from_slice(&decrypt(&fs::read(backup)?, passphrase)?)?;
+    ensure!(
+        backup.version <= BACKUP_VERSION,
+        "the backup was made by a newer client, version {} backups aren't supported",
+        backup.version
+    );
+    for name in backup.files.keys() {
+        ensure!(
+            WALLET_FILES.contains(&name.as_str()),
+            "unexpected file {name} in the backup"
+        );
+    }
+
+    let account = backup
+        .files
+        .get("account.json")
+        .ok_or_else(|| anyhow!("the backup has no account"))?;
+    let credentials: Credentials = serde_json::from_str(account)?;
+    if let Ok(current) = fs::read_to_string(home.join("account.json")) {
+        if current != *account && !force {
+            bail!(
+                "{} already has a different account, which would be lost; back it up and pass --force to replace it",
+                home.display()
+            );
+        }
+    }
+
+    fs::create_dir_all(home)?;
+    for (name, contents) in &backup.files {
+        fs::write(home.join(name), contents)?;
+    }
+    let names: Vec<&String> = backup.files.keys().collect();
+    Ok(json!({ "address": credentials.address, "restored": names }))
+}
+
+/// Return the given passphrase, or else prompt for it on stdin.
+pub fn passphrase(given: Option<String>) -> Result<String> {
+    let passphrase = match given {
+        Some(passphrase) => passphrase,
+        None => {
+            eprint!("Backup passphrase: ");
+            io::stderr().flush()?;
+            let mut line = String::new();
+            io::stdin().lock().read_line(&mut line)?;
+            line.trim_end_matches(['\r', '\n']).to_string()
+        }
+    };
+    ensure!(!passphrase.is_empty(), "the passphrase can't be empty");
+    Ok(passphrase)
+}
+
+fn encrypt(plaintext: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    let encryptor = age::Encryptor::with_user_passphrase(Secret::new(passphrase.to_string()));
+    let mut encrypted = Vec::new();
+    let mut writer = encryptor.wrap_output(&mut encrypted)?;
+    writer.write_all(plaintext)?;
+    writer.finish()?;
+    Ok(encrypted)
+}
+
+fn decrypt(encrypted: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    let decryptor = match age::Decryptor::new(encrypted)? {
+        age::Decryptor::Passphrase(decryptor) => decryptor,
+        _ => bail!("the backup isn't encrypted with a passphrase"),
+    };
+    let mut reader = decryptor
+        .decrypt(&Secret::new(passphrase.to_string()), None)
+        .map_err(|e| anyhow!("could not decrypt the backup, check the passphrase: {e}"))?;
+    let mut decrypted = Vec::new();
+    reader.read_to_end(&mut decrypted)?;
+    Ok(decrypted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::TempDir;
+
+    #[test]
+    fn backups_are_restored_with_the_passphrase() {
+        let dir = TempDir::new().unwrap();
+        let (home, other_home) = (dir.path().join("home"), dir.path().join("other"));
+        let account = serde_json::to_string(&Credentials::new().unwrap()).unwrap();
+        fs::create_dir_all(&home).unwrap();
+        fs::write(home.join("account.json"), &account).unwrap();
+        fs::write(home.join("chain_id"), "test-chain").unwrap();
+        fs::write(home.join("notes.txt"), "not part of the wallet").unwrap();
+
+        let backup = dir.path().join("backup.age");
+        create(&home, &backup, "correct horse").unwrap();
+        assert!(create(&home, &backup, "correct horse").is_err());
+        assert!(!String::from_utf8_lossy(&fs::read(&backup).unwrap()).contains("chain_id"));
+
+        assert!(restore(&other_home, &backup, "wrong horse", false).is_err());
+        let output = restore(&other_home, &backup, "correct horse", false).unwrap();
+        assert_eq!(json!(["account.json", "chain_id"]), output["restored"]);
+        assert_eq!(
+            account,
+            fs::read_to_string(other_home.join("account.json")).unwrap()
+        );
+        assert!(!other_home.join("notes.txt").exists());
+        // restoring the same account again is fine
+        restore(&other_home, &backup, "correct horse", false).unwrap();
+
+        // a different account is only replaced when forced
+        let other_account = serde_json::to_string(&Credentials::new().unwrap()).unwrap();
+        fs::write(other_home.join("account.json"), other_account).unwrap();
+        assert!(restore(&other_home, &backup, "correct horse", false).is_err());
+        restore(&other_home, &backup, "correct horse", true).unwrap();
+        assert_eq!(
+            account,
+            fs::read_to_string(other_home.join("account.json")).unwrap()
+        );
+    }
+}
@@ -0,0 +1,160 @@
+use anyhow::{anyhow, Result};
+use lib::vm;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+/// Timings and sizes of running a program function locally, as reported by `program bench`.
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub program: String,
+    pub function: String,
+    pub key_synthesis_ms: u128,
+    /// Time to sign the requests, None on backends that can't authorize apart from proving.
+    pub authorization_ms: Option<u128>,
+    pub proving_ms: u128,
+    /// Time the nodes take to verify the proof of the function, which is done for every execution they receive.
+    pub verification_ms: u128,
+    /// Constraints of the function circuit, None on backends whose keys don't expose it.
+    pub constraints: Option<usize>,
+    pub verifying_key_bytes: usize,
+    /// Size of the transitions of the execution, which makes most of the size of the transaction.
+    pub transitions_bytes: usize,
+}
+
+/// Synthesize the keys of the function and run it with the given inputs, timing each step. Only the transition
+/// of the function itself is verified, the ones of the functions it calls in other programs need their keys.
+#[allow(clippy::clone_on_copy)]
+pub fn run(
+    program: vm::Program,
+    function: vm::Identifier,
+    inputs: &[vm::UserInputValueType],
+    private_key: &vm::PrivateKey,
+) -> Result<Report> {
+    let program_id = program.id().to_string();
+    let function_name = function.to_string();
+
+    let start = Instant::now();
+    let (proving_key, verifying_key) =
+        vm::synthesize_function_keys(&program, &mut rand::thread_rng(), &function)?;
+    let key_synthesis = start.elapsed();
+
+    let (authorization, proving, transitions) =
+        execute(program, function.clone(), inputs, private_key, proving_key)?;
+
+    let transition = transitions
+        .last()
+        .ok_or_else(|| anyhow!("the execution produced no transitions"))?;
+    let verifying_keys = vm::VerifyingKeyMap {
+        map: [(function, verifying_key.clone())].into_iter().collect(),
+    };
+    let start = Instant::now();
+    vm::verify_execution(transition, &verifying_keys)?;
+    let verification = start.elapsed();
+
+    Ok(Report {
+        program: program_id,
+        function: function_name,
+        key_synthesis_ms: key_synthesis.as_millis(),
+        authorization_ms: authorization.map(|duration| duration.as_millis()),
+        proving_ms: proving.as_millis(),
+        verification_ms: verification.as_millis(),
+        constraints: vm::num_constraints(&verifying_key),
+        verifying_key_bytes: bincode::serialized_size(&verifying_key)? as usize,
+        transitions_bytes: bincode::serialized_size(&transitions)? as usize,
+    })
+}
+
+/// Authorize and prove the execution separately, timing each step.
+#[cfg(feature = "snarkvm_backend")]
+fn execute(
+    program: vm::Program,
+    function: vm::Identifier,
+    inputs: &[vm::UserInputValueType],
+    private_key: &vm::PrivateKey,
+    proving_key: vm::ProvingKey,
+) -> Result<(Option<Duration>, Duration, Vec<vm::Transition>)> {
+    let start = Instant::now();
+    let authorization = vm::authorize(program, function, inputs, private_key)?;
+    let authorization_time = start.elapsed();
+
+    let start = Instant::now();
+    let transitions = vm::prove(authorization, Some(proving_key))?;
+    Ok((Some(authorization_time), start.elapsed(), transitions))
+}
+
+/// This backend can't authorize executions apart from proving them, so the whole execution is timed as proving.
+#[cfg(feature = "lambdavm_backend")]
+fn execute(
+    program: vm::Program,
+    function: vm::Identifier,
+    inputs: &[vm::UserInputValueType],
+    private_key: &vm::PrivateKey,
+    proving_key: vm::ProvingKey,
+) -> Result<(Option<Duration>, Duration, Vec<vm::Transition>)> {
+    let start = Instant::now();
+    let transitions = vm::execution(program, function, inputs, private_key, Some(proving_key))?;
+    Ok((None, start.elapsed(), transitions))
+}
+
+/// Render the report as a table of steps and their timings, followed by the sizes.
+pub fn table(report: &Report) -> String {
+    let ms = |millis: u128| format!("{:.3}s", millis as f64 / 1000.0);
+    let rows = [
+        ("key synthesis", ms(report.key_synthesis_ms)),
+        (
+            "authorization",
+            report
+                .authorization_ms
+                .map(ms)
+                .unwrap_or_else(|| "n/a".to_string()),
+        ),
+        ("proving", ms(report.proving_ms)),
+        ("verification", ms(report.verification_ms)),
+        (
+            "constraints",
+            report
+                .constraints
+                .map(|constraints| constraints.to_string())
+                .unwrap_or_else(|| "n/a".to_string()),
+        ),
+        (
+            "verifying key",
+            format!("{} bytes", report.verifying_key_bytes),
+        ),
+        ("transitions", format!("{} bytes", report.transitions_bytes)),
+    ];
+
+    let mut table = format!("{}/{}\n", report.program, report.function);
+    for (step, value) in rows {
+        table.push_str(&format!("  {step:<15}{value:>16}\n"));
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_table() {
+        let report = Report {
+            program: "hello.aleo".to_string(),
+            function: "hello".to_string(),
+            key_synthesis_ms: 12_345,
+            authorization_ms: None,
+            proving_ms: 1_500,
+            verification_ms: 20,
+            constraints: Some(1024),
+            verifying_key_bytes: 700,
+            transitions_bytes: 2048,
+        };
+        let table = table(&report);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!("hello.aleo/hello", lines[0]);
+        assert_eq!(8, lines.len());
+        assert!(lines[1].starts_with("  key synthesis") && lines[1].ends_with("12.345s"));
+        assert!(lines[2].ends_with("n/a"));
+        assert!(lines[4].ends_with("0.020s"));
+        assert!(lines[5].ends_with("1024"));
+    }
+}
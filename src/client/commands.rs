@@ -1,19 +1,42 @@
-use crate::{account, tendermint};
-use anyhow::{anyhow, bail, Result};
+use crate::config::ClientConfig;
+use crate::exit_code::{bad_arguments, not_found};
+use crate::node::{self, NodeDirs};
+use crate::record_cache::{self, RecordCache};
+use crate::token::{self, TokenMetadata, TokenRegistry};
+use crate::{account, backup, bench, debug, history, inputs, tendermint};
+use anyhow::{anyhow, bail, ensure, Result};
+use bip39::Mnemonic;
 use clap::Parser;
+use indicatif::{ProgressBar, ProgressStyle};
 use itertools::Itertools;
+use lib::analysis;
+use lib::bridge;
+use lib::broadcast::BroadcastOutcome;
+use lib::client::Client;
+use lib::decryptor::{RecordDecryptor, ViewKeyDecryptor};
+use lib::governance;
+use lib::names;
 use lib::program_file::ProgramFile;
-use lib::query::AbciQuery;
-use lib::transaction::Transaction;
-use lib::vm::{self, compute_serial_number};
+use lib::query::RecordEntry;
+use lib::transaction::{
+    AuthorizedExecution, Transaction, UnsignedTransaction, MAX_TRANSACTION_BYTES,
+};
+use lib::validator::{pub_key_to_address, ValidatorKey};
+use lib::vm;
 #[allow(unused_imports)]
 use lib::vm::{EncryptedRecord, ProgramID};
-use log::debug;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
 use serde_json::json;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::Mutex;
+
+/// Programs already fetched from the blockchain by id. Deployed programs don't change,
+/// so a long running session like the REPL only needs to fetch each of them once.
+static PROGRAM_CACHE: Mutex<Option<HashMap<String, vm::Program>>> = Mutex::new(None);
 
 #[derive(Debug, Parser)]
 pub enum Command {
@@ -23,18 +46,252 @@ pub enum Command {
     Credits(Credits),
     #[clap(subcommand)]
     Program(Program),
+    #[clap(subcommand)]
+    Governance(Governance),
+    #[clap(subcommand)]
+    Records(Records),
+    #[clap(subcommand)]
+    Token(Token),
+    #[clap(subcommand)]
+    Bridge(Bridge),
+    #[clap(subcommand)]
+    Tx(Tx),
+    #[clap(subcommand)]
+    Mempool(Mempool),
+    #[clap(subcommand)]
+    Validator(Validator),
+    #[clap(subcommand)]
+    Block(Block),
+    #[clap(subcommand)]
+    Node(Node),
+    #[clap(subcommand)]
+    Config(Config),
+    #[clap(subcommand)]
+    Backup(Backup),
+    #[clap(subcommand)]
+    History(History),
     #[clap(name = "get")]
     Get(Get),
+    #[clap(name = "audit")]
+    Audit(Audit),
+    /// Shows a summary of the chain: its id, height, app hash, amounts of transactions, records and active validators,
+    /// base fee and the version of the node.
+    Status,
+    /// Prints the completion script of the given shell for this client, e.g. `aleo completions bash > /etc/bash_completion.d/aleo`
+    Completions {
+        #[clap(value_enum)]
+        shell: clap_complete::Shell,
+    },
+    /// Starts an interactive session that runs commands, e.g. `account balance`, without restarting the client.
+    /// The account credentials are loaded once, and the programs fetched from the blockchain are kept for the session.
+    Repl,
+    /// Serves a JSON-RPC API on localhost backed by this account, so other applications on the host can check its
+    /// balance and records, and build and send transfers, without running the client for each call.
+    /// The methods are getBalance, getRecords, getChainStatus, buildTransfer and broadcast.
+    Serve {
+        /// Port to listen on.
+        #[clap(long, default_value_t = 8080)]
+        port: u16,
+    },
+}
+
+/// Commands to manage the client settings, stored in $ALEO_HOME/config.json.
+#[derive(Debug, Parser)]
+pub enum Config {
+    /// Shows the client settings.
+    Show,
+    /// Sets the fee paid by the commands that take a `--fee` when it isn't passed.
+    SetFee {
+        /// Amount of gates to pay as fee.
+        #[clap(value_parser)]
+        amount: u64,
+        /// Record to pay the fees with when `--fee-record` isn't passed. After each transaction that pays with it,
+        /// the change of the fee becomes the new fee record. If omitted, the record with most gates is picked each time.
+        #[clap(long, value_parser=parse_fee_record)]
+        record: Option<vm::EncryptedRecord>,
+    },
+    /// Removes the default fee and fee record, so no fee is paid unless `--fee` is passed.
+    ClearFee,
 }
 
 /// Commands to manage accounts.
 #[derive(Debug, Parser)]
 pub enum Account {
-    New,
+    /// Creates a new account, replacing the credentials of the client profile.
+    New {
+        /// Derive the account from a new 24 words mnemonic, printed so it can be written down as a backup.
+        #[clap(long)]
+        mnemonic: bool,
+        /// Index of the account derived from the mnemonic, each index derives a different account.
+        #[clap(long, default_value_t = 0, requires = "mnemonic")]
+        index: u32,
+    },
+    /// Restores an account from the mnemonic it was created with, or imports a watch-only account from its view key,
+    /// replacing the credentials of the client profile.
+    Import {
+        /// The words of the mnemonic, separated by spaces.
+        #[clap(
+            long,
+            value_parser=parse_mnemonic,
+            required_unless_present = "view_key",
+            conflicts_with = "view_key"
+        )]
+        mnemonic: Option<Mnemonic>,
+        /// Index of the account derived from the mnemonic.
+        #[clap(long, default_value_t = 0, requires = "mnemonic")]
+        index: u32,
+        /// View key of a watch-only account: it can fetch the records and balance of the account, but not sign
+        /// transactions, which have to be built with `tx build` and signed where the private key is kept.
+        #[clap(long)]
+        view_key: Option<vm::ViewKey>,
+    },
     /// Fetches the unspent records owned by the given account.
     Records,
+    /// Imports the keys of another account file, e.g. the account.json of another client profile, so its records
+    /// are listed and spent along with the ones of this account. Transactions are signed with the key of the account
+    /// that owns their input records.
+    Merge {
+        /// Path to the account file to import.
+        #[clap(value_parser)]
+        path: PathBuf,
+    },
     /// Fetches the unspent records owned by the given account and calculates the final credits balance.
     Balance,
+    /// Waits for payments to the account in transactions that reach the mempool of a node running the pending record
+    /// relay (`aleo_abci --pending-relay-addr`), printing each one as it arrives. They aren't final until committed.
+    Pending {
+        /// Websocket url of the node pending record relay.
+        #[clap(long, default_value = "ws://127.0.0.1:26670")]
+        relay_url: String,
+        /// Stop after receiving this amount of payments, instead of waiting until the node closes the connection.
+        #[clap(long)]
+        count: Option<usize>,
+    },
+    /// Waits for records of the account, and of the accounts merged into it, in the blocks committed from now on,
+    /// printing each one as it's committed. The node record relay (`aleo_abci --pending-relay-addr`) decrypts them
+    /// with the view keys of the accounts, which are sent to it, so it should be a node you trust.
+    Watch {
+        /// Websocket url of the node record relay.
+        #[clap(long, default_value = "ws://127.0.0.1:26670")]
+        relay_url: String,
+        /// Stop after receiving this amount of records, instead of waiting until the node closes the connection.
+        #[clap(long)]
+        count: Option<usize>,
+    },
+}
+
+#[derive(Debug, Parser)]
+pub enum Records {
+    /// Join the smallest records of the account into bigger ones, until at most the target amount of records is left.
+    /// Each execution joins a batch of records, so accounts with many records may need to run this more than once.
+    Consolidate {
+        /// Amount of records the account should be left with.
+        #[clap(long)]
+        target: usize,
+        /// Maximum amount of records joined in a single execution.
+        #[clap(long, default_value_t = 10)]
+        batch_size: usize,
+        /// Amount of gates to pay as fee for each execution. If omitted not fee is paid.
+        #[clap(long)]
+        fee: Option<u64>,
+        /// Maximum amount of gates to pay in fees for the whole consolidation. Batches beyond it are skipped.
+        #[clap(long)]
+        max_fees: Option<u64>,
+    },
+    /// Breaks an unspent record of the account into records of the given amounts, so that spending one of them
+    /// doesn't tie up the whole balance until the change is committed. The amounts plus the fee have to add up to
+    /// the gates of the record.
+    Split {
+        /// Commitment of the record to split, as listed by `account records`.
+        #[clap(value_parser=vm::Field::from_str)]
+        commitment: vm::Field,
+        /// Amounts of gates of the resulting records, at least two.
+        #[clap(value_parser, num_args = 2.., required = true)]
+        amounts: Vec<u64>,
+        /// Amount of gates to pay as fee, taken out of the record. If omitted no fee is paid.
+        #[clap(long)]
+        fee: Option<u64>,
+    },
+    /// Sets an alias for an unspent record of the account, so it can be passed as `record:<alias>` to the commands
+    /// that take records. The record is given by its commitment, or a unique prefix of it, as listed by
+    /// `account records`, and the alias is dropped once the record is spent.
+    Alias {
+        /// Alias of the record, starting with a letter.
+        alias: String,
+        /// Commitment of the record, or a prefix of it.
+        commitment: String,
+    },
+    /// Decrypts a record ciphertext with the account view key, printing its plaintext.
+    Decrypt {
+        #[clap(value_parser=vm::EncryptedRecord::from_str)]
+        ciphertext: vm::EncryptedRecord,
+    },
+    /// Decrypts a record ciphertext with the account view key, printing its owner, gates and data entries along
+    /// with its commitment and serial number. It doesn't query the blockchain, so it works offline.
+    Show {
+        #[clap(value_parser=vm::EncryptedRecord::from_str)]
+        ciphertext: vm::EncryptedRecord,
+        /// Program that defines the record, part of its commitment.
+        #[clap(long, default_value = "credits.aleo")]
+        program: String,
+        /// Name of the record type in the program, part of its commitment.
+        #[clap(long, default_value = "credits")]
+        record_name: String,
+    },
+}
+
+/// Commands to create tokens from the bundled token program template.
+#[derive(Debug, Parser)]
+pub enum Token {
+    /// Deploys a new token program named after the symbol, e.g. `lmb.aleo`, and mints its supply to this account.
+    /// Only the account that created the token can receive the records minted by the program.
+    Create {
+        /// Name of the token, e.g. "Lambda Coin".
+        #[clap(long)]
+        name: String,
+        /// Symbol of the token, e.g. LMB. The program id is the symbol in lowercase.
+        #[clap(long)]
+        symbol: String,
+        /// Amount of tokens minted to this account.
+        #[clap(long)]
+        supply: u64,
+        /// Amount of gates to pay as fee for each of the deployment and the mint. If omitted, the fee set with `config set-fee` is paid, if any.
+        #[clap(long)]
+        fee: Option<u64>,
+    },
+    /// Lists the tokens created from this client, as saved in $ALEO_HOME/tokens.json.
+    List,
+}
+
+/// Commands to move credits to and from the counterparty chains of the bridge.
+#[derive(Debug, Parser)]
+pub enum Bridge {
+    /// Shows the counterparty chains of the bridge and the packets exchanged with them.
+    Status,
+    /// Burns credits of this account to be minted to the receiver on the destination chain, once a relayer
+    /// submits the packet there.
+    Transfer {
+        /// Chain id of the destination chain.
+        #[clap(long)]
+        to_chain: String,
+        /// Account that gets the credits on the destination chain.
+        #[clap(value_parser=vm::Address::from_str)]
+        receiver: vm::Address,
+        /// Amount of gates to send.
+        #[clap(value_parser)]
+        amount: u64,
+        /// The record to burn the credits from. If omitted, the record with most gates is used.
+        #[clap(long, value_parser=parse_input_record)]
+        record: Option<vm::UserInputValueType>,
+    },
+    /// Relays the pending packets sent by the chain of the node to the destination chain served at the given url.
+    /// The packets are submitted along with a header of the source chain, which the destination needs to be able
+    /// to verify with the validator set it trusts for it.
+    Relay {
+        /// Url of the tendermint RPC of a node of the destination chain.
+        #[clap(long)]
+        to: String,
+    },
 }
 
 #[derive(Debug, Parser)]
@@ -47,22 +304,60 @@ pub enum Credits {
         recipient_address: vm::UserInputValueType,
         #[clap()]
         amount: u64,
-        /// Amount of gates to pay as fee for this execution. If omitted not fee is paid.
+        /// Amount of gates to pay as fee for this execution. If omitted, the fee set with `config set-fee` is paid, if any.
         #[clap(long)]
         fee: Option<u64>,
-        /// The record to use to subtract the fee amount. If omitted, the record with most gates in the account is used.
+        /// The record to use to subtract the fee amount. If omitted, the one set with `config set-fee --record` is used, or else the record with most gates in the account.
+        #[clap(long, value_parser=parse_input_record)]
+        fee_record: Option<vm::UserInputValueType>,
+        /// A short message for the recipient, e.g. a payment reference. It's encrypted so only the recipient can read it.
+        #[clap(long)]
+        memo: Option<String>,
+        /// Send the credits in a locked record that the recipient can't spend before this block height.
+        /// The record has to be unlocked with `credits unlock` once the height is reached.
+        #[clap(long)]
+        unlock_height: Option<u64>,
+        /// A note about the payment, e.g. what it was for, kept in the local history. It's not sent to the blockchain.
+        #[clap(long)]
+        label: Option<String>,
+    },
+    /// Turn a locked credits record into a regular one, once the chain reached its unlock height.
+    Unlock {
+        /// The locked credits record.
+        #[clap(value_parser=parse_input_record)]
+        record: vm::UserInputValueType,
+        /// Amount of gates to pay as fee for this execution. If omitted, the fee set with `config set-fee` is paid, if any.
+        #[clap(long)]
+        fee: Option<u64>,
+        /// The record to use to subtract the fee amount. If omitted, the one set with `config set-fee --record` is used, or else the record with most gates in the account.
         #[clap(long, value_parser=parse_input_record)]
         fee_record: Option<vm::UserInputValueType>,
     },
+    /// Transfer credits to every recipient listed in a CSV file of address,amount lines, using as few of the account
+    /// records as possible. The payouts are sent in batches, each in a single execution.
+    TransferMany {
+        /// Path to the CSV file with the payouts. Empty lines, lines starting with # and an address,amount header are skipped.
+        #[clap(value_parser)]
+        path: PathBuf,
+        /// Maximum amount of payouts included in a single execution.
+        #[clap(long, default_value_t = 20)]
+        batch_size: usize,
+        /// Amount of gates to pay as fee for each execution. If omitted, the fee set with `config set-fee` is paid, if any.
+        #[clap(long)]
+        fee: Option<u64>,
+        /// A note about the payouts, e.g. what they were for, kept in the local history.
+        #[clap(long)]
+        label: Option<String>,
+    },
     /// Split input record by amount
     Split {
         #[clap(value_parser=parse_input_record)]
         input_record: vm::UserInputValueType,
         amount: u64,
-        /// Amount of gates to pay as fee for this execution. If omitted not fee is paid.
+        /// Amount of gates to pay as fee for this execution. If omitted, the fee set with `config set-fee` is paid, if any.
         #[clap(long)]
         fee: Option<u64>,
-        /// The record to use to subtract the fee amount. If omitted, the record with most gates in the account is used.
+        /// The record to use to subtract the fee amount. If omitted, the one set with `config set-fee --record` is used, or else the record with most gates in the account.
         #[clap(long, value_parser=parse_input_record)]
         fee_record: Option<vm::UserInputValueType>,
     },
@@ -72,10 +367,10 @@ pub enum Credits {
         first_record: vm::UserInputValueType,
         #[clap(value_parser=parse_input_record)]
         second_record: vm::UserInputValueType,
-        /// Amount of gates to pay as fee for this execution. If omitted not fee is paid.
+        /// Amount of gates to pay as fee for this execution. If omitted, the fee set with `config set-fee` is paid, if any.
         #[clap(long)]
         fee: Option<u64>,
-        /// The record to use to subtract the fee amount. If omitted, the record with most gates in the account is used.
+        /// The record to use to subtract the fee amount. If omitted, the one set with `config set-fee --record` is used, or else the record with most gates in the account.
         #[clap(long, value_parser=parse_input_record)]
         fee_record: Option<vm::UserInputValueType>,
     },
@@ -91,10 +386,10 @@ pub enum Credits {
         /// The tendermint address of the validator that will stake the credits.
         #[clap()]
         validator: String,
-        /// Amount of gates to pay as fee for this execution. If omitted not fee is paid.
+        /// Amount of gates to pay as fee for this execution. If omitted, the fee set with `config set-fee` is paid, if any.
         #[clap(long)]
         fee: Option<u64>,
-        /// The record to use to subtract the fee amount. If omitted, the record with most gates in the account is used.
+        /// The record to use to subtract the fee amount. If omitted, the one set with `config set-fee --record` is used, or else the record with most gates in the account.
         #[clap(long, value_parser=parse_input_record)]
         fee_record: Option<vm::UserInputValueType>,
     },
@@ -106,10 +401,10 @@ pub enum Credits {
         /// The stake record to recover the staked amount from.
         #[clap(value_parser=parse_input_record)]
         record: vm::UserInputValueType,
-        /// Amount of gates to pay as fee for this execution. If omitted not fee is paid.
+        /// Amount of gates to pay as fee for this execution. If omitted, the fee set with `config set-fee` is paid, if any.
         #[clap(long)]
         fee: Option<u64>,
-        /// The record to use to subtract the fee amount. If omitted, the record with most gates in the account is used.
+        /// The record to use to subtract the fee amount. If omitted, the one set with `config set-fee --record` is used, or else the record with most gates in the account.
         #[clap(long, value_parser=parse_input_record)]
         fee_record: Option<vm::UserInputValueType>,
     },
@@ -123,16 +418,33 @@ pub enum Program {
         /// Path where the aleo program file resides.
         #[clap(value_parser)]
         path: PathBuf,
-        /// Amount of gates to pay as fee for this execution. If omitted not fee is paid.
+        /// Amount of gates to pay as fee for this execution. If omitted, the fee set with `config set-fee` is paid, if any.
         #[clap(long)]
         fee: Option<u64>,
-        /// The record to use to subtract the fee amount. If omitted, the record with most gates in the account is used.
+        /// The record to use to subtract the fee amount. If omitted, the one set with `config set-fee --record` is used, or else the record with most gates in the account.
         #[clap(long, value_parser=parse_input_record)]
         fee_record: Option<vm::UserInputValueType>,
+        /// Attach the hash of the program source code to the deployment, so anyone with the source can verify it
+        /// with `program verify-source`.
+        #[clap(long, default_value_t = false)]
+        publish_source_hash: bool,
+        /// Send the deployment split in chunk transactions, which the nodes put back together once all of them
+        /// are delivered. Deployments larger than the node transaction size limit are always sent in chunks.
+        #[clap(long, default_value_t = false)]
+        chunked: bool,
+        /// Take the randomness of the keys and the fee from an rng seeded with this value, so the same program
+        /// always gives the same deployment, e.g. for test fixtures. Anyone who knows the seed can recompute the
+        /// randomizers of the fee change record, so it shouldn't be used with real credits.
+        #[clap(long)]
+        seed: Option<u64>,
+        /// Deploy the program even if the static checks found potential problems in it, which are otherwise
+        /// reported without sending the deployment.
+        #[clap(long, default_value_t = false)]
+        force: bool,
     },
     /// Runs locally and sends an execution transaction to the blockchain, returning the Transaction ID
     Execute {
-        /// Program to execute (path or program_id).
+        /// Program to execute (path, program_id or registered name).
         #[clap(value_parser)]
         program: String,
         /// The function name.
@@ -141,15 +453,64 @@ pub enum Program {
         /// The function inputs.
         #[clap(value_parser=parse_input_value)]
         inputs: Vec<vm::UserInputValueType>,
-        /// Amount of gates to pay as fee for this execution. If omitted not fee is paid.
+        /// JSON file with the function inputs instead of the arguments, or `-` to read them from stdin. It's an array
+        /// with a literal, record ciphertext or plaintext record for each input, where the records of the account can
+        /// also be referenced by commitment, e.g. `["1u32", {"commitment": "123field"}]`.
+        #[clap(long, conflicts_with = "inputs")]
+        inputs_file: Option<PathBuf>,
+        /// Amount of gates to pay as fee for this execution. If omitted, the fee set with `config set-fee` is paid, if any.
         #[clap(long)]
         fee: Option<u64>,
-        /// The record to use to subtract the fee amount. If omitted, the record with most gates in the account is used.
+        /// Pay the minimum fee currently accepted by the node for this execution, instead of a fixed amount.
+        #[clap(long, conflicts_with = "fee")]
+        auto_fee: bool,
+        /// The record to use to subtract the fee amount. If omitted, the one set with `config set-fee --record` is used, or else the record with most gates in the account.
         #[clap(long, value_parser=parse_input_record)]
         fee_record: Option<vm::UserInputValueType>,
+        /// Schedule the execution for a future block height: the inputs are spent when the transaction is included,
+        /// but the output records are only added to the ledger once the chain reaches the given height.
+        #[clap(long)]
+        execute_at_height: Option<u64>,
         /// Run the input code locally, generating the execution proof but without sending it over to the blockchain. Displays execution and decrypted records.
         #[clap(long, short, default_value_t = false)]
         dry_run: bool,
+        /// Take the randomness of the execution from an rng seeded with this value, so the same inputs always give
+        /// the same transaction, e.g. for test fixtures or replays. Anyone who knows the seed can recompute the
+        /// randomizers of the output records, so it shouldn't be used to move real credits.
+        #[clap(long)]
+        seed: Option<u64>,
+        /// Sign the execution with the account of the program instead of the local one, to spend the records owned
+        /// by the program address, see `program account`. Those records have to be passed as plaintext JSON, and
+        /// the fee is paid with a record of the program account.
+        #[clap(long, default_value_t = false)]
+        as_program: bool,
+    },
+    /// Runs a program function locally without generating the execution proof, displaying its outputs and records.
+    /// Nothing is sent to the blockchain, this is meant to quickly try out programs during development.
+    RunLocal {
+        /// Program to run (path, program_id or registered name).
+        #[clap(value_parser)]
+        program: String,
+        /// The function name.
+        #[clap(value_parser)]
+        function: vm::Identifier,
+        /// The function inputs.
+        #[clap(value_parser=parse_input_value)]
+        inputs: Vec<vm::UserInputValueType>,
+    },
+    /// Synthesizes the keys of a program function and runs it locally with the given inputs, timing the key
+    /// synthesis, authorization, proving and verification, to estimate the cost of the function for its users
+    /// and for the nodes before deploying it. The table is printed to stderr. Nothing is sent to the blockchain.
+    Bench {
+        /// Program to benchmark (path, program_id or registered name).
+        #[clap(value_parser)]
+        program: String,
+        /// The function name.
+        #[clap(value_parser)]
+        function: vm::Identifier,
+        /// The function inputs.
+        #[clap(value_parser=parse_input_value)]
+        inputs: Vec<vm::UserInputValueType>,
     },
     /// Builds an .aleo program's keys and saves them to an .avm file
     Build {
@@ -157,6 +518,228 @@ pub enum Program {
         #[clap(value_parser)]
         path: PathBuf,
     },
+    /// Lists the programs deployed to the blockchain sorted by id, along with their deployment height, deployer and functions
+    List {
+        /// Only list the programs whose id starts with this prefix.
+        #[clap(long)]
+        prefix: Option<String>,
+        /// List the programs after this program id, as returned in the `next` field of a previous listing.
+        #[clap(long)]
+        from: Option<vm::ProgramID>,
+        /// Maximum amount of programs to list.
+        #[clap(long, default_value_t = 50)]
+        limit: usize,
+    },
+    /// Deprecates a program deployed by this account, so its executions are rejected once the grace period is over
+    Deprecate {
+        /// The id of the program to deprecate.
+        #[clap(value_parser)]
+        program_id: vm::ProgramID,
+        /// Amount of blocks during which executions of the program are still accepted.
+        #[clap(long, default_value_t = 100)]
+        grace_period: u64,
+    },
+    /// Registers a name, e.g. `token/usdx`, for a program deployed by this account, so it can be used instead of the
+    /// program id in other commands, e.g. `program execute token/usdx transfer ...`. The first name registered in a
+    /// namespace makes this account its owner, and only the owner can register names in it
+    RegisterName {
+        /// The name to register, in `namespace/name` form.
+        #[clap(value_parser)]
+        name: String,
+        /// The id of the program the name points to.
+        #[clap(value_parser)]
+        program_id: vm::ProgramID,
+    },
+    /// Shows the program a registered name points to and the owner of its namespace
+    Resolve {
+        /// The name, in `namespace/name` form.
+        #[clap(value_parser)]
+        name: String,
+    },
+    /// Compiles a program source file and compares it with the deployed program and the source hash published by
+    /// its deployer.
+    VerifySource {
+        /// The id of the deployed program.
+        #[clap(value_parser)]
+        program_id: vm::ProgramID,
+        /// Path to the .aleo source file.
+        #[clap(value_parser)]
+        path: PathBuf,
+    },
+    /// Shows the address of the account of a program, which anyone can derive from its id, along with the unspent
+    /// records it owns. Records owned by a program address can only be spent by executions of the program, signed
+    /// with `program execute --as-program`
+    Account {
+        /// The id of the program.
+        #[clap(value_parser)]
+        program_id: vm::ProgramID,
+    },
+    /// Shows how many times a deployed program was executed, the total fees paid by its executions and the height of the last one
+    Stats {
+        /// The id of the program.
+        #[clap(value_parser)]
+        program_id: vm::ProgramID,
+    },
+    /// Shows whether a program id can be deployed, who deployed it and when if it's taken, and the last deployments
+    /// of it that failed in a block, with their errors
+    Availability {
+        /// The id of the program.
+        #[clap(value_parser)]
+        program_id: vm::ProgramID,
+    },
+    /// Lists the record ciphertexts created by the executions of a program sorted by commitment, along with the height
+    /// that added them, e.g. all the records minted by a token program
+    Records {
+        /// The id of the program.
+        #[clap(value_parser)]
+        program_id: vm::ProgramID,
+        /// List the records after this commitment, as returned in the `next` field of a previous listing.
+        #[clap(long, value_parser=vm::Field::from_str)]
+        from: Option<vm::Field>,
+        /// Maximum amount of records to list.
+        #[clap(long, default_value_t = 50)]
+        limit: usize,
+    },
+}
+
+#[derive(Debug, Parser)]
+pub enum Governance {
+    /// Propose a change to a chain parameter, signed with the validator key. The proposal id is the id of the
+    /// resulting transaction, and the change takes effect at the activation height if more than 2/3 of the
+    /// voting power approves it.
+    Propose {
+        /// The parameter to change, one of: block_reward, base_fee, app_version, min_record_gates, min_self_stake,
+        /// max_validators, community_pool_percentage.
+        #[clap(value_parser)]
+        parameter: governance::Parameter,
+        /// The new value of the parameter.
+        #[clap(value_parser)]
+        value: u64,
+        /// The block height at which the proposal is resolved.
+        #[clap(value_parser)]
+        activation_height: u64,
+        /// Path to the tendermint validator key, defaults to ~/.tendermint/config/priv_validator_key.json
+        #[clap(long)]
+        validator_key: Option<PathBuf>,
+    },
+    /// Vote on a pending proposal, signed with the validator key. Approves the proposal unless --reject is passed.
+    Vote {
+        /// The id of the proposal to vote on.
+        #[clap(value_parser)]
+        proposal_id: String,
+        /// Vote against the proposal.
+        #[clap(long, default_value_t = false)]
+        reject: bool,
+        /// Path to the tendermint validator key, defaults to ~/.tendermint/config/priv_validator_key.json
+        #[clap(long)]
+        validator_key: Option<PathBuf>,
+    },
+    /// Propose to pay gates out of the community pool to an account, signed with the validator key. The record is
+    /// minted at the activation height if more than 2/3 of the voting power approves it and the pool has enough.
+    Spend {
+        /// The account that receives the gates.
+        #[clap(value_parser=vm::Address::from_str)]
+        recipient: vm::Address,
+        /// Amount of gates to pay.
+        #[clap(value_parser)]
+        amount: u64,
+        /// The block height at which the proposal is resolved.
+        #[clap(value_parser)]
+        activation_height: u64,
+        /// Path to the tendermint validator key, defaults to ~/.tendermint/config/priv_validator_key.json
+        #[clap(long)]
+        validator_key: Option<PathBuf>,
+    },
+    /// Show the current chain parameters and the pending proposals.
+    Show,
+    /// Show the balance of the community pool and the pending proposals to spend it.
+    CommunityPool,
+}
+
+/// Commands to move a wallet between machines: the account keys, including the merged accounts, the client config,
+/// the created tokens and the transaction history are saved to a single file encrypted with a passphrase.
+/// The passphrase is prompted for unless given with --passphrase or the ALEO_BACKUP_PASSPHRASE variable.
+#[derive(Debug, Parser)]
+pub enum Backup {
+    /// Saves the wallet files of $ALEO_HOME to an encrypted backup file.
+    Create {
+        /// Path of the backup file, which must not exist.
+        #[clap(long, default_value = "backup.age")]
+        out: PathBuf,
+        #[clap(long, env = "ALEO_BACKUP_PASSPHRASE", hide_env_values = true)]
+        passphrase: Option<String>,
+    },
+    /// Restores the wallet files of an encrypted backup file to $ALEO_HOME.
+    Restore {
+        /// Path of the backup file.
+        #[clap(value_parser)]
+        path: PathBuf,
+        #[clap(long, env = "ALEO_BACKUP_PASSPHRASE", hide_env_values = true)]
+        passphrase: Option<String>,
+        /// Replace the account of $ALEO_HOME if it's a different one. Its keys are lost unless they were backed up.
+        #[clap(long, default_value_t = false)]
+        force: bool,
+    },
+}
+
+/// Commands for the local history of the payments of the account, kept in $ALEO_HOME/history.json.
+/// Payments sent with `credits transfer` and `credits transfer-many` are added with their `--label`, if any.
+#[derive(Debug, Parser)]
+pub enum History {
+    /// Lists the payments sent and received, after adding the received ones found in the blockchain.
+    List {
+        /// Write the history to this path as CSV, instead of printing it.
+        #[clap(long)]
+        csv: Option<PathBuf>,
+    },
+    /// Sets the label of a payment, given the transaction id of a sent payment or the record commitment of a received one.
+    Label {
+        #[clap(value_parser)]
+        id: String,
+        #[clap(value_parser)]
+        label: String,
+    },
+}
+
+/// Commands to manage a validator node, signed with its tendermint validator key, and to follow its rewards.
+#[derive(Debug, Parser)]
+pub enum Validator {
+    /// Shows the credits assigned to the given account by each block it got rewards from, as the aleo address of
+    /// a validator, along with their total. Blocks committed before the node recorded their rewards aren't included.
+    Rewards {
+        /// The aleo address the validator rewards are assigned to.
+        #[clap(value_parser=vm::Address::from_str)]
+        address: vm::Address,
+        /// First block height of the range.
+        #[clap(long, default_value_t = 1)]
+        from: u64,
+        /// Last block height of the range, defaults to the last committed block.
+        #[clap(long)]
+        to: Option<u64>,
+        /// Url of an archive node to query if the node pruned the first blocks of the range.
+        #[clap(long, env = "ARCHIVE_URL")]
+        archive_url: Option<String>,
+    },
+    /// Shows the uptime, proposed blocks, rewards and annual yield of each validator over the last committed blocks,
+    /// to compare them before staking.
+    Performance {
+        /// Amount of blocks to measure, up to 10000. Defaults to the last 1000.
+        #[clap(long)]
+        blocks: Option<u64>,
+    },
+    /// Shows the validators that were slashed for double signing, whose keys can't be part of the validator set
+    /// anymore, along with the offense and the height from which their remaining stake can be unstaked.
+    Tombstones,
+    /// Switch the validator to a new tendermint consensus key, keeping its voting power and stakes.
+    /// The node should start signing with the new key once the change takes effect, two blocks after it's included.
+    RotateKey {
+        /// Path to the new tendermint validator key, in the priv_validator_key.json format.
+        #[clap(value_parser)]
+        new_validator_key: PathBuf,
+        /// Path to the current tendermint validator key, defaults to ~/.tendermint/config/priv_validator_key.json
+        #[clap(long)]
+        validator_key: Option<PathBuf>,
+    },
 }
 
 /// Return the status of a Transaction: Type, whether it is committed to the ledger, and the program name.
@@ -170,22 +753,524 @@ pub struct Get {
     /// Whether to decrypt the incoming transaction private records
     #[clap(short, long, default_value_t = false)]
     pub decrypt: bool,
+
+    /// Verify that the transaction is included in a block signed by the validators and that its output records
+    /// are part of the blockchain state, instead of trusting the node.
+    #[clap(long, default_value_t = false)]
+    pub verify: bool,
+
+    /// Show a receipt of what the transaction did instead of the raw transaction: the height that included it,
+    /// the gas and fee it consumed, its public outputs, its records, decrypted if owned by the account, and the events
+    /// emitted for it.
+    #[clap(long, default_value_t = false)]
+    pub receipt: bool,
+}
+
+/// Commands to generate transactions in separate steps, so the account private key can be kept on an offline host:
+/// the transaction is built on a host connected to the blockchain with just the view key, signed on the offline one,
+/// and then broadcast from the connected one.
+#[derive(Debug, Parser)]
+pub enum Tx {
+    /// Builds an unsigned transaction file, with the program and the decrypted input records, without the private key
+    #[clap(subcommand)]
+    Build(TxBuild),
+    /// Generates the transaction of an unsigned transaction file with the account credentials. Doesn't need network access
+    Sign {
+        /// Path to the unsigned transaction file.
+        #[clap(long)]
+        file: PathBuf,
+        /// Path where the signed transaction is saved.
+        #[clap(long, default_value = "signed_tx.json")]
+        output: PathBuf,
+    },
+    /// Signs the function calls of an unsigned execution file with the account credentials, without generating
+    /// the proofs. The result can be proven on a host without the private key with `tx prove`
+    Authorize {
+        /// Path to the unsigned transaction file.
+        #[clap(long)]
+        file: PathBuf,
+        /// Path where the authorized execution is saved.
+        #[clap(long, default_value = "authorized_tx.json")]
+        output: PathBuf,
+    },
+    /// Generates the proofs of an authorized execution file. Doesn't need the account credentials
+    Prove {
+        /// Path to the authorized execution file.
+        #[clap(long)]
+        file: PathBuf,
+        /// Path where the signed transaction is saved.
+        #[clap(long, default_value = "signed_tx.json")]
+        output: PathBuf,
+    },
+    /// Sends a signed transaction file to the blockchain, returning the Transaction ID
+    Broadcast {
+        /// Path to the signed transaction file.
+        #[clap(long)]
+        file: PathBuf,
+        /// Wrap the transaction in an envelope signed with the account key, so nodes know it was sent by this
+        /// account and the chain id is bound by the signature. Needed for the transactions that aren't signed yet,
+        /// e.g. the ones proven with `tx prove`, since nodes reject them otherwise. Deployments need it for the
+        /// account to be recorded as their deployer, which can deprecate the program and point names to it.
+        #[clap(long)]
+        sign: bool,
+        /// Last block height at which the signed envelope can be included in the blockchain.
+        #[clap(long, requires = "sign")]
+        expires_at: Option<u64>,
+    },
+    /// Bundles signed deployment and execution files in a single transaction and sends it to the blockchain, in an
+    /// envelope signed with the account key. The transactions are applied in the given order and either all of them
+    /// succeed or none does
+    Batch {
+        /// Paths to the signed transaction files.
+        #[clap(value_parser, required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// Re-verifies a signed transaction file locally against the state of the node, listing a diagnostic for each
+    /// check that fails, e.g. the transition whose proof doesn't verify or the input record that was already spent
+    Debug {
+        /// Path to the signed transaction file.
+        #[clap(value_parser)]
+        file: PathBuf,
+    },
+    /// Decodes a transaction in the wire format, the bincode bytes gossiped by Tendermint, to the JSON of a signed
+    /// transaction file. Takes the hex or base64 text of the bytes, as returned by the Tendermint RPC, or a file
+    /// with either the text or the raw bytes
+    Decode {
+        /// Hex or base64 encoded transaction, or path to a file with the encoded or raw transaction.
+        #[clap(value_parser)]
+        input: String,
+        /// Path where the signed transaction file is saved, instead of printing it.
+        #[clap(long)]
+        output: Option<PathBuf>,
+    },
+    /// Encodes a signed transaction file in the wire format, the bincode bytes sent to Tendermint
+    Encode {
+        /// Path to the signed transaction file.
+        #[clap(value_parser)]
+        file: PathBuf,
+        /// Encoding of the bytes in the output.
+        #[clap(long, value_enum, default_value_t = WireEncoding::Base64)]
+        format: WireEncoding,
+        /// Path where the encoded transaction is saved, instead of printing it. Required for the raw format.
+        #[clap(long, required_if_eq("format", "raw"))]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum WireEncoding {
+    Base64,
+    Hex,
+    Raw,
+}
+
+#[derive(Debug, Parser)]
+pub enum TxBuild {
+    /// Builds an unsigned execution of a program function
+    Execute {
+        /// Program to execute (path, program_id or registered name).
+        #[clap(value_parser)]
+        program: String,
+        /// The function name.
+        #[clap(value_parser)]
+        function: vm::Identifier,
+        /// The function inputs. Record ciphertexts are decrypted with the view key.
+        #[clap(value_parser)]
+        inputs: Vec<String>,
+        /// The view key of the account that will sign the transaction.
+        #[clap(long)]
+        view_key: vm::ViewKey,
+        /// Amount of gates to pay as fee for this execution. If omitted not fee is paid.
+        #[clap(long, requires = "fee_record")]
+        fee: Option<u64>,
+        /// The record ciphertext to use to subtract the fee amount. Required along with the fee, since the spent status
+        /// of the account records can't be known without the private key.
+        #[clap(long)]
+        fee_record: Option<String>,
+        /// Path where the unsigned transaction is saved.
+        #[clap(long, default_value = "unsigned_tx.json")]
+        output: PathBuf,
+    },
+    /// Builds an unsigned deployment of a program
+    Deploy {
+        /// Path where the aleo program file resides.
+        #[clap(value_parser)]
+        path: PathBuf,
+        /// The view key of the account that will sign the transaction.
+        #[clap(long)]
+        view_key: vm::ViewKey,
+        /// Amount of gates to pay as fee for this deployment. If omitted not fee is paid.
+        #[clap(long, requires = "fee_record")]
+        fee: Option<u64>,
+        /// The record ciphertext to use to subtract the fee amount. Required along with the fee, since the spent status
+        /// of the account records can't be known without the private key.
+        #[clap(long)]
+        fee_record: Option<String>,
+        /// Path where the unsigned transaction is saved.
+        #[clap(long, default_value = "unsigned_tx.json")]
+        output: PathBuf,
+    },
+}
+
+/// Commands to inspect the transactions waiting to be included in a block.
+#[derive(Debug, Parser)]
+pub enum Mempool {
+    /// Lists the transactions in the node mempool with their id, kind, programs, fee and size, in the order they'll
+    /// be proposed. A sent transaction that's neither listed nor committed was dropped, e.g. because it failed the
+    /// mempool validations again after a block spent its input records.
+    List {
+        /// Maximum amount of transactions to fetch, tendermint returns at most 100.
+        #[clap(long, default_value_t = 30)]
+        limit: usize,
+        /// Only list the transactions of the account: the ones that spend its records, e.g. to pay a fee,
+        /// and the deployments it made.
+        #[clap(long, default_value_t = false)]
+        mine: bool,
+    },
+}
+
+/// Commands to inspect committed blocks.
+#[derive(Debug, Parser)]
+pub enum Block {
+    /// Shows the events emitted by the application while processing the block at the given height, e.g. the ids of
+    /// the delivered transactions and the scheduled executions that were applied. The node keeps them after
+    /// tendermint discards them.
+    Events {
+        /// The block height.
+        #[clap(value_parser)]
+        height: u64,
+        /// Url of an archive node to query if the node pruned the block.
+        #[clap(long, env = "ARCHIVE_URL")]
+        archive_url: Option<String>,
+    },
+}
+
+/// Commands to manage the state of a local devnet node. Both tendermint and aleo_abci have to be stopped while they run,
+/// except for `stats`, which queries the running node.
+#[derive(Debug, Parser)]
+pub enum Node {
+    /// Removes the application state and the tendermint blocks, so the chain starts again from scratch.
+    Reset {
+        /// Keep the tendermint configuration, genesis and validator keys, so the node can be started right away.
+        /// Otherwise the whole tendermint home is removed and has to be initialized again, e.g. with `make genesis`.
+        #[clap(long, default_value_t = false)]
+        keep_genesis: bool,
+        #[clap(flatten)]
+        dirs: NodeDirs,
+    },
+    #[clap(subcommand)]
+    Snapshot(Snapshot),
+    /// Shows the amount of records, spent and unspent, programs and index entries stored by the node, and the disk
+    /// space taken by each of its stores.
+    Stats,
+}
+
+/// Commands to save the state of a local devnet node and go back to it later, e.g. between test runs.
+/// Snapshots are kept in the `snapshots` directory of the aleo_abci directory.
+#[derive(Debug, Parser)]
+pub enum Snapshot {
+    /// Saves the application state, the tendermint data and the genesis file of the node under the given name.
+    Save {
+        #[clap(value_parser)]
+        name: String,
+        #[clap(flatten)]
+        dirs: NodeDirs,
+    },
+    /// Replaces the state of the node with the snapshot of the given name.
+    Restore {
+        #[clap(value_parser)]
+        name: String,
+        #[clap(flatten)]
+        dirs: NodeDirs,
+    },
+    /// Lists the saved snapshots.
+    List {
+        #[clap(flatten)]
+        dirs: NodeDirs,
+    },
+}
+
+/// Produce a statement of all the records received and spent by the address associated to the given view key,
+/// along with the heights at which that happened. It doesn't require the account private key, nor the credentials
+/// file when the view key is passed.
+#[derive(Debug, Parser)]
+pub struct Audit {
+    /// The view key of the account to audit. If omitted, the one of the client profile is used.
+    #[clap(long)]
+    pub view_key: Option<vm::ViewKey>,
 }
 
 impl Command {
-    pub async fn run(self, url: String) -> Result<serde_json::Value> {
-        let output = if let Command::Account(Account::New) = self {
-            let credentials = account::Credentials::new()?;
+    pub async fn run(mut self, url: String) -> Result<serde_json::Value> {
+        let output = if let Command::Account(Account::New { mnemonic, index }) = self {
+            if mnemonic {
+                let mnemonic = account::Credentials::new_mnemonic()?;
+                let credentials = account::Credentials::from_mnemonic(&mnemonic, index)?;
+                let path = credentials.save()?;
+                json!({
+                    "path": path,
+                    "account": credentials,
+                    "mnemonic": mnemonic.to_string(),
+                    "index": index,
+                })
+            } else {
+                let credentials = account::Credentials::new()?;
+                let path = credentials.save()?;
+                json!({"path": path, "account": credentials})
+            }
+        } else if let Command::Account(Account::Import {
+            mnemonic,
+            index,
+            view_key,
+        }) = self
+        {
+            let credentials = match (mnemonic, view_key) {
+                (Some(mnemonic), _) => account::Credentials::from_mnemonic(&mnemonic, index)?,
+                (None, Some(view_key)) => account::Credentials::watch_only(view_key)?,
+                (None, None) => {
+                    return Err(bad_arguments("either a mnemonic or a view key is required"))
+                }
+            };
             let path = credentials.save()?;
-
-            json!({"path": path, "account": credentials})
+            json!({
+                "path": path,
+                "account": credentials,
+                "watch_only": credentials.private_key.is_none(),
+            })
+        } else if let Command::Audit(Audit { view_key }) = self {
+            // the private key of the profile, when it's the audited account, derives the serial numbers on any backend
+            let (address, decryptor) = match view_key {
+                None => {
+                    let credentials = account::Credentials::load()?;
+                    (credentials.address, credentials.decryptor()?)
+                }
+                Some(view_key) => match account::Credentials::load() {
+                    Ok(credentials) if credentials.view_key.to_string() == view_key.to_string() => {
+                        (credentials.address, credentials.decryptor()?)
+                    }
+                    _ => (
+                        vm::Address::try_from(&view_key)?,
+                        ViewKeyDecryptor::new(view_key)?,
+                    ),
+                },
+            };
+            audit(&address, &decryptor, &url).await?
+        } else if let Command::Program(Program::List {
+            prefix,
+            from,
+            limit,
+        }) = self
+        {
+            let (programs, next) = Client::new(&url)?
+                .list_programs(prefix, from, Some(limit))
+                .await?;
+            json!({ "programs": programs, "next": next })
+        } else if let Command::Program(Program::Stats { program_id }) = self {
+            let stats = Client::new(&url)?.get_program_stats(program_id).await?;
+            json!(stats)
+        } else if let Command::Program(Program::Resolve { name }) = self {
+            names::parse(&name).map_err(bad_arguments)?;
+            let resolved = Client::new(&url)?.resolve_name(&name).await?;
+            json!(resolved)
+        } else if let Command::Program(Program::Availability { program_id }) = self {
+            let availability = Client::new(&url)?
+                .get_program_availability(program_id)
+                .await?;
+            json!(availability)
+        } else if let Command::Program(Program::Account { program_id }) = self {
+            let credentials = account::Credentials::of_program(&program_id)?;
+            let records: Vec<serde_json::Value> = get_records(&credentials, &url)
+                .await?
+                .into_iter()
+                .map(
+                    |(commitment, _, record)| json!({ "commitment": commitment, "record": record }),
+                )
+                .collect();
+            json!({
+                "program_id": program_id,
+                "address": credentials.address,
+                "view_key": credentials.view_key,
+                "records": records,
+            })
+        } else if let Command::Program(Program::Records {
+            program_id,
+            from,
+            limit,
+        }) = self
+        {
+            let (records, next) = Client::new(&url)?
+                .get_records_by_program(&program_id, from, Some(limit))
+                .await?;
+            json!({ "records": records, "next": next })
+        } else if let Command::Program(Program::VerifySource { program_id, path }) = self {
+            verify_source(program_id, &path, &url).await?
+        } else if let Command::Block(Block::Events {
+            height,
+            archive_url,
+        }) = self
+        {
+            let events = Client::new(&url)?
+                .with_history_from(height, archive_url.as_deref())
+                .await?
+                .get_block_events(height)
+                .await?;
+            json!({ "height": height, "events": events })
+        } else if let Command::Token(Token::List) = self {
+            json!(TokenRegistry::load()?)
+        } else if let Command::Governance(command) = self {
+            governance(command, &url).await?
+        } else if let Command::Bridge(Bridge::Status) = self {
+            json!(Client::new(&url)?.get_bridge_status().await?)
+        } else if let Command::Bridge(Bridge::Relay { to }) = self {
+            relay_packets(&url, &to).await?
+        } else if let Command::Tx(command) = self {
+            tx(command, &url).await?
+        } else if let Command::Mempool(command) = self {
+            mempool(command, &url).await?
+        } else if let Command::Config(command) = self {
+            config(command)?
+        } else if let Command::Backup(command) = self {
+            match command {
+                Backup::Create { out, passphrase } => {
+                    backup::create(&lib::aleo_home(), &out, &backup::passphrase(passphrase)?)?
+                }
+                Backup::Restore {
+                    path,
+                    passphrase,
+                    force,
+                } => backup::restore(
+                    &lib::aleo_home(),
+                    &path,
+                    &backup::passphrase(passphrase)?,
+                    force,
+                )?,
+            }
+        } else if let Command::History(History::Label { id, label }) = self {
+            let mut history = history::History::load()?;
+            history.label(&id, &label)?;
+            history.save()?;
+            json!({ "id": id, "label": label })
+        } else if let Command::Records(Records::Alias { alias, commitment }) = self {
+            let mut cache = RecordCache::load()?;
+            let commitment = cache.set_alias(&alias, &commitment)?;
+            cache.save()?;
+            json!({ "alias": alias, "commitment": commitment })
+        } else if let Command::Node(command) = self {
+            match command {
+                Node::Reset { keep_genesis, dirs } => node::reset(&dirs, keep_genesis)?,
+                Node::Snapshot(Snapshot::Save { name, dirs }) => node::save_snapshot(&dirs, &name)?,
+                Node::Snapshot(Snapshot::Restore { name, dirs }) => {
+                    node::restore_snapshot(&dirs, &name)?
+                }
+                Node::Snapshot(Snapshot::List { dirs }) => node::list_snapshots(&dirs)?,
+                Node::Stats => json!(Client::new(&url)?.get_store_stats().await?),
+            }
+        } else if let Command::Validator(Validator::Rewards {
+            address,
+            from,
+            to,
+            archive_url,
+        }) = self
+        {
+            let owner = address.to_string();
+            let blocks = Client::new(&url)?
+                .with_history_from(from, archive_url.as_deref())
+                .await?
+                .get_block_rewards(Some(address), from, to)
+                .await?;
+            let total: u64 = blocks
+                .iter()
+                .flat_map(|block| &block.rewards)
+                .map(|reward| reward.gates)
+                .sum();
+            json!({ "address": owner, "total_gates": total, "blocks": blocks })
+        } else if let Command::Status = self {
+            json!(Client::new(&url)?.get_chain_status().await?)
+        } else if let Command::Validator(Validator::Tombstones) = self {
+            json!(Client::new(&url)?.get_tombstones().await?)
+        } else if let Command::Validator(Validator::Performance { blocks }) = self {
+            json!(Client::new(&url)?.get_validator_performance(blocks).await?)
+        } else if let Command::Validator(Validator::RotateKey {
+            new_validator_key,
+            validator_key,
+        }) = self
+        {
+            let key = ValidatorKey::load(&validator_key_path(validator_key)?)?;
+            let new_key = ValidatorKey::load(&new_validator_key)?;
+            let chain_id = chain_id(&url).await?;
+            let transaction = Transaction::key_rotation(&key, &new_key, Some(chain_id))?;
+            let outcome = Client::new(&url)?.broadcast(&transaction).await?;
+            with_broadcast(json!(transaction), outcome)
         } else {
             let credentials =
-                account::Credentials::load().map_err(|_| anyhow!("credentials not found"))?;
+                account::Credentials::load().map_err(|_| not_found("credentials not found"))?;
+            // the inputs read from a file take the place of the arguments, so their records pick the signer too
+            if let Command::Program(Program::Execute {
+                inputs,
+                inputs_file: Some(path),
+                ..
+            }) = &mut self
+            {
+                *inputs = inputs::read(path, &credentials, &url).await?;
+            }
+            // transactions are signed by the account that owns their input records, see `account merge`
+            let credentials = match &self {
+                Command::Account(_)
+                | Command::Get(_)
+                | Command::History(_)
+                | Command::Records(Records::Split { .. }) => credentials,
+                Command::Program(Program::Execute {
+                    program,
+                    as_program: true,
+                    ..
+                }) => match get_program(&url, program).await? {
+                    Some(program) => account::Credentials::of_program(program.id())?,
+                    None => return Err(not_found(format!("Could not find program {program}"))),
+                },
+                _ => credentials.owner_of(&self.input_records())?,
+            };
 
             match self {
-                Command::Account(Account::New) => {
-                    bail!("this shouldn't be reachable, the account new is a special case handled elsewhere")
+                Command::Account(Account::New { .. })
+                | Command::Account(Account::Import { .. })
+                | Command::Audit(_)
+                | Command::Program(Program::List { .. })
+                | Command::Program(Program::Stats { .. })
+                | Command::Program(Program::Availability { .. })
+                | Command::Program(Program::Resolve { .. })
+                | Command::Program(Program::Account { .. })
+                | Command::Program(Program::Records { .. })
+                | Command::Program(Program::VerifySource { .. })
+                | Command::Token(Token::List)
+                | Command::Governance(_)
+                | Command::Bridge(Bridge::Status)
+                | Command::Bridge(Bridge::Relay { .. })
+                | Command::Tx(_)
+                | Command::Mempool(_)
+                | Command::Validator(_)
+                | Command::Block(_)
+                | Command::Node(_)
+                | Command::Config(_)
+                | Command::Backup(_)
+                | Command::History(History::Label { .. })
+                | Command::Records(Records::Alias { .. })
+                | Command::Status
+                | Command::Completions { .. }
+                | Command::Repl
+                | Command::Serve { .. } => {
+                    bail!("this shouldn't be reachable, the account new, account import, audit, program list, program stats, program account, program records, program verify-source, token list, governance, bridge status, bridge relay, tx, mempool, validator, block, node, config, backup, history label, records alias, status, completions, repl and serve are special cases handled elsewhere")
+                }
+                Command::History(History::List { csv }) => {
+                    let mut history = history::History::load()?;
+                    history.sync(&credentials, &url).await?;
+                    history.save()?;
+                    match csv {
+                        Some(path) => {
+                            fs::write(&path, history.to_csv())?;
+                            json!({ "path": path })
+                        }
+                        None => json!(history.entries),
+                    }
                 }
                 Command::Account(Account::Balance) => {
                     let balance = get_records(&credentials, &url).await?.iter().fold(
@@ -201,70 +1286,248 @@ impl Command {
 
                     json!({ "balance": balance })
                 }
-                Command::Account(Account::Records) => {
-                    let records: Vec<serde_json::Value> = get_records(&credentials, &url)
-                        .await?
+                Command::Account(Account::Pending { relay_url, count }) => {
+                    #[allow(clippy::clone_on_copy)]
+                    let decryptor = ViewKeyDecryptor::new(credentials.view_key.clone())?;
+                    let mut received = 0;
+                    lib::pending::watch(&relay_url, &decryptor, |payment| {
+                        println!("{}", json!(payment));
+                        received += 1;
+                        count.map_or(true, |count| received < count)
+                    })
+                    .await?;
+                    json!({ "received": received })
+                }
+                Command::Account(Account::Watch { relay_url, count }) => {
+                    #[allow(clippy::clone_on_copy)]
+                    let view_keys = credentials
+                        .accounts()
                         .iter()
-                        .map(|(commitment, ciphertext, plaintext)| {
-                            json!({
-                                "commitment": commitment,
-                                "ciphertext": ciphertext,
-                                "record": plaintext
-                            })
-                        })
+                        .map(|account| account.view_key.clone())
                         .collect();
+                    let mut received = 0;
+                    lib::pending::watch_committed(&relay_url, view_keys, |record| {
+                        println!("{}", json!(record));
+                        received += 1;
+                        count.map_or(true, |count| received < count)
+                    })
+                    .await?;
+                    json!({ "received": received })
+                }
+                Command::Account(Account::Records) => {
+                    let mut records: Vec<serde_json::Value> = Vec::new();
+                    // memos are decrypted with the view key of the account that owns the record
+                    for account in credentials.accounts() {
+                        let account_records = get_records(&account, &url).await?;
+                        #[allow(clippy::clone_on_copy)]
+                        let commitments = account_records
+                            .iter()
+                            .map(|(commitment, _, _)| commitment.clone())
+                            .collect();
+                        let memos = Client::new(&url)?
+                            .get_memos(&account.view_key, commitments)
+                            .await?;
+
+                        records.extend(account_records.iter().map(
+                            |(commitment, ciphertext, plaintext)| {
+                                json!({
+                                    "commitment": commitment,
+                                    "ciphertext": ciphertext,
+                                    "record": plaintext,
+                                    "memo": memos.get(commitment),
+                                })
+                            },
+                        ));
+                    }
                     json!(&records)
                 }
+                Command::Account(Account::Merge { path }) => {
+                    let other: account::Credentials = serde_json::from_str(
+                        &fs::read_to_string(&path)
+                            .map_err(|e| anyhow!("could not read {}: {e}", path.display()))?,
+                    )?;
+                    let mut credentials = credentials;
+                    let imported = credentials.merge(other);
+                    let path = credentials.save()?;
+                    let accounts: Vec<vm::Address> = credentials
+                        .accounts()
+                        .into_iter()
+                        .map(|account| account.address)
+                        .collect();
+                    json!({"path": path, "imported": imported, "accounts": accounts})
+                }
+                Command::Token(Token::Create {
+                    name,
+                    symbol,
+                    supply,
+                    fee,
+                }) => create_token(&credentials, &url, name, &symbol, supply, fee).await?,
+                Command::Bridge(Bridge::Transfer {
+                    to_chain,
+                    receiver,
+                    amount,
+                    record,
+                }) => {
+                    let (_, record) =
+                        choose_fee_record(&credentials, &url, &Some(amount), &record, &[])
+                            .await?
+                            .ok_or_else(|| anyhow!("no record to send the credits from"))?;
+                    let transaction = Transaction::bridge_transfer(
+                        &to_chain,
+                        receiver,
+                        amount,
+                        record,
+                        credentials.private_key()?,
+                    )?
+                    .for_chain(&chain_id(&url).await?, credentials.private_key()?)?;
+                    let outcome = Client::new(&url)?.broadcast(&transaction).await?;
+                    with_broadcast(json!(transaction), outcome)
+                }
                 Command::Program(Program::Deploy {
                     path,
                     fee,
                     fee_record,
+                    publish_source_hash,
+                    chunked,
+                    seed,
+                    force,
                 }) => {
+                    let source = fs::read_to_string(&path)?;
+                    let warnings = analysis::analyze(&source);
+                    if !warnings.is_empty() {
+                        let report = warnings
+                            .iter()
+                            .map(|warning| format!("  {warning}"))
+                            .join("\n");
+                        ensure!(
+                            force,
+                            "The static checks found potential problems in the program, fix them or pass --force to deploy it anyway:\n{report}"
+                        );
+                        eprintln!("Deploying despite the potential problems found by the static checks:\n{report}");
+                    }
                     let fee = choose_fee_record(&credentials, &url, &fee, &fee_record, &[]).await?;
+                    let chain_id = chain_id(&url).await?;
+                    let progress = key_synthesis_progress();
+                    let mut transaction = Transaction::deployment_with_rng(
+                        &source,
+                        credentials.private_key()?,
+                        fee.clone(),
+                        |done, total| {
+                            progress.set_length(total as u64);
+                            progress.set_position(done as u64);
+                        },
+                        &mut seeded_rng(seed)?,
+                    )?
+                    .with_chain_id(&chain_id)?;
+                    progress.finish_and_clear();
+                    if publish_source_hash {
+                        transaction = transaction.with_source_hash(&source)?;
+                    }
+                    // the envelope binds the deployer and the chain id, which lets this account deprecate the program
+                    // and name it
                     let transaction =
-                        Transaction::deployment(&path, &credentials.private_key, fee)?;
-                    let transaction_serialized = bincode::serialize(&transaction).unwrap();
-                    tendermint::broadcast(transaction_serialized, &url).await?;
-                    json!(transaction)
+                        Transaction::signed(transaction, credentials.private_key()?, None)?;
+                    let size = size_report(&transaction)?;
+                    let mut chunk_ids = Vec::new();
+                    let mut outcomes = Vec::new();
+                    if chunked || transaction.size()? > MAX_TRANSACTION_BYTES {
+                        // the staging of the chunks is paid with a record other than the deployment fee one
+                        let staging_fee = Transaction::deployment_chunks_fee(&transaction)?;
+                        let exclude: Vec<_> = fee
+                            .iter()
+                            .map(|(_, record)| vm::UserInputValueType::Record(record.clone()))
+                            .collect();
+                        let (_, staging_record) = choose_fee_record(
+                            &credentials,
+                            &url,
+                            &Some(staging_fee),
+                            &None,
+                            &exclude,
+                        )
+                        .await?
+                        .ok_or_else(|| anyhow!("no record to pay the staging of the chunks"))?;
+                        for chunk in Transaction::deployment_chunks(
+                            &transaction,
+                            credentials.private_key()?,
+                            staging_record,
+                        )? {
+                            outcomes.push(
+                                tendermint::broadcast(bincode::serialize(&chunk)?, &url).await?,
+                            );
+                            chunk_ids.push(chunk.id().to_string());
+                        }
+                    } else {
+                        let transaction_serialized = bincode::serialize(&transaction).unwrap();
+                        outcomes.push(tendermint::broadcast(transaction_serialized, &url).await?);
+                    }
+                    remember_fee_change(&credentials, &fee, &transaction)?;
+
+                    let mut transaction_json = json!(transaction);
+                    let object = transaction_json.as_object_mut().unwrap();
+                    object.insert("size".to_string(), size);
+                    if !chunk_ids.is_empty() {
+                        object.insert("chunks".to_string(), json!(chunk_ids));
+                        object.insert("broadcasts".to_string(), json!(outcomes));
+                    } else {
+                        object.insert("broadcast".to_string(), json!(outcomes[0]));
+                    }
+                    if !warnings.is_empty() {
+                        object.insert("warnings".to_string(), json!(warnings));
+                    }
+                    transaction_json
                 }
                 Command::Program(Program::Execute {
                     program,
                     function,
                     inputs,
                     fee,
+                    auto_fee,
                     fee_record,
+                    execute_at_height,
                     dry_run,
+                    seed,
+                    ..
                 }) => {
-                    let fee =
-                        choose_fee_record(&credentials, &url, &fee, &fee_record, &inputs).await?;
                     let program = match get_program(&url, &program).await? {
                         Some(program) => program,
-                        None => bail!("Could not find program {}", program),
+                        None => return Err(not_found(format!("Could not find program {program}"))),
                     };
-                    let transaction = Transaction::execution(
+                    let fee = if auto_fee {
+                        #[allow(clippy::clone_on_copy)]
+                        let estimate = Client::new(&url)?
+                            .estimate_fee(*program.id(), function.clone())
+                            .await?;
+                        // no need to spend a record when the node doesn't require fees
+                        Some(estimate.min_fee).filter(|amount| *amount > 0)
+                    } else {
+                        fee
+                    };
+                    let fee =
+                        choose_fee_record(&credentials, &url, &fee, &fee_record, &inputs).await?;
+                    let mut transaction = Transaction::execution_with_rng(
                         program,
                         function,
                         &inputs,
-                        &credentials.private_key,
-                        fee,
+                        credentials.private_key()?,
+                        fee.clone(),
+                        &mut seeded_rng(seed)?,
                     )?;
+                    if let Some(height) = execute_at_height {
+                        transaction = transaction.with_execution_height(height)?;
+                    }
+                    let transaction = transaction
+                        .for_chain(&chain_id(&url).await?, credentials.private_key()?)?;
 
                     let mut transaction_json = json!(transaction);
+                    transaction_json
+                        .as_object_mut()
+                        .unwrap()
+                        .insert("size".to_string(), size_report(&transaction)?);
                     if !dry_run {
-                        let mut transaction_json = json!(transaction);
-                        if !dry_run {
-                            let transaction_serialized = bincode::serialize(&transaction).unwrap();
-                            tendermint::broadcast(transaction_serialized, &url).await?;
-                        } else {
-                            let records = Self::decrypt_records(&transaction, credentials);
-
-                            if !records.is_empty() {
-                                transaction_json
-                                    .as_object_mut()
-                                    .unwrap()
-                                    .insert("decrypted_records".to_string(), json!(records));
-                            }
-                        }
+                        let transaction_serialized = bincode::serialize(&transaction).unwrap();
+                        let outcome = tendermint::broadcast(transaction_serialized, &url).await?;
+                        remember_fee_change(&credentials, &fee, &transaction)?;
+                        transaction_json = with_broadcast(transaction_json, outcome);
                     } else {
                         let records = Self::decrypt_records(&transaction, credentials);
 
@@ -277,6 +1540,68 @@ impl Command {
                     }
                     json!(transaction_json)
                 }
+                Command::Program(Program::RegisterName { name, program_id }) => {
+                    names::parse(&name).map_err(bad_arguments)?;
+                    let sequence = Client::new(&url)?.resolve_name(&name).await?.sequence;
+                    let transaction = Transaction::name_registration(
+                        &name,
+                        program_id,
+                        sequence,
+                        credentials.private_key()?,
+                        Some(chain_id(&url).await?),
+                    )?;
+                    let transaction_serialized = bincode::serialize(&transaction).unwrap();
+                    let outcome = tendermint::broadcast(transaction_serialized, &url).await?;
+                    with_broadcast(json!(transaction), outcome)
+                }
+                Command::Program(Program::Deprecate {
+                    program_id,
+                    grace_period,
+                }) => {
+                    let transaction = Transaction::deprecation(
+                        program_id,
+                        grace_period,
+                        credentials.private_key()?,
+                        Some(chain_id(&url).await?),
+                    )?;
+                    let transaction_serialized = bincode::serialize(&transaction).unwrap();
+                    let outcome = tendermint::broadcast(transaction_serialized, &url).await?;
+                    with_broadcast(json!(transaction), outcome)
+                }
+                Command::Program(Program::RunLocal {
+                    program,
+                    function,
+                    inputs,
+                }) => {
+                    let program = match get_program(&url, &program).await? {
+                        Some(program) => program,
+                        None => return Err(not_found(format!("Could not find program {program}"))),
+                    };
+                    let (records, outputs): (Vec<_>, Vec<_>) = vm::simulate_execution(
+                        program,
+                        function,
+                        &inputs,
+                        credentials.private_key()?,
+                    )?
+                    .into_iter()
+                    .partition(|output| matches!(output, vm::UserInputValueType::Record(_)));
+
+                    json!({ "outputs": outputs, "records": records })
+                }
+                Command::Program(Program::Bench {
+                    program,
+                    function,
+                    inputs,
+                }) => {
+                    let program = match get_program(&url, &program).await? {
+                        Some(program) => program,
+                        None => return Err(not_found(format!("Could not find program {program}"))),
+                    };
+                    let report =
+                        bench::run(program, function, &inputs, credentials.private_key()?)?;
+                    eprint!("{}", bench::table(&report));
+                    json!(report)
+                }
                 Command::Program(Program::Build { path }) => {
                     let program_source = std::fs::read_to_string(&path)?;
                     let program_file = ProgramFile::build(&program_source)?;
@@ -290,15 +1615,77 @@ impl Command {
                     amount,
                     fee,
                     fee_record,
+                    memo,
+                    unlock_height,
+                    label,
                 }) => {
-                    let inputs = [
+                    let mut inputs = vec![
                         input_record.clone(),
                         recipient_address.clone(),
                         vm::u64_to_value(amount),
                     ];
-                    run_credits_command(&credentials, &url, "transfer", &inputs, &fee, &fee_record)
-                        .await?
+                    let function = match unlock_height {
+                        Some(height) => {
+                            inputs.push(vm::u64_to_value(height));
+                            "transfer_locked"
+                        }
+                        None => "transfer",
+                    };
+                    let mut transaction = credits_transaction(
+                        &credentials,
+                        &url,
+                        function,
+                        &inputs,
+                        &fee,
+                        &fee_record,
+                    )
+                    .await?;
+
+                    let recipient = vm::Address::from_str(&recipient_address.to_string())?;
+                    if let Some(memo) = memo {
+                        // the first output of the transfer is the record of the recipient
+                        let (commitment, _) = transaction
+                            .output_records()
+                            .into_iter()
+                            .next()
+                            .ok_or_else(|| anyhow!("transfer has no output records"))?;
+                        transaction = transaction.with_memo(commitment, &recipient, &memo)?;
+                    }
+                    let transaction =
+                        Transaction::signed(transaction, credentials.private_key()?, None)?;
+
+                    let transaction_serialized = bincode::serialize(&transaction).unwrap();
+                    let outcome = tendermint::broadcast(transaction_serialized, &url).await?;
+                    let mut history = history::History::load()?;
+                    history.add_sent(&transaction, &[(recipient, amount)], label);
+                    history.save()?;
+                    with_broadcast(json!(transaction), outcome)
                 }
+                Command::Credits(Credits::TransferMany {
+                    path,
+                    batch_size,
+                    fee,
+                    label,
+                }) => transfer_many(&credentials, &url, &path, batch_size, fee, label).await?,
+                Command::Records(Records::Consolidate {
+                    target,
+                    batch_size,
+                    fee,
+                    max_fees,
+                }) => consolidate(&credentials, &url, target, batch_size, fee, max_fees).await?,
+                Command::Records(Records::Split {
+                    commitment,
+                    amounts,
+                    fee,
+                }) => split_record(&credentials, &url, &commitment, &amounts, fee).await?,
+                Command::Records(Records::Decrypt { ciphertext }) => {
+                    json!({ "record": decrypt_record(&credentials, &ciphertext)? })
+                }
+                Command::Records(Records::Show {
+                    ciphertext,
+                    program,
+                    record_name,
+                }) => show_record(&credentials, &ciphertext, &program, &record_name)?,
                 Command::Credits(Credits::Combine {
                     first_record,
                     second_record,
@@ -341,6 +1728,15 @@ impl Command {
                     run_credits_command(&credentials, &url, "stake", &inputs, &fee, &fee_record)
                         .await?
                 }
+                Command::Credits(Credits::Unlock {
+                    record,
+                    fee,
+                    fee_record,
+                }) => {
+                    let inputs = [record.clone()];
+                    run_credits_command(&credentials, &url, "unlock", &inputs, &fee, &fee_record)
+                        .await?
+                }
                 Command::Credits(Credits::Unstake {
                     amount,
                     record,
@@ -351,74 +1747,433 @@ impl Command {
                     run_credits_command(&credentials, &url, "unstake", &inputs, &fee, &fee_record)
                         .await?
                 }
+                Command::Get(Get {
+                    transaction_id,
+                    verify,
+                    receipt: true,
+                    ..
+                }) => {
+                    let client = Client::new(&url)?;
+                    if verify {
+                        client.get_verified_transaction(&transaction_id).await?;
+                    }
+                    let receipt = client.get_receipt(&transaction_id).await?;
+                    let accounts = credentials.accounts();
+                    let owned_records: Vec<serde_json::Value> = receipt
+                        .records
+                        .iter()
+                        .filter_map(|(commitment, ciphertext)| {
+                            accounts.iter().find_map(|account| {
+                                if !ciphertext.is_owner(&account.address, &account.view_key) {
+                                    return None;
+                                }
+                                let record = ciphertext.decrypt(&account.view_key).ok()?;
+                                Some(json!({ "commitment": commitment, "record": record }))
+                            })
+                        })
+                        .collect();
+
+                    let mut output = json!(receipt);
+                    output["decrypted_records"] = json!(owned_records);
+                    output
+                }
                 Command::Get(Get {
                     transaction_id,
                     decrypt,
+                    verify,
+                    receipt: false,
                 }) => {
-                    let transaction = tendermint::get_transaction(&transaction_id, &url).await?;
-                    let transaction: Transaction = bincode::deserialize(&transaction)?;
+                    let client = Client::new(&url)?;
+                    let transaction = if verify {
+                        client.get_verified_transaction(&transaction_id).await?
+                    } else {
+                        client.get_transaction(&transaction_id).await?
+                    };
+
+                    if !decrypt {
+                        json!(transaction)
+                    } else {
+                        let records = Self::decrypt_records(&transaction, credentials);
+
+                        json!({
+                            "execution": transaction,
+                            "decrypted_records": records
+                        })
+                    }
+                }
+            }
+        };
+
+        Ok(output)
+    }
+
+    /// Records passed to the command to be spent, including the fee record, which have to be owned by the account
+    /// that signs the transaction.
+    fn input_records(&self) -> Vec<vm::Record> {
+        let inputs: Vec<&vm::UserInputValueType> = match self {
+            Command::Program(Program::Execute {
+                inputs, fee_record, ..
+            }) => inputs.iter().chain(fee_record).collect(),
+            Command::Program(Program::Bench { inputs, .. }) => inputs.iter().collect(),
+            Command::Program(Program::Deploy { fee_record, .. }) => fee_record.iter().collect(),
+            Command::Credits(Credits::Transfer {
+                input_record: record,
+                fee_record,
+                ..
+            })
+            | Command::Credits(Credits::Split {
+                input_record: record,
+                fee_record,
+                ..
+            })
+            | Command::Credits(Credits::Unlock {
+                record, fee_record, ..
+            })
+            | Command::Credits(Credits::Stake {
+                record, fee_record, ..
+            })
+            | Command::Credits(Credits::Unstake {
+                record, fee_record, ..
+            }) => std::iter::once(record).chain(fee_record).collect(),
+            Command::Credits(Credits::Combine {
+                first_record,
+                second_record,
+                fee_record,
+                ..
+            }) => [first_record, second_record]
+                .into_iter()
+                .chain(fee_record)
+                .collect(),
+            Command::Bridge(Bridge::Transfer { record, .. }) => record.iter().collect(),
+            _ => Vec::new(),
+        };
+        inputs
+            .into_iter()
+            .filter_map(|input| match input {
+                vm::UserInputValueType::Record(record) => Some(record.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn decrypt_records(
+        transaction: &Transaction,
+        credentials: account::Credentials,
+    ) -> Vec<vm::Record> {
+        transaction
+            .output_records()
+            .iter()
+            .filter(|(_commitment, record)| {
+                record.is_owner(&credentials.address, &credentials.view_key)
+            })
+            .filter_map(|(_commitment, record)| record.decrypt(&credentials.view_key).ok())
+            .collect()
+    }
+}
+
+async fn run_credits_command(
+    credentials: &account::Credentials,
+    url: &str,
+    function: &str,
+    inputs: &[vm::UserInputValueType],
+    fee_amount: &Option<u64>,
+    fee_record: &Option<vm::UserInputValueType>,
+) -> Result<serde_json::Value> {
+    let transaction =
+        credits_transaction(credentials, url, function, inputs, fee_amount, fee_record).await?;
+    let transaction = Transaction::signed(transaction, credentials.private_key()?, None)?;
+    let transaction_serialized = bincode::serialize(&transaction).unwrap();
+    let outcome = tendermint::broadcast(transaction_serialized, url).await?;
+    Ok(with_broadcast(json!(transaction), outcome))
+}
+
+/// Pay every recipient of the CSV file, in batches of executions where each one spends the change of the previous one.
+async fn transfer_many(
+    credentials: &account::Credentials,
+    url: &str,
+    path: &Path,
+    batch_size: usize,
+    fee: Option<u64>,
+    label: Option<String>,
+) -> Result<serde_json::Value> {
+    if batch_size == 0 {
+        return Err(bad_arguments("the batch size should be positive"));
+    }
+    let payouts = parse_payouts(&fs::read_to_string(path)?)?;
+    ensure!(!payouts.is_empty(), "there are no payouts in {path:?}");
+
+    let batches: Vec<_> = payouts.chunks(batch_size).collect();
+    let fee = fee.unwrap_or(0);
+    let total: u64 = payouts.iter().map(|(_, amount)| amount).sum();
+    let required = total + fee * batches.len() as u64;
+
+    // use the biggest records first, so the fewest of them are spent
+    let mut records = Vec::new();
+    let mut available = 0;
+    for (_, _, record) in get_records(credentials, url)
+        .await?
+        .into_iter()
+        .sorted_by_key(|(_, _, record)| std::cmp::Reverse(vm::gates(record)))
+    {
+        if available >= required {
+            break;
+        }
+        available += vm::gates(&record);
+        records.push(record);
+    }
+    ensure!(
+        available >= required,
+        "the account has {available} gates, but {required} are needed for the payouts and fees"
+    );
+    let records_used = records.len();
+
+    // build every batch before sending any, so a proving failure doesn't leave the payouts half done
+    let chain_id = chain_id(url).await?;
+    let mut transactions = Vec::new();
+    for batch in &batches {
+        let (transaction, change) =
+            Transaction::transfer_many(records, batch, fee, credentials.private_key()?)?;
+        transactions.push(transaction.for_chain(&chain_id, credentials.private_key()?)?);
+        records = vec![change];
+    }
+
+    let client = Client::new(url)?;
+    let mut history = history::History::load()?;
+    let mut outcomes = Vec::new();
+    for (transaction, batch) in transactions.iter().zip(&batches) {
+        outcomes.push(client.broadcast(transaction).await?);
+        history.add_sent(transaction, batch, label.clone());
+        history.save()?;
+    }
+
+    Ok(json!({
+        "transactions": transactions.iter().map(Transaction::id).collect::<Vec<_>>(),
+        "broadcasts": outcomes,
+        "payouts": payouts.len(),
+        "total": total,
+        "fees": fee * transactions.len() as u64,
+        "records_used": records_used,
+    }))
+}
+
+/// Join the smallest unspent records of the account, see `Records::Consolidate`.
+async fn consolidate(
+    credentials: &account::Credentials,
+    url: &str,
+    target: usize,
+    batch_size: usize,
+    fee: Option<u64>,
+    max_fees: Option<u64>,
+) -> Result<serde_json::Value> {
+    ensure!(target > 0, "the target should be at least one record");
+    ensure!(
+        batch_size > 1,
+        "at least two records are needed to join them"
+    );
+    let fee = fee.unwrap_or(0);
 
-                    if !decrypt {
-                        json!(transaction)
-                    } else {
-                        let records = Self::decrypt_records(&transaction, credentials);
+    let records: Vec<vm::Record> = get_records(credentials, url)
+        .await?
+        .into_iter()
+        .map(|(_, _, record)| record)
+        .sorted_by_key(vm::gates)
+        .collect();
+    let gates: Vec<u64> = records.iter().map(vm::gates).collect();
+    let batches = consolidation_batches(&gates, target, batch_size, fee, max_fees);
 
-                        json!({
-                            "execution": transaction,
-                            "decrypted_records": records
-                        })
-                    }
-                }
-            }
-        };
+    // build every batch before sending any, so a proving failure doesn't leave the account half consolidated
+    let chain_id = chain_id(url).await?;
+    let mut records = records.into_iter();
+    let mut transactions = Vec::new();
+    for size in &batches {
+        let batch = records.by_ref().take(*size).collect();
+        let (transaction, _joined) =
+            Transaction::consolidate(batch, fee, credentials.private_key()?)?;
+        transactions.push(transaction.for_chain(&chain_id, credentials.private_key()?)?);
+    }
 
-        Ok(output)
+    let client = Client::new(url)?;
+    let mut outcomes = Vec::new();
+    for transaction in &transactions {
+        outcomes.push(client.broadcast(transaction).await?);
     }
 
-    fn decrypt_records(
-        transaction: &Transaction,
-        credentials: account::Credentials,
-    ) -> Vec<vm::Record> {
-        transaction
-            .output_records()
-            .iter()
-            .filter(|(_commitment, record)| {
-                record.is_owner(&credentials.address, &credentials.view_key)
-            })
-            .filter_map(|(_commitment, record)| record.decrypt(&credentials.view_key).ok())
-            .collect()
+    let joined: usize = batches.iter().sum();
+    Ok(json!({
+        "transactions": transactions.iter().map(Transaction::id).collect::<Vec<_>>(),
+        "broadcasts": outcomes,
+        "records_joined": joined,
+        "records_left": gates.len() - joined + batches.len(),
+        "fees": fee * transactions.len() as u64,
+    }))
+}
+
+/// Decrypt a record ciphertext, failing with a clear error when it isn't owned by the account.
+fn decrypt_record(
+    credentials: &account::Credentials,
+    ciphertext: &vm::EncryptedRecord,
+) -> Result<vm::Record> {
+    ensure!(
+        ciphertext.is_owner(&credentials.address, &credentials.view_key),
+        "the record is not owned by {}",
+        credentials.address
+    );
+    ciphertext.decrypt(&credentials.view_key)
+}
+
+/// Print the fields of a record of the account, see `Records::Show`.
+#[allow(clippy::clone_on_copy)]
+fn show_record(
+    credentials: &account::Credentials,
+    ciphertext: &vm::EncryptedRecord,
+    program: &str,
+    record_name: &str,
+) -> Result<serde_json::Value> {
+    let record = decrypt_record(credentials, ciphertext)?;
+    let commitment = vm::record_commitment(&record, program, record_name)?;
+    let serial_number = match &credentials.private_key {
+        Some(private_key) => Some(vm::compute_serial_number(
+            private_key.clone(),
+            commitment.clone(),
+        )?),
+        None => vm::serial_number_from_view_key(&credentials.view_key, commitment.clone()),
+    };
+    Ok(json!({
+        "owner": vm::record_owner(&record)?.to_string(),
+        "gates": vm::gates(&record),
+        "data": vm::record_data(&record)?,
+        "commitment": commitment,
+        "serial_number": serial_number,
+        "record": record,
+    }))
+}
+
+/// Return the sizes of the batches of records to join, taking them in the given order, until at most
+/// `target` records would be left. Batches are skipped when their records can't pay the fee, or when
+/// their fee would exceed the fee budget.
+fn consolidation_batches(
+    gates: &[u64],
+    target: usize,
+    batch_size: usize,
+    fee: u64,
+    max_fees: Option<u64>,
+) -> Vec<usize> {
+    let mut batches = Vec::new();
+    let mut next = 0;
+    let mut left = gates.len();
+    let mut fees = 0;
+    while left > target {
+        // joining n records leaves a single one, so only join as many as needed to reach the target
+        let size = batch_size.min(left - target + 1).min(gates.len() - next);
+        let total: u64 = gates[next..next + size].iter().sum();
+        let over_budget = max_fees.map_or(false, |max_fees| fees + fee > max_fees);
+        if size < 2 || total < fee || over_budget {
+            break;
+        }
+        batches.push(size);
+        next += size;
+        left -= size - 1;
+        fees += fee;
     }
+    batches
 }
 
-async fn run_credits_command(
+/// Parse the address,amount lines of a payouts CSV file.
+fn parse_payouts(csv: &str) -> Result<Vec<(vm::Address, u64)>> {
+    csv.lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .filter(|(number, line)| !(*number == 1 && line.starts_with("address")))
+        .map(|(number, line)| {
+            let (address, amount) = line
+                .split_once(',')
+                .ok_or_else(|| anyhow!("line {number}: expected address,amount"))?;
+            let address = vm::Address::from_str(address.trim())
+                .map_err(|e| anyhow!("line {number}: invalid address: {e}"))?;
+            let amount = amount
+                .trim()
+                .parse()
+                .map_err(|e| anyhow!("line {number}: invalid amount: {e}"))?;
+            Ok((address, amount))
+        })
+        .collect()
+}
+
+/// Build an execution of the given credits program function, paying the requested fee. It's left out of an
+/// envelope, so memos can still be attached before signing one to bind it to the chain, see `Transaction::for_chain`.
+pub async fn credits_transaction(
     credentials: &account::Credentials,
     url: &str,
     function: &str,
     inputs: &[vm::UserInputValueType],
     fee_amount: &Option<u64>,
     fee_record: &Option<vm::UserInputValueType>,
-) -> Result<serde_json::Value> {
+) -> Result<Transaction> {
     let fee = choose_fee_record(credentials, url, fee_amount, fee_record, inputs).await?;
     let function_identifier = vm::Identifier::from_str(function)?;
-    let transaction =
-        Transaction::credits_execution(function_identifier, inputs, &credentials.private_key, fee)?;
-    let transaction_serialized = bincode::serialize(&transaction).unwrap();
-    tendermint::broadcast(transaction_serialized, url).await?;
-    Ok(json!(transaction))
+    let transaction = Transaction::credits_execution(
+        function_identifier,
+        inputs,
+        credentials.private_key()?,
+        fee.clone(),
+    )?
+    .with_chain_id(&chain_id(url).await?)?;
+    remember_fee_change(credentials, &fee, &transaction)?;
+    Ok(transaction)
+}
+
+/// The rng used to build transactions: seeded with the given value for reproducible ones, or from the system otherwise.
+fn seeded_rng(seed: Option<u64>) -> Result<ChaCha8Rng> {
+    match seed {
+        Some(seed) => Ok(ChaCha8Rng::seed_from_u64(seed)),
+        None => Ok(ChaCha8Rng::from_rng(rand::thread_rng())?),
+    }
+}
+
+/// A progress bar of the functions whose keys were synthesized, printed to stderr so it doesn't mix with the output.
+fn key_synthesis_progress() -> ProgressBar {
+    let progress = ProgressBar::new(0);
+    progress.set_style(
+        ProgressStyle::with_template("{msg} [{bar:30}] {pos}/{len} functions")
+            .expect("invalid progress bar template")
+            .progress_chars("=> "),
+    );
+    progress.set_message("Synthesizing program keys");
+    progress
+}
+
+/// Return the id of the chain of the node at the given url, pinning it for the current ALEO_HOME the
+/// first time it's seen. Fails if the node is part of another chain than the pinned one, so transactions
+/// meant for a devnet aren't sent to a different network by mistake.
+async fn chain_id(url: &str) -> Result<String> {
+    let chain_id = Client::new(url)?.chain_id().await?;
+    let path = lib::aleo_home().join("chain_id");
+    match fs::read_to_string(&path) {
+        Ok(pinned) => ensure!(
+            pinned.trim() == chain_id,
+            "the node at {url} is part of chain {chain_id}, but the client is pinned to chain {} (remove {} to switch chains)",
+            pinned.trim(),
+            path.display()
+        ),
+        Err(_) => {
+            fs::create_dir_all(lib::aleo_home())?;
+            fs::write(&path, &chain_id)?;
+        }
+    }
+    Ok(chain_id)
 }
 
-/// Extends the snarkvm's default argument parsing to support using record ciphertexts as record inputs
+/// Extends the snarkvm's default argument parsing to support using record ciphertexts, and references to the
+/// cached records, as record inputs
 fn parse_input_value(input: &str) -> Result<vm::UserInputValueType> {
-    // try parsing an encrypted record string
-    if input.starts_with("record") {
+    if input.starts_with(record_cache::REFERENCE_PREFIX) {
         return parse_input_record(input);
     }
-
-    // %account is a syntactic sugar for current user address
-    if input == "%account" {
+    // encrypted records and %account need the account keys, loaded only when used
+    if input.starts_with("record") || input == "%account" {
         let credentials = account::Credentials::load()?;
-        let address = credentials.address.to_string();
-        return vm::UserInputValueType::from_str(&address);
+        return parse_input_with_view_key(input, &credentials.view_key);
     }
 
     // try parsing a jsonified plaintext record
@@ -429,45 +2184,212 @@ fn parse_input_value(input: &str) -> Result<vm::UserInputValueType> {
     vm::UserInputValueType::from_str(input)
 }
 
+/// Same as `parse_input_value`, but using the given view key instead of the stored credentials,
+/// to decrypt records and resolve `%account`.
+pub fn parse_input_with_view_key(
+    input: &str,
+    view_key: &vm::ViewKey,
+) -> Result<vm::UserInputValueType> {
+    if let Some(reference) = input.strip_prefix(record_cache::REFERENCE_PREFIX) {
+        return RecordCache::load()?
+            .resolve(reference)?
+            .decrypt(view_key)
+            .map(vm::UserInputValueType::Record);
+    }
+    if input.starts_with("record") {
+        return vm::EncryptedRecord::from_str(input)?
+            .decrypt(view_key)
+            .map(vm::UserInputValueType::Record);
+    }
+    if input == "%account" {
+        let address = vm::Address::try_from(view_key)?.to_string();
+        return vm::UserInputValueType::from_str(&address);
+    }
+    if let Ok(record) = serde_json::from_str::<vm::Record>(input) {
+        return Ok(vm::UserInputValueType::Record(record));
+    }
+    vm::UserInputValueType::from_str(input)
+}
+
+/// Parse a record ciphertext, or a `record:` reference to a cached record, decrypting it with the account keys.
 pub fn parse_input_record(input: &str) -> Result<vm::UserInputValueType> {
-    let encrypted_record = vm::EncryptedRecord::from_str(input)?;
+    let encrypted_record = match input.strip_prefix(record_cache::REFERENCE_PREFIX) {
+        Some(reference) => RecordCache::load()?.resolve(reference)?,
+        None => vm::EncryptedRecord::from_str(input)?,
+    };
 
+    // the record can be owned by any of the merged accounts, see `account merge`
     let credentials = account::Credentials::load()?;
-    encrypted_record
-        .decrypt(&credentials.view_key)
-        .map(vm::UserInputValueType::Record)
+    let record = credentials
+        .accounts()
+        .iter()
+        .find_map(|account| encrypted_record.decrypt(&account.view_key).ok());
+    match record {
+        Some(record) => Ok(vm::UserInputValueType::Record(record)),
+        None => encrypted_record
+            .decrypt(&credentials.view_key)
+            .map(vm::UserInputValueType::Record),
+    }
 }
 
 /// Retrieves all records from the blockchain, and only those that are correctly decrypted
-/// (i.e, are owned by the ssed credentials) and have not been spent are returned
-async fn get_records(
+/// (i.e, are owned by the ssed credentials, or the accounts merged into them) and have not been spent are returned.
+/// They are kept in the record cache, so the execution inputs can reference them.
+pub async fn get_records(
     credentials: &account::Credentials,
     url: &str,
 ) -> Result<Vec<(vm::Field, vm::EncryptedRecord, vm::Record)>> {
-    let get_records_response = tendermint::query(AbciQuery::GetRecords.into(), url).await?;
-    let get_spent_records_response =
-        tendermint::query(AbciQuery::GetSpentSerialNumbers.into(), url).await?;
-
-    let records: Vec<(vm::Field, vm::EncryptedRecord)> =
-        bincode::deserialize(&get_records_response)?;
-    let spent_records: HashSet<vm::Field> = bincode::deserialize(&get_spent_records_response)?;
+    let client = Client::new(url)?;
+    let mut records = Vec::new();
+    for account in credentials.accounts() {
+        records.extend(
+            client
+                .get_unspent_records_with(&account.decryptor()?)
+                .await?,
+        );
+    }
+    let mut cache = RecordCache::load()?;
+    cache.update(&records);
+    cache.save()?;
+    Ok(records)
+}
 
-    debug!("Records: {:?}", records);
-    #[allow(clippy::clone_on_copy)]
-    let records = records
+/// Split the unspent record with the given commitment, owned by any of the merged accounts, into records of the
+/// given amounts, see `Records::Split`.
+async fn split_record(
+    credentials: &account::Credentials,
+    url: &str,
+    commitment: &vm::Field,
+    amounts: &[u64],
+    fee: Option<u64>,
+) -> Result<serde_json::Value> {
+    let fee = fee.unwrap_or(0);
+    let record = get_records(credentials, url)
+        .await?
         .into_iter()
-        .filter_map(|(commitment, ciphertext)| {
-            ciphertext
-                .decrypt(&credentials.view_key)
-                .map(|decrypted_record| (commitment.clone(), ciphertext, decrypted_record))
-                .ok()
-                .filter(|(_, _ciphertext, _decrypted_record)| {
-                    let serial_number = compute_serial_number(credentials.private_key, commitment);
-                    serial_number.is_ok() && !spent_records.contains(&serial_number.unwrap())
-                })
-        })
+        .find(|(record_commitment, _, _)| record_commitment.to_string() == commitment.to_string())
+        .map(|(_, _, record)| record)
+        .ok_or_else(|| {
+            not_found(format!(
+                "there's no unspent record with commitment {commitment} in the account"
+            ))
+        })?;
+    check_denominations(vm::gates(&record), amounts, fee)?;
+    let owner = credentials.owner_of(std::slice::from_ref(&record))?;
+
+    // the last amount is the change left after splitting the rest and paying the fee
+    let (transaction, _change) = Transaction::split_many(
+        record,
+        &amounts[..amounts.len() - 1],
+        fee,
+        owner.private_key()?,
+    )?;
+    let transaction = transaction.for_chain(&chain_id(url).await?, owner.private_key()?)?;
+    let outcome = Client::new(url)?.broadcast(&transaction).await?;
+    Ok(json!({
+        "transaction": transaction.id(),
+        "amounts": amounts,
+        "fee": fee,
+        "broadcast": outcome,
+    }))
+}
+
+/// Check that a record with the given gates can be split into the amounts, paying the fee, without gates left over.
+fn check_denominations(gates: u64, amounts: &[u64], fee: u64) -> Result<()> {
+    ensure!(
+        amounts.len() > 1,
+        "at least two amounts are needed to split a record"
+    );
+    ensure!(
+        amounts.iter().all(|amount| *amount > 0),
+        "the amounts should be positive"
+    );
+    let total = amounts
+        .iter()
+        .try_fold(fee, |total, amount| total.checked_add(*amount))
+        .ok_or_else(|| anyhow!("the amounts plus the fee overflow"))?;
+    ensure!(
+        total <= gates,
+        "the amounts plus the fee add up to {total} gates, but the record has {gates}"
+    );
+    ensure!(
+        total == gates,
+        "the amounts plus the fee add up to {total} gates, but the record has {gates}; add the {} gates left as another amount",
+        gates - total
+    );
+    Ok(())
+}
+
+/// Scan all the records in the blockchain, keeping the ones the decryptor tells are owned by the given address, and
+/// build a statement of the received and spent amounts for it. Whether a record was spent is told by its serial
+/// number, so it's only known if the decryptor can derive them, i.e. with the private key, or with the view key on
+/// the VM backends that allow it.
+#[allow(clippy::clone_on_copy)]
+async fn audit(
+    address: &vm::Address,
+    decryptor: &impl RecordDecryptor,
+    url: &str,
+) -> Result<serde_json::Value> {
+    let client = Client::new(url)?;
+    let records = client.get_record_entries_with(decryptor).await?;
+    let commitments: Vec<vm::Field> = records
+        .iter()
+        .map(|(entry, _)| entry.commitment.clone())
         .collect();
-    Ok(records)
+    let serial_numbers = decryptor.serial_numbers(&commitments).await?;
+    let spent_records: HashMap<vm::Field, Option<u64>> = match serial_numbers {
+        Some(_) => client
+            .get_spent_serial_numbers()
+            .await?
+            .into_iter()
+            .map(|entry| (entry.serial_number, entry.height))
+            .collect(),
+        None => HashMap::new(),
+    };
+
+    let mut statement = Vec::new();
+    let mut total_received = 0;
+    let mut total_spent = 0;
+    for (
+        index,
+        (
+            RecordEntry {
+                commitment, height, ..
+            },
+            record,
+        ),
+    ) in records.into_iter().enumerate()
+    {
+        let gates = vm::gates(&record);
+        total_received += gates;
+
+        let spent = serial_numbers.as_ref().map(|serial_numbers| {
+            match spent_records.get(&serial_numbers[index]) {
+                Some(spent_height) => {
+                    total_spent += gates;
+                    json!({ "spent": true, "height": spent_height })
+                }
+                None => json!({ "spent": false }),
+            }
+        });
+
+        statement.push(json!({
+            "commitment": commitment,
+            "gates": gates,
+            "height": height,
+            "record": record,
+            // null if the spent status can't be determined without the private key
+            "spent": spent,
+        }));
+    }
+
+    Ok(json!({
+        "address": address,
+        "records": statement,
+        "total_received": total_received,
+        // null along with the spent status of the records
+        "total_spent": serial_numbers.is_some().then_some(total_spent),
+    }))
 }
 
 /// Given a desired amount of fee to pay, find the record on this account with the biggest
@@ -481,27 +2403,135 @@ async fn choose_fee_record(
     record: &Option<vm::UserInputValueType>,
     inputs: &[vm::UserInputValueType],
 ) -> Result<Option<(u64, vm::Record)>> {
-    if amount.is_none() {
-        return Ok(None);
-    }
-    let amount = amount.unwrap();
+    let config = ClientConfig::load()?;
+    let amount = match amount.or(config.fee) {
+        Some(amount) => amount,
+        None => return Ok(None),
+    };
 
     if let Some(vm::UserInputValueType::Record(record_value)) = record {
         return Ok(Some((amount, record_value.clone())));
     }
 
-    let account_records: Vec<vm::Record> = get_records(credentials, url)
-        .await?
+    let account_records = get_records(credentials, url).await?;
+    // the configured record may be owned by another of the merged accounts, which can't pay for this one
+    if let Some(Ok(fee_record)) = config
+        .fee_record
+        .as_ref()
+        .map(|fee_record| fee_record.decrypt(&credentials.view_key))
+    {
+        let fee_record = fee_record.to_string();
+        let configured = account_records
+            .iter()
+            .find(|(_, _, record)| record.to_string() == fee_record)
+            .map(|(_, _, record)| record.clone());
+        match configured.map(|record| select_default_fee_record(amount, inputs, &[record])) {
+            Some(Ok(record)) => return Ok(Some((amount, record))),
+            _ => eprintln!("The configured fee record is spent, used as input or doesn't have enough gates, paying with the record with most gates instead"),
+        }
+    }
+
+    let account_records: Vec<vm::Record> = account_records
         .into_iter()
         .map(|(_, _, record)| record)
         .collect();
-
     select_default_fee_record(amount, inputs, &account_records).map(|record| Some((amount, record)))
 }
 
+/// If the transaction pays its fee with the record set with `config set-fee --record`, set the change of the fee
+/// as the fee record instead, so the next transaction can pay with it.
+fn remember_fee_change(
+    credentials: &account::Credentials,
+    fee: &Option<(u64, vm::Record)>,
+    transaction: &Transaction,
+) -> Result<()> {
+    let mut config = ClientConfig::load()?;
+    let paid_with_fee_record = match (fee, &config.fee_record) {
+        (Some((_, record)), Some(fee_record)) => fee_record
+            .decrypt(&credentials.view_key)
+            .map_or(false, |fee_record| {
+                fee_record.to_string() == record.to_string()
+            }),
+        _ => false,
+    };
+    if let (true, Some(change)) = (paid_with_fee_record, transaction.fee_change_record()) {
+        config.fee_record = Some(change);
+        config.save()?;
+    }
+    Ok(())
+}
+
+fn config(command: Config) -> Result<serde_json::Value> {
+    let mut config = ClientConfig::load()?;
+    match command {
+        Config::Show => return Ok(json!(config)),
+        Config::SetFee { amount, record } => {
+            config.fee = Some(amount);
+            config.fee_record = record;
+        }
+        Config::ClearFee => {
+            config.fee = None;
+            config.fee_record = None;
+        }
+    }
+    let path = config.save()?;
+    Ok(json!({ "path": path, "config": config }))
+}
+
+fn parse_mnemonic(input: &str) -> Result<Mnemonic> {
+    Mnemonic::from_str(input.trim()).map_err(|e| anyhow!("invalid mnemonic: {e}"))
+}
+
+/// Parse a record ciphertext, checking it's owned by the account so fees can be paid with it.
+fn parse_fee_record(input: &str) -> Result<vm::EncryptedRecord> {
+    let encrypted_record = vm::EncryptedRecord::from_str(input)?;
+    let credentials = account::Credentials::load()?;
+    encrypted_record.decrypt(&credentials.view_key)?;
+    Ok(encrypted_record)
+}
+
+/// Compare the compiled source file with the deployed program locally, and then have the node check it against the
+/// published source hash as well. The local comparison doesn't depend on the node being honest about the result.
+async fn verify_source(
+    program_id: vm::ProgramID,
+    path: &Path,
+    url: &str,
+) -> Result<serde_json::Value> {
+    let source = fs::read_to_string(path)?;
+    let compiled = vm::generate_program(&source)?;
+    ensure!(
+        compiled.id() == &program_id,
+        "the source file defines {} instead of {program_id}",
+        compiled.id()
+    );
+    let client = Client::new(url)?;
+    let deployed = client
+        .get_program(&program_id)
+        .await?
+        .ok_or_else(|| not_found(format!("Program {program_id} does not exist")))?;
+    let program_matches =
+        lib::merkle::program_hash(&compiled) == lib::merkle::program_hash(&deployed);
+
+    let verification = client.verify_source(program_id, source.clone()).await?;
+    Ok(json!({
+        "program_id": program_id.to_string(),
+        "source_hash": Transaction::hash_source(&source),
+        "program_matches": program_matches,
+        "source_hash_matches": verification.source_hash_matches,
+        "verified": program_matches && verification.verified,
+    }))
+}
+
+/// Load a program from a source file, or fetch it from the blockchain by program id or registered name.
 async fn get_program(url: &str, program: &str) -> Result<Option<vm::Program>> {
     match fs::read_to_string(PathBuf::from(program)) {
         Ok(program_string) => vm::generate_program(&program_string).map(Some),
+        Err(_) if names::parse(program).is_ok() => {
+            match Client::new(url)?.resolve_name(program).await?.program_id {
+                Some(program_id) => get_program_from_blockchain(url, program_id).await,
+                None => Ok(None),
+            }
+        }
         Err(_) => get_program_from_blockchain(url, ProgramID::from_str(program)?).await,
     }
 }
@@ -510,8 +2540,24 @@ async fn get_program_from_blockchain(
     url: &str,
     program_id: vm::ProgramID,
 ) -> Result<Option<vm::Program>> {
-    let result = tendermint::query(AbciQuery::GetProgram { program_id }.into(), url).await?;
-    let program: Option<vm::Program> = bincode::deserialize(&result)?;
+    let key = program_id.to_string();
+    if let Some(program) = PROGRAM_CACHE
+        .lock()
+        .unwrap()
+        .get_or_insert_with(HashMap::new)
+        .get(&key)
+    {
+        return Ok(Some(program.clone()));
+    }
+
+    let program = Client::new(url)?.get_program(&program_id).await?;
+    if let Some(program) = &program {
+        PROGRAM_CACHE
+            .lock()
+            .unwrap()
+            .get_or_insert_with(HashMap::new)
+            .insert(key, program.clone());
+    }
     Ok(program)
 }
 
@@ -564,6 +2610,305 @@ fn select_default_fee_record(
         .cloned()
 }
 
+/// Run a command of the offline signing workflow. Only signing needs the account credentials.
+async fn tx(command: Tx, url: &str) -> Result<serde_json::Value> {
+    match command {
+        Tx::Build(build) => {
+            let (unsigned, output) = match build {
+                TxBuild::Execute {
+                    program,
+                    function,
+                    inputs,
+                    view_key,
+                    fee,
+                    fee_record,
+                    output,
+                } => {
+                    let program = match get_program(url, &program).await? {
+                        Some(program) => program,
+                        None => return Err(not_found(format!("Could not find program {program}"))),
+                    };
+                    let inputs = inputs
+                        .iter()
+                        .map(|input| parse_input_with_view_key(input, &view_key))
+                        .collect::<Result<Vec<_>>>()?;
+                    let fee = unsigned_fee(fee, fee_record, &view_key)?;
+                    let unsigned = UnsignedTransaction::Execution {
+                        program: Box::new(program),
+                        function,
+                        inputs,
+                        fee,
+                        chain_id: Some(chain_id(url).await?),
+                    };
+                    (unsigned, output)
+                }
+                TxBuild::Deploy {
+                    path,
+                    view_key,
+                    fee,
+                    fee_record,
+                    output,
+                } => {
+                    let program = fs::read_to_string(&path)?;
+                    // fail early instead of on the offline host
+                    vm::generate_program(&program)?;
+                    let fee = unsigned_fee(fee, fee_record, &view_key)?;
+                    let unsigned = UnsignedTransaction::Deployment {
+                        program,
+                        fee,
+                        chain_id: Some(chain_id(url).await?),
+                    };
+                    (unsigned, output)
+                }
+            };
+            fs::write(&output, serde_json::to_string_pretty(&unsigned)?)?;
+            Ok(json!({ "unsigned_transaction": output }))
+        }
+        Tx::Sign { file, output } => {
+            let credentials = account::Credentials::load()?;
+            let unsigned: UnsignedTransaction = serde_json::from_str(&fs::read_to_string(&file)?)?;
+            let transaction = unsigned.prove(credentials.private_key()?)?;
+            fs::write(&output, serde_json::to_string_pretty(&transaction)?)?;
+            Ok(json!({
+                "transaction": output,
+                "id": transaction.id(),
+                "size": size_report(&transaction)?,
+            }))
+        }
+        Tx::Authorize { file, output } => {
+            let credentials = account::Credentials::load()?;
+            let unsigned: UnsignedTransaction = serde_json::from_str(&fs::read_to_string(&file)?)?;
+            let authorized = unsigned.authorize(credentials.private_key()?)?;
+            fs::write(&output, serde_json::to_string_pretty(&authorized)?)?;
+            Ok(json!({ "authorized_transaction": output }))
+        }
+        Tx::Prove { file, output } => {
+            let authorized: AuthorizedExecution =
+                serde_json::from_str(&fs::read_to_string(&file)?)?;
+            let transaction = authorized.prove()?;
+            fs::write(&output, serde_json::to_string_pretty(&transaction)?)?;
+            Ok(json!({
+                "transaction": output,
+                "id": transaction.id(),
+                "size": size_report(&transaction)?,
+            }))
+        }
+        Tx::Broadcast {
+            file,
+            sign,
+            expires_at,
+        } => {
+            let mut transaction: Transaction = serde_json::from_str(&fs::read_to_string(&file)?)?;
+            // transactions proven without knowing the chain id, e.g. by a delegated prover, are bound to it here
+            let unsigned = !transaction.binds_chain_id();
+            if transaction.chain_id().is_none() && unsigned {
+                transaction = transaction.with_chain_id(&chain_id(url).await?)?;
+            }
+            if sign {
+                if !unsigned {
+                    return Err(bad_arguments("the transaction is already signed"));
+                }
+                let credentials =
+                    account::Credentials::load().map_err(|_| not_found("credentials not found"))?;
+                transaction =
+                    Transaction::signed(transaction, credentials.private_key()?, expires_at)?;
+            } else if unsigned {
+                return Err(bad_arguments(
+                    "the transaction has to be wrapped in a signed envelope to be bound to the chain, pass --sign",
+                ));
+            }
+            transaction.verify()?;
+            let outcome = Client::new(url)?.broadcast(&transaction).await?;
+            Ok(with_broadcast(json!(transaction), outcome))
+        }
+        Tx::Batch { files } => {
+            // the envelopes of the signed files are replaced by the one of the batch, which binds all of them
+            let transactions = files
+                .iter()
+                .map(|file| {
+                    let transaction: Transaction =
+                        serde_json::from_str(&fs::read_to_string(file)?)?;
+                    Ok(match transaction {
+                        Transaction::Signed { transaction, .. } => *transaction,
+                        transaction => transaction,
+                    })
+                })
+                .collect::<Result<Vec<Transaction>>>()?;
+            let credentials =
+                account::Credentials::load().map_err(|_| not_found("credentials not found"))?;
+            let transaction = Transaction::batch(transactions)?
+                .for_chain(&chain_id(url).await?, credentials.private_key()?)?;
+            transaction.verify()?;
+            let size = size_report(&transaction)?;
+            let outcome = Client::new(url)?.broadcast(&transaction).await?;
+            Ok(json!({
+                "id": transaction.id(),
+                "transactions": transaction.parts().iter().map(|part| part.id()).collect::<Vec<_>>(),
+                "size": size,
+                "broadcast": outcome,
+            }))
+        }
+        Tx::Debug { file } => {
+            let transaction: Transaction = serde_json::from_str(&fs::read_to_string(&file)?)?;
+            debug::run(&transaction, url).await
+        }
+        Tx::Decode { input, output } => {
+            let path = Path::new(&input);
+            let transaction = if path.is_file() {
+                decode_transaction(&fs::read(path)?)?
+            } else {
+                decode_transaction(input.as_bytes())?
+            };
+            match output {
+                Some(output) => {
+                    fs::write(&output, serde_json::to_string_pretty(&transaction)?)?;
+                    Ok(json!({
+                        "transaction": output,
+                        "id": transaction.id(),
+                    }))
+                }
+                None => Ok(json!(transaction)),
+            }
+        }
+        Tx::Encode {
+            file,
+            format,
+            output,
+        } => {
+            let transaction: Transaction = serde_json::from_str(&fs::read_to_string(&file)?)?;
+            let bytes = bincode::serialize(&transaction)?;
+            let encoded = match format {
+                WireEncoding::Base64 => base64::encode(&bytes),
+                WireEncoding::Hex => hex::encode(&bytes),
+                WireEncoding::Raw => String::new(),
+            };
+            let mut result = json!({
+                "id": transaction.id(),
+                "size": bytes.len(),
+            });
+            match output {
+                Some(output) => {
+                    match format {
+                        WireEncoding::Raw => fs::write(&output, &bytes)?,
+                        _ => fs::write(&output, &encoded)?,
+                    }
+                    result["transaction"] = json!(output);
+                }
+                None => result["encoded"] = json!(encoded),
+            }
+            Ok(result)
+        }
+    }
+}
+
+/// Decode a transaction from its wire bytes, or from their hex or base64 text. Each of them is tried in turn,
+/// since a text could be valid in more than one encoding but only the right one deserializes to a transaction.
+fn decode_transaction(input: &[u8]) -> Result<Transaction> {
+    let mut candidates = vec![input.to_vec()];
+    if let Ok(text) = std::str::from_utf8(input) {
+        let text: String = text.split_whitespace().collect();
+        let text = text.trim_matches('"');
+        if let Ok(bytes) = hex::decode(text.trim_start_matches("0x")) {
+            candidates.push(bytes);
+        }
+        if let Ok(bytes) = base64::decode(text) {
+            candidates.push(bytes);
+        }
+    }
+    candidates
+        .iter()
+        .find_map(|bytes| bincode::deserialize::<Transaction>(bytes).ok())
+        .ok_or_else(|| {
+            bad_arguments(
+                "the input is not a transaction in the wire format, nor its hex or base64 text",
+            )
+        })
+}
+
+/// Describe the size and verification cost of the transaction. If it exceeds the node limits a warning is
+/// printed to stderr, since it will most likely be rejected when broadcast.
+async fn mempool(command: Mempool, url: &str) -> Result<serde_json::Value> {
+    let Mempool::List { limit, mine } = command;
+    let (mut transactions, total) = Client::new(url)?
+        .get_unconfirmed_transactions(limit)
+        .await?;
+
+    if mine {
+        let credentials =
+            account::Credentials::load().map_err(|_| not_found("credentials not found"))?;
+        // records spent by pending transactions are still unspent for the node
+        let commitments: Vec<vm::Field> = get_records(&credentials, url)
+            .await?
+            .into_iter()
+            .map(|(commitment, _, _)| commitment)
+            .collect();
+        let serial_numbers: HashSet<vm::Field> = credentials
+            .decryptor()?
+            .serial_numbers(&commitments)
+            .await?
+            .ok_or_else(|| {
+                anyhow!(
+                    "the serial numbers of the account records can't be derived with its view key"
+                )
+            })?
+            .into_iter()
+            .collect();
+        let address = credentials.address.to_string();
+        transactions.retain(|transaction| {
+            transaction.sender().as_ref() == Some(&address)
+                || transaction
+                    .record_serial_numbers()
+                    .iter()
+                    .any(|serial_number| serial_numbers.contains(serial_number))
+        });
+    }
+
+    let transactions = transactions
+        .iter()
+        .map(Transaction::summary)
+        .collect::<Result<Vec<_>>>()?;
+    Ok(json!({ "total": total, "transactions": transactions }))
+}
+
+/// Add how the node took the transaction to the command output, e.g. whether it was already in its cache.
+fn with_broadcast(mut output: serde_json::Value, outcome: BroadcastOutcome) -> serde_json::Value {
+    if let Some(object) = output.as_object_mut() {
+        object.insert("broadcast".to_string(), json!(outcome));
+    }
+    output
+}
+
+fn size_report(transaction: &Transaction) -> Result<serde_json::Value> {
+    let warnings = transaction.limit_warnings()?;
+    for warning in &warnings {
+        eprintln!("warning: {warning}");
+    }
+    Ok(json!({
+        "bytes": transaction.size()?,
+        "transitions": transaction.transition_count(),
+        "gas": transaction.gas(),
+        "warnings": warnings,
+    }))
+}
+
+/// Decrypt the fee record given to build an unsigned transaction.
+fn unsigned_fee(
+    fee: Option<u64>,
+    fee_record: Option<String>,
+    view_key: &vm::ViewKey,
+) -> Result<Option<(u64, vm::Record)>> {
+    match (fee, fee_record) {
+        (Some(amount), Some(record)) => {
+            let record = EncryptedRecord::from_str(&record)?.decrypt(view_key)?;
+            Ok(Some((amount, record)))
+        }
+        (None, None) => Ok(None),
+        _ => Err(bad_arguments(
+            "--fee and --fee-record must be given together",
+        )),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -627,6 +2972,94 @@ mod tests {
         assert_eq!(record6, result);
     }
 
+    #[test]
+    #[allow(clippy::clone_on_copy)]
+    fn show_record_of_the_account() {
+        let credentials = account::Credentials::new().unwrap();
+        let (commitment, ciphertext) =
+            vm::mint_record("credits.aleo", "credits", &credentials.address, 10, 1).unwrap();
+
+        let shown = show_record(&credentials, &ciphertext, "credits.aleo", "credits").unwrap();
+        assert_eq!(json!(credentials.address.to_string()), shown["owner"]);
+        assert_eq!(json!(10), shown["gates"]);
+        assert_eq!(json!(commitment), shown["commitment"]);
+        let serial_number =
+            vm::compute_serial_number(credentials.private_key().unwrap().clone(), commitment)
+                .unwrap();
+        assert_eq!(json!(serial_number), shown["serial_number"]);
+
+        let other = account::Credentials::new().unwrap();
+        assert!(show_record(&other, &ciphertext, "credits.aleo", "credits").is_err());
+    }
+
+    #[test]
+    #[allow(clippy::clone_on_copy)]
+    fn parse_payouts_csv() {
+        let private_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let view_key = vm::ViewKey::try_from(&private_key).unwrap();
+        let address = Address::try_from(&view_key).unwrap();
+
+        let csv = format!("address,amount\n{address},10\n\n# bonus\n {address} , 5 \n");
+        let payouts = parse_payouts(&csv).unwrap();
+        assert_eq!(vec![(address.clone(), 10), (address.clone(), 5)], payouts);
+
+        let error = parse_payouts(&format!("{address},ten")).unwrap_err();
+        assert!(error.to_string().starts_with("line 1: invalid amount"));
+        let error = parse_payouts(&format!("{address},10\n{address}")).unwrap_err();
+        assert_eq!("line 2: expected address,amount", error.to_string());
+    }
+
+    #[test]
+    fn denominations_add_up_to_the_record() {
+        check_denominations(100, &[50, 30, 15], 5).unwrap();
+        check_denominations(100, &[60, 40], 0).unwrap();
+
+        assert!(check_denominations(100, &[100], 0).is_err());
+        assert!(check_denominations(100, &[100, 0], 0).is_err());
+        assert!(check_denominations(100, &[50, 30], 5)
+            .unwrap_err()
+            .to_string()
+            .contains("add the 15 gates left"));
+        assert!(check_denominations(100, &[60, 40], 1).is_err());
+        assert!(check_denominations(u64::MAX, &[u64::MAX, 1], 0).is_err());
+    }
+
+    #[test]
+    fn consolidation_batches_stop_at_target() {
+        // only as many records as needed to reach the target are joined
+        assert_eq!(
+            vec![3],
+            consolidation_batches(&[1, 2, 3, 4, 5], 3, 10, 0, None)
+        );
+        // large amounts of records are split in batches
+        assert_eq!(vec![4, 4], consolidation_batches(&[1; 8], 1, 4, 0, None));
+        // nothing to do if the target is already met
+        assert!(consolidation_batches(&[1, 2], 2, 10, 0, None).is_empty());
+
+        // records that can't pay the fee are not joined
+        assert!(consolidation_batches(&[1, 1, 1], 1, 10, 5, None).is_empty());
+        // batches beyond the fee budget are skipped
+        assert_eq!(vec![2], consolidation_batches(&[10; 6], 1, 2, 5, Some(9)));
+    }
+
+    #[test]
+    fn decode_wire_transactions() {
+        let transaction = Transaction::batch(vec![]).unwrap();
+        let bytes = bincode::serialize(&transaction).unwrap();
+
+        for input in [
+            bytes.clone(),
+            hex::encode(&bytes).into_bytes(),
+            format!("0x{}\n", hex::encode(&bytes)).into_bytes(),
+            base64::encode(&bytes).into_bytes(),
+        ] {
+            let decoded = decode_transaction(&input).unwrap();
+            assert_eq!(transaction.id(), decoded.id());
+        }
+
+        assert!(decode_transaction(b"not a transaction").is_err());
+    }
+
     fn mint_record(view_key: &vm::ViewKey, amount: u64) -> vm::Record {
         let address = Address::try_from(view_key).unwrap();
         vm::mint_record("credits.aleo", "credits", &address, amount, 123)
@@ -636,3 +3069,191 @@ mod tests {
             .unwrap()
     }
 }
+
+/// Run a governance command. These are signed with the validator key instead of the account credentials.
+async fn governance(command: Governance, url: &str) -> Result<serde_json::Value> {
+    let (action, validator_key) = match command {
+        Governance::Show => {
+            let status = Client::new(url)?.get_governance().await?;
+            return Ok(json!(status));
+        }
+        Governance::CommunityPool => {
+            let pool = Client::new(url)?.get_community_pool().await?;
+            return Ok(json!(pool));
+        }
+        Governance::Propose {
+            parameter,
+            value,
+            activation_height,
+            validator_key,
+        } => (
+            governance::Action::Propose {
+                parameter,
+                value,
+                activation_height,
+            },
+            validator_key,
+        ),
+        Governance::Vote {
+            proposal_id,
+            reject,
+            validator_key,
+        } => (
+            governance::Action::Vote {
+                proposal_id,
+                approve: !reject,
+            },
+            validator_key,
+        ),
+        Governance::Spend {
+            recipient,
+            amount,
+            activation_height,
+            validator_key,
+        } => (
+            governance::Action::SpendCommunityPool {
+                recipient,
+                amount,
+                activation_height,
+            },
+            validator_key,
+        ),
+    };
+
+    let key = ValidatorKey::load(&validator_key_path(validator_key)?)?;
+    let address = hex::encode_upper(pub_key_to_address(&key.pub_key));
+    let status = Client::new(url)?.get_governance().await?;
+    let nonce = status.nonces.get(&address).copied().unwrap_or_default();
+    let transaction = Transaction::governance(action, nonce, &key, Some(chain_id(url).await?))?;
+    let transaction_serialized = bincode::serialize(&transaction).unwrap();
+    let outcome = tendermint::broadcast(transaction_serialized, url).await?;
+    Ok(with_broadcast(json!(transaction), outcome))
+}
+
+/// Submit the packets sent by the chain of the node at `url` that the destination chain at `to` didn't receive yet,
+/// along with the header that commits them. The packets are read at the last committed height, so the header at the
+/// next height is awaited before submitting them.
+async fn relay_packets(url: &str, to: &str) -> Result<serde_json::Value> {
+    let source = Client::new(url)?;
+    let destination = Client::new(to)?;
+    let source_chain = source.chain_id().await?;
+    let destination_chain = destination.chain_id().await?;
+
+    let next_sequence = destination
+        .get_bridge_status()
+        .await?
+        .counterparties
+        .into_iter()
+        .find(|counterparty| counterparty.chain_id == source_chain)
+        .ok_or_else(|| anyhow!("{destination_chain} doesn't accept packets from {source_chain}"))?
+        .next_receive_sequence;
+    let pending = source
+        .get_packets(
+            &destination_chain,
+            next_sequence,
+            bridge::MAX_PACKETS_PER_RECEIVE,
+        )
+        .await?;
+    if pending.packets.is_empty() {
+        return Ok(json!({ "relayed": 0 }));
+    }
+
+    let mut attempts = 0;
+    let (header, validators) = loop {
+        match source.signed_header(pending.height + 1).await {
+            Ok(header) => break header,
+            Err(e) if attempts >= 30 => return Err(e),
+            Err(_) => {
+                attempts += 1;
+                tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+            }
+        }
+    };
+
+    let relayed = pending.packets.len();
+    let transaction = Transaction::bridge_receive(&header, &validators, pending.packets)?
+        .with_chain_id(&destination_chain)?;
+    let outcome = destination.broadcast(&transaction).await?;
+    Ok(json!({ "relayed": relayed, "transaction": transaction, "broadcast": outcome }))
+}
+
+/// Deploy a token program from the bundled template and mint its supply to the account, registering it in the
+/// client token registry. The mint is sent right after the deployment, without waiting for it to be committed,
+/// since nodes accept executions of programs whose deployment is in their mempool.
+async fn create_token(
+    credentials: &account::Credentials,
+    url: &str,
+    name: String,
+    symbol: &str,
+    supply: u64,
+    fee: Option<u64>,
+) -> Result<serde_json::Value> {
+    let program_id = token::program_id(symbol)?;
+    ensure!(
+        Client::new(url)?.get_program(&program_id).await?.is_none(),
+        "program {program_id} is already deployed, pick another symbol"
+    );
+    #[allow(clippy::clone_on_copy)]
+    let creator = credentials.address.clone();
+    let source = token::program_source(&program_id, &creator);
+    let chain_id = chain_id(url).await?;
+
+    let deployment_fee = choose_fee_record(credentials, url, &fee, &None, &[]).await?;
+    let progress = key_synthesis_progress();
+    let deployment = Transaction::deployment_with_progress(
+        &source,
+        credentials.private_key()?,
+        deployment_fee.clone(),
+        |done, total| {
+            progress.set_length(total as u64);
+            progress.set_position(done as u64);
+        },
+    )?
+    .with_source_hash(&source)?
+    .for_chain(&chain_id, credentials.private_key()?)?;
+    progress.finish_and_clear();
+
+    // the mint can't pay with the record spent by the deployment fee, since it's not committed yet
+    let spent_by_deployment: Vec<vm::UserInputValueType> = deployment_fee
+        .iter()
+        .map(|(_, record)| vm::UserInputValueType::Record(record.clone()))
+        .collect();
+    let mint_fee = choose_fee_record(credentials, url, &fee, &None, &spent_by_deployment).await?;
+    let mint = Transaction::execution(
+        vm::generate_program(&source)?,
+        vm::Identifier::from_str("mint")?,
+        &[vm::UserInputValueType::from_str(&format!("{supply}u64"))?],
+        credentials.private_key()?,
+        mint_fee.clone(),
+    )?
+    .for_chain(&chain_id, credentials.private_key()?)?;
+
+    tendermint::broadcast(bincode::serialize(&deployment)?, url).await?;
+    remember_fee_change(credentials, &deployment_fee, &deployment)?;
+    tendermint::broadcast(bincode::serialize(&mint)?, url).await?;
+    remember_fee_change(credentials, &mint_fee, &mint)?;
+
+    let token = TokenMetadata {
+        program_id: program_id.to_string(),
+        name,
+        symbol: symbol.to_string(),
+        supply,
+        creator,
+        deployment: deployment.id().to_string(),
+        mint: mint.id().to_string(),
+    };
+    let mut registry = TokenRegistry::load()?;
+    registry.add(token.clone());
+    registry.save()?;
+    Ok(json!(token))
+}
+
+/// Return the given validator key path, or the default tendermint one if missing.
+fn validator_key_path(path: Option<PathBuf>) -> Result<PathBuf> {
+    match path {
+        Some(path) => Ok(path),
+        None => Ok(dirs::home_dir()
+            .ok_or_else(|| anyhow!("couldn't find the home directory"))?
+            .join(".tendermint/config/priv_validator_key.json")),
+    }
+}
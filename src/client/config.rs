@@ -0,0 +1,40 @@
+use anyhow::Result;
+use lib::vm;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+/// Client settings, stored at $ALEO_HOME/config.json. Commands fall back to them when the matching flags aren't passed.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ClientConfig {
+    /// Amount of gates paid as fee by the commands that take a `--fee`.
+    #[serde(default)]
+    pub fee: Option<u64>,
+    /// Record fees are paid with when `--fee-record` isn't passed. Every time a transaction pays with it, it's replaced
+    /// by the change record of the fee. If not set, the record of the account with most gates is picked for each fee.
+    #[serde(default)]
+    pub fee_record: Option<vm::EncryptedRecord>,
+}
+
+impl ClientConfig {
+    /// Load the client settings, or the defaults if they were never saved.
+    pub fn load() -> Result<Self> {
+        match fs::read_to_string(Self::path()) {
+            Ok(json) => Ok(serde_json::from_str(&json)?),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    pub fn save(&self) -> Result<PathBuf> {
+        let file = Self::path();
+        fs::create_dir_all(lib::aleo_home())?;
+        debug!("Saving client config to {}", file.to_string_lossy());
+        fs::write(&file, serde_json::to_string_pretty(&self)?)?;
+        Ok(file)
+    }
+
+    fn path() -> PathBuf {
+        lib::aleo_home().join("config.json")
+    }
+}
@@ -0,0 +1,234 @@
+use anyhow::Result;
+use itertools::Itertools;
+use lib::client::Client;
+use lib::diagnostic::{Check, Diagnostic};
+use lib::transaction::{Transaction, MAX_BLOCK_GAS};
+use lib::vm;
+use serde_json::json;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// The state of the node a transaction is checked against by `tx debug`.
+#[derive(Debug, Default)]
+pub struct NodeState {
+    pub chain_id: String,
+    /// Height of the last committed block, the transaction would be included at best in the next one.
+    pub height: u64,
+    /// Height of the block that spent each spent record, by serial number, if known.
+    pub spent: HashMap<String, Option<u64>>,
+    /// Verifying keys of the programs deployed or run by the transaction, None for the ones that aren't deployed.
+    pub verifying_keys: HashMap<String, Option<vm::VerifyingKeyMap>>,
+}
+
+impl NodeState {
+    /// Fetch the parts of the node state the transaction depends on.
+    pub async fn fetch(client: &Client, transaction: &Transaction) -> Result<Self> {
+        let spent = client
+            .get_spent_serial_numbers()
+            .await?
+            .into_iter()
+            .map(|entry| (entry.serial_number.to_string(), entry.height))
+            .collect();
+        let mut verifying_keys = HashMap::new();
+        for program in transaction.programs() {
+            let keys = client
+                .get_verifying_keys(&vm::ProgramID::from_str(&program)?)
+                .await?;
+            verifying_keys.insert(program, keys);
+        }
+        Ok(Self {
+            chain_id: client.chain_id().await?,
+            height: client.latest_height().await?,
+            spent,
+            verifying_keys,
+        })
+    }
+}
+
+/// Re-verify the transaction locally against the state of the node, to explain why it was rejected, or would be.
+pub async fn run(transaction: &Transaction, url: &str) -> Result<serde_json::Value> {
+    let state = NodeState::fetch(&Client::new(url)?, transaction).await?;
+    let diagnostics = diagnose(transaction, &state);
+    Ok(json!({
+        "id": transaction.id(),
+        "height": state.height,
+        "valid": diagnostics.is_empty(),
+        "diagnostics": diagnostics,
+    }))
+}
+
+/// Run the checks the node does on the transaction, returning a diagnostic for each one that fails. Unlike the
+/// node, it doesn't stop at the first failure. The checks that depend on the node configuration, e.g. the program
+/// policy and the fee parameters, aren't run.
+pub fn diagnose(transaction: &Transaction, state: &NodeState) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut fail = |diagnostic: Diagnostic| {
+        diagnostics.push(diagnostic.for_transaction(transaction.id()));
+    };
+    let next_height = state.height + 1;
+
+    if let Err(e) = transaction.verify_structure() {
+        fail(Diagnostic::new(Check::Structure, e));
+    }
+    if let Err(e) = transaction.verify_signatures() {
+        fail(Diagnostic::new(Check::Signature, e));
+    }
+    let gas = transaction.gas();
+    if gas > MAX_BLOCK_GAS {
+        fail(Diagnostic::new(
+            Check::BlockLimits,
+            format!("Transaction gas {gas} exceeds the block gas limit {MAX_BLOCK_GAS}"),
+        ));
+    }
+    if transaction.chain_id() != Some(state.chain_id.as_str()) {
+        fail(Diagnostic::new(
+            Check::ChainId,
+            format!("Transaction is not meant for chain {}", state.chain_id),
+        ));
+    } else if !transaction.binds_chain_id() {
+        fail(Diagnostic::new(
+            Check::ChainId,
+            format!(
+                "Transaction has to be wrapped in a signed envelope to be bound to chain {}",
+                state.chain_id
+            ),
+        ));
+    }
+
+    let serial_numbers = transaction.record_serial_numbers();
+    for serial_number in serial_numbers.iter().duplicates() {
+        fail(
+            Diagnostic::new(
+                Check::DuplicateInput,
+                format!("record with serial number {serial_number} is spent more than once"),
+            )
+            .with_serial_number(serial_number),
+        );
+    }
+    for serial_number in &serial_numbers {
+        if let Some(height) = state.spent.get(&serial_number.to_string()) {
+            let message = match height {
+                Some(height) => format!(
+                    "input record serial number {serial_number} was already spent at height {height}"
+                ),
+                None => format!("input record serial number {serial_number} is already spent"),
+            };
+            fail(Diagnostic::new(Check::SpentInput, message).with_serial_number(serial_number));
+        }
+    }
+    match transaction.unlock_heights() {
+        Ok(unlock_heights) => {
+            for unlock_height in unlock_heights.into_iter().filter(|h| *h > next_height) {
+                fail(Diagnostic::new(
+                    Check::LockedInput,
+                    format!("input record is locked until height {unlock_height}"),
+                ));
+            }
+        }
+        Err(e) => fail(Diagnostic::new(Check::Other, e)),
+    }
+
+    // programs deployed by the previous parts of a batch can be run by the following ones
+    let mut deployed = state.verifying_keys.clone();
+    for part in transaction.parts() {
+        let mut fail = |diagnostic: Diagnostic| {
+            diagnostics.push(diagnostic.for_transaction(part.id()));
+        };
+        if let Some(expires_at) = part
+            .expires_at()
+            .filter(|expires_at| next_height > *expires_at)
+        {
+            fail(Diagnostic::new(
+                Check::Expired,
+                format!("Transaction expired at height {expires_at}"),
+            ));
+        }
+
+        let transitions: Vec<&vm::Transition> = match part {
+            Transaction::Deployment {
+                program,
+                verifying_keys,
+                fee,
+                ..
+            } => {
+                let program_id = program.id().to_string();
+                if matches!(deployed.get(&program_id), Some(Some(_))) {
+                    fail(Diagnostic::new(
+                        Check::Other,
+                        format!("Program already exists: {program_id}"),
+                    ));
+                }
+                if let Err(e) = vm::verify_deployment(program, verifying_keys.clone()) {
+                    fail(Diagnostic::new(Check::Proof, e));
+                }
+                deployed.insert(program_id, Some(verifying_keys.clone()));
+                fee.iter().collect()
+            }
+            Transaction::Execution { transitions, .. } => transitions.iter().collect(),
+            Transaction::BridgeTransfer { burn, .. } => vec![burn],
+            _ => vec![],
+        };
+        for (index, transition) in transitions.into_iter().enumerate() {
+            let program_id = transition.program_id().to_string();
+            let result = match deployed.get(&program_id) {
+                Some(Some(keys)) => vm::verify_execution(transition, keys)
+                    .map_err(|e| Diagnostic::new(Check::Proof, e)),
+                _ => Err(Diagnostic::new(
+                    Check::MissingProgram,
+                    format!("Program {program_id} does not exist"),
+                )),
+            };
+            if let Err(diagnostic) = result {
+                fail(diagnostic.with_transition(transition).at_index(index));
+            }
+        }
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn failed_checks_are_diagnosed() {
+        let private_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let deployment =
+            Transaction::deployment(Path::new("aleo/hello.aleo"), &private_key, None).unwrap();
+        let verifying_keys = match &deployment {
+            Transaction::Deployment { verifying_keys, .. } => verifying_keys.clone(),
+            _ => unreachable!(),
+        };
+
+        // the program is already deployed and the transaction doesn't have the chain id
+        let mut state = NodeState {
+            chain_id: "testnet".to_string(),
+            height: 10,
+            ..Default::default()
+        };
+        state
+            .verifying_keys
+            .insert("hello.aleo".to_string(), Some(verifying_keys));
+        let diagnostics = diagnose(&deployment, &state);
+        let checks: Vec<Check> = diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.check)
+            .collect();
+        assert_eq!(vec![Check::ChainId, Check::Other], checks);
+        assert!(diagnostics[1].message.contains("Program already exists"));
+        assert_eq!(
+            Some(deployment.id().to_string()),
+            diagnostics[0].transaction
+        );
+
+        state.verifying_keys.insert("hello.aleo".to_string(), None);
+        let deployment = deployment.with_chain_id("testnet").unwrap();
+        let diagnostics = diagnose(&deployment, &state);
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(Check::ChainId, diagnostics[0].check);
+
+        let signed = Transaction::signed(deployment, &private_key, None).unwrap();
+        assert!(diagnose(&signed, &state).is_empty());
+    }
+}
@@ -0,0 +1,145 @@
+//! Exit codes of the client by class of failure, so scripts can tell e.g. a node that's down from a rejected
+//! transaction without parsing the error message.
+
+use lib::diagnostic::Diagnostic;
+use lib::vm;
+use std::fmt;
+
+/// Exit codes table shown at the end of `client --help`.
+pub const HELP: &str = "Exit codes:
+  0  success
+  1  any other failure
+  2  invalid arguments
+  3  the node couldn't be reached, or its response couldn't be read
+  4  the transaction was rejected by the node (check_tx)
+  5  running the program or generating its keys or proofs failed
+  6  what was asked for doesn't exist, e.g. a transaction, program, record or the credentials";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExitCode {
+    Success = 0,
+    Failure = 1,
+    /// The arguments are invalid. Clap exits with the same code for the arguments it rejects itself.
+    BadArguments = 2,
+    Network = 3,
+    Rejected = 4,
+    Proof = 5,
+    NotFound = 6,
+}
+
+impl ExitCode {
+    /// The exit code for the error, given by the first error of its chain that belongs to a class.
+    pub fn of(error: &anyhow::Error) -> Self {
+        for cause in error.chain() {
+            if let Some(failure) = cause.downcast_ref::<Failure>() {
+                return failure.code;
+            }
+            if cause.is::<Diagnostic>() {
+                return Self::Rejected;
+            }
+            if cause.is::<vm::ProvingError>() {
+                return Self::Proof;
+            }
+            if cause.is::<lib::client::NotFound>() {
+                return Self::NotFound;
+            }
+            if cause.is::<tendermint_rpc::Error>() || cause.is::<reqwest::Error>() {
+                return Self::Network;
+            }
+            if cause.is::<clap::Error>() {
+                return Self::BadArguments;
+            }
+            if let Some(error) = cause.downcast_ref::<std::io::Error>() {
+                if error.kind() == std::io::ErrorKind::NotFound {
+                    return Self::NotFound;
+                }
+            }
+        }
+        Self::Failure
+    }
+
+    pub fn code(self) -> i32 {
+        self as i32
+    }
+}
+
+/// Error of the client commands that exits with the given code.
+#[derive(Debug)]
+pub struct Failure {
+    pub code: ExitCode,
+    pub message: String,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for Failure {}
+
+pub fn not_found(message: impl ToString) -> anyhow::Error {
+    Failure {
+        code: ExitCode::NotFound,
+        message: message.to_string(),
+    }
+    .into()
+}
+
+pub fn bad_arguments(message: impl ToString) -> anyhow::Error {
+    Failure {
+        code: ExitCode::BadArguments,
+        message: message.to_string(),
+    }
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+    use lib::diagnostic::Check;
+
+    #[test]
+    fn errors_are_mapped_to_exit_codes() {
+        assert_eq!(ExitCode::Failure, ExitCode::of(&anyhow!("other")));
+        assert_eq!(
+            ExitCode::NotFound,
+            ExitCode::of(&not_found("credentials not found"))
+        );
+        assert_eq!(
+            ExitCode::BadArguments,
+            ExitCode::of(&bad_arguments("the batch size should be positive"))
+        );
+
+        // the class is found anywhere in the chain, and the message is kept
+        let rejected = anyhow::Error::new(Diagnostic::new(Check::SpentInput, "already spent"))
+            .context("Error executing transaction 1");
+        assert_eq!(ExitCode::Rejected, ExitCode::of(&rejected));
+        assert_eq!("Error executing transaction 1", rejected.to_string());
+
+        let proof = vm::proving_failed(anyhow!("expects 2 inputs"));
+        assert_eq!(ExitCode::Proof, ExitCode::of(&proof));
+        assert_eq!("expects 2 inputs", proof.to_string());
+
+        let missing = lib::client::NotFound("Transaction ID at1 is invalid".to_string());
+        assert_eq!(ExitCode::NotFound, ExitCode::of(&missing.into()));
+
+        let missing_file = std::fs::read("no/such/file.json").unwrap_err();
+        assert_eq!(ExitCode::NotFound, ExitCode::of(&missing_file.into()));
+
+        assert_eq!(
+            vec![0, 1, 2, 3, 4, 5, 6],
+            [
+                ExitCode::Success,
+                ExitCode::Failure,
+                ExitCode::BadArguments,
+                ExitCode::Network,
+                ExitCode::Rejected,
+                ExitCode::Proof,
+                ExitCode::NotFound
+            ]
+            .map(ExitCode::code)
+        );
+    }
+}
@@ -0,0 +1,225 @@
+use crate::account::Credentials;
+use anyhow::{ensure, Result};
+use lib::client::Client;
+use lib::transaction::Transaction;
+use lib::vm;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+
+/// Whether a payment left or reached the account.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+/// A payment sent or received by the account, as kept in $ALEO_HOME/history.json.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// Id of the transaction of sent payments. For received ones, the commitment of the received record,
+    /// since the transaction that created a record can't be told from it.
+    pub id: String,
+    pub direction: Direction,
+    /// The recipient of sent payments. Unknown for received ones, the sender isn't part of the record.
+    pub counterparty: Option<vm::Address>,
+    /// Amount of gates sent or received, without the fee.
+    pub amount: u64,
+    /// Height of the block that included the payment, None until it's known to be committed.
+    pub height: Option<u64>,
+    pub label: Option<String>,
+    /// Commitments of the records created by the transaction of a sent payment, e.g. the change,
+    /// so they aren't listed as received.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub outputs: Vec<vm::Field>,
+}
+
+/// The payments of the account, in the order the client learned about them. Sent payments are added by the commands
+/// that make them, and received ones by `sync`, when the history is listed.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct History {
+    pub entries: Vec<HistoryEntry>,
+}
+
+impl History {
+    /// Load the history, or an empty one if there's none yet.
+    pub fn load() -> Result<Self> {
+        match fs::read_to_string(Self::path()) {
+            Ok(json) => Ok(serde_json::from_str(&json)?),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    pub fn save(&self) -> Result<PathBuf> {
+        let file = Self::path();
+        fs::create_dir_all(lib::aleo_home())?;
+        debug!("Saving transaction history to {}", file.to_string_lossy());
+        fs::write(&file, serde_json::to_string_pretty(&self)?)?;
+        Ok(file)
+    }
+
+    /// Add the payments of a sent transaction, one entry for each recipient and amount.
+    #[allow(clippy::clone_on_copy)]
+    pub fn add_sent(
+        &mut self,
+        transaction: &Transaction,
+        payments: &[(vm::Address, u64)],
+        label: Option<String>,
+    ) {
+        let outputs: Vec<vm::Field> = transaction
+            .output_records()
+            .into_iter()
+            .map(|(commitment, _)| commitment)
+            .collect();
+        for (recipient, amount) in payments {
+            self.entries.push(HistoryEntry {
+                id: transaction.id().to_string(),
+                direction: Direction::Sent,
+                counterparty: Some(recipient.clone()),
+                amount: *amount,
+                height: None,
+                label: label.clone(),
+                outputs: outputs.clone(),
+            });
+        }
+    }
+
+    /// Set the label of the entries with the given id, failing if there's none.
+    pub fn label(&mut self, id: &str, label: &str) -> Result<()> {
+        let mut found = false;
+        for entry in self.entries.iter_mut().filter(|entry| entry.id == id) {
+            entry.label = Some(label.to_string());
+            found = true;
+        }
+        ensure!(found, "there's no payment with id {id} in the history");
+        Ok(())
+    }
+
+    /// Bring the history up to date with the blockchain: set the height of the sent payments that got committed,
+    /// and add the records of the accounts that weren't created by them as received payments. Records the accounts
+    /// created with other commands, e.g. `credits split`, are listed as received too.
+    pub async fn sync(&mut self, credentials: &Credentials, url: &str) -> Result<()> {
+        let client = Client::new(url)?;
+        for entry in &mut self.entries {
+            if entry.direction == Direction::Sent && entry.height.is_none() {
+                entry.height = client.get_transaction_height(&entry.id).await?;
+            }
+        }
+
+        let known: HashSet<String> = self
+            .entries
+            .iter()
+            .flat_map(|entry| {
+                entry
+                    .outputs
+                    .iter()
+                    .map(|commitment| commitment.to_string())
+                    .chain(std::iter::once(entry.id.clone()))
+            })
+            .collect();
+        let accounts = credentials.accounts();
+        for record in client.scan_records().await? {
+            if known.contains(&record.commitment.to_string()) {
+                continue;
+            }
+            let plaintext = accounts.iter().find_map(|account| {
+                if record
+                    .ciphertext
+                    .is_owner(&account.address, &account.view_key)
+                {
+                    record.ciphertext.decrypt(&account.view_key).ok()
+                } else {
+                    None
+                }
+            });
+            if let Some(plaintext) = plaintext {
+                self.entries.push(HistoryEntry {
+                    id: record.commitment.to_string(),
+                    direction: Direction::Received,
+                    counterparty: None,
+                    amount: vm::gates(&plaintext),
+                    height: record.height,
+                    label: None,
+                    outputs: Vec::new(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Render the history as CSV, with an id,direction,counterparty,amount,height,label header.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("id,direction,counterparty,amount,height,label\n");
+        for entry in &self.entries {
+            let direction = match entry.direction {
+                Direction::Sent => "sent",
+                Direction::Received => "received",
+            };
+            let fields = [
+                entry.id.clone(),
+                direction.to_string(),
+                entry
+                    .counterparty
+                    .as_ref()
+                    .map(ToString::to_string)
+                    .unwrap_or_default(),
+                entry.amount.to_string(),
+                entry.height.map(|h| h.to_string()).unwrap_or_default(),
+                entry.label.clone().unwrap_or_default(),
+            ];
+            let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+            csv.push_str(&fields.join(","));
+            csv.push('\n');
+        }
+        csv
+    }
+
+    fn path() -> PathBuf {
+        lib::aleo_home().join("history.json")
+    }
+}
+
+/// Quote the field if it has a comma, quote or line break, as labels are free text.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_are_labeled_and_exported() {
+        let address = Credentials::new().unwrap().address;
+        let expected = format!("tx1,sent,{address},10,3,\"rent, \"\"march\"\"\"");
+        let mut history = History {
+            entries: vec![HistoryEntry {
+                id: "tx1".to_string(),
+                direction: Direction::Sent,
+                counterparty: Some(address),
+                amount: 10,
+                height: Some(3),
+                label: None,
+                outputs: Vec::new(),
+            }],
+        };
+        history.label("tx1", "rent, \"march\"").unwrap();
+        assert!(history.label("tx2", "unknown").is_err());
+
+        let csv = history.to_csv();
+        let mut lines = csv.lines();
+        assert_eq!(
+            Some("id,direction,counterparty,amount,height,label"),
+            lines.next()
+        );
+        assert_eq!(Some(expected.as_str()), lines.next());
+        assert_eq!(None, lines.next());
+    }
+}
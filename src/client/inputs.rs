@@ -0,0 +1,117 @@
+use crate::account::Credentials;
+use crate::commands::{get_records, parse_input_with_view_key};
+use crate::record_cache::{RecordCache, REFERENCE_PREFIX};
+use anyhow::{anyhow, Context, Result};
+use lib::vm;
+use serde::Deserialize;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+
+/// An input of the function in an inputs file, see `read`.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum InputValue {
+    /// A literal like `1u32`, an address, `%account`, a record ciphertext or a `record:` reference to a cached
+    /// record, as passed in the command line.
+    Literal(String),
+    /// A record of the account referenced by its commitment, e.g. `{"commitment": "123field"}`.
+    Reference { commitment: String },
+    /// A plaintext record, as printed by `records decrypt`.
+    Plaintext(vm::Record),
+}
+
+/// Parse an inputs file: a JSON array with a value for each input of the function.
+pub fn parse(json: &str) -> Result<Vec<InputValue>> {
+    serde_json::from_str(json).context(
+        "the inputs should be a JSON array of literals, record ciphertexts, plaintext records or {\"commitment\": ...} references",
+    )
+}
+
+/// Read the inputs of an execution from the given JSON file, or from stdin if the path is `-`, so long record
+/// ciphertexts don't have to be passed as arguments. Record references are resolved from the record cache, which is
+/// refreshed from the node if the record isn't cached, and every record is decrypted with the account keys.
+pub async fn read(
+    path: &Path,
+    credentials: &Credentials,
+    url: &str,
+) -> Result<Vec<vm::UserInputValueType>> {
+    let json = if path == Path::new("-") {
+        let mut json = String::new();
+        std::io::stdin().read_to_string(&mut json)?;
+        json
+    } else {
+        std::fs::read_to_string(path)?
+    };
+
+    let mut cache = RecordCache::load()?;
+    let mut inputs = Vec::new();
+    for value in parse(&json)? {
+        let input = match value {
+            InputValue::Literal(literal) if literal.starts_with(REFERENCE_PREFIX) => {
+                let reference = &literal[REFERENCE_PREFIX.len()..];
+                vm::UserInputValueType::Record(
+                    cached(&mut cache, reference, credentials, url).await?,
+                )
+            }
+            InputValue::Literal(literal) if literal.starts_with("record") => {
+                vm::UserInputValueType::Record(decrypt(
+                    &vm::EncryptedRecord::from_str(&literal)?,
+                    credentials,
+                )?)
+            }
+            InputValue::Literal(literal) => {
+                parse_input_with_view_key(&literal, &credentials.view_key)?
+            }
+            InputValue::Reference { commitment } => vm::UserInputValueType::Record(
+                cached(&mut cache, &commitment, credentials, url).await?,
+            ),
+            InputValue::Plaintext(record) => vm::UserInputValueType::Record(record),
+        };
+        inputs.push(input);
+    }
+    Ok(inputs)
+}
+
+/// Decrypt the cached record referenced by an alias or a commitment prefix, refreshing the cache from the node if
+/// it's not there.
+async fn cached(
+    cache: &mut RecordCache,
+    reference: &str,
+    credentials: &Credentials,
+    url: &str,
+) -> Result<vm::Record> {
+    if cache.resolve(reference).is_err() {
+        // fetching the records refreshes the cache
+        get_records(credentials, url).await?;
+        *cache = RecordCache::load()?;
+    }
+    decrypt(&cache.resolve(reference)?, credentials)
+}
+
+/// Decrypt the record with the keys of the account, or of any of the accounts merged into it.
+fn decrypt(ciphertext: &vm::EncryptedRecord, credentials: &Credentials) -> Result<vm::Record> {
+    credentials
+        .accounts()
+        .iter()
+        .find_map(|account| ciphertext.decrypt(&account.view_key).ok())
+        .ok_or_else(|| anyhow!("the record {ciphertext} isn't owned by the account"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inputs_files_are_parsed() {
+        let inputs = parse(r#"["1u32", "%account", {"commitment": "123field"}]"#).unwrap();
+        assert!(matches!(&inputs[0], InputValue::Literal(literal) if literal == "1u32"));
+        assert!(matches!(&inputs[1], InputValue::Literal(literal) if literal == "%account"));
+        assert!(
+            matches!(&inputs[2], InputValue::Reference { commitment } if commitment == "123field")
+        );
+
+        assert!(parse(r#"{"inputs": ["1u32"]}"#).is_err());
+        assert!(parse(r#"[1]"#).is_err());
+    }
+}
@@ -1,17 +1,31 @@
-use clap::Parser;
+use clap::{CommandFactory, Parser};
+use exit_code::ExitCode;
+use lib::diagnostic::Diagnostic;
 use serde_json::json;
 use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::EnvFilter;
 
 mod account;
+mod backup;
+mod bench;
 mod commands;
+mod config;
+mod debug;
+mod exit_code;
+mod history;
+mod inputs;
+mod node;
+mod record_cache;
+mod repl;
+mod serve;
 mod tendermint;
+mod token;
 
 /// Default tendermint url
 const LOCAL_BLOCKCHAIN_URL: &str = "http://127.0.0.1:26657";
 
 #[derive(Debug, Parser)]
-#[clap()]
+#[clap(after_help = exit_code::HELP)]
 pub struct Cli {
     /// Specify a subcommand.
     #[clap(subcommand)]
@@ -40,11 +54,34 @@ async fn main() {
             .init();
     }
 
-    let (exit_code, output) = match cli.command.run(cli.url).await {
-        Ok(output) => (0, output),
-        Err(err) => (1, json!({"error": err.to_string()})),
+    let result = match cli.command {
+        commands::Command::Completions { shell } => {
+            // the Makefile installs the client as bin/aleo
+            clap_complete::generate(shell, &mut Cli::command(), "aleo", &mut std::io::stdout());
+            return;
+        }
+        commands::Command::Repl => match repl::run(&cli.url).await {
+            Ok(()) => return,
+            Err(err) => Err(err),
+        },
+        commands::Command::Serve { port } => match serve::run(&cli.url, port).await {
+            Ok(()) => return,
+            Err(err) => Err(err),
+        },
+        command => command.run(cli.url).await,
+    };
+    let (exit_code, output) = match result {
+        Ok(output) => (ExitCode::Success, output),
+        // transactions rejected by the node come with a diagnostic of the check that failed
+        Err(err) => {
+            let output = match err.downcast_ref::<Diagnostic>() {
+                Some(diagnostic) => json!({"error": err.to_string(), "diagnostic": diagnostic}),
+                None => json!({"error": err.to_string()}),
+            };
+            (ExitCode::of(&err), output)
+        }
     };
 
     println!("{output:#}");
-    std::process::exit(exit_code);
+    std::process::exit(exit_code.code());
 }
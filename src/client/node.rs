@@ -0,0 +1,267 @@
+use anyhow::{anyhow, ensure, Result};
+use clap::Parser;
+use serde_json::json;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Validator state tendermint starts from when its data is removed, so the node can sign again from the first block.
+const EMPTY_VALIDATOR_STATE: &str = "{\n  \"height\": \"0\",\n  \"round\": 0,\n  \"step\": 0\n}";
+
+/// Directories where a local node keeps its state.
+#[derive(Debug, Parser)]
+pub struct NodeDirs {
+    /// Directory where aleo_abci runs, which holds the application state (the `*.db` stores and `abci.*` files).
+    #[clap(long, default_value = ".")]
+    pub abci_dir: PathBuf,
+    /// Tendermint home of the node, defaults to ~/.tendermint
+    #[clap(long)]
+    pub tendermint_home: Option<PathBuf>,
+}
+
+impl NodeDirs {
+    fn tendermint_home(&self) -> Result<PathBuf> {
+        match &self.tendermint_home {
+            Some(path) => Ok(path.clone()),
+            None => Ok(dirs::home_dir()
+                .ok_or_else(|| anyhow!("couldn't find the home directory"))?
+                .join(".tendermint")),
+        }
+    }
+
+    fn snapshot(&self, name: &str) -> Result<PathBuf> {
+        ensure!(
+            !name.is_empty() && !name.starts_with('.') && !name.contains(['/', '\\']),
+            "invalid snapshot name {name}"
+        );
+        Ok(self.abci_dir.join("snapshots").join(name))
+    }
+}
+
+/// Remove the application state and the tendermint blocks, so the node starts the chain again from scratch.
+/// With `keep_genesis` the tendermint configuration, genesis and keys are kept; otherwise the whole tendermint home
+/// is removed and has to be initialized again.
+pub fn reset(dirs: &NodeDirs, keep_genesis: bool) -> Result<serde_json::Value> {
+    let removed = clear_app_state(&dirs.abci_dir)?;
+    let tendermint_home = dirs.tendermint_home()?;
+    if keep_genesis {
+        clear_tendermint_data(&tendermint_home)?;
+    } else {
+        remove(&tendermint_home)?;
+    }
+    Ok(json!({
+        "removed": removed,
+        "tendermint_home": tendermint_home,
+        "keep_genesis": keep_genesis,
+    }))
+}
+
+/// Copy the application state, the tendermint data and the genesis of the node to a snapshot with the given name.
+pub fn save_snapshot(dirs: &NodeDirs, name: &str) -> Result<serde_json::Value> {
+    let snapshot = dirs.snapshot(name)?;
+    ensure!(
+        !snapshot.exists(),
+        "snapshot {name} already exists in {}",
+        snapshot.display()
+    );
+    let tendermint_home = dirs.tendermint_home()?;
+
+    for entry in app_state_entries(&dirs.abci_dir)? {
+        copy(&entry, &snapshot.join("abci").join(file_name(&entry)?))?;
+    }
+    copy(
+        &tendermint_home.join("data"),
+        &snapshot.join("tendermint").join("data"),
+    )?;
+    copy(
+        &tendermint_home.join("config").join("genesis.json"),
+        &snapshot.join("tendermint").join("genesis.json"),
+    )?;
+    Ok(json!({ "snapshot": snapshot }))
+}
+
+/// Replace the state of the node with the one saved in the snapshot with the given name.
+pub fn restore_snapshot(dirs: &NodeDirs, name: &str) -> Result<serde_json::Value> {
+    let snapshot = dirs.snapshot(name)?;
+    ensure!(
+        snapshot.is_dir(),
+        "snapshot {name} not found in {}",
+        snapshot.display()
+    );
+    let tendermint_home = dirs.tendermint_home()?;
+
+    clear_app_state(&dirs.abci_dir)?;
+    let saved_state = snapshot.join("abci");
+    if saved_state.is_dir() {
+        for entry in fs::read_dir(&saved_state)? {
+            let entry = entry?.path();
+            copy(&entry, &dirs.abci_dir.join(file_name(&entry)?))?;
+        }
+    }
+    remove(&tendermint_home.join("data"))?;
+    copy(
+        &snapshot.join("tendermint").join("data"),
+        &tendermint_home.join("data"),
+    )?;
+    copy(
+        &snapshot.join("tendermint").join("genesis.json"),
+        &tendermint_home.join("config").join("genesis.json"),
+    )?;
+    Ok(json!({ "snapshot": snapshot }))
+}
+
+/// Return the names of the saved snapshots, sorted.
+pub fn list_snapshots(dirs: &NodeDirs) -> Result<serde_json::Value> {
+    let snapshots = dirs.abci_dir.join("snapshots");
+    let mut names = Vec::new();
+    if snapshots.is_dir() {
+        for entry in fs::read_dir(&snapshots)? {
+            let entry = entry?.path();
+            if entry.is_dir() {
+                names.push(file_name(&entry)?.to_string());
+            }
+        }
+    }
+    names.sort();
+    Ok(json!({ "snapshots": names }))
+}
+
+/// Return the paths of the application state in the aleo_abci directory, the same ones `make reset` removes.
+fn app_state_entries(abci_dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut entries = Vec::new();
+    if !abci_dir.is_dir() {
+        return Ok(entries);
+    }
+    for entry in fs::read_dir(abci_dir)? {
+        let path = entry?.path();
+        let name = file_name(&path)?;
+        if name.ends_with(".db") || name.starts_with("abci.") {
+            entries.push(path);
+        }
+    }
+    entries.sort();
+    Ok(entries)
+}
+
+fn clear_app_state(abci_dir: &Path) -> Result<Vec<PathBuf>> {
+    let entries = app_state_entries(abci_dir)?;
+    for entry in &entries {
+        remove(entry)?;
+    }
+    Ok(entries)
+}
+
+/// Same as `tendermint unsafe-reset-all`: remove the blocks and reset the validator state, keeping the config.
+fn clear_tendermint_data(tendermint_home: &Path) -> Result<()> {
+    let data = tendermint_home.join("data");
+    remove(&data)?;
+    fs::create_dir_all(&data)?;
+    fs::write(
+        data.join("priv_validator_state.json"),
+        EMPTY_VALIDATOR_STATE,
+    )?;
+    Ok(())
+}
+
+fn file_name(path: &Path) -> Result<&str> {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| anyhow!("invalid file name {}", path.display()))
+}
+
+fn remove(path: &Path) -> Result<()> {
+    if path.is_dir() {
+        fs::remove_dir_all(path)?;
+    } else if path.exists() {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// Copy a file or a directory with all of its contents, creating the parent directories of the destination.
+fn copy(from: &Path, to: &Path) -> Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if from.is_dir() {
+        fs::create_dir_all(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?.path();
+            copy(&entry, &to.join(file_name(&entry)?))?;
+        }
+    } else {
+        fs::copy(from, to).map_err(|e| anyhow!("couldn't copy {}: {e}", from.display()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::TempDir;
+
+    fn write(path: &Path, contents: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    #[test]
+    fn snapshots_are_restored_and_reset() {
+        let dir = TempDir::new().unwrap();
+        let dirs = NodeDirs {
+            abci_dir: dir.path().join("abci"),
+            tendermint_home: Some(dir.path().join("tendermint")),
+        };
+        let tendermint = dir.path().join("tendermint");
+        write(&dirs.abci_dir.join("records.db/CURRENT"), "records at 10");
+        write(&dirs.abci_dir.join("abci.height"), "10");
+        write(&dirs.abci_dir.join("policy.json"), "{}");
+        write(
+            &tendermint.join("data/blockstore.db/CURRENT"),
+            "blocks at 10",
+        );
+        write(&tendermint.join("config/genesis.json"), "genesis");
+
+        save_snapshot(&dirs, "height-10").unwrap();
+        assert!(save_snapshot(&dirs, "height-10").is_err());
+        assert!(save_snapshot(&dirs, "../outside").is_err());
+        assert_eq!(
+            json!({ "snapshots": ["height-10"] }),
+            list_snapshots(&dirs).unwrap()
+        );
+
+        write(&dirs.abci_dir.join("records.db/CURRENT"), "records at 20");
+        write(&dirs.abci_dir.join("programs.db/CURRENT"), "programs at 20");
+        write(
+            &tendermint.join("data/blockstore.db/CURRENT"),
+            "blocks at 20",
+        );
+        restore_snapshot(&dirs, "height-10").unwrap();
+        let read = |path: PathBuf| fs::read_to_string(path).unwrap();
+        assert_eq!(
+            "records at 10",
+            read(dirs.abci_dir.join("records.db/CURRENT"))
+        );
+        assert!(!dirs.abci_dir.join("programs.db").exists());
+        assert_eq!(
+            "blocks at 10",
+            read(tendermint.join("data/blockstore.db/CURRENT"))
+        );
+
+        reset(&dirs, true).unwrap();
+        assert!(!dirs.abci_dir.join("records.db").exists());
+        assert!(!dirs.abci_dir.join("abci.height").exists());
+        assert!(dirs.abci_dir.join("policy.json").exists());
+        assert!(!tendermint.join("data/blockstore.db").exists());
+        assert_eq!(
+            EMPTY_VALIDATOR_STATE,
+            read(tendermint.join("data/priv_validator_state.json"))
+        );
+        assert_eq!("genesis", read(tendermint.join("config/genesis.json")));
+        // the snapshots survive the reset
+        restore_snapshot(&dirs, "height-10").unwrap();
+        assert_eq!("10", read(dirs.abci_dir.join("abci.height")));
+
+        reset(&dirs, false).unwrap();
+        assert!(!tendermint.exists());
+    }
+}
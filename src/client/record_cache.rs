@@ -0,0 +1,124 @@
+use crate::exit_code::{bad_arguments, not_found};
+use anyhow::Result;
+use lib::vm;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Prefix of the inputs that reference a cached record, by alias or by a prefix of its commitment, e.g.
+/// `record:fees` or `record:3141`.
+pub const REFERENCE_PREFIX: &str = "record:";
+
+/// The unspent records of the account as last fetched from the node, kept in $ALEO_HOME/records.json so the
+/// execution inputs can reference them by commitment instead of passing their ciphertexts around. It's refreshed every
+/// time the client fetches the account records, so it may still have records spent since then.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct RecordCache {
+    /// Record ciphertexts by commitment.
+    pub records: BTreeMap<String, String>,
+    /// Commitments of the records by the aliases set with `records alias`.
+    #[serde(default)]
+    pub aliases: BTreeMap<String, String>,
+}
+
+impl RecordCache {
+    /// Load the cached records, or an empty cache if the records weren't fetched yet.
+    pub fn load() -> Result<Self> {
+        match fs::read_to_string(Self::path()) {
+            Ok(json) => Ok(serde_json::from_str(&json)?),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    pub fn save(&self) -> Result<PathBuf> {
+        let file = Self::path();
+        fs::create_dir_all(lib::aleo_home())?;
+        debug!("Saving record cache to {}", file.to_string_lossy());
+        fs::write(&file, serde_json::to_string_pretty(&self)?)?;
+        Ok(file)
+    }
+
+    /// Replace the cached records with the given unspent ones, dropping the aliases of the records spent since the
+    /// last update.
+    pub fn update(&mut self, records: &[(vm::Field, vm::EncryptedRecord, vm::Record)]) {
+        self.records = records
+            .iter()
+            .map(|(commitment, ciphertext, _)| (commitment.to_string(), ciphertext.to_string()))
+            .collect();
+        let records = &self.records;
+        self.aliases
+            .retain(|_, commitment| records.contains_key(commitment));
+    }
+
+    /// Return the ciphertext of the cached record referenced by an alias or by a unique prefix of its commitment.
+    pub fn resolve(&self, reference: &str) -> Result<vm::EncryptedRecord> {
+        let commitment = self.commitment(reference)?;
+        Ok(vm::EncryptedRecord::from_str(&self.records[commitment])?)
+    }
+
+    /// Set the alias of the cached record referenced by the given commitment prefix, returning its full commitment.
+    /// Aliases have to start with a letter, so they can't be mistaken for commitment prefixes.
+    pub fn set_alias(&mut self, alias: &str, reference: &str) -> Result<String> {
+        if !alias.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            return Err(bad_arguments(format!(
+                "the alias {alias} should start with a letter"
+            )));
+        }
+        let commitment = self.commitment(reference)?.to_string();
+        self.aliases.insert(alias.to_string(), commitment.clone());
+        Ok(commitment)
+    }
+
+    /// The commitment of the cached record referenced by an alias or by a unique prefix of its commitment.
+    fn commitment(&self, reference: &str) -> Result<&str> {
+        if let Some(commitment) = self.aliases.get(reference) {
+            return Ok(commitment);
+        }
+        let mut matches = self
+            .records
+            .keys()
+            .filter(|commitment| commitment.starts_with(reference));
+        match (matches.next(), matches.next()) {
+            (Some(commitment), None) => Ok(commitment),
+            (Some(_), Some(_)) => Err(bad_arguments(format!(
+                "more than one record commitment starts with {reference}, use a longer prefix"
+            ))),
+            (None, _) => Err(not_found(format!(
+                "there's no cached record for {reference}, run `account records` to refresh the cache"
+            ))),
+        }
+    }
+
+    fn path() -> PathBuf {
+        lib::aleo_home().join("records.json")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_are_referenced_by_alias_or_prefix() {
+        let mut cache = RecordCache::default();
+        for commitment in ["3141field", "3145field", "2718field"] {
+            cache
+                .records
+                .insert(commitment.to_string(), "record1".to_string());
+        }
+
+        assert_eq!("2718field", cache.commitment("27").unwrap());
+        assert_eq!("3141field", cache.commitment("3141").unwrap());
+        assert_eq!("3141field", cache.commitment("3141field").unwrap());
+        assert!(cache.commitment("314").is_err());
+        assert!(cache.commitment("1").is_err());
+
+        assert_eq!("3145field", cache.set_alias("fees", "3145").unwrap());
+        assert_eq!("3145field", cache.commitment("fees").unwrap());
+        assert!(cache.set_alias("1fees", "3145").is_err());
+        assert!(cache.set_alias("other", "314").is_err());
+    }
+}
@@ -0,0 +1,55 @@
+use crate::commands::Command;
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use serde_json::json;
+use std::io::{self, BufRead, Write};
+
+/// Wrapper to parse the commands typed in the session, which don't include the client binary name.
+#[derive(Debug, Parser)]
+#[clap(name = "", no_binary_name = true)]
+struct ReplLine {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+/// Read commands from stdin and run them against the given node until the input ends or `exit` is typed.
+/// Each command prints its output like it would when run from the shell, and errors don't end the session.
+pub async fn run(url: &str) -> Result<()> {
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("aleo> ");
+        io::stdout().flush()?;
+
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => return Ok(()),
+        };
+        let line = line.trim();
+        match line {
+            "" => continue,
+            "exit" | "quit" => return Ok(()),
+            _ => {}
+        }
+
+        let output = match parse(line) {
+            Ok(command) => command
+                .run(url.to_string())
+                .await
+                .unwrap_or_else(|err| json!({"error": err.to_string()})),
+            Err(err) => json!({"error": err.to_string()}),
+        };
+        println!("{output:#}");
+    }
+}
+
+fn parse(line: &str) -> Result<Command> {
+    let words = shlex::split(line).ok_or_else(|| anyhow!("unbalanced quotes in command"))?;
+    let command = ReplLine::try_parse_from(words)?.command;
+    match command {
+        Command::Repl | Command::Serve { .. } | Command::Completions { .. } => {
+            Err(anyhow!("this command is not available in the repl"))
+        }
+        command => Ok(command),
+    }
+}
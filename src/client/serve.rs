@@ -0,0 +1,287 @@
+use crate::account;
+use crate::commands::{self, Account, Command};
+use anyhow::{anyhow, ensure, Result};
+use lib::client::Client;
+use lib::transaction::{Transaction, MAX_TRANSACTION_BYTES};
+use lib::vm;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::str::FromStr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Maximum size of the request line and headers.
+const MAX_HEADER_BYTES: usize = 8192;
+
+/// Maximum size of a request body. Transactions are bigger encoded as JSON than they are when broadcast,
+/// so this leaves room for the largest one the node accepts.
+const MAX_BODY_BYTES: usize = 2 * MAX_TRANSACTION_BYTES as usize;
+
+// JSON-RPC 2.0 error codes, see https://www.jsonrpc.org/specification#error_object
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// Used when the method fails, e.g. because the node rejected the transaction. The message has the details.
+const SERVER_ERROR: i64 = -32000;
+
+#[derive(Debug, Deserialize)]
+struct Request {
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+/// Params of `buildTransfer`.
+#[derive(Debug, Deserialize)]
+struct TransferParams {
+    recipient: String,
+    amount: u64,
+    /// Ciphertext of the record to spend. If omitted, the record of the account with most gates is used.
+    #[serde(default)]
+    record: Option<String>,
+    /// Amount of gates to pay as fee. If omitted, the fee set with `config set-fee` is paid, if any.
+    #[serde(default)]
+    fee: Option<u64>,
+    #[serde(default)]
+    memo: Option<String>,
+}
+
+/// Params of `broadcast`.
+#[derive(Debug, Deserialize)]
+struct BroadcastParams {
+    transaction: Transaction,
+}
+
+#[derive(Debug)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl ToString) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl From<anyhow::Error> for RpcError {
+    fn from(error: anyhow::Error) -> Self {
+        Self::new(SERVER_ERROR, error)
+    }
+}
+
+/// Serve the JSON-RPC API of the local account at the given port of localhost, until the process is stopped.
+/// The methods are:
+/// * `getBalance`: returns the credits balance of the account, like `account balance`.
+/// * `getRecords`: returns the unspent records of the account, like `account records`.
+/// * `buildTransfer`: builds and signs a credits transfer, see `TransferParams`, returning the transaction without
+///   sending it.
+/// * `broadcast`: sends a transaction, e.g. the one returned by `buildTransfer`, returning its id.
+///
+/// Requests are answered one at a time, so concurrent transfers don't pick the same records to spend.
+pub async fn run(url: &str, port: u16) -> Result<()> {
+    // fail on start rather than on each request if there's no account
+    account::Credentials::load().map_err(|_| anyhow!("credentials not found"))?;
+
+    let listener = TcpListener::bind(("127.0.0.1", port)).await?;
+    eprintln!(
+        "Serving the JSON-RPC API at http://{}",
+        listener.local_addr()?
+    );
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        if let Err(e) = answer(stream, url).await {
+            eprintln!("Failed to answer the request of {peer}: {e}");
+        }
+    }
+}
+
+/// Read an HTTP request from the stream and write the response. Only POST requests with a JSON-RPC body are handled.
+async fn answer(mut stream: TcpStream, url: &str) -> Result<()> {
+    let mut request = Vec::new();
+    let mut buffer = [0; 8192];
+    let header_end = loop {
+        if let Some(position) = request.windows(4).position(|window| window == b"\r\n\r\n") {
+            break position + 4;
+        }
+        ensure!(
+            request.len() < MAX_HEADER_BYTES,
+            "the request headers are too long"
+        );
+        let read = stream.read(&mut buffer).await?;
+        ensure!(read > 0, "the connection was closed mid request");
+        request.extend_from_slice(&buffer[..read]);
+    };
+
+    let head = String::from_utf8_lossy(&request[..header_end]).to_string();
+    let content_length = head
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .map(|(_, value)| value.trim().parse::<usize>())
+        .transpose()?
+        .unwrap_or(0);
+    ensure!(
+        content_length <= MAX_BODY_BYTES,
+        "the request body is too long"
+    );
+
+    let mut body = request.split_off(header_end);
+    while body.len() < content_length {
+        let read = stream.read(&mut buffer).await?;
+        ensure!(read > 0, "the connection was closed mid request");
+        body.extend_from_slice(&buffer[..read]);
+    }
+    body.truncate(content_length);
+
+    let (status, body) = if head.starts_with("POST ") {
+        ("200 OK", handle(&body, url).await.to_string())
+    } else {
+        ("405 Method Not Allowed", String::new())
+    };
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// Answer a JSON-RPC request, returning the response object. Batches aren't supported, and notifications,
+/// i.e. requests without id, are answered like the rest.
+async fn handle(body: &[u8], url: &str) -> Value {
+    let request: Value = match serde_json::from_slice(body) {
+        Ok(request) => request,
+        Err(e) => return error_response(Value::Null, RpcError::new(PARSE_ERROR, e)),
+    };
+    let request: Request = match serde_json::from_value(request) {
+        Ok(request) => request,
+        Err(e) => return error_response(Value::Null, RpcError::new(INVALID_REQUEST, e)),
+    };
+
+    match call(&request.method, request.params, url).await {
+        Ok(result) => json!({"jsonrpc": "2.0", "id": request.id, "result": result}),
+        Err(error) => error_response(request.id, error),
+    }
+}
+
+async fn call(method: &str, params: Value, url: &str) -> Result<Value, RpcError> {
+    match method {
+        "getBalance" => Ok(Command::Account(Account::Balance)
+            .run(url.to_string())
+            .await?),
+        "getRecords" => Ok(Command::Account(Account::Records)
+            .run(url.to_string())
+            .await?),
+        "getChainStatus" => Ok(json!(Client::new(url)?.get_chain_status().await?)),
+        "getStoreStats" => Ok(json!(Client::new(url)?.get_store_stats().await?)),
+        "buildTransfer" => {
+            let params: TransferParams = parse_params(params)?;
+            let recipient = vm::Address::from_str(&params.recipient)
+                .map_err(|e| RpcError::new(INVALID_PARAMS, e))?;
+            Ok(json!(build_transfer(params, recipient, url).await?))
+        }
+        "broadcast" => {
+            let BroadcastParams { transaction } = parse_params(params)?;
+            let outcome = Client::new(url)?.broadcast(&transaction).await?;
+            Ok(json!({"id": transaction.id(), "outcome": outcome}))
+        }
+        _ => Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("unknown method {method}"),
+        )),
+    }
+}
+
+fn parse_params<T: serde::de::DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|e| RpcError::new(INVALID_PARAMS, e))
+}
+
+/// Build a credits transfer signed by the account that owns the spent record, like `credits transfer` does.
+async fn build_transfer(
+    params: TransferParams,
+    recipient: vm::Address,
+    url: &str,
+) -> Result<Transaction> {
+    let credentials = account::Credentials::load().map_err(|_| anyhow!("credentials not found"))?;
+    let record = match &params.record {
+        Some(ciphertext) => match commands::parse_input_record(ciphertext)? {
+            vm::UserInputValueType::Record(record) => record,
+            _ => return Err(anyhow!("{ciphertext} is not a record")),
+        },
+        None => commands::get_records(&credentials, url)
+            .await?
+            .into_iter()
+            .map(|(_, _, record)| record)
+            .max_by_key(vm::gates)
+            .filter(|record| vm::gates(record) >= params.amount)
+            .ok_or_else(|| anyhow!("the account has no record with {} gates", params.amount))?,
+    };
+    let credentials = credentials.owner_of(std::slice::from_ref(&record))?;
+
+    let inputs = vec![
+        vm::UserInputValueType::Record(record),
+        vm::UserInputValueType::from_str(&recipient.to_string())?,
+        vm::u64_to_value(params.amount),
+    ];
+    let transaction =
+        commands::credits_transaction(&credentials, url, "transfer", &inputs, &params.fee, &None)
+            .await?;
+
+    let transaction = match &params.memo {
+        Some(memo) => {
+            // the first output of the transfer is the record of the recipient
+            let (commitment, _) = transaction
+                .output_records()
+                .into_iter()
+                .next()
+                .ok_or_else(|| anyhow!("transfer has no output records"))?;
+            transaction.with_memo(commitment, &recipient, memo)?
+        }
+        None => transaction,
+    };
+    Transaction::signed(transaction, credentials.private_key()?, None)
+}
+
+fn error_response(id: Value, error: RpcError) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": {"code": error.code, "message": error.message},
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn invalid_requests_are_answered_with_errors() {
+        // none of these get to the node
+        let url = "http://127.0.0.1:1";
+        let error_code = |response: Value| response["error"]["code"].clone();
+
+        assert_eq!(PARSE_ERROR, error_code(handle(b"{", url).await));
+        assert_eq!(
+            INVALID_REQUEST,
+            error_code(handle(br#"{"jsonrpc": "2.0", "id": 1}"#, url).await)
+        );
+
+        let response = handle(br#"{"jsonrpc": "2.0", "id": 7, "method": "mine"}"#, url).await;
+        assert_eq!(7, response["id"]);
+        assert_eq!(METHOD_NOT_FOUND, error_code(response));
+
+        let request =
+            br#"{"jsonrpc": "2.0", "id": 1, "method": "buildTransfer", "params": {"amount": 10}}"#;
+        assert_eq!(INVALID_PARAMS, error_code(handle(request, url).await));
+        let request = br#"{"jsonrpc": "2.0", "id": 1, "method": "buildTransfer", "params": {"recipient": "bob", "amount": 10}}"#;
+        assert_eq!(INVALID_PARAMS, error_code(handle(request, url).await));
+    }
+}
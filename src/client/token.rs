@@ -0,0 +1,101 @@
+use anyhow::{ensure, Result};
+use lib::vm;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Source of the programs deployed by `token create`, with `{{program_id}}` and `{{creator}}` placeholders.
+/// Its `mint` function can only create records owned by the creator, so the supply is minted to it.
+const TOKEN_TEMPLATE: &str = include_str!("../../aleo/templates/token.aleo");
+
+/// Details of a token created with `token create`, kept in $ALEO_HOME/tokens.json.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TokenMetadata {
+    pub program_id: String,
+    pub name: String,
+    pub symbol: String,
+    /// Amount minted to the creator along with the deployment.
+    pub supply: u64,
+    pub creator: vm::Address,
+    /// Id of the deployment transaction of the token program.
+    pub deployment: String,
+    /// Id of the execution that minted the supply.
+    pub mint: String,
+}
+
+/// The tokens created from this client, in the order they were created.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct TokenRegistry {
+    pub tokens: Vec<TokenMetadata>,
+}
+
+impl TokenRegistry {
+    /// Load the registered tokens, or an empty registry if none was created yet.
+    pub fn load() -> Result<Self> {
+        match fs::read_to_string(Self::path()) {
+            Ok(json) => Ok(serde_json::from_str(&json)?),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    /// Register a new token, replacing any previous one with the same program id.
+    pub fn add(&mut self, token: TokenMetadata) {
+        self.tokens
+            .retain(|existing| existing.program_id != token.program_id);
+        self.tokens.push(token);
+    }
+
+    pub fn save(&self) -> Result<PathBuf> {
+        let file = Self::path();
+        fs::create_dir_all(lib::aleo_home())?;
+        debug!("Saving token registry to {}", file.to_string_lossy());
+        fs::write(&file, serde_json::to_string_pretty(&self)?)?;
+        Ok(file)
+    }
+
+    fn path() -> PathBuf {
+        lib::aleo_home().join("tokens.json")
+    }
+}
+
+/// Return the program id of a token with the given symbol, e.g. `lmb.aleo` for `LMB`.
+pub fn program_id(symbol: &str) -> Result<vm::ProgramID> {
+    ensure!(
+        !symbol.is_empty()
+            && symbol
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_'),
+        "invalid token symbol {symbol}, only letters, digits and underscores are allowed"
+    );
+    vm::ProgramID::from_str(&format!("{}.aleo", symbol.to_lowercase()))
+}
+
+/// Return the source of the token program with the given id, whose records can only be minted to the creator.
+pub fn program_source(program_id: &vm::ProgramID, creator: &vm::Address) -> String {
+    TOKEN_TEMPLATE
+        .replace("{{program_id}}", &program_id.to_string())
+        .replace("{{creator}}", &creator.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_programs_are_generated_from_the_template() {
+        let private_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let creator = vm::Address::try_from(&vm::ViewKey::try_from(&private_key).unwrap()).unwrap();
+
+        let id = program_id("LMB").unwrap();
+        assert_eq!("lmb.aleo", id.to_string());
+        assert!(program_id("").is_err());
+        assert!(program_id("L-MB").is_err());
+
+        let source = program_source(&id, &creator);
+        assert!(source.contains(&creator.to_string()));
+        let program = vm::generate_program(&source).unwrap();
+        assert_eq!(id.to_string(), program.id().to_string());
+    }
+}
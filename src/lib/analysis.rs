@@ -0,0 +1,237 @@
+//! Static checks run on the source of a program before it's deployed, to flag common mistakes that would
+//! otherwise only be noticed after paying the deployment fee. They work on the program text, so they're the
+//! same for every VM backend, and they're heuristics: a flagged program may be fine as it is.
+
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fmt;
+
+/// Functions with more instructions than this are flagged as likely to have a huge circuit.
+pub const MAX_FUNCTION_INSTRUCTIONS: usize = 500;
+
+/// Functions with more hash or commitment instructions than this are flagged as likely to have a huge circuit,
+/// since each of them takes far more constraints than arithmetic instructions.
+pub const MAX_FUNCTION_HASHES: usize = 50;
+
+/// A potential problem found in a program, along with the function, closure or record it was found in.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Warning {
+    pub item: String,
+    pub message: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.item, self.message)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum BlockKind {
+    Function,
+    Closure,
+    Record,
+    /// Other blocks, e.g. interfaces, mappings and finalize blocks, which aren't checked.
+    Other,
+}
+
+/// A top level block of the program, e.g. `function transfer:` and the lines following it.
+#[derive(Debug)]
+struct Block {
+    kind: BlockKind,
+    name: String,
+    /// Types of the inputs, e.g. `u64.private` or `token.record`.
+    inputs: Vec<String>,
+    outputs: usize,
+    /// Lines that aren't inputs or outputs, without the trailing semicolon.
+    instructions: Vec<String>,
+}
+
+/// Check the program source for:
+/// * functions with no outputs;
+/// * closures that are never called, so they can't be executed;
+/// * functions that create records without spending any record or asserting anything, which any account can call
+///   to mint records, e.g. because an owner check was forgotten;
+/// * functions with so many instructions, or hashes and commitments, that they likely have a huge circuit,
+///   making their keys large to deploy and their proofs slow to generate.
+pub fn analyze(source: &str) -> Vec<Warning> {
+    let blocks = parse_blocks(source);
+    let records: HashSet<&str> = blocks
+        .iter()
+        .filter(|block| block.kind == BlockKind::Record)
+        .map(|block| block.name.as_str())
+        .collect();
+    let called: HashSet<&str> = blocks
+        .iter()
+        .flat_map(|block| &block.instructions)
+        .filter_map(|instruction| instruction.strip_prefix("call "))
+        .filter_map(|call| call.split_whitespace().next())
+        .collect();
+
+    let mut warnings = Vec::new();
+    let mut warn = |item: String, message: String| warnings.push(Warning { item, message });
+    for block in &blocks {
+        match block.kind {
+            BlockKind::Function => {
+                let item = format!("function {}", block.name);
+                if block.outputs == 0 {
+                    warn(
+                        item.clone(),
+                        "has no outputs, so its executions don't return anything to the caller"
+                            .to_string(),
+                    );
+                }
+
+                let spends_records = block.inputs.iter().any(|input| input.ends_with(".record"));
+                let asserts = block
+                    .instructions
+                    .iter()
+                    .any(|instruction| instruction.starts_with("assert."));
+                if !spends_records && !asserts {
+                    if let Some(record) = created_records(block)
+                        .into_iter()
+                        .find(|record| records.contains(record.as_str()))
+                    {
+                        warn(item.clone(), format!("creates {record} records without spending any record or asserting anything about the caller, so any account can call it to mint them"));
+                    }
+                }
+
+                let hashes = block
+                    .instructions
+                    .iter()
+                    .filter(|instruction| {
+                        instruction.starts_with("hash.") || instruction.starts_with("commit.")
+                    })
+                    .count();
+                if block.instructions.len() > MAX_FUNCTION_INSTRUCTIONS
+                    || hashes > MAX_FUNCTION_HASHES
+                {
+                    warn(item, format!("has {} instructions, {hashes} of them hashes or commitments, which likely makes for a huge circuit: its keys will be large to deploy and its proofs slow to generate", block.instructions.len()));
+                }
+            }
+            BlockKind::Closure if !called.contains(block.name.as_str()) => warn(
+                format!("closure {}", block.name),
+                "is never called by the program, so it can't be executed".to_string(),
+            ),
+            _ => {}
+        }
+    }
+    warnings
+}
+
+/// Split the program in its top level blocks, ignoring comments and the program and import declarations.
+fn parse_blocks(source: &str) -> Vec<Block> {
+    let mut blocks: Vec<Block> = Vec::new();
+    for line in source.lines() {
+        let line = line.split("//").next().unwrap_or_default().trim();
+        if line.is_empty() || line.starts_with("program ") || line.starts_with("import ") {
+            continue;
+        }
+
+        if let Some(header) = line.strip_suffix(':') {
+            let (keyword, name) = header.split_once(' ').unwrap_or((header, ""));
+            let kind = match keyword {
+                "function" => BlockKind::Function,
+                "closure" => BlockKind::Closure,
+                "record" => BlockKind::Record,
+                _ => BlockKind::Other,
+            };
+            blocks.push(Block {
+                kind,
+                name: name.trim().to_string(),
+                inputs: Vec::new(),
+                outputs: 0,
+                instructions: Vec::new(),
+            });
+            continue;
+        }
+
+        let block = match blocks.last_mut() {
+            Some(block) => block,
+            None => continue,
+        };
+        let line = line.trim_end_matches(';').trim();
+        if let Some(input) = line.strip_prefix("input ") {
+            let input_type = input.rsplit(" as ").next().unwrap_or_default();
+            block.inputs.push(input_type.trim().to_string());
+        } else if line.starts_with("output ") {
+            block.outputs += 1;
+        } else {
+            block.instructions.push(line.to_string());
+        }
+    }
+    blocks
+}
+
+/// Names of the record types created by the cast instructions of the block.
+fn created_records(block: &Block) -> Vec<String> {
+    block
+        .instructions
+        .iter()
+        .filter(|instruction| instruction.starts_with("cast "))
+        .filter_map(|instruction| instruction.rsplit(" as ").next())
+        .filter_map(|cast_type| cast_type.trim().strip_suffix(".record"))
+        .map(|record| record.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundled_programs() {
+        assert!(analyze(include_str!("../../aleo/hello.aleo")).is_empty());
+
+        let warnings = analyze(include_str!("../../aleo/token.aleo"));
+        assert_eq!(1, warnings.len());
+        assert_eq!("function mint", warnings[0].item);
+        assert!(warnings[0].message.contains("token records"));
+    }
+
+    #[test]
+    fn problems_are_flagged() {
+        let source = r"
+program checks.aleo;
+
+record ticket:
+    owner as address.private;
+    gates as u64.private;
+
+closure double:
+    input r0 as u64;
+    add r0 r0 into r1;
+    output r1 as u64;
+
+closure unused:
+    input r0 as u64;
+    output r0 as u64;
+
+function noop:
+    input r0 as u64.private;
+    call double r0 into r1;
+
+function issue:
+    input r0 as address.private;
+    // only the issuer can create tickets
+    assert.eq self.caller aleo1qqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqq;
+    cast r0 0u64 into r1 as ticket.record;
+    output r1 as ticket.record;
+";
+        let warnings: Vec<String> = analyze(source)
+            .iter()
+            .map(|warning| warning.to_string())
+            .collect();
+        assert_eq!(2, warnings.len());
+        assert!(warnings[0].starts_with("closure unused: is never called"));
+        assert!(warnings[1].starts_with("function noop: has no outputs"));
+
+        let hashes = "    hash.psd2 r0 into r1;\n".repeat(MAX_FUNCTION_HASHES + 1);
+        let source = format!(
+            "program hashes.aleo;\n\nfunction hash:\n    input r0 as u64.public;\n{hashes}    output r1 as field.public;\n"
+        );
+        let warnings = analyze(&source);
+        assert_eq!(1, warnings.len());
+        assert!(warnings[0].message.contains("huge circuit"));
+    }
+}
@@ -0,0 +1,272 @@
+//! Scaffolding to move credits between chains running this application, in the style of IBC.
+//!
+//! A `BridgeTransfer` transaction burns credits on the source chain with a credits fee transition and commits a
+//! `Packet` for the destination chain in the packets component of its app hash. A relayer then submits to the
+//! destination chain a `BridgeReceive` transaction with a header of the source chain, the validator set that signed
+//! it and the packets along with their state proofs. The destination acts as a light client of the source: it checks
+//! the header is signed by the validator set it trusts for it, the packets are committed in its app hash, and mints
+//! the credits to the receivers, accepting the packets of each source in order.
+//!
+//! Counterparty chains are configured at genesis with the hash of the validator set trusted to sign their headers.
+//! There's no trusting period nor misbehavior handling, and headers can only be verified with the trusted set, so
+//! relayers need to submit headers as the counterparty validator set changes.
+
+use crate::light;
+use crate::merkle::{self, StateProof};
+use crate::vm;
+use anyhow::{anyhow, ensure, Result};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use tendermint::block::signed_header::SignedHeader;
+use tendermint::block::Header;
+use tendermint::validator;
+use tendermint_proto::Protobuf;
+
+/// Maximum amount of packets a single `BridgeReceive` transaction can carry.
+pub const MAX_PACKETS_PER_RECEIVE: usize = 100;
+
+/// Credits sent from a source to a destination chain.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Packet {
+    pub source_chain: String,
+    pub destination_chain: String,
+    /// Position of the packet among the ones sent from the source to the destination, starting at 0.
+    /// The destination receives them in this order.
+    pub sequence: u64,
+    /// Account that gets the credits on the destination chain.
+    pub receiver: vm::Address,
+    /// Gates burnt on the source chain and minted on the destination.
+    pub amount: u64,
+}
+
+impl Packet {
+    /// Key of the packet in the packets component of the source chain.
+    pub fn key(&self) -> Vec<u8> {
+        packet_key(&self.destination_chain, self.sequence)
+    }
+
+    /// Value committed for the packet in the packets component of the source chain.
+    pub fn commitment(&self) -> Result<Vec<u8>> {
+        Ok(bincode::serialize(self)?)
+    }
+}
+
+/// A counterparty chain as configured at genesis.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CounterpartyChain {
+    pub chain_id: String,
+    /// Hash of the counterparty validator set trusted to sign its headers, as the hex `validators_hash` of its headers.
+    pub validators_hash: String,
+}
+
+impl FromStr for CounterpartyChain {
+    type Err = anyhow::Error;
+
+    /// Parse a `{chain_id}={validators_hash}` pair.
+    fn from_str(s: &str) -> Result<Self> {
+        let (chain_id, validators_hash) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("expected {{chain_id}}={{validators_hash}}, got {s}"))?;
+        ensure!(
+            !chain_id.is_empty() && !chain_id.contains('/'),
+            "invalid counterparty chain id {chain_id}"
+        );
+        let validators_hash = tendermint::Hash::from_str(validators_hash)
+            .map_err(|e| anyhow!("invalid validators hash {validators_hash}: {e}"))?;
+        Ok(Self {
+            chain_id: chain_id.to_string(),
+            validators_hash: validators_hash.to_string(),
+        })
+    }
+}
+
+/// State of the bridge with a counterparty chain, as returned by the `GetBridgeStatus` query.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Counterparty {
+    pub chain_id: String,
+    /// Hash of the counterparty validator set trusted to sign its next headers.
+    pub validators_hash: String,
+    /// Height of the last counterparty header verified, 0 if none was yet.
+    pub latest_height: u64,
+    /// Sequence of the next packet sent to the counterparty.
+    pub next_send_sequence: u64,
+    /// Sequence of the next packet expected from the counterparty.
+    pub next_receive_sequence: u64,
+    /// Gates burnt on this chain by the packets sent to the counterparty.
+    pub sent: u64,
+    /// Gates minted on this chain by the packets received from the counterparty.
+    pub received: u64,
+}
+
+impl Counterparty {
+    pub fn new(chain: &CounterpartyChain) -> Self {
+        Self {
+            chain_id: chain.chain_id.clone(),
+            validators_hash: chain.validators_hash.clone(),
+            latest_height: 0,
+            next_send_sequence: 0,
+            next_receive_sequence: 0,
+            sent: 0,
+            received: 0,
+        }
+    }
+}
+
+/// Result of the `GetBridgeStatus` query.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct BridgeStatus {
+    pub counterparties: Vec<Counterparty>,
+}
+
+/// Result of the `GetPackets` query. The proofs are against the app hash of the block at `height`, which is
+/// included in the header at the next height.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PacketsResponse {
+    pub height: u64,
+    pub packets: Vec<(Packet, StateProof)>,
+}
+
+/// Key of a packet in the packets component: the destination chain followed by the zero padded sequence,
+/// so the packets sent to each chain are sorted by sequence.
+pub fn packet_key(destination_chain: &str, sequence: u64) -> Vec<u8> {
+    [
+        packets_prefix(destination_chain),
+        format!("{sequence:020}").into_bytes(),
+    ]
+    .concat()
+}
+
+/// Prefix of the keys of the packets sent to a chain. Counterparty chain ids with a slash are rejected,
+/// so the prefix of a chain never matches the keys of another one.
+pub fn packets_prefix(destination_chain: &str) -> Vec<u8> {
+    format!("{destination_chain}/").into_bytes()
+}
+
+/// Verify that the packet is committed by its source chain, given the (verified) source header at the height
+/// following the one of the proof.
+pub fn verify_packet(packet: &Packet, proof: &StateProof, header: &Header) -> Result<()> {
+    ensure!(
+        header.chain_id.as_str() == packet.source_chain,
+        "packet {} was sent by {}, not by {}",
+        packet.sequence,
+        packet.source_chain,
+        header.chain_id
+    );
+    let value = light::verify_state(proof, merkle::PACKETS_COMPONENT, &packet.key(), header)?
+        .ok_or_else(|| {
+            anyhow!(
+                "packet {} to {} is not committed by {}",
+                packet.sequence,
+                packet.destination_chain,
+                packet.source_chain
+            )
+        })?;
+    ensure!(
+        value == packet.commitment()?,
+        "packet {} doesn't match the one committed by {}",
+        packet.sequence,
+        packet.source_chain
+    );
+    Ok(())
+}
+
+/// Encode a signed header to be included in a transaction, with its protobuf encoding.
+pub fn encode_header(header: &SignedHeader) -> Result<Vec<u8>> {
+    header
+        .encode_vec()
+        .map_err(|e| anyhow!("couldn't encode header: {e}"))
+}
+
+pub fn decode_header(bytes: &[u8]) -> Result<SignedHeader> {
+    SignedHeader::decode_vec(bytes).map_err(|e| anyhow!("invalid header: {e}"))
+}
+
+/// Encode a validator set to be included in a transaction, with its protobuf encoding.
+pub fn encode_validators(validators: &validator::Set) -> Result<Vec<u8>> {
+    validators
+        .encode_vec()
+        .map_err(|e| anyhow!("couldn't encode validator set: {e}"))
+}
+
+pub fn decode_validators(bytes: &[u8]) -> Result<validator::Set> {
+    validator::Set::decode_vec(bytes).map_err(|e| anyhow!("invalid validator set: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packets_are_sorted_by_destination_and_sequence() {
+        assert!(packet_key("chain-b", 2) < packet_key("chain-b", 10));
+        assert!(packet_key("chain-b", 999) < packet_key("chain-c", 0));
+
+        let counterparty: CounterpartyChain =
+            "chain-b=8F9C0E5C4D9A1B2C3D4E5F60718293A4B5C6D7E8F90A1B2C3D4E5F60718293A4"
+                .parse()
+                .unwrap();
+        assert_eq!("chain-b", counterparty.chain_id);
+        assert!("chain-b".parse::<CounterpartyChain>().is_err());
+        assert!("chain-b=not-a-hash".parse::<CounterpartyChain>().is_err());
+    }
+
+    #[test]
+    #[allow(clippy::clone_on_copy)]
+    fn packet_commitments_are_proven() {
+        let private_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let receiver =
+            vm::Address::try_from(&vm::ViewKey::try_from(&private_key).unwrap()).unwrap();
+        let packets: Vec<Packet> = (0..3)
+            .map(|sequence| Packet {
+                source_chain: "chain-a".to_string(),
+                destination_chain: "chain-b".to_string(),
+                sequence,
+                receiver: receiver.clone(),
+                amount: 10 + sequence,
+            })
+            .collect();
+        let entries: Vec<(Vec<u8>, Vec<u8>)> = packets
+            .iter()
+            .map(|packet| (packet.key(), packet.commitment().unwrap()))
+            .collect();
+        let leaves: Vec<merkle::Hash> = entries
+            .iter()
+            .map(|(key, value)| merkle::leaf_hash(key, value))
+            .collect();
+        let components = [
+            (merkle::SPENT_COMPONENT, [0; 32]),
+            (merkle::PACKETS_COMPONENT, merkle::root(&leaves)),
+        ];
+        let app_hash = merkle::app_hash(&components);
+
+        let proof = StateProof::new(
+            5,
+            &components,
+            merkle::PACKETS_COMPONENT,
+            merkle::KeyProof::new(&entries, &packets[1].key()),
+        )
+        .unwrap();
+        let value = proof
+            .verify(merkle::PACKETS_COMPONENT, &packets[1].key(), &app_hash)
+            .unwrap();
+        assert_eq!(Some(packets[1].commitment().unwrap()), value);
+
+        let missing = Packet {
+            sequence: 3,
+            ..packets[2].clone()
+        };
+        let proof = StateProof::new(
+            5,
+            &components,
+            merkle::PACKETS_COMPONENT,
+            merkle::KeyProof::new(&entries, &missing.key()),
+        )
+        .unwrap();
+        assert_eq!(
+            None,
+            proof
+                .verify(merkle::PACKETS_COMPONENT, &missing.key(), &app_hash)
+                .unwrap()
+        );
+    }
+}
@@ -0,0 +1,160 @@
+use crate::diagnostic::Diagnostic;
+use crate::transaction::Transaction;
+use anyhow::{anyhow, bail, Result};
+use log::{debug, warn};
+use serde::Serialize;
+use std::time::Duration;
+use tendermint_rpc::{Client, HttpClient};
+
+/// Default amount of times a broadcast is attempted when the node is busy before giving up.
+const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+/// Default delay before the first retry, doubled on each subsequent attempt.
+const DEFAULT_INITIAL_DELAY: Duration = Duration::from_millis(500);
+
+/// Async client to submit transactions to a tendermint node, so they can be sent to the blockchain
+/// from Rust code without shelling out to the client binary.
+/// Broadcasts are retried with exponential backoff when the node mempool is full or the request
+/// times out, and are idempotent: sending a transaction that the node already has is not an error.
+#[derive(Debug, Clone)]
+pub struct BroadcastClient {
+    client: HttpClient,
+    max_attempts: u32,
+    initial_delay: Duration,
+}
+
+/// The result of a successful broadcast, serialized as e.g. `{"status": "accepted", "attempts": 1}`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum BroadcastOutcome {
+    /// The transaction passed the mempool validations (check_tx) and was accepted by the node.
+    Accepted { attempts: u32 },
+    /// The node already had the transaction in its cache, e.g. because it was sent before
+    /// or a previous attempt succeeded even though the response didn't get back to us.
+    AlreadyInCache { attempts: u32 },
+}
+
+/// How to handle an error returned by the node when broadcasting.
+#[derive(Debug, PartialEq, Eq)]
+enum BroadcastError {
+    /// The transaction is already known by the node, so the broadcast can be considered successful.
+    AlreadyInCache,
+    /// The node is busy or didn't respond in time, the broadcast can be retried later.
+    Retryable,
+    /// The transaction was rejected or there was some other unexpected error.
+    Fatal,
+}
+
+impl BroadcastClient {
+    /// Create a client for the tendermint node RPC at the given url, e.g. http://127.0.0.1:26657
+    pub fn new(url: &str) -> Result<Self> {
+        Ok(Self {
+            client: HttpClient::new(url)?,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            initial_delay: DEFAULT_INITIAL_DELAY,
+        })
+    }
+
+    /// Set how many times a broadcast is attempted, and how long to wait before the first retry.
+    pub fn with_retries(mut self, max_attempts: u32, initial_delay: Duration) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self.initial_delay = initial_delay;
+        self
+    }
+
+    /// Send the transaction to the node, failing if it's rejected by the mempool validations (check_tx).
+    pub async fn broadcast(&self, transaction: &Transaction) -> Result<BroadcastOutcome> {
+        self.broadcast_bytes(bincode::serialize(transaction)?).await
+    }
+
+    /// Send an already serialized transaction to the node.
+    pub async fn broadcast_bytes(&self, transaction: Vec<u8>) -> Result<BroadcastOutcome> {
+        let mut delay = self.initial_delay;
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let error = match self.try_broadcast(transaction.clone()).await {
+                Ok(()) => return Ok(BroadcastOutcome::Accepted { attempts }),
+                Err(error) => error,
+            };
+
+            match classify_error(&error.to_string()) {
+                BroadcastError::AlreadyInCache => {
+                    debug!("transaction already in the node cache");
+                    return Ok(BroadcastOutcome::AlreadyInCache { attempts });
+                }
+                BroadcastError::Retryable if attempts < self.max_attempts => {
+                    warn!("node is busy, retrying broadcast in {delay:?}: {error}");
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                }
+                BroadcastError::Retryable => {
+                    return Err(anyhow!(
+                        "gave up broadcasting after {attempts} attempts: {error}"
+                    ))
+                }
+                BroadcastError::Fatal => return Err(error),
+            }
+        }
+    }
+
+    async fn try_broadcast(&self, transaction: Vec<u8>) -> Result<()> {
+        let tx: tendermint::abci::Transaction = transaction.into();
+
+        let response = self.client.broadcast_tx_sync(tx).await?;
+
+        debug!("Response from CheckTx: {:?}", response);
+        match response.code {
+            tendermint::abci::Code::Ok => Ok(()),
+            tendermint::abci::Code::Err(code) => match Diagnostic::from_log(&response.log) {
+                // keep the diagnostic as the source of the error, so callers can downcast to it
+                Some(diagnostic) => {
+                    let message = format!(
+                        "Error executing transaction {code}: Could not verify transaction: {diagnostic}"
+                    );
+                    Err(anyhow::Error::new(diagnostic).context(message))
+                }
+                None => bail!("Error executing transaction {}: {}", code, response.log),
+            },
+        }
+    }
+}
+
+/// Tendermint doesn't return specific error codes for these conditions, so the message is inspected instead.
+fn classify_error(message: &str) -> BroadcastError {
+    let message = message.to_lowercase();
+    if message.contains("tx already exists in cache") {
+        BroadcastError::AlreadyInCache
+    } else if message.contains("mempool is full")
+        || message.contains("timed out")
+        || message.contains("timeout")
+    {
+        BroadcastError::Retryable
+    } else {
+        BroadcastError::Fatal
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classify_broadcast_errors() {
+        assert_eq!(
+            BroadcastError::AlreadyInCache,
+            classify_error("Internal error: tx already exists in cache")
+        );
+        assert_eq!(
+            BroadcastError::Retryable,
+            classify_error("Internal error: mempool is full: number of txs 5000 (max: 5000)")
+        );
+        assert_eq!(
+            BroadcastError::Retryable,
+            classify_error("operation timed out")
+        );
+        assert_eq!(
+            BroadcastError::Fatal,
+            classify_error("Error executing transaction 1: Could not verify transaction")
+        );
+    }
+}
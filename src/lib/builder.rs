@@ -0,0 +1,190 @@
+use crate::transaction::Transaction;
+use crate::vm;
+use anyhow::{anyhow, Result};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+/// Helper to construct execution transactions programmatically, e.g. from external Rust services
+/// that need to interact with the blockchain without going through the client binary.
+///
+/// ```ignore
+/// let transaction = TransactionBuilder::new()
+///     .program(program)
+///     .function(function)
+///     .inputs(&inputs)
+///     .fee_record(10, fee_record)
+///     .expires_at(1000)
+///     .execute_at(1200)
+///     .chain_id("testnet")
+///     .prove(&private_key)?;
+/// ```
+#[derive(Debug, Default)]
+pub struct TransactionBuilder {
+    program: Option<vm::Program>,
+    function: Option<vm::Identifier>,
+    inputs: Vec<vm::UserInputValueType>,
+    fee: Option<(u64, vm::Record)>,
+    expires_at: Option<u64>,
+    execute_at: Option<u64>,
+    chain_id: Option<String>,
+    seed: Option<u64>,
+}
+
+impl TransactionBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The program to execute. For credits executions, the cached credits program keys are used.
+    pub fn program(mut self, program: vm::Program) -> Self {
+        self.program = Some(program);
+        self
+    }
+
+    /// The name of the program function to execute.
+    pub fn function(mut self, function: vm::Identifier) -> Self {
+        self.function = Some(function);
+        self
+    }
+
+    /// The function inputs, in the order expected by the function.
+    pub fn inputs(mut self, inputs: &[vm::UserInputValueType]) -> Self {
+        self.inputs = inputs.to_vec();
+        self
+    }
+
+    /// Pay the given amount of gates as fee, subtracting it from the given record.
+    pub fn fee_record(mut self, amount: u64, record: vm::Record) -> Self {
+        self.fee = Some((amount, record));
+        self
+    }
+
+    /// Last block height at which the transaction can be included in the blockchain. With a chain id it's the one of
+    /// the signed envelope, otherwise it isn't bound to the proofs, see `Transaction::with_expiration`.
+    pub fn expires_at(mut self, height: u64) -> Self {
+        self.expires_at = Some(height);
+        self
+    }
+
+    /// Block height at which the output records of the execution are added to the ledger, instead of the block
+    /// that includes the transaction.
+    pub fn execute_at(mut self, height: u64) -> Self {
+        self.execute_at = Some(height);
+        self
+    }
+
+    /// The chain the transaction is meant for, so it's rejected by nodes of other chains. The transaction is wrapped
+    /// in an envelope signed by the proving account, which binds it to the chain, see `Transaction::for_chain`.
+    pub fn chain_id(mut self, chain_id: &str) -> Self {
+        self.chain_id = Some(chain_id.to_string());
+        self
+    }
+
+    /// Take the randomness of the execution from an rng seeded with the given value, so proving the same
+    /// execution again yields the same transaction, e.g. for test fixtures. Anyone who knows the seed can
+    /// recompute the randomizers of the output records, so it shouldn't be used to move real credits.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Run the execution with the given private key, generating the transaction proofs.
+    pub fn prove(self, private_key: &vm::PrivateKey) -> Result<Transaction> {
+        let program = self
+            .program
+            .ok_or_else(|| anyhow!("a program is required to build the transaction"))?;
+        let function = self
+            .function
+            .ok_or_else(|| anyhow!("a function is required to build the transaction"))?;
+
+        let mut rng = match self.seed {
+            Some(seed) => ChaCha8Rng::seed_from_u64(seed),
+            None => ChaCha8Rng::from_rng(rand::thread_rng())?,
+        };
+        let transaction = if program.id().to_string() == "credits.aleo" {
+            Transaction::credits_execution_with_rng(
+                function,
+                &self.inputs,
+                private_key,
+                self.fee,
+                &mut rng,
+            )?
+        } else {
+            Transaction::execution_with_rng(
+                program,
+                function,
+                &self.inputs,
+                private_key,
+                self.fee,
+                &mut rng,
+            )?
+        };
+
+        let transaction = match self.execute_at {
+            Some(height) => transaction.with_execution_height(height)?,
+            None => transaction,
+        };
+        match (self.chain_id, self.expires_at) {
+            (Some(chain_id), expires_at) => Transaction::signed(
+                transaction.with_chain_id(&chain_id)?,
+                private_key,
+                expires_at,
+            ),
+            (None, Some(height)) => transaction.with_expiration(height),
+            (None, None) => Ok(transaction),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TransactionBuilder;
+    use crate::vm;
+    use std::str::FromStr;
+
+    #[test]
+    fn missing_builder_fields() {
+        let private_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+
+        let error = TransactionBuilder::new().prove(&private_key).unwrap_err();
+        assert_eq!(
+            "a program is required to build the transaction",
+            error.to_string()
+        );
+
+        let program = vm::generate_program(include_str!("../../aleo/hello.aleo")).unwrap();
+        let error = TransactionBuilder::new()
+            .program(program)
+            .inputs(&[vm::UserInputValueType::from_str("1u32").unwrap()])
+            .prove(&private_key)
+            .unwrap_err();
+        assert_eq!(
+            "a function is required to build the transaction",
+            error.to_string()
+        );
+    }
+
+    #[cfg(feature = "snarkvm_backend")]
+    #[test]
+    fn seeded_executions_are_reproducible() {
+        let private_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let program = vm::generate_program(include_str!("../../aleo/hello.aleo")).unwrap();
+        let build = |seed| {
+            TransactionBuilder::new()
+                .program(program.clone())
+                .function(vm::Identifier::from_str("hello").unwrap())
+                .inputs(&[
+                    vm::UserInputValueType::from_str("1u32").unwrap(),
+                    vm::UserInputValueType::from_str("2u32").unwrap(),
+                ])
+                .seed(seed)
+                .prove(&private_key)
+                .unwrap()
+        };
+
+        let transaction = build(7);
+        assert_eq!(transaction.id(), build(7).id());
+        assert_ne!(transaction.id(), build(8).id());
+        transaction.verify().unwrap();
+    }
+}
@@ -0,0 +1,666 @@
+use crate::bridge::{BridgeStatus, PacketsResponse};
+use crate::broadcast::{BroadcastClient, BroadcastOutcome};
+use crate::decryptor::{RecordDecryptor, ViewKeyDecryptor};
+use crate::governance::{CommunityPool, GovernanceStatus};
+use crate::light;
+use crate::merkle::{self, StateProof};
+use crate::query::{
+    AbciQuery, BlockEvent, BlockEventsResponse, BlockRewards, BlockRewardsResponse, ChainStatus,
+    FeeEstimate, MemosResponse, NameResponse, NodeInfo, ProgramAvailability, ProgramListResponse,
+    ProgramResponse, ProgramStats, ProgramSummary, QueryResponse, Receipt, RecordEntry,
+    RecordResponse, RecordsResponse, SourceVerification, SpentEntry, SpentResponse, StoreStats,
+    TombstonesResponse, ValidatorPerformanceResponse, ValidatorsResponse, VerifyingKeysResponse,
+};
+use crate::transaction::Transaction;
+use crate::validator::Validator;
+use crate::vm;
+use anyhow::{anyhow, bail, Result};
+use log::debug;
+use serde::de::DeserializeOwned;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use tendermint::block::signed_header::SignedHeader;
+use tendermint::block::{Header, Height};
+use tendermint_rpc::query::Query;
+use tendermint_rpc::{Client as RpcClient, HttpClient, Order, Paging};
+
+/// Amount of records fetched per `GetRecords` query when scanning the whole ledger.
+const RECORDS_PAGE_SIZE: usize = 1000;
+
+/// Error of a request for something the node doesn't have, e.g. a transaction that wasn't committed.
+#[derive(Debug)]
+pub struct NotFound(pub String);
+
+impl fmt::Display for NotFound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for NotFound {}
+
+/// Typed client for the blockchain node, wrapping the tendermint RPC calls and ABCI queries
+/// so they return domain types instead of raw bytes. This allows other crates to interact with the
+/// blockchain without knowing the details of how the queries and transactions are encoded.
+#[derive(Debug, Clone)]
+pub struct Client {
+    url: String,
+    rpc: HttpClient,
+    broadcaster: BroadcastClient,
+}
+
+impl Client {
+    /// Create a client for the tendermint node RPC at the given url, e.g. http://127.0.0.1:26657
+    pub fn new(url: &str) -> Result<Self> {
+        Ok(Self {
+            url: url.to_string(),
+            rpc: HttpClient::new(url)?,
+            broadcaster: BroadcastClient::new(url)?,
+        })
+    }
+
+    /// Fetch a committed transaction by its id.
+    pub async fn get_transaction(&self, tx_id: &str) -> Result<Transaction> {
+        Ok(self.get_committed_transaction(tx_id).await?.0)
+    }
+
+    /// Fetch a committed transaction by its id, along with the height of the block that included it.
+    pub async fn get_committed_transaction(&self, tx_id: &str) -> Result<(Transaction, u64)> {
+        let query = Query::contains("app.tx_id", tx_id);
+
+        let response = self
+            .rpc
+            .tx_search(query, false, 1, 1, Order::Ascending)
+            .await?;
+
+        // early return with error if no transaction has been indexed for that tx id
+        if response.total_count == 0 {
+            return Err(NotFound(format!(
+                "Transaction ID {tx_id} is invalid or has not yet been committed to the blockchain"
+            ))
+            .into());
+        }
+
+        let result = response.txs.into_iter().next().unwrap();
+        let height = result.height.value();
+        let tx_bytes: Vec<u8> = result.tx.into();
+        Ok((bincode::deserialize(&tx_bytes)?, height))
+    }
+
+    /// Fetch a committed transaction by its id and summarize what it did: the height that included it, the gas and fee
+    /// it consumed, its public outputs and created records, and the events the application emitted for it.
+    pub async fn get_receipt(&self, tx_id: &str) -> Result<Receipt> {
+        let (transaction, height) = self.get_committed_transaction(tx_id).await?;
+        let tx_id_attribute = ("tx_id".to_string(), transaction.id().to_string());
+        let events = self
+            .get_block_events(height)
+            .await?
+            .into_iter()
+            .filter(|event| event.attributes.contains(&tx_id_attribute))
+            .collect();
+
+        Ok(Receipt {
+            id: transaction.id().to_string(),
+            kind: transaction.kind().to_string(),
+            height,
+            gas: transaction.gas(),
+            fee: transaction.fees(),
+            public_outputs: transaction.public_outputs(),
+            records: transaction.output_records(),
+            events,
+        })
+    }
+
+    /// Return the height of the block that included the transaction with the given id, or None if it's not committed.
+    pub async fn get_transaction_height(&self, tx_id: &str) -> Result<Option<u64>> {
+        let query = Query::contains("app.tx_id", tx_id);
+        let response = self
+            .rpc
+            .tx_search(query, false, 1, 1, Order::Ascending)
+            .await?;
+        Ok(response
+            .txs
+            .into_iter()
+            .next()
+            .map(|result| result.height.value()))
+    }
+
+    /// Fetch a committed transaction by its id, verifying that it's included in a block signed by the validators
+    /// and that its output records are part of the state, as described in `light`.
+    #[allow(clippy::clone_on_copy)]
+    pub async fn get_verified_transaction(&self, tx_id: &str) -> Result<Transaction> {
+        let query = Query::contains("app.tx_id", tx_id);
+
+        let response = self
+            .rpc
+            .tx_search(query, true, 1, 1, Order::Ascending)
+            .await?;
+        if response.total_count == 0 {
+            return Err(NotFound(format!(
+                "Transaction ID {tx_id} is invalid or has not yet been committed to the blockchain"
+            ))
+            .into());
+        }
+
+        let result = response.txs.into_iter().next().unwrap();
+        let tx_bytes: Vec<u8> = result.tx.into();
+        let proof = result
+            .proof
+            .ok_or_else(|| anyhow!("the node didn't return the transaction proof"))?;
+        let header = self.verified_header(result.height.value()).await?;
+        light::verify_transaction(&tx_bytes, &proof, &header)?;
+
+        let transaction: Transaction = bincode::deserialize(&tx_bytes)?;
+        for (commitment, record) in transaction.output_records() {
+            let proof: StateProof = self
+                .query(AbciQuery::GetRecordProof {
+                    commitment: commitment.clone(),
+                })
+                .await?;
+            let header = self.verified_header(proof.height + 1).await?;
+            light::verify_record(&commitment, &record, &proof, &header)?;
+        }
+        Ok(transaction)
+    }
+
+    /// Fetch the block header at the given height, verifying that it's signed by the validator set
+    /// that the node reports for that height. Fails if the block wasn't produced yet.
+    pub async fn verified_header(&self, height: u64) -> Result<Header> {
+        let (signed_header, _) = self.signed_header(height).await?;
+        Ok(signed_header.header)
+    }
+
+    /// Fetch the signed block header at the given height along with the validator set that signed it,
+    /// verifying the signatures. Fails if the block wasn't produced yet.
+    pub async fn signed_header(
+        &self,
+        height: u64,
+    ) -> Result<(SignedHeader, tendermint::validator::Set)> {
+        let height = Height::try_from(height)?;
+        let commit = self.rpc.commit(height).await.map_err(|e| {
+            anyhow!("couldn't fetch the block header at height {height}, it may not be produced yet: {e}")
+        })?;
+        let validators = self.rpc.validators(height, Paging::All).await?;
+        let validators = tendermint::validator::Set::without_proposer(validators.validators);
+
+        light::verify_header(&commit.signed_header, &validators)?;
+        Ok((commit.signed_header, validators))
+    }
+
+    /// Fetch the id of the chain the node is part of, which transactions need to include to be accepted.
+    pub async fn chain_id(&self) -> Result<String> {
+        let status = self.rpc.status().await?;
+        Ok(status.node_info.network.to_string())
+    }
+
+    /// Fetch the height of the last block committed by the node.
+    pub async fn latest_height(&self) -> Result<u64> {
+        let status = self.rpc.status().await?;
+        Ok(status.sync_info.latest_block_height.value())
+    }
+
+    /// Fetch the role of the node and the earliest height whose history it keeps, from its ABCI Info response.
+    pub async fn node_info(&self) -> Result<NodeInfo> {
+        let info = self.rpc.abci_info().await?;
+        serde_json::from_str(&info.data)
+            .map_err(|e| anyhow!("the node doesn't report its role: {e}"))
+    }
+
+    /// Return a client for a node that keeps the history of the given height: this one if it does, otherwise
+    /// the archive node at `archive_url`, failing if there's none. Nodes that don't report their role keep
+    /// the whole history.
+    pub async fn with_history_from(&self, height: u64, archive_url: Option<&str>) -> Result<Self> {
+        let earliest_height = self
+            .node_info()
+            .await
+            .map_or(0, |info| info.earliest_height);
+        if height >= earliest_height {
+            return Ok(self.clone());
+        }
+        match archive_url {
+            Some(url) => {
+                debug!("Sending the queries of height {height} to the archive node {url}");
+                Self::new(url)
+            }
+            None => bail!(
+                "the node only keeps the blocks from height {earliest_height}, pass the url of an archive node to query height {height}"
+            ),
+        }
+    }
+
+    /// Fetch a deployed program by its id, returning None if it's not known by the node.
+    pub async fn get_program(&self, program_id: &vm::ProgramID) -> Result<Option<vm::Program>> {
+        Ok(self.get_program_with_metadata(program_id).await?.program)
+    }
+
+    /// Fetch a deployed program by its id along with its deployment information, see `ProgramResponse`.
+    pub async fn get_program_with_metadata(
+        &self,
+        program_id: &vm::ProgramID,
+    ) -> Result<ProgramResponse> {
+        self.query(AbciQuery::GetProgram {
+            program_id: program_id.to_owned(),
+        })
+        .await
+    }
+
+    /// Fetch a deployed program by its id along with its deployment information, verifying that the program is
+    /// part of the state committed in a block signed by the validators, as described in `light`. The deployment
+    /// information isn't committed in the state, so it's trusted from the node.
+    pub async fn get_verified_program(
+        &self,
+        program_id: &vm::ProgramID,
+    ) -> Result<ProgramResponse> {
+        let (response, proof): (ProgramResponse, StateProof) = self
+            .query_with_proof(AbciQuery::GetProgram {
+                program_id: program_id.to_owned(),
+            })
+            .await?;
+        if let Some(program) = &response.program {
+            let header = self.verified_header(proof.height + 1).await?;
+            light::verify_program(program, &proof, &header)?;
+        }
+        Ok(response)
+    }
+
+    /// Fetch a page of up to `limit` deployed programs sorted by id, optionally only those whose id starts with `prefix`.
+    /// Pass the returned program id as `from` to get the next page.
+    pub async fn list_programs(
+        &self,
+        prefix: Option<String>,
+        from: Option<vm::ProgramID>,
+        limit: Option<usize>,
+    ) -> Result<(Vec<ProgramSummary>, Option<vm::ProgramID>)> {
+        let response: ProgramListResponse = self
+            .query(AbciQuery::ListPrograms {
+                prefix,
+                from,
+                limit,
+            })
+            .await?;
+        Ok((response.programs, response.cursor))
+    }
+
+    /// Fetch a page of up to `limit` record ciphertexts created by the given program, sorted by commitment.
+    /// Pass the returned commitment as `from` to get the next page.
+    pub async fn get_records_by_program(
+        &self,
+        program_id: &vm::ProgramID,
+        from: Option<vm::Field>,
+        limit: Option<usize>,
+    ) -> Result<(Vec<RecordEntry>, Option<vm::Field>)> {
+        let response: RecordsResponse = self
+            .query(AbciQuery::GetRecordsByProgram {
+                program_id: program_id.to_owned(),
+                from,
+                limit,
+            })
+            .await?;
+        Ok((response.records, response.cursor))
+    }
+
+    /// Fetch all the record ciphertexts in the blockchain, page by page, along with the height that added them.
+    pub async fn scan_records(&self) -> Result<Vec<RecordEntry>> {
+        let mut records = Vec::new();
+        let mut from = None;
+        loop {
+            let page: RecordsResponse = self
+                .query(AbciQuery::GetRecords {
+                    from,
+                    limit: Some(RECORDS_PAGE_SIZE),
+                })
+                .await?;
+            records.extend(page.records);
+            match page.cursor {
+                Some(cursor) => from = Some(cursor),
+                None => return Ok(records),
+            }
+        }
+    }
+
+    /// Fetch the serial numbers of all the spent records, along with the height that spent them.
+    pub async fn get_spent_serial_numbers(&self) -> Result<Vec<SpentEntry>> {
+        let response: SpentResponse = self.query(AbciQuery::GetSpentSerialNumbers).await?;
+        Ok(response.serial_numbers)
+    }
+
+    /// Fetch all the records in the blockchain that can be decrypted with the given view key, regardless of
+    /// whether they were spent or not. Returns their commitments, ciphertexts and decrypted plaintexts.
+    #[allow(clippy::clone_on_copy)]
+    pub async fn get_records(
+        &self,
+        view_key: &vm::ViewKey,
+    ) -> Result<Vec<(vm::Field, vm::EncryptedRecord, vm::Record)>> {
+        self.get_records_with(&ViewKeyDecryptor::new(view_key.clone())?)
+            .await
+    }
+
+    /// Same as `get_records`, telling which records belong to the account with the given decryptor, e.g. one backed
+    /// by a remote key service. The ciphertexts are sent to it a page at a time.
+    pub async fn get_records_with(
+        &self,
+        decryptor: &impl RecordDecryptor,
+    ) -> Result<Vec<(vm::Field, vm::EncryptedRecord, vm::Record)>> {
+        Ok(self
+            .get_record_entries_with(decryptor)
+            .await?
+            .into_iter()
+            .map(|(entry, record)| (entry.commitment, entry.ciphertext, record))
+            .collect())
+    }
+
+    /// Same as `get_records_with`, keeping the height that added each record.
+    pub async fn get_record_entries_with(
+        &self,
+        decryptor: &impl RecordDecryptor,
+    ) -> Result<Vec<(RecordEntry, vm::Record)>> {
+        let records = self.scan_records().await?;
+        debug!("Records: {:?}", records);
+
+        let mut owned = Vec::new();
+        for page in records.chunks(RECORDS_PAGE_SIZE) {
+            let ciphertexts: Vec<vm::EncryptedRecord> =
+                page.iter().map(|entry| entry.ciphertext.clone()).collect();
+            let plaintexts = decryptor.decrypt(&ciphertexts).await?;
+            for (entry, plaintext) in page.iter().zip(plaintexts) {
+                if let Some(record) = plaintext {
+                    owned.push((entry.clone(), record));
+                }
+            }
+        }
+        Ok(owned)
+    }
+
+    /// Fetch the memos attached to the given records and decrypt them with the view key of their owner.
+    /// Records without a memo are omitted from the result.
+    pub async fn get_memos(
+        &self,
+        view_key: &vm::ViewKey,
+        commitments: Vec<vm::Field>,
+    ) -> Result<HashMap<vm::Field, String>> {
+        let response: MemosResponse = self.query(AbciQuery::GetMemos { commitments }).await?;
+
+        response
+            .memos
+            .into_iter()
+            .map(|(commitment, memo)| Ok((commitment, vm::decrypt_memo(view_key, &memo)?)))
+            .collect()
+    }
+
+    /// Fetch the unspent records owned by the given account. The private key is required (instead of just the
+    /// view key) to compute the serial numbers that tell whether a record was spent.
+    #[allow(clippy::clone_on_copy)]
+    pub async fn get_unspent_records(
+        &self,
+        private_key: &vm::PrivateKey,
+    ) -> Result<Vec<(vm::Field, vm::EncryptedRecord, vm::Record)>> {
+        self.get_unspent_records_with(&ViewKeyDecryptor::with_private_key(private_key.clone())?)
+            .await
+    }
+
+    /// Same as `get_unspent_records`, with a decryptor that can derive the serial numbers of the account records
+    /// to tell the spent ones apart.
+    pub async fn get_unspent_records_with(
+        &self,
+        decryptor: &impl RecordDecryptor,
+    ) -> Result<Vec<(vm::Field, vm::EncryptedRecord, vm::Record)>> {
+        let records = self.get_records_with(decryptor).await?;
+        let spent_records: HashSet<vm::Field> = self
+            .get_spent_serial_numbers()
+            .await?
+            .into_iter()
+            .map(|entry| entry.serial_number)
+            .collect();
+
+        #[allow(clippy::clone_on_copy)]
+        let commitments: Vec<vm::Field> = records
+            .iter()
+            .map(|(commitment, _, _)| commitment.clone())
+            .collect();
+        let serial_numbers = decryptor
+            .serial_numbers(&commitments)
+            .await?
+            .ok_or_else(|| {
+                anyhow!("the record decryptor can't tell whether the records were spent, e.g. because it only has the view key")
+            })?;
+
+        let records = records
+            .into_iter()
+            .zip(serial_numbers)
+            .filter(|(_, serial_number)| !spent_records.contains(serial_number))
+            .map(|(record, _)| record)
+            .collect();
+        Ok(records)
+    }
+
+    /// Fetch the validator set currently known by the node.
+    pub async fn get_validators(&self) -> Result<Vec<Validator>> {
+        let response: ValidatorsResponse = self.query(AbciQuery::GetValidators).await?;
+        Ok(response.validators)
+    }
+
+    /// Fetch the current chain parameters and the pending governance proposals.
+    pub async fn get_governance(&self) -> Result<GovernanceStatus> {
+        self.query(AbciQuery::GetGovernance).await
+    }
+
+    /// Fetch the balance of the community pool and the pending proposals to spend it.
+    pub async fn get_community_pool(&self) -> Result<CommunityPool> {
+        self.query(AbciQuery::GetCommunityPool).await
+    }
+
+    /// Fetch a summary of the chain: its id, height, app hash, counts of transactions, records and validators, base fee
+    /// and the version of the node.
+    pub async fn get_chain_status(&self) -> Result<ChainStatus> {
+        self.query(AbciQuery::GetChainStatus).await
+    }
+
+    /// Fetch the counts of stored records, programs and index entries of the node, and the disk space its stores
+    /// take. The node goes through all of its entries to answer, so it's slow on large chains.
+    pub async fn get_store_stats(&self) -> Result<StoreStats> {
+        self.query(AbciQuery::GetStoreStats).await
+    }
+
+    /// Fetch the counterparty chains of the bridge and the packets exchanged with them.
+    pub async fn get_bridge_status(&self) -> Result<BridgeStatus> {
+        self.query(AbciQuery::GetBridgeStatus).await
+    }
+
+    /// Fetch up to `limit` packets sent to the given chain starting at the given sequence, with their state proofs.
+    pub async fn get_packets(
+        &self,
+        destination_chain: &str,
+        from_sequence: u64,
+        limit: usize,
+    ) -> Result<PacketsResponse> {
+        self.query(AbciQuery::GetPackets {
+            destination_chain: destination_chain.to_string(),
+            from_sequence,
+            limit,
+        })
+        .await
+    }
+
+    /// Ask the node for the minimum fee it would accept for an execution of the given function.
+    pub async fn estimate_fee(
+        &self,
+        program_id: vm::ProgramID,
+        function: vm::Identifier,
+    ) -> Result<FeeEstimate> {
+        self.query(AbciQuery::EstimateFee {
+            program_id,
+            function,
+        })
+        .await
+    }
+
+    /// Fetch the execution statistics of a deployed program, failing if the program doesn't exist.
+    pub async fn get_program_stats(&self, program_id: vm::ProgramID) -> Result<ProgramStats> {
+        self.query(AbciQuery::GetProgramStats { program_id }).await
+    }
+
+    /// Fetch whether a program id can be deployed, and the deployments of it that failed in a block.
+    pub async fn get_program_availability(
+        &self,
+        program_id: vm::ProgramID,
+    ) -> Result<ProgramAvailability> {
+        self.query(AbciQuery::GetProgramAvailability { program_id })
+            .await
+    }
+
+    /// Fetch the program a registry name points to, e.g. `token/usdx`, and the owner of its namespace.
+    pub async fn resolve_name(&self, name: &str) -> Result<NameResponse> {
+        self.query(AbciQuery::ResolveName {
+            name: name.to_string(),
+        })
+        .await
+    }
+
+    /// Have the node compare the given source code with a deployed program and its published source hash.
+    pub async fn verify_source(
+        &self,
+        program_id: vm::ProgramID,
+        source: String,
+    ) -> Result<SourceVerification> {
+        self.query(AbciQuery::VerifySource { program_id, source })
+            .await
+    }
+
+    /// Fetch the events the application emitted while processing the committed block at the given height.
+    pub async fn get_block_events(&self, height: u64) -> Result<Vec<BlockEvent>> {
+        let response: BlockEventsResponse =
+            self.query(AbciQuery::GetBlockEvents { height }).await?;
+        Ok(response.events)
+    }
+
+    /// Fetch the rewards distributed to the validators by the committed blocks from height `from` to `to`, or to the
+    /// last committed one, only keeping the ones assigned to the given account if any. See `AbciQuery::GetBlockRewards`.
+    pub async fn get_block_rewards(
+        &self,
+        address: Option<vm::Address>,
+        from: u64,
+        to: Option<u64>,
+    ) -> Result<Vec<BlockRewards>> {
+        let response: BlockRewardsResponse = self
+            .query(AbciQuery::GetBlockRewards { address, from, to })
+            .await?;
+        Ok(response.blocks)
+    }
+
+    /// Fetch the uptime, proposals, rewards and yield of each validator over the last `blocks` committed blocks,
+    /// or the node default if not given. See `AbciQuery::GetValidatorPerformance`.
+    pub async fn get_validator_performance(
+        &self,
+        blocks: Option<u64>,
+    ) -> Result<ValidatorPerformanceResponse> {
+        self.query(AbciQuery::GetValidatorPerformance { blocks })
+            .await
+    }
+
+    /// Fetch the validators that were slashed and tombstoned for double signing, see `AbciQuery::GetTombstones`.
+    pub async fn get_tombstones(&self) -> Result<Vec<Validator>> {
+        let response: TombstonesResponse = self.query(AbciQuery::GetTombstones).await?;
+        Ok(response.validators)
+    }
+
+    /// Fetch the verifying keys of the functions of a deployed program, returning None if it's not known by the node.
+    pub async fn get_verifying_keys(
+        &self,
+        program_id: &vm::ProgramID,
+    ) -> Result<Option<vm::VerifyingKeyMap>> {
+        let response: VerifyingKeysResponse = self
+            .query(AbciQuery::GetVerifyingKeys {
+                program_id: program_id.to_owned(),
+            })
+            .await?;
+        Ok(response.verifying_keys)
+    }
+
+    /// Fetch a proof of the spent status of the record with the given serial number, see `AbciQuery::GetSpendProof`.
+    pub async fn get_spend_proof(&self, serial_number: vm::Field) -> Result<StateProof> {
+        self.query(AbciQuery::GetSpendProof { serial_number }).await
+    }
+
+    /// Fetch up to `limit` of the transactions waiting in the node mempool, in the order they'll be proposed,
+    /// along with the total amount of transactions in it. Tendermint caps the limit at 100.
+    pub async fn get_unconfirmed_transactions(
+        &self,
+        limit: usize,
+    ) -> Result<(Vec<Transaction>, u64)> {
+        let url = format!(
+            "{}/unconfirmed_txs?limit={limit}",
+            self.url.trim_end_matches('/')
+        );
+        let response: serde_json::Value = reqwest::get(&url).await?.json().await?;
+        let unexpected = || anyhow!("unexpected response from {url}: {response}");
+
+        // tendermint encodes integers as strings and transactions in base64
+        let total = response["result"]["total"]
+            .as_str()
+            .and_then(|total| total.parse().ok())
+            .ok_or_else(unexpected)?;
+        let transactions = response["result"]["txs"]
+            .as_array()
+            .ok_or_else(unexpected)?
+            .iter()
+            .map(|tx| {
+                let bytes = base64::decode(tx.as_str().ok_or_else(unexpected)?)?;
+                Ok(bincode::deserialize(&bytes)?)
+            })
+            .collect::<Result<_>>()?;
+        Ok((transactions, total))
+    }
+
+    /// Send the transaction to the node, failing if it's rejected by the mempool validations (check_tx).
+    pub async fn broadcast(&self, transaction: &Transaction) -> Result<BroadcastOutcome> {
+        self.broadcaster.broadcast(transaction).await
+    }
+
+    /// Fetch the ciphertext of the record with the given commitment, along with the proof of whether it's
+    /// part of the state. Check it with `light::verify_record` before trusting the result.
+    pub async fn get_record(
+        &self,
+        commitment: vm::Field,
+    ) -> Result<(Option<vm::EncryptedRecord>, StateProof)> {
+        let (response, proof): (RecordResponse, StateProof) = self
+            .query_with_proof(AbciQuery::GetRecord { commitment })
+            .await?;
+        Ok((response.record, proof))
+    }
+
+    /// Run the given ABCI query against the node, deserializing its result along with the state proof
+    /// attached to the response. Only supported by the queries that document it.
+    pub async fn query_with_proof<T: DeserializeOwned>(
+        &self,
+        query: AbciQuery,
+    ) -> Result<(T, StateProof)> {
+        let response = self.rpc.abci_query(None, query, None, true).await?;
+        if let tendermint::abci::Code::Err(code) = response.code {
+            bail!("Error executing transaction {}: {}", code, response.log)
+        }
+
+        let op = response
+            .proof
+            .iter()
+            .flat_map(|proof| proof.ops.iter())
+            .find(|op| op.field_type == merkle::STATE_PROOF_OP)
+            .ok_or_else(|| anyhow!("the node didn't include a state proof in the response"))?;
+        Ok((
+            QueryResponse::decode(&response.value)?,
+            bincode::deserialize(&op.data)?,
+        ))
+    }
+
+    /// Run the given ABCI query against the node and deserialize its result.
+    pub async fn query<T: DeserializeOwned>(&self, query: AbciQuery) -> Result<T> {
+        let response = self.rpc.abci_query(None, query, None, true).await?;
+
+        debug!("Response from Query: {:?}", response);
+        match response.code {
+            tendermint::abci::Code::Ok => QueryResponse::decode(&response.value),
+            tendermint::abci::Code::Err(code) => {
+                bail!("Error executing transaction {}: {}", code, response.log)
+            }
+        }
+    }
+}
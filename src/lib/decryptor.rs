@@ -0,0 +1,207 @@
+use crate::vm;
+use anyhow::{anyhow, ensure, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+/// Tells which record ciphertexts belong to an account and decrypts them, so the code that scans the ledger for the
+/// records and balance of an account doesn't need to hold its keys. `ViewKeyDecryptor` uses the account keys
+/// directly, while `RpcDecryptor` delegates to a remote key service, e.g. the one of a custodial wallet.
+#[async_trait]
+pub trait RecordDecryptor: Send + Sync {
+    /// Return the plaintext of each of the given records, or None for the ones not owned by the account.
+    async fn decrypt(&self, records: &[vm::EncryptedRecord]) -> Result<Vec<Option<vm::Record>>>;
+
+    /// Return the serial numbers of the given records of the account, used to tell whether they were spent.
+    /// Returns None if the decryptor can't derive them, e.g. with just a view key on some VM backends.
+    async fn serial_numbers(&self, commitments: &[vm::Field]) -> Result<Option<Vec<vm::Field>>>;
+}
+
+/// Decrypt records with the account view key. Serial numbers are derived with the private key if given, or with the
+/// view key when the VM backend allows it.
+pub struct ViewKeyDecryptor {
+    view_key: vm::ViewKey,
+    address: vm::Address,
+    private_key: Option<vm::PrivateKey>,
+}
+
+impl ViewKeyDecryptor {
+    pub fn new(view_key: vm::ViewKey) -> Result<Self> {
+        let address = vm::Address::try_from(&view_key)?;
+        Ok(Self {
+            view_key,
+            address,
+            private_key: None,
+        })
+    }
+
+    pub fn with_private_key(private_key: vm::PrivateKey) -> Result<Self> {
+        let view_key = vm::ViewKey::try_from(&private_key)?;
+        Ok(Self {
+            private_key: Some(private_key),
+            ..Self::new(view_key)?
+        })
+    }
+}
+
+#[async_trait]
+impl RecordDecryptor for ViewKeyDecryptor {
+    async fn decrypt(&self, records: &[vm::EncryptedRecord]) -> Result<Vec<Option<vm::Record>>> {
+        Ok(records
+            .iter()
+            .map(|record| {
+                if record.is_owner(&self.address, &self.view_key) {
+                    record.decrypt(&self.view_key).ok()
+                } else {
+                    None
+                }
+            })
+            .collect())
+    }
+
+    #[allow(clippy::clone_on_copy)]
+    async fn serial_numbers(&self, commitments: &[vm::Field]) -> Result<Option<Vec<vm::Field>>> {
+        match &self.private_key {
+            Some(private_key) => commitments
+                .iter()
+                .map(|commitment| {
+                    vm::compute_serial_number(private_key.clone(), commitment.clone())
+                })
+                .collect::<Result<_>>()
+                .map(Some),
+            None => Ok(commitments
+                .iter()
+                .map(|commitment| {
+                    vm::serial_number_from_view_key(&self.view_key, commitment.clone())
+                })
+                .collect()),
+        }
+    }
+}
+
+/// Body of the `POST {url}/decrypt` request sent by `RpcDecryptor`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DecryptRequest {
+    pub records: Vec<vm::EncryptedRecord>,
+}
+
+/// Expected response of the `POST {url}/decrypt` request: the plaintext of each record, in the order of the request,
+/// or null for the records not owned by the account.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DecryptResponse {
+    pub records: Vec<Option<vm::Record>>,
+}
+
+/// Body of the `POST {url}/serial_numbers` request sent by `RpcDecryptor`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SerialNumbersRequest {
+    pub commitments: Vec<vm::Field>,
+}
+
+/// Expected response of the `POST {url}/serial_numbers` request: the serial number of each record, in the order of the
+/// request, or null if the service doesn't compute them.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SerialNumbersResponse {
+    pub serial_numbers: Option<Vec<vm::Field>>,
+}
+
+/// Delegate the decryption to a remote key service that holds the account keys, so they are never exposed to this
+/// process. The service gets the ciphertexts in JSON and returns the plaintexts of the ones owned by the account,
+/// see `DecryptRequest` and `SerialNumbersRequest`.
+pub struct RpcDecryptor {
+    url: String,
+    http: reqwest::Client,
+}
+
+impl RpcDecryptor {
+    /// Create a decryptor for the key service at the given base url, e.g. http://127.0.0.1:8080
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            http: reqwest::Client::new(),
+        }
+    }
+
+    async fn post<Req: Serialize + Sync, Res: for<'de> Deserialize<'de>>(
+        &self,
+        path: &str,
+        request: &Req,
+    ) -> Result<Res> {
+        let url = format!("{}/{path}", self.url);
+        let response = self.http.post(&url).json(request).send().await?;
+        let status = response.status();
+        ensure!(
+            status.is_success(),
+            "the key service at {url} failed with status {status}"
+        );
+        response
+            .json()
+            .await
+            .map_err(|e| anyhow!("invalid response from the key service at {url}: {e}"))
+    }
+}
+
+#[async_trait]
+impl RecordDecryptor for RpcDecryptor {
+    async fn decrypt(&self, records: &[vm::EncryptedRecord]) -> Result<Vec<Option<vm::Record>>> {
+        let request = DecryptRequest {
+            records: records.to_vec(),
+        };
+        let response: DecryptResponse = self.post("decrypt", &request).await?;
+        ensure!(
+            response.records.len() == records.len(),
+            "the key service returned {} records for {} ciphertexts",
+            response.records.len(),
+            records.len()
+        );
+        Ok(response.records)
+    }
+
+    async fn serial_numbers(&self, commitments: &[vm::Field]) -> Result<Option<Vec<vm::Field>>> {
+        let request = SerialNumbersRequest {
+            commitments: commitments.to_vec(),
+        };
+        let response: SerialNumbersResponse = self.post("serial_numbers", &request).await?;
+        if let Some(serial_numbers) = &response.serial_numbers {
+            ensure!(
+                serial_numbers.len() == commitments.len(),
+                "the key service returned {} serial numbers for {} records",
+                serial_numbers.len(),
+                commitments.len()
+            );
+        }
+        Ok(response.serial_numbers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    #[allow(clippy::clone_on_copy)]
+    async fn view_key_decryptor_only_decrypts_owned_records() {
+        let private_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let address = vm::Address::try_from(&vm::ViewKey::try_from(&private_key).unwrap()).unwrap();
+        let other_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let other_address =
+            vm::Address::try_from(&vm::ViewKey::try_from(&other_key).unwrap()).unwrap();
+
+        let (commitment, owned) =
+            vm::mint_record("credits.aleo", "credits", &address, 10, 1).unwrap();
+        let (_, other) = vm::mint_record("credits.aleo", "credits", &other_address, 10, 2).unwrap();
+
+        let decryptor = ViewKeyDecryptor::with_private_key(private_key.clone()).unwrap();
+        let records = decryptor.decrypt(&[owned, other]).await.unwrap();
+        assert_eq!(10, vm::gates(records[0].as_ref().unwrap()));
+        assert!(records[1].is_none());
+
+        let serial_numbers = decryptor
+            .serial_numbers(&[commitment.clone()])
+            .await
+            .unwrap();
+        assert_eq!(
+            vec![vm::compute_serial_number(private_key, commitment).unwrap()],
+            serial_numbers.unwrap()
+        );
+    }
+}
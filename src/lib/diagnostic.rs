@@ -0,0 +1,182 @@
+//! Structured explanations of why a transaction was rejected. The node puts them as JSON in the `log` of the
+//! check_tx and deliver_tx responses, so clients can tell which check failed and on what part of the transaction
+//! without parsing the error message. `tx debug` produces the same diagnostics running the checks locally.
+
+use crate::vm;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// The validation a transaction failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Check {
+    /// The bytes sent to the node aren't a transaction.
+    Decode,
+    /// The transaction is malformed, e.g. its id doesn't match its contents.
+    Structure,
+    /// A signature of the transaction doesn't match the key that should have made it.
+    Signature,
+    ChainId,
+    Expired,
+    /// The transaction doesn't fit in the block, or in any block.
+    BlockLimits,
+    /// The same record is spent twice in the transaction.
+    DuplicateInput,
+    /// An input record is unknown or was already spent.
+    SpentInput,
+    /// An input credits record is locked until a later height.
+    LockedInput,
+    /// An input record is owned by the address of a program the execution doesn't run.
+    ProgramOwnedInput,
+    /// A transition runs a program that isn't deployed.
+    MissingProgram,
+    /// A program pinned by the execution doesn't match the deployed one, see `Transaction::pinned_programs`.
+    ProgramMismatch,
+    /// The proof of a transition doesn't verify with the verifying key of its function.
+    Proof,
+    /// Any other validation, e.g. of fees, staking or governance. The message has the details.
+    Other,
+}
+
+/// The transition of an execution a diagnostic refers to.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransitionRef {
+    /// Position of the transition in the execution, if known.
+    pub index: Option<usize>,
+    pub program: String,
+    pub function: String,
+}
+
+/// Why a transaction was rejected. It's an error on its own, so validations can return it and the node can
+/// recover it from the error with `Diagnostic::of`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub check: Check,
+    /// The error message, the same one the node logs.
+    pub message: String,
+    /// Id of the rejected transaction, or of the part of a batch that failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transaction: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transition: Option<TransitionRef>,
+    /// Serial number of the input record that failed the check.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub serial_number: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(check: Check, message: impl ToString) -> Self {
+        Self {
+            check,
+            message: message.to_string(),
+            transaction: None,
+            transition: None,
+            serial_number: None,
+        }
+    }
+
+    /// Set the transaction the diagnostic refers to, unless it's already set by a more specific check,
+    /// e.g. to the part of a batch that failed.
+    pub fn for_transaction(mut self, id: impl ToString) -> Self {
+        self.transaction.get_or_insert_with(|| id.to_string());
+        self
+    }
+
+    pub fn with_transition(mut self, transition: &vm::Transition) -> Self {
+        self.transition = Some(TransitionRef {
+            index: None,
+            program: transition.program_id().to_string(),
+            function: transition.function_name().to_string(),
+        });
+        self
+    }
+
+    /// Set the position of the transition in the execution, if the diagnostic refers to one.
+    pub fn at_index(mut self, index: usize) -> Self {
+        if let Some(transition) = &mut self.transition {
+            transition.index = Some(index);
+        }
+        self
+    }
+
+    pub fn with_serial_number(mut self, serial_number: &vm::Field) -> Self {
+        self.serial_number = Some(serial_number.to_string());
+        self
+    }
+
+    /// The diagnostic of the given error, or one of the `Other` check with its message if the error doesn't have one.
+    pub fn of(error: &anyhow::Error) -> Self {
+        error
+            .downcast_ref::<Diagnostic>()
+            .cloned()
+            .unwrap_or_else(|| Self::new(Check::Other, error))
+    }
+
+    /// Parse the diagnostic from the log of a node response, None if the log isn't one, e.g. if it comes
+    /// from tendermint instead of the application.
+    pub fn from_log(log: &str) -> Option<Self> {
+        serde_json::from_str(log).ok()
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_else(|_| self.message.clone())
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+/// Turn the errors of a check into diagnostics, for use with `map_err`. Errors that already have a diagnostic,
+/// e.g. from a more specific check, are left as they are.
+pub fn failed(check: Check) -> impl Fn(anyhow::Error) -> anyhow::Error {
+    move |error| {
+        if error.is::<Diagnostic>() {
+            error
+        } else {
+            Diagnostic::new(check, error).into()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{anyhow, Result};
+
+    fn spent() -> Result<()> {
+        Err(Diagnostic::new(Check::SpentInput, "input record is already spent").into())
+    }
+
+    #[test]
+    fn diagnostics_are_recovered_from_errors() {
+        let error = spent().map_err(failed(Check::Other)).unwrap_err();
+        assert_eq!("input record is already spent", error.to_string());
+        let diagnostic = Diagnostic::of(&error).for_transaction("tx1");
+        assert_eq!(Check::SpentInput, diagnostic.check);
+        assert_eq!(Some("tx1".to_string()), diagnostic.transaction);
+        // the batch part that failed isn't replaced by the batch id
+        assert_eq!(
+            Some("tx1".to_string()),
+            diagnostic.clone().for_transaction("batch").transaction
+        );
+
+        let json = diagnostic.to_json();
+        assert_eq!(
+            r#"{"check":"spent_input","message":"input record is already spent","transaction":"tx1"}"#,
+            json
+        );
+        assert_eq!(Some(diagnostic), Diagnostic::from_log(&json));
+        assert_eq!(None, Diagnostic::from_log("mempool is full"));
+
+        let error = Err::<(), _>(anyhow!("bad signature"))
+            .map_err(failed(Check::Signature))
+            .unwrap_err();
+        assert_eq!(Check::Signature, Diagnostic::of(&error).check);
+        assert_eq!(Check::Other, Diagnostic::of(&anyhow!("other")).check);
+    }
+}
@@ -0,0 +1,179 @@
+use crate::vm;
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+/// Chain parameters that can be changed through governance proposals.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Parameter {
+    /// Baseline amount of credits distributed among validators on each block, in addition to fees.
+    BlockReward,
+    /// Minimum amount of fees that deployments and executions need to pay.
+    BaseFee,
+    /// Minimum version of the application required to process blocks. Raising it schedules a chain upgrade:
+    /// nodes running an older version halt after the proposal's activation height until they are restarted
+    /// with an upgraded binary.
+    AppVersion,
+    /// Minimum amount of gates of the output records of transactions, to prevent filling the record store with
+    /// dust records. Only enforced on records with public gates, since the rest can't be read by the nodes.
+    MinRecordGates,
+    /// Minimum stake of a validator, made by its own aleo account, to be part of the active set.
+    /// Validators below it are kept as candidates until they stake enough.
+    MinSelfStake,
+    /// Maximum amount of validators in the active set, the ones with the most voting power. Zero for no limit.
+    MaxValidators,
+    /// Percentage of the block rewards and fees diverted to the community pool instead of the validators.
+    CommunityPoolPercentage,
+}
+
+/// The current values of the chain parameters.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Params {
+    pub block_reward: u64,
+    pub base_fee: u64,
+    pub app_version: u64,
+    #[serde(default)]
+    pub min_record_gates: u64,
+    #[serde(default)]
+    pub min_self_stake: u64,
+    #[serde(default)]
+    pub max_validators: u64,
+    #[serde(default)]
+    pub community_pool_percentage: u64,
+}
+
+/// An action that a validator can submit with a governance transaction.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Action {
+    /// Propose to change the given parameter to a new value. If it gets enough votes, the change takes
+    /// effect at the activation height. The id of the proposal is the id of the transaction that submits it.
+    Propose {
+        parameter: Parameter,
+        value: u64,
+        activation_height: u64,
+    },
+    /// Vote on a pending proposal. Votes can be changed until the activation height.
+    Vote { proposal_id: String, approve: bool },
+    /// Propose to pay the given amount of gates out of the community pool to the recipient. If it gets enough
+    /// votes and the pool still has the amount at the activation height, a credits record is minted to the recipient.
+    SpendCommunityPool {
+        recipient: vm::Address,
+        amount: u64,
+        activation_height: u64,
+    },
+}
+
+/// A governance proposal that hasn't reached its activation height yet.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Proposal {
+    pub id: String,
+    /// The parameter to change, None for community pool spends.
+    pub parameter: Option<Parameter>,
+    /// The new value of the parameter, or the gates paid by a community pool spend.
+    pub value: u64,
+    /// Recipient of a community pool spend.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recipient: Option<vm::Address>,
+    pub activation_height: u64,
+    /// Hex encoded tendermint address of each validator that voted, and whether it approved the proposal.
+    pub votes: Vec<(String, bool)>,
+}
+
+/// The governance state as returned by the `GetGovernance` query.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GovernanceStatus {
+    pub params: Params,
+    pub proposals: Vec<Proposal>,
+    /// Gates in the community pool, see `CommunityPool`.
+    #[serde(default)]
+    pub community_pool: u64,
+    /// Nonce the next governance action of each validator has to be signed with, by validator address. Validators
+    /// that never submitted an action aren't listed, their next nonce is zero.
+    #[serde(default)]
+    pub nonces: BTreeMap<String, u64>,
+}
+
+/// The community pool as returned by the `GetCommunityPool` query. It accrues the `community_pool_percentage`
+/// of the rewards and fees of every block, and can only be spent through governance proposals.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CommunityPool {
+    /// Gates in the pool, including the ones of spend proposals that didn't reach their activation height yet.
+    pub balance: u64,
+    pub percentage: u64,
+    /// Spend proposals that didn't reach their activation height yet.
+    pub pending_spends: Vec<Proposal>,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            block_reward: 100,
+            base_fee: 0,
+            app_version: 1,
+            min_record_gates: 0,
+            min_self_stake: 0,
+            max_validators: 0,
+            community_pool_percentage: 0,
+        }
+    }
+}
+
+impl Params {
+    pub fn get(&self, parameter: Parameter) -> u64 {
+        match parameter {
+            Parameter::BlockReward => self.block_reward,
+            Parameter::BaseFee => self.base_fee,
+            Parameter::AppVersion => self.app_version,
+            Parameter::MinRecordGates => self.min_record_gates,
+            Parameter::MinSelfStake => self.min_self_stake,
+            Parameter::MaxValidators => self.max_validators,
+            Parameter::CommunityPoolPercentage => self.community_pool_percentage,
+        }
+    }
+
+    pub fn set(&mut self, parameter: Parameter, value: u64) {
+        match parameter {
+            Parameter::BlockReward => self.block_reward = value,
+            Parameter::BaseFee => self.base_fee = value,
+            Parameter::AppVersion => self.app_version = value,
+            Parameter::MinRecordGates => self.min_record_gates = value,
+            Parameter::MinSelfStake => self.min_self_stake = value,
+            Parameter::MaxValidators => self.max_validators = value,
+            Parameter::CommunityPoolPercentage => self.community_pool_percentage = value,
+        }
+    }
+}
+
+impl FromStr for Parameter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "block_reward" => Ok(Parameter::BlockReward),
+            "base_fee" => Ok(Parameter::BaseFee),
+            "app_version" => Ok(Parameter::AppVersion),
+            "min_record_gates" => Ok(Parameter::MinRecordGates),
+            "min_self_stake" => Ok(Parameter::MinSelfStake),
+            "max_validators" => Ok(Parameter::MaxValidators),
+            "community_pool_percentage" => Ok(Parameter::CommunityPoolPercentage),
+            _ => {
+                bail!("unknown parameter {s}, expected one of: block_reward, base_fee, app_version, min_record_gates, min_self_stake, max_validators, community_pool_percentage")
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Parameter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Parameter::BlockReward => write!(f, "block_reward"),
+            Parameter::BaseFee => write!(f, "base_fee"),
+            Parameter::AppVersion => write!(f, "app_version"),
+            Parameter::MinRecordGates => write!(f, "min_record_gates"),
+            Parameter::MinSelfStake => write!(f, "min_self_stake"),
+            Parameter::MaxValidators => write!(f, "max_validators"),
+            Parameter::CommunityPoolPercentage => write!(f, "community_pool_percentage"),
+        }
+    }
+}
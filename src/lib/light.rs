@@ -0,0 +1,128 @@
+//! Light client verification helpers, to check the data returned by an untrusted full node against
+//! the block headers signed by the validators. A header is trusted if it's signed by more than 2/3 of the
+//! voting power of the validator set it commits to; the state proofs returned by the node queries are then
+//! checked against the app hash of those headers (see `merkle`).
+//!
+//! Note that this doesn't verify the validator set itself: it's fetched from the same node, so a client that
+//! doesn't trust it should compare the validators hash of the headers against a known one (e.g. from genesis).
+
+use crate::merkle::{self, InclusionProof, StateProof};
+use crate::vm;
+use anyhow::{anyhow, ensure, Result};
+use sha2::{Digest, Sha256};
+use tendermint::block::signed_header::SignedHeader;
+use tendermint::block::Header;
+use tendermint::validator;
+use tendermint_light_client_verifier::operations::voting_power::{
+    ProdVotingPowerCalculator, VotingPowerCalculator,
+};
+
+/// Verify that the header was committed by validators with more than 2/3 of the voting power of the given set,
+/// and that the set is the one the header commits to.
+pub fn verify_header(signed_header: &SignedHeader, validators: &validator::Set) -> Result<()> {
+    let header = &signed_header.header;
+    ensure!(
+        header.validators_hash == validators.hash(),
+        "validator set doesn't match the header at height {}",
+        header.height
+    );
+    ensure!(
+        signed_header.commit.block_id.hash == header.hash(),
+        "commit doesn't match the header at height {}",
+        header.height
+    );
+
+    ProdVotingPowerCalculator::default()
+        .check_signers_overlap(signed_header, validators)
+        .map_err(|e| {
+            anyhow!(
+                "header at height {} is not signed by enough validators: {e}",
+                header.height
+            )
+        })
+}
+
+/// Verify that the transaction bytes are included in the block of the given (verified) header.
+pub fn verify_transaction(
+    transaction: &[u8],
+    proof: &tendermint::tx::Proof,
+    header: &Header,
+) -> Result<()> {
+    // tendermint hashes each transaction before building the tree of the block data
+    let leaf = merkle::raw_leaf_hash(&Sha256::digest(transaction));
+    let inclusion = InclusionProof {
+        index: proof.proof.index as usize,
+        total: proof.proof.total as usize,
+        siblings: proof
+            .proof
+            .aunts
+            .iter()
+            .map(|aunt| hash_bytes(aunt.as_bytes()))
+            .collect::<Result<_>>()?,
+    };
+
+    let data_hash = header
+        .data_hash
+        .ok_or_else(|| anyhow!("block at height {} has no data", header.height))?;
+    ensure!(
+        inclusion.root(leaf)?.as_slice() == data_hash.as_bytes(),
+        "transaction is not included in the block at height {}",
+        header.height
+    );
+    Ok(())
+}
+
+/// Verify a state proof against the app hash of the given (verified) header, returning the value of the key if present.
+/// Tendermint includes the app hash resulting from a block in the header of the next one,
+/// so the header should be the one at `proof.height + 1`.
+pub fn verify_state(
+    proof: &StateProof,
+    component: &str,
+    key: &[u8],
+    header: &Header,
+) -> Result<Option<Vec<u8>>> {
+    ensure!(
+        header.height.value() == proof.height + 1,
+        "proof for height {} should be checked against the header at the next height, got {}",
+        proof.height,
+        header.height
+    );
+    proof.verify(component, key, header.app_hash.as_ref())
+}
+
+/// Verify that the record with the given commitment and ciphertext is part of the state.
+#[allow(clippy::redundant_clone)] // commitments are strings on lambdavm and so clippy generates a warning for `.to_string()`
+pub fn verify_record(
+    commitment: &vm::Field,
+    record: &vm::EncryptedRecord,
+    proof: &StateProof,
+    header: &Header,
+) -> Result<()> {
+    let key = commitment.to_string().into_bytes();
+    let value = verify_state(proof, merkle::RECORDS_COMPONENT, &key, header)?
+        .ok_or_else(|| anyhow!("record {commitment} is not in the state"))?;
+    ensure!(
+        value == record.to_string().into_bytes(),
+        "record {commitment} doesn't match the one in the state"
+    );
+    Ok(())
+}
+
+/// Verify that the given program is deployed.
+pub fn verify_program(program: &vm::Program, proof: &StateProof, header: &Header) -> Result<()> {
+    let key = program.id().to_string().into_bytes();
+    let value = verify_state(proof, merkle::PROGRAMS_COMPONENT, &key, header)?
+        .ok_or_else(|| anyhow!("program {} is not deployed", program.id()))?;
+    ensure!(
+        value == merkle::program_hash(program),
+        "program {} doesn't match the deployed one",
+        program.id()
+    );
+    Ok(())
+}
+
+fn hash_bytes(bytes: &[u8]) -> Result<merkle::Hash> {
+    bytes
+        .try_into()
+        .map_err(|_| anyhow!("invalid hash length {}", bytes.len()))
+}
@@ -0,0 +1,507 @@
+//! Merkle trees used to commit the application state in the block app hash, so clients can verify
+//! query results against a block header without trusting the node that answered them.
+//!
+//! Each state component (e.g. the spent serial numbers) is a sorted key/value set committed by the root
+//! of a binary Merkle tree over its entries, built as described in RFC 6962 (https://www.rfc-editor.org/rfc/rfc6962#section-2.1).
+//! The app hash is in turn the root of a tree with one leaf per component, in a fixed order.
+//! Sorting the entries allows proving that a key is absent by showing the two adjacent keys around it.
+
+use crate::vm;
+use anyhow::{bail, ensure, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+pub type Hash = [u8; 32];
+
+/// Spent record serial numbers, with the height they were spent at as big endian bytes.
+pub const SPENT_COMPONENT: &str = "spent";
+/// Record ciphertexts by commitment.
+pub const RECORDS_COMPONENT: &str = "records";
+/// Deployed programs by id, with the `program_hash` of their source as value.
+pub const PROGRAMS_COMPONENT: &str = "programs";
+/// Serial numbers of the records owned by a program account, with the id of the program as value. They decide which
+/// transitions can spend those records, so every node must agree on them.
+pub const PROGRAM_OWNED_COMPONENT: &str = "program_owned";
+/// Registry entries by name, namespace and `name#sequence`, with the bincode serialization of the program id, the
+/// namespace owner and the sequence respectively as value.
+pub const NAMES_COMPONENT: &str = "names";
+/// Executions waiting for their target height, by `record_store::scheduled_key`, with their bincode serialization
+/// as value. Their output records are added to the ledger at that height, so every node must agree on them.
+pub const SCHEDULED_COMPONENT: &str = "scheduled";
+/// Packets sent to counterparty chains, by `bridge::packet_key`, with their bincode serialization as value.
+/// Only committed by chains with bridge counterparties, so the app hash of the rest doesn't change.
+pub const PACKETS_COMPONENT: &str = "packets";
+
+/// Type of the `ProofOp` with a bincode serialized `StateProof`, attached to the responses of the queries
+/// that support it (`GetProgram` and `GetRecord`) when they are run with `prove`.
+pub const STATE_PROOF_OP: &str = "aleo:state";
+
+/// Value committed for a deployed program in the programs component.
+pub fn program_hash(program: &vm::Program) -> Hash {
+    Sha256::digest(program.to_string().as_bytes()).into()
+}
+
+/// Hash of a tree entry. Leaves and inner nodes are prefixed differently to prevent second preimage attacks.
+pub fn leaf_hash(key: &[u8], value: &[u8]) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([0u8]);
+    hasher.update((key.len() as u64).to_be_bytes());
+    hasher.update(key);
+    hasher.update(value);
+    hasher.finalize().into()
+}
+
+/// Hash of a leaf holding arbitrary data, as used by tendermint for the block data.
+pub fn raw_leaf_hash(data: &[u8]) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([0u8]);
+    hasher.update(data);
+    hasher.finalize().into()
+}
+
+fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([1u8]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// Largest power of two smaller than n, for n > 1.
+fn split_point(n: usize) -> usize {
+    let mut k = 1;
+    while k * 2 < n {
+        k *= 2;
+    }
+    k
+}
+
+/// Root of the tree with the given leaf hashes.
+pub fn root(leaves: &[Hash]) -> Hash {
+    match leaves.len() {
+        0 => Sha256::digest([]).into(),
+        1 => leaves[0],
+        n => {
+            let k = split_point(n);
+            node_hash(&root(&leaves[..k]), &root(&leaves[k..]))
+        }
+    }
+}
+
+/// The sibling hashes needed to recompute the root from a leaf, from the bottom of the tree up.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct InclusionProof {
+    pub index: usize,
+    pub total: usize,
+    pub siblings: Vec<Hash>,
+}
+
+impl InclusionProof {
+    /// Build the proof for the leaf at the given index.
+    pub fn new(leaves: &[Hash], index: usize) -> Self {
+        Self {
+            index,
+            total: leaves.len(),
+            siblings: path(leaves, index),
+        }
+    }
+
+    /// Compute the root of the tree that contains the given leaf at the proof position.
+    pub fn root(&self, leaf: Hash) -> Result<Hash> {
+        ensure!(self.index < self.total, "leaf index out of range");
+
+        let mut index = self.index;
+        let mut last = self.total - 1;
+        let mut hash = leaf;
+        for sibling in &self.siblings {
+            ensure!(last > 0, "proof has too many siblings");
+            if index % 2 == 1 || index == last {
+                hash = node_hash(sibling, &hash);
+                // skip the levels where this node has no right sibling
+                while index % 2 == 0 && index != 0 {
+                    index >>= 1;
+                    last >>= 1;
+                }
+            } else {
+                hash = node_hash(&hash, sibling);
+            }
+            index >>= 1;
+            last >>= 1;
+        }
+        ensure!(last == 0, "proof has too few siblings");
+        Ok(hash)
+    }
+}
+
+fn path(leaves: &[Hash], index: usize) -> Vec<Hash> {
+    if leaves.len() <= 1 {
+        return vec![];
+    }
+    let k = split_point(leaves.len());
+    if index < k {
+        let mut path = path(&leaves[..k], index);
+        path.push(root(&leaves[k..]));
+        path
+    } else {
+        let mut path = path(&leaves[k..], index - k);
+        path.push(root(&leaves[..k]));
+        path
+    }
+}
+
+/// An entry of a key/value set along with the proof of its position in the tree.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EntryProof {
+    pub key: Vec<u8>,
+    pub value: Vec<u8>,
+    pub proof: InclusionProof,
+}
+
+impl EntryProof {
+    fn new(entries: &[(Vec<u8>, Vec<u8>)], leaves: &[Hash], index: usize) -> Self {
+        let (key, value) = entries[index].clone();
+        Self {
+            key,
+            value,
+            proof: InclusionProof::new(leaves, index),
+        }
+    }
+
+    fn verify(&self, root: &Hash) -> Result<()> {
+        ensure!(
+            self.proof.root(leaf_hash(&self.key, &self.value))? == *root,
+            "entry proof doesn't match the root"
+        );
+        Ok(())
+    }
+}
+
+/// Proof that a key is present in a key/value set with a given value, or that it's absent from it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum KeyProof {
+    Present(EntryProof),
+    /// The entries immediately before and after the key, if any.
+    Absent {
+        left: Option<EntryProof>,
+        right: Option<EntryProof>,
+        total: usize,
+    },
+}
+
+impl KeyProof {
+    /// Build the proof for the key out of the entries of the set, sorted by key.
+    pub fn new(entries: &[(Vec<u8>, Vec<u8>)], key: &[u8]) -> Self {
+        let leaves: Vec<Hash> = entries
+            .iter()
+            .map(|(key, value)| leaf_hash(key, value))
+            .collect();
+        let position = entries.binary_search_by(|(entry_key, _)| entry_key.as_slice().cmp(key));
+        Self::build(position, entries.len(), |index| {
+            EntryProof::new(entries, &leaves, index)
+        })
+    }
+
+    /// Build the proof out of the position of the key among the entries of the set, as returned by a binary
+    /// search, and a function that builds the proof of the entry at an index.
+    fn build(
+        position: Result<usize, usize>,
+        total: usize,
+        entry: impl Fn(usize) -> EntryProof,
+    ) -> Self {
+        match position {
+            Ok(index) => KeyProof::Present(entry(index)),
+            Err(index) => KeyProof::Absent {
+                left: index.checked_sub(1).map(&entry),
+                right: (index < total).then(|| entry(index)),
+                total,
+            },
+        }
+    }
+
+    /// Check the proof against the root of the set, returning the value of the key if it's present.
+    pub fn verify(&self, key: &[u8], root: &Hash) -> Result<Option<Vec<u8>>> {
+        match self {
+            KeyProof::Present(entry) => {
+                ensure!(entry.key == key, "proof is for a different key");
+                entry.verify(root)?;
+                Ok(Some(entry.value.clone()))
+            }
+            KeyProof::Absent { left, right, total } => {
+                match (left, right) {
+                    (None, None) => {
+                        ensure!(
+                            *total == 0 && self::root(&[]) == *root,
+                            "the set is not empty"
+                        )
+                    }
+                    (Some(left), None) => ensure!(
+                        left.proof.index + 1 == *total,
+                        "left neighbor is not the last entry"
+                    ),
+                    (None, Some(right)) => {
+                        ensure!(
+                            right.proof.index == 0,
+                            "right neighbor is not the first entry"
+                        )
+                    }
+                    (Some(left), Some(right)) => ensure!(
+                        left.proof.index + 1 == right.proof.index,
+                        "neighbors are not adjacent"
+                    ),
+                }
+                if let Some(left) = left {
+                    ensure!(
+                        left.key.as_slice() < key,
+                        "left neighbor is not before the key"
+                    );
+                    ensure!(left.proof.total == *total, "inconsistent tree size");
+                    left.verify(root)?;
+                }
+                if let Some(right) = right {
+                    ensure!(
+                        right.key.as_slice() > key,
+                        "right neighbor is not after the key"
+                    );
+                    ensure!(right.proof.total == *total, "inconsistent tree size");
+                    right.verify(root)?;
+                }
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// A key/value set kept sorted by key along with the leaf hash of each entry, so it can be updated as the entries
+/// change instead of being rebuilt. Its root and key proofs match the ones of `root` and `KeyProof::new` for the same
+/// entries, but are computed without rehashing the entries or holding their values, which are only read for the
+/// entries included in a proof.
+#[derive(Clone, Debug, Default)]
+pub struct MerkleSet {
+    keys: Vec<Vec<u8>>,
+    leaves: Vec<Hash>,
+    /// Root of the current entries, computed on demand and cleared when they change.
+    root: Option<Hash>,
+}
+
+impl MerkleSet {
+    /// Build the set out of entries sorted by key.
+    pub fn new(entries: impl IntoIterator<Item = (Vec<u8>, Vec<u8>)>) -> Self {
+        let (keys, leaves) = entries
+            .into_iter()
+            .map(|(key, value)| {
+                let leaf = leaf_hash(&key, &value);
+                (key, leaf)
+            })
+            .unzip();
+        Self {
+            keys,
+            leaves,
+            root: None,
+        }
+    }
+
+    /// Add an entry to the set, or update its value if the key is already in it.
+    pub fn insert(&mut self, key: Vec<u8>, value: &[u8]) {
+        let leaf = leaf_hash(&key, value);
+        match self.position(&key) {
+            Ok(index) => self.leaves[index] = leaf,
+            Err(index) => {
+                self.keys.insert(index, key);
+                self.leaves.insert(index, leaf);
+            }
+        }
+        self.root = None;
+    }
+
+    /// Remove the entry with the given key from the set, if any.
+    pub fn remove(&mut self, key: &[u8]) {
+        if let Ok(index) = self.position(key) {
+            self.keys.remove(index);
+            self.leaves.remove(index);
+            self.root = None;
+        }
+    }
+
+    /// Root of the tree of the entries of the set.
+    pub fn root(&mut self) -> Hash {
+        let leaves = &self.leaves;
+        *self.root.get_or_insert_with(|| root(leaves))
+    }
+
+    /// Build the proof for the key, reading the values of the entries it includes with the given function.
+    pub fn proof(&self, key: &[u8], value: impl Fn(&[u8]) -> Vec<u8>) -> KeyProof {
+        KeyProof::build(self.position(key), self.keys.len(), |index| EntryProof {
+            key: self.keys[index].clone(),
+            value: value(&self.keys[index]),
+            proof: InclusionProof::new(&self.leaves, index),
+        })
+    }
+
+    fn position(&self, key: &[u8]) -> Result<usize, usize> {
+        self.keys
+            .binary_search_by(|entry_key| entry_key.as_slice().cmp(key))
+    }
+}
+
+/// Compute the app hash committing to the given state component roots, in order.
+pub fn app_hash(components: &[(&str, Hash)]) -> Hash {
+    let leaves: Vec<Hash> = components
+        .iter()
+        .map(|(name, component_root)| leaf_hash(name.as_bytes(), component_root))
+        .collect();
+    root(&leaves)
+}
+
+/// Proof of the value of a key (or its absence) in one of the state components committed in the app hash.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StateProof {
+    /// Height of the block whose commit produced the app hash. Since tendermint includes the app hash in the next
+    /// block header, the proof should be checked against the header at `height + 1`.
+    pub height: u64,
+    pub component: String,
+    pub component_root: Hash,
+    /// Position of the component root in the app hash tree.
+    pub component_proof: InclusionProof,
+    pub key_proof: KeyProof,
+}
+
+impl StateProof {
+    /// Build the proof out of the roots of all the state components and the key proof in one of them.
+    pub fn new(
+        height: u64,
+        components: &[(&str, Hash)],
+        component: &str,
+        key_proof: KeyProof,
+    ) -> Result<Self> {
+        let index = match components.iter().position(|(name, _)| *name == component) {
+            Some(index) => index,
+            None => bail!("unknown state component {component}"),
+        };
+        let leaves: Vec<Hash> = components
+            .iter()
+            .map(|(name, component_root)| leaf_hash(name.as_bytes(), component_root))
+            .collect();
+
+        Ok(Self {
+            height,
+            component: component.to_string(),
+            component_root: components[index].1,
+            component_proof: InclusionProof::new(&leaves, index),
+            key_proof,
+        })
+    }
+
+    /// Check the proof against the given app hash, returning the value of the key if it's present.
+    pub fn verify(&self, component: &str, key: &[u8], app_hash: &[u8]) -> Result<Option<Vec<u8>>> {
+        ensure!(
+            self.component == component,
+            "proof is for a different component"
+        );
+        let root = self
+            .component_proof
+            .root(leaf_hash(component.as_bytes(), &self.component_root))?;
+        ensure!(
+            root.as_slice() == app_hash,
+            "proof doesn't match the app hash"
+        );
+        self.key_proof.verify(key, &self.component_root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inclusion_proofs() {
+        for size in 1..20 {
+            let leaves: Vec<Hash> = (0..size).map(|i: u8| leaf_hash(&[i], b"value")).collect();
+            let root = root(&leaves);
+            for (index, leaf) in leaves.iter().enumerate() {
+                let proof = InclusionProof::new(&leaves, index);
+                assert_eq!(root, proof.root(*leaf).unwrap());
+                assert_ne!(root, proof.root(leaf_hash(b"other", b"value")).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn key_proofs() {
+        let entries: Vec<(Vec<u8>, Vec<u8>)> = vec![
+            (b"b".to_vec(), b"1".to_vec()),
+            (b"d".to_vec(), b"2".to_vec()),
+            (b"f".to_vec(), b"3".to_vec()),
+        ];
+        let leaves: Vec<Hash> = entries.iter().map(|(k, v)| leaf_hash(k, v)).collect();
+        let root = root(&leaves);
+
+        let proof = KeyProof::new(&entries, b"d");
+        assert_eq!(Some(b"2".to_vec()), proof.verify(b"d", &root).unwrap());
+        assert!(proof.verify(b"b", &root).is_err());
+
+        for key in [b"a", b"c", b"e", b"g"] {
+            let proof = KeyProof::new(&entries, key);
+            assert_eq!(None, proof.verify(key, &root).unwrap());
+        }
+        // an absence proof can't be used for a present key
+        assert!(KeyProof::new(&entries, b"c").verify(b"d", &root).is_err());
+
+        let proof = KeyProof::new(&[], b"a");
+        assert_eq!(None, proof.verify(b"a", &super::root(&[])).unwrap());
+    }
+
+    #[test]
+    fn merkle_sets_match_the_rebuilt_tree() {
+        let mut entries: Vec<(Vec<u8>, Vec<u8>)> =
+            (0..10_u8).map(|i| (vec![i * 2], vec![i])).collect();
+        let mut set = MerkleSet::new(entries.clone());
+        let value_of = |entries: &[(Vec<u8>, Vec<u8>)], key: &[u8]| {
+            entries
+                .iter()
+                .find(|(entry_key, _)| entry_key == key)
+                .unwrap()
+                .1
+                .clone()
+        };
+
+        for (key, value) in [(vec![5], vec![1]), (vec![0], vec![2]), (vec![30], vec![3])] {
+            set.insert(key.clone(), &value);
+            entries.retain(|(entry_key, _)| *entry_key != key);
+            entries.push((key, value));
+        }
+        set.remove(&[4]);
+        set.remove(&[7]);
+        entries.retain(|(key, _)| *key != [4]);
+        entries.sort();
+
+        let leaves: Vec<Hash> = entries.iter().map(|(k, v)| leaf_hash(k, v)).collect();
+        let expected = root(&leaves);
+        assert_eq!(expected, set.root());
+        for key in [vec![0], vec![4], vec![5], vec![31]] {
+            let proof = set.proof(&key, |key| value_of(&entries, key));
+            assert_eq!(
+                KeyProof::new(&entries, &key)
+                    .verify(&key, &expected)
+                    .unwrap(),
+                proof.verify(&key, &expected).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn state_proofs() {
+        let entries = vec![(b"serial".to_vec(), 5u64.to_be_bytes().to_vec())];
+        let spent_root = root(&[leaf_hash(&entries[0].0, &entries[0].1)]);
+        let components = [("spent", spent_root), ("other", root(&[]))];
+        let app_hash = app_hash(&components);
+
+        let key_proof = KeyProof::new(&entries, b"serial");
+        let proof = StateProof::new(5, &components, "spent", key_proof).unwrap();
+        assert_eq!(
+            Some(5u64.to_be_bytes().to_vec()),
+            proof.verify("spent", b"serial", &app_hash).unwrap()
+        );
+        assert!(proof.verify("spent", b"serial", &[0; 32]).is_err());
+        assert!(proof.verify("other", b"serial", &app_hash).is_err());
+    }
+}
@@ -1,5 +1,18 @@
+use sha2::{Digest, Sha256};
 use std::{path::PathBuf, str::FromStr};
 
+pub mod analysis;
+pub mod bridge;
+pub mod broadcast;
+pub mod builder;
+pub mod client;
+pub mod decryptor;
+pub mod diagnostic;
+pub mod governance;
+pub mod light;
+pub mod merkle;
+pub mod names;
+pub mod pending;
 pub mod program_file;
 pub mod query;
 pub mod transaction;
@@ -13,18 +26,37 @@ pub fn aleo_home() -> PathBuf {
         .unwrap_or_else(|_| dirs::home_dir().unwrap().join(".aleo"))
 }
 
+/// Source of the credits program, see `load_credits`.
+pub const CREDITS_SOURCE: &str = include_str!("../../aleo/credits.aleo");
+
+/// Source of the credits program before locked credits were added to it. Chains keep it until they are upgraded
+/// through governance to the app version that introduced them, and the functions of both versions that they have
+/// in common are the same, so transactions built with either one are valid on both.
+pub const CREDITS_V1_SOURCE: &str = include_str!("../../aleo/credits_v1.aleo");
+
 /// Get the credits program. This is a special built-in program of the system, which contains
 /// functions to move aleo money. Since it's required for most uses in clients and servers, it's
 /// cached to only be built once.
 pub fn load_credits() -> (vm::Program, vm::ProgramBuild) {
+    load_credits_source(CREDITS_SOURCE)
+}
+
+/// Same as `load_credits`, for the version of the program chains start with, see `CREDITS_V1_SOURCE`.
+pub fn load_credits_v1() -> (vm::Program, vm::ProgramBuild) {
+    load_credits_source(CREDITS_V1_SOURCE)
+}
+
+fn load_credits_source(source: &str) -> (vm::Program, vm::ProgramBuild) {
     // TODO: move this to lambdaVM-specific module or to the crate
     // currently, lambda VM does not check whether the params are created on disk before using them
     // so if they do not exist, make sure they are generated
     #[cfg(feature = "lambdavm_backend")]
     vm::ensure_srs_file_exists().expect("Error reading or creating Universal SRS file");
 
-    // try to fetch from cache
-    let cache_path = aleo_home().join("cache/credits.avm");
+    // try to fetch from cache. the file name includes a hash of the source, so a cache built from
+    // another version of the program isn't used
+    let source_hash = hex::encode(Sha256::digest(source));
+    let cache_path = aleo_home().join(format!("cache/credits-{}.avm", &source_hash[..16]));
     if let Ok(program) = program_file::ProgramFile::load(&cache_path) {
         log::debug!("found credits program in {cache_path:?}");
         return program;
@@ -32,7 +64,6 @@ pub fn load_credits() -> (vm::Program, vm::ProgramBuild) {
 
     // else build keys and cache for future use
     log::debug!("cached credits not found, building and saving to {cache_path:?}");
-    let source = include_str!("../../aleo/credits.aleo");
     let file = program_file::ProgramFile::build(source).expect("couldn't build credits program");
     std::fs::create_dir_all(aleo_home().join("cache")).expect("couldn't create cache dir");
     file.save(&cache_path)
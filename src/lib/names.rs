@@ -0,0 +1,52 @@
+//! Human readable names of deployed programs, e.g. `token/usdx`, registered on chain with
+//! `Transaction::NameRegistration`. A name is made of a namespace and a name within it: the first account that
+//! registers a name in a namespace owns it, and only that account can register or update names in it afterwards.
+//! Names can only point to programs deployed by the owner of their namespace, so a name can't be taken by
+//! deploying a program before its author does.
+
+use anyhow::{anyhow, ensure, Result};
+
+/// Maximum length of the namespace and of the name, each of them.
+pub const MAX_NAME_LENGTH: usize = 32;
+
+/// Split a registry name in its namespace and name, failing if it's not a valid one. Both parts start with a
+/// lowercase letter followed by lowercase letters, digits and underscores, up to `MAX_NAME_LENGTH` characters.
+pub fn parse(name: &str) -> Result<(&str, &str)> {
+    let (namespace, local) = name
+        .split_once('/')
+        .ok_or_else(|| anyhow!("name {name} is not in the namespace/name form"))?;
+    for part in [namespace, local] {
+        ensure!(
+            !part.is_empty() && part.len() <= MAX_NAME_LENGTH,
+            "the parts of name {name} must have between 1 and {MAX_NAME_LENGTH} characters"
+        );
+        ensure!(
+            part.starts_with(|c: char| c.is_ascii_lowercase())
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_'),
+            "the parts of name {name} must start with a lowercase letter and only have lowercase letters, digits and underscores"
+        );
+    }
+    Ok((namespace, local))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_names() {
+        assert_eq!(("token", "usdx"), parse("token/usdx").unwrap());
+        assert_eq!(("a1", "b_2"), parse("a1/b_2").unwrap());
+
+        assert!(parse("usdx").is_err());
+        assert!(parse("token/").is_err());
+        assert!(parse("/usdx").is_err());
+        assert!(parse("token/usdx/v2").is_err());
+        assert!(parse("Token/usdx").is_err());
+        assert!(parse("token/usdx.aleo").is_err());
+        assert!(parse("1token/usdx").is_err());
+        assert!(parse(&format!("token/{}", "x".repeat(MAX_NAME_LENGTH + 1))).is_err());
+    }
+}
@@ -0,0 +1,151 @@
+use crate::decryptor::RecordDecryptor;
+use crate::transaction::Transaction;
+use crate::vm;
+use anyhow::{anyhow, bail, ensure, Result};
+use futures_util::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio_tungstenite::tungstenite::Message;
+
+/// Output records of a transaction accepted in the mempool of a node, announced by the nodes that run the pending
+/// record relay before the transaction is committed. Each message of the relay websocket is one of these, in JSON.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PendingRecords {
+    pub tx_id: String,
+    /// Commitments and ciphertexts of the output records. Wallets try to decrypt them to find their payments.
+    pub records: Vec<(vm::Field, vm::EncryptedRecord)>,
+}
+
+impl PendingRecords {
+    pub fn of(transaction: &Transaction) -> Self {
+        Self {
+            tx_id: transaction.id().to_string(),
+            records: transaction.output_records(),
+        }
+    }
+}
+
+/// A record of the account in a transaction that isn't committed yet. It may never be, e.g. if a conflicting
+/// transaction spends the same inputs first, so it can't be relied upon until it's in a block.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PendingPayment {
+    pub tx_id: String,
+    pub commitment: vm::Field,
+    pub record: vm::Record,
+}
+
+/// Subscribe to the pending record relay of a node at the given websocket url, e.g. ws://127.0.0.1:26670, calling
+/// `on_payment` with each pending record the decryptor recognizes as owned by the account. Runs until the node closes
+/// the connection or `on_payment` returns false.
+pub async fn watch(
+    url: &str,
+    decryptor: &impl RecordDecryptor,
+    mut on_payment: impl FnMut(PendingPayment) -> bool,
+) -> Result<()> {
+    let (mut socket, _) = tokio_tungstenite::connect_async(url)
+        .await
+        .map_err(|e| anyhow!("couldn't connect to the pending record relay at {url}: {e}"))?;
+
+    while let Some(message) = socket.next().await {
+        let announcement: PendingRecords = match message? {
+            Message::Text(json) => serde_json::from_str(&json)?,
+            Message::Close(_) => break,
+            _ => continue,
+        };
+        let ciphertexts: Vec<vm::EncryptedRecord> = announcement
+            .records
+            .iter()
+            .map(|(_, ciphertext)| ciphertext.clone())
+            .collect();
+        let plaintexts = decryptor.decrypt(&ciphertexts).await?;
+        for ((commitment, _), plaintext) in announcement.records.into_iter().zip(plaintexts) {
+            if let Some(record) = plaintext {
+                let payment = PendingPayment {
+                    tx_id: announcement.tx_id.clone(),
+                    commitment,
+                    record,
+                };
+                if !on_payment(payment) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Message a subscriber sends to the record relay to receive, instead of the pending announcements, the records
+/// owned by the given accounts as soon as the blocks that create them are committed. Holding the view key is what
+/// authorizes a subscriber to learn the records of an account, so the relay decrypts them on its side.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Registration {
+    pub view_keys: Vec<vm::ViewKey>,
+}
+
+/// A record of one of the registered accounts, created by a transaction of a committed block.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CommittedRecord {
+    pub height: u64,
+    pub tx_id: String,
+    pub owner: vm::Address,
+    pub commitment: vm::Field,
+    pub record: vm::Record,
+}
+
+/// Messages the relay sends to the subscribers that registered view keys, in JSON.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RelayMessage {
+    /// Acknowledges a `Registration`, with the addresses of the view keys. Records are pushed from then on.
+    Registered {
+        addresses: Vec<vm::Address>,
+    },
+    Committed(CommittedRecord),
+}
+
+/// Register the given view keys with the record relay of a node at the given websocket url, calling `on_record`
+/// with each record of those accounts in the blocks committed from then on. Runs until the node closes the
+/// connection or `on_record` returns false.
+/// The view keys are sent to the node, so this should only be used with a node the accounts trust, e.g. one of
+/// their own, and through an encrypted connection if it's not local.
+pub async fn watch_committed(
+    url: &str,
+    view_keys: Vec<vm::ViewKey>,
+    mut on_record: impl FnMut(CommittedRecord) -> bool,
+) -> Result<()> {
+    let (mut socket, _) = tokio_tungstenite::connect_async(url)
+        .await
+        .map_err(|e| anyhow!("couldn't connect to the record relay at {url}: {e}"))?;
+    let registration = serde_json::to_string(&Registration { view_keys })?;
+    socket.send(Message::Text(registration)).await?;
+
+    // pending announcements may arrive until the relay processes the registration
+    let mut registered = false;
+    while let Some(message) = socket.next().await {
+        let json = match message? {
+            Message::Text(json) => json,
+            // the relay gives a reason when it rejects the registration or the subscriber falls behind
+            Message::Close(Some(frame)) if !frame.reason.is_empty() => {
+                bail!("the record relay closed the connection: {}", frame.reason)
+            }
+            Message::Close(_) => break,
+            _ => continue,
+        };
+        if !registered {
+            registered = matches!(
+                serde_json::from_str(&json),
+                Ok(RelayMessage::Registered { .. })
+            );
+            continue;
+        }
+        if let RelayMessage::Committed(record) = serde_json::from_str(&json)? {
+            if !on_record(record) {
+                return Ok(());
+            }
+        }
+    }
+    ensure!(
+        registered,
+        "the record relay at {url} closed the connection without accepting the view keys"
+    );
+    Ok(())
+}
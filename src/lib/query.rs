@@ -1,14 +1,532 @@
-use crate::vm::ProgramID;
+use crate::transaction::PublicOutput;
+use crate::validator::Validator;
+use crate::vm::{self, ProgramID};
+use anyhow::{anyhow, ensure, Result};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::str::FromStr;
 
+/// Version of the query response schema. It's only increased when a response changes in a way older clients
+/// can't read, e.g. when a field is removed or its type changes; adding fields doesn't require a new version.
+pub const QUERY_VERSION: u32 = 1;
+
+/// Maximum amount of blocks covered by a single `GetBlockRewards` query.
+pub const MAX_REWARDS_BLOCKS: u64 = 10_000;
+
+/// Amount of blocks covered by the `GetValidatorPerformance` query when none is given.
+pub const DEFAULT_PERFORMANCE_BLOCKS: u64 = 1_000;
+
+/// Queries supported by the application. Each of them documents the response type it returns,
+/// wrapped in a `QueryResponse`.
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub enum AbciQuery {
-    /// Returns all records's ciphertexts from the blockchain
-    GetRecords,
-    /// Returns all spent records's serial numbers
+    /// Returns a `RecordsResponse` with a page of up to `limit` records from the blockchain, sorted by commitment,
+    /// starting after the `from` commitment. Pass the returned cursor as `from` to get the next page.
+    GetRecords {
+        from: Option<vm::Field>,
+        limit: Option<usize>,
+    },
+    /// Returns a `SpentResponse` with all the spent records's serial numbers
     GetSpentSerialNumbers,
-    /// Returns the program struct given it's id
+    /// Returns a `MemosResponse` with the encrypted memos attached to the records with the given commitments,
+    /// for the ones that have one.
+    GetMemos { commitments: Vec<vm::Field> },
+    /// Returns a `ProgramResponse` with the program with the given id. When run with `prove`, the response includes a
+    /// `merkle::StateProof` of whether it's deployed.
     GetProgram { program_id: ProgramID },
+    /// Returns a `RecordResponse` with the record ciphertext with the given commitment, if it exists.
+    /// When run with `prove`, the response includes a `merkle::StateProof` of whether it's part of the state.
+    GetRecord { commitment: vm::Field },
+    /// Returns a `ValidatorsResponse` with the currently known validator set
+    GetValidators,
+    /// Returns a `ProgramListResponse` with a page of summaries of the deployed programs sorted by id, optionally
+    /// filtered by an id prefix. `from` is the last program id of the previous page, as returned in the cursor.
+    ListPrograms {
+        prefix: Option<String>,
+        from: Option<ProgramID>,
+        limit: Option<usize>,
+    },
+    /// Returns the `GovernanceStatus` with the current chain parameters and the pending governance proposals
+    GetGovernance,
+    /// Returns a `merkle::StateProof` of whether the record with the given serial number was spent, and at what height,
+    /// that can be verified against the app hash of the last committed block.
+    GetSpendProof { serial_number: vm::Field },
+    /// Returns a `merkle::StateProof` of whether the record with the given commitment exists, and its ciphertext.
+    GetRecordProof { commitment: vm::Field },
+    /// Returns a `merkle::StateProof` of whether the program with the given id is deployed, and its `merkle::program_hash`.
+    GetProgramProof { program_id: ProgramID },
+    /// Returns a `FeeEstimate` of the minimum fee the node would currently accept for an execution
+    /// of the given function, before it's generated. The estimate doesn't depend on the execution
+    /// inputs nor on its randomness, so the same request always gets the same answer until the fee
+    /// parameters change.
+    EstimateFee {
+        program_id: ProgramID,
+        function: vm::Identifier,
+    },
+    /// Returns the `ProgramStats` of the deployed program with the given id.
+    GetProgramStats { program_id: ProgramID },
+    /// Returns a `BlockEventsResponse` with the events emitted by the application while processing the block
+    /// at the given height, as persisted by the node, so they are available after tendermint discards them.
+    GetBlockEvents { height: u64 },
+    /// Compiles the given source code and compares it with the deployed program and its published source hash,
+    /// returning a `SourceVerification`. The query doesn't change the node state.
+    VerifySource {
+        program_id: ProgramID,
+        source: String,
+    },
+    /// Same as `GetRecords`, only for the records created by the transitions of the given program, e.g. the ones
+    /// minted by a token program. It goes through an index, so it doesn't scan the rest of the records.
+    /// Records committed before the node kept the index aren't returned.
+    GetRecordsByProgram {
+        program_id: ProgramID,
+        from: Option<vm::Field>,
+        limit: Option<usize>,
+    },
+    /// Returns a `BridgeStatus` with the counterparty chains of the bridge and the packets exchanged with them.
+    GetBridgeStatus,
+    /// Returns a `PacketsResponse` with up to `limit` packets sent to the given chain, starting at the given sequence,
+    /// along with the proofs that they are committed in the app hash, all at the same height.
+    GetPackets {
+        destination_chain: String,
+        from_sequence: u64,
+        limit: usize,
+    },
+    /// Returns a `BlockRewardsResponse` with the credits distributed to the validators by each committed block
+    /// from height `from` to `to`, both included, or to the last committed block if `to` isn't given. If an address
+    /// is given, only the rewards assigned to that account are returned, skipping the blocks that didn't assign it any.
+    /// At most `MAX_REWARDS_BLOCKS` blocks can be requested at once.
+    GetBlockRewards {
+        address: Option<vm::Address>,
+        from: u64,
+        to: Option<u64>,
+    },
+    /// Returns a `ValidatorPerformanceResponse` with the uptime, proposals, rewards and yield of each validator
+    /// over the last `blocks` committed blocks, or the last `DEFAULT_PERFORMANCE_BLOCKS` if not given.
+    /// At most `MAX_REWARDS_BLOCKS` blocks can be requested at once.
+    GetValidatorPerformance { blocks: Option<u64> },
+    /// Returns a `TombstonesResponse` with the validators that were slashed for double signing.
+    GetTombstones,
+    /// Returns a `VerifyingKeysResponse` with the verifying keys of the functions of the deployed program,
+    /// e.g. to verify the proofs of an execution locally before sending it.
+    GetVerifyingKeys { program_id: ProgramID },
+    /// Returns a `governance::CommunityPool` with the balance of the community pool, the percentage of the block
+    /// rewards it receives and the pending proposals to spend it.
+    GetCommunityPool,
+    /// Returns a `ChainStatus` summary of the chain, cheap enough to poll as a heartbeat.
+    GetChainStatus,
+    /// Returns the `StoreStats` of the node, e.g. to plan its disk capacity. It goes through every stored entry,
+    /// so it's slow on large chains and shouldn't be polled.
+    GetStoreStats,
+    /// Returns a `ProgramAvailability` telling whether a deployment of the program with the given id would be
+    /// accepted by the node, along with the deployments of the program that failed in a block.
+    GetProgramAvailability { program_id: ProgramID },
+    /// Returns a `NameResponse` with the program a registry name, e.g. `token/usdx`, points to, if it's registered,
+    /// and the owner of its namespace. See `lib::names`.
+    ResolveName { name: String },
+}
+
+/// Envelope of every query response, encoded as JSON so fields can be added to the response types without
+/// breaking older clients, which ignore the fields they don't know. Fields added to a response should
+/// be `#[serde(default)]`, so newer clients can still read the responses of older nodes.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct QueryResponse<T> {
+    /// The `QUERY_VERSION` of the node that answered the query.
+    pub version: u32,
+    pub data: T,
+}
+
+impl<T: Serialize> QueryResponse<T> {
+    /// Wrap and encode the given response data with the current query version.
+    pub fn encode(data: T) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(&QueryResponse {
+            version: QUERY_VERSION,
+            data,
+        })?)
+    }
+}
+
+impl<T: DeserializeOwned> QueryResponse<T> {
+    /// Decode the data of a response, failing if the node uses a newer schema than this client understands.
+    pub fn decode(bytes: &[u8]) -> Result<T> {
+        let response: QueryResponse<T> = serde_json::from_slice(bytes)?;
+        ensure!(
+            response.version <= QUERY_VERSION,
+            "the node answered with query version {}, but this client only supports up to version {QUERY_VERSION}",
+            response.version
+        );
+        Ok(response.data)
+    }
+}
+
+/// Result of the `GetRecords` query.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct RecordsResponse {
+    pub records: Vec<RecordEntry>,
+    /// Commitment of the last record of the page, to pass as `from` to get the next one. None on the last page.
+    pub cursor: Option<vm::Field>,
+}
+
+/// A record ciphertext as stored in the blockchain.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct RecordEntry {
+    pub commitment: vm::Field,
+    pub ciphertext: vm::EncryptedRecord,
+    /// Height of the block that added the record, if known. Records added before heights were tracked don't have one.
+    pub height: Option<u64>,
+}
+
+/// Result of the `GetSpentSerialNumbers` query.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct SpentResponse {
+    pub serial_numbers: Vec<SpentEntry>,
+}
+
+/// The serial number of a spent record.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct SpentEntry {
+    pub serial_number: vm::Field,
+    /// Height of the block that spent the record, if known.
+    pub height: Option<u64>,
+}
+
+/// Result of the `GetMemos` query.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct MemosResponse {
+    /// Commitments of the records with a memo, along with the encrypted memo.
+    pub memos: Vec<(vm::Field, vm::EncryptedMemo)>,
+}
+
+/// Result of the `GetProgram` query.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ProgramResponse {
+    /// The deployed program, None if it's not known by the node.
+    pub program: Option<vm::Program>,
+    /// Deployment information of the program, as listed by `ListPrograms`.
+    pub metadata: Option<ProgramSummary>,
+}
+
+/// Result of the `GetRecord` query.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct RecordResponse {
+    /// The record ciphertext, None if there's no record with the requested commitment.
+    pub record: Option<vm::EncryptedRecord>,
+}
+
+/// Result of the `GetValidators` query.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ValidatorsResponse {
+    pub validators: Vec<Validator>,
+}
+
+/// Result of the `GetTombstones` query, sorted by tendermint address.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct TombstonesResponse {
+    /// The tombstoned validators with what's left of their voting power. Their `tombstone` has the offense
+    /// they were slashed for and the height from which their stake can be unstaked.
+    pub validators: Vec<Validator>,
+}
+
+/// Result of the `GetVerifyingKeys` query.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct VerifyingKeysResponse {
+    /// The verifying keys by function name, None if the program is not known by the node.
+    pub verifying_keys: Option<vm::VerifyingKeyMap>,
+}
+
+/// Result of the `ListPrograms` query.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ProgramListResponse {
+    pub programs: Vec<ProgramSummary>,
+    /// Id of the last program of the page, to pass as `from` to get the next one.
+    pub cursor: Option<ProgramID>,
+}
+
+/// Result of the `GetBlockRewards` query, sorted by height. Blocks committed before the node recorded
+/// their rewards aren't included.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct BlockRewardsResponse {
+    pub blocks: Vec<BlockRewards>,
+}
+
+/// Credits distributed to the validators by a committed block.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct BlockRewards {
+    pub height: u64,
+    /// Baseline credits minted by the block, in gates, see the `block_reward` chain parameter.
+    pub minted: u64,
+    /// Sum of the fees paid by the transactions of the block, in gates.
+    pub fees: u64,
+    /// Part of the minted credits plus the fees paid to the community pool, in gates.
+    #[serde(default)]
+    pub community_pool: u64,
+    /// How the rest of the minted credits plus the fees were split between the validators.
+    pub rewards: Vec<ValidatorReward>,
+    /// Unix time of the block in seconds, as set by its proposer. None for blocks recorded before it was tracked.
+    #[serde(default)]
+    pub time: Option<u64>,
+}
+
+/// Credits assigned to a validator by a block, minted as a credits record owned by its aleo address.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct ValidatorReward {
+    pub pub_key: tendermint::PublicKey,
+    pub aleo_address: vm::Address,
+    pub gates: u64,
+    /// Whether the validator proposed the block, which gets it the proposer share of the rewards.
+    pub proposer: bool,
+}
+
+/// Result of the `GetValidatorPerformance` query, computed from the rewards of the blocks in the window.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ValidatorPerformanceResponse {
+    /// First height of the window.
+    pub from: u64,
+    /// Last height of the window, the last committed block.
+    pub to: u64,
+    /// Blocks of the window with recorded rewards, which the uptime is relative to. Blocks committed before
+    /// the node recorded their rewards aren't counted.
+    pub blocks: u64,
+    /// Blocks produced per year at the pace of the window, estimated from the times of its first and last blocks.
+    /// None if the node didn't record their times.
+    pub blocks_per_year: Option<u64>,
+    /// Sorted by voting power, from the highest.
+    pub validators: Vec<ValidatorPerformance>,
+}
+
+/// How a validator performed over the blocks of a `GetValidatorPerformance` window.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ValidatorPerformance {
+    pub pub_key: tendermint::PublicKey,
+    pub aleo_address: vm::Address,
+    /// Current voting power of the validator, which is its stake in gates.
+    pub voting_power: u64,
+    /// Blocks that assigned rewards to the validator, i.e. blocks whose previous block it signed, or that it proposed.
+    pub signed_blocks: u64,
+    pub proposed_blocks: u64,
+    /// Share of the blocks of the window that the validator signed, from 0 to 1.
+    pub uptime: f64,
+    /// Sum of the rewards assigned to the validator over the window, in gates.
+    pub rewards: u64,
+    /// Rewards per year relative to the stake of the validator, e.g. 0.05 for 5%, if the rewards and the pace of
+    /// the window hold. It isn't compounded, since rewards aren't staked automatically. None if the blocks per year
+    /// are unknown or the validator has no stake.
+    pub apy: Option<f64>,
+}
+
+/// Result of the `GetChainStatus` query.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ChainStatus {
+    /// Id of the chain, None for chains started before it was tracked.
+    pub chain_id: Option<String>,
+    /// Height of the last committed block.
+    pub height: u64,
+    /// App hash of the last committed block, hex encoded.
+    pub app_hash: String,
+    /// Amount of committed transactions. Transactions committed before the node counted them aren't included.
+    pub transactions: u64,
+    /// Amount of records committed to the chain, spent or not.
+    pub records: u64,
+    /// Amount of validators in the active set.
+    pub validators: usize,
+    /// Minimum fee of the transactions, in gates, see the `base_fee` chain parameter.
+    pub base_fee: u64,
+    /// Version of the node software.
+    pub version: String,
+}
+
+/// Result of the `GetStoreStats` query.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct StoreStats {
+    /// Height of the last committed block.
+    pub height: u64,
+    /// Amount of records committed to the chain, spent or not.
+    pub records: u64,
+    pub spent_records: u64,
+    pub unspent_records: u64,
+    /// Amount of deployed programs, including credits.aleo.
+    pub programs: u64,
+    /// Amount of entries of each table of the stores, by name, e.g. `program_records` for the index of the records
+    /// created by each program. Tables without entries are left out.
+    pub entries: BTreeMap<String, u64>,
+    /// Bytes taken on disk by each store, by name, e.g. `records`. Empty for nodes that keep their state in memory.
+    pub disk_bytes: BTreeMap<String, u64>,
+}
+
+/// A deployment that was included in a block but failed to apply, e.g. because its fee record was spent earlier in
+/// the block. The program id stays available, so it can be deployed again, by the same account or any other.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct FailedDeployment {
+    /// Id of the transaction that carried the deployment, i.e. of the batch or envelope that wrapped it, if any.
+    pub transaction_id: String,
+    /// Account that sent the deployment. Deployments aren't signed, so it's only what the transaction claims.
+    pub deployer: vm::Address,
+    /// Height of the block that included the deployment.
+    pub height: u64,
+    /// Why the deployment failed.
+    pub error: String,
+}
+
+/// Result of the `GetProgramAvailability` query.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ProgramAvailability {
+    pub program_id: ProgramID,
+    /// Whether the program id can be deployed: it's not deployed, nor is a deployment of it in the node mempool.
+    pub available: bool,
+    /// Height of the block that deployed the program, if it's deployed.
+    pub deployed_at: Option<u64>,
+    /// Account that deployed the program, if it's deployed by a transaction.
+    pub deployer: Option<vm::Address>,
+    /// Whether a deployment of the program is waiting in the node mempool.
+    pub pending: bool,
+    /// The last deployments of the program that failed, oldest first, if it was never deployed. Cleared once a
+    /// deployment succeeds.
+    pub failed_deployments: Vec<FailedDeployment>,
+}
+
+/// Result of the `ResolveName` query.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct NameResponse {
+    pub name: String,
+    /// The program the name points to, if it's registered.
+    pub program_id: Option<ProgramID>,
+    /// The account that owns the namespace of the name, if any name was registered in it.
+    pub owner: Option<vm::Address>,
+    /// Sequence the next registration of the name has to be signed with, see `Transaction::name_registration`.
+    pub sequence: u64,
+}
+
+/// Result of the `GetBlockEvents` query.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct BlockEventsResponse {
+    pub events: Vec<BlockEvent>,
+}
+
+/// Public information about a deployed program, as returned by the `ListPrograms` query.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ProgramSummary {
+    pub program_id: ProgramID,
+    /// Height of the block that included the deployment, zero for programs that are part of the genesis.
+    pub height: u64,
+    /// Address of the account that deployed the program, None for programs that are part of the genesis.
+    pub deployer: Option<vm::Address>,
+    /// Height from which executions of the program are rejected, if it was deprecated by its deployer.
+    pub deprecated_at: Option<u64>,
+    /// Hash of the program source code, if the deployer published it.
+    pub source_hash: Option<String>,
+    pub functions: Vec<String>,
+}
+
+/// Result of the `EstimateFee` query.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct FeeEstimate {
+    /// Minimum amount of gates the execution needs to pay as fee to be accepted.
+    pub min_fee: u64,
+    /// Gas of the execution, including the fee transition if a fee is required.
+    pub gas: u64,
+}
+
+/// Usage counters of a deployed program, as returned by the `GetProgramStats` query.
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+pub struct ProgramStats {
+    /// Amount of execution transactions that included a transition of the program.
+    pub executions: u64,
+    /// Sum of the fees paid by those transactions, in gates.
+    pub fees: u64,
+    /// Height of the last block that included an execution of the program, if any.
+    pub last_execution_height: Option<u64>,
+}
+
+/// Result of the `VerifySource` query.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct SourceVerification {
+    /// Whether the source code compiles to the deployed program.
+    pub program_matches: bool,
+    /// Whether the hash of the source code is the one published by the deployer. False if none was published.
+    pub source_hash_matches: bool,
+    /// Whether both of the above hold.
+    pub verified: bool,
+}
+
+/// An event emitted by the application while processing a block, as returned by the `GetBlockEvents` query.
+/// Events of delivered transactions come first, in block order, followed by the ones emitted at the end of the block.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct BlockEvent {
+    /// The event type, e.g. `app` for delivered transactions or `scheduled_execution`.
+    pub kind: String,
+    /// The event attributes as key, value pairs.
+    pub attributes: Vec<(String, String)>,
+}
+
+/// What a committed transaction did, as returned by `Client::get_receipt`.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Receipt {
+    pub id: String,
+    /// Kind of transaction, see `Transaction::kind`.
+    pub kind: String,
+    /// Height of the block that included the transaction.
+    pub height: u64,
+    pub gas: u64,
+    /// Credits paid to the validators, see `Transaction::fees`.
+    pub fee: i64,
+    pub public_outputs: Vec<PublicOutput>,
+    /// Commitments and ciphertexts of the created records, which only their owners can decrypt.
+    pub records: Vec<(vm::Field, vm::EncryptedRecord)>,
+    /// Events emitted by the application for the transaction, e.g. `app` or `bridge_packet`.
+    pub events: Vec<BlockEvent>,
+}
+
+/// Which history of the chain a node keeps, set by its operator.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeRole {
+    /// Keeps the events and rewards of every block, and tendermint keeps every block.
+    #[default]
+    Archive,
+    /// Keeps the events and rewards of the last `retained_heights` blocks, and lets tendermint prune the older
+    /// blocks. The records and spent serial numbers are kept whole, since transactions are validated against them.
+    Pruned { retained_heights: u64 },
+}
+
+impl NodeRole {
+    /// The earliest height whose history the node keeps once the block at the given height is committed.
+    pub fn earliest_height(self, height: u64) -> u64 {
+        match self {
+            Self::Archive => 1,
+            Self::Pruned { retained_heights } => {
+                (height + 1).saturating_sub(retained_heights).max(1)
+            }
+        }
+    }
+}
+
+impl FromStr for NodeRole {
+    type Err = anyhow::Error;
+
+    /// Parse `archive`, or `pruned:<heights>` with the amount of heights to keep.
+    fn from_str(s: &str) -> Result<Self> {
+        if s == "archive" {
+            return Ok(Self::Archive);
+        }
+        let retained_heights: u64 = s
+            .strip_prefix("pruned:")
+            .and_then(|heights| heights.parse().ok())
+            .ok_or_else(|| anyhow!("expected archive or pruned:<heights>, got {s}"))?;
+        ensure!(
+            retained_heights > 0,
+            "a pruned node needs to keep at least one height"
+        );
+        Ok(Self::Pruned { retained_heights })
+    }
+}
+
+/// What the node reports as the data of the ABCI Info response, encoded as JSON, so clients can tell whether it
+/// can answer queries about a given height or they should be sent to an archive node.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct NodeInfo {
+    pub role: NodeRole,
+    /// The earliest height whose events and rewards the node keeps.
+    pub earliest_height: u64,
 }
 
 impl From<AbciQuery> for Vec<u8> {
@@ -17,3 +535,27 @@ impl From<AbciQuery> for Vec<u8> {
         bincode::serialize(&q).unwrap()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn node_roles() {
+        assert_eq!(NodeRole::Archive, NodeRole::from_str("archive").unwrap());
+        let pruned = NodeRole::from_str("pruned:100").unwrap();
+        assert_eq!(
+            NodeRole::Pruned {
+                retained_heights: 100
+            },
+            pruned
+        );
+        assert!(NodeRole::from_str("pruned:0").is_err());
+        assert!(NodeRole::from_str("pruned").is_err());
+
+        assert_eq!(1, NodeRole::Archive.earliest_height(500));
+        assert_eq!(1, pruned.earliest_height(50));
+        // the last 100 heights, including the committed one
+        assert_eq!(401, pruned.earliest_height(500));
+    }
+}
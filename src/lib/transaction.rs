@@ -1,15 +1,46 @@
+use crate::bridge::{self, Packet};
+use crate::governance;
 use crate::load_credits;
-use crate::validator;
+use crate::merkle;
+use crate::names;
+use crate::validator::{self, ValidatorKey};
 use crate::vm::{self, VerifyingKeyMap};
-use anyhow::{anyhow, ensure, Result};
+use anyhow::{anyhow, bail, ensure, Result};
 use itertools::Itertools;
 use log::debug;
+use rand::{CryptoRng, Rng};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use std::collections::HashSet;
 use std::fs;
 use std::path::Path;
 use std::str::FromStr;
 
+/// Gas charged to every transaction, regardless of its contents.
+pub const TRANSACTION_GAS: u64 = 10;
+/// Gas charged for each transition that needs its proof verified.
+pub const TRANSITION_GAS: u64 = 100;
+/// Gas charged for each function of a deployed program, to account for its verifying key checks.
+pub const DEPLOYMENT_FUNCTION_GAS: u64 = 500;
+/// Maximum cumulative gas of the transactions delivered in a single block. See `Transaction::gas`.
+pub const MAX_BLOCK_GAS: u64 = 20_000;
+/// Maximum size in bytes of a serialized transaction accepted by the nodes, as set for the tendermint
+/// mempool (`max_tx_bytes`) by the Makefile node targets.
+pub const MAX_TRANSACTION_BYTES: u64 = 10_485_770;
+/// Maximum length in bytes of a record memo, before encryption.
+pub const MAX_MEMO_BYTES: usize = 128;
+/// Maximum size in bytes of an encrypted record memo, as serialized in the transaction.
+const MAX_ENCRYPTED_MEMO_BYTES: usize = 1024;
+/// Maximum amount of blocks an execution can be scheduled ahead of the block that includes it.
+pub const MAX_EXECUTION_DELAY: u64 = 100_000;
+/// Size in bytes of the data carried by each chunk of a deployment split with `Transaction::deployment_chunks`.
+pub const DEPLOYMENT_CHUNK_BYTES: usize = 1_048_576;
+/// Maximum amount of chunks a deployment can be split in.
+pub const MAX_DEPLOYMENT_CHUNKS: u32 = 64;
+/// Gates paid by the first chunk of a deployment for each of its chunks, to stage them until the deployment is
+/// complete. It's paid on top of the fee of the deployment itself, which is paid when it's applied.
+pub const DEPLOYMENT_CHUNK_FEE: u64 = 1_000;
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub enum Transaction {
     Deployment {
@@ -17,13 +48,312 @@ pub enum Transaction {
         program: Box<vm::Program>,
         verifying_keys: vm::VerifyingKeyMap,
         fee: Option<vm::Transition>,
+        /// Address of the account that deployed the program. It isn't signed on its own, so nodes only trust it
+        /// when the deployment is wrapped in an envelope signed by the same account, see `Transaction::signed`.
+        deployer: vm::Address,
+        /// Last block height at which the transaction can be included in the blockchain, if any.
+        expires_at: Option<u64>,
+        /// Chain the transaction is meant for, if any. Nodes reject transactions for other chains, see `with_chain_id`.
+        chain_id: Option<String>,
+        /// Hash of the source code the program was compiled from, if the deployer chose to publish it,
+        /// so anyone with the source can check it matches the deployed program. See `Transaction::hash_source`.
+        source_hash: Option<String>,
     },
     Execution {
         id: String,
         transitions: Vec<vm::Transition>,
+        /// Memos attached to output records by commitment, encrypted for the record owners.
+        memos: Vec<(vm::Field, vm::EncryptedMemo)>,
+        /// Last block height at which the transaction can be included in the blockchain, if any.
+        expires_at: Option<u64>,
+        /// Chain the transaction is meant for, if any. Nodes reject transactions for other chains, see `with_chain_id`.
+        chain_id: Option<String>,
+        /// Block height at which the output records are added to the ledger, if the execution is scheduled.
+        /// The inputs are spent and the fees paid when the transaction is included.
+        execute_at: Option<u64>,
+        /// Hashes of the programs the transitions were proven against, see `Transaction::pinned_programs`.
+        pinned_programs: Vec<(vm::ProgramID, String)>,
+    },
+    /// A governance action, e.g. proposing or voting a change of the chain parameters, signed by a validator.
+    Governance {
+        id: String,
+        action: governance::Action,
+        /// The tendermint public key of the validator submitting the action.
+        validator: tendermint::PublicKey,
+        /// Amount of governance actions the validator submitted before, see `GovernanceStatus::nonces`. Nodes only
+        /// accept the next one, so the same signed action can't be applied twice.
+        nonce: u64,
+        /// Signature of the action and the nonce with the validator key.
+        signature: Vec<u8>,
+        /// Last block height at which the transaction can be included in the blockchain, if any.
+        expires_at: Option<u64>,
+        /// Chain the transaction is meant for, if any. Nodes reject transactions for other chains, see `with_chain_id`.
+        chain_id: Option<String>,
+    },
+    /// Deprecate a deployed program, signed by the account that deployed it.
+    /// Executions of the program are rejected after the grace period.
+    Deprecation {
+        id: String,
+        program_id: vm::ProgramID,
+        /// Amount of blocks during which executions of the program are still accepted.
+        grace_period: u64,
+        /// Address of the account that deployed the program.
+        deployer: vm::Address,
+        /// Signature of the deprecation message with the deployer account.
+        signature: vm::Signature,
+        /// Last block height at which the transaction can be included in the blockchain, if any.
+        expires_at: Option<u64>,
+        /// Chain the transaction is meant for, if any. Nodes reject transactions for other chains, see `with_chain_id`.
+        chain_id: Option<String>,
+    },
+    /// Switch the tendermint consensus key of a validator to a new one, keeping its voting power.
+    KeyRotation {
+        id: String,
+        /// The current tendermint public key of the validator.
+        validator: tendermint::PublicKey,
+        /// The tendermint public key the validator switches to.
+        new_key: tendermint::PublicKey,
+        /// Signature of the rotation message with the current validator key.
+        signature: Vec<u8>,
+        /// Signature of the rotation message with the new key, proving the validator owns it.
+        new_key_signature: Vec<u8>,
+        /// Last block height at which the transaction can be included in the blockchain, if any.
+        expires_at: Option<u64>,
+        /// Chain the transaction is meant for, if any. Nodes reject transactions for other chains, see `with_chain_id`.
+        chain_id: Option<String>,
+    },
+    /// A piece of a serialized deployment too large to fit in a single transaction. Nodes stage the chunks
+    /// until all of them are delivered, and the last one applies the deployment as if it was sent whole.
+    /// See `Transaction::deployment_chunks`.
+    DeploymentChunk {
+        id: String,
+        /// Hex encoded sha256 hash of the serialized deployment, which identifies the chunks that belong to it.
+        deployment_hash: String,
+        index: u32,
+        total: u32,
+        data: Vec<u8>,
+        /// Credits fee transition of the first chunk, paying `DEPLOYMENT_CHUNK_FEE` for each chunk. The rest of
+        /// the chunks have none.
+        fee: Option<vm::Transition>,
+        /// Last block height at which the transaction can be included in the blockchain, if any.
+        expires_at: Option<u64>,
+        /// Chain the transaction is meant for, if any. Nodes reject transactions for other chains, see `with_chain_id`.
+        chain_id: Option<String>,
+    },
+    /// Deployments and executions that are applied in order and together: if any of them is invalid, none is.
+    /// Executions can use the programs deployed by the previous transactions of the batch.
+    Batch {
+        id: String,
+        transactions: Vec<Transaction>,
+        /// Last block height at which the transaction can be included in the blockchain, if any.
+        expires_at: Option<u64>,
+        /// Chain the transaction is meant for, if any. Nodes reject transactions for other chains, see `with_chain_id`.
+        chain_id: Option<String>,
+    },
+    /// Burn credits to be minted to an account of a counterparty chain, committing a packet for it. See `bridge`.
+    BridgeTransfer {
+        id: String,
+        destination_chain: String,
+        /// Account that gets the credits on the destination chain.
+        receiver: vm::Address,
+        /// Credits fee transition that burns the amount sent.
+        burn: vm::Transition,
+        /// Last block height at which the transaction can be included in the blockchain, if any.
+        expires_at: Option<u64>,
+        /// Chain the transaction is meant for, if any. Nodes reject transactions for other chains, see `with_chain_id`.
+        chain_id: Option<String>,
+    },
+    /// Packets sent by a counterparty chain, relayed along with the header that commits them. See `bridge`.
+    BridgeReceive {
+        id: String,
+        /// Protobuf encoded signed header of the counterparty, at the height following the one of the proofs.
+        header: Vec<u8>,
+        /// Protobuf encoded counterparty validator set that signed the header.
+        validators: Vec<u8>,
+        /// The packets, in sequence order, with the proofs that they are committed by the counterparty.
+        packets: Vec<(Packet, merkle::StateProof)>,
+        /// Last block height at which the transaction can be included in the blockchain, if any.
+        expires_at: Option<u64>,
+        /// Chain the transaction is meant for, if any. Nodes reject transactions for other chains, see `with_chain_id`.
+        chain_id: Option<String>,
+    },
+    /// A deployment, execution, batch, deployment chunk or bridge transfer wrapped in an envelope signed by the account
+    /// that sends it, so nodes know who sent it before verifying any proof, and its chain id is covered by a signature.
+    /// The wrapped transaction is applied as if it was sent on its own.
+    /// See `Transaction::signed`.
+    Signed {
+        id: String,
+        transaction: Box<Transaction>,
+        /// Address of the account that sent the transaction.
+        sender: vm::Address,
+        /// Signature of the envelope message with the sender account, see `envelope_message`.
+        signature: vm::Signature,
+        /// Last block height at which the transaction can be included in the blockchain, if any.
+        expires_at: Option<u64>,
+        /// Chain the transaction is meant for, the same one as the wrapped transaction.
+        chain_id: Option<String>,
+    },
+    /// Point a name of the registry, e.g. `token/usdx`, to a program deployed by the owner of its namespace,
+    /// claiming the namespace if it has no owner yet. See `lib::names`.
+    NameRegistration {
+        id: String,
+        /// Name in `namespace/name` form.
+        name: String,
+        program_id: vm::ProgramID,
+        /// Address of the account that owns the namespace and deployed the program.
+        owner: vm::Address,
+        /// Amount of previous registrations of the name. Nodes only accept the next one, so the signed registration
+        /// can't be replayed to point the name back to a program it was moved from.
+        sequence: u64,
+        /// Signature of the registration message with the owner account.
+        signature: vm::Signature,
+        /// Last block height at which the transaction can be included in the blockchain, if any.
+        expires_at: Option<u64>,
+        /// Chain the transaction is meant for, if any. Nodes reject transactions for other chains, see `with_chain_id`.
+        chain_id: Option<String>,
     },
 }
 
+/// Everything needed to generate a transaction except for the account private key, so it can be built on a host
+/// connected to the blockchain (e.g. to fetch the program and choose the records to spend) and then proven on
+/// one that isn't. Note that it includes the input records in plaintext.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub enum UnsignedTransaction {
+    Deployment {
+        /// Source code of the program to deploy.
+        program: String,
+        fee: Option<(u64, vm::Record)>,
+        /// Chain the transaction is built for, if known by the connected host, see `UnsignedTransaction::prove`.
+        #[serde(default)]
+        chain_id: Option<String>,
+    },
+    Execution {
+        program: Box<vm::Program>,
+        function: vm::Identifier,
+        inputs: Vec<vm::UserInputValueType>,
+        fee: Option<(u64, vm::Record)>,
+        /// Chain the transaction is built for, if known by the connected host, see `UnsignedTransaction::prove`.
+        #[serde(default)]
+        chain_id: Option<String>,
+    },
+}
+
+impl UnsignedTransaction {
+    /// Generate the transaction proofs with the given account private key, which should own the records being spent.
+    /// If it has a chain id, the transaction is bound to it with an envelope signed by the same account, see
+    /// `Transaction::for_chain`.
+    pub fn prove(self, private_key: &vm::PrivateKey) -> Result<Transaction> {
+        let (transaction, chain_id) = match self {
+            UnsignedTransaction::Deployment {
+                program,
+                fee,
+                chain_id,
+            } => (
+                Transaction::deployment_from_source(&program, private_key, fee)?,
+                chain_id,
+            ),
+            UnsignedTransaction::Execution {
+                program,
+                function,
+                inputs,
+                fee,
+                chain_id,
+            } => (
+                Transaction::execution(*program, function, &inputs, private_key, fee)?,
+                chain_id,
+            ),
+        };
+        match chain_id {
+            Some(chain_id) => transaction.for_chain(&chain_id, private_key),
+            None => Ok(transaction),
+        }
+    }
+
+    /// Sign the function calls of the execution with the given account private key, leaving the proofs to be
+    /// generated later, see `AuthorizedExecution`. Deployments can only be proven at once, see `prove`. The prover
+    /// can't sign an envelope, so the proven execution is bound to its chain when the account sends it.
+    pub fn authorize(self, private_key: &vm::PrivateKey) -> Result<AuthorizedExecution> {
+        match self {
+            UnsignedTransaction::Deployment { .. } => {
+                bail!("deployments can't be authorized apart from proving them, prove them instead")
+            }
+            UnsignedTransaction::Execution {
+                program,
+                function,
+                inputs,
+                fee,
+                ..
+            } => Transaction::authorize_execution(*program, function, &inputs, private_key, fee),
+        }
+    }
+}
+
+/// Short description of a transaction, e.g. to list the ones waiting in the mempool. See `Transaction::summary`.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct TransactionSummary {
+    pub id: String,
+    /// Kind of transaction, see `Transaction::kind`.
+    pub kind: String,
+    /// Programs deployed, deprecated or run by the transaction, in order of appearance.
+    pub programs: Vec<String>,
+    pub fee: i64,
+    /// Size in bytes, as sent to the nodes.
+    pub size: u64,
+}
+
+/// A public output of one of the transitions of a transaction, see `Transaction::public_outputs`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PublicOutput {
+    pub program: String,
+    pub function: String,
+    /// Position of the output among the ones of the transition.
+    pub index: usize,
+    pub value: String,
+}
+
+/// An execution whose function calls were signed with the account private key but not proven yet, see
+/// `Transaction::authorize_execution`. Proving it doesn't need the key, so it can be delegated to an untrusted
+/// host, which does learn the execution inputs.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct AuthorizedExecution {
+    pub authorizations: Vec<vm::Authorization>,
+}
+
+impl AuthorizedExecution {
+    /// Generate the proofs of the authorized calls, returning the resulting execution transaction.
+    pub fn prove(self) -> Result<Transaction> {
+        let (credits, keys) = load_credits();
+        let mut transitions = Vec::new();
+        let mut pinned_programs: Vec<(vm::ProgramID, String)> = Vec::new();
+        for authorization in self.authorizations {
+            let pin = Transaction::program_pin(&authorization.program);
+            if authorization.program.id() != credits.id() && !pinned_programs.contains(&pin) {
+                pinned_programs.push(pin);
+            }
+            // the credits keys are cached, the ones of other programs are synthesized
+            let proving_key = if authorization.program.id() == credits.id() {
+                keys.map
+                    .get(&authorization.function_name)
+                    .map(|(proving_key, _)| proving_key.clone())
+            } else {
+                None
+            };
+            transitions.extend(vm::prove(authorization, proving_key).map_err(vm::proving_failed)?);
+        }
+
+        Transaction::Execution {
+            id: "not known yet".to_string(),
+            transitions,
+            memos: vec![],
+            expires_at: None,
+            chain_id: None,
+            execute_at: None,
+            pinned_programs,
+        }
+        .set_hashed_id()
+    }
+}
+
 impl Transaction {
     // Used to generate deployment of a new program in path
     pub fn deployment(
@@ -32,10 +362,49 @@ impl Transaction {
         fee: Option<(u64, vm::Record)>,
     ) -> Result<Self> {
         let program_string = fs::read_to_string(path)?;
+        Self::deployment_from_source(&program_string, private_key, fee)
+    }
+
+    // Used to generate deployment of a new program from its source code
+    pub fn deployment_from_source(
+        program_string: &str,
+        private_key: &vm::PrivateKey,
+        fee: Option<(u64, vm::Record)>,
+    ) -> Result<Self> {
+        Self::deployment_with_progress(program_string, private_key, fee, |_, _| {})
+    }
+
+    /// Same as `deployment_from_source`, calling `on_progress` with the amount of functions whose keys were
+    /// synthesized and the total amount of functions, see `vm::build_program_with_progress`.
+    pub fn deployment_with_progress(
+        program_string: &str,
+        private_key: &vm::PrivateKey,
+        fee: Option<(u64, vm::Record)>,
+        on_progress: impl Fn(usize, usize) + Sync,
+    ) -> Result<Self> {
+        Self::deployment_with_rng(
+            program_string,
+            private_key,
+            fee,
+            on_progress,
+            &mut rand::thread_rng(),
+        )
+    }
+
+    /// Same as `deployment_with_progress`, taking the randomness of the keys and the fee transition from the given
+    /// rng, so a seeded one always builds the same transaction.
+    pub fn deployment_with_rng<R: Rng + CryptoRng>(
+        program_string: &str,
+        private_key: &vm::PrivateKey,
+        fee: Option<(u64, vm::Record)>,
+        on_progress: impl Fn(usize, usize) + Sync,
+        rng: &mut R,
+    ) -> Result<Self> {
         debug!("Deploying program {}", program_string);
 
         // generate program keys (proving and verifying) and keep the verifying one for the deploy
-        let (program, program_build) = vm::build_program(&program_string)?;
+        let (program, program_build) = vm::build_program_with_rng(program_string, on_progress, rng)
+            .map_err(vm::proving_failed)?;
 
         let verifying_keys = program_build
             .map
@@ -43,15 +412,21 @@ impl Transaction {
             .map(|(i, keys)| (i, keys.1))
             .collect();
 
-        let fee = Self::execute_fee(private_key, fee, 0)?;
+        let fee = Self::execute_fee(private_key, fee, 0, rng)?;
+        let view_key = vm::ViewKey::try_from(private_key)?;
+        let deployer = vm::Address::try_from(&view_key)?;
 
         Transaction::Deployment {
             id: "not known yet".to_string(),
             fee,
+            deployer,
             program: Box::new(program),
             verifying_keys: VerifyingKeyMap {
                 map: verifying_keys,
             },
+            expires_at: None,
+            chain_id: None,
+            source_hash: None,
         }
         .set_hashed_id()
     }
@@ -64,49 +439,835 @@ impl Transaction {
         private_key: &vm::PrivateKey,
         requested_fee: Option<(u64, vm::Record)>,
     ) -> Result<Self> {
-        let mut transitions = vm::execution(program, function_name, inputs, private_key, None)?;
+        Self::execution_with_rng(
+            program,
+            function_name,
+            inputs,
+            private_key,
+            requested_fee,
+            &mut rand::thread_rng(),
+        )
+    }
+
+    /// Same as `execution`, taking the randomness of the transitions from the given rng, so a seeded one always
+    /// builds the same transaction. Meant for test fixtures and replays: anyone who knows the seed can recompute
+    /// the randomizers of the output records, so it shouldn't be used to move real credits.
+    pub fn execution_with_rng<R: Rng + CryptoRng>(
+        program: vm::Program,
+        function_name: vm::Identifier,
+        inputs: &[vm::UserInputValueType],
+        private_key: &vm::PrivateKey,
+        requested_fee: Option<(u64, vm::Record)>,
+        rng: &mut R,
+    ) -> Result<Self> {
+        let pinned_programs = vec![Self::program_pin(&program)];
+        let mut transitions =
+            vm::execution_with_rng(program, function_name, inputs, private_key, None, rng)
+                .map_err(vm::proving_failed)?;
 
         // some amount of fees may be implicit if the execution drops credits. in that case, those credits are
         // subtracted from the fees that were requested to be paid.
         let implicit_fees = transitions.iter().map(|transition| transition.fee()).sum();
-        if let Some(transition) = Self::execute_fee(private_key, requested_fee, implicit_fees)? {
+        if let Some(transition) = Self::execute_fee(private_key, requested_fee, implicit_fees, rng)?
+        {
             transitions.push(transition);
         }
 
         Self::Execution {
             id: "not known yet".to_string(),
             transitions,
+            memos: vec![],
+            expires_at: None,
+            chain_id: None,
+            execute_at: None,
+            pinned_programs,
         }
         .set_hashed_id()
     }
 
+    /// Sign the calls of an execution with the given private key, without generating their proofs, so that can be
+    /// done later by `AuthorizedExecution::prove` on a host that doesn't hold the key. Since the credits an execution
+    /// drops as implicit fee are only known once it's proven, the requested fee is authorized in full.
+    pub fn authorize_execution(
+        program: vm::Program,
+        function_name: vm::Identifier,
+        inputs: &[vm::UserInputValueType],
+        private_key: &vm::PrivateKey,
+        requested_fee: Option<(u64, vm::Record)>,
+    ) -> Result<AuthorizedExecution> {
+        let mut authorizations = vec![vm::authorize(program, function_name, inputs, private_key)
+            .map_err(vm::proving_failed)?];
+        if let Some((gates, record)) = requested_fee {
+            let credits = vm::generate_program(crate::CREDITS_SOURCE)?;
+            authorizations.push(
+                vm::authorize(
+                    credits,
+                    vm::Identifier::from_str("fee")?,
+                    &Self::fee_inputs(record, gates)?,
+                    private_key,
+                )
+                .map_err(vm::proving_failed)?,
+            );
+        }
+        Ok(AuthorizedExecution { authorizations })
+    }
+
     pub fn credits_execution(
         function_name: vm::Identifier,
         inputs: &[vm::UserInputValueType],
         private_key: &vm::PrivateKey,
         requested_fee: Option<(u64, vm::Record)>,
+    ) -> Result<Self> {
+        Self::credits_execution_with_rng(
+            function_name,
+            inputs,
+            private_key,
+            requested_fee,
+            &mut rand::thread_rng(),
+        )
+    }
+
+    /// Same as `credits_execution`, taking the randomness of the transitions from the given rng,
+    /// see `execution_with_rng`.
+    pub fn credits_execution_with_rng<R: Rng + CryptoRng>(
+        function_name: vm::Identifier,
+        inputs: &[vm::UserInputValueType],
+        private_key: &vm::PrivateKey,
+        requested_fee: Option<(u64, vm::Record)>,
+        rng: &mut R,
     ) -> Result<Self> {
         let mut transitions =
-            Self::execute_credits(&function_name.to_string(), inputs, private_key)?;
+            Self::execute_credits(&function_name.to_string(), inputs, private_key, rng)?;
 
         // some amount of fees may be implicit if the execution drops credits. in that case, those credits are
         // subtracted from the fees that were requested to be paid.
         let implicit_fees = transitions.iter().map(|transition| transition.fee()).sum();
-        if let Some(transition) = Self::execute_fee(private_key, requested_fee, implicit_fees)? {
+        if let Some(transition) = Self::execute_fee(private_key, requested_fee, implicit_fees, rng)?
+        {
             transitions.push(transition);
         }
 
         Self::Execution {
             id: "not known yet".to_string(),
             transitions,
+            memos: vec![],
+            expires_at: None,
+            chain_id: None,
+            execute_at: None,
+            pinned_programs: vec![],
+        }
+        .set_hashed_id()
+    }
+
+    /// Used to pay several recipients in a single execution. The given records are combined into one, and each payout
+    /// is a transfer that spends the change of the previous one. The fee, if any, is paid from the remaining change.
+    /// Returns the transaction along with the final change record, which can be used to continue paying in another batch.
+    pub fn transfer_many(
+        records: Vec<vm::Record>,
+        payouts: &[(vm::Address, u64)],
+        fee: u64,
+        private_key: &vm::PrivateKey,
+    ) -> Result<(Self, vm::Record)> {
+        let view_key = vm::ViewKey::try_from(private_key)?;
+        let mut records = records.into_iter();
+        let mut change = records
+            .next()
+            .ok_or_else(|| anyhow!("at least one record is needed to pay"))?;
+
+        let mut transitions = Vec::new();
+        let mut run = |function: &str, inputs: &[vm::UserInputValueType]| -> Result<vm::Record> {
+            let transition =
+                Self::execute_credits(function, inputs, private_key, &mut rand::thread_rng())?
+                    .pop()
+                    .ok_or_else(|| anyhow!("credits {function} produced no transitions"))?;
+            let change = Self::last_output_record(&transition, &view_key)?;
+            transitions.push(transition);
+            Ok(change)
+        };
+
+        for record in records {
+            change = run(
+                "combine",
+                &[
+                    vm::UserInputValueType::Record(change),
+                    vm::UserInputValueType::Record(record),
+                ],
+            )?;
+        }
+        for (address, amount) in payouts {
+            change = run(
+                "transfer",
+                &[
+                    vm::UserInputValueType::Record(change),
+                    vm::UserInputValueType::from_str(&address.to_string())?,
+                    vm::u64_to_value(*amount),
+                ],
+            )?;
+        }
+        if fee > 0 {
+            change = run(
+                "fee",
+                &[
+                    vm::UserInputValueType::Record(change),
+                    vm::u64_to_value(fee),
+                ],
+            )?;
+        }
+
+        let transaction = Self::Execution {
+            id: "not known yet".to_string(),
+            transitions,
+            memos: vec![],
+            expires_at: None,
+            chain_id: None,
+            execute_at: None,
+            pinned_programs: vec![],
+        }
+        .set_hashed_id()?;
+        Ok((transaction, change))
+    }
+
+    /// Used to join the given records into a single one with credits combine executions,
+    /// paying the given fee out of the result. Returns the transaction along with the joined record.
+    pub fn consolidate(
+        records: Vec<vm::Record>,
+        fee: u64,
+        private_key: &vm::PrivateKey,
+    ) -> Result<(Self, vm::Record)> {
+        Self::transfer_many(records, &[], fee, private_key)
+    }
+
+    /// Used to break a record into smaller ones with credits split executions: each of the given amounts is split
+    /// from the change of the previous split, and the fee, if any, is paid from the last change.
+    /// Returns the transaction along with the final change record, which holds the gates left.
+    pub fn split_many(
+        record: vm::Record,
+        amounts: &[u64],
+        fee: u64,
+        private_key: &vm::PrivateKey,
+    ) -> Result<(Self, vm::Record)> {
+        let view_key = vm::ViewKey::try_from(private_key)?;
+        let mut transitions = Vec::new();
+        let mut run = |function: &str, inputs: &[vm::UserInputValueType]| -> Result<vm::Record> {
+            let transition =
+                Self::execute_credits(function, inputs, private_key, &mut rand::thread_rng())?
+                    .pop()
+                    .ok_or_else(|| anyhow!("credits {function} produced no transitions"))?;
+            let change = Self::last_output_record(&transition, &view_key)?;
+            transitions.push(transition);
+            Ok(change)
+        };
+
+        let mut change = record;
+        for amount in amounts {
+            change = run(
+                "split",
+                &[
+                    vm::UserInputValueType::Record(change),
+                    vm::u64_to_value(*amount),
+                ],
+            )?;
+        }
+        if fee > 0 {
+            change = run(
+                "fee",
+                &[
+                    vm::UserInputValueType::Record(change),
+                    vm::u64_to_value(fee),
+                ],
+            )?;
+        }
+
+        let transaction = Self::Execution {
+            id: "not known yet".to_string(),
+            transitions,
+            memos: vec![],
+            expires_at: None,
+            chain_id: None,
+            execute_at: None,
+            pinned_programs: vec![],
+        }
+        .set_hashed_id()?;
+        Ok((transaction, change))
+    }
+
+    /// Used to submit a governance action signed with the given validator key, for the given chain if any. The nonce
+    /// is the amount of actions the validator submitted before, see `GovernanceStatus::nonces`.
+    pub fn governance(
+        action: governance::Action,
+        nonce: u64,
+        validator_key: &ValidatorKey,
+        chain_id: Option<String>,
+    ) -> Result<Self> {
+        let signature = validator_key.sign(&Self::signed_message(
+            bincode::serialize(&(&action, nonce))?,
+            chain_id.as_deref(),
+        ));
+
+        Self::Governance {
+            id: "not known yet".to_string(),
+            action,
+            validator: validator_key.pub_key,
+            nonce,
+            signature,
+            expires_at: None,
+            chain_id,
+        }
+        .set_hashed_id()
+    }
+
+    /// Used to deprecate a program that was deployed by the account of the given private key,
+    /// on the given chain if any.
+    pub fn deprecation(
+        program_id: vm::ProgramID,
+        grace_period: u64,
+        private_key: &vm::PrivateKey,
+        chain_id: Option<String>,
+    ) -> Result<Self> {
+        let view_key = vm::ViewKey::try_from(private_key)?;
+        let deployer = vm::Address::try_from(&view_key)?;
+        let signature = vm::sign(
+            private_key,
+            &Self::signed_message(
+                Self::deprecation_message(&program_id, grace_period),
+                chain_id.as_deref(),
+            ),
+        )?;
+
+        Self::Deprecation {
+            id: "not known yet".to_string(),
+            program_id,
+            grace_period,
+            deployer,
+            signature,
+            expires_at: None,
+            chain_id,
+        }
+        .set_hashed_id()
+    }
+
+    /// Used to point a name of the registry to a program deployed by the account of the given private key,
+    /// on the given chain if any. The sequence is the amount of times the name was registered before, see
+    /// `query::NameResponse`.
+    pub fn name_registration(
+        name: &str,
+        program_id: vm::ProgramID,
+        sequence: u64,
+        private_key: &vm::PrivateKey,
+        chain_id: Option<String>,
+    ) -> Result<Self> {
+        let view_key = vm::ViewKey::try_from(private_key)?;
+        let owner = vm::Address::try_from(&view_key)?;
+        let signature = vm::sign(
+            private_key,
+            &Self::signed_message(
+                Self::name_registration_message(name, &program_id, sequence),
+                chain_id.as_deref(),
+            ),
+        )?;
+
+        Self::NameRegistration {
+            id: "not known yet".to_string(),
+            name: name.to_string(),
+            program_id,
+            owner,
+            sequence,
+            signature,
+            expires_at: None,
+            chain_id,
+        }
+        .set_hashed_id()
+    }
+
+    /// Wrap a deployment, execution, batch, deployment chunk or bridge transfer in an envelope signed by the account
+    /// of the given private key, valid until the given height if any. The envelope is bound to the chain of the
+    /// transaction, which should be set before, see `with_chain_id` and `for_chain`.
+    pub fn signed(
+        transaction: Transaction,
+        private_key: &vm::PrivateKey,
+        expires_at: Option<u64>,
+    ) -> Result<Self> {
+        let view_key = vm::ViewKey::try_from(private_key)?;
+        let sender = vm::Address::try_from(&view_key)?;
+        let chain_id = transaction.chain_id().map(str::to_string);
+        let signature = vm::sign(
+            private_key,
+            &Self::signed_message(
+                Self::envelope_message(&transaction, expires_at),
+                chain_id.as_deref(),
+            ),
+        )?;
+
+        Self::Signed {
+            id: "not known yet".to_string(),
+            transaction: Box::new(transaction),
+            sender,
+            signature,
+            expires_at,
+            chain_id,
+        }
+        .set_hashed_id()
+    }
+
+    /// Used to rotate the consensus key of a validator, signed with both its current and its new key,
+    /// on the given chain if any.
+    pub fn key_rotation(
+        validator_key: &ValidatorKey,
+        new_key: &ValidatorKey,
+        chain_id: Option<String>,
+    ) -> Result<Self> {
+        let message = Self::signed_message(
+            Self::key_rotation_message(&validator_key.pub_key, &new_key.pub_key),
+            chain_id.as_deref(),
+        );
+
+        Self::KeyRotation {
+            id: "not known yet".to_string(),
+            validator: validator_key.pub_key,
+            new_key: new_key.pub_key,
+            signature: validator_key.sign(&message),
+            new_key_signature: new_key.sign(&message),
+            expires_at: None,
+            chain_id,
+        }
+        .set_hashed_id()
+    }
+
+    /// Burn the given amount of gates out of the record, to be minted to the receiver on the destination chain.
+    pub fn bridge_transfer(
+        destination_chain: &str,
+        receiver: vm::Address,
+        amount: u64,
+        record: vm::Record,
+        private_key: &vm::PrivateKey,
+    ) -> Result<Self> {
+        let inputs = Self::fee_inputs(record, amount)?;
+        let burn = Self::execute_credits("fee", &inputs, private_key, &mut rand::thread_rng())?
+            .pop()
+            .ok_or_else(|| anyhow!("the credits fee function produced no transition"))?;
+
+        Self::BridgeTransfer {
+            id: "not known yet".to_string(),
+            destination_chain: destination_chain.to_string(),
+            receiver,
+            burn,
+            expires_at: None,
+            chain_id: None,
+        }
+        .set_hashed_id()
+    }
+
+    /// Relay packets of a counterparty chain, given its header at the height following the one of the proofs
+    /// and the validator set that signed it.
+    pub fn bridge_receive(
+        header: &tendermint::block::signed_header::SignedHeader,
+        validators: &tendermint::validator::Set,
+        packets: Vec<(Packet, merkle::StateProof)>,
+    ) -> Result<Self> {
+        Self::BridgeReceive {
+            id: "not known yet".to_string(),
+            header: bridge::encode_header(header)?,
+            validators: bridge::encode_validators(validators)?,
+            packets,
+            expires_at: None,
+            chain_id: None,
         }
         .set_hashed_id()
     }
 
     pub fn id(&self) -> &str {
         match self {
-            Transaction::Deployment { id, .. } => id,
-            Transaction::Execution { id, .. } => id,
+            Transaction::Deployment { id, .. } => id,
+            Transaction::Execution { id, .. } => id,
+            Transaction::Governance { id, .. } => id,
+            Transaction::Deprecation { id, .. } => id,
+            Transaction::NameRegistration { id, .. } => id,
+            Transaction::KeyRotation { id, .. } => id,
+            Transaction::Batch { id, .. } => id,
+            Transaction::DeploymentChunk { id, .. } => id,
+            Transaction::BridgeTransfer { id, .. } => id,
+            Transaction::BridgeReceive { id, .. } => id,
+            Transaction::Signed { id, .. } => id,
+        }
+    }
+
+    /// Split a deployment, or a deployment wrapped in a signed envelope, in chunk transactions that fit within the
+    /// node size limit, to be delivered in order. The chunks are bound to the same chain as the deployment, wrapped
+    /// in envelopes signed by the account of the private key when it has a chain id, see `binds_chain_id`.
+    /// The first chunk pays the staging of all of them, `DEPLOYMENT_CHUNK_FEE` per chunk, out of the given record
+    /// of the account of the private key, which can't be the one that pays the fee of the deployment.
+    pub fn deployment_chunks(
+        deployment: &Transaction,
+        private_key: &vm::PrivateKey,
+        fee_record: vm::Record,
+    ) -> Result<Vec<Transaction>> {
+        ensure!(
+            Self::is_deployment(deployment),
+            "only deployments can be split in chunks"
+        );
+        let bytes = bincode::serialize(deployment)?;
+        let deployment_hash = hex::encode(Sha256::digest(&bytes));
+        let chunks: Vec<&[u8]> = bytes.chunks(DEPLOYMENT_CHUNK_BYTES).collect();
+        let total = chunks.len() as u32;
+        ensure!(
+            total <= MAX_DEPLOYMENT_CHUNKS,
+            "the deployment needs {total} chunks, the limit is {MAX_DEPLOYMENT_CHUNKS}"
+        );
+
+        let mut fee = Self::execute_fee(
+            private_key,
+            Some((total as u64 * DEPLOYMENT_CHUNK_FEE, fee_record)),
+            0,
+            &mut rand::thread_rng(),
+        )?;
+
+        chunks
+            .into_iter()
+            .enumerate()
+            .map(|(index, data)| {
+                let chunk = Self::DeploymentChunk {
+                    id: "not known yet".to_string(),
+                    deployment_hash: deployment_hash.clone(),
+                    index: index as u32,
+                    total,
+                    data: data.to_vec(),
+                    fee: fee.take(),
+                    expires_at: None,
+                    chain_id: deployment.chain_id().map(str::to_string),
+                }
+                .set_hashed_id()?;
+                match deployment.chain_id() {
+                    Some(_) => Self::signed(chunk, private_key, None),
+                    None => Ok(chunk),
+                }
+            })
+            .collect()
+    }
+
+    /// The gates paid by the first chunk when splitting the given deployment with `deployment_chunks`.
+    pub fn deployment_chunks_fee(deployment: &Transaction) -> Result<u64> {
+        let size = bincode::serialized_size(deployment)? as usize;
+        let total = (size + DEPLOYMENT_CHUNK_BYTES - 1) / DEPLOYMENT_CHUNK_BYTES;
+        Ok(total as u64 * DEPLOYMENT_CHUNK_FEE)
+    }
+
+    /// Put back together the deployment split with `deployment_chunks`, given the data of all its chunks
+    /// sorted by index.
+    pub fn from_chunks(deployment_hash: &str, chunks: &[Vec<u8>]) -> Result<Transaction> {
+        let bytes = chunks.concat();
+        ensure!(
+            hex::encode(Sha256::digest(&bytes)) == deployment_hash,
+            "the chunks don't match the deployment hash {deployment_hash}"
+        );
+        let deployment: Transaction = bincode::deserialize(&bytes)?;
+        ensure!(
+            Self::is_deployment(&deployment),
+            "the chunks of {deployment_hash} don't contain a deployment"
+        );
+        Ok(deployment)
+    }
+
+    fn is_deployment(transaction: &Transaction) -> bool {
+        match transaction {
+            Transaction::Deployment { .. } => true,
+            Transaction::Signed { transaction, .. } => {
+                matches!(**transaction, Transaction::Deployment { .. })
+            }
+            _ => false,
+        }
+    }
+
+    /// Bundle signed deployments and executions in a batch, so they are applied together or not at all.
+    pub fn batch(transactions: Vec<Transaction>) -> Result<Self> {
+        Self::Batch {
+            id: "not known yet".to_string(),
+            transactions,
+            expires_at: None,
+            chain_id: None,
+        }
+        .set_hashed_id()
+    }
+
+    /// Return the transactions contained in a batch, or the transaction itself for other kinds of transactions.
+    /// Signed envelopes return the parts of the transaction they wrap.
+    pub fn parts(&self) -> Vec<&Transaction> {
+        match self {
+            Transaction::Batch { transactions, .. } => transactions.iter().collect(),
+            Transaction::Signed { transaction, .. } => transaction.parts(),
+            _ => vec![self],
+        }
+    }
+
+    /// Return who submitted the transaction, for the kinds of transactions that reveal it: the deployer account of
+    /// deployments and deprecations, the validator key of governance and key rotation transactions, and the signer of
+    /// envelopes. Executions don't reveal their sender unless they are signed, so they return None.
+    pub fn sender(&self) -> Option<String> {
+        match self {
+            Transaction::Signed { sender, .. } => Some(sender.to_string()),
+            Transaction::Deployment { deployer, .. }
+            | Transaction::Deprecation { deployer, .. } => Some(deployer.to_string()),
+            Transaction::NameRegistration { owner, .. } => Some(owner.to_string()),
+            Transaction::Governance { validator, .. }
+            | Transaction::KeyRotation { validator, .. } => {
+                Some(hex::encode_upper(validator::pub_key_to_address(validator)))
+            }
+            Transaction::Execution { .. }
+            | Transaction::DeploymentChunk { .. }
+            | Transaction::BridgeTransfer { .. }
+            | Transaction::BridgeReceive { .. } => None,
+            Transaction::Batch { transactions, .. } => {
+                transactions.iter().find_map(Transaction::sender)
+            }
+        }
+    }
+
+    /// Return the account that signed the envelope of the transaction, if it's wrapped in one. Unlike the deployer
+    /// field of deployments, which anyone can fill in, it's bound to the transaction by the envelope signature.
+    pub fn envelope_sender(&self) -> Option<&vm::Address> {
+        match self {
+            Transaction::Signed { sender, .. } => Some(sender),
+            _ => None,
+        }
+    }
+
+    /// Return the chain the transaction is meant for, if any.
+    pub fn chain_id(&self) -> Option<&str> {
+        match self {
+            Transaction::Deployment { chain_id, .. }
+            | Transaction::Execution { chain_id, .. }
+            | Transaction::Governance { chain_id, .. }
+            | Transaction::Deprecation { chain_id, .. }
+            | Transaction::NameRegistration { chain_id, .. }
+            | Transaction::KeyRotation { chain_id, .. }
+            | Transaction::Batch { chain_id, .. }
+            | Transaction::DeploymentChunk { chain_id, .. }
+            | Transaction::BridgeTransfer { chain_id, .. }
+            | Transaction::BridgeReceive { chain_id, .. }
+            | Transaction::Signed { chain_id, .. } => chain_id.as_deref(),
+        }
+    }
+
+    /// Whether the chain id of the transaction is covered by a signature, so it can't be moved to another chain by
+    /// rewriting it. Deployments, executions, batches, chunks and bridge transfers only have it in their id, which
+    /// anyone can recompute, so nodes of a chain with a chain id only accept them wrapped in a signed envelope.
+    /// Bridge receives are bound to their destination by the packets, which are committed by the source chain.
+    pub fn binds_chain_id(&self) -> bool {
+        match self {
+            Transaction::Governance { .. }
+            | Transaction::Deprecation { .. }
+            | Transaction::NameRegistration { .. }
+            | Transaction::KeyRotation { .. }
+            | Transaction::BridgeReceive { .. }
+            | Transaction::Signed { .. } => true,
+            Transaction::Deployment { .. }
+            | Transaction::Execution { .. }
+            | Transaction::Batch { .. }
+            | Transaction::DeploymentChunk { .. }
+            | Transaction::BridgeTransfer { .. } => false,
+        }
+    }
+
+    /// Set the chain the transaction is meant for and wrap it in an envelope signed by the account of the given
+    /// private key, so the chain id is covered by its signature. See `binds_chain_id`.
+    pub fn for_chain(self, chain_id: &str, private_key: &vm::PrivateKey) -> Result<Self> {
+        Self::signed(self.with_chain_id(chain_id)?, private_key, None)
+    }
+
+    /// Set the chain the transaction is meant for, updating its id accordingly. Signed transactions
+    /// (governance, deprecations and key rotations) include the chain id in their signature, so it has to be
+    /// passed when they are created instead. For the rest the chain id is only bound by an envelope, see `for_chain`.
+    pub fn with_chain_id(mut self, new_chain_id: &str) -> Result<Self> {
+        match self {
+            Transaction::Deployment {
+                ref mut chain_id, ..
+            }
+            | Transaction::Execution {
+                ref mut chain_id, ..
+            }
+            | Transaction::Batch {
+                ref mut chain_id, ..
+            }
+            | Transaction::DeploymentChunk {
+                ref mut chain_id, ..
+            }
+            | Transaction::BridgeTransfer {
+                ref mut chain_id, ..
+            }
+            | Transaction::BridgeReceive {
+                ref mut chain_id, ..
+            } => *chain_id = Some(new_chain_id.to_string()),
+            _ => bail!("the chain id of signed transactions is set when they are signed"),
+        };
+        self.set_hashed_id()
+    }
+
+    /// Return the last block height at which this transaction can be included in the blockchain, if any.
+    pub fn expires_at(&self) -> Option<u64> {
+        match self {
+            Transaction::Deployment { expires_at, .. } => *expires_at,
+            Transaction::Execution { expires_at, .. } => *expires_at,
+            Transaction::Governance { expires_at, .. } => *expires_at,
+            Transaction::Deprecation { expires_at, .. } => *expires_at,
+            Transaction::NameRegistration { expires_at, .. } => *expires_at,
+            Transaction::KeyRotation { expires_at, .. } => *expires_at,
+            Transaction::Batch { expires_at, .. } => *expires_at,
+            Transaction::DeploymentChunk { expires_at, .. } => *expires_at,
+            Transaction::BridgeTransfer { expires_at, .. } => *expires_at,
+            Transaction::BridgeReceive { expires_at, .. } => *expires_at,
+            Transaction::Signed { expires_at, .. } => *expires_at,
+        }
+    }
+
+    /// Set the last block height at which this transaction can be included in the blockchain,
+    /// updating its id accordingly.
+    /// The expiration is only covered by the id, not by the proofs nor the signatures of the transaction, so whoever
+    /// relays it can change or remove it: it's advisory. Wrap the transaction in an envelope signed by the sender,
+    /// see `signed`, for an expiration that can't be tampered with.
+    pub fn with_expiration(mut self, height: u64) -> Result<Self> {
+        match self {
+            Transaction::Deployment {
+                ref mut expires_at, ..
+            } => *expires_at = Some(height),
+            Transaction::Execution {
+                ref mut expires_at, ..
+            } => *expires_at = Some(height),
+            Transaction::Governance {
+                ref mut expires_at, ..
+            } => *expires_at = Some(height),
+            Transaction::Deprecation {
+                ref mut expires_at, ..
+            } => *expires_at = Some(height),
+            Transaction::NameRegistration {
+                ref mut expires_at, ..
+            } => *expires_at = Some(height),
+            Transaction::KeyRotation {
+                ref mut expires_at, ..
+            } => *expires_at = Some(height),
+            Transaction::Batch {
+                ref mut expires_at, ..
+            } => *expires_at = Some(height),
+            Transaction::DeploymentChunk {
+                ref mut expires_at, ..
+            } => *expires_at = Some(height),
+            Transaction::BridgeTransfer {
+                ref mut expires_at, ..
+            } => *expires_at = Some(height),
+            Transaction::BridgeReceive {
+                ref mut expires_at, ..
+            } => *expires_at = Some(height),
+            Transaction::Signed { .. } => {
+                bail!("the expiration of signed envelopes is set when they are signed")
+            }
+        };
+        self.set_hashed_id()
+    }
+
+    /// Hash of a program source code, as attached to deployments with `with_source_hash`.
+    pub fn hash_source(source: &str) -> String {
+        hex::encode(Sha256::digest(source.as_bytes()))
+    }
+
+    /// Attach the hash of the given program source code to a deployment, updating its id accordingly.
+    /// The source should be the one the deployed program was compiled from.
+    pub fn with_source_hash(mut self, source: &str) -> Result<Self> {
+        match self {
+            Transaction::Deployment {
+                ref program,
+                ref mut source_hash,
+                ..
+            } => {
+                ensure!(
+                    merkle::program_hash(program)
+                        == merkle::program_hash(&vm::generate_program(source)?),
+                    "the source code doesn't match the deployed program"
+                );
+                *source_hash = Some(Self::hash_source(source));
+            }
+            _ => bail!("only deployments can have a source hash"),
+        };
+        self.set_hashed_id()
+    }
+
+    /// Return the block height an execution is scheduled for, if any.
+    pub fn execute_at(&self) -> Option<u64> {
+        match self {
+            Transaction::Execution { execute_at, .. } => *execute_at,
+            Transaction::Signed { transaction, .. } => transaction.execute_at(),
+            _ => None,
+        }
+    }
+
+    /// Return the programs an execution pins, by id along with the hex encoded `merkle::program_hash` of the
+    /// version its transitions were proven against. Nodes reject the execution if the deployed program with that id
+    /// doesn't match, e.g. because it was deployed again with other instructions. Executions built with
+    /// `Transaction::execution` and `AuthorizedExecution::prove` pin the programs they run, except credits.aleo.
+    /// The pins of an unsigned execution are advisory, since a relayer can remove them and recompute the id;
+    /// within a `Signed` envelope they are covered by the sender signature.
+    pub fn pinned_programs(&self) -> &[(vm::ProgramID, String)] {
+        match self {
+            Transaction::Execution {
+                pinned_programs, ..
+            } => pinned_programs,
+            Transaction::Signed { transaction, .. } => transaction.pinned_programs(),
+            _ => &[],
+        }
+    }
+
+    /// The pin of the given program, as kept in `pinned_programs`.
+    pub fn program_pin(program: &vm::Program) -> (vm::ProgramID, String) {
+        (*program.id(), hex::encode(merkle::program_hash(program)))
+    }
+
+    /// Schedule the execution so its output records are added at the given block height instead of when the
+    /// transaction is included, updating its id accordingly.
+    /// Like the expiration, the height isn't covered by the proofs, so a relayer can reschedule or unschedule an
+    /// unsigned execution. Only an envelope signed by the sender, see `signed`, makes it binding.
+    pub fn with_execution_height(mut self, height: u64) -> Result<Self> {
+        match self {
+            Transaction::Execution {
+                ref mut execute_at, ..
+            } => *execute_at = Some(height),
+            _ => bail!("only executions can be scheduled"),
+        };
+        self.set_hashed_id()
+    }
+
+    /// Attach a memo to the output record with the given commitment, encrypted so it can only be read with the
+    /// view key of the given address (which should be the record owner), updating the transaction id accordingly.
+    /// The proofs don't commit to memos: unless the execution is wrapped in a signed envelope afterwards, whoever
+    /// relays it can drop or replace them, so recipients shouldn't rely on the memo of an unsigned execution.
+    pub fn with_memo(
+        mut self,
+        commitment: vm::Field,
+        owner: &vm::Address,
+        memo: &str,
+    ) -> Result<Self> {
+        ensure!(
+            memo.len() <= MAX_MEMO_BYTES,
+            "memo is longer than {MAX_MEMO_BYTES} bytes"
+        );
+        ensure!(
+            self.output_records()
+                .iter()
+                .any(|(output, _)| *output == commitment),
+            "transaction has no output record {commitment}"
+        );
+
+        match self {
+            Transaction::Execution { ref mut memos, .. } => {
+                memos.push((commitment, vm::encrypt_memo(owner, memo)?))
+            }
+            _ => bail!("memos can only be attached to executions"),
+        };
+        self.set_hashed_id()
+    }
+
+    /// Return the encrypted memos attached to output records, by record commitment.
+    pub fn memos(&self) -> &[(vm::Field, vm::EncryptedMemo)] {
+        match self {
+            Transaction::Execution { memos, .. } => memos,
+            Transaction::Signed { transaction, .. } => transaction.memos(),
+            _ => &[],
         }
     }
 
@@ -128,6 +1289,64 @@ impl Transaction {
             .collect();
     }
 
+    /// Same as `output_records`, along with the id of the program of the transition that created each record.
+    pub fn output_records_by_program(&self) -> Vec<(String, vm::Field, vm::EncryptedRecord)> {
+        #[cfg(feature = "snarkvm_backend")]
+        return self
+            .transitions()
+            .iter()
+            .flat_map(|transition| {
+                let program_id = transition.program_id().to_string();
+                transition
+                    .output_records()
+                    .map(move |(commitment, record)| {
+                        (program_id.clone(), *commitment, record.clone())
+                    })
+            })
+            .collect();
+
+        #[cfg(feature = "lambdavm_backend")]
+        return self
+            .transitions()
+            .iter()
+            .flat_map(|transition| {
+                let program_id = transition.program_id().to_string();
+                transition
+                    .output_records()
+                    .into_iter()
+                    .map(move |(commitment, record)| (program_id.clone(), commitment, record))
+            })
+            .collect();
+    }
+
+    /// Same as `record_serial_numbers`, along with the id of the program of the transition that consumed each record.
+    pub fn record_serial_numbers_by_program(&self) -> Vec<(String, vm::Field)> {
+        #[cfg(feature = "snarkvm_backend")]
+        return self
+            .transitions()
+            .iter()
+            .flat_map(|transition| {
+                let program_id = transition.program_id().to_string();
+                transition
+                    .serial_numbers()
+                    .map(move |serial_number| (program_id.clone(), *serial_number))
+            })
+            .collect();
+
+        #[cfg(feature = "lambdavm_backend")]
+        return self
+            .transitions()
+            .iter()
+            .flat_map(|transition| {
+                let program_id = transition.program_id().to_string();
+                transition
+                    .serial_numbers()
+                    .into_iter()
+                    .map(move |serial_number| (program_id.clone(), serial_number))
+            })
+            .collect();
+    }
+
     /// If the transaction is an execution, return the list of input record serial numbers
     pub fn record_serial_numbers(&self) -> Vec<vm::Field> {
         #[cfg(feature = "snarkvm_backend")]
@@ -155,7 +1374,155 @@ impl Transaction {
                 }
             }
             Transaction::Execution { transitions, .. } => transitions.clone(),
+            Transaction::Batch { transactions, .. } => transactions
+                .iter()
+                .flat_map(Transaction::transitions)
+                .collect(),
+            Transaction::BridgeTransfer { burn, .. } => vec![burn.clone()],
+            Transaction::DeploymentChunk { fee, .. } => fee.iter().cloned().collect(),
+            Transaction::Signed { transaction, .. } => transaction.transitions(),
+            Transaction::Governance { .. }
+            | Transaction::Deprecation { .. }
+            | Transaction::NameRegistration { .. }
+            | Transaction::KeyRotation { .. }
+            | Transaction::BridgeReceive { .. } => vec![],
+        }
+    }
+
+    /// Return a deterministic estimate of the work needed to verify this transaction, used to bound
+    /// the work a single block can require from validators.
+    pub fn gas(&self) -> u64 {
+        let work = match self {
+            Transaction::Deployment { verifying_keys, .. } => {
+                verifying_keys.map.len() as u64 * DEPLOYMENT_FUNCTION_GAS
+            }
+            // a batch costs the same as sending its transactions separately
+            Transaction::Batch { transactions, .. } => {
+                return transactions.iter().map(Transaction::gas).sum();
+            }
+            // checking the envelope signature is cheap compared to the transaction it wraps
+            Transaction::Signed { transaction, .. } => return transaction.gas(),
+            // the deployment is verified when its last chunk is delivered, so each chunk pays for part of it
+            Transaction::DeploymentChunk { .. } => DEPLOYMENT_FUNCTION_GAS,
+            Transaction::Execution { .. }
+            | Transaction::Governance { .. }
+            | Transaction::Deprecation { .. }
+            | Transaction::NameRegistration { .. }
+            | Transaction::KeyRotation { .. }
+            | Transaction::BridgeTransfer { .. }
+            | Transaction::BridgeReceive { .. } => 0,
+        };
+        TRANSACTION_GAS + work + self.transitions().len() as u64 * TRANSITION_GAS
+    }
+
+    /// Return the amount of transitions that need their proofs verified.
+    pub fn transition_count(&self) -> usize {
+        self.transitions().len()
+    }
+
+    /// Return the size in bytes of the transaction, as sent to the nodes.
+    pub fn size(&self) -> Result<u64> {
+        Ok(bincode::serialized_size(self)?)
+    }
+
+    /// Return the name of the kind of transaction, e.g. `execution`. Signed envelopes have the kind of the
+    /// transaction they wrap.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Transaction::Deployment { .. } => "deployment",
+            Transaction::Execution { .. } => "execution",
+            Transaction::Governance { .. } => "governance",
+            Transaction::Deprecation { .. } => "deprecation",
+            Transaction::NameRegistration { .. } => "name_registration",
+            Transaction::KeyRotation { .. } => "key_rotation",
+            Transaction::DeploymentChunk { .. } => "deployment_chunk",
+            Transaction::Batch { .. } => "batch",
+            Transaction::BridgeTransfer { .. } => "bridge_transfer",
+            Transaction::BridgeReceive { .. } => "bridge_receive",
+            Transaction::Signed { transaction, .. } => transaction.kind(),
+        }
+    }
+
+    /// Return the ids of the programs deployed, deprecated or run by the transaction, in order of appearance.
+    pub fn programs(&self) -> Vec<String> {
+        match self {
+            Transaction::Deployment { program, .. } => vec![program.id().to_string()],
+            Transaction::Deprecation { program_id, .. }
+            | Transaction::NameRegistration { program_id, .. } => vec![program_id.to_string()],
+            Transaction::Signed { transaction, .. } => transaction.programs(),
+            _ => self
+                .transitions()
+                .iter()
+                .map(|transition| transition.program_id().to_string())
+                .unique()
+                .collect(),
+        }
+    }
+
+    /// Return the ids of the programs whose functions are run by the transitions of the transaction, not counting
+    /// the credits fee transition, e.g. to tell which programs can spend the records owned by their address.
+    pub fn executed_programs(&self) -> Vec<String> {
+        self.transitions()
+            .iter()
+            .filter(|transition| {
+                transition.program_id().to_string() != "credits.aleo"
+                    || transition.function_name().to_string() != "fee"
+            })
+            .map(|transition| transition.program_id().to_string())
+            .unique()
+            .collect()
+    }
+
+    /// Return the id, kind, programs, fee and size of the transaction.
+    pub fn summary(&self) -> Result<TransactionSummary> {
+        Ok(TransactionSummary {
+            id: self.id().to_string(),
+            kind: self.kind().to_string(),
+            programs: self.programs(),
+            fee: self.fees(),
+            size: self.size()?,
+        })
+    }
+
+    /// Return the public outputs of the transitions of the transaction, in order, e.g. the staked amounts.
+    /// Private outputs and records are left out, since only their owner can decrypt them.
+    pub fn public_outputs(&self) -> Vec<PublicOutput> {
+        self.transitions()
+            .iter()
+            .flat_map(|transition| {
+                transition
+                    .outputs()
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(index, output)| {
+                        vm::public_output_value(output).map(|value| PublicOutput {
+                            program: transition.program_id().to_string(),
+                            function: transition.function_name().to_string(),
+                            index,
+                            value,
+                        })
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// Return the reasons why nodes would reject the transaction because of its size or verification cost, if any.
+    pub fn limit_warnings(&self) -> Result<Vec<String>> {
+        let mut warnings = Vec::new();
+        let size = self.size()?;
+        if size > MAX_TRANSACTION_BYTES {
+            warnings.push(format!(
+                "transaction size {size} exceeds the node limit of {MAX_TRANSACTION_BYTES} bytes"
+            ));
         }
+        let gas = self.gas();
+        if gas > MAX_BLOCK_GAS {
+            warnings.push(format!(
+                "transaction gas {gas} exceeds the block gas limit {MAX_BLOCK_GAS}"
+            ));
+        }
+        Ok(warnings)
     }
 
     /// Return the sum of the transition fees contained in this transition.
@@ -166,17 +1533,55 @@ impl Transaction {
             Transaction::Deployment { fee, .. } => {
                 fee.as_ref().map_or(0, |transition| *transition.fee())
             }
-            Transaction::Execution { transitions, .. } => transitions
-                .iter()
-                .fold(0, |acc, transition| acc + transition.fee()),
+            // saturating, so made up fees of transactions that fail validation can't overflow
+            Transaction::Execution { transitions, .. } => {
+                transitions.iter().fold(0, |acc: i64, transition| {
+                    acc.saturating_add(*transition.fee())
+                })
+            }
+            Transaction::Batch { transactions, .. } => {
+                transactions.iter().fold(0, |acc: i64, transaction| {
+                    acc.saturating_add(transaction.fees())
+                })
+            }
+            Transaction::Signed { transaction, .. } => transaction.fees(),
+            // the credits burnt by a bridge transfer are sent to the destination chain, they aren't a fee
+            Transaction::Governance { .. }
+            | Transaction::Deprecation { .. }
+            | Transaction::NameRegistration { .. }
+            | Transaction::KeyRotation { .. }
+            | Transaction::BridgeTransfer { .. }
+            | Transaction::BridgeReceive { .. } => 0,
+            Transaction::DeploymentChunk { fee, .. } => {
+                fee.as_ref().map_or(0, |transition| *transition.fee())
+            }
+        }
+    }
+
+    /// Return the amount of gates burnt by a bridge transfer, to be minted on the destination chain.
+    pub fn bridge_amount(&self) -> Result<u64> {
+        match self {
+            Transaction::BridgeTransfer { burn, .. } => Ok(u64::try_from(*burn.fee())?),
+            _ => bail!("{self} is not a bridge transfer"),
         }
     }
 
     /// Extract a list of validator updates that result from the current execution.
     /// This will return a non-empty vector in case some of the transitions are of the
     /// stake or unstake functions in the credits program.
+    /// The validator key is read from the public outputs of the transition, which are the key inputs of `stake` or
+    /// the fields of the unstaked record, so they are part of what the proof attests to: changing the validator of
+    /// a staking execution after it was proven makes `validate_transaction` reject it.
     pub fn stake_updates(&self) -> Result<Vec<validator::Stake>> {
         let mut result = Vec::new();
+        if let Self::Signed { transaction, .. } = self {
+            return transaction.stake_updates();
+        }
+        if let Self::Batch { transactions, .. } = self {
+            for transaction in transactions {
+                result.extend(transaction.stake_updates()?);
+            }
+        }
         if let Self::Execution { transitions, .. } = self {
             for transition in transitions {
                 if transition.program_id().to_string() == "credits.aleo" {
@@ -214,14 +1619,61 @@ impl Transaction {
         Ok(result)
     }
 
+    /// Return the record with the change of the fee paid by the transaction, if it paid one out of a record: the output
+    /// of the fee transition of a deployment, or of the credits fee transition that ends an execution.
+    pub fn fee_change_record(&self) -> Option<vm::EncryptedRecord> {
+        let fee = match self {
+            Transaction::Deployment { fee, .. } => fee.clone()?,
+            Transaction::Execution { transitions, .. } => transitions
+                .last()
+                .filter(|transition| {
+                    transition.program_id().to_string() == "credits.aleo"
+                        && transition.function_name().to_string() == "fee"
+                })?
+                .clone(),
+            Transaction::Signed { transaction, .. } => return transaction.fee_change_record(),
+            _ => return None,
+        };
+        let transaction = Self::Execution {
+            id: String::new(),
+            transitions: vec![fee],
+            memos: vec![],
+            expires_at: None,
+            chain_id: None,
+            execute_at: None,
+            pinned_programs: vec![],
+        };
+        transaction.output_records().pop().map(|(_, record)| record)
+    }
+
+    /// Return the unlock heights revealed by the credits unlock transitions of the transaction. The locked records
+    /// being spent can't be unlocked before the chain reaches these heights.
+    pub fn unlock_heights(&self) -> Result<Vec<u64>> {
+        self.transitions()
+            .iter()
+            .filter(|transition| {
+                transition.program_id().to_string() == "credits.aleo"
+                    && transition.function_name().to_string() == "unlock"
+            })
+            .map(|transition| {
+                let output = transition
+                    .outputs()
+                    .get(1)
+                    .ok_or_else(|| anyhow!("couldn't find unlock height output in transition"))?;
+                vm::int_from_output(output)
+            })
+            .collect()
+    }
+
     /// If there is some required fee, return the transition resulting of executing
     /// the fee function of the credits program for the requested amount.
     /// The fee function just burns the desired amount of credits, so its effect is just
     /// to produce a difference between the input/output records of its transition.
-    fn execute_fee(
+    fn execute_fee<R: Rng + CryptoRng>(
         private_key: &vm::PrivateKey,
         requested_fee: Option<(u64, vm::Record)>,
         implicit_fee: i64,
+        rng: &mut R,
     ) -> Result<Option<vm::Transition>> {
         if let Some((gates, record)) = requested_fee {
             ensure!(
@@ -234,36 +1686,43 @@ impl Transaction {
                 return Ok(None);
             }
 
-            let gates = gates as i64 - implicit_fee;
-            #[cfg(feature = "lambdavm_backend")]
-            let inputs = [
-                vm::UserInputValueType::Record(crate::vm::Record {
-                    owner: record.owner,
-                    gates: record.gates,
-                    data: record.data,
-                    nonce: record.nonce,
-                }),
-                // TODO: Revisit the cast below.
-                vm::UserInputValueType::U64(gates as u64),
-            ];
-
-            #[cfg(feature = "snarkvm_backend")]
-            let inputs = [
-                vm::UserInputValueType::Record(record),
-                vm::UserInputValueType::from_str(&format!("{gates}u64"))?,
-            ];
-
-            let transitions = Self::execute_credits("fee", &inputs, private_key)?;
+            // TODO: Revisit the cast below.
+            let gates = (gates as i64 - implicit_fee) as u64;
+            let inputs = Self::fee_inputs(record, gates)?;
+            let transitions = Self::execute_credits("fee", &inputs, private_key, rng)?;
             Ok(Some(transitions.first().unwrap().clone()))
         } else {
             Ok(None)
         }
     }
 
-    fn execute_credits(
+    /// The inputs of the credits fee function, to pay the given amount of gates out of the record.
+    fn fee_inputs(record: vm::Record, gates: u64) -> Result<[vm::UserInputValueType; 2]> {
+        #[cfg(feature = "lambdavm_backend")]
+        let inputs = [
+            vm::UserInputValueType::Record(crate::vm::Record {
+                owner: record.owner,
+                gates: record.gates,
+                data: record.data,
+                nonce: record.nonce,
+            }),
+            vm::UserInputValueType::U64(gates),
+        ];
+
+        #[cfg(feature = "snarkvm_backend")]
+        let inputs = [
+            vm::UserInputValueType::Record(record),
+            vm::UserInputValueType::from_str(&format!("{gates}u64"))?,
+        ];
+
+        Ok(inputs)
+    }
+
+    fn execute_credits<R: Rng + CryptoRng>(
         function: &str,
         inputs: &[vm::UserInputValueType],
         private_key: &vm::PrivateKey,
+        rng: &mut R,
     ) -> Result<Vec<vm::Transition>> {
         let function = vm::Identifier::from_str(function)?;
         let (program, keys) = load_credits();
@@ -272,31 +1731,379 @@ impl Transaction {
             .get(&function)
             .ok_or_else(|| anyhow!("credits function not found"))?;
 
-        vm::execution(
+        vm::execution_with_rng(
             program,
             function,
             inputs,
             private_key,
             Some(proving_key.clone()),
+            rng,
         )
+        .map_err(vm::proving_failed)
     }
 
-    /// Verify that the transaction id is consistent with its contents, by checking it's sha256 hash.
+    /// Decrypt the last output record of the transition, which for the credits functions is the one returned to the caller.
+    fn last_output_record(
+        transition: &vm::Transition,
+        view_key: &vm::ViewKey,
+    ) -> Result<vm::Record> {
+        let transaction = Self::Execution {
+            id: String::new(),
+            transitions: vec![transition.clone()],
+            memos: vec![],
+            expires_at: None,
+            chain_id: None,
+            execute_at: None,
+            pinned_programs: vec![],
+        };
+        let (_commitment, record) = transaction
+            .output_records()
+            .pop()
+            .ok_or_else(|| anyhow!("transition has no output records"))?;
+        record.decrypt(view_key)
+    }
+
+    /// Verify the structure and the signatures of the transaction, see `verify_structure` and `verify_signatures`.
     pub fn verify(&self) -> Result<()> {
+        self.verify_structure()?;
+        self.verify_signatures()
+    }
+
+    /// Verify that the transaction id is consistent with its contents, by checking it's sha256 hash.
+    /// Memos should be attached to distinct output records of the transaction, and batches, chunks, bridge
+    /// transactions and registered names should be well formed. Signatures and proofs are not checked.
+    pub fn verify_structure(&self) -> Result<()> {
         ensure!(
             self.id() == self.hash()?,
             "Corrupted transaction: Inconsistent transaction id"
         );
 
+        let outputs: HashSet<vm::Field> = self
+            .output_records()
+            .into_iter()
+            .map(|(commitment, _)| commitment)
+            .collect();
+        let mut memo_commitments = HashSet::new();
+        for (commitment, memo) in self.memos() {
+            ensure!(
+                outputs.contains(commitment),
+                "memo attached to unknown output record {commitment}"
+            );
+            ensure!(
+                memo_commitments.insert(commitment),
+                "more than one memo attached to output record {commitment}"
+            );
+            ensure!(
+                bincode::serialized_size(memo)? as usize <= MAX_ENCRYPTED_MEMO_BYTES,
+                "memo attached to output record {commitment} is too long"
+            );
+        }
+
+        let programs: HashSet<vm::ProgramID> = self
+            .transitions()
+            .iter()
+            .map(|transition| *transition.program_id())
+            .collect();
+        let mut pinned = HashSet::new();
+        for (program_id, _) in self.pinned_programs() {
+            ensure!(
+                programs.contains(program_id),
+                "program {program_id} is pinned but not run by the execution"
+            );
+            ensure!(
+                pinned.insert(program_id),
+                "program {program_id} is pinned more than once"
+            );
+        }
+
+        match self {
+            Transaction::Batch { transactions, .. } => {
+                ensure!(!transactions.is_empty(), "The batch has no transactions");
+                let mut ids = HashSet::new();
+                for transaction in transactions {
+                    ensure!(
+                        matches!(
+                            transaction,
+                            Transaction::Deployment { .. } | Transaction::Execution { .. }
+                        ),
+                        "Batches can only contain deployments and executions, found {transaction}"
+                    );
+                    ensure!(
+                        transaction.execute_at().is_none(),
+                        "Scheduled executions can't be part of a batch"
+                    );
+                    ensure!(
+                        transaction.chain_id().is_none()
+                            || transaction.chain_id() == self.chain_id(),
+                        "Transaction {} is for a different chain than the batch",
+                        transaction.id()
+                    );
+                    ensure!(
+                        ids.insert(transaction.id()),
+                        "Transaction {} is more than once in the batch",
+                        transaction.id()
+                    );
+                    transaction.verify_structure()?;
+                }
+            }
+            Transaction::DeploymentChunk {
+                index,
+                total,
+                data,
+                fee,
+                ..
+            } => {
+                ensure!(
+                    *total <= MAX_DEPLOYMENT_CHUNKS,
+                    "Deployments can't be split in more than {MAX_DEPLOYMENT_CHUNKS} chunks"
+                );
+                ensure!(index < total, "Chunk index {index} out of {total}");
+                ensure!(
+                    !data.is_empty() && data.len() <= DEPLOYMENT_CHUNK_BYTES,
+                    "Chunks must have between 1 and {DEPLOYMENT_CHUNK_BYTES} bytes"
+                );
+                match fee {
+                    Some(fee) => {
+                        ensure!(
+                            *index == 0,
+                            "Only the first chunk of a deployment pays a fee"
+                        );
+                        ensure!(
+                            fee.program_id().to_string() == "credits.aleo"
+                                && fee.function_name().to_string() == "fee",
+                            "Chunks pay with a credits fee transition"
+                        );
+                        ensure!(
+                            self.fees() >= *total as i64 * DEPLOYMENT_CHUNK_FEE as i64,
+                            "The first chunk has to pay {DEPLOYMENT_CHUNK_FEE} gates for each of the {total} chunks"
+                        );
+                    }
+                    None => ensure!(
+                        *index > 0,
+                        "The first chunk of a deployment has to pay a fee"
+                    ),
+                }
+            }
+            Transaction::BridgeTransfer {
+                destination_chain,
+                burn,
+                ..
+            } => {
+                ensure!(
+                    !destination_chain.is_empty() && !destination_chain.contains('/'),
+                    "Invalid destination chain {destination_chain}"
+                );
+                ensure!(
+                    burn.program_id().to_string() == "credits.aleo"
+                        && burn.function_name().to_string() == "fee",
+                    "Bridge transfers burn credits with a credits fee transition"
+                );
+                ensure!(
+                    self.bridge_amount()? > 0,
+                    "Bridge transfers need to send some gates"
+                );
+            }
+            Transaction::BridgeReceive {
+                header,
+                validators,
+                packets,
+                ..
+            } => {
+                ensure!(
+                    !packets.is_empty() && packets.len() <= bridge::MAX_PACKETS_PER_RECEIVE,
+                    "Bridge receives must have between 1 and {} packets",
+                    bridge::MAX_PACKETS_PER_RECEIVE
+                );
+                bridge::decode_header(header)?;
+                bridge::decode_validators(validators)?;
+            }
+            Transaction::Signed { transaction, .. } => {
+                ensure!(
+                    matches!(
+                        **transaction,
+                        Transaction::Deployment { .. }
+                            | Transaction::Execution { .. }
+                            | Transaction::Batch { .. }
+                            | Transaction::DeploymentChunk { .. }
+                            | Transaction::BridgeTransfer { .. }
+                    ),
+                    "Envelopes can only contain deployments, executions, batches, chunks and bridge transfers, found {transaction}"
+                );
+                ensure!(
+                    transaction.chain_id() == self.chain_id(),
+                    "Transaction {} is for a different chain than its envelope",
+                    transaction.id()
+                );
+                for part in transaction.parts() {
+                    if let Transaction::Deployment { deployer, .. } = part {
+                        ensure!(
+                            Some(deployer) == self.envelope_sender(),
+                            "Deployment {} is signed by an account other than its deployer",
+                            part.id()
+                        );
+                    }
+                }
+                transaction.verify_structure()?;
+            }
+            Transaction::NameRegistration { name, .. } => {
+                names::parse(name)?;
+            }
+            Transaction::Deployment { .. }
+            | Transaction::Execution { .. }
+            | Transaction::Governance { .. }
+            | Transaction::Deprecation { .. }
+            | Transaction::KeyRotation { .. } => {}
+        }
+
+        Ok(())
+    }
+
+    /// For governance and deprecation transactions, verify that they were signed by the validator and
+    /// the deployer respectively, and for key rotations that they were signed with both validator keys.
+    /// Batches can only contain deployments and executions, which aren't signed, see `verify_structure`.
+    /// Envelopes and name registrations are verified against the address of their sender and namespace owner.
+    pub fn verify_signatures(&self) -> Result<()> {
+        match self {
+            Transaction::Signed {
+                transaction,
+                sender,
+                signature,
+                expires_at,
+                ..
+            } => {
+                vm::verify_signature(
+                    sender,
+                    &Self::signed_message(
+                        Self::envelope_message(transaction, *expires_at),
+                        self.chain_id(),
+                    ),
+                    signature,
+                )?;
+            }
+            Transaction::Governance {
+                action,
+                validator,
+                nonce,
+                signature,
+                ..
+            } => {
+                validator::verify_signature(
+                    validator,
+                    &Self::signed_message(bincode::serialize(&(action, nonce))?, self.chain_id()),
+                    signature,
+                )?;
+            }
+            Transaction::Deprecation {
+                program_id,
+                grace_period,
+                deployer,
+                signature,
+                ..
+            } => {
+                vm::verify_signature(
+                    deployer,
+                    &Self::signed_message(
+                        Self::deprecation_message(program_id, *grace_period),
+                        self.chain_id(),
+                    ),
+                    signature,
+                )?;
+            }
+            Transaction::NameRegistration {
+                name,
+                program_id,
+                owner,
+                sequence,
+                signature,
+                ..
+            } => {
+                vm::verify_signature(
+                    owner,
+                    &Self::signed_message(
+                        Self::name_registration_message(name, program_id, *sequence),
+                        self.chain_id(),
+                    ),
+                    signature,
+                )?;
+            }
+            Transaction::KeyRotation {
+                validator,
+                new_key,
+                signature,
+                new_key_signature,
+                ..
+            } => {
+                let message = Self::signed_message(
+                    Self::key_rotation_message(validator, new_key),
+                    self.chain_id(),
+                );
+                validator::verify_signature(validator, &message, signature)?;
+                validator::verify_signature(new_key, &message, new_key_signature)?;
+            }
+            _ => {}
+        }
         Ok(())
     }
 
+    /// Bind a message to be signed to the given chain, so the signature isn't valid on other chains.
+    /// Messages signed without a chain id are left as they were before chain ids were introduced.
+    fn signed_message(mut message: Vec<u8>, chain_id: Option<&str>) -> Vec<u8> {
+        if let Some(chain_id) = chain_id {
+            message.extend_from_slice(b"\0chain_id:");
+            message.extend_from_slice(chain_id.as_bytes());
+        }
+        message
+    }
+
+    /// The message signed by the owner of a namespace to point one of its names to a program.
+    fn name_registration_message(name: &str, program_id: &vm::ProgramID, sequence: u64) -> Vec<u8> {
+        format!("register {name} {program_id} {sequence}").into_bytes()
+    }
+
+    /// The message signed by the deployer to deprecate a program.
+    fn deprecation_message(program_id: &vm::ProgramID, grace_period: u64) -> Vec<u8> {
+        format!("deprecate {program_id} {grace_period}").into_bytes()
+    }
+
+    /// The message signed by the sender of an envelope. It has the id of the wrapped transaction, which is the hash
+    /// of its contents, checked by `verify_structure`.
+    fn envelope_message(transaction: &Transaction, expires_at: Option<u64>) -> Vec<u8> {
+        let mut message = format!("envelope {}", transaction.id()).into_bytes();
+        if let Some(height) = expires_at {
+            message.extend_from_slice(b"\0expires_at:");
+            message.extend_from_slice(&height.to_be_bytes());
+        }
+        message
+    }
+
+    /// The message signed with both keys to rotate the consensus key of a validator.
+    fn key_rotation_message(
+        validator: &tendermint::PublicKey,
+        new_key: &tendermint::PublicKey,
+    ) -> Vec<u8> {
+        [
+            b"rotate".as_slice(),
+            &validator.to_bytes(),
+            &new_key.to_bytes(),
+        ]
+        .concat()
+    }
+
     /// Hash the contents of the given enum and return it with the hash as its id.
     fn set_hashed_id(mut self) -> Result<Self> {
         let new_id = self.hash()?;
         match self {
             Transaction::Deployment { ref mut id, .. } => *id = new_id,
             Transaction::Execution { ref mut id, .. } => *id = new_id,
+            Transaction::Governance { ref mut id, .. } => *id = new_id,
+            Transaction::Deprecation { ref mut id, .. } => *id = new_id,
+            Transaction::NameRegistration { ref mut id, .. } => *id = new_id,
+            Transaction::KeyRotation { ref mut id, .. } => *id = new_id,
+            Transaction::Batch { ref mut id, .. } => *id = new_id,
+            Transaction::DeploymentChunk { ref mut id, .. } => *id = new_id,
+            Transaction::BridgeTransfer { ref mut id, .. } => *id = new_id,
+            Transaction::BridgeReceive { ref mut id, .. } => *id = new_id,
+            Transaction::Signed { ref mut id, .. } => *id = new_id,
         };
         Ok(self)
     }
@@ -308,6 +2115,15 @@ impl Transaction {
         let variant_code: u8 = match self {
             Transaction::Deployment { .. } => 0,
             Transaction::Execution { .. } => 1,
+            Transaction::Governance { .. } => 2,
+            Transaction::Deprecation { .. } => 3,
+            Transaction::KeyRotation { .. } => 4,
+            Transaction::Batch { .. } => 5,
+            Transaction::DeploymentChunk { .. } => 6,
+            Transaction::BridgeTransfer { .. } => 7,
+            Transaction::BridgeReceive { .. } => 8,
+            Transaction::Signed { .. } => 9,
+            Transaction::NameRegistration { .. } => 10,
         };
         hasher.update(variant_code.to_be_bytes());
 
@@ -317,8 +2133,18 @@ impl Transaction {
                 program,
                 verifying_keys,
                 fee,
+                deployer,
+                expires_at: _,
+                chain_id: _,
+                source_hash,
             } => {
                 hasher.update(program.id().to_string());
+                hasher.update(deployer.to_string());
+                // deployments without a source hash keep the ids they had before it was introduced
+                if let Some(source_hash) = source_hash {
+                    hasher.update(b"source_hash");
+                    hasher.update(source_hash);
+                }
 
                 for (key, value) in verifying_keys.map.clone().into_iter() {
                     hasher.update(key.to_string());
@@ -336,13 +2162,165 @@ impl Transaction {
             Transaction::Execution {
                 id: _id,
                 transitions,
+                memos,
+                expires_at: _,
+                chain_id: _,
+                execute_at,
+                pinned_programs,
             } => {
                 for transition in transitions.iter() {
                     hasher.update(serde_json::to_string(transition)?);
                 }
+                // executions without memos keep the ids they had before memos were introduced
+                if !memos.is_empty() {
+                    hasher.update(serde_json::to_string(memos)?);
+                }
+                // likewise for executions that aren't scheduled
+                if let Some(height) = execute_at {
+                    hasher.update(b"execute_at");
+                    hasher.update(height.to_be_bytes());
+                }
+                // and for executions that don't pin programs
+                for (program_id, program_hash) in pinned_programs {
+                    hasher.update(b"pinned_program");
+                    hasher.update(program_id.to_string());
+                    hasher.update(program_hash);
+                }
+            }
+            Transaction::Governance {
+                id: _id,
+                action,
+                validator,
+                nonce,
+                signature,
+                expires_at: _,
+                chain_id: _,
+            } => {
+                hasher.update(bincode::serialize(action)?);
+                hasher.update(validator.to_bytes());
+                hasher.update(nonce.to_be_bytes());
+                hasher.update(signature);
+            }
+            Transaction::Deprecation {
+                id: _id,
+                program_id,
+                grace_period,
+                deployer,
+                signature,
+                expires_at: _,
+                chain_id: _,
+            } => {
+                hasher.update(program_id.to_string());
+                hasher.update(grace_period.to_be_bytes());
+                hasher.update(deployer.to_string());
+                hasher.update(signature.to_string());
+            }
+            Transaction::NameRegistration {
+                id: _id,
+                name,
+                program_id,
+                owner,
+                sequence,
+                signature,
+                expires_at: _,
+                chain_id: _,
+            } => {
+                hasher.update(name);
+                hasher.update(program_id.to_string());
+                hasher.update(owner.to_string());
+                hasher.update(sequence.to_be_bytes());
+                hasher.update(signature.to_string());
+            }
+            Transaction::KeyRotation {
+                id: _id,
+                validator,
+                new_key,
+                signature,
+                new_key_signature,
+                expires_at: _,
+                chain_id: _,
+            } => {
+                hasher.update(validator.to_bytes());
+                hasher.update(new_key.to_bytes());
+                hasher.update(signature);
+                hasher.update(new_key_signature);
+            }
+            Transaction::Batch {
+                id: _id,
+                transactions,
+                expires_at: _,
+                chain_id: _,
+            } => {
+                // the id of each transaction is the hash of its contents, checked when the batch is verified
+                for transaction in transactions {
+                    hasher.update(transaction.id());
+                }
+            }
+            Transaction::DeploymentChunk {
+                id: _id,
+                deployment_hash,
+                index,
+                total,
+                data,
+                fee,
+                expires_at: _,
+                chain_id: _,
+            } => {
+                hasher.update(deployment_hash);
+                hasher.update(index.to_be_bytes());
+                hasher.update(total.to_be_bytes());
+                hasher.update(data);
+                if let Some(fee) = fee {
+                    hasher.update(serde_json::to_string(fee)?);
+                }
+            }
+            Transaction::BridgeTransfer {
+                id: _id,
+                destination_chain,
+                receiver,
+                burn,
+                expires_at: _,
+                chain_id: _,
+            } => {
+                hasher.update(destination_chain);
+                hasher.update(receiver.to_string());
+                hasher.update(serde_json::to_string(burn)?);
+            }
+            Transaction::BridgeReceive {
+                id: _id,
+                header,
+                validators,
+                packets,
+                expires_at: _,
+                chain_id: _,
+            } => {
+                hasher.update(header);
+                hasher.update(validators);
+                hasher.update(bincode::serialize(packets)?);
+            }
+            Transaction::Signed {
+                id: _id,
+                transaction,
+                sender,
+                signature,
+                expires_at: _,
+                chain_id: _,
+            } => {
+                hasher.update(transaction.id());
+                hasher.update(sender.to_string());
+                hasher.update(signature.to_string());
             }
         }
 
+        if let Some(height) = self.expires_at() {
+            hasher.update(height.to_be_bytes());
+        }
+        // transactions without a chain id keep the ids they had before it was introduced
+        if let Some(chain_id) = self.chain_id() {
+            hasher.update(b"chain_id");
+            hasher.update(chain_id);
+        }
+
         let hash = hasher.finalize().as_slice().to_owned();
         Ok(hex::encode(hash))
     }
@@ -410,17 +2388,54 @@ impl std::fmt::Display for Transaction {
             Transaction::Deployment { id, program, .. } => {
                 write!(f, "Deployment({},{})", id, program.id())
             }
-            Transaction::Execution { id, transitions } => {
+            Transaction::Execution {
+                id, transitions, ..
+            } => {
                 let transition = transitions.first().unwrap();
                 write!(f, "Execution({},{id})", transition.program_id())
             }
+            Transaction::Governance { id, .. } => write!(f, "Governance({id})"),
+            Transaction::Deprecation { id, program_id, .. } => {
+                write!(f, "Deprecation({program_id},{id})")
+            }
+            Transaction::NameRegistration { id, name, .. } => {
+                write!(f, "NameRegistration({name},{id})")
+            }
+            Transaction::KeyRotation { id, .. } => write!(f, "KeyRotation({id})"),
+            Transaction::Batch {
+                id, transactions, ..
+            } => {
+                write!(f, "Batch({},{id})", transactions.len())
+            }
+            Transaction::DeploymentChunk {
+                id,
+                deployment_hash,
+                index,
+                total,
+                ..
+            } => {
+                write!(f, "DeploymentChunk({deployment_hash},{index}/{total},{id})")
+            }
+            Transaction::BridgeTransfer {
+                id,
+                destination_chain,
+                ..
+            } => write!(f, "BridgeTransfer({destination_chain},{id})"),
+            Transaction::BridgeReceive { id, packets, .. } => {
+                write!(f, "BridgeReceive({},{id})", packets.len())
+            }
+            Transaction::Signed {
+                id, transaction, ..
+            } => write!(f, "Signed({transaction},{id})"),
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::transaction::Transaction;
+    use crate::transaction::{AuthorizedExecution, Transaction};
+    use crate::vm;
+    use std::str::FromStr;
 
     #[test]
     fn convert_validator_address_u128() {
@@ -433,6 +2448,236 @@ mod tests {
         assert!(Transaction::validator_key_from_u128s(h, l).unwrap() == pub_key);
     }
 
+    #[test]
+    fn empty_batch_is_invalid() {
+        let batch = Transaction::batch(vec![]).unwrap();
+        assert!(batch.verify().is_err());
+        assert_eq!(0, batch.fees());
+    }
+
+    #[test]
+    fn deployment_chunks_are_put_back_together() {
+        let private_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let deployment = Transaction::deployment_from_source(
+            include_str!("../../aleo/hello.aleo"),
+            &private_key,
+            None,
+        )
+        .unwrap()
+        .with_chain_id("test-chain")
+        .unwrap();
+
+        let view_key = vm::ViewKey::try_from(&private_key).unwrap();
+        let address = vm::Address::try_from(&view_key).unwrap();
+        let (_, record) = vm::mint_record("credits.aleo", "credits", &address, 100_000, 0).unwrap();
+        let record = record.decrypt(&view_key).unwrap();
+
+        let chunks = Transaction::deployment_chunks(&deployment, &private_key, record).unwrap();
+        assert_eq!(
+            (chunks.len() as u64 * DEPLOYMENT_CHUNK_FEE) as i64,
+            chunks[0].fees()
+        );
+        assert!(chunks[1..].iter().all(|chunk| chunk.fees() == 0));
+
+        let mut data = Vec::new();
+        let mut hash = String::new();
+        for chunk in &chunks {
+            chunk.verify().unwrap();
+            assert_eq!(Some("test-chain"), chunk.chain_id());
+            // chunks of a deployment for a chain come in envelopes, which bind them to it
+            assert_eq!(Some(address.to_string()), chunk.sender());
+            if let Transaction::DeploymentChunk {
+                deployment_hash,
+                data: chunk_data,
+                ..
+            } = chunk.parts()[0]
+            {
+                hash = deployment_hash.clone();
+                data.push(chunk_data.clone());
+            }
+        }
+
+        let restored = Transaction::from_chunks(&hash, &data).unwrap();
+        assert_eq!(deployment.id(), restored.id());
+        assert!(Transaction::from_chunks("not the hash", &data).is_err());
+    }
+
+    #[test]
+    fn signatures_are_verified_apart_from_the_structure() {
+        let private_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let other_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let hello = || vm::ProgramID::from_str("hello.aleo").unwrap();
+        let deprecation = Transaction::deprecation(hello(), 10, &private_key, None).unwrap();
+        deprecation.verify().unwrap();
+
+        // re-signed by another account, but with a consistent id
+        let forged = match (
+            deprecation,
+            Transaction::deprecation(hello(), 10, &other_key, None).unwrap(),
+        ) {
+            (
+                Transaction::Deprecation {
+                    id,
+                    program_id,
+                    grace_period,
+                    deployer,
+                    expires_at,
+                    chain_id,
+                    ..
+                },
+                Transaction::Deprecation { signature, .. },
+            ) => Transaction::Deprecation {
+                id,
+                program_id,
+                grace_period,
+                deployer,
+                signature,
+                expires_at,
+                chain_id,
+            }
+            .set_hashed_id()
+            .unwrap(),
+            _ => unreachable!(),
+        };
+        forged.verify_structure().unwrap();
+        assert!(forged.verify_signatures().is_err());
+        assert!(forged.verify().is_err());
+    }
+
+    #[test]
+    fn envelopes_authenticate_their_sender() {
+        let private_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let other_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let deployment = Transaction::deployment_from_source(
+            include_str!("../../aleo/hello.aleo"),
+            &private_key,
+            None,
+        )
+        .unwrap()
+        .with_chain_id("test-chain")
+        .unwrap();
+
+        let signed = Transaction::signed(deployment.clone(), &private_key, Some(100)).unwrap();
+        signed.verify().unwrap();
+        assert_eq!(Some("test-chain"), signed.chain_id());
+        assert_eq!(Some(100), signed.expires_at());
+        assert_eq!(
+            vec![deployment.id()],
+            signed
+                .parts()
+                .iter()
+                .map(|part| part.id())
+                .collect::<Vec<_>>()
+        );
+        let address = vm::Address::try_from(&vm::ViewKey::try_from(&private_key).unwrap()).unwrap();
+        assert_eq!(Some(address.to_string()), signed.sender());
+
+        // claiming another sender, with a consistent id
+        let other_address =
+            vm::Address::try_from(&vm::ViewKey::try_from(&other_key).unwrap()).unwrap();
+        let forged = match signed.clone() {
+            Transaction::Signed {
+                id,
+                transaction,
+                signature,
+                expires_at,
+                chain_id,
+                ..
+            } => Transaction::Signed {
+                id,
+                transaction,
+                sender: other_address,
+                signature,
+                expires_at,
+                chain_id,
+            }
+            .set_hashed_id()
+            .unwrap(),
+            _ => unreachable!(),
+        };
+        // rejected both because it isn't the deployer and because it didn't sign the envelope
+        assert!(forged.verify_structure().is_err());
+        assert!(forged.verify_signatures().is_err());
+
+        // another account can't wrap the deployment in its own envelope either
+        assert!(Transaction::signed(deployment, &other_key, None)
+            .unwrap()
+            .verify_structure()
+            .is_err());
+
+        assert!(Transaction::signed(signed, &private_key, None)
+            .unwrap()
+            .verify_structure()
+            .is_err());
+    }
+
+    #[test]
+    fn deployments_are_summarized() {
+        let private_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let deployment = Transaction::deployment_from_source(
+            include_str!("../../aleo/hello.aleo"),
+            &private_key,
+            None,
+        )
+        .unwrap();
+
+        let summary = deployment.summary().unwrap();
+        assert_eq!(deployment.id(), summary.id);
+        assert_eq!("deployment", summary.kind);
+        assert_eq!(vec!["hello.aleo".to_string()], summary.programs);
+        assert_eq!(0, summary.fee);
+        assert_eq!(deployment.size().unwrap(), summary.size);
+    }
+
+    #[cfg(feature = "snarkvm_backend")]
+    #[test]
+    fn authorized_execution_is_proven_without_the_key() {
+        let private_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let program = vm::generate_program(include_str!("../../aleo/hello.aleo")).unwrap();
+        let inputs = [
+            vm::UserInputValueType::from_str("1u32").unwrap(),
+            vm::UserInputValueType::from_str("2u32").unwrap(),
+        ];
+        let authorized = Transaction::authorize_execution(
+            program,
+            vm::Identifier::from_str("hello").unwrap(),
+            &inputs,
+            &private_key,
+            None,
+        )
+        .unwrap();
+
+        // the authorization is what gets handed to the prover
+        let authorized: AuthorizedExecution =
+            serde_json::from_str(&serde_json::to_string(&authorized).unwrap()).unwrap();
+        let transaction = authorized.prove().unwrap();
+        assert_eq!(1, transaction.transitions().len());
+        transaction.verify().unwrap();
+        let program = vm::generate_program(include_str!("../../aleo/hello.aleo")).unwrap();
+        assert_eq!(
+            vec![Transaction::program_pin(&program)],
+            transaction.pinned_programs()
+        );
+    }
+
+    #[test]
+    fn only_programs_run_by_the_execution_can_be_pinned() {
+        let program = vm::generate_program(include_str!("../../aleo/hello.aleo")).unwrap();
+        let execution = Transaction::Execution {
+            id: "not known yet".to_string(),
+            transitions: vec![],
+            memos: vec![],
+            expires_at: None,
+            chain_id: None,
+            execute_at: None,
+            pinned_programs: vec![Transaction::program_pin(&program)],
+        }
+        .set_hashed_id()
+        .unwrap();
+        let error = execution.verify_structure().unwrap_err();
+        assert!(error.to_string().contains("not run by the execution"));
+    }
+
     #[test]
     fn convert_validator_address_u64() {
         let pub_key = "KvYujhwQVoCOH1B3FrmtjSN5GgKUjarOKDNIbWfA8hc=";
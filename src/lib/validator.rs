@@ -1,9 +1,11 @@
+use std::path::Path;
 use std::str::FromStr;
 
 use anyhow::{anyhow, ensure, Result};
 use log::debug;
 use serde::{Deserialize, Serialize};
 
+use crate::bridge::CounterpartyChain;
 use crate::vm;
 
 pub type VotingPower = u64;
@@ -16,6 +18,32 @@ pub struct Validator {
     pub aleo_address: vm::Address,
     pub pub_key: tendermint::PublicKey,
     pub voting_power: VotingPower,
+    /// The consensus key this validator switched to, if its key was rotated. Stakes made to this key
+    /// apply to the validator with the new one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rotated_to: Option<tendermint::PublicKey>,
+    /// Whether the validator has stake but was left out of the active set, because it's below the minimum self stake
+    /// or there are enough validators with more voting power. Tendermint knows candidates with zero voting power.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub candidate: bool,
+    /// The double signing offense the validator was slashed for, if any. Its key is tombstoned: it can't receive
+    /// stakes nor be part of the active set ever again.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tombstone: Option<Offense>,
+}
+
+/// A double signing offense reported by tendermint evidence, recorded when the validator is slashed and tombstoned.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct Offense {
+    /// Height of the block the validator signed twice.
+    pub height: u64,
+    /// Height of the block that included the evidence, when the validator was slashed.
+    pub slashed_at: u64,
+    /// Voting power removed from the validator. The staked credits records still hold those gates,
+    /// but they can't be unstaked.
+    pub slashed: u64,
+    /// Height from which the rest of the stake can be unstaked.
+    pub unbonding_height: u64,
 }
 
 /// Represents an amount of credits (positive or negative) that are staked on a specific validator.
@@ -26,10 +54,54 @@ pub struct Stake {
     gates_delta: i64,
 }
 
+/// The tendermint validator signing key, used to sign transactions that can only be submitted by validators.
+pub struct ValidatorKey {
+    signing_key: ed25519_consensus::SigningKey,
+    pub pub_key: tendermint::PublicKey,
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct GenesisState {
     pub records: Vec<(vm::Field, vm::EncryptedRecord)>,
     pub validators: Vec<Validator>,
+    /// Aleo account that gets the rewards of the validators of the tendermint genesis that aren't listed in
+    /// `validators`. If not set, every tendermint genesis validator needs to be listed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_reward_address: Option<vm::Address>,
+    /// Credits released to an account over time, materialized as locked records at genesis.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub vesting: Vec<VestingSchedule>,
+    /// Counterparty chains of the bridge, see `bridge`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bridge: Vec<CounterpartyChain>,
+    /// Initial value of the `min_self_stake` chain parameter, the stake a validator needs to be in the active set.
+    #[serde(default)]
+    pub min_self_stake: u64,
+    /// Initial value of the `max_validators` chain parameter, the size of the active set. Zero for no limit.
+    #[serde(default)]
+    pub max_validators: u64,
+    /// Initial value of the `community_pool_percentage` chain parameter, the share of the block rewards and fees
+    /// paid to the community pool.
+    #[serde(default)]
+    pub community_pool_percentage: u64,
+}
+
+/// Credits assigned to an account at genesis that it can't spend all at once, e.g. the share of a team or investor.
+/// The total is split in `releases` equal parts, the first unlocked at `cliff_height` and the rest every
+/// `release_period` blocks after it; any remainder of the split goes to the last part.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct VestingSchedule {
+    pub address: vm::Address,
+    pub total: u64,
+    pub cliff_height: u64,
+    #[serde(default)]
+    pub release_period: u64,
+    #[serde(default = "one_release")]
+    pub releases: u64,
+}
+
+fn one_release() -> u64 {
+    1
 }
 
 impl Validator {
@@ -41,6 +113,9 @@ impl Validator {
             pub_key: parse_pub_key(pub_key)?,
             aleo_address,
             voting_power,
+            rotated_to: None,
+            candidate: false,
+            tombstone: None,
         })
     }
 
@@ -54,6 +129,9 @@ impl Validator {
             aleo_address: stake.aleo_address,
             pub_key: stake.pub_key,
             voting_power: stake.gates_delta as u64,
+            rotated_to: None,
+            candidate: false,
+            tombstone: None,
         })
     }
 
@@ -86,6 +164,134 @@ impl Validator {
     pub fn address(&self) -> Address {
         pub_key_to_address(&self.pub_key)
     }
+
+    /// Voting power of the validator in the tendermint consensus, zero for candidates and tombstoned validators.
+    pub fn consensus_power(&self) -> VotingPower {
+        if self.candidate || self.tombstone.is_some() {
+            0
+        } else {
+            self.voting_power
+        }
+    }
+}
+
+impl VestingSchedule {
+    /// Return the height at which each part of the schedule is unlocked, along with its amount of gates.
+    pub fn releases(&self) -> Result<Vec<(u64, u64)>> {
+        ensure!(
+            self.releases > 0,
+            "the vesting schedule of {} has no releases",
+            self.address
+        );
+        ensure!(
+            self.total >= self.releases,
+            "the vesting schedule of {} releases less than a gate at a time",
+            self.address
+        );
+        ensure!(
+            self.releases == 1 || self.release_period > 0,
+            "the vesting schedule of {} needs a release period",
+            self.address
+        );
+
+        let part = self.total / self.releases;
+        (0..self.releases)
+            .map(|release| {
+                let height = self
+                    .release_period
+                    .checked_mul(release)
+                    .and_then(|delay| delay.checked_add(self.cliff_height))
+                    .ok_or_else(|| {
+                        anyhow!("the vesting schedule of {} is too long", self.address)
+                    })?;
+                let gates = if release == self.releases - 1 {
+                    self.total - part * (self.releases - 1)
+                } else {
+                    part
+                };
+                Ok((height, gates))
+            })
+            .collect()
+    }
+}
+
+impl GenesisState {
+    /// Generate the locked records of the vesting schedules, one per release. Their nonces are derived from the
+    /// position of the release, so every node generates the same records.
+    pub fn vesting_records(&self) -> Result<Vec<(vm::Field, vm::EncryptedRecord)>> {
+        let mut records = Vec::new();
+        for (index, schedule) in self.vesting.iter().enumerate() {
+            for (release, (unlock_height, gates)) in schedule.releases()?.into_iter().enumerate() {
+                let seed = ((index as u64) << 32) | release as u64;
+                records.push(vm::mint_locked_record(
+                    &schedule.address,
+                    gates,
+                    unlock_height,
+                    seed,
+                )?);
+            }
+        }
+        Ok(records)
+    }
+
+    /// Merge the validators of the app state with the ones of the tendermint genesis, received in init_chain, so the
+    /// app and tendermint start from the same validator set. If tendermint has none, the app state ones are used.
+    /// Otherwise every app state validator must be in the tendermint set with the same voting power, and the
+    /// tendermint validators missing from the app state get the default reward address.
+    pub fn merge_validators(
+        &self,
+        tendermint_validators: &[(tendermint::PublicKey, VotingPower)],
+    ) -> Result<Vec<Validator>> {
+        if tendermint_validators.is_empty() {
+            return Ok(self.validators.clone());
+        }
+
+        let mut validators = Vec::new();
+        for validator in &self.validators {
+            ensure!(
+                !validators
+                    .iter()
+                    .any(|other: &Validator| other.pub_key == validator.pub_key),
+                "validator {validator} is listed twice in the genesis app state"
+            );
+            let (_, voting_power) = tendermint_validators
+                .iter()
+                .find(|(pub_key, _)| *pub_key == validator.pub_key)
+                .ok_or_else(|| {
+                    anyhow!("genesis app state validator {validator} is not a tendermint genesis validator")
+                })?;
+            ensure!(
+                *voting_power == validator.voting_power,
+                "validator {validator} has voting power {} in the genesis app state but {voting_power} in tendermint",
+                validator.voting_power
+            );
+            validators.push(validator.clone());
+        }
+
+        for (pub_key, voting_power) in tendermint_validators {
+            if validators
+                .iter()
+                .any(|validator| validator.pub_key == *pub_key)
+            {
+                continue;
+            }
+            let aleo_address = self.default_reward_address.ok_or_else(|| {
+                anyhow!(
+                    "tendermint genesis validator {} has no aleo account in the genesis app state, and there's no default_reward_address",
+                    base64::encode(pub_key.to_bytes())
+                )
+            })?;
+            validators.push(Validator {
+                aleo_address,
+                pub_key: *pub_key,
+                voting_power: *voting_power,
+                rotated_to: None,
+                candidate: false,
+                tombstone: None,
+            });
+        }
+        Ok(validators)
+    }
 }
 
 impl Stake {
@@ -100,10 +306,67 @@ impl Stake {
         })
     }
 
+    /// Amount of gates staked, negative for unstakes.
+    pub fn gates_delta(&self) -> i64 {
+        self.gates_delta
+    }
+
     /// Return the tendermint validator address (which is derived from its public key) as bytes.
     pub fn validator_address(&self) -> Address {
         pub_key_to_address(&self.pub_key)
     }
+
+    /// Return the same stake made to the validator with the given public key, e.g. after its key was rotated.
+    pub fn with_pub_key(self, pub_key: tendermint::PublicKey) -> Self {
+        Self { pub_key, ..self }
+    }
+}
+
+impl ValidatorKey {
+    /// Load the validator key from a tendermint priv_validator_key.json file, typically ~/.tendermint/config/priv_validator_key.json
+    pub fn load(path: &Path) -> Result<Self> {
+        let json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        let private_key = json["priv_key"]["value"]
+            .as_str()
+            .ok_or_else(|| anyhow!("couldn't extract private key from {path:?}"))?;
+
+        // tendermint stores the 32 bytes seed followed by the 32 bytes public key
+        let bytes = base64::decode(private_key)?;
+        ensure!(bytes.len() == 64, "unexpected validator private key length");
+        let seed: [u8; 32] = bytes[..32].try_into()?;
+        let signing_key = ed25519_consensus::SigningKey::from(seed);
+
+        let pub_key =
+            tendermint::PublicKey::from_raw_ed25519(signing_key.verification_key().as_bytes())
+                .ok_or_else(|| anyhow!("failed to generate tendermint public key"))?;
+
+        Ok(Self {
+            signing_key,
+            pub_key,
+        })
+    }
+
+    pub fn sign(&self, message: &[u8]) -> Vec<u8> {
+        self.signing_key.sign(message).to_bytes().to_vec()
+    }
+}
+
+/// Check that the signature of the message was made with the private key of the given validator public key.
+pub fn verify_signature(
+    pub_key: &tendermint::PublicKey,
+    message: &[u8],
+    signature: &[u8],
+) -> Result<()> {
+    let verification_key =
+        ed25519_consensus::VerificationKey::try_from(pub_key.to_bytes().as_slice())
+            .map_err(|e| anyhow!("invalid validator public key: {e}"))?;
+    let signature: [u8; 64] = signature
+        .try_into()
+        .map_err(|_| anyhow!("invalid signature length"))?;
+
+    verification_key
+        .verify(&ed25519_consensus::Signature::from(signature), message)
+        .map_err(|_| anyhow!("invalid validator signature"))
 }
 
 impl std::hash::Hash for Validator {
@@ -140,8 +403,154 @@ fn parse_pub_key(key: &str) -> Result<tendermint::PublicKey> {
         .ok_or_else(|| anyhow!("failed to generate tendermint public key"))
 }
 
-fn pub_key_to_address(key: &tendermint::PublicKey) -> Address {
+/// Tendermint address of the validator with the given public key.
+pub fn pub_key_to_address(key: &tendermint::PublicKey) -> Address {
     tendermint::account::Id::from(key.ed25519().expect("unsupported public key type"))
         .as_bytes()
         .to_vec()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY_A: &str = "3gx3phUFxwY48Ljz53Ahlo+oo4oQbmQj8OMyT9/FHxk=";
+    const KEY_B: &str = "92RH2EgC60bUXJJIZ9Ydf263DC6cFqUJmc0KIj1QmyU=";
+
+    fn new_address() -> vm::Address {
+        let private_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        vm::Address::try_from(&vm::ViewKey::try_from(&private_key).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn vesting_releases() {
+        let schedule = VestingSchedule {
+            address: new_address(),
+            total: 1000,
+            cliff_height: 100,
+            release_period: 10,
+            releases: 3,
+        };
+        assert_eq!(
+            vec![(100, 333), (110, 333), (120, 334)],
+            schedule.releases().unwrap()
+        );
+
+        let single = VestingSchedule {
+            release_period: 0,
+            releases: 1,
+            ..schedule.clone()
+        };
+        assert_eq!(vec![(100, 1000)], single.releases().unwrap());
+
+        assert!(VestingSchedule {
+            release_period: 0,
+            ..schedule.clone()
+        }
+        .releases()
+        .is_err());
+        assert!(VestingSchedule {
+            total: 2,
+            ..schedule
+        }
+        .releases()
+        .is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "snarkvm_backend")]
+    fn vesting_schedules_are_locked_records() {
+        let private_key = vm::PrivateKey::new(&mut rand::thread_rng()).unwrap();
+        let view_key = vm::ViewKey::try_from(&private_key).unwrap();
+        let address = vm::Address::try_from(&view_key).unwrap();
+        let schedule = VestingSchedule {
+            address,
+            total: 100,
+            cliff_height: 50,
+            release_period: 5,
+            releases: 2,
+        };
+        let genesis = GenesisState {
+            records: Vec::new(),
+            validators: Vec::new(),
+            default_reward_address: None,
+            vesting: vec![schedule.clone(), schedule],
+            bridge: Vec::new(),
+            min_self_stake: 0,
+            max_validators: 0,
+            community_pool_percentage: 0,
+        };
+
+        let records = genesis.vesting_records().unwrap();
+        assert_eq!(4, records.len());
+        // every node generates the same records, and they don't collide with each other
+        assert_eq!(
+            records
+                .iter()
+                .map(|(commitment, _)| *commitment)
+                .collect::<Vec<_>>(),
+            genesis
+                .vesting_records()
+                .unwrap()
+                .iter()
+                .map(|(commitment, _)| *commitment)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            4,
+            records
+                .iter()
+                .map(|(commitment, _)| commitment.to_string())
+                .collect::<std::collections::HashSet<_>>()
+                .len()
+        );
+
+        let record = records[1].1.decrypt(&view_key).unwrap();
+        assert_eq!(50, vm::gates(&record));
+        assert_eq!(
+            serde_json::json!({ "unlock_height": "55u64.public" }),
+            vm::record_data(&record).unwrap()
+        );
+    }
+
+    #[test]
+    fn genesis_validators_are_merged_with_tendermint_ones() {
+        let address = new_address();
+        let validator = Validator::from_str(KEY_A, &address.to_string(), 10).unwrap();
+        let mut genesis = GenesisState {
+            records: Vec::new(),
+            validators: vec![validator],
+            default_reward_address: None,
+            vesting: Vec::new(),
+            bridge: Vec::new(),
+            min_self_stake: 0,
+            max_validators: 0,
+            community_pool_percentage: 0,
+        };
+        let key_a = parse_pub_key(KEY_A).unwrap();
+        let key_b = parse_pub_key(KEY_B).unwrap();
+
+        // without tendermint validators the app state ones are used
+        assert_eq!(1, genesis.merge_validators(&[]).unwrap().len());
+        assert_eq!(1, genesis.merge_validators(&[(key_a, 10)]).unwrap().len());
+
+        // mismatches fail
+        assert!(genesis.merge_validators(&[(key_a, 5)]).is_err());
+        assert!(genesis.merge_validators(&[(key_b, 10)]).is_err());
+        assert!(genesis
+            .merge_validators(&[(key_a, 10), (key_b, 3)])
+            .is_err());
+
+        // unless the missing ones can get the default reward address
+        let default_address = new_address();
+        genesis.default_reward_address = Some(default_address);
+        let validators = genesis
+            .merge_validators(&[(key_a, 10), (key_b, 3)])
+            .unwrap();
+        assert_eq!(2, validators.len());
+        assert_eq!(address, validators[0].aleo_address);
+        assert_eq!(key_b, validators[1].pub_key);
+        assert_eq!(default_address, validators[1].aleo_address);
+        assert_eq!(3, validators[1].voting_power);
+    }
+}
@@ -8,6 +8,8 @@ pub use lambdavm::jaleo::{get_credits_key, mint_credits};
 pub use lambdavm::jaleo::{Itertools, UserInputValueType};
 use lambdavm::VariableType;
 use log::debug;
+use rand::{CryptoRng, Rng};
+use serde::{Deserialize, Serialize};
 use sha3::{Digest, Sha3_256};
 
 const MAX_INPUTS: usize = 8;
@@ -28,8 +30,42 @@ pub type ProvingKey = lambdavm::jaleo::ProvingKey;
 pub type Deployment = lambdavm::jaleo::Deployment;
 pub type Transition = lambdavm::jaleo::Transition;
 pub type VerifyingKeyMap = lambdavm::jaleo::VerifyingKeyMap;
+/// Account signatures are not implemented on this backend, see `sign`.
+pub type Signature = String;
+/// Record memos are not implemented on this backend, see `encrypt_memo`.
+pub type EncryptedMemo = String;
+
+/// Generate the keys of every function in the program, see `build_program`. This backend builds them all at once,
+/// so `on_progress` is only called when they are done.
+pub fn build_program_with_progress(
+    program_string: &str,
+    on_progress: impl Fn(usize, usize) + Sync,
+) -> Result<(Program, ProgramBuild)> {
+    let (program, build) = build_program(program_string)?;
+    let total = program.functions().len();
+    on_progress(total, total);
+    Ok((program, build))
+}
+
+/// Same as `build_program_with_progress`. This backend doesn't take the randomness of the keys from the caller,
+/// so the rng is not used.
+pub fn build_program_with_rng<R: Rng + CryptoRng>(
+    program_string: &str,
+    on_progress: impl Fn(usize, usize) + Sync,
+    _rng: &mut R,
+) -> Result<(Program, ProgramBuild)> {
+    build_program_with_progress(program_string, on_progress)
+}
+
+/// Separate authorizations are not implemented on this backend, see `authorize`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Authorization {
+    pub program: Program,
+    pub function_name: Identifier,
+}
 
 /// Basic deployment validations
+#[tracing::instrument(skip_all, fields(program = %program.id()))]
 pub fn verify_deployment(program: &Program, verifying_keys: VerifyingKeyMap) -> Result<()> {
     // Ensure the deployment contains verifying keys.
     let program_id = program.id();
@@ -73,6 +109,7 @@ pub fn ensure_srs_file_exists() -> Result<()> {
     Ok(())
 }
 
+#[tracing::instrument(skip_all, fields(program = %transition.program_id, function = %transition.function_name))]
 pub fn verify_execution(
     transition: &Transition,
     verifying_key_map: &VerifyingKeyMap,
@@ -169,12 +206,45 @@ pub fn program_is_coinbase(program_id: &str, function_name: &str) -> bool {
     (function_name == "mint" || function_name == "genesis") && program_id == "credits.aleo"
 }
 
+/// Generate the proving and verifying keys of the given function. This backend builds the keys of every function
+/// at once, so the ones of the rest of the program are built and dropped. The rng is not used.
+pub fn synthesize_function_keys<R: Rng + CryptoRng>(
+    program: &Program,
+    _rng: &mut R,
+    function_name: &Identifier,
+) -> Result<(ProvingKey, VerifyingKey)> {
+    let (_, build) = build_program(&program.to_string())?;
+    build
+        .map
+        .get(function_name)
+        .cloned()
+        .ok_or_else(|| anyhow!("keys not found for function {function_name}"))
+}
+
+/// The constraint count of the circuits isn't exposed by the keys of this backend.
+pub fn num_constraints(_verifying_key: &VerifyingKey) -> Option<usize> {
+    None
+}
+
 // Generates a program deployment for source transactions
 pub fn generate_program(program_string: &str) -> Result<Program> {
     // Verify program is valid by parsing it and returning it
     Program::from_str(program_string)
 }
 
+/// Same as `execution`. This backend doesn't take the randomness of the proofs from the caller,
+/// so the rng is not used.
+pub fn execution_with_rng<R: Rng + CryptoRng>(
+    program: Program,
+    function_name: Identifier,
+    inputs: &[UserInputValueType],
+    private_key: &PrivateKey,
+    proving_key: Option<ProvingKey>,
+    _rng: &mut R,
+) -> Result<Vec<Transition>> {
+    execution(program, function_name, inputs, private_key, proving_key)
+}
+
 pub fn execution(
     program: Program,
     function_name: Identifier,
@@ -222,17 +292,93 @@ pub fn execution(
     Ok(vec![transition])
 }
 
+/// Running functions without generating a proof is not supported by this backend yet.
+/// The private key is needed to process the circuit outputs on this backend, so executions can't be
+/// authorized apart from generating their proofs.
+pub fn authorize(
+    _program: Program,
+    _function_name: Identifier,
+    _inputs: &[UserInputValueType],
+    _private_key: &PrivateKey,
+) -> Result<Authorization> {
+    bail!("authorizing executions apart from proving them is not supported on the lambdavm backend")
+}
+
+/// Executions can't be authorized on this backend, see `authorize`.
+pub fn prove(
+    _authorization: Authorization,
+    _proving_key: Option<ProvingKey>,
+) -> Result<Vec<Transition>> {
+    bail!("authorizing executions apart from proving them is not supported on the lambdavm backend")
+}
+
+pub fn simulate_execution(
+    _program: Program,
+    _function_name: Identifier,
+    _inputs: &[UserInputValueType],
+    _private_key: &PrivateKey,
+) -> Result<Vec<UserInputValueType>> {
+    bail!("running programs without proofs is not supported on the lambdavm backend")
+}
+
+/// Encrypting memos is not supported by this backend yet.
+pub fn encrypt_memo(_address: &Address, _memo: &str) -> Result<EncryptedMemo> {
+    bail!("record memos are not supported on the lambdavm backend")
+}
+
+/// Encrypting memos is not supported by this backend yet, so there are no memos to decrypt.
+pub fn decrypt_memo(_view_key: &ViewKey, _memo: &EncryptedMemo) -> Result<String> {
+    bail!("record memos are not supported on the lambdavm backend")
+}
+
 /// Extract the record gates (the minimal credits unit) as a u64 integer, instead of a snarkvm internal type.
 pub fn gates(record: &Record) -> u64 {
     record.gates
 }
 
+/// Records are fully encrypted on this backend, so their gates can't be read without the owner's view key.
+pub fn public_gates(_record: &EncryptedRecord) -> Option<u64> {
+    None
+}
+
+/// Return the address of the record owner.
+#[allow(clippy::clone_on_copy)]
+pub fn record_owner(record: &Record) -> Result<Address> {
+    Ok(record.owner.clone())
+}
+
+/// Return the data entries of the record as a JSON object, keyed by entry name.
+pub fn record_data(record: &Record) -> Result<serde_json::Value> {
+    Ok(serde_json::to_value(&record.data)?)
+}
+
+/// Compute the commitment of a record. On this backend it doesn't depend on the program and record name.
+pub fn record_commitment(record: &Record, _program_id: &str, _record_name: &str) -> Result<Field> {
+    record.commitment()
+}
+
+/// Signing messages with the account keys is not supported by this backend yet.
+pub fn sign(_private_key: &PrivateKey, _message: &[u8]) -> Result<Signature> {
+    bail!("account signatures are not supported on the lambdavm backend")
+}
+
+/// Signing messages with the account keys is not supported by this backend yet, so there are no valid signatures.
+pub fn verify_signature(_address: &Address, _message: &[u8], _signature: &Signature) -> Result<()> {
+    bail!("account signatures are not supported on the lambdavm backend")
+}
+
 /// This is temporary. We should be using the `serial_number` method in the Record struct, but
 /// we are doing this to conform to the current API.
 pub fn compute_serial_number(_private_key: PrivateKey, commitment: Field) -> Result<Field> {
     Ok(sha3_hash(&hex::decode(commitment)?))
 }
 
+/// Serial numbers on this backend are derived from the record commitment alone, so they can be
+/// computed with just a view key (e.g. to audit the spent records of an account).
+pub fn serial_number_from_view_key(_view_key: &ViewKey, commitment: Field) -> Option<Field> {
+    hex::decode(commitment).ok().map(|bytes| sha3_hash(&bytes))
+}
+
 fn sha3_hash(input: &[u8]) -> String {
     let mut hasher = Sha3_256::new();
     hasher.update(input);
@@ -258,6 +404,16 @@ pub fn mint_record(
     mint_credits(owner_address, gates, seed)
 }
 
+/// Minting records with data entries is not supported by this backend yet, so there are no locked genesis records.
+pub fn mint_locked_record(
+    _owner_address: &Address,
+    _gates: u64,
+    _unlock_height: u64,
+    _seed: u64,
+) -> Result<(Field, EncryptedRecord)> {
+    bail!("locked records can't be minted on the lambdavm backend")
+}
+
 /// Matches types of literals (that we know are numbers) and turns them into u128 before trying to downcast to the desired type
 // TODO: Once https://trello.com/c/vtHu588B/77-handle-inputs-and-outputs-visibility-encryption is merged, fix this
 pub fn int_from_output<T: std::convert::TryFrom<u128>>(output: &VariableType) -> Result<T>
@@ -283,6 +439,14 @@ where
     }
 }
 
+/// Return the value of a public output, None for private outputs and records.
+pub fn public_output_value(output: &VariableType) -> Option<String> {
+    match output {
+        VariableType::Public(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
 // same as above
 pub fn address_from_output(output: &VariableType) -> Result<Address> {
     if let VariableType::Public(UserInputValueType::Address(address)) = output {
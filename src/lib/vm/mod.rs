@@ -7,3 +7,55 @@ pub use self::snarkvm::*;
 mod lambdavm;
 #[cfg(feature = "lambdavm_backend")]
 pub use self::lambdavm::*;
+
+use anyhow::Result;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+/// Error running a program function or generating its keys or proofs locally, as opposed to e.g. failing to reach
+/// the node, so clients can tell failed executions apart. It wraps the error of the VM, keeping its message.
+#[derive(Debug)]
+pub struct ProvingError(pub anyhow::Error);
+
+impl fmt::Display for ProvingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for ProvingError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.0.source()
+    }
+}
+
+/// Turn the error of the VM into a `ProvingError`, for use with `map_err`.
+pub fn proving_failed(error: anyhow::Error) -> anyhow::Error {
+    ProvingError(error).into()
+}
+
+/// Keys of the account of a program, whose address can own records on behalf of the program, e.g. the credits
+/// held in escrow. They are derived from the program id, so anyone can compute them and sign for the program:
+/// nodes only accept spending the records owned by a program address in executions that run the program.
+#[derive(Clone, Debug)]
+pub struct ProgramAccount {
+    pub private_key: PrivateKey,
+    pub view_key: ViewKey,
+    pub address: Address,
+}
+
+/// Derive the account of the given program, see `ProgramAccount`.
+pub fn program_account(program_id: &ProgramID) -> Result<ProgramAccount> {
+    let mut seed = [0u8; 32];
+    seed.copy_from_slice(&Sha256::digest(format!("program account {program_id}")));
+    let private_key = PrivateKey::new(&mut ChaCha8Rng::from_seed(seed))?;
+    let view_key = ViewKey::try_from(&private_key)?;
+    let address = Address::try_from(&view_key)?;
+    Ok(ProgramAccount {
+        private_key,
+        view_key,
+        address,
+    })
+}
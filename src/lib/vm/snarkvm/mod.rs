@@ -1,21 +1,23 @@
 /// Library for interfacing with the VM, and generating Transactions
 ///
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::{ops::Deref, str::FromStr, sync::Arc};
 
 use anyhow::{anyhow, bail, ensure, Result};
 use indexmap::IndexMap;
 use log::debug;
 use parking_lot::{lock_api::RwLock, RawRwLock};
-use rand::{rngs::ThreadRng, SeedableRng};
+use rand::{CryptoRng, Rng, SeedableRng};
 use rand_chacha::ChaCha8Rng;
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 use snarkvm::prelude::Parser;
 use snarkvm::{
     circuit::AleoV0,
     console::types::string::Integer,
     prelude::{
-        Balance, CallStack, Environment, Itertools, Literal, Network, One, Owner, Plaintext,
-        Testnet3, ToBits, ToField, Uniform, I64,
+        Balance, CallStack, Entry, Environment, Group, Itertools, Literal, Network, One, Owner,
+        Plaintext, Scalar, StringType, Testnet3, ToBits, ToField, Uniform, I64,
     },
 };
 
@@ -39,6 +41,17 @@ pub type VerifyingKey = snarkvm::prelude::VerifyingKey<Testnet3>;
 pub type ProvingKey = snarkvm::prelude::ProvingKey<Testnet3>;
 pub type Deployment = snarkvm::prelude::Deployment<Testnet3>;
 pub type Transition = snarkvm::prelude::Transition<Testnet3>;
+pub type Signature = snarkvm::prelude::Signature<Testnet3>;
+pub type Request = snarkvm::prelude::Request<Testnet3>;
+
+/// A short message attached to an output record, encrypted so only the record owner can read it.
+/// It's encrypted like record fields are: with a key derived from the owner address and a random nonce,
+/// which the owner can recompute from the nonce with its view key.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EncryptedMemo {
+    nonce: Group<Testnet3>,
+    ciphertext: Ciphertext,
+}
 
 /// These structs are nothing more than a wrapper around the actual IndexMap that is used
 /// for the verifying keys map. Why does it exist? The problem comes from the lambdavm backend.
@@ -56,7 +69,18 @@ pub struct ProgramBuild {
     pub map: IndexMap<Identifier, (ProvingKey, VerifyingKey)>,
 }
 
+/// The signed requests to run a function, including the ones of the functions it calls, as produced by `authorize`.
+/// It doesn't include the private key, so it can be handed to another host to generate the proofs with `prove`.
+/// Note that the requests include the function inputs, so the host proving them learns the input records.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Authorization {
+    pub program: Program,
+    pub function_name: Identifier,
+    requests: Vec<Request>,
+}
+
 /// Basic deployment validations
+#[tracing::instrument(skip_all, fields(program = %program.id()))]
 pub fn verify_deployment(program: &Program, verifying_keys: VerifyingKeyMap) -> Result<()> {
     // Ensure the deployment contains verifying keys.
     let program_id = program.id();
@@ -92,6 +116,7 @@ pub fn verify_deployment(program: &Program, verifying_keys: VerifyingKeyMap) ->
     Ok(())
 }
 
+#[tracing::instrument(skip_all, fields(program = %transition.program_id(), function = %transition.function_name()))]
 pub fn verify_execution(transition: &Transition, verifying_keys: &VerifyingKeyMap) -> Result<()> {
     // Verify each transition.
     log::debug!(
@@ -189,30 +214,59 @@ pub fn verify_execution(transition: &Transition, verifying_keys: &VerifyingKeyMa
 /// Generate proving and verifying keys for each function in the given program,
 /// and return them in a function name -> (proving key, verifying key) map.
 pub fn build_program(program_string: &str) -> Result<(Program, ProgramBuild)> {
-    let (_, program) = Program::parse(program_string).map_err(|e| anyhow!("{}", e))?;
+    build_program_with_progress(program_string, |_, _| {})
+}
 
-    let mut verifying_keys = IndexMap::new();
+/// Same as `build_program`, calling `on_progress` with the amount of functions whose keys are ready and the
+/// total amount of functions every time one of them is done. The keys of each function are synthesized in parallel.
+pub fn build_program_with_progress(
+    program_string: &str,
+    on_progress: impl Fn(usize, usize) + Sync,
+) -> Result<(Program, ProgramBuild)> {
+    build_program_with_rng(program_string, on_progress, &mut rand::thread_rng())
+}
 
-    for function_name in program.functions().keys() {
-        let rng = &mut rand::thread_rng();
-        verifying_keys.insert(
-            *function_name,
-            synthesize_function_keys(&program, rng, function_name)?,
-        );
-    }
+/// Same as `build_program_with_progress`, taking the randomness of the keys from the given rng, so a seeded one
+/// always builds the same keys. Each function gets an rng derived from it, so they are still synthesized in parallel.
+pub fn build_program_with_rng<R: Rng + CryptoRng>(
+    program_string: &str,
+    on_progress: impl Fn(usize, usize) + Sync,
+    rng: &mut R,
+) -> Result<(Program, ProgramBuild)> {
+    let (_, program) = Program::parse(program_string).map_err(|e| anyhow!("{}", e))?;
+
+    let function_names: Vec<Identifier> = program.functions().keys().copied().collect();
+    let total = function_names.len();
+    let done = AtomicUsize::new(0);
+    // derived up front, in the order of the functions, so it doesn't depend on how the threads are scheduled
+    let rngs = function_names
+        .iter()
+        .map(|_| ChaCha8Rng::from_rng(&mut *rng))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // collecting a parallel iterator keeps the order of the functions in the program
+    let keys = function_names
+        .par_iter()
+        .zip(rngs)
+        .map(|(function_name, mut rng)| {
+            let keys = synthesize_function_keys(&program, &mut rng, function_name)?;
+            on_progress(done.fetch_add(1, Ordering::SeqCst) + 1, total);
+            Ok((*function_name, keys))
+        })
+        .collect::<Result<Vec<_>>>()?;
 
     Ok((
         program,
         ProgramBuild {
-            map: verifying_keys,
+            map: keys.into_iter().collect(),
         },
     ))
 }
 
 /// Generate proving and verifying keys for the given function.
-pub fn synthesize_function_keys(
+pub fn synthesize_function_keys<R: Rng + CryptoRng>(
     program: &Program,
-    rng: &mut ThreadRng,
+    rng: &mut R,
     function_name: &Identifier,
 ) -> Result<(ProvingKey, VerifyingKey)> {
     let stack = stack::new_init(program)?;
@@ -227,12 +281,18 @@ pub fn synthesize_function_keys(
     Ok((proving_key, verifying_key))
 }
 
+/// Return the amount of constraints of the circuit the verifying key was synthesized for.
+pub fn num_constraints(verifying_key: &VerifyingKey) -> Option<usize> {
+    Some(verifying_key.circuit_info.num_constraints)
+}
+
 // Generates a program deployment for source transactions
 pub fn generate_program(program_string: &str) -> Result<Program> {
     // Verify program is valid by parsing it and returning it
     Program::from_str(program_string)
 }
 
+/// Run the given function and generate the proofs of its transitions. It's the same as proving its authorization.
 pub fn execution(
     program: Program,
     function_name: Identifier,
@@ -240,6 +300,54 @@ pub fn execution(
     private_key: &PrivateKey,
     proving_key: Option<ProvingKey>,
 ) -> Result<Vec<Transition>> {
+    execution_with_rng(
+        program,
+        function_name,
+        inputs,
+        private_key,
+        proving_key,
+        &mut rand::thread_rng(),
+    )
+}
+
+/// Same as `execution`, taking the randomness of the requests, records and proofs from the given rng,
+/// so a seeded one always generates the same transitions.
+pub fn execution_with_rng<R: Rng + CryptoRng>(
+    program: Program,
+    function_name: Identifier,
+    inputs: &[UserInputValueType],
+    private_key: &PrivateKey,
+    proving_key: Option<ProvingKey>,
+    rng: &mut R,
+) -> Result<Vec<Transition>> {
+    let authorization = authorize_with_rng(program, function_name, inputs, private_key, rng)?;
+    prove_with_rng(authorization, proving_key, rng)
+}
+
+/// Sign the requests to run the given function with the private key. This is the only step of an execution
+/// that needs the key and it's fast, since no circuit is synthesized.
+pub fn authorize(
+    program: Program,
+    function_name: Identifier,
+    inputs: &[UserInputValueType],
+    private_key: &PrivateKey,
+) -> Result<Authorization> {
+    authorize_with_rng(
+        program,
+        function_name,
+        inputs,
+        private_key,
+        &mut rand::thread_rng(),
+    )
+}
+
+fn authorize_with_rng<R: Rng + CryptoRng>(
+    program: Program,
+    function_name: Identifier,
+    inputs: &[UserInputValueType],
+    private_key: &PrivateKey,
+    rng: &mut R,
+) -> Result<Authorization> {
     ensure!(
         !Program::is_coinbase(program.id(), &function_name),
         "Coinbase functions cannot be called"
@@ -251,11 +359,46 @@ pub fn execution(
     );
 
     debug!(
-        "executing program {} function {} inputs {:?}",
-        program, function_name, inputs
+        "authorizing program {} function {} inputs {:?}",
+        program.id(),
+        function_name,
+        inputs
     );
 
-    let rng = &mut rand::thread_rng();
+    let stack = stack::new_init(&program)?;
+    let authorization = stack.authorize::<AleoV0, _>(private_key, function_name, inputs, rng)?;
+
+    Ok(Authorization {
+        program,
+        function_name,
+        requests: authorization.to_vec_deque().into_iter().collect(),
+    })
+}
+
+/// Generate the transitions of an authorized execution, along with their proofs. This is the slow step of an
+/// execution and doesn't need the private key. The proving key of the function is synthesized if not given.
+pub fn prove(
+    authorization: Authorization,
+    proving_key: Option<ProvingKey>,
+) -> Result<Vec<Transition>> {
+    prove_with_rng(authorization, proving_key, &mut rand::thread_rng())
+}
+
+fn prove_with_rng<R: Rng + CryptoRng>(
+    authorization: Authorization,
+    proving_key: Option<ProvingKey>,
+    rng: &mut R,
+) -> Result<Vec<Transition>> {
+    let Authorization {
+        program,
+        function_name,
+        requests,
+    } = authorization;
+    debug!(
+        "proving program {} function {}",
+        program.id(),
+        function_name
+    );
 
     let stack = stack::new_init(&program)?;
 
@@ -265,7 +408,7 @@ pub fn execution(
     };
     stack.insert_proving_key(&function_name, proving_key)?;
 
-    let authorization = stack.authorize::<AleoV0, _>(private_key, function_name, inputs, rng)?;
+    let authorization = snarkvm::prelude::Authorization::new(&requests);
     let execution: Arc<RwLock<RawRwLock, _>> = Arc::new(RwLock::new(Execution::new()));
 
     // Execute the circuit.
@@ -279,11 +422,108 @@ pub fn execution(
     Ok(execution.into_transitions().collect())
 }
 
+/// Run the given function without synthesizing keys nor generating the execution proof, returning its outputs.
+/// Output records are returned in plaintext, as they would be received by their owner.
+pub fn simulate_execution(
+    program: Program,
+    function_name: Identifier,
+    inputs: &[UserInputValueType],
+    private_key: &PrivateKey,
+) -> Result<Vec<UserInputValueType>> {
+    ensure!(
+        !Program::is_coinbase(program.id(), &function_name),
+        "Coinbase functions cannot be called"
+    );
+
+    ensure!(
+        program.contains_function(&function_name),
+        "Function '{function_name}' does not exist."
+    );
+
+    debug!(
+        "simulating program {} function {} inputs {:?}",
+        program, function_name, inputs
+    );
+
+    let rng = &mut rand::thread_rng();
+    let stack = stack::new_init(&program)?;
+    let authorization = stack.authorize::<AleoV0, _>(private_key, function_name, inputs, rng)?;
+
+    // evaluating the function runs it on the console types only, so no circuit is built
+    let response = stack.evaluate_function::<AleoV0>(CallStack::evaluate(authorization)?)?;
+    Ok(response.outputs().to_vec())
+}
+
+/// Encrypt the memo so it can only be decrypted with the view key of the given address.
+pub fn encrypt_memo(address: &Address, memo: &str) -> Result<EncryptedMemo> {
+    let plaintext = Plaintext::Literal(Literal::String(StringType::new(memo)), Default::default());
+    let randomizer = Scalar::<Testnet3>::rand(&mut rand::thread_rng());
+    let nonce = Testnet3::g_scalar_multiply(&randomizer);
+    let ciphertext = plaintext.encrypt(address, randomizer)?;
+    Ok(EncryptedMemo { nonce, ciphertext })
+}
+
+/// Decrypt a memo encrypted for the address of the given view key.
+pub fn decrypt_memo(view_key: &ViewKey, memo: &EncryptedMemo) -> Result<String> {
+    match memo.ciphertext.decrypt(*view_key, memo.nonce)? {
+        Plaintext::Literal(Literal::String(memo), _) => Ok(memo.deref().to_string()),
+        _ => bail!("memo is not a string"),
+    }
+}
+
 /// Extract the record gates (the minimal credits unit) as a u64 integer, instead of a snarkvm internal type.
 pub fn gates(record: &Record) -> u64 {
     *record.gates().deref().deref()
 }
 
+/// Return the gates of an encrypted record if they are public, i.e. readable without the view key of its owner.
+pub fn public_gates(record: &EncryptedRecord) -> Option<u64> {
+    match record.gates() {
+        Balance::Public(gates) => Some(**gates),
+        Balance::Private(_) => None,
+    }
+}
+
+/// Return the address of the record owner.
+pub fn record_owner(record: &Record) -> Result<Address> {
+    match record.owner() {
+        Owner::Public(address) => Ok(*address),
+        Owner::Private(Plaintext::Literal(Literal::Address(address), _)) => Ok(*address),
+        _ => bail!("the record owner is not an address"),
+    }
+}
+
+/// Return the data entries of the record as a JSON object, keyed by entry name.
+pub fn record_data(record: &Record) -> Result<serde_json::Value> {
+    Ok(record
+        .data()
+        .iter()
+        .map(|(name, entry)| (name.to_string(), serde_json::Value::from(entry.to_string())))
+        .collect::<serde_json::Map<_, _>>()
+        .into())
+}
+
+/// Compute the commitment of a record, which depends on the program and the name of the record type.
+pub fn record_commitment(record: &Record, program_id: &str, record_name: &str) -> Result<Field> {
+    let program_id = ProgramID::from_str(program_id)?;
+    let record_name = Identifier::from_str(record_name)?;
+    record.to_commitment(&program_id, &record_name)
+}
+
+/// Sign an arbitrary message with the account private key, e.g. to prove the ownership of the account address.
+pub fn sign(private_key: &PrivateKey, message: &[u8]) -> Result<Signature> {
+    Signature::sign_bytes(private_key, message, &mut rand::thread_rng())
+}
+
+/// Fail if the signature of the message doesn't correspond to the given address.
+pub fn verify_signature(address: &Address, message: &[u8], signature: &Signature) -> Result<()> {
+    ensure!(
+        signature.verify_bytes(address, message),
+        "invalid signature for address {address}"
+    );
+    Ok(())
+}
+
 /// A helper method to derive the serial number from the private key and commitment.
 pub fn compute_serial_number(private_key: PrivateKey, commitment: Field) -> Result<Field> {
     // Compute the generator `H` as `HashToGroup(commitment)`.
@@ -302,6 +542,12 @@ pub fn compute_serial_number(private_key: PrivateKey, commitment: Field) -> Resu
     )
 }
 
+/// Serial numbers are derived from the account's signature secret key, which can't be obtained
+/// from a view key, so there's no way to know whether a record was spent with the view key alone.
+pub fn serial_number_from_view_key(_view_key: &ViewKey, _commitment: Field) -> Option<Field> {
+    None
+}
+
 /// Generate a record for a specific program with the given attributes,
 /// by using the given seed to deterministically generate a nonce.
 /// This could be replaced by a more user-friendly record constructor.
@@ -311,6 +557,47 @@ pub fn mint_record(
     owner_address: &Address,
     gates: u64,
     seed: u64,
+) -> Result<(Field, EncryptedRecord)> {
+    mint_record_with_data(
+        program_id,
+        record_name,
+        owner_address,
+        gates,
+        IndexMap::new(),
+        seed,
+    )
+}
+
+/// Generate a `credits.aleo` `locked_credits` record, which can't be unlocked before the given height,
+/// by using the given seed to deterministically generate a nonce.
+pub fn mint_locked_record(
+    owner_address: &Address,
+    gates: u64,
+    unlock_height: u64,
+    seed: u64,
+) -> Result<(Field, EncryptedRecord)> {
+    let unlock_height = Entry::Public(Plaintext::Literal(
+        Literal::U64(Integer::new(unlock_height)),
+        Default::default(),
+    ));
+    let data = IndexMap::from([(Identifier::from_str("unlock_height")?, unlock_height)]);
+    mint_record_with_data(
+        "credits.aleo",
+        "locked_credits",
+        owner_address,
+        gates,
+        data,
+        seed,
+    )
+}
+
+fn mint_record_with_data(
+    program_id: &str,
+    record_name: &str,
+    owner_address: &Address,
+    gates: u64,
+    data: IndexMap<Identifier, Entry<Testnet3, Plaintext<Testnet3>>>,
+    seed: u64,
 ) -> Result<(Field, EncryptedRecord)> {
     // TODO have someone verify/audit this, probably it's unsafe or breaks cryptographic assumptions
 
@@ -320,13 +607,12 @@ pub fn mint_record(
     ));
     let amount = Integer::new(gates);
     let gates = Balance::Private(Plaintext::Literal(Literal::U64(amount), Default::default()));
-    let empty_data = IndexMap::new();
 
     let mut rng = ChaCha8Rng::seed_from_u64(seed);
     let randomizer = Uniform::rand(&mut rng);
     let nonce = Testnet3::g_scalar_multiply(&randomizer);
 
-    let public_record = Record::from_plaintext(owner, gates, empty_data, nonce)?;
+    let public_record = Record::from_plaintext(owner, gates, data, nonce)?;
     let record_name = Identifier::from_str(record_name)?;
     let program_id = ProgramID::from_str(program_id)?;
     let commitment = public_record.to_commitment(&program_id, &record_name)?;
@@ -352,6 +638,16 @@ where
     bail!("output type extraction not supported");
 }
 
+/// Return the value of a public or constant output, None for private outputs and records.
+pub fn public_output_value(output: &Output) -> Option<String> {
+    match output {
+        Output::Constant(_, Some(value)) | Output::Public(_, Some(value)) => {
+            Some(value.to_string())
+        }
+        _ => None,
+    }
+}
+
 // same as above
 pub fn address_from_output(output: &Output) -> Result<Address> {
     if let Output::Public(_, Some(Plaintext::Literal(Literal::Address(value), _))) = output {
@@ -41,9 +41,9 @@ fn basic_program() {
     let transaction = retry_command(home_path, &["get", transaction_id]).unwrap();
 
     #[cfg(feature = "lambdavm_backend")]
-    let pointer_path = "/Execution/transitions/0/outputs/0/Private";
+    let pointer_path = "/Signed/transaction/Execution/transitions/0/outputs/0/Private";
     #[cfg(feature = "snarkvm_backend")]
-    let pointer_path = "/Execution/transitions/0/outputs/0/value";
+    let pointer_path = "/Signed/transaction/Execution/transitions/0/outputs/0/value";
 
     let value = transaction.pointer(pointer_path).unwrap().as_str().unwrap();
 
@@ -71,12 +71,12 @@ fn program_validations() {
     .unwrap();
 
     // deploy a program
-    client_command(home_path, &["program", "deploy", &program_path]).unwrap();
-
-    // fail on already deployed compiled locally
-    let error = client_command(home_path, &["program", "deploy", &program_path]).unwrap_err();
+    let transaction = client_command(home_path, &["program", "deploy", &program_path]).unwrap();
+    assert_eq!("accepted", transaction["broadcast"]["status"]);
 
-    assert!(error.contains("Internal error: tx already exists in cache"));
+    // broadcasting is idempotent, sending the same deployment again is reported as a cache hit
+    let transaction = client_command(home_path, &["program", "deploy", &program_path]).unwrap();
+    assert_eq!("already_in_cache", transaction["broadcast"]["status"]);
 
     // execute the program, retrieving it from the blockchain, using it's id
     execute_program(home_path, &program_id, "hello", &["1u32", "1u32"]).unwrap();
@@ -103,13 +103,106 @@ fn program_validations() {
     assert!(error.contains("expects 2 inputs"));
 }
 
+#[test]
+fn exit_codes() {
+    let (_tempfile, home_path, _) = &new_account();
+    let (_program_file, program_path, _) = load_program(HELLO_PROGRAM);
+    let exit_code = |args: &[&str]| {
+        Command::cargo_bin("client")
+            .unwrap()
+            .env("ALEO_HOME", home_path)
+            .args(args)
+            .output()
+            .unwrap()
+            .status
+            .code()
+    };
+
+    let help = Command::cargo_bin("client")
+        .unwrap()
+        .arg("--help")
+        .output()
+        .unwrap();
+    assert!(String::from_utf8_lossy(&help.stdout).contains("Exit codes:"));
+
+    // invalid arguments
+    assert_eq!(Some(2), exit_code(&["program", "execute"]));
+    // no node listening
+    assert_eq!(
+        Some(3),
+        exit_code(&["--url", "http://127.0.0.1:1", "get", "at1unknown"])
+    );
+    // rejected by check_tx, the program isn't deployed
+    assert_eq!(
+        Some(4),
+        exit_code(&[
+            "program",
+            "execute",
+            &program_path,
+            HELLO_PROGRAM,
+            "1u32",
+            "1u32"
+        ])
+    );
+    // missing input of the function
+    assert_eq!(
+        Some(5),
+        exit_code(&["program", "execute", &program_path, HELLO_PROGRAM, "1u32"])
+    );
+    // not found
+    assert_eq!(Some(6), exit_code(&["get", "at1unknown"]));
+    assert_eq!(
+        Some(6),
+        exit_code(&[
+            "program",
+            "execute",
+            "inexistent_id.aleo",
+            HELLO_PROGRAM,
+            "1u32",
+            "1u32"
+        ])
+    );
+}
+
+#[test]
+fn list_programs() {
+    let (_tempfile, home_path, credentials) = &new_account();
+
+    // deploy a program and wait until it gets committed
+    let (_program_file, program_path, program_id) = load_program(HELLO_PROGRAM);
+    let transaction = client_command(home_path, &["program", "deploy", &program_path]).unwrap();
+    let transaction_id = get_transaction_id(&transaction).unwrap();
+    retry_command(home_path, &["get", transaction_id]).unwrap();
+
+    let listing = client_command(home_path, &["program", "list", "--prefix", &program_id]).unwrap();
+    let programs = listing.pointer("/programs").unwrap().as_array().unwrap();
+    assert_eq!(1, programs.len());
+
+    let program = &programs[0];
+    assert_eq!(program_id, program["program_id"].as_str().unwrap());
+    assert_eq!(
+        credentials.get("address").unwrap(),
+        program["deployer"].as_str().unwrap()
+    );
+    assert!(program["height"].as_u64().unwrap() > 0);
+    assert!(program["functions"]
+        .as_array()
+        .unwrap()
+        .contains(&serde_json::json!("hello")));
+
+    // credits is part of the genesis, so it has no deployer
+    let listing = client_command(home_path, &["program", "list", "--prefix", "credits"]).unwrap();
+    let credits = &listing.pointer("/programs").unwrap().as_array().unwrap()[0];
+    assert!(credits["deployer"].is_null());
+}
+
 #[test]
 fn decrypt_records() {
     let (_acc_file, home_path, credentials) = &new_account();
     let (_program_file, program_path, _) = load_program(TOKEN_PROGRAM);
 
     // deploy a program, save txid
-    client_command(home_path, &["program", "deploy", &program_path]).unwrap();
+    client_command(home_path, &["program", "deploy", &program_path, "--force"]).unwrap();
 
     // get address
     let address = credentials.get("address").unwrap();
@@ -145,6 +238,10 @@ fn decrypt_records() {
     assert_eq!(gates.to_string(), expected_gates);
     assert_eq!(owner.to_string(), expected_owner);
 
+    // the transaction and its records can be verified against the signed headers
+    let verified = retry_command(home_path, &["get", transaction_id, "--verify"]).unwrap();
+    assert_eq!(Some(transaction_id), get_transaction_id(&verified));
+
     // dry run contains decrypted records
     let output = execute_program(
         home_path,
@@ -184,7 +281,7 @@ fn token_transaction() {
     let (_program_file, program_path, _) = load_program("token");
 
     // Deploy the token program to the blockchain
-    client_command(alice_home, &["program", "deploy", &program_path]).unwrap();
+    client_command(alice_home, &["program", "deploy", &program_path, "--force"]).unwrap();
 
     // Mint 10 tokens into an Alice Record
     let transaction = execute_program(
@@ -267,7 +364,7 @@ fn consume_records() {
     let (_program_file, program_path, _) = load_program("records");
 
     // deploy "records" program
-    client_command(home_path, &["program", "deploy", &program_path]).unwrap();
+    client_command(home_path, &["program", "deploy", &program_path, "--force"]).unwrap();
 
     // execute mint
     let transaction = execute_program(
@@ -278,11 +375,7 @@ fn consume_records() {
     )
     .unwrap();
 
-    let transaction_id = transaction
-        .pointer("/Execution/id")
-        .unwrap()
-        .as_str()
-        .unwrap();
+    let transaction_id = transaction.pointer("/Signed/id").unwrap().as_str().unwrap();
 
     // Get the mint record
     let transaction = retry_command(home_path, &["get", transaction_id]).unwrap();
@@ -338,7 +431,7 @@ fn try_create_credits() {
     assert!(output.contains("Coinbase functions cannot be called"));
 
     let (_program_file, program_path, _) = load_program("records");
-    client_command(home_path, &["program", "deploy", &program_path]).unwrap();
+    client_command(home_path, &["program", "deploy", &program_path, "--force"]).unwrap();
     let output = execute_program(
         home_path,
         &program_path,
@@ -382,21 +475,27 @@ fn transfer_credits() {
 
     let (_tempfile, receiver_home, credentials) = &new_account();
 
-    client_command(
-        &validator_home,
-        &[
-            "credits",
-            "transfer",
-            &record,
-            credentials.get("address").unwrap(),
-            "10",
-        ],
-    )
-    .unwrap();
+    let mut args = vec![
+        "credits",
+        "transfer",
+        record.as_str(),
+        credentials.get("address").unwrap().as_str(),
+        "10",
+    ];
+    // memos are only supported on snarkvm
+    #[cfg(feature = "snarkvm_backend")]
+    args.extend(["--memo", "invoice 42"]);
+    client_command(&validator_home, &args).unwrap();
 
     // check the the account received the balance
     // (validator balance can't be checked because it could receive a reward while the test is running)
     assert_balance(receiver_home, 10).unwrap();
+
+    #[cfg(feature = "snarkvm_backend")]
+    {
+        let records = client_command(receiver_home, &["account", "records"]).unwrap();
+        assert_eq!("invoice 42", records.pointer("/0/memo").unwrap());
+    }
 }
 
 #[test]
@@ -707,14 +806,16 @@ fn staking() {
 
     #[cfg(feature = "snarkvm_backend")]
     let staked_credits_record = transaction
-        .pointer("/Execution/transitions/0/outputs/1/value")
+        .pointer("/Signed/transaction/Execution/transitions/0/outputs/1/value")
         .unwrap()
         .as_str()
         .unwrap();
 
     #[cfg(feature = "lambdavm_backend")]
     let staked_credits_record = transaction
-        .pointer("/Execution/transitions/0/outputs/1/EncryptedRecord/1/ciphertext")
+        .pointer(
+            "/Signed/transaction/Execution/transitions/0/outputs/1/EncryptedRecord/1/ciphertext",
+        )
         .unwrap()
         .as_str()
         .unwrap();
@@ -822,6 +923,9 @@ fn get_transaction_id(transaction: &serde_json::Value) -> Option<&str> {
     if let Some(value) = transaction.pointer("/Execution/id") {
         return value.as_str();
     }
+    if let Some(value) = transaction.pointer("/Signed/id") {
+        return value.as_str();
+    }
     transaction.pointer("/Deployment/id").unwrap().as_str()
 }
 
@@ -847,10 +951,11 @@ fn get_decrypted_record(transaction: &serde_json::Value) -> (&str, &str, &str) {
 
 fn get_encrypted_record(transaction: &serde_json::Value) -> &str {
     #[cfg(feature = "snarkvm_backend")]
-    let pointer_path = "/Execution/transitions/0/outputs/0/value";
+    let pointer_path = "/Signed/transaction/Execution/transitions/0/outputs/0/value";
 
     #[cfg(feature = "lambdavm_backend")]
-    let pointer_path = "/Execution/transitions/0/outputs/0/EncryptedRecord/1/ciphertext";
+    let pointer_path =
+        "/Signed/transaction/Execution/transitions/0/outputs/0/EncryptedRecord/1/ciphertext";
 
     transaction.pointer(pointer_path).unwrap().as_str().unwrap()
 }
@@ -0,0 +1,425 @@
+//! Tests of the features coupled to consensus between several validators, e.g. validator set updates, block rewards
+//! and slashing, which the single node the client tests run against can't cover. Each test starts a localnet of its
+//! own: `make localnet` writes the tendermint home, account and genesis of each validator, and the harness runs
+//! tendermint and the ABCI application of each of them as child processes, stopped when the test ends.
+//! They need `bin/tendermint` and the ports `make localnet` configures, so they are ignored by default and run one
+//! at a time with `make localnet_test`.
+
+use lib::client::Client;
+use lib::validator::Validator;
+use lib::vm;
+use std::fs::{self, File};
+use std::future::Future;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+use tendermint_rpc::{Client as _, HttpClient, Paging};
+
+/// Validators of the localnets, the default of `make localnet`.
+const VALIDATORS: usize = 4;
+
+/// Time to wait for the network to reach a condition before failing the test.
+const TIMEOUT: Duration = Duration::from_secs(180);
+
+#[tokio::test]
+#[ignore = "starts a localnet, run with make localnet_test"]
+async fn staking_updates_the_validator_set() {
+    let localnet = Localnet::start();
+    localnet.wait_for_height(2).await;
+    let node = &localnet.nodes[0];
+    let pub_key = node.pub_key();
+    let power = voting_power(&node.client(), &pub_key).await.unwrap();
+
+    let record = node.largest_record().await;
+    let transaction = node
+        .command(&["credits", "stake", "100", &record, &node.validator_key()])
+        .unwrap();
+
+    // every node applies the update, and passes it on to tendermint
+    localnet
+        .wait_for_power(&pub_key, power + 100, "the stake")
+        .await;
+
+    #[cfg(feature = "snarkvm_backend")]
+    let staked_record = transaction
+        .pointer("/Signed/transaction/Execution/transitions/0/outputs/1/value")
+        .unwrap()
+        .as_str()
+        .unwrap();
+    #[cfg(feature = "lambdavm_backend")]
+    let staked_record = transaction
+        .pointer(
+            "/Signed/transaction/Execution/transitions/0/outputs/1/EncryptedRecord/1/ciphertext",
+        )
+        .unwrap()
+        .as_str()
+        .unwrap();
+    node.command(&["credits", "unstake", "100", staked_record])
+        .unwrap();
+    localnet
+        .wait_for_power(&pub_key, power, "the unstake")
+        .await;
+}
+
+#[tokio::test]
+#[ignore = "starts a localnet, run with make localnet_test"]
+async fn blocks_reward_their_validators() {
+    let localnet = Localnet::start();
+    localnet.wait_for_height(5).await;
+    let node = &localnet.nodes[0];
+    let client = node.client();
+
+    // all the nodes agree on how the first blocks were rewarded
+    let rewards = client.get_block_rewards(None, 1, Some(5)).await.unwrap();
+    assert!(!rewards.is_empty());
+    for other in &localnet.nodes[1..] {
+        let other_rewards = other
+            .client()
+            .get_block_rewards(None, 1, Some(5))
+            .await
+            .unwrap();
+        assert_eq!(rewards, other_rewards);
+    }
+    let addresses: Vec<vm::Address> = localnet.nodes.iter().map(Node::address).collect();
+    for block in &rewards {
+        assert_eq!(
+            1,
+            block
+                .rewards
+                .iter()
+                .filter(|reward| reward.proposer)
+                .count(),
+            "block {} doesn't have a single proposer",
+            block.height
+        );
+        assert!(block
+            .rewards
+            .iter()
+            .all(|reward| addresses.contains(&reward.aleo_address)));
+    }
+
+    // the rewards are minted as credits records owned by the validator accounts
+    let address = node.address();
+    let rewarded: u64 = rewards
+        .iter()
+        .flat_map(|block| &block.rewards)
+        .filter(|reward| reward.aleo_address == address)
+        .map(|reward| reward.gates)
+        .sum();
+    assert!(rewarded > 0, "validator 0 got no rewards in 5 blocks");
+    let private_key = node.private_key();
+    let client = &client;
+    let private_key = &private_key;
+    eventually("the reward records", || async move {
+        let records = client.get_unspent_records(private_key).await.ok()?;
+        let balance: u64 = records.iter().map(|(_, _, record)| vm::gates(record)).sum();
+        (balance >= GENESIS_GATES + rewarded).then_some(())
+    })
+    .await;
+}
+
+#[tokio::test]
+#[ignore = "starts a localnet, run with make localnet_test"]
+async fn double_signing_tombstones_the_validator() {
+    let mut localnet = Localnet::start();
+    localnet.wait_for_height(2).await;
+
+    // a second node signing with the key of the last validator eventually votes for a different block than it
+    let offender = localnet.nodes.len() - 1;
+    localnet.start_twin(offender);
+    let pub_key = localnet.nodes[offender].pub_key();
+
+    for node in &localnet.nodes[..offender] {
+        let client = node.client();
+        let client = &client;
+        let pub_key = &pub_key;
+        let validator = eventually("the double signing evidence", || async move {
+            let tombstones = client.get_tombstones().await.ok()?;
+            tombstones
+                .into_iter()
+                .find(|validator| &validator.pub_key == pub_key)
+        })
+        .await;
+        assert!(validator.tombstone.is_some());
+        assert_eq!(0, validator.consensus_power());
+    }
+
+    // tendermint drops it from the active set, and the rest keep producing blocks
+    let url = &localnet.nodes[0].url();
+    let pub_key = &pub_key;
+    eventually("the validator set update", || async move {
+        let validators = tendermint_validators(url).await.ok()?;
+        validators
+            .iter()
+            .all(|(key, _)| key != pub_key)
+            .then_some(())
+    })
+    .await;
+    let height = localnet.nodes[0].client().latest_height().await.unwrap();
+    localnet.wait_for_height(height + 2).await;
+}
+
+// HARNESS
+
+/// Gates of the credits record each account gets at genesis, the default of the genesis binary.
+const GENESIS_GATES: u64 = 1000;
+
+/// A network of `VALIDATORS` nodes on localhost, with the tendermint homes in a directory under target/ that's
+/// removed along with the processes when it's dropped.
+struct Localnet {
+    dir: PathBuf,
+    nodes: Vec<Node>,
+    /// Nodes that run with the key of a validator, see `start_twin`.
+    twins: Vec<Node>,
+}
+
+impl Localnet {
+    /// Set up the nodes with `make localnet` and start them.
+    fn start() -> Self {
+        // the makefile prefixes the home dir with ./, so it has to be relative
+        let dir = PathBuf::from(format!("target/localnet-{}", uuid::Uuid::new_v4()));
+        let feature = if cfg!(feature = "snarkvm_backend") {
+            "snarkvm_backend"
+        } else {
+            "lambdavm_backend"
+        };
+        let status = Command::new("make")
+            .arg("localnet")
+            .arg(format!("HOMEDIR={}", dir.display()))
+            .arg(format!("VALIDATORS={VALIDATORS}"))
+            .arg(format!("VM_FEATURE={feature}"))
+            .stdout(Stdio::null())
+            .status()
+            .expect("could not run make localnet");
+        assert!(status.success(), "make localnet failed");
+
+        let nodes = (0..VALIDATORS)
+            .map(|index| Node::start(dir.join(format!("node{index}")), index))
+            .collect();
+        Self {
+            dir,
+            nodes,
+            twins: Vec::new(),
+        }
+    }
+
+    /// Start another node with the tendermint key of the given validator, so the network sees the validator
+    /// signing twice whenever the two nodes vote for different blocks, e.g. each for its own proposal.
+    /// It gets a node id, ports and an application state of its own, and syncs the blocks from the others.
+    fn start_twin(&mut self, index: usize) {
+        let home = self.dir.join(format!("node{index}_twin"));
+        let config = home.join("config");
+        fs::create_dir_all(&config).unwrap();
+        fs::create_dir_all(home.join("data")).unwrap();
+        fs::create_dir_all(home.join("abci")).unwrap();
+        let original = &self.nodes[index].home;
+        for file in ["genesis.json", "priv_validator_key.json"] {
+            fs::copy(original.join("config").join(file), config.join(file)).unwrap();
+        }
+        fs::copy(original.join("account.json"), home.join("account.json")).unwrap();
+        fs::write(
+            home.join("data/priv_validator_state.json"),
+            r#"{"height": "0", "round": 0, "step": 0}"#,
+        )
+        .unwrap();
+
+        // same peers as the original, on the ports after the ones of the validators
+        let port = VALIDATORS + self.twins.len();
+        let toml = fs::read_to_string(original.join("config/config.toml"))
+            .unwrap()
+            .replace(&format!(":26{index}56"), &format!(":26{port}56"))
+            .replace(&format!(":26{index}57"), &format!(":26{port}57"))
+            .replace(&format!(":26{index}58"), &format!(":26{port}58"));
+        fs::write(config.join("config.toml"), toml).unwrap();
+
+        self.twins.push(Node::start(home, port));
+    }
+
+    /// Wait until every node committed the given height.
+    async fn wait_for_height(&self, height: u64) {
+        for node in &self.nodes {
+            let client = node.client();
+            let client = &client;
+            eventually(&format!("height {height}"), || async move {
+                let latest = client.latest_height().await.ok()?;
+                (latest >= height).then_some(())
+            })
+            .await;
+        }
+    }
+
+    /// Wait until every node, and the tendermint node it runs, give the validator the given voting power.
+    async fn wait_for_power(&self, pub_key: &tendermint::PublicKey, power: u64, what: &str) {
+        for node in &self.nodes {
+            let client = node.client();
+            let client = &client;
+            let url = &node.url();
+            eventually(what, || async move {
+                let tendermint_power = tendermint_validators(url)
+                    .await
+                    .ok()?
+                    .into_iter()
+                    .find(|(key, _)| key == pub_key)
+                    .map(|(_, power)| power);
+                let app_power = voting_power(client, pub_key).await;
+                (app_power == Some(power) && tendermint_power == Some(power)).then_some(())
+            })
+            .await;
+        }
+    }
+}
+
+impl Drop for Localnet {
+    fn drop(&mut self) {
+        for node in self.nodes.iter_mut().chain(self.twins.iter_mut()) {
+            node.stop();
+        }
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// A node of a localnet: its tendermint home, which `make localnet` also makes the aleo home of the validator
+/// account, and the processes running it.
+struct Node {
+    home: PathBuf,
+    /// Position of the node in the localnet, which sets its ports: 26<index>56 for p2p, 26<index>57 for the
+    /// tendermint RPC and 26<index>58 for the ABCI application.
+    index: usize,
+    processes: Vec<Child>,
+}
+
+impl Node {
+    /// Start the ABCI application in the abci dir of the home, and tendermint, logging to files in the home.
+    fn start(home: PathBuf, index: usize) -> Self {
+        let log = |name: &str| Stdio::from(File::create(home.join(name)).unwrap());
+        let abci = Command::new(env!("CARGO_BIN_EXE_aleo_abci"))
+            .current_dir(home.join("abci"))
+            .args(["--port", &format!("26{index}58")])
+            .stdout(log("abci.log"))
+            .stderr(log("abci.err.log"))
+            .spawn()
+            .expect("could not start aleo_abci");
+        let tendermint = Command::new("bin/tendermint")
+            .args(["node", "--home"])
+            .arg(&home)
+            .arg("--consensus.create_empty_blocks_interval=0s")
+            .stdout(log("tendermint.log"))
+            .stderr(log("tendermint.err.log"))
+            .spawn()
+            .expect("could not start tendermint, see make bin/tendermint");
+        Self {
+            home,
+            index,
+            processes: vec![abci, tendermint],
+        }
+    }
+
+    fn stop(&mut self) {
+        for process in &mut self.processes {
+            let _ = process.kill();
+            let _ = process.wait();
+        }
+    }
+
+    fn url(&self) -> String {
+        format!("http://127.0.0.1:26{}57", self.index)
+    }
+
+    fn client(&self) -> Client {
+        Client::new(&self.url()).unwrap()
+    }
+
+    /// The tendermint public key of the validator, encoded as the client expects it.
+    fn validator_key(&self) -> String {
+        let json = fs::read_to_string(self.home.join("config/priv_validator_key.json")).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        json["pub_key"]["value"].as_str().unwrap().to_string()
+    }
+
+    fn pub_key(&self) -> tendermint::PublicKey {
+        let bytes = base64::decode(self.validator_key()).unwrap();
+        tendermint::PublicKey::from_raw_ed25519(&bytes).unwrap()
+    }
+
+    fn private_key(&self) -> vm::PrivateKey {
+        let json = fs::read_to_string(self.home.join("account.json")).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        serde_json::from_value(json["private_key"].clone()).unwrap()
+    }
+
+    fn address(&self) -> vm::Address {
+        let view_key = vm::ViewKey::try_from(&self.private_key()).unwrap();
+        vm::Address::try_from(&view_key).unwrap()
+    }
+
+    /// The ciphertext of the unspent record of the validator account with the most gates, as the client takes it.
+    async fn largest_record(&self) -> String {
+        let records = self
+            .client()
+            .get_unspent_records(&self.private_key())
+            .await
+            .unwrap();
+        let (_, ciphertext, _) = records
+            .iter()
+            .max_by_key(|(_, _, record)| vm::gates(record))
+            .expect("the validator account has no records");
+        let json = serde_json::to_value(ciphertext).unwrap();
+        json.get("ciphertext")
+            .unwrap_or(&json)
+            .as_str()
+            .unwrap()
+            .to_string()
+    }
+
+    /// Run a client command with the validator account against this node, returning its json output.
+    fn command(&self, args: &[&str]) -> Result<serde_json::Value, String> {
+        let output = Command::new(env!("CARGO_BIN_EXE_client"))
+            .env("ALEO_HOME", &self.home)
+            .args(["--url", &self.url()])
+            .args(args)
+            .output()
+            .unwrap();
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).to_string());
+        }
+        serde_json::from_slice(&output.stdout).map_err(|e| e.to_string())
+    }
+}
+
+/// Voting power the application gives to the validator with the given key, if it knows it.
+async fn voting_power(client: &Client, pub_key: &tendermint::PublicKey) -> Option<u64> {
+    let validators: Vec<Validator> = client.get_validators().await.ok()?;
+    validators
+        .into_iter()
+        .find(|validator| &validator.pub_key == pub_key)
+        .map(|validator| validator.voting_power)
+}
+
+/// Keys and voting power of the validator set of the tendermint node at the given url.
+async fn tendermint_validators(
+    url: &str,
+) -> Result<Vec<(tendermint::PublicKey, u64)>, tendermint_rpc::Error> {
+    let client = HttpClient::new(url)?;
+    let height = client.status().await?.sync_info.latest_block_height;
+    let response = client.validators(height, Paging::All).await?;
+    Ok(response
+        .validators
+        .into_iter()
+        .map(|validator| (validator.pub_key, validator.power.value()))
+        .collect())
+}
+
+/// Poll `condition` every second until it returns Some, failing the test after `TIMEOUT`.
+async fn eventually<T, F: Future<Output = Option<T>>>(
+    what: &str,
+    mut condition: impl FnMut() -> F,
+) -> T {
+    let deadline = Instant::now() + TIMEOUT;
+    loop {
+        if let Some(value) = condition().await {
+            return value;
+        }
+        assert!(Instant::now() < deadline, "timed out waiting for {what}");
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}